@@ -6,6 +6,12 @@ use itertools::Itertools;
 
 const ERROR_KIND_DST: &str = "error_kind.rs";
 
+// error kinds which do not map to a directory under src/bft/,
+// reporting failures of a particular sub-protocol; the kinds
+// `Consensus` and `Cst` are already generated from their
+// respective module directories
+const SUB_PROTOCOL_ERROR_KINDS: &[&str] = &["ViewChange", "Timeout"];
+
 fn main() {
     generate_error_kinds();
 }
@@ -89,5 +95,8 @@ pub enum ErrorKind {{"#
     )
     .unwrap();
     generate(&mut path_buf, &mut name_buf, &mut buf);
+    for kind_name in SUB_PROTOCOL_ERROR_KINDS {
+        writeln!(&mut buf, "    {},", kind_name).unwrap();
+    }
     writeln!(&mut buf, "}}").unwrap();
 }
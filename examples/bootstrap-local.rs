@@ -2,9 +2,6 @@ mod common;
 
 use common::*;
 
-use std::time::Duration;
-
-use futures_timer::Delay;
 use rand_core::{OsRng, RngCore};
 
 use bafomet::bft::async_runtime as rt;
@@ -12,6 +9,7 @@ use bafomet::bft::collections::HashMap;
 use bafomet::bft::communication::message::{RequestMessage, SystemMessage};
 use bafomet::bft::communication::NodeId;
 use bafomet::bft::crypto::signature::{KeyPair, PublicKey};
+use bafomet::bft::ordering::SeqNo;
 use bafomet::bft::threadpool;
 use bafomet::bft::{init, InitConfig};
 
@@ -35,6 +33,7 @@ async fn async_main() {
         .collect();
 
     let pool = threadpool::Builder::new().num_threads(4).build();
+    let mut handles = Vec::new();
 
     for id in NodeId::targets(0..4) {
         let addrs = map! {
@@ -45,12 +44,12 @@ async fn async_main() {
         };
         let sk = secret_keys.remove(&id).unwrap();
         let fut = setup_node(pool.clone(), id, sk, addrs, public_keys.clone());
-        rt::spawn(async move {
+        let handle = rt::spawn(async move {
             println!("Bootstrapping node #{}", u32::from(id));
             let (mut node, rogue) = fut.await.unwrap();
             println!("Spawned node #{}", u32::from(id));
             println!("Rogue on node #{} => {}", u32::from(id), debug_rogue(rogue));
-            let m = SystemMessage::Request(RequestMessage::new(Action::Sqrt));
+            let m = SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, Action::Sqrt));
             node.broadcast(m, NodeId::targets(0..4));
             for _ in 0..4 {
                 let m = node.receive().await.unwrap();
@@ -72,17 +71,20 @@ async fn async_main() {
                 let () = std::future::pending().await;
             });
         });
+        handles.push(handle);
     }
     drop(pool);
 
-    // wait 3 seconds then exit
-    Delay::new(Duration::from_secs(3)).await;
+    // `Node::bootstrap` only returns once every replica is
+    // connected, so we just wait for all messages to arrive
+    for handle in handles {
+        handle.await.unwrap();
+    }
 }
 
 fn sk_stream() -> impl Iterator<Item = KeyPair> {
     std::iter::repeat_with(|| {
-        // only valid for ed25519!
-        let mut buf = [0; 32];
+        let mut buf = [0; KeyPair::SEED_LENGTH];
 
         // gen key
         OsRng.fill_bytes(&mut buf[..]);
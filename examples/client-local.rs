@@ -74,8 +74,7 @@ async fn async_main() {
 
 fn sk_stream() -> impl Iterator<Item = KeyPair> {
     std::iter::repeat_with(|| {
-        // only valid for ed25519!
-        let buf = [0; 32];
+        let buf = [0; KeyPair::SEED_LENGTH];
         KeyPair::from_bytes(&buf[..]).unwrap()
     })
 }
@@ -3,6 +3,7 @@
 use std::fs::File;
 use std::io::{BufReader, Read, Write};
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use rustls::{
     internal::pemfile, AllowAnyAuthenticatedClient, ClientConfig, RootCertStore, ServerConfig,
@@ -12,9 +13,12 @@ use serde::{Deserialize, Serialize};
 use bafomet::bft::collections::HashMap;
 use bafomet::bft::communication::message::{Message, SystemMessage};
 use bafomet::bft::communication::serialize::SharedData;
-use bafomet::bft::communication::{Node, NodeConfig, NodeId};
+use bafomet::bft::communication::shard::ShardId;
+use bafomet::bft::communication::{Node, NodeConfig, NodeConfigBuilder, NodeId};
+use bafomet::bft::consensus::VoteVerification;
 use bafomet::bft::core::client::{self, Client};
 use bafomet::bft::core::server::{Replica, ReplicaConfig};
+use bafomet::bft::crypto::hash::DefaultHasher;
 use bafomet::bft::crypto::signature::{KeyPair, PublicKey};
 use bafomet::bft::error::*;
 use bafomet::bft::executable::Service;
@@ -58,13 +62,20 @@ pub fn debug_msg(m: Message<f32, Action, f32>) -> &'static str {
             SystemMessage::Request(_) => "Req",
             _ => unreachable!(),
         },
-        Message::ConnectedTx(_, _) => "CTx",
-        Message::ConnectedRx(_, _) => "CRx",
+        Message::ConnectedTx(_, _, _) => "CTx",
+        Message::ConnectedBulkTx(_, _, _) => "CBx",
+        Message::ConnectedRx(_, _, _) => "CRx",
         Message::DisconnectedTx(_) => "DTx",
         Message::DisconnectedRx(_) => "DRx",
+        Message::Departed(_) => "Dep",
         Message::ExecutionFinished(_) => "Exe",
         Message::ExecutionFinishedWithAppstate(_, _) => "ExA",
         Message::Timeout(_) => "Tim",
+        Message::ConsensusStalled(_, _) => "Stl",
+        Message::StatusQueried(_, _) => "Qry",
+        Message::ClusterUnrecoverable(_) => "Unr",
+        Message::Command(_) => "Cmd",
+        Message::Error(_, _) => "Err",
     }
 }
 
@@ -83,17 +94,23 @@ async fn node_config(
     };
 
     // build the node conf
-    NodeConfig {
-        id,
-        n: 4,
-        f: 1,
-        sk,
-        pk,
-        addrs,
-        client_config,
-        server_config,
-        first_cli: NodeId::from(1000u32),
-    }
+    NodeConfigBuilder::new()
+        .id(id)
+        .n(4)
+        .f(1)
+        .sk(sk)
+        .pk(pk)
+        .addrs(addrs)
+        .client_config(client_config)
+        .server_config(server_config)
+        .first_cli(NodeId::from(1000u32))
+        .channel_bound(128)
+        .connect_retries(3 * 60)
+        .pool(t.clone())
+        .deserialize_timeout(Duration::from_millis(500))
+        .authenticate_peers(cfg!(feature = "peer_auth"))
+        .build()
+        .unwrap()
 }
 
 pub async fn setup_client(
@@ -104,7 +121,11 @@ pub async fn setup_client(
     pk: HashMap<NodeId, PublicKey>,
 ) -> Result<Client<CalcData>> {
     let node = node_config(&t, id, sk, addrs, pk).await;
-    let conf = client::ClientConfig { node };
+    let conf = client::ClientConfig {
+        leader_hint: None,
+        shard: ShardId::ZERO,
+        node,
+    };
     Client::bootstrap(conf).await
 }
 
@@ -118,7 +139,29 @@ pub async fn setup_replica(
     let node = node_config(&t, id, sk, addrs, pk).await;
     let conf = ReplicaConfig {
         node,
+        checkpoint_period: 1000,
         batch_size: 1024,
+        max_batch_bytes: 16 * 1024 * 1024,
+        max_deciding_bytes: usize::MAX,
+        max_log_bytes: 1024 * 1024 * 1024,
+        request_timeout: Duration::from_secs(2 * 60),
+        max_failed_view_changes: None,
+        cst_timeout: Duration::from_secs(30),
+        state_serve_interval: Duration::from_secs(60),
+        max_state_transfers: 2,
+        state_chunk_size: Some(1 << 20),
+        state_chunk_rate: Some(64 << 20),
+        state_chunk_window: 256,
+        checkpoint_store: None,
+        dead_letters: None,
+        consensus_watchdog: Duration::from_secs(60),
+        vote_verification: VoteVerification::Lazy,
+        reply_cache_size: 4096,
+        observers: Vec::new(),
+        forward_requests: false,
+        redirect_requests: false,
+        client_rate_limit: None,
+        max_request_priority: u32::MAX,
         next_consensus_seq: SeqNo::ZERO,
         view: SeqNo::ZERO,
         service: CalcService(id, 0),
@@ -238,6 +281,7 @@ impl SharedData for CalcData {
     type State = f32;
     type Request = Action;
     type Reply = f32;
+    type Hasher = DefaultHasher;
 
     fn serialize_state<W>(w: W, s: &f32) -> Result<()>
     where
@@ -45,7 +45,7 @@ async fn async_main() {
         let fut = setup_replica(pool.clone(), id, sk, addrs, public_keys.clone());
         rt::spawn(async move {
             println!("Bootstrapping replica #{}", u32::from(id));
-            let mut replica = fut.await.unwrap();
+            let replica = fut.await.unwrap();
             println!("Running replica #{}", u32::from(id));
             replica.run().await.unwrap();
         });
@@ -58,8 +58,7 @@ async fn async_main() {
 
 fn sk_stream() -> impl Iterator<Item = KeyPair> {
     std::iter::repeat_with(|| {
-        // only valid for ed25519!
-        let buf = [0; 32];
+        let buf = [0; KeyPair::SEED_LENGTH];
         KeyPair::from_bytes(&buf[..]).unwrap()
     })
 }
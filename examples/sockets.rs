@@ -1,12 +1,10 @@
 use std::io;
 use std::net::SocketAddr;
-use std::time::Duration;
 
 use futures::io::{AsyncReadExt, AsyncWriteExt};
-use futures_timer::Delay;
 
 use bafomet::bft::async_runtime as rt;
-use bafomet::bft::communication::socket::{self, Socket};
+use bafomet::bft::communication::socket::{self, Listener, Socket};
 use bafomet::bft::{init, InitConfig};
 
 fn main() {
@@ -16,10 +14,12 @@ fn main() {
 }
 
 async fn client_main() -> io::Result<()> {
-    rt::spawn(listener_main());
-    Delay::new(Duration::from_millis(1)).await;
-
     let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+
+    // bind before connecting, so the connection can't be refused
+    let listener = socket::bind(addr).await?;
+    rt::spawn(listener_main(listener));
+
     let mut sock = socket::connect(addr).await?;
 
     let mut buf = Vec::new();
@@ -44,10 +44,7 @@ async fn client_main() -> io::Result<()> {
     }
 }
 
-async fn listener_main() -> io::Result<()> {
-    let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
-    let listener = socket::bind(addr).await?;
-
+async fn listener_main(listener: Listener) -> io::Result<()> {
     loop {
         if let Ok(sock) = listener.accept().await {
             rt::spawn(handle_client(sock));
@@ -6,6 +6,7 @@ use bafomet::bft::async_runtime as rt;
 use bafomet::bft::communication::channel;
 use bafomet::bft::communication::message::Message;
 use bafomet::bft::executable::{Reply, Request, State};
+use bafomet::bft::ordering::SeqNo;
 use bafomet::bft::timeouts::{TimeoutKind, Timeouts};
 use bafomet::bft::{init, InitConfig};
 
@@ -30,7 +31,7 @@ async fn async_main() {
     for i in 1..=5 {
         println!("Created timeout of {} seconds", i * 5);
         let dur = Duration::from_secs(i * 5);
-        timeouts.timeout(dur, TimeoutKind::Cst(0));
+        timeouts.timeout(dur, TimeoutKind::Cst(SeqNo::ZERO));
     }
 
     while let Ok(message) = rx.recv().await {
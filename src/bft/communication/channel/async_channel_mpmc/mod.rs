@@ -42,6 +42,17 @@ impl<T> ChannelTx<T> {
             .await
             .simple(ErrorKind::CommunicationChannelAsyncChannelMpmc)
     }
+
+    #[inline]
+    pub fn try_send(&mut self, message: T) -> Result<bool> {
+        match self.inner.try_send(message) {
+            Ok(_) => Ok(true),
+            Err(async_channel::TrySendError::Full(_)) => Ok(false),
+            Err(_) => Err(Error::simple(
+                ErrorKind::CommunicationChannelAsyncChannelMpmc,
+            )),
+        }
+    }
 }
 
 impl<T> ChannelRx<T> {
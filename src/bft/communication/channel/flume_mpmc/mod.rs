@@ -40,6 +40,15 @@ impl<T> ChannelTx<T> {
             .await
             .simple(ErrorKind::CommunicationChannelFlumeMpmc)
     }
+
+    #[inline]
+    pub fn try_send(&mut self, message: T) -> Result<bool> {
+        match self.inner.try_send(message) {
+            Ok(_) => Ok(true),
+            Err(::flume::TrySendError::Full(_)) => Ok(false),
+            Err(_) => Err(Error::simple(ErrorKind::CommunicationChannelFlumeMpmc)),
+        }
+    }
 }
 
 impl<T> ChannelRx<T> {
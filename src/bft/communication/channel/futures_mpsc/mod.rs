@@ -43,6 +43,15 @@ impl<T> ChannelTx<T> {
             .simple(ErrorKind::CommunicationChannelFuturesMpsc)
     }
 
+    #[inline]
+    pub fn try_send(&mut self, message: T) -> Result<bool> {
+        match self.inner.try_send(message) {
+            Ok(_) => Ok(true),
+            Err(e) if e.is_full() => Ok(false),
+            Err(_) => Err(Error::simple(ErrorKind::CommunicationChannelFuturesMpsc)),
+        }
+    }
+
     #[inline]
     async fn ready(&mut self) -> Result<()> {
         poll_fn(|cx| match self.inner.poll_ready(cx) {
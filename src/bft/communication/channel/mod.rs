@@ -95,6 +95,15 @@ impl<T> ChannelTx<T> {
     pub async fn send(&mut self, message: T) -> Result<()> {
         self.inner.send(message).await
     }
+
+    /// Attempts to send a message without blocking.
+    ///
+    /// Returns `Ok(false)` if the channel is full, in which
+    /// case the message is dropped.
+    #[inline]
+    pub fn try_send(&mut self, message: T) -> Result<bool> {
+        self.inner.try_send(message)
+    }
 }
 
 impl<T> ChannelRx<T> {
@@ -190,10 +199,62 @@ impl<S, O, P> MessageChannelTx<S, O, P> {
                 message @ SystemMessage::ForwardedRequests(_) => {
                     self.other.send(Message::System(header, message)).await
                 }
+                message @ SystemMessage::ForwardRequest(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::LeaderStepDown(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::RequestMissing(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::ReadRequest(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::QueryStatus(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::QueryStatusReply(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::Decided(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::Redirect(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::Throttled(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::ReplyBatch(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::CancelRequest(_) => {
+                    self.other.send(Message::System(header, message)).await
+                }
+                message @ SystemMessage::Goodbye => {
+                    self.other.send(Message::System(header, message)).await
+                }
             },
             _ => self.other.send(message).await,
         }
     }
+
+    /// Attempts to send a message without blocking.
+    ///
+    /// Returns `Ok(false)` if the queue the message belongs
+    /// to is full, in which case the message is dropped.
+    pub fn try_send(&mut self, message: Message<S, O, P>) -> Result<bool> {
+        match message {
+            Message::System(header, message) => match message {
+                SystemMessage::Request(message) => self.requests.try_send((header, message)),
+                SystemMessage::Reply(message) => self.replies.try_send((header, message)),
+                SystemMessage::Consensus(message) => self.consensus.try_send((header, message)),
+                message => self.other.try_send(Message::System(header, message)),
+            },
+            _ => self.other.try_send(message),
+        }
+    }
 }
 
 impl<S, O, P> MessageChannelRx<S, O, P> {
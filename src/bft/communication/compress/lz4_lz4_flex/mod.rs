@@ -0,0 +1,19 @@
+use crate::bft::error::*;
+
+pub fn compress(payload: &[u8]) -> Result<Vec<u8>> {
+    Ok(lz4_flex::compress_prepend_size(payload))
+}
+
+pub fn decompress(payload: &[u8], bound: usize) -> Result<Vec<u8>> {
+    // the uncompressed length is prepended to the payload
+    let length = payload
+        .get(..4)
+        .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .ok_or("Truncated payload")
+        .wrapped(ErrorKind::CommunicationCompressLz4Lz4Flex)?;
+    if length > bound {
+        return Err("Decompressed payload too large")
+            .wrapped(ErrorKind::CommunicationCompressLz4Lz4Flex);
+    }
+    lz4_flex::decompress_size_prepended(payload).wrapped(ErrorKind::CommunicationCompressLz4Lz4Flex)
+}
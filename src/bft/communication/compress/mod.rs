@@ -0,0 +1,158 @@
+//! Compression of the frames sent over the connections between nodes.
+//!
+//! A codec is negotiated once per connection, during its handshake:
+//! the connecting node offers the codecs it supports, and the accepting
+//! node replies with the strongest codec both of them support. Every
+//! payload sent over the connection is then compressed with that codec,
+//! while its `Header` keeps the digest of the uncompressed payload,
+//! so signatures are unaffected by the codec of the link. The codecs
+//! other than `Codec::None` are backed by the crates selected with the
+//! `compress_*` features.
+
+#[cfg(feature = "compress_lz4")]
+mod lz4_lz4_flex;
+
+#[cfg(feature = "compress_zstd")]
+mod zstd_zstd;
+
+#[cfg(not(all(feature = "compress_lz4", feature = "compress_zstd")))]
+mod unsupported;
+
+#[cfg(not(feature = "compress_lz4"))]
+use unsupported as lz4_lz4_flex;
+
+#[cfg(not(feature = "compress_zstd"))]
+use unsupported as zstd_zstd;
+
+use std::borrow::Cow;
+
+use bytes::BytesMut;
+
+use crate::bft::error::*;
+
+/// A codec used to compress the payloads sent over a connection.
+///
+/// Codecs are ordered from the weakest to the strongest.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Codec {
+    /// Payloads are sent as is.
+    None,
+    /// Payloads are compressed with LZ4.
+    ///
+    /// Requires the `compress_lz4` feature.
+    Lz4,
+    /// Payloads are compressed with Zstandard.
+    ///
+    /// Requires the `compress_zstd` feature.
+    Zstd,
+}
+
+impl Codec {
+    const ALL: [Codec; 3] = [Codec::None, Codec::Lz4, Codec::Zstd];
+
+    /// Returns the codecs supported by this build, i.e. those
+    /// whose `compress_*` feature is enabled, plus `Codec::None`.
+    pub fn available() -> Vec<Codec> {
+        Self::ALL
+            .iter()
+            .copied()
+            .filter(|codec| codec.is_available())
+            .collect()
+    }
+
+    /// Checks if this codec is supported by this build.
+    pub fn is_available(self) -> bool {
+        match self {
+            Codec::None => true,
+            Codec::Lz4 => cfg!(feature = "compress_lz4"),
+            Codec::Zstd => cfg!(feature = "compress_zstd"),
+        }
+    }
+
+    // the set of `codecs` offered in a handshake, as a bit mask
+    pub(crate) fn offer(codecs: &[Codec]) -> u8 {
+        codecs
+            .iter()
+            .filter(|codec| codec.is_available())
+            .fold(0, |mask, &codec| mask | (1 << codec as u8))
+    }
+
+    // the strongest codec offered both by us and by a peer, given
+    // the two offers; `Codec::None` is always supported, even if
+    // it wasn't offered
+    pub(crate) fn negotiate(mine: u8, theirs: u8) -> Codec {
+        Self::ALL
+            .iter()
+            .copied()
+            .filter(|&codec| mine & theirs & (1 << codec as u8) != 0)
+            .max()
+            .unwrap_or(Codec::None)
+    }
+
+    // the codec picked by a peer, in reply to our `offer`
+    pub(crate) fn picked(offer: u8, reply: u8) -> Result<Codec> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&codec| codec as u8 == reply)
+            .filter(|&codec| codec == Codec::None || offer & (1 << codec as u8) != 0)
+            .ok_or("The peer picked a codec we didn't offer")
+            .wrapped(ErrorKind::CommunicationCompress)
+    }
+
+    /// Compresses `payload` with this codec.
+    pub fn compress(self, payload: &[u8]) -> Result<Cow<'_, [u8]>> {
+        match self {
+            Codec::None => Ok(Cow::Borrowed(payload)),
+            Codec::Lz4 => lz4_lz4_flex::compress(payload).map(Cow::Owned),
+            Codec::Zstd => zstd_zstd::compress(payload).map(Cow::Owned),
+        }
+    }
+
+    /// Decompresses a `payload` compressed with this codec, failing
+    /// if it would decompress into more than `bound` bytes.
+    pub fn decompress(self, payload: BytesMut, bound: usize) -> Result<BytesMut> {
+        let decompressed = match self {
+            Codec::None => return Ok(payload),
+            Codec::Lz4 => lz4_lz4_flex::decompress(&payload[..], bound)?,
+            Codec::Zstd => zstd_zstd::decompress(&payload[..], bound)?,
+        };
+        Ok(BytesMut::from(&decompressed[..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::Codec;
+
+    #[test]
+    fn test_negotiate_strongest_common_codec() {
+        let all = [Codec::None, Codec::Lz4, Codec::Zstd];
+        let offer = Codec::offer(&all);
+        let strongest = Codec::available().into_iter().max().unwrap();
+        let none = Codec::offer(&[Codec::None]);
+        assert_eq!(Codec::negotiate(offer, offer), strongest);
+        assert_eq!(Codec::negotiate(none, offer), Codec::None);
+        assert_eq!(Codec::negotiate(offer, Codec::offer(&[])), Codec::None);
+        assert_eq!(Codec::picked(offer, strongest as u8).unwrap(), strongest);
+        assert_eq!(Codec::picked(0, Codec::None as u8).unwrap(), Codec::None);
+        assert!(Codec::picked(0, 7).is_err());
+    }
+
+    #[test]
+    fn test_codecs_roundtrip() {
+        let payload = b"abcabcabcabcabcabcabcabcabcabcabcabcabcabcabc".repeat(16);
+        for codec in Codec::available() {
+            let compressed = codec.compress(&payload[..]).unwrap();
+            let compressed = BytesMut::from(&compressed[..]);
+            let decompressed = codec.decompress(compressed.clone(), payload.len()).unwrap();
+            assert_eq!(&decompressed[..], &payload[..]);
+            if codec != Codec::None {
+                assert!(compressed.len() < payload.len());
+                assert!(codec.decompress(compressed, payload.len() - 1).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+// stands in for the backends whose `compress_*` feature is disabled;
+// the codecs they implement are never negotiated, so these are only
+// reached if a peer sends frames with a codec it didn't agree on
+
+use crate::bft::error::*;
+
+pub fn compress(_payload: &[u8]) -> Result<Vec<u8>> {
+    Err("Codec not supported by this build").wrapped(ErrorKind::CommunicationCompressUnsupported)
+}
+
+pub fn decompress(_payload: &[u8], _bound: usize) -> Result<Vec<u8>> {
+    Err("Codec not supported by this build").wrapped(ErrorKind::CommunicationCompressUnsupported)
+}
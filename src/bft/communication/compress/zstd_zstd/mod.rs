@@ -0,0 +1,13 @@
+use crate::bft::error::*;
+
+// the default compression level of zstd, which
+// favors throughput over the compression ratio
+const LEVEL: i32 = 3;
+
+pub fn compress(payload: &[u8]) -> Result<Vec<u8>> {
+    zstd::bulk::compress(payload, LEVEL).wrapped(ErrorKind::CommunicationCompressZstdZstd)
+}
+
+pub fn decompress(payload: &[u8], bound: usize) -> Result<Vec<u8>> {
+    zstd::bulk::decompress(payload, bound).wrapped(ErrorKind::CommunicationCompressZstdZstd)
+}
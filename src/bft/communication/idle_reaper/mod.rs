@@ -0,0 +1,118 @@
+//! Reaping of idle client connections.
+//!
+//! Clients come and go, but once a client connects to a replica, its
+//! socket would otherwise linger until it errors. The receiving task of
+//! each client connection records its activity with an `IdleReaper`,
+//! which periodically closes the connections idle for too long.
+
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use parking_lot::Mutex;
+
+use crate::bft::collections::{self, HashMap};
+use crate::bft::communication::NodeId;
+
+// the activity of a client connection
+struct Activity {
+    last_activity: Instant,
+    // dropping this sender closes the connection
+    _close: oneshot::Sender<()>,
+}
+
+/// Tracks the last activity of each client connection.
+pub struct IdleReaper {
+    timeout: Duration,
+    peers: Mutex<HashMap<NodeId, Activity>>,
+}
+
+impl IdleReaper {
+    /// Creates a new `IdleReaper`, reaping the connections
+    /// idle for longer than `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            peers: Mutex::new(collections::hash_map()),
+        }
+    }
+
+    /// Returns the idle timeout of this `IdleReaper`.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Starts tracking the connection of the client `peer_id`.
+    ///
+    /// The returned receiver resolves once the connection is reaped,
+    /// or superseded by a newer connection of the same client.
+    pub fn register(&self, peer_id: NodeId, now: Instant) -> oneshot::Receiver<()> {
+        let (close, closed) = oneshot::channel();
+        let activity = Activity {
+            last_activity: now,
+            _close: close,
+        };
+        self.peers.lock().insert(peer_id, activity);
+        closed
+    }
+
+    /// Records activity on the connection of the client `peer_id`.
+    pub fn touch(&self, peer_id: NodeId, now: Instant) {
+        if let Some(activity) = self.peers.lock().get_mut(&peer_id) {
+            activity.last_activity = now;
+        }
+    }
+
+    /// Checks if the connection of the client `peer_id` is being tracked.
+    pub fn is_tracked(&self, peer_id: NodeId) -> bool {
+        self.peers.lock().contains_key(&peer_id)
+    }
+
+    /// Stops tracking the connections idle at the instant `now`,
+    /// closing them, and returns the ids of their clients.
+    pub fn reap(&self, now: Instant) -> Vec<NodeId> {
+        let mut reaped = Vec::new();
+        self.peers.lock().retain(|&peer_id, activity| {
+            let idle = now.saturating_duration_since(activity.last_activity) > self.timeout;
+            if idle {
+                reaped.push(peer_id);
+            }
+            !idle
+        });
+        reaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::IdleReaper;
+    use crate::bft::communication::NodeId;
+
+    #[test]
+    fn test_idle_connections_reaped() {
+        const TIMEOUT: Duration = Duration::from_secs(10);
+
+        let idle = NodeId::from(1000u32);
+        let busy = NodeId::from(1001u32);
+        let start = Instant::now();
+        let reaper = IdleReaper::new(TIMEOUT);
+
+        let mut idle_closed = reaper.register(idle, start);
+        let mut busy_closed = reaper.register(busy, start);
+
+        reaper.touch(busy, start + TIMEOUT);
+        assert!(reaper.reap(start + TIMEOUT).is_empty());
+
+        assert_eq!(reaper.reap(start + TIMEOUT * 3 / 2), vec![idle]);
+        assert!(!reaper.is_tracked(idle));
+        assert!(reaper.is_tracked(busy));
+        assert!(idle_closed.try_recv().is_err());
+        assert_eq!(busy_closed.try_recv(), Ok(None));
+
+        // a reconnection supersedes the previous connection
+        let _closed = reaper.register(busy, start + TIMEOUT * 2);
+        assert!(busy_closed.try_recv().is_err());
+        assert!(reaper.reap(start + TIMEOUT * 2).is_empty());
+    }
+}
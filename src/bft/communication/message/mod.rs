@@ -1,24 +1,33 @@
 //! This module contains types associated with messages traded
 //! between the system processes.
 
-use std::io;
+use std::io::{self, Read, Write};
 use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::time::Duration;
 
 #[cfg(feature = "serialize_serde")]
 use serde::{Deserialize, Serialize};
 
 use async_tls::{client::TlsStream as TlsStreamCli, server::TlsStream as TlsStreamSrv};
+use bytes::Bytes;
 use futures::io::{AsyncWrite, AsyncWriteExt};
 use smallvec::{Array, SmallVec};
 
+use crate::bft::communication::compress::Codec;
+use crate::bft::communication::serialize::ToBytes;
+use crate::bft::communication::shard::ShardId;
 use crate::bft::communication::socket::Socket;
 use crate::bft::communication::NodeId;
-use crate::bft::consensus::log::CollectData;
-use crate::bft::crypto::hash::{Context, Digest};
+use crate::bft::consensus::log::{CollectData, DecisionLog};
+use crate::bft::consensus::ProtoPhase;
+use crate::bft::core::server::handle::ReplicaCommand;
+use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
 use crate::bft::crypto::signature::{KeyPair, PublicKey, Signature};
-use crate::bft::cst::RecoveryState;
+use crate::bft::cst::chunks::{StateChunk, StateManifest};
+use crate::bft::cst::{CheckpointRef, Delta, RecoveryState};
 use crate::bft::error::*;
-use crate::bft::executable::UpdateBatchReplies;
+use crate::bft::executable::{UpdateBatch, UpdateBatchReplies};
 use crate::bft::ordering::{Orderable, SeqNo};
 use crate::bft::sync::LeaderCollects;
 use crate::bft::timeouts::TimeoutKind;
@@ -53,6 +62,14 @@ impl<M> StoredMessage<M> {
     }
 }
 
+impl<O> StoredMessage<RequestMessage<O>> {
+    /// Returns the serialized `SystemMessage::Request` the stored
+    /// request was received in, if it was retained.
+    pub fn serialized(&self) -> Option<&Bytes> {
+        self.message.serialized()
+    }
+}
+
 /*
 impl<S, O, P> StoredMessage<SystemMessage<S, O, P>> {
     /// Convert the inner `SystemMessage` into a `ConsensusMessage`,
@@ -76,11 +93,18 @@ impl<S, O, P> StoredMessage<SystemMessage<S, O, P>> {
 /// A fixed amount of `Header::LENGTH` bytes are read before
 /// a message is read. Contains the protocol version, message
 /// length, as well as other metadata.
+///
+/// The size of the signature, and therefore `Header::LENGTH`,
+/// depends on the signature scheme picked with the
+/// `crypto_signature_*` features. The digest of the payload is
+/// prefixed by its length, since it depends on the `DigestHasher`
+/// of the application.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(C, packed)]
 pub struct Header {
-    // manually align memory for cross platform compat
-    pub(crate) _align: u32,
+    // the shard of the sender, which also
+    // aligns memory for cross platform compat
+    pub(crate) shard: u32,
     // the protocol version
     pub(crate) version: u32,
     // origin of the message
@@ -92,8 +116,8 @@ pub struct Header {
     // length of the payload
     pub(crate) length: u64,
     // the digest of the serialized payload
-    pub(crate) digest: [u8; Digest::LENGTH],
-    // sign(hash(le(version) + le(from) + le(to) + le(nonce) + le(length) + hash(serialize(payload))))
+    pub(crate) digest: Digest,
+    // sign(hash(le(version) + le(shard) + le(from) + le(to) + le(nonce) + le(length) + hash(serialize(payload))))
     pub(crate) signature: [u8; Signature::LENGTH],
 }
 
@@ -118,9 +142,10 @@ impl<'de> serde::Deserialize<'de> for Header {
         D: serde::Deserializer<'de>,
     {
         let bytes: Vec<u8> = serde_bytes::deserialize(deserializer)?;
-        let mut hdr: [u8; Self::LENGTH] = [0; Self::LENGTH];
-        hdr.copy_from_slice(&bytes);
-        Ok(unsafe { std::mem::transmute(hdr) })
+        if bytes.len() != Self::LENGTH {
+            return Err(serde::de::Error::invalid_length(bytes.len(), &"a header"));
+        }
+        Header::deserialize_from(&bytes[..]).map_err(serde::de::Error::custom)
     }
 }
 
@@ -139,6 +164,24 @@ pub struct OwnedWireMessage<T> {
     pub(crate) payload: T,
 }
 
+/// A `WireMessage` whose `Header` was signed by its sender.
+///
+/// Every message carrying a `SystemMessage` must be signed, hence
+/// the receive path of a `Node` only accepts messages of this type.
+#[derive(Debug)]
+pub struct SignedWireMessage<'a> {
+    inner: WireMessage<'a>,
+}
+
+/// A `WireMessage` whose `Header` isn't signed.
+///
+/// These are only exchanged in the handshake performed when
+/// connecting to a peer node, which is never stored in a log.
+#[derive(Debug)]
+pub struct UnsignedWireMessage<'a> {
+    inner: WireMessage<'a>,
+}
+
 /// The `Message` type encompasses all the messages traded between different
 /// asynchronous tasks in the system.
 ///
@@ -147,16 +190,29 @@ pub enum Message<S, O, P> {
     System(Header, SystemMessage<S, O, P>),
     /// A client with id `NodeId` has finished connecting to the socket `Socket`.
     /// This socket should only perform write operations.
-    ConnectedTx(NodeId, TlsStreamCli<Socket>),
+    ///
+    /// The payloads sent over it are compressed with the negotiated `Codec`.
+    ConnectedTx(NodeId, TlsStreamCli<Socket>, Codec),
+    /// Same as `Message::ConnectedTx`, but the socket is reserved for
+    /// bulk traffic to the replica with id `NodeId`, such as states.
+    ///
+    /// This message is only delivered if `NodeConfig::bulk_link` is set.
+    ConnectedBulkTx(NodeId, TlsStreamCli<Socket>, Codec),
     /// A client with id `NodeId` has finished connecting to the socket `Socket`.
     /// This socket should only perform read operations.
-    ConnectedRx(NodeId, TlsStreamSrv<Socket>),
+    ///
+    /// The payloads read from it are compressed with the negotiated `Codec`.
+    ConnectedRx(NodeId, TlsStreamSrv<Socket>, Codec),
     /// Send half of node with id `NodeId` has disconnected.
     DisconnectedTx(NodeId),
     /// Receive half of node with id `Some(NodeId)` has disconnected.
     ///
     /// The id is only equal to `None` during a `Node` bootstrap process.
     DisconnectedRx(Option<NodeId>),
+    /// Same as `Message::DisconnectedRx`, but the node with id `NodeId`
+    /// sent a `SystemMessage::Goodbye` before closing its connection,
+    /// i.e. it was shut down gracefully, rather than having crashed.
+    Departed(NodeId),
     /// A batch of client requests has finished executing.
     ///
     /// The type of the payload delivered to the clients is `P`.
@@ -168,6 +224,23 @@ pub enum Message<S, O, P> {
     ExecutionFinishedWithAppstate(UpdateBatchReplies<P>, S),
     /// We received a timeout from the timeouts layer.
     Timeout(TimeoutKind),
+    /// The consensus instance with the given sequence number
+    /// has been stuck in the given phase for longer than the
+    /// timeout of the consensus watchdog.
+    ConsensusStalled(SeqNo, ProtoPhase),
+    /// The executor has looked up the status of a client request,
+    /// on behalf of the node with id `NodeId`.
+    StatusQueried(NodeId, QueryStatusReplyMessage<P>),
+    /// Too many view changes failed in a row, the last of which was
+    /// meant to install the view with the given sequence number, so
+    /// the replica gave up on changing views.
+    ClusterUnrecoverable(SeqNo),
+    /// A message received from the node with id `NodeId` was rejected,
+    /// e.g. because its signature was invalid, so its connection is
+    /// dropped, which is announced with a `Message::DisconnectedRx`.
+    Error(NodeId, Error),
+    /// An administrative command, issued through a `ReplicaHandle`.
+    Command(ReplicaCommand<S>),
 }
 
 /// A `SystemMessage` corresponds to a message regarding one of the SMR
@@ -178,13 +251,24 @@ pub enum Message<S, O, P> {
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 pub enum SystemMessage<S, O, P> {
-    // TODO: ReadRequest,
     Request(RequestMessage<O>),
+    ReadRequest(ReadRequestMessage<O>),
     Reply(ReplyMessage<P>),
+    ReplyBatch(ReplyBatchMessage<P>),
     Consensus(ConsensusMessage),
     Cst(CstMessage<S, O>),
     ViewChange(ViewChangeMessage<O>),
     ForwardedRequests(ForwardedRequestsMessage<O>),
+    ForwardRequest(ForwardRequestMessage<O>),
+    LeaderStepDown(LeaderStepDownMessage),
+    RequestMissing(RequestMissingMessage),
+    QueryStatus(QueryStatusMessage),
+    QueryStatusReply(QueryStatusReplyMessage<P>),
+    Decided(DecidedMessage<O>),
+    Redirect(RedirectMessage<O>),
+    Throttled(ThrottledMessage),
+    CancelRequest(CancelRequestMessage),
+    Goodbye,
 }
 
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
@@ -205,6 +289,248 @@ impl<O> ForwardedRequestsMessage<O> {
     }
 }
 
+/// Sent by a follower replica to the leader of the current view, carrying
+/// a client request the follower received, such that the leader may propose
+/// it, even if the client didn't send the request to the leader itself.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct ForwardRequestMessage<O> {
+    inner: StoredMessage<RequestMessage<O>>,
+}
+
+impl<O> ForwardRequestMessage<O> {
+    /// Creates a new `ForwardRequestMessage`, containing the given client request.
+    pub fn new(inner: StoredMessage<RequestMessage<O>>) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the client request contained in this `ForwardRequestMessage`.
+    pub fn into_inner(self) -> StoredMessage<RequestMessage<O>> {
+        self.inner
+    }
+}
+
+/// Sent by a follower replica to a client, rejecting a request the client
+/// sent to it, and pointing the client to the leader of the current view.
+///
+/// The rejected request is sent back along with its header, such that the
+/// client may send it to the leader, without keeping a copy of it.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct RedirectMessage<O> {
+    leader: NodeId,
+    rejected: StoredMessage<RequestMessage<O>>,
+}
+
+impl<O> RedirectMessage<O> {
+    /// Creates a new `RedirectMessage`, rejecting the given client request.
+    pub fn new(leader: NodeId, rejected: StoredMessage<RequestMessage<O>>) -> Self {
+        Self { leader, rejected }
+    }
+
+    /// Returns the id of the leader the client is redirected to.
+    pub fn leader(&self) -> NodeId {
+        self.leader
+    }
+
+    /// Unwraps this `RedirectMessage`.
+    pub fn into_inner(self) -> (NodeId, StoredMessage<RequestMessage<O>>) {
+        (self.leader, self.rejected)
+    }
+}
+
+/// Sent by a replica to a client whose request was dropped, because the
+/// client exceeded the rate of requests the replica admits from it.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct ThrottledMessage {
+    seq: SeqNo,
+    retry_after: Duration,
+}
+
+impl ThrottledMessage {
+    /// Creates a new `ThrottledMessage`, dropping the request with
+    /// sequence number `seq`, and asking the client to back off
+    /// for `retry_after` before sending another request.
+    pub fn new(seq: SeqNo, retry_after: Duration) -> Self {
+        Self { seq, retry_after }
+    }
+
+    /// Returns the duration the client should back off for.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+}
+
+impl Orderable for ThrottledMessage {
+    /// Returns the sequence number of the dropped request.
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+/// Sent by a replica that accepted a `PRE-PREPARE` referencing client
+/// requests it doesn't have in its log, to pull these requests from its peers.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct RequestMissingMessage {
+    digests: Vec<Digest>,
+}
+
+impl RequestMissingMessage {
+    /// Creates a new `RequestMissingMessage`, asking for the client requests
+    /// with the given digests.
+    pub fn new(digests: Vec<Digest>) -> Self {
+        Self { digests }
+    }
+
+    /// Returns the digests of the client requests being requested.
+    pub fn digests(&self) -> &[Digest] {
+        &self.digests[..]
+    }
+}
+
+/// Sent by a client to learn whether one of its requests was executed,
+/// e.g. after losing its reply, without resubmitting the request.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone)]
+pub struct QueryStatusMessage {
+    digest: Digest,
+}
+
+impl QueryStatusMessage {
+    /// Creates a new `QueryStatusMessage`, asking for the status
+    /// of the client request with the given digest.
+    pub fn new(digest: Digest) -> Self {
+        Self { digest }
+    }
+
+    /// Returns the digest of the client request being queried.
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+}
+
+/// Sent by a client to withdraw one of its requests, which it
+/// no longer needs the result of, before it is proposed.
+///
+/// Requests which were already proposed are still executed.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone)]
+pub struct CancelRequestMessage {
+    digest: Digest,
+}
+
+impl CancelRequestMessage {
+    /// Creates a new `CancelRequestMessage`, withdrawing
+    /// the client request with the given digest.
+    pub fn new(digest: Digest) -> Self {
+        Self { digest }
+    }
+
+    /// Returns the digest of the client request being withdrawn.
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+}
+
+/// The status of a client request, as observed by a replica.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub enum RequestStatus<P> {
+    /// The request was executed, producing the given reply.
+    Executed(P),
+    /// The request is in the log, but it wasn't executed yet.
+    Pending,
+    /// The request is unknown to the replica, or its reply
+    /// was evicted from the cache of recent replies.
+    Unknown,
+}
+
+/// Sent by a replica in response to a `QueryStatusMessage`.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct QueryStatusReplyMessage<P> {
+    digest: Digest,
+    status: RequestStatus<P>,
+}
+
+impl<P> QueryStatusReplyMessage<P> {
+    /// Creates a new `QueryStatusReplyMessage`, reporting the status
+    /// of the client request with the given digest.
+    pub fn new(digest: Digest, status: RequestStatus<P>) -> Self {
+        Self { digest, status }
+    }
+
+    /// Returns the digest of the client request being queried.
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+
+    /// Returns a reference to the status of the client request.
+    pub fn status(&self) -> &RequestStatus<P> {
+        &self.status
+    }
+
+    /// Unwraps this `QueryStatusReplyMessage`.
+    pub fn into_inner(self) -> (Digest, RequestStatus<P>) {
+        (self.digest, self.status)
+    }
+}
+
+/// Sent by a replica to the observers attached to it, carrying
+/// a batch of client requests decided by the consensus layer.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct DecidedMessage<O> {
+    seq: SeqNo,
+    batch: UpdateBatch<O>,
+}
+
+impl<O> Orderable for DecidedMessage<O> {
+    /// Returns the sequence number of the consensus instance
+    /// that decided this batch.
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+impl<O> DecidedMessage<O> {
+    /// Creates a new `DecidedMessage`, carrying the batch decided
+    /// by the consensus instance with sequence number `seq`.
+    pub fn new(seq: SeqNo, batch: UpdateBatch<O>) -> Self {
+        Self { seq, batch }
+    }
+
+    /// Unwraps this `DecidedMessage`.
+    pub fn into_inner(self) -> UpdateBatch<O> {
+        self.batch
+    }
+}
+
+/// Sent by the leader of a view, to voluntarily hand off leadership
+/// to the leader of the next view, e.g. before planned maintenance.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone)]
+pub struct LeaderStepDownMessage {
+    view: SeqNo,
+}
+
+impl Orderable for LeaderStepDownMessage {
+    /// Returns the sequence number of the view the leader is stepping down from.
+    fn sequence_number(&self) -> SeqNo {
+        self.view
+    }
+}
+
+impl LeaderStepDownMessage {
+    /// Creates a new `LeaderStepDownMessage`, pertaining to the view
+    /// with sequence number `view`.
+    pub fn new(view: SeqNo) -> Self {
+        Self { view }
+    }
+}
+
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 pub struct ViewChangeMessage<O> {
@@ -276,6 +602,25 @@ pub enum CstMessageKind<S, O> {
     ReplyLatestConsensusSeq(SeqNo),
     RequestState,
     ReplyState(RecoveryState<S, O>),
+    // the replica placed its state in a shared checkpoint
+    // store, where it should be fetched from
+    ReplyStateRef(CheckpointRef),
+    // the replica has served its state to the requesting
+    // node too recently, and refused to serve it again
+    ReplyStateThrottled,
+    // the requests decided in a range of consensus instances,
+    // from the first seq no. up to, but excluding, the second
+    RequestDelta(SeqNo, SeqNo),
+    ReplyDelta(Delta<O>),
+    // the replica serves its state in chunks, described by the
+    // manifest, which should be requested from the replica; its
+    // decision log is served along with the manifest, since the
+    // votes in it vary across the replicas holding the state
+    ReplyStateManifest(StateManifest, DecisionLog),
+    // the chunks with the given indices of the state
+    // identified by the merkle root of its chunks
+    RequestChunks(Digest, Vec<u64>),
+    ReplyChunk(StateChunk),
 }
 
 impl<S, O> Orderable for CstMessage<S, O> {
@@ -297,6 +642,43 @@ impl<S, O> CstMessage<S, O> {
         &self.kind
     }
 
+    /// Takes the delta embedded in this cst message, if it is available.
+    pub fn take_delta(&mut self) -> Option<Delta<O>> {
+        let kind = std::mem::replace(&mut self.kind, CstMessageKind::RequestState);
+        match kind {
+            CstMessageKind::ReplyDelta(delta) => Some(delta),
+            _ => {
+                self.kind = kind;
+                None
+            }
+        }
+    }
+
+    /// Takes the state manifest embedded in this cst message, along with
+    /// the decision log of the replica serving the state, if they are available.
+    pub fn take_manifest(&mut self) -> Option<(StateManifest, DecisionLog)> {
+        let kind = std::mem::replace(&mut self.kind, CstMessageKind::RequestState);
+        match kind {
+            CstMessageKind::ReplyStateManifest(manifest, declog) => Some((manifest, declog)),
+            _ => {
+                self.kind = kind;
+                None
+            }
+        }
+    }
+
+    /// Takes the state chunk embedded in this cst message, if it is available.
+    pub fn take_chunk(&mut self) -> Option<StateChunk> {
+        let kind = std::mem::replace(&mut self.kind, CstMessageKind::RequestState);
+        match kind {
+            CstMessageKind::ReplyChunk(chunk) => Some(chunk),
+            _ => {
+                self.kind = kind;
+                None
+            }
+        }
+    }
+
     /// Takes the recovery state embedded in this cst message, if it is available.
     pub fn take_state(&mut self) -> Option<RecoveryState<S, O>> {
         let kind = std::mem::replace(&mut self.kind, CstMessageKind::RequestState);
@@ -317,7 +699,17 @@ impl<S, O> CstMessage<S, O> {
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 pub struct RequestMessage<O> {
+    // the sequence number of this request, local to the client that
+    // sent it; replicas drop requests whose sequence number is not
+    // higher than that of the last request executed for that client
+    seq: SeqNo,
     operation: O,
+    trace_id: Option<TraceId>,
+    metadata: Option<RequestMeta>,
+    // the serialized `SystemMessage::Request` this operation was
+    // received in; never transmitted over the wire
+    #[cfg_attr(feature = "serialize_serde", serde(skip))]
+    serialized: Option<Bytes>,
 }
 
 /// Represents a reply to a client.
@@ -328,6 +720,72 @@ pub struct RequestMessage<O> {
 pub struct ReplyMessage<P> {
     digest: Digest,
     payload: P,
+    trace_id: Option<TraceId>,
+}
+
+/// The replies to several requests of the same client, which
+/// were executed as part of the same decided batch.
+///
+/// Clients split them back into individual `ReplyMessage`s.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct ReplyBatchMessage<P> {
+    replies: Vec<ReplyMessage<P>>,
+}
+
+/// An id attached to a client request by the application, and echoed
+/// back in its reply, to follow the request across replicas.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TraceId(u64);
+
+impl From<u64> for TraceId {
+    #[inline]
+    fn from(id: u64) -> TraceId {
+        TraceId(id)
+    }
+}
+
+impl From<TraceId> for u64 {
+    #[inline]
+    fn from(id: TraceId) -> u64 {
+        id.0
+    }
+}
+
+/// Metadata attached to a client request by the application, which
+/// replicas take into account when scheduling it to be proposed.
+///
+/// Requests with a higher priority are batched first; among requests
+/// with the same priority, those with an earlier deadline are batched
+/// first, followed by those without a deadline. Deadlines are relative
+/// to the arrival of a request at each replica. Requests which waited
+/// for too long are batched first regardless, in arrival order.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct RequestMeta {
+    priority: u32,
+    // the amount of time the client is willing to
+    // wait for the request to be executed
+    deadline: Option<Duration>,
+}
+
+impl RequestMeta {
+    /// Creates new request metadata, with the given priority and deadline.
+    pub fn new(priority: u32, deadline: Option<Duration>) -> Self {
+        Self { priority, deadline }
+    }
+
+    /// Returns the priority of the request; the default is 0.
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    /// Returns the amount of time the client is willing to wait
+    /// for the request to be executed, if any.
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
 }
 
 /// Represents a message from the consensus sub-protocol.
@@ -364,10 +822,76 @@ pub enum ConsensusMessageKind {
     Commit(Digest),
 }
 
+impl<O> Orderable for RequestMessage<O> {
+    /// Returns the sequence number of this request, attributed
+    /// by the client that sent it.
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
 impl<O> RequestMessage<O> {
-    /// Creates a new `RequestMessage`.
-    pub fn new(operation: O) -> Self {
-        Self { operation }
+    /// Creates a new `RequestMessage`, with the sequence number `seq`,
+    /// which must increase with every request sent by the same client.
+    pub fn new(seq: SeqNo, operation: O) -> Self {
+        Self {
+            seq,
+            operation,
+            trace_id: None,
+            metadata: None,
+            serialized: None,
+        }
+    }
+
+    /// Creates a new `RequestMessage`, retaining the serialized
+    /// `SystemMessage::Request` it was received in.
+    ///
+    /// The bytes are reused when the request is decided, to avoid
+    /// cloning or serializing the operation once more, at the cost
+    /// of keeping them in memory until the next checkpoint.
+    pub fn with_serialized(seq: SeqNo, operation: O, serialized: Bytes) -> Self {
+        Self {
+            seq,
+            operation,
+            trace_id: None,
+            metadata: None,
+            serialized: Some(serialized),
+        }
+    }
+
+    /// Retains the serialized `SystemMessage::Request` this request
+    /// was received in, leaving the rest of the request untouched;
+    /// check out `with_serialized()`.
+    pub fn set_serialized(&mut self, serialized: Bytes) {
+        self.serialized = Some(serialized);
+    }
+
+    /// Attaches the trace id `trace_id` to this request, if any.
+    pub fn with_trace_id(mut self, trace_id: Option<TraceId>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    /// Returns the trace id attached to this request, if any.
+    pub fn trace_id(&self) -> Option<TraceId> {
+        self.trace_id
+    }
+
+    /// Attaches the metadata `metadata` to this request, if any.
+    pub fn with_metadata(mut self, metadata: Option<RequestMeta>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Returns the metadata attached to this request, if any.
+    pub fn metadata(&self) -> Option<&RequestMeta> {
+        self.metadata.as_ref()
+    }
+
+    /// Returns the serialized `SystemMessage::Request` this
+    /// request was received in, if it was retained.
+    pub fn serialized(&self) -> Option<&Bytes> {
+        self.serialized.as_ref()
     }
 
     /// Returns a reference to the operation of type `O`.
@@ -381,10 +905,68 @@ impl<O> RequestMessage<O> {
     }
 }
 
+/// Represents a read only request from a client, which is served
+/// by each replica without being ordered by the consensus layer.
+///
+/// The `O` type argument symbolizes the client operation to be performed
+/// over the replicated state.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct ReadRequestMessage<O> {
+    // the sequence number of the latest update request of the
+    // client that must be executed before serving this read
+    after: Option<SeqNo>,
+    operation: O,
+}
+
+impl<O> ReadRequestMessage<O> {
+    /// Creates a new `ReadRequestMessage`.
+    ///
+    /// If `after` is set, replicas only serve the read once they have
+    /// executed the update request of the same client with that
+    /// sequence number, such that clients read their own writes.
+    pub fn new(after: Option<SeqNo>, operation: O) -> Self {
+        Self { after, operation }
+    }
+
+    /// Returns the sequence number of the update request
+    /// that must be executed before serving this read, if any.
+    pub fn after(&self) -> Option<SeqNo> {
+        self.after
+    }
+
+    /// Returns a reference to the operation of type `O`.
+    pub fn operation(&self) -> &O {
+        &self.operation
+    }
+
+    /// Unwraps this `ReadRequestMessage`.
+    pub fn into_inner(self) -> O {
+        self.operation
+    }
+}
+
 impl<P> ReplyMessage<P> {
     /// Creates a new `ReplyMessage`.
     pub fn new(digest: Digest, payload: P) -> Self {
-        Self { digest, payload }
+        Self {
+            digest,
+            payload,
+            trace_id: None,
+        }
+    }
+
+    /// Attaches the trace id `trace_id` to this reply, if any.
+    ///
+    /// Replicas echo the trace id of the request being replied to.
+    pub fn with_trace_id(mut self, trace_id: Option<TraceId>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    /// Returns the trace id of the request this reply pertains to, if any.
+    pub fn trace_id(&self) -> Option<TraceId> {
+        self.trace_id
     }
 
     /// Returns a reference to the payload of type `P`.
@@ -404,6 +986,23 @@ impl<P> ReplyMessage<P> {
     }
 }
 
+impl<P> ReplyBatchMessage<P> {
+    /// Creates a new `ReplyBatchMessage`, containing the given replies.
+    pub fn new(replies: Vec<ReplyMessage<P>>) -> Self {
+        Self { replies }
+    }
+
+    /// Returns a reference to the replies contained in this `ReplyBatchMessage`.
+    pub fn replies(&self) -> &[ReplyMessage<P>] {
+        &self.replies[..]
+    }
+
+    /// Returns the replies contained in this `ReplyBatchMessage`.
+    pub fn into_inner(self) -> Vec<ReplyMessage<P>> {
+        self.replies
+    }
+}
+
 impl Orderable for ConsensusMessage {
     /// Returns the sequence number of this consensus message.
     fn sequence_number(&self) -> SeqNo {
@@ -465,6 +1064,7 @@ impl Header {
         #[cfg(target_endian = "big")]
         {
             self.version = self.version.to_le();
+            self.shard = self.shard.to_le();
             self.nonce = self.nonce.to_le();
             self.from = self.from.to_le();
             self.to = self.to.to_le();
@@ -492,6 +1092,7 @@ impl Header {
         #[cfg(target_endian = "big")]
         {
             hdr.version = hdr.version.to_be();
+            hdr.shard = hdr.shard.to_be();
             hdr.nonce = hdr.nonce.to_be();
             hdr.from = hdr.from.to_be();
             hdr.to = hdr.to.to_le();
@@ -506,7 +1107,11 @@ impl Header {
             return Err("Buffer is too short to deserialize from")
                 .wrapped(ErrorKind::CommunicationMessage);
         }
-        Ok(unsafe { Self::deserialize_from_unchecked(buf) })
+        let header = unsafe { Self::deserialize_from_unchecked(buf) };
+        if !header.digest.is_well_formed() {
+            return Err("Invalid digest length").wrapped(ErrorKind::CommunicationMessage);
+        }
+        Ok(header)
     }
 
     /// Reports the current version of the wire protocol,
@@ -515,6 +1120,12 @@ impl Header {
         self.version
     }
 
+    /// The shard of the originating `Node`, which is
+    /// `ShardId::ZERO` unless the `Node` was split into shards.
+    pub fn shard(&self) -> ShardId {
+        self.shard.into()
+    }
+
     /// The originating `NodeId`.
     pub fn from(&self) -> NodeId {
         self.from.into()
@@ -537,7 +1148,7 @@ impl Header {
 
     /// The digest of the associated payload serialized data.
     pub fn digest(&self) -> &Digest {
-        unsafe { std::mem::transmute(&self.digest) }
+        &self.digest
     }
 
     /// Hashes the digest of the associated message's payload
@@ -553,6 +1164,168 @@ impl Header {
     pub fn nonce(&self) -> u64 {
         self.nonce
     }
+
+    /// Checks if the signature of this `Header` was produced with the
+    /// secret key matching `public_key`.
+    ///
+    /// Since the signature covers the digest of the payload, rather than
+    /// the payload itself, this check does not require the payload.
+    pub fn verify_signature(&self, public_key: &PublicKey) -> bool {
+        self.verify_signature_hashed::<DefaultHasher>(public_key)
+    }
+
+    /// Same as `verify_signature()`, but the signed parts of the `Header`
+    /// are digested with `H`, i.e. the `SharedData::Hasher` of the
+    /// application.
+    pub fn verify_signature_hashed<H: DigestHasher>(&self, public_key: &PublicKey) -> bool {
+        self.version == WireMessage::CURRENT_VERSION
+            && WireMessage::verify_parts::<H>(
+                public_key,
+                self.signature(),
+                self.shard,
+                self.from,
+                self.to,
+                self.nonce,
+                self.digest.as_ref(),
+            )
+            .is_ok()
+    }
+}
+
+impl ToBytes for Header {
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        let mut buf = [0; Self::LENGTH];
+        self.serialize_into(&mut buf[..])?;
+        w.write_all(&buf[..])
+            .wrapped(ErrorKind::CommunicationMessage)
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        let mut buf = [0; Self::LENGTH];
+        r.read_exact(&mut buf[..])
+            .wrapped(ErrorKind::CommunicationMessage)?;
+        Self::deserialize_from(&buf[..])
+    }
+}
+
+impl ToBytes for ConsensusMessageKind {
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        match self {
+            ConsensusMessageKind::PrePrepare(digests) => {
+                0_u8.to_bytes(w)?;
+                digests.to_bytes(w)
+            }
+            ConsensusMessageKind::Prepare(digest) => {
+                1_u8.to_bytes(w)?;
+                digest.to_bytes(w)
+            }
+            ConsensusMessageKind::Commit(digest) => {
+                2_u8.to_bytes(w)?;
+                digest.to_bytes(w)
+            }
+        }
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        match u8::from_bytes(r)? {
+            0 => Vec::from_bytes(r).map(ConsensusMessageKind::PrePrepare),
+            1 => <Digest as ToBytes>::from_bytes(r).map(ConsensusMessageKind::Prepare),
+            2 => <Digest as ToBytes>::from_bytes(r).map(ConsensusMessageKind::Commit),
+            _ => Err("Invalid consensus message kind").wrapped(ErrorKind::CommunicationMessage),
+        }
+    }
+}
+
+impl ToBytes for ConsensusMessage {
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        self.seq.to_bytes(w)?;
+        self.view.to_bytes(w)?;
+        self.kind.to_bytes(w)
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        let seq = SeqNo::from_bytes(r)?;
+        let view = SeqNo::from_bytes(r)?;
+        let kind = ConsensusMessageKind::from_bytes(r)?;
+        Ok(Self::new(seq, view, kind))
+    }
+}
+
+impl<O: ToBytes> ToBytes for RequestMessage<O> {
+    /// Serializes the operation of this request; much like with
+    /// `serde`, the retained serialized message is not included.
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        self.seq.to_bytes(w)?;
+        match self.trace_id {
+            Some(TraceId(id)) => {
+                1u8.to_bytes(w)?;
+                id.to_bytes(w)?;
+            }
+            None => 0u8.to_bytes(w)?,
+        }
+        match self.metadata {
+            Some(RequestMeta { priority, deadline }) => {
+                1u8.to_bytes(w)?;
+                priority.to_bytes(w)?;
+                match deadline {
+                    Some(deadline) => {
+                        1u8.to_bytes(w)?;
+                        deadline.as_secs().to_bytes(w)?;
+                        deadline.subsec_nanos().to_bytes(w)?;
+                    }
+                    None => 0u8.to_bytes(w)?,
+                }
+            }
+            None => 0u8.to_bytes(w)?,
+        }
+        self.operation.to_bytes(w)
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        let seq = SeqNo::from_bytes(r)?;
+        let trace_id = match u8::from_bytes(r)? {
+            0 => None,
+            1 => Some(TraceId(u64::from_bytes(r)?)),
+            _ => {
+                return Err("Invalid trace id tag in request")
+                    .wrapped(ErrorKind::CommunicationMessage)
+            }
+        };
+        let metadata = match u8::from_bytes(r)? {
+            0 => None,
+            1 => {
+                let priority = u32::from_bytes(r)?;
+                let deadline = match u8::from_bytes(r)? {
+                    0 => None,
+                    1 => {
+                        let secs = u64::from_bytes(r)?;
+                        let nanos = u32::from_bytes(r)?;
+                        // `Duration::new()` carries excess nanoseconds
+                        // into the seconds, which may overflow; encoded
+                        // durations never carry any
+                        if nanos >= 1_000_000_000 {
+                            return Err("Invalid deadline in request")
+                                .wrapped(ErrorKind::CommunicationMessage);
+                        }
+                        Some(Duration::new(secs, nanos))
+                    }
+                    _ => {
+                        return Err("Invalid deadline tag in request")
+                            .wrapped(ErrorKind::CommunicationMessage)
+                    }
+                };
+                Some(RequestMeta::new(priority, deadline))
+            }
+            _ => {
+                return Err("Invalid metadata tag in request")
+                    .wrapped(ErrorKind::CommunicationMessage)
+            }
+        };
+        let operation = O::from_bytes(r)?;
+        Ok(Self::new(seq, operation)
+            .with_trace_id(trace_id)
+            .with_metadata(metadata))
+    }
 }
 
 impl From<WireMessage<'_>> for OwnedWireMessage<Box<[u8]>> {
@@ -594,7 +1367,11 @@ impl<T: AsRef<[u8]>> OwnedWireMessage<T> {
 
 impl<'a> WireMessage<'a> {
     /// The current version of the wire protocol.
-    pub const CURRENT_VERSION: u32 = 0;
+    ///
+    /// The upper 16 bits identify the signature scheme, i.e.
+    /// `Signature::SCHEME`, such that headers sent by nodes using
+    /// another scheme, with a different `Header::LENGTH`, are rejected.
+    pub const CURRENT_VERSION: u32 = 4 | (Signature::SCHEME << 16);
 
     /// Wraps a `Header` and a byte array payload into a `WireMessage`.
     pub fn from_parts(header: Header, payload: &'a [u8]) -> Result<Self> {
@@ -614,21 +1391,54 @@ impl<'a> WireMessage<'a> {
         digest: Option<Digest>,
         sk: Option<&KeyPair>,
     ) -> Self {
-        let digest = digest
-            // safety: digests have repr(transparent)
-            .map(|d| unsafe { std::mem::transmute(d) })
-            // if payload length is 0
-            .unwrap_or([0; Digest::LENGTH]);
+        Self::new_in_shard(ShardId::ZERO, from, to, payload, nonce, digest, sk)
+    }
+
+    /// Same as `new()`, but the message is sent from the shard
+    /// `shard` of a `Node`, which is covered by the signature.
+    pub fn new_in_shard(
+        shard: ShardId,
+        from: NodeId,
+        to: NodeId,
+        payload: &'a [u8],
+        nonce: u64,
+        digest: Option<Digest>,
+        sk: Option<&KeyPair>,
+    ) -> Self {
+        Self::new_in_shard_hashed::<DefaultHasher>(shard, from, to, payload, nonce, digest, sk)
+    }
+
+    /// Same as `new_in_shard()`, but the signed parts of the header are
+    /// digested with `H`, i.e. the `SharedData::Hasher` of the application.
+    pub fn new_in_shard_hashed<H: DigestHasher>(
+        shard: ShardId,
+        from: NodeId,
+        to: NodeId,
+        payload: &'a [u8],
+        nonce: u64,
+        digest: Option<Digest>,
+        sk: Option<&KeyPair>,
+    ) -> Self {
+        let shard = shard.into();
+        // if payload length is 0
+        let digest = digest.unwrap_or(Digest::EMPTY);
         let signature = sk
             .map(|sk| {
-                let signature = Self::sign_parts(sk, from.into(), to.into(), nonce, &digest[..]);
+                let signature = Self::sign_parts::<H>(
+                    sk,
+                    shard,
+                    from.into(),
+                    to.into(),
+                    nonce,
+                    digest.as_ref(),
+                );
                 // safety: signatures have repr(transparent)
                 unsafe { std::mem::transmute(signature) }
             })
             .unwrap_or([0; Signature::LENGTH]);
         let (from, to) = (from.into(), to.into());
         let header = Header {
-            _align: 0,
+            shard,
             version: Self::CURRENT_VERSION,
             length: payload.len() as u64,
             signature,
@@ -640,12 +1450,21 @@ impl<'a> WireMessage<'a> {
         Self { header, payload }
     }
 
-    fn digest_parts(from: u32, to: u32, nonce: u64, payload: &[u8]) -> Digest {
-        let mut ctx = Context::new();
+    fn digest_parts<H: DigestHasher>(
+        shard: u32,
+        from: u32,
+        to: u32,
+        nonce: u64,
+        payload: &[u8],
+    ) -> Digest {
+        let mut ctx = H::context();
 
         let buf = Self::CURRENT_VERSION.to_le_bytes();
         ctx.update(&buf[..]);
 
+        let buf = shard.to_le_bytes();
+        ctx.update(&buf[..]);
+
         let buf = from.to_le_bytes();
         ctx.update(&buf[..]);
 
@@ -662,22 +1481,30 @@ impl<'a> WireMessage<'a> {
         ctx.finish()
     }
 
-    fn sign_parts(sk: &KeyPair, from: u32, to: u32, nonce: u64, payload: &[u8]) -> Signature {
-        let digest = Self::digest_parts(from, to, nonce, payload);
+    fn sign_parts<H: DigestHasher>(
+        sk: &KeyPair,
+        shard: u32,
+        from: u32,
+        to: u32,
+        nonce: u64,
+        payload: &[u8],
+    ) -> Signature {
+        let digest = Self::digest_parts::<H>(shard, from, to, nonce, payload);
         // NOTE: unwrap() should always work, much like heap allocs
         // should always work
         sk.sign(digest.as_ref()).unwrap()
     }
 
-    fn verify_parts(
+    fn verify_parts<H: DigestHasher>(
         pk: &PublicKey,
         sig: &Signature,
+        shard: u32,
         from: u32,
         to: u32,
         nonce: u64,
         payload: &[u8],
     ) -> Result<()> {
-        let digest = Self::digest_parts(from, to, nonce, payload);
+        let digest = Self::digest_parts::<H>(shard, from, to, nonce, payload);
         pk.verify(digest.as_ref(), sig)
     }
 
@@ -697,9 +1524,24 @@ impl<'a> WireMessage<'a> {
         &self.payload
     }
 
+    // checks if the digest in the header is that of the payload, as
+    // computed by `H`; empty payloads are sent without a digest
+    fn has_payload_digest<H: DigestHasher>(&self) -> bool {
+        if self.payload.is_empty() {
+            return self.header.digest == Digest::EMPTY;
+        }
+        H::digest(&[self.payload]) == self.header.digest
+    }
+
     /// Checks for the correctness of the `WireMessage`. This implies
     /// checking its signature, if a `PublicKey` is provided.
     pub fn is_valid(&self, public_key: Option<&PublicKey>) -> bool {
+        self.is_valid_hashed::<DefaultHasher>(public_key)
+    }
+
+    // same as `is_valid()`, but the signature is checked
+    // against the header parts, as digested with `H`
+    fn is_valid_hashed<H: DigestHasher>(&self, public_key: Option<&PublicKey>) -> bool {
         let preliminary_check_failed = self.header.version != WireMessage::CURRENT_VERSION
             || self.header.length != self.payload.len() as u64;
         if preliminary_check_failed {
@@ -707,13 +1549,14 @@ impl<'a> WireMessage<'a> {
         }
         public_key
             .map(|pk| {
-                Self::verify_parts(
+                Self::verify_parts::<H>(
                     pk,
                     self.header.signature(),
+                    self.header.shard,
                     self.header.from,
                     self.header.to,
                     self.header.nonce,
-                    &self.header.digest[..],
+                    self.header.digest.as_ref(),
                 )
                 .is_ok()
             })
@@ -735,6 +1578,20 @@ impl<'a> WireMessage<'a> {
         Ok(())
     }
 
+    /// Serializes this `WireMessage` into `buf`, appending the same
+    /// bytes `write_to()` would write, i.e. the `Header` followed by
+    /// the payload.
+    ///
+    /// This is useful to frame several messages in a single buffer.
+    pub fn serialize_framed_into(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let start = buf.len();
+        buf.reserve(Header::LENGTH + self.payload.len());
+        buf.resize(start + Header::LENGTH, 0);
+        self.header.serialize_into(&mut buf[start..])?;
+        buf.extend_from_slice(self.payload);
+        Ok(())
+    }
+
     /// Converts this `WireMessage` into an owned one.
     pub fn with_owned_buffer<T: AsRef<[u8]>>(self, buf: T) -> Option<OwnedWireMessage<T>> {
         let buf_p = buf.as_ref()[0] as *const u8;
@@ -758,10 +1615,12 @@ impl<S, O, P> Message<S, O, P> {
     pub fn header(&self) -> Result<&Header> {
         match self {
             Message::System(ref h, _) => Ok(h),
-            Message::ConnectedTx(_, _) => {
+            Message::ConnectedTx(_, _, _) => {
                 Err("Expected System found ConnectedTx").wrapped(ErrorKind::CommunicationMessage)
             }
-            Message::ConnectedRx(_, _) => {
+            Message::ConnectedBulkTx(_, _, _) => Err("Expected System found ConnectedBulkTx")
+                .wrapped(ErrorKind::CommunicationMessage),
+            Message::ConnectedRx(_, _, _) => {
                 Err("Expected System found ConnectedRx").wrapped(ErrorKind::CommunicationMessage)
             }
             Message::DisconnectedTx(_) => {
@@ -770,6 +1629,9 @@ impl<S, O, P> Message<S, O, P> {
             Message::DisconnectedRx(_) => {
                 Err("Expected System found DisconnectedRx").wrapped(ErrorKind::CommunicationMessage)
             }
+            Message::Departed(_) => {
+                Err("Expected System found Departed").wrapped(ErrorKind::CommunicationMessage)
+            }
             Message::ExecutionFinished(_) => Err("Expected System found ExecutionFinished")
                 .wrapped(ErrorKind::CommunicationMessage),
             Message::ExecutionFinishedWithAppstate(_, _) => {
@@ -779,28 +1641,187 @@ impl<S, O, P> Message<S, O, P> {
             Message::Timeout(_) => {
                 Err("Expected System found Timeout").wrapped(ErrorKind::CommunicationMessage)
             }
+            Message::ConsensusStalled(_, _) => Err("Expected System found ConsensusStalled")
+                .wrapped(ErrorKind::CommunicationMessage),
+            Message::StatusQueried(_, _) => {
+                Err("Expected System found StatusQueried").wrapped(ErrorKind::CommunicationMessage)
+            }
+            Message::ClusterUnrecoverable(_) => Err("Expected System found ClusterUnrecoverable")
+                .wrapped(ErrorKind::CommunicationMessage),
+            Message::Error(_, _) => {
+                Err("Expected System found Error").wrapped(ErrorKind::CommunicationMessage)
+            }
+            Message::Command(_) => {
+                Err("Expected System found Command").wrapped(ErrorKind::CommunicationMessage)
+            }
+        }
+    }
+}
+
+impl<'a> SignedWireMessage<'a> {
+    /// Constructs a new message to be sent over the wire,
+    /// signed with the secret key `sk`.
+    pub fn new(
+        from: NodeId,
+        to: NodeId,
+        payload: &'a [u8],
+        nonce: u64,
+        digest: Option<Digest>,
+        sk: &KeyPair,
+    ) -> Self {
+        let inner = WireMessage::new(from, to, payload, nonce, digest, Some(sk));
+        Self { inner }
+    }
+
+    /// Same as `new()`, but the message is sent from the shard
+    /// `shard` of a `Node`; check `WireMessage::new_in_shard()`.
+    pub fn new_in_shard(
+        shard: ShardId,
+        from: NodeId,
+        to: NodeId,
+        payload: &'a [u8],
+        nonce: u64,
+        digest: Option<Digest>,
+        sk: &KeyPair,
+    ) -> Self {
+        let inner = WireMessage::new_in_shard(shard, from, to, payload, nonce, digest, Some(sk));
+        Self { inner }
+    }
+
+    /// Same as `new_in_shard()`, but the signed parts of the header are
+    /// digested with `H`; check `WireMessage::new_in_shard_hashed()`.
+    pub fn new_in_shard_hashed<H: DigestHasher>(
+        shard: ShardId,
+        from: NodeId,
+        to: NodeId,
+        payload: &'a [u8],
+        nonce: u64,
+        digest: Option<Digest>,
+        sk: &KeyPair,
+    ) -> Self {
+        let inner = WireMessage::new_in_shard_hashed::<H>(
+            shard,
+            from,
+            to,
+            payload,
+            nonce,
+            digest,
+            Some(sk),
+        );
+        Self { inner }
+    }
+
+    /// Wraps a `Header` and a byte array payload into a `SignedWireMessage`.
+    ///
+    /// Fails if the `Header` wasn't signed by the owner of `public_key`,
+    /// which includes unsigned headers, or if the digest it carries
+    /// isn't that of `payload`, since the signature only covers the
+    /// digest of the payload.
+    pub fn from_parts(header: Header, payload: &'a [u8], public_key: &PublicKey) -> Result<Self> {
+        Self::from_parts_hashed::<DefaultHasher>(header, payload, public_key)
+    }
+
+    /// Same as `from_parts()`, but the digest of `payload`, as well as
+    /// that of the signed parts of the `Header`, are computed with `H`,
+    /// i.e. the `SharedData::Hasher` of the application.
+    pub fn from_parts_hashed<H: DigestHasher>(
+        header: Header,
+        payload: &'a [u8],
+        public_key: &PublicKey,
+    ) -> Result<Self> {
+        let inner = WireMessage { header, payload };
+        if !inner.is_valid_hashed::<H>(Some(public_key)) {
+            return Err("Invalid signed wire message").wrapped(ErrorKind::CommunicationMessage);
+        }
+        if !inner.has_payload_digest::<H>() {
+            return Err("Payload doesn't match the signed digest")
+                .wrapped(ErrorKind::CommunicationMessage);
         }
+        Ok(Self { inner })
+    }
+
+    /// Retrieve the inner `Header` and payload byte buffer stored
+    /// inside the `SignedWireMessage`.
+    pub fn into_inner(self) -> (Header, &'a [u8]) {
+        self.inner.into_inner()
+    }
+}
+
+impl<'a> Deref for SignedWireMessage<'a> {
+    type Target = WireMessage<'a>;
+
+    fn deref(&self) -> &WireMessage<'a> {
+        &self.inner
+    }
+}
+
+impl<'a> UnsignedWireMessage<'a> {
+    /// Constructs a new message to be sent over the wire, without a signature.
+    pub fn new(
+        from: NodeId,
+        to: NodeId,
+        payload: &'a [u8],
+        nonce: u64,
+        digest: Option<Digest>,
+    ) -> Self {
+        let inner = WireMessage::new(from, to, payload, nonce, digest, None);
+        Self { inner }
+    }
+
+    /// Wraps a `Header` and a byte array payload into an `UnsignedWireMessage`.
+    pub fn from_parts(header: Header, payload: &'a [u8]) -> Result<Self> {
+        WireMessage::from_parts(header, payload).map(|inner| Self { inner })
+    }
+
+    /// Retrieve the inner `Header` and payload byte buffer stored
+    /// inside the `UnsignedWireMessage`.
+    pub fn into_inner(self) -> (Header, &'a [u8]) {
+        self.inner.into_inner()
+    }
+}
+
+impl<'a> Deref for UnsignedWireMessage<'a> {
+    type Target = WireMessage<'a>;
+
+    fn deref(&self) -> &WireMessage<'a> {
+        &self.inner
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::bft::communication::message::{Header, WireMessage};
-    use crate::bft::crypto::hash::Digest;
-    use crate::bft::crypto::signature::Signature;
+    use std::time::Duration;
+
+    use crate::bft::communication::message::{
+        ConsensusMessage, ConsensusMessageKind, Header, RequestMessage, RequestMeta,
+        SignedWireMessage, TraceId, UnsignedWireMessage, WireMessage,
+    };
+    use crate::bft::communication::serialize::ToBytes;
+    use crate::bft::communication::NodeId;
+    use crate::bft::crypto::hash::{Context, DefaultHasher, Digest, DigestHasher};
+    use crate::bft::crypto::signature::{KeyPair, PublicKey, Signature};
+    use crate::bft::error::ErrorKind;
+    use crate::bft::ordering::{Orderable, SeqNo};
+    use crate::bft::testing::forge_header;
+
+    fn round_trip<T: ToBytes>(x: &T) -> T {
+        let mut buf = Vec::new();
+        x.to_bytes(&mut buf).expect("Serialize failed");
+        let mut r = &buf[..];
+        let y = T::from_bytes(&mut r).expect("Deserialize failed");
+        assert!(r.is_empty());
+        y
+    }
+
+    fn digest(data: &[u8]) -> Digest {
+        let mut ctx = Context::new();
+        ctx.update(data);
+        ctx.finish()
+    }
 
     #[test]
     fn test_header_serialize() {
-        let old_header = Header {
-            _align: 0,
-            version: WireMessage::CURRENT_VERSION,
-            signature: [0; Signature::LENGTH],
-            digest: [0; Digest::LENGTH],
-            nonce: 0,
-            from: 0,
-            to: 3,
-            length: 0,
-        };
+        let old_header = forge_header(0, 3, 0, 0);
         let mut buf = [0; Header::LENGTH];
         old_header
             .serialize_into(&mut buf[..])
@@ -808,4 +1829,185 @@ mod tests {
         let new_header = Header::deserialize_from(&buf[..]).expect("Deserialize failed");
         assert_eq!(old_header, new_header);
     }
+    #[test]
+    fn test_header_digest_length_checked() {
+        let header = Header {
+            digest: Digest::from_bytes(&[3; DefaultHasher::LENGTH][..]).unwrap(),
+            ..forge_header(0, 3, 0, 0)
+        };
+        let mut buf = [0; Header::LENGTH];
+        header.serialize_into(&mut buf[..]).unwrap();
+        assert_eq!(Header::deserialize_from(&buf[..]).unwrap(), header);
+
+        // the length of the digest follows the fixed size fields,
+        // and the bytes past it must be zeroed
+        let offset = 4 + 4 + 4 + 4 + 8 + 8;
+        let mut longer = buf;
+        longer[offset] = (Digest::MAX_LENGTH + 1) as u8;
+        assert!(Header::deserialize_from(&longer[..]).is_err());
+        let mut shorter = buf;
+        shorter[offset] -= 1;
+        assert!(Header::deserialize_from(&shorter[..]).is_err());
+    }
+
+    #[test]
+    fn test_header_sized_by_signature_scheme() {
+        // shard + version + from + to + nonce + length
+        let fixed = 4 + 4 + 4 + 4 + 8 + 8;
+        let digest = 1 + Digest::MAX_LENGTH;
+        assert_eq!(Header::LENGTH, fixed + digest + Signature::LENGTH);
+        assert_eq!(WireMessage::CURRENT_VERSION >> 16, Signature::SCHEME);
+
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let pk: PublicKey = sk.public_key().into();
+        let (from, to) = (NodeId::from(0u32), NodeId::from(1u32));
+        let payload = b"payload";
+        let header = WireMessage::new(from, to, payload, 0, None, Some(&sk)).header;
+
+        let mut buf = [0; Header::LENGTH];
+        header.serialize_into(&mut buf[..]).unwrap();
+        let header = Header::deserialize_from(&buf[..]).unwrap();
+        let wm = WireMessage::from_parts(header, &payload[..]).unwrap();
+        assert!(wm.is_valid(Some(&pk)));
+
+        // headers of nodes using another signature scheme are rejected
+        let mut header = header;
+        header.version = 1 | ((Signature::SCHEME + 1) << 16);
+        assert!(WireMessage::from_parts(header, &payload[..]).is_err());
+    }
+
+    #[test]
+    fn test_signed_wire_message_from_parts() {
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let pk: PublicKey = sk.public_key().into();
+        let (from, to) = (NodeId::from(0u32), NodeId::from(1u32));
+        let payload = b"payload";
+        let digest = digest(payload);
+
+        let (header, _) =
+            SignedWireMessage::new(from, to, payload, 0, Some(digest), &sk).into_inner();
+        assert!(SignedWireMessage::from_parts(header, &payload[..], &pk).is_ok());
+
+        // the signature doesn't cover payloads swapped in transit
+        let err = SignedWireMessage::from_parts(header, &b"tampers"[..], &pk)
+            .err()
+            .expect("Tampered payload accepted");
+        assert_eq!(err.kind(), ErrorKind::CommunicationMessage);
+
+        // unsigned headers are only accepted as such
+        let (header, _) = UnsignedWireMessage::new(from, to, payload, 0, Some(digest)).into_inner();
+        assert!(UnsignedWireMessage::from_parts(header, &payload[..]).is_ok());
+        let err = SignedWireMessage::from_parts(header, &payload[..], &pk)
+            .err()
+            .expect("Unsigned message accepted as signed");
+        assert_eq!(err.kind(), ErrorKind::CommunicationMessage);
+    }
+
+    #[test]
+    fn test_serialize_framed_into_matches_write_to() {
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let (from, to) = (NodeId::from(0u32), NodeId::from(1u32));
+
+        for payload in [&b""[..], &b"payload"[..]] {
+            let digest = digest(payload);
+            let wm = WireMessage::new(from, to, payload, 42, Some(digest), Some(&sk));
+
+            let mut written = Vec::new();
+            futures::executor::block_on(wm.write_to(&mut written)).unwrap();
+
+            // previous contents of the buffer are kept
+            let mut framed = vec![0xff];
+            wm.serialize_framed_into(&mut framed).unwrap();
+            assert_eq!(framed[0], 0xff);
+            assert_eq!(&framed[1..], &written[..]);
+            assert_eq!(framed.len(), 1 + Header::LENGTH + payload.len());
+        }
+    }
+
+    #[test]
+    fn test_header_to_bytes() {
+        let old_header = Header {
+            signature: [7; Signature::LENGTH],
+            digest: Digest::from_bytes(&[3; Digest::MAX_LENGTH][..]).unwrap(),
+            ..forge_header(1, 2, 1234, 56)
+        };
+        assert_eq!(old_header, round_trip(&old_header));
+    }
+
+    #[test]
+    fn test_consensus_message_to_bytes() {
+        let kinds = vec![
+            ConsensusMessageKind::PrePrepare(vec![digest(b"a"), digest(b"b")]),
+            ConsensusMessageKind::PrePrepare(Vec::new()),
+            ConsensusMessageKind::Prepare(digest(b"c")),
+            ConsensusMessageKind::Commit(digest(b"d")),
+        ];
+        for kind in kinds {
+            let old_message = ConsensusMessage::new(SeqNo::from(5), SeqNo::from(2), kind);
+            let new_message = round_trip(&old_message);
+            assert_eq!(old_message.sequence_number(), new_message.sequence_number());
+            assert_eq!(old_message.view(), new_message.view());
+            match (old_message.kind(), new_message.kind()) {
+                (ConsensusMessageKind::PrePrepare(a), ConsensusMessageKind::PrePrepare(b)) => {
+                    assert!(a == b)
+                }
+                (ConsensusMessageKind::Prepare(a), ConsensusMessageKind::Prepare(b)) => {
+                    assert!(a == b)
+                }
+                (ConsensusMessageKind::Commit(a), ConsensusMessageKind::Commit(b)) => {
+                    assert!(a == b)
+                }
+                _ => panic!("Consensus message kind changed"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_consensus_message_invalid_kind() {
+        let mut buf = Vec::new();
+        SeqNo::from(0).to_bytes(&mut buf).unwrap();
+        SeqNo::from(0).to_bytes(&mut buf).unwrap();
+        buf.push(3);
+        assert!(ConsensusMessage::from_bytes(&mut &buf[..]).is_err());
+    }
+
+    #[test]
+    fn test_request_message_to_bytes() {
+        let old_request = RequestMessage::new(SeqNo::from(7), 0xcafe_u64);
+        let new_request = round_trip(&old_request);
+        assert_eq!(old_request.sequence_number(), new_request.sequence_number());
+        assert_eq!(old_request.operation(), new_request.operation());
+        assert!(new_request.serialized().is_none());
+
+        let old_request = old_request.with_trace_id(Some(TraceId::from(42)));
+        let new_request = round_trip(&old_request);
+        assert_eq!(new_request.trace_id(), Some(TraceId::from(42)));
+        assert!(new_request.metadata().is_none());
+
+        for deadline in [None, Some(Duration::new(3, 250))] {
+            let metadata = Some(RequestMeta::new(5, deadline));
+            let old_request = old_request.clone().with_metadata(metadata);
+            let new_request = round_trip(&old_request);
+            assert_eq!(new_request.metadata().copied(), metadata);
+            assert_eq!(new_request.trace_id(), Some(TraceId::from(42)));
+        }
+    }
+
+    #[test]
+    fn test_request_message_invalid_deadline() {
+        let request = RequestMessage::new(SeqNo::from(7), 0xcafe_u64)
+            .with_metadata(Some(RequestMeta::new(5, Some(Duration::new(u64::MAX, 0)))));
+        let mut buf = Vec::new();
+        request.to_bytes(&mut buf).unwrap();
+
+        // overwrite the nanoseconds of the deadline, which
+        // precede the operation, with a whole second's worth
+        let at = buf.len() - std::mem::size_of::<u64>() - std::mem::size_of::<u32>();
+        buf[at..at + 4].copy_from_slice(&1_000_000_000u32.to_le_bytes());
+        let err = match RequestMessage::<u64>::from_bytes(&mut &buf[..]) {
+            Err(err) => err,
+            Ok(_) => panic!("Deadline with excess nanoseconds accepted"),
+        };
+        assert_eq!(err.kind(), ErrorKind::CommunicationMessage);
+    }
 }
@@ -1,38 +1,66 @@
 //! Communication primitives for `bafomet`, such as wire message formats.
 
 pub mod channel;
+pub mod compress;
+pub mod idle_reaper;
 pub mod message;
+pub mod peer_auth;
+pub mod peer_writer;
+pub mod reconnect;
 pub mod serialize;
+pub mod shard;
+pub mod shutdown;
 pub mod socket;
 
 #[cfg(feature = "serialize_serde")]
 use serde::{Deserialize, Serialize};
 
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
 use async_tls::{
     client::TlsStream as TlsStreamCli, server::TlsStream as TlsStreamSrv, TlsAcceptor, TlsConnector,
 };
+use bytes::{Bytes, BytesMut};
 use either::{Either, Left, Right};
-use futures::io::{AsyncReadExt, AsyncWriteExt};
+use futures::channel::{mpsc, oneshot};
+use futures::future::BoxFuture;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite};
 use futures::lock::Mutex;
+use futures::stream::FuturesOrdered;
+use futures::{select, FutureExt, StreamExt};
 use futures_timer::Delay;
 use parking_lot::RwLock;
 use rustls::{ClientConfig, ServerConfig};
 use smallvec::SmallVec;
 
 use crate::bft::async_runtime as rt;
-use crate::bft::collections::{self, HashMap};
+use crate::bft::collections::{self, HashMap, HashSet};
 use crate::bft::communication::channel::{new_message_channel, MessageChannelRx, MessageChannelTx};
-use crate::bft::communication::message::{Header, Message, SystemMessage, WireMessage};
-use crate::bft::communication::serialize::{Buf, DigestData, SharedData};
-use crate::bft::communication::socket::{Listener, Socket};
-use crate::bft::crypto::hash::Digest;
+use crate::bft::communication::compress::Codec;
+use crate::bft::communication::idle_reaper::IdleReaper;
+use crate::bft::communication::message::{
+    CstMessageKind, Header, Message, SignedWireMessage, SystemMessage, UnsignedWireMessage,
+    WireMessage,
+};
+use crate::bft::communication::peer_auth::PeerAuth;
+use crate::bft::communication::peer_writer::{PeerWriter, WriteQueueConfig};
+use crate::bft::communication::reconnect::Backoff;
+use crate::bft::communication::serialize::{Buf, BufPool, DigestData, SharedData, WIRE_TAG_LENGTH};
+use crate::bft::communication::shard::{RootLink, ShardId};
+use crate::bft::communication::shutdown::{Shutdown, ShutdownListener, Terminated};
+use crate::bft::communication::socket::{
+    DefaultProvider, Listener, Socket, SocketOpts, SocketProvider,
+};
+use crate::bft::core::SystemParams;
+use crate::bft::crypto::hash::{Digest, DigestHasher};
 use crate::bft::crypto::signature::{KeyPair, PublicKey};
 use crate::bft::error::*;
 use crate::bft::prng;
+use crate::bft::threadpool::ThreadPool;
 
 /// A `NodeId` represents the id of a process in the BFT system.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
@@ -54,6 +82,21 @@ impl NodeId {
     {
         into_iterator.into_iter().map(NodeId::from)
     }
+
+    /// Checks if this `NodeId` belongs to a client, given the
+    /// id of the first client in the system.
+    #[inline]
+    pub fn is_client(self, first_cli: NodeId) -> bool {
+        self >= first_cli
+    }
+
+    /// Checks if this `NodeId` belongs to an observer, i.e. a node
+    /// following the decided batches without voting, given the number
+    /// of replicas `n` and the id of the first client in the system.
+    #[inline]
+    pub fn is_observer(self, n: usize, first_cli: NodeId) -> bool {
+        self >= NodeId::from(n) && self < first_cli
+    }
 }
 
 impl From<u32> for NodeId {
@@ -88,19 +131,261 @@ impl From<NodeId> for u32 {
 // hashmap between two async tasks on the client
 #[derive(Clone)]
 enum PeerTx {
-    // clients need shared access to the hashmap; the `Arc` on the second
-    // lock allows us to take ownership of a copy of the socket, so we
-    // don't block the thread with the guard of the first lock waiting
-    // on the second one
-    Client(Arc<RwLock<HashMap<NodeId, Arc<Mutex<TlsStreamCli<Socket>>>>>>),
-    // replicas don't need shared access to the hashmap, so
+    // clients, and replicas split into shards, need shared access to
+    // the hashmap; the `Arc` on the second lock allows us to take
+    // ownership of a copy of the socket, so we don't block the thread
+    // with the guard of the first lock waiting on the second one
+    Client(Arc<RwLock<HashMap<NodeId, PeerSock>>>),
+    // other replicas don't need shared access to the hashmap, so
     // we only need one lock (to restrict I/O to one producer at a time)
-    Server(HashMap<NodeId, Arc<Mutex<TlsStreamCli<Socket>>>>),
+    Server(HashMap<NodeId, PeerSock>),
+}
+
+// a handle used to write to the socket of a peer node, along with
+// the codec the payloads written to the socket are compressed with
+#[derive(Clone)]
+enum PeerSock {
+    // the tasks sending messages take turns writing to the socket
+    Locked(Arc<Mutex<TlsStreamCli<Socket>>>, Codec),
+    // a dedicated task writes the frames queued by the sending tasks
+    Queued(PeerWriter, Codec),
+}
+
+impl PeerSock {
+    // the codec negotiated for the connection to the peer
+    fn codec(&self) -> Codec {
+        match self {
+            PeerSock::Locked(_, codec) | PeerSock::Queued(_, codec) => *codec,
+        }
+    }
+
+    // the handle to the write queue of the socket, if it has one
+    fn writer(&self) -> Option<&PeerWriter> {
+        match self {
+            PeerSock::Queued(writer, _) => Some(writer),
+            PeerSock::Locked(..) => None,
+        }
+    }
+}
+
+/// The kind of traffic carried by a connection to a peer node.
+///
+/// If `NodeConfig::bulk_link` is set, replicas open a connection
+/// of each kind to every other replica, such that large transfers
+/// don't delay the latency sensitive messages, and vice versa.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LinkKind {
+    /// Small, latency sensitive messages, such as votes.
+    ///
+    /// These connections have Nagle's algorithm disabled.
+    Interactive,
+    /// Large, throughput sensitive messages, such as states.
+    ///
+    /// These connections have Nagle's algorithm enabled.
+    Bulk,
+}
+
+impl LinkKind {
+    /// Returns the kind of connection `message` should be sent over.
+    pub fn of<S, O, P>(message: &SystemMessage<S, O, P>) -> Self {
+        match message {
+            SystemMessage::Cst(m)
+                if matches!(
+                    m.kind(),
+                    CstMessageKind::ReplyState(_) | CstMessageKind::ReplyChunk(_)
+                ) =>
+            {
+                LinkKind::Bulk
+            }
+            _ => LinkKind::Interactive,
+        }
+    }
+
+    /// Returns the value of the `TCP_NODELAY` option of
+    /// the sockets of connections of this kind.
+    pub fn nodelay(self) -> bool {
+        match self {
+            LinkKind::Interactive => true,
+            LinkKind::Bulk => false,
+        }
+    }
+}
+
+// returns the value in `interactive` or `bulk` associated with
+// `peer_id`, depending on the kind of link messages are sent over;
+// if we aren't connected to the peer over a bulk link, we fall
+// back to the interactive link, and if we aren't connected to
+// the peer at all, e.g. its link dropped, `None` is returned
+fn link_for<'a, T>(
+    kind: LinkKind,
+    peer_id: NodeId,
+    interactive: &'a HashMap<NodeId, T>,
+    bulk: &'a HashMap<NodeId, T>,
+) -> Option<&'a T> {
+    match kind {
+        LinkKind::Bulk => bulk.get(&peer_id).or_else(|| interactive.get(&peer_id)),
+        LinkKind::Interactive => interactive.get(&peer_id),
+    }
+}
+
+/// The directions in which a `Node` is connected to a peer node.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConnState {
+    /// We can send messages to the peer, but not receive them.
+    TxOnly,
+    /// We can receive messages from the peer, but not send them.
+    RxOnly,
+    /// We are connected to the peer in both directions.
+    Connected,
+}
+
+/// A change in the connection to a peer node, delivered to the
+/// subscribers of `Node::subscribe_connections()`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ConnEvent {
+    /// The peer node whose connection changed.
+    pub peer_id: NodeId,
+    /// The new state of the connection, or `None`, if we
+    /// lost contact with the peer in both directions.
+    pub state: Option<ConnState>,
+}
+
+// tracks in which directions we are connected to each peer
+struct PeerLinks {
+    tx: HashSet<NodeId>,
+    rx: HashSet<NodeId>,
+    // notified of every change in the state of a connection
+    subscribers: Vec<mpsc::UnboundedSender<ConnEvent>>,
 }
 
 struct NodeShared {
     my_key: KeyPair,
     peer_keys: HashMap<NodeId, PublicKey>,
+    // no. of client messages dropped because
+    // the inbound channel was saturated
+    dropped_client_msgs: AtomicU64,
+    // no. of messages dropped during bootstrap, because
+    // the buffer of rogue messages was full
+    dropped_rogue_msgs: AtomicU64,
+    // buffers reused to serialize sent messages
+    send_bufs: BufPool,
+    // tracks the activity of client connections, if
+    // idle client connections are reaped
+    reaper: Option<IdleReaper>,
+    // whether the nonce of a message is mixed into
+    // the digest returned when sending it
+    digest_entropy: bool,
+    // the maximum amount of time spent writing
+    // a message to the socket of a peer
+    write_timeout: Option<Duration>,
+    // the no. of messages of each peer being deserialized
+    // on the thread pool, across all of its connections
+    deserializing: RwLock<HashMap<NodeId, Arc<AtomicUsize>>>,
+    // the no. of messages being sent by detached tasks,
+    // which haven't been handed to a socket yet
+    sending: Arc<AtomicUsize>,
+}
+
+impl NodeShared {
+    // the no. of messages of `peer_id` being deserialized
+    fn deserializing(&self, peer_id: NodeId) -> Arc<AtomicUsize> {
+        if let Some(counter) = self.deserializing.read().get(&peer_id) {
+            return Arc::clone(counter);
+        }
+        let mut deserializing = self.deserializing.write();
+        Arc::clone(deserializing.entry(peer_id).or_default())
+    }
+
+    // the digests of a message sent with the given digest and nonce
+    fn sent_digests(&self, digest: Digest, nonce: u64) -> SentDigests {
+        let unique = digest.entropy(nonce.to_le_bytes());
+        let returned = if self.digest_entropy { unique } else { digest };
+        SentDigests { returned, unique }
+    }
+}
+
+// the digests of a message being sent: the one returned to the caller,
+// as per `NodeConfig::digest_entropy`, and `Header::unique_digest()`
+#[derive(Copy, Clone)]
+struct SentDigests {
+    returned: Digest,
+    unique: Digest,
+}
+
+impl PeerLinks {
+    fn new() -> Self {
+        Self {
+            tx: collections::hash_set(),
+            rx: collections::hash_set(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    fn is_connected(&self, peer_id: NodeId) -> bool {
+        self.tx.contains(&peer_id) && self.rx.contains(&peer_id)
+    }
+
+    fn state(&self, peer_id: NodeId) -> Option<ConnState> {
+        match (self.tx.contains(&peer_id), self.rx.contains(&peer_id)) {
+            (true, true) => Some(ConnState::Connected),
+            (true, false) => Some(ConnState::TxOnly),
+            (false, true) => Some(ConnState::RxOnly),
+            (false, false) => None,
+        }
+    }
+
+    fn status(&self) -> HashMap<NodeId, ConnState> {
+        let mut status = collections::hash_map();
+        for &peer_id in self.tx.iter().chain(self.rx.iter()) {
+            if let Some(state) = self.state(peer_id) {
+                status.insert(peer_id, state);
+            }
+        }
+        status
+    }
+
+    fn subscribe(&mut self) -> mpsc::UnboundedReceiver<ConnEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    // applies `update` to the links of `peer_id`, notifying
+    // the subscribers if the state of the connection changed
+    fn update<F>(&mut self, peer_id: NodeId, update: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let before = self.state(peer_id);
+        update(self);
+        let state = self.state(peer_id);
+        if before != state {
+            let event = ConnEvent { peer_id, state };
+            // drop the subscribers who went away
+            self.subscribers
+                .retain(|subscriber| subscriber.unbounded_send(event).is_ok());
+        }
+    }
+
+    // checks if the node `my_id` has at least one link in each direction
+    // to every other replica in `members`; duplicate links, e.g. due to
+    // reconnections, are only counted once, so they can't keep
+    // bootstrapping from completing
+    fn replicas_connected(&self, my_id: NodeId, members: &[NodeId]) -> bool {
+        members
+            .iter()
+            .all(|&peer_id| peer_id == my_id || self.is_connected(peer_id))
+    }
+
+    // checks if the node `my_id` is connected in both directions
+    // to `quorum` replicas, counting itself if it is a replica
+    fn quorum_connected(&self, my_id: NodeId, members: &[NodeId], quorum: usize) -> bool {
+        let connected = members
+            .iter()
+            .filter(|&&peer_id| peer_id == my_id || self.is_connected(peer_id))
+            .count();
+        connected >= quorum
+    }
 }
 
 /// Container for handles to other processes in the system.
@@ -109,14 +394,41 @@ struct NodeShared {
 /// communication between processes.
 pub struct Node<D: SharedData> {
     id: NodeId,
+    // the ids of the replicas of the system
+    members: Vec<NodeId>,
+    f: usize,
     first_cli: NodeId,
     my_tx: MessageChannelTx<D::State, D::Request, D::Reply>,
     my_rx: MessageChannelRx<D::State, D::Request, D::Reply>,
-    rng: prng::State,
+    nonces: Nonces,
     shared: Arc<NodeShared>,
     peer_tx: PeerTx,
+    // the sockets of the bulk links to other replicas
+    bulk_tx: HashMap<NodeId, PeerSock>,
     connector: TlsConnector,
     peer_addrs: HashMap<NodeId, (SocketAddr, String)>,
+    connect_retries: usize,
+    pool: ThreadPool,
+    deserialize_timeout: Duration,
+    verify_parallelism: usize,
+    socket_opts: SocketOpts,
+    socket_provider: Arc<dyn SocketProvider>,
+    write_queue: Option<WriteQueueConfig>,
+    links: PeerLinks,
+    reconnect: Option<Backoff>,
+    // the replicas whose links are being re-established
+    reconnecting: HashSet<NodeId>,
+    // signals the tasks accepting and reading connections to exit
+    shutdown: Shutdown,
+    // the bound of the queues of the master channel
+    channel_bound: usize,
+    // the shard the messages we send are tagged with
+    shard: ShardId,
+    // the `Node` this shard was split from, which owns the connections
+    root: Option<RootLink<D>>,
+    // the codecs offered, or accepted, in the handshakes of
+    // our connections, as a bit mask; see `Codec::offer()`
+    codecs: u8,
 }
 
 /// Represents a configuration used to bootstrap a `Node`.
@@ -134,7 +446,9 @@ pub struct NodeConfig {
     pub id: NodeId,
     /// The first id assigned to a client`Node`.
     ///
-    /// Every other client id of the form `first_cli + i`.
+    /// Every other client id of the form `first_cli + i`. The ids
+    /// in between `n` and `first_cli` are assigned to observers,
+    /// which follow the decided batches without voting.
     pub first_cli: NodeId,
     /// The addresses of all nodes in the system (including clients),
     /// as well as the domain name associated with each address.
@@ -150,786 +464,4516 @@ pub struct NodeConfig {
     pub client_config: ClientConfig,
     /// The TLS configuration used to accept connections from peer nodes.
     pub server_config: ServerConfig,
+    /// The maximum number of messages allowed in each of the
+    /// queues of the message channel of this `Node`.
+    pub channel_bound: usize,
+    /// The number of attempts made to connect to a peer node, spaced
+    /// one second apart, before reporting it as disconnected.
+    pub connect_retries: usize,
+    /// The thread pool used to deserialize the messages received
+    /// from peer nodes, off the async runtime.
+    pub pool: ThreadPool,
+    /// The maximum amount of time allowed to deserialize a single
+    /// message received from a peer node. If it is exceeded, the
+    /// connection to that node is dropped.
+    ///
+    /// The job deserializing the message keeps running on the pool
+    /// until it completes, though. Hence, at most `verify_parallelism`
+    /// messages of a peer are deserialized at a time, counting those of
+    /// its previous connections; reading another one drops the connection.
+    pub deserialize_timeout: Duration,
+    /// Whether peer nodes must present a client certificate valid for
+    /// the domain name associated with their id in `addrs`.
+    ///
+    /// If set, a connection whose certificate doesn't match the id
+    /// claimed by the peer in its handshake is dropped. Setting it
+    /// requires the `peer_auth` feature, and a `server_config` whose
+    /// client certificate verifier asks peers for a certificate.
+    pub authenticate_peers: bool,
+    /// The configuration of the write queues of the sockets of peer nodes.
+    ///
+    /// If set, the messages sent to each peer are written by a dedicated
+    /// task, rather than by the sending tasks taking turns on its socket.
+    pub write_queue: Option<WriteQueueConfig>,
+    /// The maximum number of buffers kept for reuse, after serializing
+    /// the messages sent by this `Node` into them.
+    pub send_buf_pool: usize,
+    /// The maximum number of rogue messages, i.e. messages not pertaining
+    /// to the bootstrapping protocol, returned by `Node::bootstrap()`.
+    ///
+    /// Messages received past this bound are dropped, since they will
+    /// be superseded by the time bootstrapping completes anyway.
+    pub rogue_bound: usize,
+    /// The maximum amount of time a client connection of a replica may
+    /// stay idle, i.e. without receiving any message, before it is closed.
+    ///
+    /// If unset, client connections are kept until they error. The
+    /// connections to other replicas are never reaped.
+    pub client_idle_timeout: Option<Duration>,
+    /// Whether replicas open a second connection to each other replica,
+    /// reserved for bulk traffic, such as states.
+    ///
+    /// If set, the connections carrying the remaining messages, such as
+    /// votes, have Nagle's algorithm disabled, while the bulk connections
+    /// have it enabled. Otherwise, a single connection with the default
+    /// socket options is used per peer.
+    pub bulk_link: bool,
+    /// Whether the digests returned by `send()` and `broadcast()` have
+    /// the nonce of the message mixed into them, i.e. are equal to
+    /// `Header::unique_digest()`.
+    ///
+    /// If unset, the returned digests are the digests of the contents
+    /// of the messages, i.e. the output of `DigestData::serialize_digest()`,
+    /// which may be matched against digests computed by the application.
+    /// The nonces are still sent along with the messages. Clients match
+    /// replies against the unique digests of their requests either way,
+    /// so the digests of `RequestReceipt`s are the only ones affected.
+    pub digest_entropy: bool,
+    /// The maximum amount of time spent writing a message to the
+    /// connection of a peer, before giving up on the connection.
+    ///
+    /// Writes timing out are handled like write errors, i.e. the
+    /// connection is dropped, so that a stalled peer doesn't hang
+    /// every task sending messages to it. If unset, writes may block
+    /// for as long as the peer is not reading from its connection.
+    /// With a `NodeConfig::write_queue`, the timeout applies to each
+    /// write of the frames coalesced by the writer task of the peer.
+    pub write_timeout: Option<Duration>,
+    /// The maximum number of messages read from a single peer whose
+    /// signatures are verified concurrently, on the `ThreadPool`.
+    ///
+    /// Messages are still delivered in the order they were read from
+    /// the connection. A value of 1 verifies one message at a time.
+    pub verify_parallelism: usize,
+    /// The options applied to the sockets of this `Node`, both when
+    /// connecting to peers, and when accepting their connections.
+    pub socket_opts: SocketOpts,
+    /// The transport over which this `Node` listens for, and opens,
+    /// connections with its peers.
+    pub socket_provider: Arc<dyn SocketProvider>,
+    /// The delays between the attempts to reconnect to a replica,
+    /// after the link we send messages over to it dropped.
+    ///
+    /// If unset, dropped links to replicas are not re-established.
+    pub reconnect: Option<Backoff>,
+    /// The codecs this `Node` supports to compress the payloads sent
+    /// over its connections.
+    ///
+    /// The codec of each connection is negotiated during its handshake,
+    /// settling on the strongest codec supported by both ends, or on
+    /// `Codec::None`, if they have none in common. Defaults to every
+    /// codec in `Codec::available()`.
+    pub compression: Vec<Codec>,
 }
 
-// max no. of messages allowed in the channel
-const NODE_CHAN_BOUND: usize = 128;
-
-// max no. of SendTo's to inline before doing a heap alloc
-const NODE_VIEWSIZ: usize = 8;
-
-type SendTos<D> = SmallVec<[SendTo<D>; NODE_VIEWSIZ]>;
+/// Helper type used to construct a new `NodeConfig`.
+///
+/// The tunables of the communication layer have defaults,
+/// but every other field must be set before calling `build()`.
+#[derive(Default)]
+pub struct NodeConfigBuilder {
+    n: Option<usize>,
+    f: Option<usize>,
+    id: Option<NodeId>,
+    first_cli: Option<NodeId>,
+    addrs: Option<HashMap<NodeId, (SocketAddr, String)>>,
+    pk: Option<HashMap<NodeId, PublicKey>>,
+    sk: Option<KeyPair>,
+    client_config: Option<ClientConfig>,
+    server_config: Option<ServerConfig>,
+    channel_bound: Option<usize>,
+    connect_retries: Option<usize>,
+    pool: Option<ThreadPool>,
+    deserialize_timeout: Option<Duration>,
+    authenticate_peers: Option<bool>,
+    write_queue: Option<WriteQueueConfig>,
+    send_buf_pool: Option<usize>,
+    rogue_bound: Option<usize>,
+    client_idle_timeout: Option<Duration>,
+    bulk_link: Option<bool>,
+    digest_entropy: Option<bool>,
+    write_timeout: Option<Duration>,
+    verify_parallelism: Option<usize>,
+    socket_opts: Option<SocketOpts>,
+    socket_provider: Option<Arc<dyn SocketProvider>>,
+    reconnect: Option<Backoff>,
+    compression: Option<Vec<Codec>>,
+}
 
-impl<D> Node<D>
-where
-    D: SharedData + 'static,
-    D::State: Send + Clone + 'static,
-    D::Request: Send + 'static,
-    D::Reply: Send + 'static,
-{
-    /// Bootstrap a `Node`, i.e. create connections between itself and its
-    /// peer nodes.
-    ///
-    /// Rogue messages (i.e. not pertaining to the bootstrapping protocol)
-    /// are returned in a `Vec`.
-    pub async fn bootstrap(
-        cfg: NodeConfig,
-    ) -> Result<(Self, Vec<Message<D::State, D::Request, D::Reply>>)> {
-        let id = cfg.id;
+// default tunables of a `NodeConfig` built with a `NodeConfigBuilder`
+const DEFAULT_CHANNEL_BOUND: usize = 1024;
+const DEFAULT_CONNECT_RETRIES: usize = 60;
+const DEFAULT_DESERIALIZE_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_SEND_BUF_POOL: usize = 64;
+const DEFAULT_ROGUE_BOUND: usize = 1024;
+const DEFAULT_VERIFY_PARALLELISM: usize = 1;
 
-        // initial checks of correctness
-        if cfg.n < (3 * cfg.f + 1) {
+impl NodeConfig {
+    /// Checks if this configuration is valid, e.g. if the number
+    /// of replicas is enough to tolerate `f` faults, or if the address
+    /// and the public key of every replica are known.
+    pub fn check(&self) -> Result<()> {
+        if self.n < (3 * self.f + 1) {
             return Err("Invalid number of replicas").wrapped(ErrorKind::Communication);
         }
-        if id >= NodeId::from(cfg.n) && id < cfg.first_cli {
-            return Err("Invalid node ID").wrapped(ErrorKind::Communication);
+        if self.first_cli < NodeId::from(self.n) {
+            return Err("Client ids overlap replica ids").wrapped(ErrorKind::Communication);
+        }
+        if !self.addrs.contains_key(&self.id) {
+            return Err("Missing the address of this node").wrapped(ErrorKind::Communication);
+        }
+        for id in NodeId::targets_u32(0..self.n as u32) {
+            if !self.addrs.contains_key(&id) {
+                let s = format!("Missing the address of replica {:?}", id);
+                return Err(s).wrapped(ErrorKind::Communication);
+            }
+            if !self.pk.contains_key(&id) {
+                let s = format!("Missing the public key of replica {:?}", id);
+                return Err(s).wrapped(ErrorKind::Communication);
+            }
+        }
+        if self.verify_parallelism == 0 {
+            return Err("No messages would be verified").wrapped(ErrorKind::Communication);
+        }
+        if self.authenticate_peers {
+            PeerAuth::check(&self.server_config)?;
+        }
+        if let Some(ref backoff) = self.reconnect {
+            if !(backoff.factor >= 1.0) {
+                return Err("Reconnection delays would shrink").wrapped(ErrorKind::Communication);
+            }
+            if backoff.base > backoff.max {
+                return Err("Reconnection delays start above their maximum")
+                    .wrapped(ErrorKind::Communication);
+            }
         }
+        if let Some(codec) = self.compression.iter().find(|codec| !codec.is_available()) {
+            let s = format!("Codec {:?} not supported by this build", codec);
+            return Err(s).wrapped(ErrorKind::Communication);
+        }
+        Ok(())
+    }
+}
 
-        let listener = socket::bind(cfg.addrs[&id].0)
-            .await
-            .wrapped(ErrorKind::Communication)?;
+impl NodeConfigBuilder {
+    /// Returns a new `NodeConfig` builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let (tx, rx) = new_message_channel::<D::State, D::Request, D::Reply>(NODE_CHAN_BOUND);
-        let acceptor: TlsAcceptor = cfg.server_config.into();
-        let connector: TlsConnector = cfg.client_config.into();
+    /// Sets `NodeConfig::n`.
+    pub fn n(mut self, n: usize) -> Self {
+        self.n = Some(n);
+        self
+    }
 
-        // rx side (accept conns from replica)
-        rt::spawn(Self::rx_side_accept(
-            cfg.first_cli,
-            id,
-            listener,
-            acceptor,
-            tx.clone(),
-        ));
+    /// Sets `NodeConfig::f`.
+    pub fn f(mut self, f: usize) -> Self {
+        self.f = Some(f);
+        self
+    }
 
-        // tx side (connect to replica)
-        let mut rng = prng::State::new();
-        Self::tx_side_connect(
-            cfg.n as u32,
-            id,
-            connector.clone(),
-            tx.clone(),
-            &cfg.addrs,
-            &mut rng,
-        );
+    /// Sets `NodeConfig::id`.
+    pub fn id(mut self, id: NodeId) -> Self {
+        self.id = Some(id);
+        self
+    }
 
-        // node def
-        let peer_tx = if id >= cfg.first_cli {
-            PeerTx::Client(Arc::new(RwLock::new(collections::hash_map())))
-        } else {
-            PeerTx::Server(collections::hash_map())
-        };
-        let shared = Arc::new(NodeShared {
-            my_key: cfg.sk,
-            peer_keys: cfg.pk,
-        });
-        let mut node = Node {
-            id,
-            rng,
-            shared,
-            peer_tx,
-            my_tx: tx,
-            my_rx: rx,
-            connector,
-            peer_addrs: cfg.addrs,
-            first_cli: cfg.first_cli,
-        };
+    /// Sets `NodeConfig::first_cli`.
+    pub fn first_cli(mut self, first_cli: NodeId) -> Self {
+        self.first_cli = Some(first_cli);
+        self
+    }
 
-        // receive peer connections from channel
-        let mut rogue = Vec::new();
-        let mut c = vec![0; cfg.n];
+    /// Sets `NodeConfig::addrs`.
+    pub fn addrs(mut self, addrs: HashMap<NodeId, (SocketAddr, String)>) -> Self {
+        self.addrs = Some(addrs);
+        self
+    }
 
-        while c
-            .iter()
-            .enumerate()
-            .any(|(id, &n)| id != usize::from(node.id) && n != 2_i32)
-        {
-            let message = node.my_rx.recv().await.unwrap();
+    /// Sets `NodeConfig::pk`.
+    pub fn pk(mut self, pk: HashMap<NodeId, PublicKey>) -> Self {
+        self.pk = Some(pk);
+        self
+    }
 
-            match message {
-                Message::ConnectedTx(id, sock) => {
-                    node.handle_connected_tx(id, sock);
-                    if id < cfg.first_cli {
-                        // not a client connection, increase count
-                        c[usize::from(id)] += 1;
-                    }
-                }
-                Message::ConnectedRx(id, sock) => {
-                    node.handle_connected_rx(id, sock);
-                    if id < cfg.first_cli {
-                        // not a client connection, increase count
-                        c[usize::from(id)] += 1;
-                    }
-                }
-                Message::DisconnectedTx(NodeId(i)) => {
-                    let s = format!("Node {} disconnected from send side", i);
-                    return Err(s).wrapped(ErrorKind::Communication);
-                }
-                Message::DisconnectedRx(Some(NodeId(i))) => {
-                    let s = format!("Node {} disconnected from receive side", i);
-                    return Err(s).wrapped(ErrorKind::Communication);
-                }
-                Message::DisconnectedRx(None) => {
-                    let s = "Disconnected from receive side";
-                    return Err(s).wrapped(ErrorKind::Communication);
-                }
-                m => rogue.push(m),
-            }
-        }
+    /// Sets `NodeConfig::sk`.
+    pub fn sk(mut self, sk: KeyPair) -> Self {
+        self.sk = Some(sk);
+        self
+    }
 
-        // success
-        Ok((node, rogue))
+    /// Sets `NodeConfig::client_config`.
+    pub fn client_config(mut self, client_config: ClientConfig) -> Self {
+        self.client_config = Some(client_config);
+        self
     }
 
-    /// Returns the public key of the node with the given id `id`.
-    pub fn get_public_key(&self, id: NodeId) -> Option<&PublicKey> {
-        self.shared.peer_keys.get(&id)
+    /// Sets `NodeConfig::server_config`.
+    pub fn server_config(mut self, server_config: ServerConfig) -> Self {
+        self.server_config = Some(server_config);
+        self
     }
 
-    /// Reports the id of this `Node`.
-    pub fn id(&self) -> NodeId {
-        self.id
+    /// Sets `NodeConfig::channel_bound`, which defaults to 1024.
+    pub fn channel_bound(mut self, channel_bound: usize) -> Self {
+        self.channel_bound = Some(channel_bound);
+        self
     }
 
-    /// Returns a `SendNode` sharing the same handles as this `Node`.
-    pub fn send_node(&self) -> SendNode<D> {
-        SendNode {
-            id: self.id,
-            rng: prng::State::new(),
-            shared: Arc::clone(&self.shared),
-            peer_tx: self.peer_tx.clone(),
-            my_tx: self.my_tx.clone(),
-        }
+    /// Sets `NodeConfig::connect_retries`, which defaults to 60.
+    pub fn connect_retries(mut self, connect_retries: usize) -> Self {
+        self.connect_retries = Some(connect_retries);
+        self
     }
 
-    /// Returns a handle to the master channel of this `Node`.
-    pub fn master_channel(&self) -> MessageChannelTx<D::State, D::Request, D::Reply> {
-        self.my_tx.clone()
+    /// Sets `NodeConfig::pool`, which defaults to a new thread pool.
+    pub fn pool(mut self, pool: ThreadPool) -> Self {
+        self.pool = Some(pool);
+        self
     }
 
-    /// Send a `SystemMessage` to a single destination.
-    ///
-    /// This method is somewhat more efficient than calling `broadcast()`
-    /// on a single target id.
-    pub fn send(
-        &mut self,
-        message: SystemMessage<D::State, D::Request, D::Reply>,
-        target: NodeId,
-    ) -> Digest {
-        let send_to = Self::send_to(self.id, target, &self.shared, &self.my_tx, &self.peer_tx);
-        let my_id = self.id;
-        let nonce = self.rng.next_state();
-        Self::send_impl(message, send_to, my_id, target, nonce)
+    /// Sets `NodeConfig::deserialize_timeout`, which defaults to 1 second.
+    pub fn deserialize_timeout(mut self, deserialize_timeout: Duration) -> Self {
+        self.deserialize_timeout = Some(deserialize_timeout);
+        self
     }
 
-    #[inline]
-    fn send_impl(
-        message: SystemMessage<D::State, D::Request, D::Reply>,
-        mut send_to: SendTo<D>,
-        my_id: NodeId,
-        target: NodeId,
-        nonce: u64,
-    ) -> Digest {
-        // serialize
-        let mut buf: Buf = Buf::new();
-        let digest = <D as DigestData>::serialize_digest(&message, &mut buf).unwrap();
+    /// Sets `NodeConfig::authenticate_peers`, which defaults to `false`.
+    pub fn authenticate_peers(mut self, authenticate_peers: bool) -> Self {
+        self.authenticate_peers = Some(authenticate_peers);
+        self
+    }
 
-        rt::spawn(async move {
-            // send
-            if my_id == target {
-                // Right -> our turn
-                send_to.value(Right((message, nonce, digest, buf))).await;
-            } else {
-                // Left -> peer turn
-                send_to.value(Left((nonce, digest, buf))).await;
-            }
-        });
+    /// Sets `NodeConfig::write_queue`, which defaults to `None`.
+    pub fn write_queue(mut self, write_queue: WriteQueueConfig) -> Self {
+        self.write_queue = Some(write_queue);
+        self
+    }
 
-        digest.entropy(nonce.to_le_bytes())
+    /// Sets `NodeConfig::send_buf_pool`, which defaults to 64.
+    pub fn send_buf_pool(mut self, send_buf_pool: usize) -> Self {
+        self.send_buf_pool = Some(send_buf_pool);
+        self
     }
 
-    /// Broadcast a `SystemMessage` to a group of nodes.
-    pub fn broadcast(
-        &mut self,
-        message: SystemMessage<D::State, D::Request, D::Reply>,
-        targets: impl Iterator<Item = NodeId>,
-    ) -> Digest {
-        let (mine, others) =
-            Self::send_tos(self.id, &self.peer_tx, &self.my_tx, &self.shared, targets);
-        let nonce = self.rng.next_state();
-        Self::broadcast_impl(message, mine, others, nonce)
+    /// Sets `NodeConfig::rogue_bound`, which defaults to 1024.
+    pub fn rogue_bound(mut self, rogue_bound: usize) -> Self {
+        self.rogue_bound = Some(rogue_bound);
+        self
     }
 
-    #[inline]
-    fn broadcast_impl(
-        message: SystemMessage<D::State, D::Request, D::Reply>,
-        my_send_to: Option<SendTo<D>>,
-        other_send_tos: SendTos<D>,
-        nonce: u64,
-    ) -> Digest {
-        // serialize
-        let mut buf: Buf = Buf::new();
-        let digest = <D as DigestData>::serialize_digest(&message, &mut buf).unwrap();
-
-        rt::spawn(async move {
-            // send to ourselves
-            if let Some(mut send_to) = my_send_to {
-                let buf = buf.clone();
-                rt::spawn(async move {
-                    // Right -> our turn
-                    send_to.value(Right((message, nonce, digest, buf))).await;
-                });
-            }
-
-            // send to others
-            for mut send_to in other_send_tos {
-                let buf = buf.clone();
-                rt::spawn(async move {
-                    // Left -> peer turn
-                    send_to.value(Left((nonce, digest, buf))).await;
-                });
-            }
+    /// Sets `NodeConfig::client_idle_timeout`, which defaults to `None`.
+    pub fn client_idle_timeout(mut self, client_idle_timeout: Duration) -> Self {
+        self.client_idle_timeout = Some(client_idle_timeout);
+        self
+    }
 
-            // NOTE: an either enum is used, which allows
-            // rustc to prove only one task gets ownership
-            // of the `message`, i.e. `Right` = ourselves
-        });
+    /// Sets `NodeConfig::bulk_link`, which defaults to `false`.
+    pub fn bulk_link(mut self, bulk_link: bool) -> Self {
+        self.bulk_link = Some(bulk_link);
+        self
+    }
 
-        digest.entropy(nonce.to_le_bytes())
+    /// Sets `NodeConfig::digest_entropy`, which defaults to `true`.
+    pub fn digest_entropy(mut self, digest_entropy: bool) -> Self {
+        self.digest_entropy = Some(digest_entropy);
+        self
     }
 
-    #[inline]
-    fn send_tos(
-        my_id: NodeId,
-        peer_tx: &PeerTx,
-        tx: &MessageChannelTx<D::State, D::Request, D::Reply>,
-        shared: &Arc<NodeShared>,
-        targets: impl Iterator<Item = NodeId>,
-    ) -> (Option<SendTo<D>>, SendTos<D>) {
-        let mut my_send_to = None;
-        let mut other_send_tos = SendTos::new();
+    /// Sets `NodeConfig::write_timeout`, which defaults to `None`.
+    pub fn write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = Some(write_timeout);
+        self
+    }
 
-        match peer_tx {
-            PeerTx::Client(ref lock) => {
-                let map = lock.read();
-                Self::create_send_tos(
-                    my_id,
-                    tx,
-                    shared,
-                    &*map,
-                    targets,
-                    &mut my_send_to,
-                    &mut other_send_tos,
-                );
-            }
-            PeerTx::Server(ref map) => {
-                Self::create_send_tos(
-                    my_id,
-                    tx,
-                    shared,
-                    map,
-                    targets,
-                    &mut my_send_to,
-                    &mut other_send_tos,
-                );
-            }
-        };
+    /// Sets `NodeConfig::verify_parallelism`, which defaults to 1.
+    pub fn verify_parallelism(mut self, verify_parallelism: usize) -> Self {
+        self.verify_parallelism = Some(verify_parallelism);
+        self
+    }
 
-        (my_send_to, other_send_tos)
+    /// Sets `NodeConfig::socket_opts`, which defaults to `SocketOpts::default()`.
+    pub fn socket_opts(mut self, socket_opts: SocketOpts) -> Self {
+        self.socket_opts = Some(socket_opts);
+        self
     }
 
-    #[inline]
-    fn create_send_tos(
-        my_id: NodeId,
-        tx: &MessageChannelTx<D::State, D::Request, D::Reply>,
-        shared: &Arc<NodeShared>,
-        map: &HashMap<NodeId, Arc<Mutex<TlsStreamCli<Socket>>>>,
-        targets: impl Iterator<Item = NodeId>,
-        mine: &mut Option<SendTo<D>>,
-        others: &mut SendTos<D>,
-    ) {
-        for id in targets {
-            if id == my_id {
-                let s = SendTo::Me {
-                    my_id,
-                    tx: tx.clone(),
-                    shared: Arc::clone(shared),
-                };
-                *mine = Some(s);
-            } else {
-                let sock = Arc::clone(&map[&id]);
-                let s = SendTo::Peers {
-                    sock,
-                    my_id,
-                    peer_id: id,
-                    tx: tx.clone(),
-                    shared: Arc::clone(shared),
-                };
-                others.push(s);
-            }
-        }
+    /// Sets `NodeConfig::socket_provider`, which defaults to `DefaultProvider`.
+    pub fn socket_provider(mut self, socket_provider: Arc<dyn SocketProvider>) -> Self {
+        self.socket_provider = Some(socket_provider);
+        self
     }
 
-    #[inline]
-    fn send_to(
-        my_id: NodeId,
-        peer_id: NodeId,
-        shared: &Arc<NodeShared>,
-        tx: &MessageChannelTx<D::State, D::Request, D::Reply>,
-        peer_tx: &PeerTx,
-    ) -> SendTo<D> {
-        let tx = tx.clone();
-        let shared = Arc::clone(shared);
-        if my_id == peer_id {
-            SendTo::Me { shared, my_id, tx }
-        } else {
-            let sock = match peer_tx {
-                PeerTx::Client(ref lock) => {
-                    let map = lock.read();
-                    Arc::clone(&map[&peer_id])
-                }
-                PeerTx::Server(ref map) => Arc::clone(&map[&peer_id]),
-            };
-            SendTo::Peers {
-                sock,
-                shared,
-                peer_id,
-                my_id,
-                tx,
-            }
-        }
+    /// Sets `NodeConfig::reconnect`, which defaults to `None`.
+    pub fn reconnect(mut self, reconnect: Backoff) -> Self {
+        self.reconnect = Some(reconnect);
+        self
     }
 
-    /// Receive one message from peer nodes or ourselves.
-    pub async fn receive(&mut self) -> Result<Message<D::State, D::Request, D::Reply>> {
-        self.my_rx.recv().await
+    /// Sets `NodeConfig::compression`.
+    pub fn compression(mut self, compression: Vec<Codec>) -> Self {
+        self.compression = Some(compression);
+        self
     }
 
-    /// Method called upon a `Message::ConnectedTx`.
-    pub fn handle_connected_tx(&mut self, peer_id: NodeId, sock: TlsStreamCli<Socket>) {
-        match &mut self.peer_tx {
-            PeerTx::Server(ref mut peer_tx) => {
-                peer_tx.insert(peer_id, Arc::new(Mutex::new(sock)));
-            }
-            PeerTx::Client(ref lock) => {
-                let mut peer_tx = lock.write();
-                peer_tx.insert(peer_id, Arc::new(Mutex::new(sock)));
-            }
+    /// Returns the new `NodeConfig`, if all of its fields were set,
+    /// and it passes `NodeConfig::check()`.
+    pub fn build(self) -> Result<NodeConfig> {
+        fn required<T>(field: Option<T>, name: &str) -> Result<T> {
+            field
+                .ok_or_else(|| format!("Missing field `{}` in node config", name))
+                .wrapped(ErrorKind::Communication)
         }
+        let cfg = NodeConfig {
+            n: required(self.n, "n")?,
+            f: required(self.f, "f")?,
+            id: required(self.id, "id")?,
+            first_cli: required(self.first_cli, "first_cli")?,
+            addrs: required(self.addrs, "addrs")?,
+            pk: required(self.pk, "pk")?,
+            sk: required(self.sk, "sk")?,
+            client_config: required(self.client_config, "client_config")?,
+            server_config: required(self.server_config, "server_config")?,
+            channel_bound: self.channel_bound.unwrap_or(DEFAULT_CHANNEL_BOUND),
+            connect_retries: self.connect_retries.unwrap_or(DEFAULT_CONNECT_RETRIES),
+            pool: self
+                .pool
+                .unwrap_or_else(|| crate::bft::threadpool::Builder::new().build()),
+            deserialize_timeout: self
+                .deserialize_timeout
+                .unwrap_or(DEFAULT_DESERIALIZE_TIMEOUT),
+            authenticate_peers: self.authenticate_peers.unwrap_or(false),
+            write_queue: self.write_queue,
+            send_buf_pool: self.send_buf_pool.unwrap_or(DEFAULT_SEND_BUF_POOL),
+            rogue_bound: self.rogue_bound.unwrap_or(DEFAULT_ROGUE_BOUND),
+            client_idle_timeout: self.client_idle_timeout,
+            bulk_link: self.bulk_link.unwrap_or(false),
+            digest_entropy: self.digest_entropy.unwrap_or(true),
+            write_timeout: self.write_timeout,
+            verify_parallelism: self
+                .verify_parallelism
+                .unwrap_or(DEFAULT_VERIFY_PARALLELISM),
+            socket_opts: self.socket_opts.unwrap_or_default(),
+            socket_provider: self
+                .socket_provider
+                .unwrap_or_else(|| Arc::new(DefaultProvider::default())),
+            reconnect: self.reconnect,
+            compression: self.compression.unwrap_or_else(Codec::available),
+        };
+        cfg.check()?;
+        Ok(cfg)
     }
+}
 
-    /// Method called upon a `Message::ConnectedRx`.
-    pub fn handle_connected_rx(&mut self, peer_id: NodeId, mut sock: TlsStreamSrv<Socket>) {
-        // we are a server node
-        if let PeerTx::Server(ref peer_tx) = &self.peer_tx {
-            // the node whose conn we accepted is a client
-            // and we aren't connected to it yet
-            if peer_id >= self.first_cli && !peer_tx.contains_key(&peer_id) {
-                // fetch client address
-                //
-                // FIXME: this line can crash the program if the user
-                // provides an invalid HashMap
-                let addr = self.peer_addrs[&peer_id].clone();
-
-                // connect
-                let nonce = self.rng.next_state();
-                rt::spawn(Self::tx_side_connect_task(
-                    self.id,
-                    peer_id,
-                    nonce,
-                    self.connector.clone(),
-                    self.my_tx.clone(),
-                    addr,
-                ));
-            }
-        }
+// max no. of messages allowed in the channel of a loopback node
+#[cfg(test)]
+const LOOPBACK_CHAN_BOUND: usize = 128;
 
-        let mut tx = self.my_tx.clone();
+// the lengths of the payloads of the handshake of a connection, i.e.
+// the offer of the connecting node, and the reply of the accepting one
+const HANDSHAKE_OFFER_LENGTH: usize = 1;
+const HANDSHAKE_REPLY_LENGTH: usize = 1;
 
-        rt::spawn(async move {
-            let mut buf: Buf = Buf::new();
+// how often `Node::flush()` checks if the messages being
+// sent by detached tasks were handed to their sockets
+const FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(5);
 
-            // TODO
-            //  - verify signatures???
-            //  - exit condition (when the `Replica` or `Client` is dropped)
-            loop {
-                // reserve space for header
-                buf.clear();
-                buf.resize(Header::LENGTH, 0);
-
-                // read the peer's header
-                if let Err(_) = sock.read_exact(&mut buf[..Header::LENGTH]).await {
-                    // errors reading -> faulty connection;
-                    // drop this socket
-                    break;
-                }
+// max no. of SendTo's to inline before doing a heap alloc
+const NODE_VIEWSIZ: usize = 8;
 
-                // we are passing the correct length, safe to use unwrap()
-                let header = Header::deserialize_from(&buf[..Header::LENGTH]).unwrap();
+type SendTos<D> = SmallVec<[SendTo<D>; NODE_VIEWSIZ]>;
 
-                // reserve space for message
-                //
-                // FIXME: add a max bound on the message payload length;
-                // if the length is exceeded, reject connection;
-                // the bound can be application defined, i.e.
-                // returned by `SharedData`
-                buf.clear();
-                buf.reserve(header.payload_length());
-                buf.resize(header.payload_length(), 0);
+// tags the messages sent through `mine` and `others` with `shard`
+fn in_shard<D>(
+    shard: ShardId,
+    mine: Option<SendTo<D>>,
+    others: SendTos<D>,
+) -> (Option<SendTo<D>>, SendTos<D>)
+where
+    D: SharedData + 'static,
+    D::State: Send + Clone + 'static,
+    D::Request: Send + 'static,
+    D::Reply: Send + 'static,
+{
+    let mine = mine.map(|send_to| send_to.in_shard(shard));
+    let others = others
+        .into_iter()
+        .map(|send_to| send_to.in_shard(shard))
+        .collect();
+    (mine, others)
+}
 
-                // read the peer's payload
-                if let Err(_) = sock.read_exact(&mut buf[..header.payload_length()]).await {
-                    // errors reading -> faulty connection;
-                    // drop this socket
-                    break;
-                }
+/// The source of the nonces included in the headers of the
+/// messages sent by a `Node`.
+///
+/// By default, nonces are random numbers. With the `nonce_counter`
+/// feature, a monotonic counter seeded from the id of the `Node` is
+/// used instead, which is shared by all of its `SendNode` handles;
+/// this makes the hash digests returned by `send()` and `broadcast()`
+/// reproducible across runs, which is useful to debug the transport
+/// layer, but should never be enabled in production.
+struct Nonces {
+    #[cfg(not(feature = "nonce_counter"))]
+    inner: prng::State,
 
-                // deserialize payload
-                let message = match D::deserialize_message(&buf[..header.payload_length()]) {
-                    Ok(m) => m,
-                    Err(_) => {
-                        // errors deserializing -> faulty connection;
-                        // drop this socket
-                        break;
-                    }
-                };
+    #[cfg(feature = "nonce_counter")]
+    inner: Arc<AtomicU64>,
+}
 
-                tx.send(Message::System(header, message))
-                    .await
-                    .unwrap_or(());
+impl Nonces {
+    fn new(_id: NodeId) -> Self {
+        let inner = {
+            #[cfg(not(feature = "nonce_counter"))]
+            {
+                prng::State::new()
             }
 
-            // announce we have disconnected
-            tx.send(Message::DisconnectedRx(Some(peer_id)))
-                .await
-                .unwrap_or(());
-        });
+            #[cfg(feature = "nonce_counter")]
+            {
+                Arc::new(AtomicU64::new(u64::from(u32::from(_id)) << 32))
+            }
+        };
+        Nonces { inner }
+    }
+
+    // returns the nonce source of a new `SendNode`;
+    // with the `prng_thread_local` feature, we avoid reading
+    // entropy from the OS every time a `SendNode` is cloned
+    fn fork(&self) -> Self {
+        let inner = {
+            #[cfg(all(not(feature = "nonce_counter"), feature = "prng_thread_local"))]
+            {
+                prng::State::from_thread_local()
+            }
+
+            #[cfg(all(not(feature = "nonce_counter"), not(feature = "prng_thread_local")))]
+            {
+                prng::State::new()
+            }
+
+            #[cfg(feature = "nonce_counter")]
+            {
+                Arc::clone(&self.inner)
+            }
+        };
+        Nonces { inner }
     }
 
     #[inline]
-    fn tx_side_connect(
-        n: u32,
-        my_id: NodeId,
-        connector: TlsConnector,
-        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
-        addrs: &HashMap<NodeId, (SocketAddr, String)>,
-        rng: &mut prng::State,
-    ) {
-        for peer_id in NodeId::targets_u32(0..n).filter(|&id| id != my_id) {
-            let tx = tx.clone();
-            // FIXME: this line can crash the program if the user
-            // provides an invalid HashMap, maybe return a Result<()>
-            // from this function
-            let addr = addrs[&peer_id].clone();
-            let connector = connector.clone();
-            let nonce = rng.next_state();
-            rt::spawn(Self::tx_side_connect_task(
-                my_id, peer_id, nonce, connector, tx, addr,
-            ));
+    fn next(&mut self) -> u64 {
+        #[cfg(not(feature = "nonce_counter"))]
+        {
+            self.inner.next_state()
+        }
+
+        #[cfg(feature = "nonce_counter")]
+        {
+            self.inner.fetch_add(1, Ordering::Relaxed)
         }
     }
+}
 
-    async fn tx_side_connect_task(
-        my_id: NodeId,
-        peer_id: NodeId,
-        nonce: u64,
-        connector: TlsConnector,
-        mut tx: MessageChannelTx<D::State, D::Request, D::Reply>,
-        (addr, hostname): (SocketAddr, String),
-    ) {
-        const SECS: u64 = 1;
-        const RETRY: usize = 3 * 60;
-        // NOTE:
-        // ========
-        //
-        // 1) not an issue if `tx` is closed, this is not a
-        // permanently running task, so channel send failures
-        // are tolerated
-        //
-        // 2) try to connect up to `RETRY` times, then announce
-        // failure with a channel send op
-        for _ in 0..RETRY {
-            if let Ok(sock) = socket::connect(addr).await {
-                // TLS handshake; drop connection if it fails
-                let mut sock = match connector.connect(hostname, sock).await {
-                    Ok(s) => s,
-                    Err(_) => break,
-                };
+// orders the targets of a broadcast by ascending `NodeId`
+#[cfg(any(test, feature = "broadcast_ordered"))]
+fn in_target_order<T, F>(mine: Option<T>, others: impl IntoIterator<Item = T>, target: F) -> Vec<T>
+where
+    F: FnMut(&T) -> NodeId,
+{
+    let mut targets: Vec<T> = mine.into_iter().chain(others).collect();
+    targets.sort_by_key(target);
+    targets
+}
 
-                // create header
-                let (header, _) =
-                    WireMessage::new(my_id, peer_id, &[], nonce, None, None).into_inner();
+// wraps a message read from the connection of `peer_id` into a
+// `SignedWireMessage`, whose payload is digested with `H`; only the
+// handshake of a connection is unsigned, so every other message must
+// have been signed by the peer itself
+fn signed_by_peer<'a, H: DigestHasher>(
+    peer_keys: &HashMap<NodeId, PublicKey>,
+    peer_id: NodeId,
+    header: Header,
+    payload: &'a [u8],
+) -> Result<SignedWireMessage<'a>> {
+    if header.from() != peer_id {
+        return Err("Message not sent by the peer").wrapped(ErrorKind::Communication);
+    }
+    let public_key = peer_keys
+        .get(&peer_id)
+        .ok_or("Unknown peer public key")
+        .wrapped(ErrorKind::Communication)?;
+    SignedWireMessage::from_parts_hashed::<H>(header, payload, public_key)
+}
 
-                // serialize header
-                let mut buf = [0; Header::LENGTH];
-                header.serialize_into(&mut buf[..]).unwrap();
+// buffers a rogue message received while bootstrapping, unless
+// `bound` messages were buffered already, in which case it is dropped
+fn keep_rogue<M>(rogue: &mut Vec<M>, bound: usize, dropped: &AtomicU64, message: M) {
+    if rogue.len() < bound {
+        rogue.push(message);
+    } else {
+        dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
 
-                // send header
-                if let Err(_) = sock.write_all(&buf[..]).await {
-                    // errors writing -> faulty connection;
-                    // drop this socket
-                    break;
-                }
+// delivers a message read from a peer to the master channel;
+// replica traffic is never dropped, so we block until there is
+// room in the channel, but client traffic is shed under overload,
+// to avoid stalling the read task of the client's socket
+async fn deliver_rx<S, O, P>(
+    tx: &mut MessageChannelTx<S, O, P>,
+    dropped: &AtomicU64,
+    from_client: bool,
+    header: Header,
+    message: SystemMessage<S, O, P>,
+) {
+    let message = Message::System(header, message);
+    if !from_client {
+        tx.send(message).await.unwrap_or(());
+        return;
+    }
+    if let Ok(false) = tx.try_send(message) {
+        dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
 
-                // success
-                tx.send(Message::ConnectedTx(peer_id, sock))
-                    .await
-                    .unwrap_or(());
-                return;
-            }
-            // sleep for `SECS` seconds and retry
-            Delay::new(Duration::from_secs(SECS)).await;
-        }
-        // announce we have failed to connect to the peer node
-        tx.send(Message::DisconnectedTx(peer_id))
-            .await
-            .unwrap_or(());
+// checks if the client requests carried by `message` are well
+// formed, according to `SharedData::validate_request()`
+fn well_formed<D: SharedData>(message: &SystemMessage<D::State, D::Request, D::Reply>) -> bool {
+    match message {
+        SystemMessage::Request(m) => D::validate_request(m.operation()).is_ok(),
+        SystemMessage::ReadRequest(m) => D::validate_request(m.operation()).is_ok(),
+        _ => true,
     }
+}
 
-    // TODO: check if we have terminated the node, and exit
-    async fn rx_side_accept(
-        first_cli: NodeId,
-        my_id: NodeId,
-        listener: Listener,
-        acceptor: TlsAcceptor,
-        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
-    ) {
-        loop {
-            if let Ok(sock) = listener.accept().await {
-                let tx = tx.clone();
-                let acceptor = acceptor.clone();
-                rt::spawn(Self::rx_side_accept_task(
-                    first_cli, my_id, acceptor, sock, tx,
-                ));
-            }
-        }
+// accounts for a message of a peer node being deserialized, or for
+// a message being sent, for as long as the job handling it keeps running
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn enter(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(Arc::clone(counter))
     }
+}
 
-    // performs a cryptographic handshake with a peer node;
-    // header doesn't need to be signed, since we won't be
-    // storing this message in the log
-    async fn rx_side_accept_task(
-        first_cli: NodeId,
-        my_id: NodeId,
-        acceptor: TlsAcceptor,
-        sock: Socket,
-        mut tx: MessageChannelTx<D::State, D::Request, D::Reply>,
-    ) {
-        let mut buf_header = [0; Header::LENGTH];
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
-        // this loop is just a trick;
-        // the `break` instructions act as a `goto` statement
-        loop {
-            // TLS handshake; drop connection if it fails
-            let mut sock = match acceptor.accept(sock).await {
-                Ok(s) => s,
-                Err(_) => break,
-            };
-
-            // read the peer's header
-            if let Err(_) = sock.read_exact(&mut buf_header[..]).await {
-                // errors reading -> faulty connection;
-                // drop this socket
-                break;
+// deserializes a payload received from a peer node on the thread pool,
+// giving up after `timeout`, to bound the impact of malicious payloads
+// on the async runtime; note that the job itself can't be canceled, so
+// it keeps running in the background until it completes, and only then
+// is `in_flight` released
+async fn deserialize_bounded<D>(
+    pool: &ThreadPool,
+    timeout: Duration,
+    payload: Bytes,
+    in_flight: InFlight,
+) -> Result<SystemMessage<D::State, D::Request, D::Reply>>
+where
+    D: SharedData + 'static,
+    D::State: Send + 'static,
+    D::Request: Send + 'static,
+    D::Reply: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    pool.execute(move || {
+        let result = D::deserialize_wire(&payload[..]).map(|message| match message {
+            // retain the serialized requests, without their wire
+            // tag, to reuse them when they are decided
+            SystemMessage::Request(mut m) => {
+                m.set_serialized(payload.slice(WIRE_TAG_LENGTH..));
+                SystemMessage::Request(m)
             }
+            m => m,
+        });
+        // released before the result is delivered, so the
+        // rx task never sees a delivered message as in flight
+        drop(in_flight);
+        tx.send(result).unwrap_or(());
+    });
+    select! {
+        result = rx.fuse() => result.wrapped(ErrorKind::Communication)?,
+        _ = Delay::new(timeout).fuse() => {
+            Err("Timed out deserializing message").wrapped(ErrorKind::Communication)
+        },
+    }
+}
 
-            // we are passing the correct length, safe to use unwrap()
-            let header = Header::deserialize_from(&buf_header[..]).unwrap();
-
-            // extract peer id
-            let peer_id = match WireMessage::from_parts(header, &[]) {
-                // drop connections from other clis if we are a cli
-                Ok(wm) if wm.header().from() >= first_cli && my_id >= first_cli => break,
-                // drop connections to the wrong dest
-                Ok(wm) if wm.header().to() != my_id => break,
-                // accept all other conns
-                Ok(wm) => wm.header().from(),
-                // drop connections with invalid headers
-                Err(_) => break,
-            };
-
-            tx.send(Message::ConnectedRx(peer_id, sock))
-                .await
-                .unwrap_or(());
-            return;
-        }
+// the outcome of verifying and deserializing a message
+// read from the connection of a peer node
+enum Received<S, O, P> {
+    Message(Header, SystemMessage<S, O, P>),
+    // the message wasn't signed by the peer
+    Forged(Error),
+    // errors deserializing, or a payload taking too long to deserialize
+    Malformed,
+}
 
-        // announce we have failed to connect to the peer node
-        tx.send(Message::DisconnectedRx(None)).await.unwrap_or(());
+// verifies the signature of a message read from the connection of
+// `peer_id`, and then deserializes it, both on the thread pool
+async fn verify_and_deserialize<D>(
+    pool: ThreadPool,
+    shared: Arc<NodeShared>,
+    peer_id: NodeId,
+    header: Header,
+    payload: Bytes,
+    timeout: Duration,
+    in_flight: InFlight,
+) -> Received<D::State, D::Request, D::Reply>
+where
+    D: SharedData + 'static,
+    D::State: Send + 'static,
+    D::Request: Send + 'static,
+    D::Reply: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let signed = payload.clone();
+    pool.execute(move || {
+        let result = signed_by_peer::<D::Hasher>(&shared.peer_keys, peer_id, header, &signed[..])
+            .map(|_| ());
+        tx.send(result).unwrap_or(());
+    });
+    match rx.await {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => return Received::Forged(e),
+        Err(_) => return Received::Malformed,
+    }
+    match deserialize_bounded::<D>(&pool, timeout, payload, in_flight).await {
+        Ok(message) => Received::Message(header, message),
+        Err(_) => Received::Malformed,
     }
 }
 
-/// Represents a node with sending capabilities only.
-pub struct SendNode<D: SharedData> {
-    id: NodeId,
-    shared: Arc<NodeShared>,
-    rng: prng::State,
-    peer_tx: PeerTx,
-    my_tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+// the messages read from the connection of a peer which are still
+// being verified; at most `parallelism` of them are verified at a
+// time, and they are yielded in the order they were read, regardless
+// of the order in which their verification completes
+struct Verifying<S, O, P> {
+    parallelism: usize,
+    pending: FuturesOrdered<BoxFuture<'static, Received<S, O, P>>>,
 }
 
-impl<D: SharedData> Clone for SendNode<D> {
-    fn clone(&self) -> Self {
+impl<S, O, P> Verifying<S, O, P> {
+    fn new(parallelism: usize) -> Self {
         Self {
-            id: self.id,
-            rng: prng::State::new(),
-            shared: Arc::clone(&self.shared),
-            peer_tx: self.peer_tx.clone(),
-            my_tx: self.my_tx.clone(),
+            parallelism,
+            pending: FuturesOrdered::new(),
         }
     }
+
+    // checks if the earliest message must be yielded,
+    // before another one can be pushed
+    fn is_full(&self) -> bool {
+        self.pending.len() >= self.parallelism
+    }
+
+    fn push<F>(&mut self, received: F)
+    where
+        F: Future<Output = Received<S, O, P>> + Send + 'static,
+    {
+        self.pending.push_back(received.boxed());
+    }
 }
 
-impl<D> SendNode<D>
-where
-    D: SharedData + 'static,
-    D::State: Send + Clone + 'static,
-    D::Request: Send + 'static,
-    D::Reply: Send + 'static,
-{
-    /// Check the `send()` documentation for `Node`.
-    pub fn send(
-        &mut self,
-        message: SystemMessage<D::State, D::Request, D::Reply>,
-        target: NodeId,
-    ) -> Digest {
-        let send_to = <Node<D>>::send_to(self.id, target, &self.shared, &self.my_tx, &self.peer_tx);
-        let my_id = self.id;
-        let nonce = self.rng.next_state();
-        <Node<D>>::send_impl(message, send_to, my_id, target, nonce)
+// delivers a verified message read from the connection of `peer_id`;
+// returns `Some` if the connection should be dropped afterwards, along
+// with whether the peer said goodbye, or is otherwise faulty
+async fn deliver_received<D: SharedData>(
+    tx: &mut MessageChannelTx<D::State, D::Request, D::Reply>,
+    shared: &NodeShared,
+    peer_id: NodeId,
+    from_client: bool,
+    received: Received<D::State, D::Request, D::Reply>,
+) -> Option<bool> {
+    let (header, message) = match received {
+        Received::Message(header, message) => (header, message),
+        // unsigned messages, messages signed by another node,
+        // or payloads not matching their signed digest ->
+        // faulty connection; drop this socket
+        Received::Forged(e) => {
+            tx.send(Message::Error(peer_id, e)).await.unwrap_or(());
+            return Some(false);
+        }
+        // errors deserializing, or a payload taking too
+        // long to deserialize -> faulty connection
+        Received::Malformed => return Some(false),
+    };
+
+    // the peer is shutting down -> stop reading from the
+    // connection, rather than waiting for it to be closed
+    if let SystemMessage::Goodbye = message {
+        return Some(true);
     }
 
-    /// Check the `broadcast()` documentation for `Node`.
-    pub fn broadcast(
-        &mut self,
-        message: SystemMessage<D::State, D::Request, D::Reply>,
-        targets: impl Iterator<Item = NodeId>,
-    ) -> Digest {
-        let (mine, others) =
-            <Node<D>>::send_tos(self.id, &self.peer_tx, &self.my_tx, &self.shared, targets);
-        let nonce = self.rng.next_state();
-        <Node<D>>::broadcast_impl(message, mine, others, nonce)
+    if let Some(ref reaper) = shared.reaper {
+        if from_client {
+            reaper.touch(peer_id, Instant::now());
+        }
+    }
+
+    // malformed requests are dropped here, rather
+    // than being ordered and handed to the service
+    if well_formed::<D>(&message) {
+        deliver_rx(
+            tx,
+            &shared.dropped_client_msgs,
+            from_client,
+            header,
+            message,
+        )
+        .await;
     }
+    None
 }
 
-// helper type used when either a `send()` or a `broadcast()`
-// is called by a `Node` or `SendNode`.
+// writes `wm` to `w`, giving up after `timeout` elapses, if any;
+// returns whether the message was written successfully
 //
-// holds some data that can be shared between threads, relevant
-// to a network write operation, or channel write operation,
-// depending on whether we're sending a message to a peer node
-// or ourselves
-enum SendTo<D: SharedData> {
-    Me {
-        // our id
-        my_id: NodeId,
-        // shared data
-        shared: Arc<NodeShared>,
-        // a handle to our message channel
-        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
-    },
-    Peers {
-        // our id
-        my_id: NodeId,
-        // the id of the peer
-        peer_id: NodeId,
-        // shared data
-        shared: Arc<NodeShared>,
-        // handle to socket
-        sock: Arc<Mutex<TlsStreamCli<Socket>>>,
-        // a handle to our message channel
-        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
-    },
+// a timed out write may leave a partially written message in `w`,
+// so the connection must be dropped afterwards
+async fn write_bounded<W: AsyncWrite + Unpin>(
+    wm: &WireMessage<'_>,
+    w: W,
+    timeout: Option<Duration>,
+) -> bool {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return wm.write_to(w).await.is_ok(),
+    };
+    select! {
+        result = wm.write_to(w).fuse() => result.is_ok(),
+        _ = Delay::new(timeout).fuse() => false,
+    }
 }
 
-impl<D> SendTo<D>
+impl<D> Node<D>
 where
     D: SharedData + 'static,
     D::State: Send + Clone + 'static,
     D::Request: Send + 'static,
     D::Reply: Send + 'static,
 {
-    async fn value(
-        &mut self,
-        m: Either<
-            (u64, Digest, Buf),
-            (
-                SystemMessage<D::State, D::Request, D::Reply>,
-                u64,
-                Digest,
-                Buf,
-            ),
-        >,
-    ) {
-        match self {
-            SendTo::Me {
-                my_id,
-                shared: ref sh,
-                ref mut tx,
-            } => {
-                if let Right((m, n, d, b)) = m {
-                    Self::me(*my_id, m, n, d, b, &sh.my_key, tx).await
-                } else {
-                    // optimize code path
-                    unreachable!()
+    /// Bootstrap a `Node`, i.e. create connections between itself and its
+    /// peer nodes.
+    ///
+    /// Rogue messages (i.e. not pertaining to the bootstrapping protocol)
+    /// are returned in a `Vec`.
+    pub async fn bootstrap(
+        cfg: NodeConfig,
+    ) -> Result<(Self, Vec<Message<D::State, D::Request, D::Reply>>)> {
+        let id = cfg.id;
+
+        // initial checks of correctness
+        cfg.check()?;
+
+        let listener = cfg
+            .socket_provider
+            .bind(cfg.addrs[&id].0)
+            .await
+            .wrapped(ErrorKind::Communication)?;
+
+        let (tx, rx) = new_message_channel::<D::State, D::Request, D::Reply>(cfg.channel_bound);
+        let peer_auth = if cfg.authenticate_peers {
+            let auth = PeerAuth::new(cfg.server_config.clone(), &cfg.addrs)?;
+            Some(Arc::new(auth))
+        } else {
+            None
+        };
+        let acceptor: TlsAcceptor = cfg.server_config.into();
+        let connector: TlsConnector = cfg.client_config.into();
+
+        // rx side (accept conns from replica)
+        let codecs = Codec::offer(&cfg.compression);
+        let (shutdown, alive) = Shutdown::new();
+        rt::spawn(Self::rx_side_accept(
+            cfg.first_cli,
+            id,
+            codecs,
+            listener,
+            acceptor,
+            peer_auth,
+            cfg.socket_opts,
+            tx.clone(),
+            alive.clone(),
+        ));
+
+        // replicas take the ids below `n`
+        let members: Vec<_> = NodeId::targets(0..cfg.n).collect();
+
+        // tx side (connect to replica)
+        let mut nonces = Nonces::new(id);
+        Self::tx_side_connect(
+            &members,
+            id,
+            cfg.connect_retries,
+            connector.clone(),
+            tx.clone(),
+            &cfg.addrs,
+            &mut nonces,
+            cfg.bulk_link,
+            cfg.socket_opts,
+            &cfg.socket_provider,
+            codecs,
+        )?;
+
+        // node def
+        let peer_tx = if id >= cfg.first_cli {
+            PeerTx::Client(Arc::new(RwLock::new(collections::hash_map())))
+        } else {
+            PeerTx::Server(collections::hash_map())
+        };
+        let replica = members.contains(&id);
+        let shared = Arc::new(NodeShared {
+            my_key: cfg.sk,
+            peer_keys: cfg.pk,
+            dropped_client_msgs: AtomicU64::new(0),
+            dropped_rogue_msgs: AtomicU64::new(0),
+            send_bufs: BufPool::new(cfg.send_buf_pool),
+            reaper: cfg
+                .client_idle_timeout
+                .filter(|_| replica)
+                .map(IdleReaper::new),
+            digest_entropy: cfg.digest_entropy,
+            write_timeout: cfg.write_timeout,
+            deserializing: RwLock::new(collections::hash_map()),
+            sending: Arc::new(AtomicUsize::new(0)),
+        });
+        if let Some(ref reaper) = shared.reaper {
+            rt::spawn(Self::reap_idle_clients(
+                Arc::downgrade(&shared),
+                tx.clone(),
+                reaper.timeout() / 2,
+                alive,
+            ));
+        }
+        let mut node = Node {
+            id,
+            members,
+            f: cfg.f,
+            nonces,
+            shared,
+            peer_tx,
+            bulk_tx: collections::hash_map(),
+            my_tx: tx,
+            my_rx: rx,
+            connector,
+            peer_addrs: cfg.addrs,
+            first_cli: cfg.first_cli,
+            connect_retries: cfg.connect_retries,
+            pool: cfg.pool,
+            deserialize_timeout: cfg.deserialize_timeout,
+            verify_parallelism: cfg.verify_parallelism,
+            socket_opts: cfg.socket_opts,
+            socket_provider: cfg.socket_provider,
+            write_queue: cfg.write_queue,
+            links: PeerLinks::new(),
+            reconnect: cfg.reconnect,
+            reconnecting: collections::hash_set(),
+            shutdown,
+            channel_bound: cfg.channel_bound,
+            shard: ShardId::ZERO,
+            root: None,
+            codecs,
+        };
+
+        // receive peer connections from channel, until
+        // we are connected to every replica both ways
+        let mut rogue = Vec::new();
+
+        while !node.links.replicas_connected(id, &node.members) {
+            let message = node.my_rx.recv().await.unwrap();
+
+            match message {
+                Message::ConnectedTx(id, sock, codec) => node.handle_connected_tx(id, sock, codec),
+                Message::ConnectedBulkTx(id, sock, codec) => {
+                    node.handle_connected_bulk_tx(id, sock, codec)
                 }
-            }
-            SendTo::Peers {
-                my_id,
-                peer_id,
-                shared: ref sh,
-                ref sock,
-                ref mut tx,
-            } => {
-                if let Left((n, d, b)) = m {
-                    Self::peers(*my_id, *peer_id, n, d, b, &sh.my_key, &*sock, tx).await
-                } else {
-                    // optimize code path
-                    unreachable!()
+                Message::ConnectedRx(id, sock, codec) => node.handle_connected_rx(id, sock, codec),
+                Message::DisconnectedTx(NodeId(i)) => {
+                    let s = format!("Node {} disconnected from send side", i);
+                    return Err(s).wrapped(ErrorKind::Communication);
+                }
+                Message::DisconnectedRx(Some(NodeId(i))) => {
+                    let s = format!("Node {} disconnected from receive side", i);
+                    return Err(s).wrapped(ErrorKind::Communication);
+                }
+                Message::DisconnectedRx(None) => {
+                    let s = "Disconnected from receive side";
+                    return Err(s).wrapped(ErrorKind::Communication);
+                }
+                Message::Departed(NodeId(i)) => {
+                    let s = format!("Node {} shut down while bootstrapping", i);
+                    return Err(s).wrapped(ErrorKind::Communication);
                 }
+                m => keep_rogue(
+                    &mut rogue,
+                    cfg.rogue_bound,
+                    &node.shared.dropped_rogue_msgs,
+                    m,
+                ),
             }
         }
+
+        // success
+        Ok((node, rogue))
     }
 
-    async fn me(
-        my_id: NodeId,
-        m: SystemMessage<D::State, D::Request, D::Reply>,
-        n: u64,
-        d: Digest,
-        b: Buf,
-        sk: &KeyPair,
-        tx: &mut MessageChannelTx<D::State, D::Request, D::Reply>,
-    ) {
-        // create wire msg
-        let (h, _) = WireMessage::new(my_id, my_id, &b[..], n, Some(d), Some(sk)).into_inner();
+    /// Creates a `Node` which is only able to send messages to itself,
+    /// without binding any sockets, e.g. to run a single replica
+    /// (`n = 1, f = 0`) in unit tests.
+    #[cfg(test)]
+    pub(crate) fn loopback(id: NodeId, sk: KeyPair) -> Self {
+        let mut peer_keys: HashMap<NodeId, PublicKey> = collections::hash_map();
+        peer_keys.insert(id, sk.public_key().into());
 
-        // send
-        tx.send(Message::System(h, m)).await.unwrap_or(())
-    }
+        let (my_tx, my_rx) =
+            new_message_channel::<D::State, D::Request, D::Reply>(LOOPBACK_CHAN_BOUND);
+        let pool = crate::bft::threadpool::Builder::new()
+            .num_threads(1)
+            .build();
+        let shared = Arc::new(NodeShared {
+            my_key: sk,
+            peer_keys,
+            dropped_client_msgs: AtomicU64::new(0),
+            dropped_rogue_msgs: AtomicU64::new(0),
+            send_bufs: BufPool::new(DEFAULT_SEND_BUF_POOL),
+            reaper: None,
+            digest_entropy: true,
+            write_timeout: None,
+            deserializing: RwLock::new(collections::hash_map()),
+            sending: Arc::new(AtomicUsize::new(0)),
+        });
 
-    async fn peers(
-        my_id: NodeId,
-        peer_id: NodeId,
-        n: u64,
-        d: Digest,
-        b: Buf,
-        sk: &KeyPair,
-        lock: &Mutex<TlsStreamCli<Socket>>,
-        tx: &mut MessageChannelTx<D::State, D::Request, D::Reply>,
-    ) {
-        // create wire msg
-        let wm = WireMessage::new(my_id, peer_id, &b[..], n, Some(d), Some(sk));
+        Node {
+            id,
+            members: vec![NodeId::from(0u32)],
+            f: 0,
+            my_tx,
+            my_rx,
+            shared,
+            nonces: Nonces::new(id),
+            peer_tx: PeerTx::Server(collections::hash_map()),
+            bulk_tx: collections::hash_map(),
+            connector: ClientConfig::new().into(),
+            peer_addrs: collections::hash_map(),
+            first_cli: NodeId::from(1000u32),
+            connect_retries: 0,
+            pool,
+            deserialize_timeout: Duration::from_secs(1),
+            verify_parallelism: DEFAULT_VERIFY_PARALLELISM,
+            socket_opts: SocketOpts::default(),
+            socket_provider: Arc::new(DefaultProvider::default()),
+            write_queue: None,
+            links: PeerLinks::new(),
+            reconnect: None,
+            reconnecting: collections::hash_set(),
+            shutdown: Shutdown::new().0,
+            channel_bound: LOOPBACK_CHAN_BOUND,
+            shard: ShardId::ZERO,
+            root: None,
+            codecs: Codec::offer(&[Codec::None]),
+        }
+    }
+
+    /// Returns the public key of the node with the given id `id`.
+    pub fn get_public_key(&self, id: NodeId) -> Option<&PublicKey> {
+        self.shared.peer_keys.get(&id)
+    }
+
+    /// Trusts `pk` as the public key of `peer_id`, e.g. to verify the
+    /// messages of peers a loopback `Node` never connects to.
+    ///
+    /// Panics if the shared state of this `Node` was handed out already.
+    #[cfg(test)]
+    pub(crate) fn insert_peer_key(&mut self, peer_id: NodeId, pk: PublicKey) {
+        let shared = Arc::get_mut(&mut self.shared).expect("Node is shared");
+        shared.peer_keys.insert(peer_id, pk);
+    }
+
+    /// Returns the public keys of the peers of this `Node`, as
+    /// well as its own, indexed by their respective ids.
+    pub fn public_keys(&self) -> &HashMap<NodeId, PublicKey> {
+        &self.shared.peer_keys
+    }
+
+    /// Reports the id of this `Node`.
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Returns the ids of the replicas this `Node` connects to.
+    pub fn members(&self) -> &[NodeId] {
+        &self.members
+    }
+
+    /// Checks if the node with id `peer_id` is a client.
+    pub fn is_client(&self, peer_id: NodeId) -> bool {
+        peer_id.is_client(self.first_cli)
+    }
+
+    /// Returns a `SendNode` sharing the same handles as this `Node`.
+    pub fn send_node(&self) -> SendNode<D> {
+        SendNode {
+            id: self.id,
+            shard: self.shard,
+            nonces: self.nonces.fork(),
+            shared: Arc::clone(&self.shared),
+            peer_tx: self.peer_tx.clone(),
+            bulk_tx: self.bulk_tx.clone(),
+            my_tx: self.my_tx.clone(),
+        }
+    }
+
+    /// Returns a handle to the master channel of this `Node`.
+    pub fn master_channel(&self) -> MessageChannelTx<D::State, D::Request, D::Reply> {
+        self.my_tx.clone()
+    }
+
+    /// Send a `SystemMessage` to a single destination.
+    ///
+    /// This method is somewhat more efficient than calling `broadcast()`
+    /// on a single target id.
+    ///
+    /// Messages serialized into a payload longer than
+    /// `SharedData::max_payload_len()` are dropped before being written
+    /// to any socket, since the target would reject them anyway.
+    /// Use `try_send()` to be notified of this.
+    pub fn send(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> Digest {
+        let (digests, _) = self.send_checked(message, target);
+        digests.returned
+    }
+
+    /// Same as `send()`, but also returns `Header::unique_digest()` of
+    /// the message, regardless of `NodeConfig::digest_entropy`.
+    pub(crate) fn send_with_unique(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> (Digest, Digest) {
+        let (digests, _) = self.send_checked(message, target);
+        (digests.returned, digests.unique)
+    }
+
+    /// Same as `send()`, but returns an error if the message
+    /// was dropped for exceeding `SharedData::max_payload_len()`.
+    pub fn try_send(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> Result<Digest> {
+        let (digests, result) = self.send_checked(message, target);
+        result.map(|_| digests.returned)
+    }
+
+    fn send_checked(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> (SentDigests, Result<()>) {
+        let send_to = Self::send_to(
+            self.id,
+            target,
+            &self.shared,
+            &self.my_tx,
+            &self.peer_tx,
+            &self.bulk_tx,
+            LinkKind::of(&message),
+        )
+        .in_shard(self.shard);
+        let my_id = self.id;
+        let nonce = self.nonces.next();
+        Self::send_impl(message, send_to, my_id, target, nonce)
+    }
+
+    #[inline]
+    fn send_impl(
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        mut send_to: SendTo<D>,
+        my_id: NodeId,
+        target: NodeId,
+        nonce: u64,
+    ) -> (SentDigests, Result<()>) {
+        // serialize
+        let (digest, buf) = Self::serialize_bounded(send_to.shared(), &message);
+        let digests = send_to.shared().sent_digests(digest, nonce);
+        let buf = match buf {
+            Ok(buf) => buf,
+            Err(e) => return (digests, Err(e)),
+        };
+
+        rt::spawn(async move {
+            // send
+            if my_id == target {
+                // Right -> our turn
+                send_to
+                    .value(Right((message, nonce, digest, buf)))
+                    .await
+                    .unwrap_or(());
+            } else {
+                // Left -> peer turn
+                send_to
+                    .value(Left((nonce, digest, buf)))
+                    .await
+                    .unwrap_or(());
+            }
+        });
+
+        (digests, Ok(()))
+    }
+
+    /// Broadcast a `SystemMessage` to a group of nodes.
+    ///
+    /// Much like with `send()`, oversized messages are dropped.
+    pub fn broadcast(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> Digest {
+        let (digests, _) = self.broadcast_checked(message, targets);
+        digests.returned
+    }
+
+    /// Same as `broadcast()`, but returns an error if the message
+    /// was dropped for exceeding `SharedData::max_payload_len()`.
+    pub fn try_broadcast(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> Result<Digest> {
+        let (digests, result) = self.broadcast_checked(message, targets);
+        result.map(|_| digests.returned)
+    }
+
+    /// Broadcast a `SystemMessage` to a group of nodes, reporting
+    /// whether it was delivered to each one of them.
+    ///
+    /// The message is sent right away, much like with `broadcast()`,
+    /// but the returned future resolves once every send completes,
+    /// with the outcome of the send to each target. A send fails if
+    /// writing to the socket of the target errors, or times out, as
+    /// per `NodeConfig::write_timeout`; if the target's connection
+    /// has a write queue, the send fails if the queue drops the
+    /// message. Messages are always sent in parallel, regardless
+    /// of the `broadcast_ordered` feature.
+    ///
+    /// Oversized messages are dropped, and an error is returned
+    /// instead, like with `try_broadcast()`.
+    pub fn broadcast_detached(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> Result<(
+        Digest,
+        impl Future<Output = HashMap<NodeId, Result<()>>> + Send,
+    )> {
+        let (mine, others) = Self::send_tos(
+            self.id,
+            &self.peer_tx,
+            &self.bulk_tx,
+            LinkKind::of(&message),
+            &self.my_tx,
+            &self.shared,
+            targets,
+        );
+        let (mine, others) = in_shard(self.shard, mine, others);
+        let nonce = self.nonces.next();
+        let shared = Arc::clone(&self.shared);
+        Self::broadcast_detached_impl(message, mine, others, nonce, shared)
+    }
+
+    fn broadcast_checked(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> (SentDigests, Result<()>) {
+        let (mine, others) = Self::send_tos(
+            self.id,
+            &self.peer_tx,
+            &self.bulk_tx,
+            LinkKind::of(&message),
+            &self.my_tx,
+            &self.shared,
+            targets,
+        );
+        let (mine, others) = in_shard(self.shard, mine, others);
+        let nonce = self.nonces.next();
+        let shared = Arc::clone(&self.shared);
+        Self::broadcast_impl(message, mine, others, nonce, shared)
+    }
+
+    #[inline]
+    fn broadcast_impl(
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        my_send_to: Option<SendTo<D>>,
+        other_send_tos: SendTos<D>,
+        nonce: u64,
+        shared: Arc<NodeShared>,
+    ) -> (SentDigests, Result<()>) {
+        // serialize
+        let (digest, buf) = Self::serialize_bounded(&shared, &message);
+        let digests = shared.sent_digests(digest, nonce);
+        let buf = match buf {
+            Ok(buf) => buf,
+            Err(e) => return (digests, Err(e)),
+        };
+
+        #[cfg(not(feature = "broadcast_ordered"))]
+        Self::broadcast_parallel(
+            message,
+            my_send_to,
+            other_send_tos,
+            nonce,
+            digest,
+            buf,
+            shared,
+        );
+
+        #[cfg(feature = "broadcast_ordered")]
+        Self::broadcast_ordered(
+            message,
+            my_send_to,
+            other_send_tos,
+            nonce,
+            digest,
+            buf,
+            shared,
+        );
+
+        (digests, Ok(()))
+    }
+
+    // sends a message to each target on a separate task, and returns
+    // a future resolving to the outcome of each send, once they complete
+    fn broadcast_detached_impl(
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        my_send_to: Option<SendTo<D>>,
+        other_send_tos: SendTos<D>,
+        nonce: u64,
+        shared: Arc<NodeShared>,
+    ) -> Result<(
+        Digest,
+        impl Future<Output = HashMap<NodeId, Result<()>>> + Send,
+    )> {
+        // serialize
+        let (digest, buf) = Self::serialize_bounded(&shared, &message);
+        let returned = shared.sent_digests(digest, nonce).returned;
+        let buf = buf?;
+
+        let mut outcomes = Vec::with_capacity(other_send_tos.len() + 1);
+
+        // send to ourselves
+        if let Some(mut send_to) = my_send_to {
+            let (tx, rx) = oneshot::channel();
+            let buf = shared.send_bufs.copy_of(&buf[..]);
+            outcomes.push((send_to.target(), rx));
+            rt::spawn(async move {
+                // Right -> our turn
+                let result = send_to.value(Right((message, nonce, digest, buf))).await;
+                tx.send(result).unwrap_or(());
+            });
+        }
+
+        // send to others
+        for mut send_to in other_send_tos {
+            let (tx, rx) = oneshot::channel();
+            let buf = shared.send_bufs.copy_of(&buf[..]);
+            outcomes.push((send_to.target(), rx));
+            rt::spawn(async move {
+                // Left -> peer turn
+                let result = send_to.value(Left((nonce, digest, buf))).await;
+                tx.send(result).unwrap_or(());
+            });
+        }
+        shared.send_bufs.put(buf);
+
+        let delivered = async move {
+            let mut delivered = collections::hash_map();
+            for (target, rx) in outcomes {
+                let result = rx.await.wrapped(ErrorKind::Communication).and_then(|r| r);
+                delivered.insert(target, result);
+            }
+            delivered
+        };
+        Ok((returned, delivered))
+    }
+
+    // serializes `message` into a buffer, unless its payload exceeds
+    // `D::max_payload_len()`, in which case an error is returned
+    // instead, since the peers would reject the frame anyway
+    #[inline]
+    fn serialize_bounded(
+        shared: &NodeShared,
+        message: &SystemMessage<D::State, D::Request, D::Reply>,
+    ) -> (Digest, Result<Buf>) {
+        let mut buf = shared.send_bufs.get(D::size_hint(message));
+        let digest = <D as DigestData>::serialize_digest(message, &mut buf).unwrap();
+        let max_payload_len = D::max_payload_len();
+        if buf.len() > max_payload_len {
+            let s = format!(
+                "Payload of {} bytes exceeds the maximum of {} bytes",
+                buf.len(),
+                max_payload_len
+            );
+            shared.send_bufs.put(buf);
+            return (digest, Err(s).wrapped(ErrorKind::Communication));
+        }
+        (digest, Ok(buf))
+    }
+
+    // sends a message to each target on a separate task
+    #[cfg(not(feature = "broadcast_ordered"))]
+    #[inline]
+    fn broadcast_parallel(
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        my_send_to: Option<SendTo<D>>,
+        other_send_tos: SendTos<D>,
+        nonce: u64,
+        digest: Digest,
+        buf: Buf,
+        shared: Arc<NodeShared>,
+    ) {
+        rt::spawn(async move {
+            // send to ourselves
+            if let Some(mut send_to) = my_send_to {
+                let buf = shared.send_bufs.copy_of(&buf[..]);
+                rt::spawn(async move {
+                    // Right -> our turn
+                    send_to
+                        .value(Right((message, nonce, digest, buf)))
+                        .await
+                        .unwrap_or(());
+                });
+            }
+
+            // send to others
+            for mut send_to in other_send_tos {
+                let buf = shared.send_bufs.copy_of(&buf[..]);
+                rt::spawn(async move {
+                    // Left -> peer turn
+                    send_to
+                        .value(Left((nonce, digest, buf)))
+                        .await
+                        .unwrap_or(());
+                });
+            }
+            shared.send_bufs.put(buf);
+
+            // NOTE: an either enum is used, which allows
+            // rustc to prove only one task gets ownership
+            // of the `message`, i.e. `Right` = ourselves
+        });
+    }
+
+    // sends a message to each target in turn, in ascending order of
+    // `NodeId`, from a single task, such that the send order is
+    // reproducible across runs
+    #[cfg(feature = "broadcast_ordered")]
+    #[inline]
+    fn broadcast_ordered(
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        my_send_to: Option<SendTo<D>>,
+        other_send_tos: SendTos<D>,
+        nonce: u64,
+        digest: Digest,
+        buf: Buf,
+        shared: Arc<NodeShared>,
+    ) {
+        let send_tos = in_target_order(my_send_to, other_send_tos, SendTo::target);
+        rt::spawn(async move {
+            let mut message = Some(message);
+            for mut send_to in send_tos {
+                let buf = shared.send_bufs.copy_of(&buf[..]);
+                match send_to {
+                    SendTo::Me { .. } => {
+                        // we are only included once in the targets
+                        let message = message.take().unwrap();
+                        // Right -> our turn
+                        send_to
+                            .value(Right((message, nonce, digest, buf)))
+                            .await
+                            .unwrap_or(());
+                    }
+                    SendTo::Peers { .. } | SendTo::Disconnected { .. } => {
+                        // Left -> peer turn
+                        send_to
+                            .value(Left((nonce, digest, buf)))
+                            .await
+                            .unwrap_or(());
+                    }
+                }
+            }
+            shared.send_bufs.put(buf);
+        });
+    }
+
+    #[inline]
+    fn send_tos(
+        my_id: NodeId,
+        peer_tx: &PeerTx,
+        bulk_tx: &HashMap<NodeId, PeerSock>,
+        kind: LinkKind,
+        tx: &MessageChannelTx<D::State, D::Request, D::Reply>,
+        shared: &Arc<NodeShared>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> (Option<SendTo<D>>, SendTos<D>) {
+        let mut my_send_to = None;
+        let mut other_send_tos = SendTos::new();
+
+        match peer_tx {
+            PeerTx::Client(ref lock) => {
+                let map = lock.read();
+                Self::create_send_tos(
+                    my_id,
+                    tx,
+                    shared,
+                    &*map,
+                    bulk_tx,
+                    kind,
+                    targets,
+                    &mut my_send_to,
+                    &mut other_send_tos,
+                );
+            }
+            PeerTx::Server(ref map) => {
+                Self::create_send_tos(
+                    my_id,
+                    tx,
+                    shared,
+                    map,
+                    bulk_tx,
+                    kind,
+                    targets,
+                    &mut my_send_to,
+                    &mut other_send_tos,
+                );
+            }
+        };
+
+        (my_send_to, other_send_tos)
+    }
+
+    #[inline]
+    fn create_send_tos(
+        my_id: NodeId,
+        tx: &MessageChannelTx<D::State, D::Request, D::Reply>,
+        shared: &Arc<NodeShared>,
+        map: &HashMap<NodeId, PeerSock>,
+        bulk_map: &HashMap<NodeId, PeerSock>,
+        kind: LinkKind,
+        targets: impl Iterator<Item = NodeId>,
+        mine: &mut Option<SendTo<D>>,
+        others: &mut SendTos<D>,
+    ) {
+        for id in targets {
+            if id == my_id {
+                let s = SendTo::Me {
+                    my_id,
+                    shard: ShardId::ZERO,
+                    tx: tx.clone(),
+                    shared: Arc::clone(shared),
+                    _sending: InFlight::enter(&shared.sending),
+                };
+                *mine = Some(s);
+            } else {
+                let s = match link_for(kind, id, map, bulk_map) {
+                    Some(sock) => SendTo::Peers {
+                        sock: sock.clone(),
+                        my_id,
+                        shard: ShardId::ZERO,
+                        peer_id: id,
+                        tx: tx.clone(),
+                        shared: Arc::clone(shared),
+                        _sending: InFlight::enter(&shared.sending),
+                    },
+                    None => SendTo::Disconnected {
+                        peer_id: id,
+                        shared: Arc::clone(shared),
+                    },
+                };
+                others.push(s);
+            }
+        }
+    }
+
+    #[inline]
+    fn send_to(
+        my_id: NodeId,
+        peer_id: NodeId,
+        shared: &Arc<NodeShared>,
+        tx: &MessageChannelTx<D::State, D::Request, D::Reply>,
+        peer_tx: &PeerTx,
+        bulk_tx: &HashMap<NodeId, PeerSock>,
+        kind: LinkKind,
+    ) -> SendTo<D> {
+        let tx = tx.clone();
+        let shared = Arc::clone(shared);
+        if my_id == peer_id {
+            SendTo::Me {
+                _sending: InFlight::enter(&shared.sending),
+                shared,
+                my_id,
+                shard: ShardId::ZERO,
+                tx,
+            }
+        } else {
+            let sock = match peer_tx {
+                PeerTx::Client(ref lock) => {
+                    let map = lock.read();
+                    link_for(kind, peer_id, &*map, bulk_tx).cloned()
+                }
+                PeerTx::Server(ref map) => link_for(kind, peer_id, map, bulk_tx).cloned(),
+            };
+            match sock {
+                Some(sock) => SendTo::Peers {
+                    sock,
+                    _sending: InFlight::enter(&shared.sending),
+                    shared,
+                    peer_id,
+                    my_id,
+                    shard: ShardId::ZERO,
+                    tx,
+                },
+                None => SendTo::Disconnected { shared, peer_id },
+            }
+        }
+    }
+
+    /// Receive one message from peer nodes or ourselves.
+    pub async fn receive(&mut self) -> Result<Message<D::State, D::Request, D::Reply>> {
+        self.my_rx.recv().await
+    }
+
+    /// Checks if we are connected to the node with id `peer_id` in both
+    /// directions, i.e. we can send messages to it, and receive messages
+    /// from it.
+    ///
+    /// This is kept up to date by the `handle_connected_*` and
+    /// `handle_disconnected_*` methods, so it also reflects connections
+    /// established after bootstrapping the `Node`.
+    pub fn is_connected(&self, peer_id: NodeId) -> bool {
+        self.links.is_connected(peer_id)
+    }
+
+    /// Returns the state of the connections to every peer node
+    /// we are connected to in at least one direction.
+    ///
+    /// Like `is_connected()`, this is kept up to date by the
+    /// `handle_connected_*` and `handle_disconnected_*` methods.
+    pub fn connection_status(&self) -> HashMap<NodeId, ConnState> {
+        self.links.status()
+    }
+
+    /// Subscribes to the changes in the state of the connections
+    /// to the peer nodes, e.g. such that a replica can tell when it
+    /// lost contact with the leader.
+    ///
+    /// The events are delivered as the `handle_connected_*` and
+    /// `handle_disconnected_*` methods are called.
+    pub fn subscribe_connections(&mut self) -> mpsc::UnboundedReceiver<ConnEvent> {
+        self.links.subscribe()
+    }
+
+    /// Waits until we are connected in both directions to a quorum of
+    /// replicas, counting ourselves if we are a replica, e.g. to hold
+    /// off on new requests while the links to other replicas are
+    /// re-established.
+    ///
+    /// Like in `bootstrap()`, the connection messages received in the
+    /// meantime are handled, except that disconnects are tolerated;
+    /// every other message is appended to `received`, to be processed
+    /// by the caller. Fails if no quorum is connected before `timeout`
+    /// elapses.
+    pub async fn wait_for_quorum(
+        &mut self,
+        timeout: Duration,
+        received: &mut Vec<Message<D::State, D::Request, D::Reply>>,
+    ) -> Result<()> {
+        let quorum = SystemParams::new(self.members.len(), self.f)?.quorum();
+        let mut expired = Delay::new(timeout).fuse();
+
+        while !self.links.quorum_connected(self.id, &self.members, quorum) {
+            let message = select! {
+                message = self.my_rx.recv().fuse() => message?,
+                _ = expired => {
+                    return Err("Timed out waiting for a quorum of replicas")
+                        .wrapped(ErrorKind::Communication);
+                },
+            };
+
+            match message {
+                Message::ConnectedTx(id, sock, codec) => self.handle_connected_tx(id, sock, codec),
+                Message::ConnectedBulkTx(id, sock, codec) => {
+                    self.handle_connected_bulk_tx(id, sock, codec)
+                }
+                Message::ConnectedRx(id, sock, codec) => self.handle_connected_rx(id, sock, codec),
+                Message::DisconnectedTx(id) => self.handle_disconnected_tx(id),
+                Message::DisconnectedRx(Some(id)) | Message::Departed(id) => {
+                    self.handle_disconnected_rx(id)
+                }
+                Message::DisconnectedRx(None) => (),
+                m => received.push(m),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Method called upon a `Message::ConnectedTx`.
+    pub fn handle_connected_tx(
+        &mut self,
+        peer_id: NodeId,
+        sock: TlsStreamCli<Socket>,
+        codec: Codec,
+    ) {
+        self.links.update(peer_id, |links| {
+            links.tx.insert(peer_id);
+        });
+        self.reconnecting.remove(&peer_id);
+        let sock = self.peer_sock(peer_id, sock, codec);
+        match &mut self.peer_tx {
+            PeerTx::Server(ref mut peer_tx) => {
+                peer_tx.insert(peer_id, sock);
+            }
+            PeerTx::Client(ref lock) => {
+                let mut peer_tx = lock.write();
+                peer_tx.insert(peer_id, sock);
+            }
+        }
+    }
+
+    /// Method called upon a `Message::ConnectedBulkTx`.
+    pub fn handle_connected_bulk_tx(
+        &mut self,
+        peer_id: NodeId,
+        sock: TlsStreamCli<Socket>,
+        codec: Codec,
+    ) {
+        let sock = self.peer_sock(peer_id, sock, codec);
+        self.bulk_tx.insert(peer_id, sock);
+    }
+
+    // checks if we are connected to `peer_id` on the send side
+    fn has_peer_tx(&self, peer_id: NodeId) -> bool {
+        match self.peer_tx {
+            PeerTx::Client(ref lock) => lock.read().contains_key(&peer_id),
+            PeerTx::Server(ref map) => map.contains_key(&peer_id),
+        }
+    }
+
+    // wraps the socket of a connection to `peer_id`, such that
+    // it can be shared by the tasks sending messages to the peer
+    fn peer_sock(&self, peer_id: NodeId, sock: TlsStreamCli<Socket>, codec: Codec) -> PeerSock {
+        match self.write_queue {
+            Some(cfg) => {
+                let mut tx = self.my_tx.clone();
+                let disconnected = async move {
+                    tx.send(Message::DisconnectedTx(peer_id))
+                        .await
+                        .unwrap_or(())
+                };
+                let timeout = self.shared.write_timeout;
+                let writer = PeerWriter::spawn(sock, cfg, timeout, disconnected);
+                PeerSock::Queued(writer, codec)
+            }
+            None => PeerSock::Locked(Arc::new(Mutex::new(sock)), codec),
+        }
+    }
+
+    /// Method called upon a `Message::DisconnectedTx`.
+    ///
+    /// If `NodeConfig::reconnect` is set, and `peer_id` is a replica,
+    /// we keep trying to reconnect to it in the background; success
+    /// is announced with a `Message::ConnectedTx`, like when bootstrapping.
+    /// Dedicated bulk links are not re-established, so states are sent
+    /// over the reconnected link.
+    ///
+    /// The shards of a `Node` hand the message over to it instead,
+    /// since it owns their connections; check `Node::into_shards()`.
+    pub fn handle_disconnected_tx(&mut self, peer_id: NodeId) {
+        if let Some(ref root) = self.root {
+            let mut tx = root.tx.clone();
+            rt::spawn(async move {
+                tx.send(Message::DisconnectedTx(peer_id))
+                    .await
+                    .unwrap_or(())
+            });
+            return;
+        }
+        self.reconnect_replica(peer_id);
+        self.links.update(peer_id, |links| {
+            links.tx.remove(&peer_id);
+        });
+        self.bulk_tx.remove(&peer_id);
+        match &mut self.peer_tx {
+            PeerTx::Server(ref mut peer_tx) => {
+                peer_tx.remove(&peer_id);
+            }
+            PeerTx::Client(ref lock) => {
+                let mut peer_tx = lock.write();
+                peer_tx.remove(&peer_id);
+            }
+        }
+    }
+
+    /// Method called upon a `Message::DisconnectedRx`, or a `Message::Departed`.
+    ///
+    /// The receiving side of a link is re-established by the peer,
+    /// which reconnects to us once its sending side drops.
+    pub fn handle_disconnected_rx(&mut self, peer_id: NodeId) {
+        self.links.update(peer_id, |links| {
+            links.rx.remove(&peer_id);
+        });
+    }
+
+    /// Checks if the dropped links to other replicas are re-established;
+    /// check out `NodeConfig::reconnect`.
+    pub fn reconnects(&self) -> bool {
+        self.reconnect.is_some()
+    }
+
+    // spawns a task reconnecting to `peer_id`, if it is another
+    // replica, and we aren't already reconnecting to it
+    fn reconnect_replica(&mut self, peer_id: NodeId) {
+        let backoff = match self.reconnect {
+            Some(backoff) => backoff,
+            None => return,
+        };
+        let replica = self.members.contains(&peer_id);
+        if !replica || peer_id == self.id || self.reconnecting.contains(&peer_id) {
+            return;
+        }
+        // we were shut down -> don't reconnect
+        let shutdown = match self.shutdown.listener() {
+            Some(shutdown) => shutdown,
+            None => return,
+        };
+        let addr = match self.peer_addrs.get(&peer_id).cloned() {
+            Some(addr) => addr,
+            None => return,
+        };
+        self.reconnecting.insert(peer_id);
+        let nonce = self.nonces.next();
+        rt::spawn(Self::tx_side_reconnect_task(
+            self.id,
+            peer_id,
+            nonce,
+            self.connector.clone(),
+            self.my_tx.clone(),
+            addr,
+            self.socket_opts,
+            Arc::clone(&self.socket_provider),
+            self.codecs,
+            backoff,
+            shutdown,
+        ));
+    }
+
+    /// Waits until the messages sent so far by this `Node` are written
+    /// to the sockets of their targets, or delivered to ourselves.
+    ///
+    /// Messages are sent by detached tasks, and written to the sockets
+    /// with a write queue by their writer tasks, so this is meant to be
+    /// called before `shutdown()`, such that the messages are not lost.
+    /// Returns an error if this takes longer than `timeout`, or if the
+    /// connection to a peer is gone.
+    pub async fn flush(&self, timeout: Duration) -> Result<()> {
+        let flushed = async {
+            // wait for the detached tasks to hand their messages over
+            while self.shared.sending.load(Ordering::Relaxed) > 0 {
+                Delay::new(FLUSH_POLL_INTERVAL).await;
+            }
+
+            // then for the writer tasks to write them
+            let mut writers: Vec<_> = match self.peer_tx {
+                PeerTx::Client(ref lock) => lock
+                    .read()
+                    .values()
+                    .filter_map(PeerSock::writer)
+                    .cloned()
+                    .collect(),
+                PeerTx::Server(ref map) => {
+                    map.values().filter_map(PeerSock::writer).cloned().collect()
+                }
+            };
+            writers.extend(self.bulk_tx.values().filter_map(PeerSock::writer).cloned());
+            for mut writer in writers {
+                writer.flush().await?;
+            }
+            Ok(())
+        };
+        select! {
+            result = flushed.fuse() => result,
+            _ = Delay::new(timeout).fuse() => {
+                Err("Timed out flushing the sockets of the peers")
+                    .wrapped(ErrorKind::Communication)
+            },
+        }
+    }
+
+    /// Shuts down this `Node`, signalling the task accepting connections,
+    /// as well as the tasks reading from them, to exit, which closes their
+    /// sockets. Connections established afterwards are dropped.
+    ///
+    /// A `SystemMessage::Goodbye` is sent to the peers beforehand, such
+    /// that they can tell this shutdown apart from a crash, which they
+    /// are notified of with a `Message::Departed`.
+    ///
+    /// The returned future resolves once all of these tasks exited.
+    ///
+    /// Shutting down a shard only releases its hold on the connections
+    /// of the `Node` it was split from, which is shut down once all of
+    /// its shards are, so the returned future resolves immediately.
+    pub fn shutdown(&mut self) -> Terminated {
+        if let Some(ref mut root) = self.root {
+            root.alive = None;
+            return self.shutdown.signal();
+        }
+        if !self.shutdown.is_signalled() {
+            let peers: Vec<_> = match self.peer_tx {
+                PeerTx::Client(ref lock) => lock.read().keys().copied().collect(),
+                PeerTx::Server(ref map) => map.keys().copied().collect(),
+            };
+            if !peers.is_empty() {
+                self.broadcast(SystemMessage::Goodbye, peers.into_iter());
+            }
+        }
+        self.shutdown.signal()
+    }
+
+    /// Method called upon a `Message::ConnectedRx`.
+    pub fn handle_connected_rx(
+        &mut self,
+        peer_id: NodeId,
+        sock: TlsStreamSrv<Socket>,
+        codec: Codec,
+    ) {
+        // we were shut down -> drop this socket
+        let shutdown = match self.shutdown.listener() {
+            Some(shutdown) => shutdown,
+            None => return,
+        };
+        self.links.update(peer_id, |links| {
+            links.rx.insert(peer_id);
+        });
+        // we are a server node
+        if !self.id.is_client(self.first_cli) {
+            // the node whose conn we accepted is a client or an
+            // observer, and we aren't connected to it yet
+            let replica = self.members.contains(&peer_id);
+            if !replica && !self.has_peer_tx(peer_id) {
+                // fetch client address; we can't connect to clients
+                // missing from `NodeConfig::addrs`, so we never reply
+                // to them, but their messages are still read
+                if let Some(addr) = self.peer_addrs.get(&peer_id).cloned() {
+                    // connect
+                    let nonce = self.nonces.next();
+                    rt::spawn(Self::tx_side_connect_task(
+                        self.id,
+                        peer_id,
+                        nonce,
+                        self.connect_retries,
+                        self.connector.clone(),
+                        self.my_tx.clone(),
+                        addr,
+                        None,
+                        self.socket_opts,
+                        Arc::clone(&self.socket_provider),
+                        self.codecs,
+                    ));
+                }
+            }
+        }
+
+        let shared = Arc::clone(&self.shared);
+        let from_client = peer_id.is_client(self.first_cli);
+        let reaped = shared
+            .reaper
+            .as_ref()
+            .filter(|_| from_client)
+            .map(|reaper| reaper.register(peer_id, Instant::now()));
+
+        rt::spawn(Self::rx_side_read(
+            sock,
+            codec,
+            peer_id,
+            from_client,
+            shared,
+            self.pool.clone(),
+            self.deserialize_timeout,
+            self.verify_parallelism,
+            reaped,
+            shutdown,
+            self.my_tx.clone(),
+        ));
+    }
+
+    // reads the messages sent by `peer_id` over `sock`, whose payloads are
+    // compressed with `codec`, and delivers them to the master channel,
+    // until the connection errors, is reaped, or the `Node` is shut down;
+    // the disconnect is then announced with a `DisconnectedRx`, or a
+    // `Departed`, if the peer said goodbye
+    async fn rx_side_read<R: AsyncRead + Unpin>(
+        mut sock: R,
+        codec: Codec,
+        peer_id: NodeId,
+        from_client: bool,
+        shared: Arc<NodeShared>,
+        pool: ThreadPool,
+        deserialize_timeout: Duration,
+        verify_parallelism: usize,
+        reaped: Option<oneshot::Receiver<()>>,
+        mut shutdown: ShutdownListener,
+        mut tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+    ) {
+        let mut buf: Buf = Buf::new();
+        let mut verifying = Verifying::new(verify_parallelism);
+        let deserializing = shared.deserializing(peer_id);
+
+        // connections not tracked by the reaper are never reaped,
+        // since this sender is kept alive until the task exits
+        let (_untracked, untracked) = oneshot::channel();
+        let mut reaped = reaped.unwrap_or(untracked);
+
+        // exits once the `Node` is shut down, or dropped;
+        // evaluates to whether the peer said goodbye
+        let departed = 'read: loop {
+            // reserve space for header
+            buf.clear();
+            buf.resize(Header::LENGTH, 0);
+
+            // read the peer's header, meanwhile delivering the
+            // messages read before it, as they are verified
+            let result = {
+                let mut read_header = sock.read_exact(&mut buf[..Header::LENGTH]).fuse();
+                loop {
+                    select! {
+                        result = read_header => break result,
+                        received = verifying.pending.select_next_some() => {
+                            let delivered = deliver_received::<D>(
+                                &mut tx,
+                                &shared,
+                                peer_id,
+                                from_client,
+                                received,
+                            );
+                            if let Some(departed) = delivered.await {
+                                break 'read departed;
+                            }
+                        },
+                        // idle client connection -> drop this socket
+                        _ = reaped => break 'read false,
+                        // shut down -> drop this socket
+                        _ = shutdown => break 'read false,
+                    }
+                }
+            };
+            if let Err(_) = result {
+                // errors reading -> faulty connection, unless the peer
+                // said goodbye before closing it; either way, the messages
+                // read up to this point are delivered, then this socket
+                // is dropped
+                while let Some(received) = verifying.pending.next().await {
+                    let delivered =
+                        deliver_received::<D>(&mut tx, &shared, peer_id, from_client, received);
+                    if let Some(departed) = delivered.await {
+                        break 'read departed;
+                    }
+                }
+                break false;
+            }
+
+            // we are passing the correct length, safe to use unwrap()
+            let mut header = Header::deserialize_from(&buf[..Header::LENGTH]).unwrap();
+
+            // oversized payload -> faulty connection;
+            // drop this socket
+            if header.payload_length() > D::max_payload_len() {
+                break false;
+            }
+
+            // reserve space for message, which is handed off to
+            // the thread pool as is, once read
+            let mut payload = BytesMut::with_capacity(header.payload_length());
+            payload.resize(header.payload_length(), 0);
+
+            // read the peer's payload
+            if let Err(_) = sock.read_exact(&mut payload[..]).await {
+                // errors reading -> faulty connection;
+                // drop this socket
+                break false;
+            }
+
+            // restore the payload the peer compressed; the length in
+            // the header isn't signed, so it's that of the compressed
+            // payload, while the digest is that of the original one
+            let payload = match codec.decompress(payload, D::max_payload_len()) {
+                Ok(payload) => payload,
+                // payloads which fail to decompress, or which
+                // decompress into oversized ones -> faulty peer;
+                // drop this socket
+                Err(_) => break false,
+            };
+            header.length = payload.len() as u64;
+
+            // wait for the earliest message to be verified,
+            // if too many are being verified already
+            while verifying.is_full() {
+                let received = match verifying.pending.next().await {
+                    Some(received) => received,
+                    None => break,
+                };
+                let delivered =
+                    deliver_received::<D>(&mut tx, &shared, peer_id, from_client, received);
+                if let Some(departed) = delivered.await {
+                    break 'read departed;
+                }
+            }
+
+            // messages which timed out deserializing are still hogging
+            // the thread pool -> faulty peer; drop this socket
+            if deserializing.load(Ordering::Relaxed) >= verify_parallelism {
+                break false;
+            }
+
+            // verify and deserialize the payload on the thread
+            // pool, concurrently with the messages read before it
+            verifying.push(verify_and_deserialize::<D>(
+                pool.clone(),
+                Arc::clone(&shared),
+                peer_id,
+                header,
+                payload.freeze(),
+                deserialize_timeout,
+                InFlight::enter(&deserializing),
+            ));
+        };
+
+        // announce we have disconnected
+        let message = if departed {
+            Message::Departed(peer_id)
+        } else {
+            Message::DisconnectedRx(Some(peer_id))
+        };
+        tx.send(message).await.unwrap_or(());
+    }
+
+    // periodically reaps the idle client connections, until the
+    // `Node` is shut down, or every handle to its shared state is
+    // dropped; the replica closes their send side upon the `DisconnectedTx`
+    async fn reap_idle_clients(
+        shared: Weak<NodeShared>,
+        mut tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        period: Duration,
+        mut shutdown: ShutdownListener,
+    ) {
+        loop {
+            select! {
+                _ = Delay::new(period).fuse() => (),
+                _ = shutdown => return,
+            }
+            let reaped = match shared.upgrade() {
+                Some(shared) => match shared.reaper {
+                    Some(ref reaper) => reaper.reap(Instant::now()),
+                    None => return,
+                },
+                None => return,
+            };
+            for peer_id in reaped {
+                if tx.send(Message::DisconnectedTx(peer_id)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Returns the number of client messages dropped so far,
+    /// because the inbound channel of this `Node` was saturated.
+    pub fn dropped_client_messages(&self) -> u64 {
+        self.shared.dropped_client_msgs.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of rogue messages dropped while bootstrapping
+    /// this `Node`, because `NodeConfig::rogue_bound` was reached.
+    pub fn dropped_rogue_messages(&self) -> u64 {
+        self.shared.dropped_rogue_msgs.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    fn tx_side_connect(
+        members: &[NodeId],
+        my_id: NodeId,
+        retries: usize,
+        connector: TlsConnector,
+        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        addrs: &HashMap<NodeId, (SocketAddr, String)>,
+        nonces: &mut Nonces,
+        bulk_link: bool,
+        socket_opts: SocketOpts,
+        socket_provider: &Arc<dyn SocketProvider>,
+        codecs: u8,
+    ) -> Result<()> {
+        // look up the address of every replica, before
+        // connecting to any of them
+        let peers = members
+            .iter()
+            .copied()
+            .filter(|&id| id != my_id)
+            .map(|peer_id| {
+                addrs
+                    .get(&peer_id)
+                    .map(|addr| (peer_id, addr.clone()))
+                    .ok_or_else(|| format!("Missing the address of replica {:?}", peer_id))
+                    .wrapped(ErrorKind::Communication)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // only replicas open bulk links, to other replicas
+        let links: &[Option<LinkKind>] = if bulk_link && members.contains(&my_id) {
+            &[Some(LinkKind::Interactive), Some(LinkKind::Bulk)]
+        } else {
+            &[None]
+        };
+        for (peer_id, addr) in peers {
+            for &link in links {
+                let tx = tx.clone();
+                let addr = addr.clone();
+                let connector = connector.clone();
+                let nonce = nonces.next();
+                rt::spawn(Self::tx_side_connect_task(
+                    my_id,
+                    peer_id,
+                    nonce,
+                    retries,
+                    connector,
+                    tx,
+                    addr,
+                    link,
+                    socket_opts,
+                    Arc::clone(socket_provider),
+                    codecs,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn tx_side_connect_task(
+        my_id: NodeId,
+        peer_id: NodeId,
+        nonce: u64,
+        retries: usize,
+        connector: TlsConnector,
+        mut tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        (addr, hostname): (SocketAddr, String),
+        link: Option<LinkKind>,
+        socket_opts: SocketOpts,
+        socket_provider: Arc<dyn SocketProvider>,
+        codecs: u8,
+    ) {
+        const SECS: u64 = 1;
+        // NOTE:
+        // ========
+        //
+        // 1) not an issue if `tx` is closed, this is not a
+        // permanently running task, so channel send failures
+        // are tolerated
+        //
+        // 2) try to connect up to `retries` times, then announce
+        // failure with a channel send op
+        for _ in 0..retries {
+            if let Ok(sock) = socket_provider.connect(addr).await {
+                let handshake = Self::tx_side_handshake(
+                    my_id,
+                    peer_id,
+                    nonce,
+                    &connector,
+                    &hostname,
+                    sock,
+                    link,
+                    &socket_opts,
+                    codecs,
+                );
+                let (sock, codec) = match handshake.await {
+                    Some(handshake) => handshake,
+                    None => break,
+                };
+
+                // success
+                let message = match link {
+                    Some(LinkKind::Bulk) => Message::ConnectedBulkTx(peer_id, sock, codec),
+                    _ => Message::ConnectedTx(peer_id, sock, codec),
+                };
+                tx.send(message).await.unwrap_or(());
+                return;
+            }
+            // sleep for `SECS` seconds and retry
+            Delay::new(Duration::from_secs(SECS)).await;
+        }
+        // announce we have failed to connect to the peer node
+        tx.send(Message::DisconnectedTx(peer_id))
+            .await
+            .unwrap_or(());
+    }
+
+    // sets up a newly established connection to `peer_id`, performing
+    // the TLS handshake, and sending a header identifying ourselves,
+    // along with the `codecs` we offer; the peer replies with the codec
+    // it picked for the connection; on failure, the connection is dropped
+    async fn tx_side_handshake(
+        my_id: NodeId,
+        peer_id: NodeId,
+        nonce: u64,
+        connector: &TlsConnector,
+        hostname: &str,
+        sock: Socket,
+        link: Option<LinkKind>,
+        socket_opts: &SocketOpts,
+        codecs: u8,
+    ) -> Option<(TlsStreamCli<Socket>, Codec)> {
+        sock.set_opts(socket_opts).ok()?;
+
+        // without a dedicated bulk link, keep
+        // the configured socket options
+        if let Some(link) = link {
+            sock.set_nodelay(link.nodelay()).ok()?;
+        }
+
+        // TLS handshake; drop connection if it fails
+        let mut sock = connector.connect(hostname, sock).await.ok()?;
+
+        // send header, with our offer as the payload; errors
+        // writing -> faulty connection, so drop this socket
+        let offer = [codecs];
+        UnsignedWireMessage::new(my_id, peer_id, &offer[..], nonce, None)
+            .write_to(&mut sock)
+            .await
+            .ok()?;
+
+        // read the peer's reply, carrying the codec it picked
+        let mut buf = [0; Header::LENGTH + HANDSHAKE_REPLY_LENGTH];
+        sock.read_exact(&mut buf[..]).await.ok()?;
+
+        // we are passing the correct length, safe to use unwrap()
+        let header = Header::deserialize_from(&buf[..Header::LENGTH]).unwrap();
+        let reply = &buf[Header::LENGTH..];
+
+        // drop connections replied to by another node,
+        // or with invalid headers
+        match UnsignedWireMessage::from_parts(header, reply) {
+            Ok(wm) if wm.header().from() == peer_id && wm.header().to() == my_id => (),
+            _ => return None,
+        }
+        let codec = Codec::picked(codecs, reply[0]).ok()?;
+
+        Some((sock, codec))
+    }
+
+    // reconnects to the replica `peer_id`, after the link we send messages
+    // over to it dropped; unlike when bootstrapping, we keep trying until
+    // we succeed, or the `Node` is shut down, waiting longer and longer
+    // between the attempts, as configured by `backoff`
+    async fn tx_side_reconnect_task(
+        my_id: NodeId,
+        peer_id: NodeId,
+        nonce: u64,
+        connector: TlsConnector,
+        mut tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        (addr, hostname): (SocketAddr, String),
+        socket_opts: SocketOpts,
+        socket_provider: Arc<dyn SocketProvider>,
+        codecs: u8,
+        backoff: Backoff,
+        mut shutdown: ShutdownListener,
+    ) {
+        let mut rng = prng::State::from_thread_local();
+        let mut attempt = 0;
+        loop {
+            let mut delay = Delay::new(backoff.delay(attempt, &mut rng)).fuse();
+            select! {
+                _ = delay => (),
+                _ = shutdown => return,
+            }
+            attempt = attempt.saturating_add(1);
+
+            let sock = match socket_provider.connect(addr).await {
+                Ok(sock) => sock,
+                Err(_) => continue,
+            };
+            let handshake = Self::tx_side_handshake(
+                my_id,
+                peer_id,
+                nonce,
+                &connector,
+                &hostname,
+                sock,
+                None,
+                &socket_opts,
+                codecs,
+            );
+            if let Some((sock, codec)) = handshake.await {
+                tx.send(Message::ConnectedTx(peer_id, sock, codec))
+                    .await
+                    .unwrap_or(());
+                return;
+            }
+        }
+    }
+
+    // accepts connections until the `Node` is shut down, or dropped,
+    // at which point the listener is closed
+    async fn rx_side_accept(
+        first_cli: NodeId,
+        my_id: NodeId,
+        codecs: u8,
+        listener: Listener,
+        acceptor: TlsAcceptor,
+        peer_auth: Option<Arc<PeerAuth>>,
+        socket_opts: SocketOpts,
+        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        mut shutdown: ShutdownListener,
+    ) {
+        loop {
+            let accepted = select! {
+                accepted = listener.accept().fuse() => accepted,
+                _ = shutdown => return,
+            };
+            if let Ok(sock) = accepted {
+                // errors setting the options -> drop this socket
+                if let Err(_) = sock.set_opts(&socket_opts) {
+                    continue;
+                }
+                let tx = tx.clone();
+                let acceptor = acceptor.clone();
+                let peer_auth = peer_auth.clone();
+                let shutdown = shutdown.clone();
+                rt::spawn(Self::rx_side_accept_task(
+                    first_cli,
+                    (my_id, codecs),
+                    acceptor,
+                    peer_auth,
+                    sock,
+                    tx,
+                    shutdown,
+                ));
+            }
+        }
+    }
+
+    // performs a cryptographic handshake with a peer node, replying
+    // with the strongest codec both in its offer and in our `codecs`;
+    // header doesn't need to be signed, since we won't be
+    // storing this message in the log
+    async fn rx_side_accept_task(
+        first_cli: NodeId,
+        (my_id, codecs): (NodeId, u8),
+        acceptor: TlsAcceptor,
+        peer_auth: Option<Arc<PeerAuth>>,
+        sock: Socket,
+        mut tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        mut shutdown: ShutdownListener,
+    ) {
+        let mut buf_header = [0; Header::LENGTH + HANDSHAKE_OFFER_LENGTH];
+
+        // when authenticating peers, the certificate presented
+        // on this connection is recorded during the handshake
+        let (acceptor, presented) = match &peer_auth {
+            Some(auth) => {
+                let (acceptor, presented) = auth.acceptor();
+                (acceptor, Some(presented))
+            }
+            None => (acceptor, None),
+        };
+
+        // this loop is just a trick;
+        // the `break` instructions act as a `goto` statement
+        loop {
+            // TLS handshake; drop connection if it fails,
+            // or if we are shut down in the meantime
+            let mut sock = select! {
+                result = acceptor.accept(sock).fuse() => match result {
+                    Ok(s) => s,
+                    Err(_) => break,
+                },
+                _ = shutdown => return,
+            };
+
+            // read the peer's header, along with its offer
+            let result = select! {
+                result = sock.read_exact(&mut buf_header[..]).fuse() => result,
+                _ = shutdown => return,
+            };
+            if let Err(_) = result {
+                // errors reading -> faulty connection;
+                // drop this socket
+                break;
+            }
+
+            // we are passing the correct length, safe to use unwrap()
+            let header = Header::deserialize_from(&buf_header[..Header::LENGTH]).unwrap();
+            let offer = &buf_header[Header::LENGTH..];
+
+            // extract peer id
+            let peer_id = match UnsignedWireMessage::from_parts(header, offer) {
+                // drop connections from other clis if we are a cli
+                Ok(wm) if wm.header().from() >= first_cli && my_id >= first_cli => break,
+                // drop connections to the wrong dest
+                Ok(wm) if wm.header().to() != my_id => break,
+                // accept all other conns
+                Ok(wm) => wm.header().from(),
+                // drop connections with invalid headers
+                Err(_) => break,
+            };
+
+            // drop connections whose certificate wasn't
+            // issued to the node the peer claims to be
+            if let (Some(auth), Some(presented)) = (&peer_auth, &presented) {
+                if !auth.authenticate(peer_id, presented) {
+                    break;
+                }
+            }
+
+            // reply with the codec we picked; errors
+            // writing -> faulty connection, so drop this socket
+            let codec = Codec::negotiate(codecs, offer[0]);
+            let reply = [codec as u8];
+            let reply = UnsignedWireMessage::new(my_id, peer_id, &reply[..], 0, None);
+            let result = select! {
+                result = reply.write_to(&mut sock).fuse() => result,
+                _ = shutdown => return,
+            };
+            if result.is_err() {
+                break;
+            }
+
+            tx.send(Message::ConnectedRx(peer_id, sock, codec))
+                .await
+                .unwrap_or(());
+            return;
+        }
+
+        // announce we have failed to connect to the peer node
+        tx.send(Message::DisconnectedRx(None)).await.unwrap_or(());
+    }
+}
+
+/// Represents a node with sending capabilities only.
+pub struct SendNode<D: SharedData> {
+    id: NodeId,
+    shard: ShardId,
+    shared: Arc<NodeShared>,
+    nonces: Nonces,
+    peer_tx: PeerTx,
+    bulk_tx: HashMap<NodeId, PeerSock>,
+    my_tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+}
+
+impl<D: SharedData> Clone for SendNode<D> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            shard: self.shard,
+            nonces: self.nonces.fork(),
+            shared: Arc::clone(&self.shared),
+            peer_tx: self.peer_tx.clone(),
+            bulk_tx: self.bulk_tx.clone(),
+            my_tx: self.my_tx.clone(),
+        }
+    }
+}
+
+impl<D> SendNode<D>
+where
+    D: SharedData + 'static,
+    D::State: Send + Clone + 'static,
+    D::Request: Send + 'static,
+    D::Reply: Send + 'static,
+{
+    /// Check the `send()` documentation for `Node`.
+    pub fn send(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> Digest {
+        let (digests, _) = self.send_checked(message, target);
+        digests.returned
+    }
+
+    /// Check the `send_with_unique()` documentation for `Node`.
+    pub(crate) fn send_with_unique(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> (Digest, Digest) {
+        let (digests, _) = self.send_checked(message, target);
+        (digests.returned, digests.unique)
+    }
+
+    /// Same as `broadcast()`, but also returns `Header::unique_digest()`
+    /// of the message, regardless of `NodeConfig::digest_entropy`.
+    pub(crate) fn broadcast_with_unique(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> (Digest, Digest) {
+        let (digests, _) = self.broadcast_checked(message, targets);
+        (digests.returned, digests.unique)
+    }
+
+    /// Check the `try_send()` documentation for `Node`.
+    pub fn try_send(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> Result<Digest> {
+        let (digests, result) = self.send_checked(message, target);
+        result.map(|_| digests.returned)
+    }
+
+    fn send_checked(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        target: NodeId,
+    ) -> (SentDigests, Result<()>) {
+        let send_to = <Node<D>>::send_to(
+            self.id,
+            target,
+            &self.shared,
+            &self.my_tx,
+            &self.peer_tx,
+            &self.bulk_tx,
+            LinkKind::of(&message),
+        )
+        .in_shard(self.shard);
+        let my_id = self.id;
+        let nonce = self.nonces.next();
+        <Node<D>>::send_impl(message, send_to, my_id, target, nonce)
+    }
+
+    /// Check the `broadcast()` documentation for `Node`.
+    pub fn broadcast(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> Digest {
+        let (digests, _) = self.broadcast_checked(message, targets);
+        digests.returned
+    }
+
+    /// Check the `try_broadcast()` documentation for `Node`.
+    pub fn try_broadcast(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> Result<Digest> {
+        let (digests, result) = self.broadcast_checked(message, targets);
+        result.map(|_| digests.returned)
+    }
+
+    /// Check the `broadcast_detached()` documentation for `Node`.
+    pub fn broadcast_detached(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> Result<(
+        Digest,
+        impl Future<Output = HashMap<NodeId, Result<()>>> + Send,
+    )> {
+        let (mine, others) = <Node<D>>::send_tos(
+            self.id,
+            &self.peer_tx,
+            &self.bulk_tx,
+            LinkKind::of(&message),
+            &self.my_tx,
+            &self.shared,
+            targets,
+        );
+        let (mine, others) = in_shard(self.shard, mine, others);
+        let nonce = self.nonces.next();
+        let shared = Arc::clone(&self.shared);
+        <Node<D>>::broadcast_detached_impl(message, mine, others, nonce, shared)
+    }
+
+    fn broadcast_checked(
+        &mut self,
+        message: SystemMessage<D::State, D::Request, D::Reply>,
+        targets: impl Iterator<Item = NodeId>,
+    ) -> (SentDigests, Result<()>) {
+        let (mine, others) = <Node<D>>::send_tos(
+            self.id,
+            &self.peer_tx,
+            &self.bulk_tx,
+            LinkKind::of(&message),
+            &self.my_tx,
+            &self.shared,
+            targets,
+        );
+        let (mine, others) = in_shard(self.shard, mine, others);
+        let nonce = self.nonces.next();
+        let shared = Arc::clone(&self.shared);
+        <Node<D>>::broadcast_impl(message, mine, others, nonce, shared)
+    }
+}
+
+// helper type used when either a `send()` or a `broadcast()`
+// is called by a `Node` or `SendNode`.
+//
+// holds some data that can be shared between threads, relevant
+// to a network write operation, or channel write operation,
+// depending on whether we're sending a message to a peer node
+// or ourselves
+enum SendTo<D: SharedData> {
+    Me {
+        // our id
+        my_id: NodeId,
+        // the shard we send from
+        shard: ShardId,
+        // shared data
+        shared: Arc<NodeShared>,
+        // a handle to our message channel
+        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        // accounts for the message until it is delivered
+        _sending: InFlight,
+    },
+    Peers {
+        // our id
+        my_id: NodeId,
+        // the shard we send from
+        shard: ShardId,
+        // the id of the peer
+        peer_id: NodeId,
+        // shared data
+        shared: Arc<NodeShared>,
+        // handle to socket
+        sock: PeerSock,
+        // a handle to our message channel
+        tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+        // accounts for the message until it is handed to the socket
+        _sending: InFlight,
+    },
+    // a peer we aren't connected to, e.g. because its link
+    // dropped; messages sent to it are lost
+    Disconnected {
+        // the id of the peer
+        peer_id: NodeId,
+        // shared data
+        shared: Arc<NodeShared>,
+    },
+}
+
+impl<D> SendTo<D>
+where
+    D: SharedData + 'static,
+    D::State: Send + Clone + 'static,
+    D::Request: Send + 'static,
+    D::Reply: Send + 'static,
+{
+    // the id of the node this `SendTo` delivers messages to
+    fn target(&self) -> NodeId {
+        match self {
+            SendTo::Me { my_id, .. } => *my_id,
+            SendTo::Peers { peer_id, .. } => *peer_id,
+            SendTo::Disconnected { peer_id, .. } => *peer_id,
+        }
+    }
+
+    // tags the messages sent through this `SendTo` with `shard`
+    fn in_shard(mut self, shard: ShardId) -> Self {
+        match self {
+            SendTo::Me {
+                shard: ref mut s, ..
+            }
+            | SendTo::Peers {
+                shard: ref mut s, ..
+            } => *s = shard,
+            SendTo::Disconnected { .. } => (),
+        }
+        self
+    }
+
+    // the data shared by the node sending through this `SendTo`
+    fn shared(&self) -> &Arc<NodeShared> {
+        match self {
+            SendTo::Me { shared, .. } => shared,
+            SendTo::Peers { shared, .. } => shared,
+            SendTo::Disconnected { shared, .. } => shared,
+        }
+    }
+
+    async fn value(
+        &mut self,
+        m: Either<
+            (u64, Digest, Buf),
+            (
+                SystemMessage<D::State, D::Request, D::Reply>,
+                u64,
+                Digest,
+                Buf,
+            ),
+        >,
+    ) -> Result<()> {
+        match self {
+            SendTo::Me {
+                my_id,
+                shard,
+                shared: ref sh,
+                ref mut tx,
+                ..
+            } => {
+                if let Right((m, n, d, b)) = m {
+                    Self::me(*shard, *my_id, m, (n, d, b), sh, tx).await
+                } else {
+                    // optimize code path
+                    unreachable!()
+                }
+            }
+            SendTo::Peers {
+                my_id,
+                shard,
+                peer_id,
+                shared: ref sh,
+                ref mut sock,
+                ref mut tx,
+                ..
+            } => {
+                if let Left((n, d, b)) = m {
+                    Self::peers(*shard, *my_id, *peer_id, n, d, b, sh, sock, tx).await
+                } else {
+                    // optimize code path
+                    unreachable!()
+                }
+            }
+            SendTo::Disconnected { shared: ref sh, .. } => {
+                if let Left((_, _, b)) = m {
+                    sh.send_bufs.put(b);
+                }
+                Err("Not connected to the peer").wrapped(ErrorKind::Communication)
+            }
+        }
+    }
+
+    async fn me(
+        shard: ShardId,
+        my_id: NodeId,
+        m: SystemMessage<D::State, D::Request, D::Reply>,
+        (n, d, b): (u64, Digest, Buf),
+        sh: &NodeShared,
+        tx: &mut MessageChannelTx<D::State, D::Request, D::Reply>,
+    ) -> Result<()> {
+        // create wire msg
+        let (h, _) = SignedWireMessage::new_in_shard_hashed::<D::Hasher>(
+            shard,
+            my_id,
+            my_id,
+            &b[..],
+            n,
+            Some(d),
+            &sh.my_key,
+        )
+        .into_inner();
+        sh.send_bufs.put(b);
 
         // send
-        //
-        // FIXME: sending may hang forever, because of network
-        // problems; add a timeout
-        let mut sock = lock.lock().await;
-        if let Err(_) = wm.write_to(&mut *sock).await {
-            // error sending, drop connection
-            tx.send(Message::DisconnectedTx(peer_id))
+        tx.send(Message::System(h, m)).await
+    }
+
+    async fn peers(
+        shard: ShardId,
+        my_id: NodeId,
+        peer_id: NodeId,
+        n: u64,
+        d: Digest,
+        b: Buf,
+        sh: &NodeShared,
+        sock: &mut PeerSock,
+        tx: &mut MessageChannelTx<D::State, D::Request, D::Reply>,
+    ) -> Result<()> {
+        // compress the payload with the codec of the link; the
+        // signature covers the digest of the original payload
+        let payload = match sock.codec().compress(&b[..]) {
+            Ok(payload) => payload,
+            Err(e) => {
+                sh.send_bufs.put(b);
+                return Err(e);
+            }
+        };
+
+        // create wire msg
+        let wm = SignedWireMessage::new_in_shard_hashed::<D::Hasher>(
+            shard,
+            my_id,
+            peer_id,
+            &payload[..],
+            n,
+            Some(d),
+            &sh.my_key,
+        );
+
+        let result = match sock {
+            PeerSock::Locked(lock, _) => {
+                // send; the lock is released once the write
+                // completes, fails or times out, since the guard
+                // is dropped along with the write future
+                let mut sock = lock.lock().await;
+                if write_bounded(&wm, &mut *sock, sh.write_timeout).await {
+                    Ok(())
+                } else {
+                    // error sending, drop connection
+                    tx.send(Message::DisconnectedTx(peer_id))
+                        .await
+                        .unwrap_or(());
+                    Err("Failed to write message to peer")
+                }
+            }
+            PeerSock::Queued(writer, _) => match writer.enqueue(&wm).await {
+                Ok(true) => Ok(()),
+                // dropped frames are treated as lost in the network
+                Ok(false) => Err("Message dropped by the write queue of the peer"),
+                // the writer task exited, so drop the connection,
+                // in case we haven't been notified of it yet
+                Err(_) => {
+                    tx.send(Message::DisconnectedTx(peer_id))
+                        .await
+                        .unwrap_or(());
+                    Err("Failed to write message to peer")
+                }
+            },
+        };
+        drop(payload);
+        sh.send_bufs.put(b);
+
+        result.wrapped(ErrorKind::Communication)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::executor::block_on;
+
+    #[cfg(feature = "nonce_counter")]
+    use super::Nonces;
+    use super::{
+        deliver_rx, deserialize_bounded, in_target_order, keep_rogue, link_for, signed_by_peer,
+        well_formed, write_bounded, ConnEvent, ConnState, InFlight, LinkKind, NodeConfigBuilder,
+        NodeId, PeerLinks,
+    };
+    use crate::bft::communication::channel::new_message_channel;
+    use crate::bft::communication::message::{
+        ConsensusMessage, ConsensusMessageKind, Header, Message, RequestMessage, SystemMessage,
+    };
+    use crate::bft::communication::reconnect::Backoff;
+    use crate::bft::communication::serialize::SharedData;
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::error::*;
+    use crate::bft::ordering::{Orderable, SeqNo};
+    use crate::bft::threadpool;
+
+    // the first byte of a payload sets the number of ms
+    // it takes to deserialize it; messages are serialized
+    // such that they take no time at all
+    struct SlowData;
+
+    impl SharedData for SlowData {
+        type State = ();
+        type Request = ();
+        type Reply = ();
+        type Hasher = DefaultHasher;
+
+        fn serialize_message<W: Write>(mut w: W, _m: &SystemMessage<(), (), ()>) -> Result<()> {
+            w.write_all(&[0]).wrapped(ErrorKind::CommunicationSerialize)
+        }
+
+        fn deserialize_message<R: Read>(mut r: R) -> Result<SystemMessage<(), (), ()>> {
+            let mut ms = [0];
+            r.read_exact(&mut ms[..])
+                .wrapped(ErrorKind::CommunicationSerialize)?;
+            std::thread::sleep(Duration::from_millis(ms[0] as u64));
+            Ok(SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, ())))
+        }
+
+        fn serialize_state<W: Write>(_w: W, _s: &()) -> Result<()> {
+            Ok(())
+        }
+
+        fn deserialize_state<R: Read>(_r: R) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    // requests with an odd operation are malformed
+    struct PickyData;
+
+    impl SharedData for PickyData {
+        type State = ();
+        type Request = u64;
+        type Reply = ();
+        type Hasher = DefaultHasher;
+
+        fn serialize_message<W: Write>(_w: W, _m: &SystemMessage<(), u64, ()>) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn validate_request(request: &u64) -> Result<()> {
+            if request % 2 != 0 {
+                return Err("Odd request").wrapped(ErrorKind::CommunicationSerialize);
+            }
+            Ok(())
+        }
+
+        fn deserialize_message<R: Read>(_r: R) -> Result<SystemMessage<(), u64, ()>> {
+            unimplemented!()
+        }
+
+        fn serialize_state<W: Write>(_w: W, _s: &()) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn deserialize_state<R: Read>(_r: R) -> Result<()> {
+            unimplemented!()
+        }
+    }
+
+    // requests are serialized into their raw bytes, which
+    // may only take up a handful of them
+    struct TinyData;
+
+    impl SharedData for TinyData {
+        type State = ();
+        type Request = Vec<u8>;
+        type Reply = ();
+        type Hasher = DefaultHasher;
+
+        fn serialize_message<W: Write>(mut w: W, m: &SystemMessage<(), Vec<u8>, ()>) -> Result<()> {
+            match m {
+                SystemMessage::Request(m) => w
+                    .write_all(&m.operation()[..])
+                    .wrapped(ErrorKind::CommunicationSerialize),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn max_payload_len() -> usize {
+            16
+        }
+
+        fn deserialize_message<R: Read>(_r: R) -> Result<SystemMessage<(), Vec<u8>, ()>> {
+            unimplemented!()
+        }
+
+        fn serialize_state<W: Write>(_w: W, _s: &()) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn deserialize_state<R: Read>(_r: R) -> Result<()> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_oversized_payload_not_sent() {
+        use futures::FutureExt;
+
+        use super::Node;
+        use crate::bft::crypto::signature::KeyPair;
+
+        let me = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<TinyData> = Node::loopback(me, sk);
+        let oversized = || {
+            let operation = vec![0; TinyData::max_payload_len()];
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, operation))
+        };
+
+        // the tag prefixed to the payload tips it over the limit
+        assert!(node.try_send(oversized(), me).is_err());
+        assert!(node
+            .try_broadcast(oversized(), std::iter::once(me))
+            .is_err());
+        assert!(node
+            .broadcast_detached(oversized(), std::iter::once(me))
+            .is_err());
+        node.send(oversized(), me);
+
+        // nothing was ever sent
+        assert!(node.receive().now_or_never().is_none());
+    }
+
+    #[test]
+    fn test_client_flood_shedding() {
+        const BOUND: usize = 4;
+        const FLOOD: usize = 1024;
+
+        let (mut tx, mut rx) = new_message_channel::<(), (), ()>(BOUND);
+        let dropped = AtomicU64::new(0);
+        let header = Header::deserialize_from(&[0; Header::LENGTH][..]).unwrap();
+
+        block_on(async {
+            // nobody is reading from the channel, so if client
+            // messages were not shed, this would block forever
+            for _ in 0..FLOOD {
+                let message = SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, ()));
+                deliver_rx(&mut tx, &dropped, true, header, message).await;
+            }
+            assert!(dropped.load(Ordering::Relaxed) > 0);
+
+            // replica messages still flow
+            let kind = ConsensusMessageKind::Prepare(
+                Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap(),
+            );
+            let message =
+                SystemMessage::Consensus(ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind));
+            deliver_rx(&mut tx, &dropped, false, header, message).await;
+
+            loop {
+                match rx.recv().await.unwrap() {
+                    Message::System(_, SystemMessage::Consensus(_)) => break,
+                    Message::System(_, SystemMessage::Request(_)) => continue,
+                    _ => panic!("Unexpected message"),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_rogue_messages_bounded() {
+        const BOUND: usize = 16;
+        const FLOOD: usize = 1024;
+
+        let mut rogue = Vec::new();
+        let dropped = AtomicU64::new(0);
+        let header = Header::deserialize_from(&[0; Header::LENGTH][..]).unwrap();
+
+        // a peer floods us with requests while we are bootstrapping
+        for i in 0..FLOOD {
+            let request = RequestMessage::new(SeqNo::from(i as u32), ());
+            let message: Message<(), (), ()> =
+                Message::System(header, SystemMessage::Request(request));
+            keep_rogue(&mut rogue, BOUND, &dropped, message);
+            assert!(rogue.len() <= BOUND);
+        }
+        assert_eq!(rogue.len(), BOUND);
+        assert_eq!(dropped.load(Ordering::Relaxed), (FLOOD - BOUND) as u64);
+
+        // the earliest messages are the ones kept
+        for (i, message) in rogue.iter().enumerate() {
+            match message {
+                Message::System(_, SystemMessage::Request(request)) => {
+                    assert_eq!(request.sequence_number(), SeqNo::from(i as u32));
+                }
+                _ => panic!("Unexpected message"),
+            }
+        }
+    }
+
+    #[cfg(feature = "nonce_counter")]
+    #[test]
+    fn test_counter_nonces() {
+        let take = |n: &mut Nonces| (0..64).map(|_| n.next()).collect::<Vec<_>>();
+
+        let mut nonces = Nonces::new(NodeId::from(2u32));
+        let mut forked = nonces.fork();
+
+        // nonces are strictly increasing, even across forks
+        let first = take(&mut nonces);
+        let second = take(&mut forked);
+        let all: Vec<_> = first.iter().chain(second.iter()).copied().collect();
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+
+        // the same node id always produces the same nonces
+        let mut again = Nonces::new(NodeId::from(2u32));
+        assert_eq!(take(&mut again), first);
+    }
+
+    #[test]
+    fn test_peer_links_reconnection() {
+        let peer = NodeId::from(1u32);
+        let mut links = PeerLinks::new();
+
+        links.tx.insert(peer);
+        assert!(!links.is_connected(peer));
+        links.rx.insert(peer);
+        assert!(links.is_connected(peer));
+
+        // the peer goes away, and reconnects; it is only usable
+        // again once both directions are re-established
+        links.rx.remove(&peer);
+        links.tx.remove(&peer);
+        assert!(!links.is_connected(peer));
+        links.rx.insert(peer);
+        assert!(!links.is_connected(peer));
+        links.tx.insert(peer);
+        assert!(links.is_connected(peer));
+
+        assert!(!links.is_connected(NodeId::from(2u32)));
+    }
+
+    #[test]
+    fn test_connection_events() {
+        use futures::{FutureExt, StreamExt};
+
+        let leader = NodeId::from(0u32);
+        let other = NodeId::from(2u32);
+        let mut links = PeerLinks::new();
+        let mut events = links.subscribe();
+        let mut next = || events.next().now_or_never().unwrap();
+
+        links.update(leader, |links| {
+            links.tx.insert(leader);
+        });
+        links.update(other, |links| {
+            links.rx.insert(other);
+        });
+        links.update(leader, |links| {
+            links.rx.insert(leader);
+        });
+        let expected = [
+            (leader, Some(ConnState::TxOnly)),
+            (other, Some(ConnState::RxOnly)),
+            (leader, Some(ConnState::Connected)),
+        ];
+        for &(peer_id, state) in expected.iter() {
+            assert_eq!(next(), Some(ConnEvent { peer_id, state }));
+        }
+        let status = links.status();
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[&leader], ConnState::Connected);
+        assert_eq!(status[&other], ConnState::RxOnly);
+
+        // updates leaving the state unchanged are not reported
+        links.update(leader, |links| {
+            links.rx.insert(leader);
+        });
+        assert!(events.next().now_or_never().is_none());
+
+        // losing contact with the leader, one direction at a time
+        links.update(leader, |links| {
+            links.tx.remove(&leader);
+        });
+        links.update(leader, |links| {
+            links.rx.remove(&leader);
+        });
+        let mut next = || events.next().now_or_never().unwrap();
+        let state = Some(ConnState::RxOnly);
+        assert_eq!(
+            next(),
+            Some(ConnEvent {
+                peer_id: leader,
+                state
+            })
+        );
+        assert_eq!(
+            next(),
+            Some(ConnEvent {
+                peer_id: leader,
+                state: None
+            })
+        );
+        assert!(!links.status().contains_key(&leader));
+
+        // subscribers who went away are dropped
+        drop(events);
+        links.update(other, |links| {
+            links.rx.remove(&other);
+        });
+        assert!(links.subscribers.is_empty());
+    }
+
+    #[test]
+    fn test_wait_for_quorum() {
+        use super::Node;
+        use crate::bft::crypto::signature::KeyPair;
+
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<SlowData> = Node::loopback(NodeId::from(0u32), sk);
+        let mut tx = node.my_tx.clone();
+        node.members = NodeId::targets(0..4).collect();
+        node.f = 1;
+
+        // connected to two other replicas, which
+        // along with ourselves make up a quorum
+        for peer_id in NodeId::targets(1..3) {
+            node.links.tx.insert(peer_id);
+            node.links.rx.insert(peer_id);
+        }
+        let mut received = Vec::new();
+        let waited = block_on(node.wait_for_quorum(Duration::from_millis(50), &mut received));
+        assert!(waited.is_ok());
+        assert!(received.is_empty());
+
+        // both of them shut down, one of which is only noticed
+        // while waiting; the other messages received in the
+        // meantime are handed back to the caller
+        node.handle_disconnected_rx(NodeId::from(2u32));
+        block_on(async {
+            tx.send(Message::ClusterUnrecoverable(SeqNo::ZERO))
                 .await
-                .unwrap_or(());
+                .unwrap();
+            tx.send(Message::Departed(NodeId::from(1u32)))
+                .await
+                .unwrap();
+        });
+        let waited = block_on(node.wait_for_quorum(Duration::from_millis(50), &mut received));
+        assert!(waited.is_err());
+        assert!(!node.is_connected(NodeId::from(1u32)));
+        assert!(matches!(
+            received[..],
+            [Message::ClusterUnrecoverable(seq)] if seq == SeqNo::ZERO
+        ));
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_wait_for_quorum_startup() {
+        use std::sync::Arc;
+
+        use futures::future::join_all;
+
+        use super::Node;
+        use crate::bft::async_runtime as rt;
+        use crate::bft::collections;
+        use crate::bft::communication::socket::MemoryNetwork;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::testing::{init_runtime, tls_configs, CounterData};
+
+        init_runtime();
+
+        let names = ["cop01", "cop02", "cop03", "cop04"];
+        let network = Arc::new(MemoryNetwork::new());
+        let sk = || KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut addrs = collections::hash_map();
+        let mut pk = collections::hash_map();
+        for (i, &name) in names.iter().enumerate() {
+            let addr = format!("127.0.0.1:{}", 10000 + i).parse().unwrap();
+            addrs.insert(NodeId::from(i), (addr, String::from(name)));
+            pk.insert(NodeId::from(i), sk().public_key().into());
+        }
+
+        // the four replicas start concurrently, and each of them
+        // waits for a quorum before it would send any traffic
+        let replicas = names.iter().enumerate().map(|(i, &name)| {
+            let (client_config, server_config) = tls_configs(name);
+            let cfg = NodeConfigBuilder::new()
+                .n(4)
+                .f(1)
+                .id(NodeId::from(i))
+                .first_cli(NodeId::from(1000u32))
+                .addrs(addrs.clone())
+                .pk(pk.clone())
+                .sk(sk())
+                .client_config(client_config)
+                .server_config(server_config)
+                .socket_provider(network.clone())
+                .pool(threadpool::Builder::new().num_threads(1).build())
+                .build()
+                .unwrap();
+            async move {
+                let (mut node, mut rogue) = Node::<CounterData>::bootstrap(cfg).await?;
+                node.wait_for_quorum(Duration::from_secs(10), &mut rogue)
+                    .await?;
+                Ok::<_, Error>(node)
+            }
+        });
+        let replicas = rt::block_on(join_all(replicas));
+
+        for node in replicas {
+            let node = node.unwrap();
+            let connected = NodeId::targets(0..4)
+                .filter(|&peer_id| node.is_connected(peer_id))
+                .count();
+            assert!(connected >= 2);
+        }
+    }
+
+    #[cfg(all(feature = "serialize_serde", feature = "compress_zstd"))]
+    #[test]
+    fn test_compression_negotiated() {
+        use std::sync::Arc;
+
+        use futures::future::join_all;
+
+        use super::{Codec, Node, PeerTx};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::collections;
+        use crate::bft::communication::socket::MemoryNetwork;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::testing::{init_runtime, tls_configs, CounterData};
+
+        init_runtime();
+
+        let names = ["cop01", "cop02", "cop03", "cop04"];
+        let network = Arc::new(MemoryNetwork::new());
+        let sk = || KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut addrs = collections::hash_map();
+        let mut pk = collections::hash_map();
+        for (i, &name) in names.iter().enumerate() {
+            let addr = format!("127.0.0.1:{}", 10100 + i).parse().unwrap();
+            addrs.insert(NodeId::from(i), (addr, String::from(name)));
+            pk.insert(NodeId::from(i), sk().public_key().into());
+        }
+
+        // only the first two replicas support zstd
+        let replicas = names.iter().enumerate().map(|(i, &name)| {
+            let (client_config, server_config) = tls_configs(name);
+            let compression = if i < 2 {
+                vec![Codec::None, Codec::Zstd]
+            } else {
+                vec![Codec::None]
+            };
+            let cfg = NodeConfigBuilder::new()
+                .n(4)
+                .f(1)
+                .id(NodeId::from(i))
+                .first_cli(NodeId::from(1000u32))
+                .addrs(addrs.clone())
+                .pk(pk.clone())
+                .sk(sk())
+                .client_config(client_config)
+                .server_config(server_config)
+                .socket_provider(network.clone())
+                .pool(threadpool::Builder::new().num_threads(1).build())
+                .compression(compression)
+                .build()
+                .unwrap();
+            Node::<CounterData>::bootstrap(cfg)
+        });
+        let mut replicas: Vec<_> = rt::block_on(join_all(replicas))
+            .into_iter()
+            .map(|result| result.unwrap().0)
+            .collect();
+
+        // the codec of the link `from` sends messages over to `to`
+        let codec = |node: &Node<CounterData>, to: u32| match node.peer_tx {
+            PeerTx::Server(ref map) => map[&NodeId::from(to)].codec(),
+            PeerTx::Client(_) => unreachable!(),
+        };
+        assert_eq!(codec(&replicas[0], 1), Codec::Zstd);
+        assert_eq!(codec(&replicas[1], 0), Codec::Zstd);
+        assert_eq!(codec(&replicas[0], 2), Codec::None);
+        assert_eq!(codec(&replicas[2], 0), Codec::None);
+
+        // messages are delivered intact over both kinds of links
+        let request = || SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42));
+        replicas[0].send(request(), NodeId::from(1u32));
+        replicas[0].send(request(), NodeId::from(2u32));
+        for node in &mut replicas[1..3] {
+            let received = rt::block_on(async {
+                loop {
+                    match node.receive().await.unwrap() {
+                        Message::System(header, message) => break (header, message),
+                        _ => continue,
+                    }
+                }
+            });
+            match received {
+                (header, SystemMessage::Request(m)) => {
+                    assert_eq!(header.from(), NodeId::from(0u32));
+                    assert_eq!(*m.operation(), 42);
+                }
+                _ => panic!("Expected a request"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_peer_links_duplicate_rx() {
+        let me = NodeId::from(0u32);
+        let mut links = PeerLinks::new();
+
+        // the rx side of a peer arrives twice, while
+        // its tx side only arrives afterwards
+        for peer_id in NodeId::targets(1..3) {
+            links.tx.insert(peer_id);
+            links.rx.insert(peer_id);
+        }
+        let late = NodeId::from(3u32);
+        links.rx.insert(late);
+        links.rx.insert(late);
+        let members: Vec<_> = NodeId::targets(0..4).collect();
+        assert!(!links.replicas_connected(me, &members));
+        links.tx.insert(late);
+        assert!(links.replicas_connected(me, &members));
+    }
+
+    #[test]
+    fn test_malformed_requests_dropped() {
+        use crate::bft::communication::message::ReadRequestMessage;
+
+        // well formed requests are delivered
+        let request = SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 2));
+        assert!(well_formed::<PickyData>(&request));
+
+        // deserializable, but malformed requests are dropped before
+        // reaching the master channel, and thus the log
+        let request = SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 3));
+        assert!(!well_formed::<PickyData>(&request));
+        let request = SystemMessage::ReadRequest(ReadRequestMessage::new(None, 3));
+        assert!(!well_formed::<PickyData>(&request));
+
+        // other messages carry no client operations
+        let kind = ConsensusMessageKind::Prepare(
+            Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap(),
+        );
+        let message =
+            SystemMessage::Consensus(ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind));
+        assert!(well_formed::<PickyData>(&message));
+    }
+
+    #[test]
+    fn test_deserialize_timeout() {
+        use std::sync::Arc;
+
+        const TIMEOUT: Duration = Duration::from_millis(50);
+
+        let pool = threadpool::Builder::new().num_threads(1).build();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let in_flight = || InFlight::enter(&counter);
+
+        block_on(async {
+            // payloads are prefixed with the tag of the generic encoding
+            let cheap = Bytes::from_static(&[0, 0]);
+            match deserialize_bounded::<SlowData>(&pool, TIMEOUT, cheap.clone(), in_flight()).await
+            {
+                Ok(SystemMessage::Request(m)) => {
+                    assert_eq!(m.serialized(), Some(&cheap.slice(1..)))
+                }
+                _ => panic!("Failed to deserialize request"),
+            }
+
+            // this payload exceeds the budget, so the rx
+            // task would drop the connection
+            let expensive = Bytes::from_static(&[0, 200]);
+            assert!(
+                deserialize_bounded::<SlowData>(&pool, TIMEOUT, expensive, in_flight())
+                    .await
+                    .is_err()
+            );
+
+            // the job keeps running in the background
+            assert_eq!(counter.load(Ordering::Relaxed), 1);
+
+            // malformed payloads are reported as well
+            assert!(
+                deserialize_bounded::<SlowData>(&pool, TIMEOUT, Bytes::new(), in_flight())
+                    .await
+                    .is_err()
+            );
+        });
+    }
+
+    #[test]
+    fn test_slow_payload_drops_connection() {
+        use std::pin::Pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll};
+
+        use futures::io::{AsyncRead, AsyncReadExt, Cursor};
+
+        use super::{Codec, Node};
+        use crate::bft::communication::message::SignedWireMessage;
+        use crate::bft::crypto::hash::Context as Hasher;
+        use crate::bft::crypto::signature::KeyPair;
+
+        const TIMEOUT: Duration = Duration::from_millis(100);
+
+        // a connection the peer keeps open, without sending anything else
+        struct Idle;
+
+        impl AsyncRead for Idle {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                _: &mut Context,
+                _: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                Poll::Pending
+            }
+        }
+
+        let (me, peer) = (NodeId::from(0u32), NodeId::from(1u32));
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<SlowData> = Node::loopback(peer, sk);
+        let shared = Arc::clone(&node.shared);
+        let pool = threadpool::Builder::new().num_threads(1).build();
+        let (tx, shutdown) = (node.my_tx.clone(), node.shutdown.listener().unwrap());
+
+        // the frame of a message sent by `peer`, taking `ms` to deserialize
+        let frame = |ms: u8| {
+            let payload = [0, ms];
+            let digest = {
+                let mut ctx = Hasher::new();
+                ctx.update(&payload[..]);
+                ctx.finish()
+            };
+            let mut stream = Vec::new();
+            SignedWireMessage::new(peer, me, &payload[..], 0, Some(digest), &shared.my_key)
+                .serialize_framed_into(&mut stream)
+                .unwrap();
+            stream
+        };
+        // reads `stream`, then idles or closes the connection
+        let read = |stream: Vec<u8>, idle: bool| {
+            let sock: Box<dyn AsyncRead + Unpin + Send> = if idle {
+                Box::new(Cursor::new(stream).chain(Idle))
+            } else {
+                Box::new(Cursor::new(stream))
+            };
+            block_on(Node::<SlowData>::rx_side_read(
+                sock,
+                Codec::None,
+                peer,
+                false,
+                Arc::clone(&shared),
+                pool.clone(),
+                TIMEOUT,
+                1,
+                None,
+                shutdown.clone(),
+                tx.clone(),
+            ))
+        };
+        let mut next = || block_on(node.receive()).unwrap();
+
+        // the connection is dropped once the budget runs out,
+        // even though the peer didn't close it
+        read(frame(250), true);
+        assert!(matches!(next(), Message::DisconnectedRx(Some(id)) if id == peer));
+
+        // the payload is still being deserialized, so the peer
+        // can't have another one deserialized by reconnecting
+        read(frame(0), true);
+        assert!(matches!(next(), Message::DisconnectedRx(Some(id)) if id == peer));
+        assert_eq!(shared.deserializing(peer).load(Ordering::Relaxed), 1);
+
+        // its messages are deserialized again once the job completes
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(shared.deserializing(peer).load(Ordering::Relaxed), 0);
+        read(frame(0), false);
+        let (mut requests, mut disconnected) = (0, 0);
+        for _ in 0..2 {
+            match next() {
+                Message::System(_, SystemMessage::Request(_)) => requests += 1,
+                Message::DisconnectedRx(Some(id)) if id == peer => disconnected += 1,
+                _ => panic!("Unexpected message"),
+            }
+        }
+        assert_eq!((requests, disconnected), (1, 1));
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_request_metadata_survives_wire() {
+        use crate::bft::communication::message::{RequestMeta, TraceId};
+        use crate::bft::communication::serialize::{Buf, DigestData};
+        use crate::bft::testing::CounterData;
+
+        let pool = threadpool::Builder::new().num_threads(1).build();
+        let metadata = RequestMeta::new(5, Some(Duration::from_secs(1)));
+        let request = RequestMessage::new(SeqNo::from(3u32), 42)
+            .with_trace_id(Some(TraceId::from(7)))
+            .with_metadata(Some(metadata));
+        let mut buf = Buf::new();
+        let digest = <CounterData as DigestData>::serialize_digest(
+            &SystemMessage::Request(request),
+            &mut buf,
+        )
+        .unwrap();
+        let payload = Bytes::copy_from_slice(&buf[..]);
+
+        // the request is received as it was sent, along
+        // with the serialized bytes it was received in
+        let timeout = Duration::from_secs(1);
+        let request = match block_on(deserialize_bounded::<CounterData>(
+            &pool,
+            timeout,
+            payload.clone(),
+            InFlight::enter(&Default::default()),
+        )) {
+            Ok(SystemMessage::Request(request)) => request,
+            _ => panic!("Failed to deserialize request"),
+        };
+        assert_eq!(request.sequence_number(), SeqNo::from(3u32));
+        assert_eq!(request.trace_id(), Some(TraceId::from(7)));
+        assert_eq!(request.metadata(), Some(&metadata));
+        assert_eq!(*request.operation(), 42);
+        assert_eq!(request.serialized(), Some(&payload.slice(1..)));
+
+        // hence it hashes to the same digest once relayed
+        let mut buf = Buf::new();
+        let relayed = <CounterData as DigestData>::serialize_digest(
+            &SystemMessage::Request(request),
+            &mut buf,
+        )
+        .unwrap();
+        assert!(relayed == digest);
+    }
+
+    #[test]
+    fn test_write_timeout() {
+        use std::io;
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+
+        use futures::io::AsyncWrite;
+
+        use crate::bft::communication::message::UnsignedWireMessage;
+
+        const TIMEOUT: Duration = Duration::from_millis(50);
+
+        // a peer that never reads from its connection
+        struct Stalled;
+
+        impl AsyncWrite for Stalled {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                _cx: &mut Context<'_>,
+                _buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Poll::Pending
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Pending
+            }
+
+            fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Pending
+            }
+        }
+
+        let (me, peer) = (NodeId::from(0u32), NodeId::from(1u32));
+        let payload = &[1, 2, 3][..];
+        let wm = UnsignedWireMessage::new(me, peer, payload, 0, None);
+
+        block_on(async {
+            // writes to a healthy connection complete within the timeout
+            let mut buf = Vec::new();
+            assert!(write_bounded(&wm, &mut buf, Some(TIMEOUT)).await);
+            assert_eq!(buf.len(), Header::LENGTH + payload.len());
+
+            // writes to a stalled connection are given up on, so the
+            // sending task would drop the connection
+            assert!(!write_bounded(&wm, Stalled, Some(TIMEOUT)).await);
+        });
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_idle_client_reaped() {
+        use std::sync::Arc;
+        use std::time::Instant;
+
+        use super::{Codec, IdleReaper, Node, PeerSock, PeerTx};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::peer_writer::{
+            PeerWriter, QueueFullPolicy, WriteQueueConfig,
+        };
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::testing::{init_runtime, CounterData};
+
+        const TIMEOUT: Duration = Duration::from_millis(50);
+
+        init_runtime();
+
+        let client = NodeId::from(1000u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+        Arc::get_mut(&mut node.shared).unwrap().reaper = Some(IdleReaper::new(TIMEOUT));
+
+        // the client connects, as in `handle_connected_*`
+        let cfg = WriteQueueConfig {
+            depth: 1,
+            policy: QueueFullPolicy::Drop,
+        };
+        let sock = PeerWriter::spawn(futures::io::sink(), cfg, None, async {});
+        if let PeerTx::Server(ref mut peer_tx) = node.peer_tx {
+            peer_tx.insert(client, PeerSock::Queued(sock, Codec::None));
+        }
+        node.links.tx.insert(client);
+        node.links.rx.insert(client);
+        let reaper = node.shared.reaper.as_ref().unwrap();
+        let reaped = reaper.register(client, Instant::now());
+        assert!(node.is_connected(client));
+
+        rt::spawn(Node::<CounterData>::reap_idle_clients(
+            Arc::downgrade(&node.shared),
+            node.my_tx.clone(),
+            TIMEOUT / 2,
+            node.shutdown.listener().unwrap(),
+        ));
+
+        block_on(async {
+            // the client idles past the timeout
+            match node.receive().await.unwrap() {
+                Message::DisconnectedTx(peer_id) => {
+                    assert_eq!(peer_id, client);
+                    node.handle_disconnected_tx(peer_id);
+                }
+                _ => panic!("Expected the client to be reaped"),
+            }
+            // the rx task of the client would close its socket
+            assert!(reaped.await.is_err());
+        });
+
+        assert!(!node.is_connected(client));
+        assert!(!node.shared.reaper.as_ref().unwrap().is_tracked(client));
+        match node.peer_tx {
+            PeerTx::Server(ref peer_tx) => assert!(!peer_tx.contains_key(&client)),
+            PeerTx::Client(_) => unreachable!(),
+        }
+
+        // the reaper exits once the node is shut down,
+        // even though the node itself is still alive
+        block_on(node.shutdown());
+    }
+
+    #[test]
+    fn test_unsigned_data_message_rejected() {
+        use crate::bft::collections;
+        use crate::bft::communication::message::{SignedWireMessage, UnsignedWireMessage};
+        use crate::bft::crypto::hash::Context;
+        use crate::bft::crypto::signature::KeyPair;
+
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let (me, peer) = (NodeId::from(0u32), NodeId::from(1u32));
+        let mut peer_keys = collections::hash_map();
+        peer_keys.insert(peer, sk.public_key().into());
+        let payload = b"payload";
+        let digest = {
+            let mut ctx = Context::new();
+            ctx.update(payload);
+            Some(ctx.finish())
+        };
+
+        let (header, _) = SignedWireMessage::new(peer, me, payload, 0, digest, &sk).into_inner();
+        assert!(signed_by_peer::<DefaultHasher>(&peer_keys, peer, header, payload).is_ok());
+
+        // a payload other than the one the peer signed
+        assert!(signed_by_peer::<DefaultHasher>(&peer_keys, peer, header, b"tampers").is_err());
+
+        // an unsigned message with a payload, i.e. not a handshake
+        let (header, _) = UnsignedWireMessage::new(peer, me, payload, 0, digest).into_inner();
+        assert!(signed_by_peer::<DefaultHasher>(&peer_keys, peer, header, payload).is_err());
+
+        // a message claiming to be sent by another node
+        let other = NodeId::from(2u32);
+        let (header, _) = SignedWireMessage::new(other, me, payload, 0, digest, &sk).into_inner();
+        assert!(signed_by_peer::<DefaultHasher>(&peer_keys, peer, header, payload).is_err());
+    }
+
+    #[test]
+    fn test_parallel_verification_in_order() {
+        use std::sync::Arc;
+        use std::time::Instant;
+
+        use futures::StreamExt;
+
+        use super::{verify_and_deserialize, Node, Received, Verifying};
+        use crate::bft::communication::message::SignedWireMessage;
+        use crate::bft::crypto::hash::Context;
+        use crate::bft::crypto::signature::KeyPair;
+
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        let (me, peer) = (NodeId::from(0u32), NodeId::from(1u32));
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let node: Node<SlowData> = Node::loopback(peer, sk);
+        let pool = threadpool::Builder::new().num_threads(4).build();
+
+        // later messages take less time to deserialize, so they
+        // would be delivered first, if their order wasn't kept
+        let messages: Vec<_> = (0..8u8)
+            .map(|i| {
+                let payload = Bytes::from(vec![0, 40 - 4 * i]);
+                let digest = {
+                    let mut ctx = Context::new();
+                    ctx.update(&payload[..]);
+                    ctx.finish()
+                };
+                let sk = &node.shared.my_key;
+                let (header, _) =
+                    SignedWireMessage::new(peer, me, &payload[..], i as u64, Some(digest), sk)
+                        .into_inner();
+                (header, payload)
+            })
+            .collect();
+
+        // mirrors the rx task of a connection, minus the socket
+        let receive = |parallelism| {
+            let mut verifying = Verifying::new(parallelism);
+            let mut delivered = Vec::new();
+            let mut deliver = |received: Received<(), (), ()>| match received {
+                Received::Message(header, _) => delivered.push(header),
+                _ => panic!("Rejected a valid message"),
+            };
+            let start = Instant::now();
+            block_on(async {
+                for (header, payload) in messages.iter().cloned() {
+                    while verifying.is_full() {
+                        deliver(verifying.pending.next().await.unwrap());
+                    }
+                    verifying.push(verify_and_deserialize::<SlowData>(
+                        pool.clone(),
+                        Arc::clone(&node.shared),
+                        peer,
+                        header,
+                        payload,
+                        TIMEOUT,
+                        InFlight::enter(&node.shared.deserializing(peer)),
+                    ));
+                }
+                while let Some(received) = verifying.pending.next().await {
+                    deliver(received);
+                }
+            });
+            (delivered, start.elapsed())
+        };
+
+        let (sequential, sequential_elapsed) = receive(1);
+        let (parallel, parallel_elapsed) = receive(4);
+        let expected: Vec<_> = messages.iter().map(|(header, _)| *header).collect();
+        assert_eq!(sequential, expected);
+        assert_eq!(parallel, expected);
+        assert!(parallel_elapsed * 2 < sequential_elapsed);
+
+        // forged messages are still caught
+        let (header, _) = messages[0].clone();
+        let received = block_on(verify_and_deserialize::<SlowData>(
+            pool.clone(),
+            Arc::clone(&node.shared),
+            peer,
+            header,
+            Bytes::from_static(&[0, 1]),
+            TIMEOUT,
+            InFlight::enter(&node.shared.deserializing(peer)),
+        ));
+        assert!(matches!(received, Received::Forged(_)));
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_digest_entropy_disabled() {
+        use std::sync::Arc;
+
+        use super::Node;
+        use crate::bft::communication::serialize::DigestData;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::testing::{init_runtime, CounterData};
+
+        init_runtime();
+
+        let me = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(me, sk);
+        Arc::get_mut(&mut node.shared).unwrap().digest_entropy = false;
+
+        let message = || SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42));
+        let expected = CounterData::serialize_digest(&message(), Vec::new()).unwrap();
+
+        // the returned digests are the content digests
+        let digest = node.send(message(), me);
+        assert!(digest == expected);
+        let digest = node.broadcast(message(), std::iter::once(me));
+        assert!(digest == expected);
+
+        // the unique digests, which clients match replies
+        // against, are still available within the crate
+        let (digest, unique) = node.send_with_unique(message(), me);
+        assert!(digest == expected);
+        let mut send_node = node.send_node();
+        let (digest, broadcast_unique) =
+            send_node.broadcast_with_unique(message(), std::iter::once(me));
+        assert!(digest == expected);
+
+        // the nonces are still sent along
+        let received = block_on(async {
+            let mut received = Vec::new();
+            for _ in 0..4 {
+                match node.receive().await.unwrap() {
+                    Message::System(header, _) => {
+                        assert!(header.digest() == &expected);
+                        assert!(header.unique_digest() != expected);
+                        received.push(header.unique_digest());
+                    }
+                    _ => panic!("Expected a request"),
+                }
+            }
+            received
+        });
+        assert!(received.contains(&unique));
+        assert!(received.contains(&broadcast_unique));
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_broadcast_detached() {
+        use super::Node;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::testing::{init_runtime, CounterData};
+
+        init_runtime();
+
+        let me = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(me, sk);
+
+        let message = SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42));
+        let (digest, delivered) = node
+            .broadcast_detached(message, std::iter::once(me))
+            .expect("Message dropped");
+
+        // the outcome of the send is reported for each target
+        let delivered = block_on(delivered);
+        assert_eq!(delivered.len(), 1);
+        assert!(delivered[&me].is_ok());
+
+        block_on(async {
+            match node.receive().await.unwrap() {
+                Message::System(header, _) => assert!(header.unique_digest() == digest),
+                _ => panic!("Expected a request"),
+            }
+        });
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_graceful_disconnect() {
+        use std::sync::Arc;
+
+        use futures::io::Cursor;
+
+        use super::{Codec, Node};
+        use crate::bft::communication::message::SignedWireMessage;
+        use crate::bft::communication::serialize::DigestData;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::testing::CounterData;
+
+        let (me, peer) = (NodeId::from(0u32), NodeId::from(1u32));
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(peer, sk);
+        let shared = Arc::clone(&node.shared);
+        let pool = threadpool::Builder::new().num_threads(1).build();
+        let (tx, shutdown) = (node.my_tx.clone(), node.shutdown.listener().unwrap());
+
+        // appends the frame of a message sent by `peer` to `stream`
+        let frame = |stream: &mut Vec<u8>, message, nonce| {
+            let mut payload = Vec::new();
+            let digest = CounterData::serialize_digest(&message, &mut payload).unwrap();
+            let sk = &shared.my_key;
+            SignedWireMessage::new(peer, me, &payload[..], nonce, Some(digest), sk)
+                .serialize_framed_into(stream)
+                .unwrap();
+        };
+        // reads the frames in `stream` until it ends, like when
+        // `peer` closes its connection
+        let read = |stream: Vec<u8>| {
+            block_on(Node::<CounterData>::rx_side_read(
+                Cursor::new(stream),
+                Codec::None,
+                peer,
+                false,
+                Arc::clone(&shared),
+                pool.clone(),
+                Duration::from_secs(1),
+                2,
+                None,
+                shutdown.clone(),
+                tx.clone(),
+            ))
+        };
+        let request = || SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42));
+
+        // the peer says goodbye before closing its connection
+        let mut stream = Vec::new();
+        frame(&mut stream, request(), 0);
+        frame(&mut stream, SystemMessage::Goodbye, 1);
+        read(stream);
+
+        // the peer crashes, and its connection is cut short
+        let mut stream = Vec::new();
+        frame(&mut stream, request(), 2);
+        read(stream);
+
+        // the messages read before either disconnect are delivered
+        let (mut requests, mut departed, mut disconnected) = (0, 0, 0);
+        block_on(async {
+            for _ in 0..4 {
+                match node.receive().await.unwrap() {
+                    Message::System(_, SystemMessage::Request(_)) => requests += 1,
+                    Message::Departed(id) if id == peer => departed += 1,
+                    Message::DisconnectedRx(Some(id)) if id == peer => disconnected += 1,
+                    _ => panic!("Unexpected message"),
+                }
+            }
+        });
+        assert_eq!((requests, departed, disconnected), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_broadcast_target_order() {
+        let me = NodeId::from(2u32);
+        let orders: [&[u32]; 3] = [&[0, 1, 3, 4], &[4, 3, 1, 0], &[3, 0, 4, 1]];
+
+        // regardless of the iteration order of the targets
+        // passed to `broadcast()`, the send order is the same
+        for order in orders.iter() {
+            let others = order.iter().copied().map(NodeId::from);
+            let targets = in_target_order(Some(me), others, |id| *id);
+            let expected: Vec<_> = (0..5u32).map(NodeId::from).collect();
+            assert_eq!(targets, expected);
+        }
+
+        let others = vec![NodeId::from(1u32), NodeId::from(0u32)];
+        let targets = in_target_order(None, others, |id| *id);
+        assert_eq!(targets, vec![NodeId::from(0u32), NodeId::from(1u32)]);
+    }
+
+    #[cfg(all(feature = "serialize_serde", feature = "broadcast_ordered"))]
+    #[test]
+    fn test_broadcast_ordered() {
+        use std::sync::Arc;
+
+        use futures::future::join_all;
+
+        use super::Node;
+        use crate::bft::async_runtime as rt;
+        use crate::bft::collections;
+        use crate::bft::communication::socket::MemoryNetwork;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::testing::{init_runtime, tls_configs, CounterData};
+
+        init_runtime();
+
+        let names = ["cop01", "cop02", "cop03", "cop04"];
+        let network = Arc::new(MemoryNetwork::new());
+        let sk = || KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut addrs = collections::hash_map();
+        let mut pk = collections::hash_map();
+        for (i, &name) in names.iter().enumerate() {
+            let addr = format!("127.0.0.1:{}", 10200 + i).parse().unwrap();
+            addrs.insert(NodeId::from(i), (addr, String::from(name)));
+            pk.insert(NodeId::from(i), sk().public_key().into());
+        }
+
+        let replicas = names.iter().enumerate().map(|(i, &name)| {
+            let (client_config, server_config) = tls_configs(name);
+            let cfg = NodeConfigBuilder::new()
+                .n(4)
+                .f(1)
+                .id(NodeId::from(i))
+                .first_cli(NodeId::from(1000u32))
+                .addrs(addrs.clone())
+                .pk(pk.clone())
+                .sk(sk())
+                .client_config(client_config)
+                .server_config(server_config)
+                .socket_provider(network.clone())
+                .pool(threadpool::Builder::new().num_threads(1).build())
+                .build()
+                .unwrap();
+            Node::<CounterData>::bootstrap(cfg)
+        });
+        let mut replicas: Vec<_> = rt::block_on(join_all(replicas))
+            .into_iter()
+            .map(|result| result.unwrap().0)
+            .collect();
+
+        // the targets are out of order, and include ourselves, as well
+        // as a client we are not connected to, which is skipped over
+        let request = SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42));
+        let targets = [3u32, 2, 1000, 0, 1].iter().copied().map(NodeId::from);
+        replicas[2].broadcast(request, targets);
+
+        // every replica receives the request once, ourselves included
+        for node in &mut replicas {
+            let received = rt::block_on(async {
+                loop {
+                    match node.receive().await.unwrap() {
+                        Message::System(header, message) => break (header, message),
+                        _ => continue,
+                    }
+                }
+            });
+            match received {
+                (header, SystemMessage::Request(m)) => {
+                    assert_eq!(header.from(), NodeId::from(2u32));
+                    assert_eq!(*m.operation(), 42);
+                }
+                _ => panic!("Expected a request"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_node_config_builder() {
+        use rustls::{ClientConfig, NoClientAuth, ServerConfig};
+
+        use super::Codec;
+        use crate::bft::collections;
+        use crate::bft::crypto::signature::KeyPair;
+
+        let sk = || KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let addrs = || {
+            let mut addrs = collections::hash_map();
+            for i in 0..4u32 {
+                let addr = format!("127.0.0.1:{}", 10001 + i).parse().unwrap();
+                addrs.insert(NodeId::from(i), (addr, format!("cop0{}", i + 1)));
+            }
+            addrs
+        };
+        let pk = || {
+            let mut pk = collections::hash_map();
+            for i in 0..4u32 {
+                pk.insert(NodeId::from(i), sk().public_key().into());
+            }
+            pk
+        };
+        let builder = || {
+            NodeConfigBuilder::new()
+                .n(4)
+                .f(1)
+                .id(NodeId::from(0u32))
+                .first_cli(NodeId::from(1000u32))
+                .addrs(addrs())
+                .pk(pk())
+                .sk(sk())
+                .client_config(ClientConfig::new())
+        };
+
+        // a missing field is reported
+        let err = builder().build().err().expect("Missing field not detected");
+        assert_eq!(err.kind(), ErrorKind::Communication);
+
+        let cfg = builder()
+            .server_config(ServerConfig::new(NoClientAuth::new()))
+            .connect_retries(5)
+            .build()
+            .expect("Valid config rejected");
+        assert_eq!(cfg.n, 4);
+        assert_eq!(cfg.connect_retries, 5);
+        assert_eq!(cfg.channel_bound, 1024);
+        assert_eq!(cfg.deserialize_timeout, Duration::from_secs(1));
+        assert!(!cfg.authenticate_peers);
+        assert!(cfg.write_queue.is_none());
+        assert_eq!(cfg.send_buf_pool, 64);
+        assert_eq!(cfg.rogue_bound, 1024);
+        assert!(!cfg.bulk_link);
+        assert!(cfg.digest_entropy);
+        assert!(cfg.write_timeout.is_none());
+        assert_eq!(cfg.verify_parallelism, 1);
+        assert!(cfg.socket_opts.nodelay);
+        assert!(cfg.socket_opts.send_buffer_size.is_none());
+        assert!(cfg.socket_opts.recv_buffer_size.is_none());
+        assert!(cfg.reconnect.is_none());
+        assert_eq!(cfg.compression, Codec::available());
+
+        // too few replicas to tolerate `f` faults
+        let err = builder()
+            .server_config(ServerConfig::new(NoClientAuth::new()))
+            .n(3)
+            .build()
+            .err()
+            .expect("Invalid config accepted");
+        assert_eq!(err.kind(), ErrorKind::Communication);
+
+        // no messages would ever be verified
+        let err = builder()
+            .server_config(ServerConfig::new(NoClientAuth::new()))
+            .verify_parallelism(0)
+            .build()
+            .err()
+            .expect("Invalid config accepted");
+        assert_eq!(err.kind(), ErrorKind::Communication);
+
+        // the reconnection delays would shrink
+        let err = builder()
+            .server_config(ServerConfig::new(NoClientAuth::new()))
+            .reconnect(Backoff {
+                factor: 0.5,
+                ..Backoff::default()
+            })
+            .build()
+            .err()
+            .expect("Invalid config accepted");
+        assert_eq!(err.kind(), ErrorKind::Communication);
+
+        // the address of a replica is missing
+        let mut partial = addrs();
+        partial.remove(&NodeId::from(2u32));
+        let err = builder()
+            .server_config(ServerConfig::new(NoClientAuth::new()))
+            .addrs(partial)
+            .build()
+            .err()
+            .expect("Missing replica address not detected");
+        assert_eq!(err.kind(), ErrorKind::Communication);
+        assert!(format!("{:?}", err).contains("NodeId(2)"));
+
+        // the public key of a replica is missing
+        let mut partial = pk();
+        partial.remove(&NodeId::from(3u32));
+        let err = builder()
+            .server_config(ServerConfig::new(NoClientAuth::new()))
+            .pk(partial)
+            .build()
+            .err()
+            .expect("Missing replica key not detected");
+        assert_eq!(err.kind(), ErrorKind::Communication);
+        assert!(format!("{:?}", err).contains("NodeId(3)"));
+
+        // a codec missing from this build can't be negotiated
+        if let Some(codec) = [Codec::Lz4, Codec::Zstd]
+            .iter()
+            .copied()
+            .find(|codec| !codec.is_available())
+        {
+            let err = builder()
+                .server_config(ServerConfig::new(NoClientAuth::new()))
+                .compression(vec![codec])
+                .build()
+                .err()
+                .expect("Unsupported codec accepted");
+            assert_eq!(err.kind(), ErrorKind::Communication);
+        }
+
+        // peers would never present a certificate to authenticate them
+        let err = builder()
+            .server_config(ServerConfig::new(NoClientAuth::new()))
+            .authenticate_peers(true)
+            .build()
+            .err()
+            .expect("Unauthenticated peers accepted");
+        assert_eq!(err.kind(), ErrorKind::CommunicationPeerAuth);
+    }
+
+    // a vote and a state reply, which are sent over links of different kinds
+    fn vote_and_state() -> (SystemMessage<(), (), ()>, SystemMessage<(), (), ()>) {
+        use crate::bft::communication::message::{CstMessage, CstMessageKind};
+        use crate::bft::consensus::log::{Checkpoint, DecisionLog};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::cst::RecoveryState;
+
+        let kind = ConsensusMessageKind::Commit(
+            Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap(),
+        );
+        let vote = SystemMessage::Consensus(ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind));
+        let state = RecoveryState::new(
+            ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap(),
+            Checkpoint::new(SeqNo::ZERO, ()),
+            Vec::new(),
+            DecisionLog::new(),
+        );
+        let state = SystemMessage::Cst(CstMessage::new(
+            SeqNo::ZERO,
+            CstMessageKind::ReplyState(state),
+        ));
+        (vote, state)
+    }
+
+    #[test]
+    fn test_link_kind_of() {
+        let (vote, state) = vote_and_state();
+        assert_eq!(LinkKind::of(&vote), LinkKind::Interactive);
+        assert_eq!(LinkKind::of(&state), LinkKind::Bulk);
+        assert!(LinkKind::Interactive.nodelay());
+        assert!(!LinkKind::Bulk.nodelay());
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_bulk_link_sockets() {
+        use crate::bft::async_runtime as rt;
+        use crate::bft::collections;
+        use crate::bft::communication::socket;
+        use crate::bft::testing::init_runtime;
+
+        init_runtime();
+
+        let peer = NodeId::from(1u32);
+        let (vote, state) = vote_and_state();
+
+        rt::block_on(async {
+            let listener = socket::bind(([127, 0, 0, 1], 0)).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            // open a link of each kind to the peer,
+            // as in `tx_side_connect_task()`
+            let mut interactive = collections::hash_map();
+            let mut bulk = collections::hash_map();
+            for &kind in [LinkKind::Interactive, LinkKind::Bulk].iter() {
+                let sock = socket::connect(addr).await.unwrap();
+                sock.set_nodelay(kind.nodelay()).unwrap();
+                let links = match kind {
+                    LinkKind::Interactive => &mut interactive,
+                    LinkKind::Bulk => &mut bulk,
+                };
+                links.insert(peer, sock);
+                let _accepted = listener.accept().await.unwrap();
+            }
+
+            // votes and states are sent over different
+            // sockets, with the expected options
+            let vote_sock = link_for(LinkKind::of(&vote), peer, &interactive, &bulk).unwrap();
+            let state_sock = link_for(LinkKind::of(&state), peer, &interactive, &bulk).unwrap();
+            assert!(!std::ptr::eq(vote_sock, state_sock));
+            assert!(vote_sock.nodelay().unwrap());
+            assert!(!state_sock.nodelay().unwrap());
+
+            // without a bulk link, states share the interactive link
+            bulk.clear();
+            let state_sock = link_for(LinkKind::of(&state), peer, &interactive, &bulk).unwrap();
+            assert!(std::ptr::eq(state_sock, &interactive[&peer]));
+
+            // without any link, e.g. after it dropped, there is no socket
+            interactive.clear();
+            assert!(link_for(LinkKind::of(&vote), peer, &interactive, &bulk).is_none());
+        });
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_socket_opts() {
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::socket::{self, SocketOpts};
+        use crate::bft::testing::init_runtime;
+
+        const SIZE: usize = 64 * 1024;
+
+        init_runtime();
+
+        let opts = SocketOpts {
+            nodelay: false,
+            send_buffer_size: Some(SIZE),
+            recv_buffer_size: Some(SIZE),
+        };
+
+        rt::block_on(async {
+            let listener = socket::bind(([127, 0, 0, 1], 0)).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            // the options are applied on both ends, as in
+            // `tx_side_connect_task()` and `rx_side_accept()`
+            let dialed = socket::connect(addr).await.unwrap();
+            let accepted = listener.accept().await.unwrap();
+            for sock in [&dialed, &accepted].iter() {
+                sock.set_opts(&SocketOpts::default()).unwrap();
+                assert!(sock.nodelay().unwrap());
+
+                sock.set_opts(&opts).unwrap();
+                assert!(!sock.nodelay().unwrap());
+                // the operating system may round the sizes up
+                assert!(sock.send_buffer_size().unwrap() >= SIZE);
+                assert!(sock.recv_buffer_size().unwrap() >= SIZE);
+            }
+        });
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_socket_provider() {
+        use std::io;
+        use std::net::SocketAddr;
+        use std::pin::Pin;
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+        use std::task::{Context, Poll};
+
+        use futures::future::BoxFuture;
+        use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+        use futures::FutureExt;
+
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::socket::{
+            DefaultProvider, Listener, RawSocket, Socket, SocketOpts, SocketProvider,
+        };
+        use crate::bft::testing::init_runtime;
+
+        // a transport without any of the options of TCP sockets
+        struct Opaque(Socket);
+
+        impl AsyncRead for Opaque {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.0).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for Opaque {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.0).poll_write(cx, buf)
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_flush(cx)
+            }
+
+            fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_close(cx)
+            }
+        }
+
+        impl RawSocket for Opaque {}
+
+        #[derive(Default)]
+        struct Counting {
+            inner: DefaultProvider,
+            connects: AtomicUsize,
+        }
+
+        impl SocketProvider for Counting {
+            fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Listener>> {
+                self.inner.bind(addr)
+            }
+
+            fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Socket>> {
+                self.connects.fetch_add(1, Ordering::SeqCst);
+                self.inner
+                    .connect(addr)
+                    .map(|result| result.map(|sock| Socket::new(Opaque(sock))))
+                    .boxed()
+            }
         }
+
+        init_runtime();
+
+        let counting = Arc::new(Counting::default());
+        let provider: Arc<dyn SocketProvider> = counting.clone();
+        rt::block_on(async {
+            let listener = provider.bind(([127, 0, 0, 1], 0).into()).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let mut dialed = provider.connect(addr).await.unwrap();
+            let mut accepted = listener.accept().await.unwrap();
+
+            // options unsupported by the transport are ignored
+            // when set, as in `tx_side_connect_task()`
+            dialed.set_opts(&SocketOpts::default()).unwrap();
+            let err = dialed.nodelay().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+            dialed.write_all(b"hello").await.unwrap();
+            let mut buf = [0; 5];
+            accepted.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        });
+
+        assert_eq!(counting.connects.load(Ordering::SeqCst), 1);
     }
 }
@@ -0,0 +1,243 @@
+//! Authentication of the id claimed by a peer node, with the
+//! client certificate it presents during the TLS handshake.
+//!
+//! The certificate of the node with id `i` must be valid for the
+//! domain name associated with `i` in `NodeConfig::addrs`.
+//!
+//! Recording the certificate of a peer requires a custom client
+//! certificate verifier, which `rustls` only exposes with its
+//! `dangerous_configuration` feature. Hence, peers can only be
+//! authenticated with the `peer_auth` feature of this crate.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_tls::TlsAcceptor;
+use parking_lot::Mutex;
+use rustls::{Certificate, ServerConfig};
+#[cfg(feature = "peer_auth")]
+use rustls::{ClientCertVerified, ClientCertVerifier, DistinguishedNames, TLSError};
+#[cfg(feature = "peer_auth")]
+use webpki::{DNSName, DNSNameRef, EndEntityCert};
+
+use crate::bft::collections::HashMap;
+use crate::bft::communication::NodeId;
+use crate::bft::error::*;
+
+/// The end entity certificate presented by a peer node
+/// on a single connection, if any.
+pub type PresentedCert = Arc<Mutex<Option<Certificate>>>;
+
+/// Maps the client certificates of peer nodes to their ids.
+#[cfg(feature = "peer_auth")]
+pub struct PeerAuth {
+    base: Arc<ServerConfig>,
+    names: HashMap<NodeId, String>,
+}
+
+/// Stands in for `PeerAuth` without the `peer_auth` feature,
+/// in which case it can never be created.
+#[cfg(not(feature = "peer_auth"))]
+pub enum PeerAuth {}
+
+// wraps the client certificate verifier of the user, recording
+// the certificate presented by the peer, if it is valid
+#[cfg(feature = "peer_auth")]
+struct RecordingVerifier {
+    base: Arc<ServerConfig>,
+    presented: PresentedCert,
+}
+
+#[cfg(feature = "peer_auth")]
+impl PeerAuth {
+    /// Creates a new `PeerAuth`, accepting connections with the TLS
+    /// configuration in `server_config`, whose client certificates
+    /// are checked against the domain names in `addrs`.
+    pub fn new(
+        server_config: ServerConfig,
+        addrs: &HashMap<NodeId, (SocketAddr, String)>,
+    ) -> Result<Self> {
+        Self::check(&server_config)?;
+        let names = addrs
+            .iter()
+            .map(|(id, (_, name))| (*id, name.clone()))
+            .collect();
+        let base = Arc::new(server_config);
+        Ok(Self { base, names })
+    }
+
+    /// Checks if peers can be authenticated with `server_config`.
+    ///
+    /// Its client certificate verifier must ask peers for a
+    /// certificate, e.g. `rustls::AllowAnyAuthenticatedClient`;
+    /// with `rustls::NoClientAuth`, every peer would be rejected.
+    pub fn check(server_config: &ServerConfig) -> Result<()> {
+        if !server_config.get_verifier().offer_client_auth() {
+            return Err("Peers are never asked for a client certificate")
+                .wrapped(ErrorKind::CommunicationPeerAuth);
+        }
+        Ok(())
+    }
+
+    /// Returns a `TlsAcceptor` meant for a single connection, along
+    /// with the slot where the certificate of the peer is recorded.
+    pub fn acceptor(&self) -> (TlsAcceptor, PresentedCert) {
+        let presented = Arc::new(Mutex::new(None));
+        let verifier = Arc::new(RecordingVerifier {
+            base: Arc::clone(&self.base),
+            presented: Arc::clone(&presented),
+        });
+        let mut cfg = (*self.base).clone();
+        cfg.set_client_certificate_verifier(verifier);
+        (cfg.into(), presented)
+    }
+
+    /// Checks if the certificate presented by a peer node
+    /// was issued to the node with id `peer_id`.
+    pub fn authenticate(&self, peer_id: NodeId, presented: &PresentedCert) -> bool {
+        let name = match self.names.get(&peer_id) {
+            Some(n) => n,
+            None => return false,
+        };
+        let presented = presented.lock();
+        let cert = match &*presented {
+            Some(c) => c,
+            None => return false,
+        };
+        is_valid_for(cert, name)
+    }
+}
+
+#[cfg(not(feature = "peer_auth"))]
+impl PeerAuth {
+    /// Always fails, since peers can't be authenticated.
+    pub fn new(
+        server_config: ServerConfig,
+        _addrs: &HashMap<NodeId, (SocketAddr, String)>,
+    ) -> Result<Self> {
+        Self::check(&server_config).map(|_| unreachable!())
+    }
+
+    /// Always fails, since peers can't be authenticated.
+    pub fn check(_server_config: &ServerConfig) -> Result<()> {
+        Err("Peer authentication requires the peer_auth feature")
+            .wrapped(ErrorKind::CommunicationPeerAuth)
+    }
+
+    /// Never called, since a `PeerAuth` can't be created.
+    pub fn acceptor(&self) -> (TlsAcceptor, PresentedCert) {
+        match *self {}
+    }
+
+    /// Never called, since a `PeerAuth` can't be created.
+    pub fn authenticate(&self, _peer_id: NodeId, _presented: &PresentedCert) -> bool {
+        match *self {}
+    }
+}
+
+// checks if `cert` was issued to the domain name `name`
+#[cfg(feature = "peer_auth")]
+fn is_valid_for(cert: &Certificate, name: &str) -> bool {
+    let name = match DNSNameRef::try_from_ascii_str(name) {
+        Ok(n) => n,
+        Err(_) => return false,
+    };
+    EndEntityCert::from(&cert.0[..])
+        .and_then(|cert| cert.verify_is_valid_for_dns_name(name))
+        .is_ok()
+}
+
+// the signatures of the handshake are checked by the default
+// methods of `ClientCertVerifier`, like every verifier of `rustls`
+#[cfg(feature = "peer_auth")]
+impl ClientCertVerifier for RecordingVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.base.get_verifier().offer_client_auth()
+    }
+
+    // the peer id can't be authenticated without a certificate
+    fn client_auth_mandatory(&self, sni: Option<&DNSName>) -> Option<bool> {
+        self.base
+            .get_verifier()
+            .client_auth_mandatory(sni)
+            .map(|_| true)
+    }
+
+    fn client_auth_root_subjects(&self, sni: Option<&DNSName>) -> Option<DistinguishedNames> {
+        self.base.get_verifier().client_auth_root_subjects(sni)
+    }
+
+    fn verify_client_cert(
+        &self,
+        presented_certs: &[Certificate],
+        sni: Option<&DNSName>,
+    ) -> std::result::Result<ClientCertVerified, TLSError> {
+        let verified = self
+            .base
+            .get_verifier()
+            .verify_client_cert(presented_certs, sni)?;
+        *self.presented.lock() = presented_certs.first().cloned();
+        Ok(verified)
+    }
+}
+
+#[cfg(all(test, feature = "peer_auth"))]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+    use rustls::internal::pemfile;
+    use rustls::{
+        AllowAnyAuthenticatedClient, Certificate, NoClientAuth, RootCertStore, ServerConfig,
+    };
+
+    use super::PeerAuth;
+    use crate::bft::collections;
+    use crate::bft::communication::NodeId;
+
+    fn cert(path: &str) -> Certificate {
+        let path = format!("{}/ca-root/{}", env!("CARGO_MANIFEST_DIR"), path);
+        let mut file = BufReader::new(File::open(path).unwrap());
+        let mut certs = pemfile::certs(&mut file).unwrap();
+        certs.remove(0)
+    }
+
+    fn presented(name: &str) -> super::PresentedCert {
+        let path = format!("{}/{}.crt", name, name);
+        Arc::new(Mutex::new(Some(cert(&path))))
+    }
+
+    #[test]
+    fn test_peer_cert_for_wrong_id_rejected() {
+        let mut addrs = collections::hash_map();
+        addrs.insert(
+            NodeId::from(0u32),
+            ("127.0.0.1:10001".parse().unwrap(), String::from("cop01")),
+        );
+        addrs.insert(
+            NodeId::from(1u32),
+            ("127.0.0.1:10002".parse().unwrap(), String::from("cop02")),
+        );
+        // peers are never asked for their certificates
+        let err = PeerAuth::new(ServerConfig::new(NoClientAuth::new()), &addrs);
+        assert!(err.is_err());
+
+        let mut roots = RootCertStore::empty();
+        roots.add(&cert("root.crt")).unwrap();
+        let server_config = ServerConfig::new(AllowAnyAuthenticatedClient::new(roots));
+        let auth = PeerAuth::new(server_config, &addrs).unwrap();
+
+        assert!(auth.authenticate(NodeId::from(0u32), &presented("cop01")));
+        assert!(auth.authenticate(NodeId::from(1u32), &presented("cop02")));
+
+        // a peer claiming the id of another node is rejected
+        assert!(!auth.authenticate(NodeId::from(0u32), &presented("cop02")));
+        assert!(!auth.authenticate(NodeId::from(2u32), &presented("cop03")));
+
+        // as are peers presenting no certificate at all
+        let none = Arc::new(Mutex::new(None));
+        assert!(!auth.authenticate(NodeId::from(1u32), &none));
+    }
+}
@@ -0,0 +1,362 @@
+//! Dedicated writer tasks for the sockets of peer nodes.
+//!
+//! Rather than having every task sending a message to a peer contend
+//! for the lock on its socket, these tasks enqueue serialized frames,
+//! which are written in order by the single task owning the socket.
+//! Frames queued while a write is in progress are coalesced into the
+//! next write.
+
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures::io::{AsyncWrite, AsyncWriteExt};
+use futures::{select, FutureExt};
+use futures_timer::Delay;
+
+use crate::bft::async_runtime as rt;
+use crate::bft::communication::channel::{self, ChannelRx, ChannelTx};
+use crate::bft::communication::message::{Header, WireMessage};
+use crate::bft::error::*;
+
+/// What to do with a frame enqueued while the queue of a peer is full.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum QueueFullPolicy {
+    /// The frame is dropped, as if it was lost in the network.
+    Drop,
+    /// The sender waits until the writer task makes room for the frame.
+    Backpressure,
+}
+
+/// Configuration of the write queue of each peer socket.
+#[derive(Copy, Clone, Debug)]
+pub struct WriteQueueConfig {
+    /// The maximum number of frames queued for a single peer.
+    pub depth: usize,
+    /// The policy applied to frames enqueued while the queue is full.
+    pub policy: QueueFullPolicy,
+}
+
+/// A handle to the write queue of a peer socket.
+///
+/// The handle can be cloned as many times as needed for cheap.
+#[derive(Clone)]
+pub struct PeerWriter {
+    tx: ChannelTx<Queued>,
+    policy: QueueFullPolicy,
+}
+
+// an item of the queue of a writer task
+enum Queued {
+    // a serialized frame, to be written to the socket
+    Frame(Vec<u8>),
+    // notified once the frames queued before it were written
+    Flush(oneshot::Sender<()>),
+}
+
+impl PeerWriter {
+    /// Spawns the writer task owning `sock`, returning a handle to its queue.
+    ///
+    /// If writing to the socket fails, or a single write takes longer
+    /// than `timeout`, the task awaits on `disconnected` and exits,
+    /// dropping the socket; enqueueing frames afterwards fails.
+    pub fn spawn<W, F>(
+        sock: W,
+        cfg: WriteQueueConfig,
+        timeout: Option<Duration>,
+        disconnected: F,
+    ) -> Self
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (writer, rx) = Self::channel(cfg);
+        rt::spawn(async move {
+            if write_loop(sock, rx, timeout).await.is_err() {
+                disconnected.await;
+            }
+        });
+        writer
+    }
+
+    fn channel(cfg: WriteQueueConfig) -> (Self, ChannelRx<Queued>) {
+        let (tx, rx) = channel::new_bounded(cfg.depth);
+        let policy = cfg.policy;
+        (Self { tx, policy }, rx)
+    }
+
+    /// Enqueues the frame of `wm` to be written to the socket.
+    ///
+    /// Returns `Ok(false)` if the frame was dropped because of the
+    /// `QueueFullPolicy`, and an error if the writer task has exited,
+    /// i.e. the connection to the peer is gone.
+    pub async fn enqueue(&mut self, wm: &WireMessage<'_>) -> Result<bool> {
+        let frame = Queued::Frame(frame(wm));
+        match self.policy {
+            QueueFullPolicy::Drop => self.tx.try_send(frame),
+            QueueFullPolicy::Backpressure => self.tx.send(frame).await.map(|_| true),
+        }
+    }
+
+    /// Waits until every frame enqueued so far is written to the socket.
+    ///
+    /// Regardless of the `QueueFullPolicy`, this waits for room in the
+    /// queue. Returns an error if the writer task has exited, in which
+    /// case the frames still queued were lost.
+    pub async fn flush(&mut self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(Queued::Flush(tx)).await?;
+        rx.await.simple_msg(
+            ErrorKind::CommunicationPeerWriter,
+            "The writer task has exited",
+        )
+    }
+}
+
+// serializes `wm` into a single buffer, in the format read by peers
+fn frame(wm: &WireMessage<'_>) -> Vec<u8> {
+    let mut buf = vec![0; Header::LENGTH + wm.payload.len()];
+    wm.header
+        .serialize_into(&mut buf[..Header::LENGTH])
+        .unwrap();
+    buf[Header::LENGTH..].copy_from_slice(wm.payload);
+    buf
+}
+
+// writes the queued frames to `sock` until every handle to
+// the queue is dropped, an I/O error occurs, or a single write
+// takes longer than `timeout`, e.g. because the link stalled
+async fn write_loop<W>(
+    mut sock: W,
+    mut rx: ChannelRx<Queued>,
+    timeout: Option<Duration>,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut batch = Vec::new();
+    let mut flushes = Vec::new();
+    while let Ok(item) = rx.recv().await {
+        batch.clear();
+
+        // coalesce the frames that are already queued
+        let mut next = Some(item);
+        while let Some(item) = next {
+            match item {
+                Queued::Frame(frame) => batch.extend_from_slice(&frame),
+                Queued::Flush(tx) => flushes.push(tx),
+            }
+            next = rx.recv().now_or_never().and_then(|item| item.ok());
+        }
+
+        if !batch.is_empty() {
+            let write = async {
+                sock.write_all(&batch).await?;
+                sock.flush().await
+            };
+            match timeout {
+                Some(timeout) => select! {
+                    result = write.fuse() => result?,
+                    _ = Delay::new(timeout).fuse() => {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "Write timed out"));
+                    },
+                },
+                None => write.await?,
+            }
+        }
+
+        // the frames queued before each flush were written
+        for tx in flushes.drain(..) {
+            tx.send(()).unwrap_or(());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use futures::channel::oneshot;
+    use futures::io::AsyncWrite;
+    use parking_lot::Mutex;
+
+    use super::{frame, write_loop, PeerWriter, QueueFullPolicy, WriteQueueConfig};
+    use crate::bft::async_runtime as rt;
+    use crate::bft::communication::message::{Header, WireMessage};
+    use crate::bft::communication::NodeId;
+    use crate::bft::testing::init_runtime;
+
+    const TASKS: u8 = 4;
+    const FRAMES: u8 = 64;
+    const PAYLOAD_LEN: usize = 8;
+
+    #[test]
+    fn test_frames_written_in_enqueue_order() {
+        init_runtime();
+
+        let cfg = WriteQueueConfig {
+            depth: 16,
+            policy: QueueFullPolicy::Backpressure,
+        };
+        let (writer, rx) = PeerWriter::channel(cfg);
+
+        // every task enqueues its frames in ascending order
+        for task in 0..TASKS {
+            let mut writer = writer.clone();
+            rt::spawn(async move {
+                for i in 0..FRAMES {
+                    let payload = [task, i, 0, 0, 0, 0, 0, 0];
+                    let wm = WireMessage::new(
+                        NodeId::from(0u32),
+                        NodeId::from(1u32),
+                        &payload[..],
+                        0,
+                        None,
+                        None,
+                    );
+                    assert!(writer.enqueue(&wm).await.unwrap());
+                }
+            });
+        }
+        drop(writer);
+
+        let mut written = Vec::new();
+        rt::block_on(write_loop(&mut written, rx, None)).unwrap();
+
+        // frames are written whole, and in the order of each task
+        let frame_len = Header::LENGTH + PAYLOAD_LEN;
+        assert_eq!(
+            written.len(),
+            frame_len * (TASKS as usize) * (FRAMES as usize)
+        );
+
+        let mut next = [0; TASKS as usize];
+        for chunk in written.chunks_exact(frame_len) {
+            let payload = &chunk[Header::LENGTH..];
+            let (task, i) = (payload[0] as usize, payload[1]);
+            assert_eq!(i, next[task]);
+            next[task] += 1;
+
+            let wm = WireMessage::new(
+                NodeId::from(0u32),
+                NodeId::from(1u32),
+                payload,
+                0,
+                None,
+                None,
+            );
+            assert_eq!(chunk, &frame(&wm)[..]);
+        }
+        assert!(next.iter().all(|&n| n == FRAMES));
+    }
+
+    // a socket recording the bytes written to it
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<u8>>>);
+
+    impl AsyncWrite for Recorder {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.0.lock().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_flush_waits_for_queued_frames() {
+        init_runtime();
+
+        let cfg = WriteQueueConfig {
+            depth: FRAMES as usize,
+            policy: QueueFullPolicy::Drop,
+        };
+        let written = Recorder::default();
+        let mut writer = PeerWriter::spawn(written.clone(), cfg, None, async {});
+
+        let payload = [0; PAYLOAD_LEN];
+        let wm = WireMessage::new(
+            NodeId::from(0u32),
+            NodeId::from(1u32),
+            &payload[..],
+            0,
+            None,
+            None,
+        );
+        rt::block_on(async {
+            for _ in 0..FRAMES {
+                assert!(writer.enqueue(&wm).await.unwrap());
+            }
+            writer.flush().await.unwrap();
+        });
+
+        // every frame enqueued before the flush was written
+        let frame_len = Header::LENGTH + PAYLOAD_LEN;
+        assert_eq!(written.0.lock().len(), frame_len * (FRAMES as usize));
+    }
+
+    // a socket whose peer stopped reading from its connection
+    struct Stalled;
+
+    impl AsyncWrite for Stalled {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context, _: &[u8]) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Pending
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_stalled_write_times_out() {
+        init_runtime();
+
+        let cfg = WriteQueueConfig {
+            depth: 16,
+            policy: QueueFullPolicy::Backpressure,
+        };
+        let (tx, rx) = oneshot::channel();
+        let disconnected = async move { tx.send(()).unwrap() };
+        let timeout = Some(Duration::from_millis(50));
+        let mut writer = PeerWriter::spawn(Stalled, cfg, timeout, disconnected);
+
+        let payload = [0; PAYLOAD_LEN];
+        let wm = WireMessage::new(
+            NodeId::from(0u32),
+            NodeId::from(1u32),
+            &payload[..],
+            0,
+            None,
+            None,
+        );
+        rt::block_on(async {
+            assert!(writer.enqueue(&wm).await.unwrap());
+
+            // the write times out, and the connection is dropped
+            rx.await.unwrap();
+            assert!(writer.enqueue(&wm).await.is_err());
+            assert!(writer.flush().await.is_err());
+        });
+    }
+}
@@ -0,0 +1,83 @@
+//! Reconnection of dropped links to other replicas.
+//!
+//! Replicas connect to each other while bootstrapping, but a link may
+//! drop afterwards, e.g. during a transient network partition. With a
+//! `Backoff` configured, a `Node` keeps trying to reconnect to the peer,
+//! waiting exponentially longer between attempts, up to a maximum.
+
+use std::time::Duration;
+
+use crate::bft::prng;
+
+/// Configuration of the delays between reconnection attempts.
+///
+/// The delay before the attempt `i`, starting at zero, is at most
+/// `base * factor^i`, capped at `max`. A random jitter of up to half
+/// of this delay is subtracted from it, such that the replicas who
+/// lost contact with the same peer don't all retry at once.
+#[derive(Copy, Clone, Debug)]
+pub struct Backoff {
+    /// The delay before the first attempt.
+    pub base: Duration,
+    /// The maximum delay between two attempts.
+    pub max: Duration,
+    /// The growth of the delay after each failed attempt.
+    pub factor: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            factor: 2.0,
+        }
+    }
+}
+
+impl Backoff {
+    /// Returns the delay before the reconnection attempt `attempt`.
+    pub fn delay(&self, attempt: u32, rng: &mut prng::State) -> Duration {
+        let exp = self.factor.powi(attempt.min(i32::MAX as u32) as i32);
+        let delay = self.base.as_secs_f64() * exp;
+        let delay = if delay.is_finite() {
+            Duration::from_secs_f64(delay.min(self.max.as_secs_f64()))
+        } else {
+            self.max
+        };
+        let half = delay / 2;
+        let jitter = (rng.next_state() >> 11) as f64 / (1u64 << 53) as f64;
+        half + half.mul_f64(jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Backoff;
+    use crate::bft::prng;
+
+    #[test]
+    fn test_backoff_delays() {
+        let backoff = Backoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(2),
+            factor: 2.0,
+        };
+        let mut rng = prng::State::new();
+
+        // the delays double, within their jitter, until the maximum
+        for attempt in 0..64 {
+            let ceiling = Duration::from_millis(100 << attempt.min(5)).min(backoff.max);
+            for _ in 0..16 {
+                let delay = backoff.delay(attempt, &mut rng);
+                assert!(delay <= ceiling, "{:?} > {:?}", delay, ceiling);
+                assert!(delay >= ceiling / 2, "{:?} < {:?}", delay, ceiling / 2);
+            }
+        }
+
+        // huge attempts don't overflow the delay
+        assert!(backoff.delay(u32::MAX, &mut rng) <= backoff.max);
+    }
+}
@@ -5,14 +5,24 @@
 //! serialization routines, for better throughput, can be utilized, such
 //! as [Cap'n'Proto](https://capnproto.org/capnp-tool.html), but these are
 //! expected to be implemented by the user.
+//!
+//! Core types, such as `Header`, `Digest`, `ConsensusMessage` and
+//! `RequestMessage`, additionally implement `ToBytes`, a hand-rolled
+//! binary format that is available without `serde`.
+//!
+//! On the wire, `PREPARE` and `COMMIT` messages, which make up most of
+//! the traffic of the consensus layer, are always encoded with `ToBytes`,
+//! rather than with the routines of `SharedData`.
 
 use std::io::{Read, Write};
 
+use parking_lot::Mutex;
 use smallvec::SmallVec;
 
-use crate::bft::communication::message::SystemMessage;
-use crate::bft::crypto::hash::{Context, Digest};
+use crate::bft::communication::message::{ConsensusMessage, ConsensusMessageKind, SystemMessage};
+use crate::bft::crypto::hash::{Digest, DigestHasher};
 use crate::bft::error::*;
+use crate::bft::ordering::SeqNo;
 
 /// Marker trait containing the types used by the application,
 /// as well as routines to serialize the application data.
@@ -32,6 +42,13 @@ pub trait SharedData {
     /// in the BFT system.
     type Reply;
 
+    /// The hash digest algorithm used to digest the payloads of
+    /// the messages of the application, which every node must agree on.
+    ///
+    /// Use `DefaultHasher` for the algorithm picked with the
+    /// `crypto_hash_*` features.
+    type Hasher: DigestHasher;
+
     /// Serialize a wire message into the writer `W`.
     fn serialize_message<W>(
         w: W,
@@ -40,6 +57,34 @@ pub trait SharedData {
     where
         W: Write;
 
+    /// Returns an estimate of the number of bytes `m` is serialized
+    /// into, used to size the buffer it is serialized into when sent.
+    ///
+    /// The default implementation returns zero, i.e. no estimate.
+    fn size_hint(_m: &SystemMessage<Self::State, Self::Request, Self::Reply>) -> usize {
+        0
+    }
+
+    /// Returns the maximum length of the payload of a wire message,
+    /// in bytes, including the tag prefixed to it.
+    ///
+    /// Nodes refuse to send messages serialized into larger payloads,
+    /// and drop the connections of peers sending them. The default
+    /// implementation returns `DEFAULT_MAX_PAYLOAD_LEN`.
+    fn max_payload_len() -> usize {
+        DEFAULT_MAX_PAYLOAD_LEN
+    }
+
+    /// Checks if the operation of a client request is well formed.
+    ///
+    /// Nodes drop the requests failing this check as soon as they are
+    /// deserialized, so these never take up room in a batch, nor reach
+    /// the `Service` of a replica. The connection of the client is kept.
+    /// The default implementation accepts every request.
+    fn validate_request(_request: &Self::Request) -> Result<()> {
+        Ok(())
+    }
+
     /// Deserialize a wire message from a reader `R`.
     fn deserialize_message<R>(
         r: R,
@@ -58,25 +103,457 @@ pub trait SharedData {
         R: Read;
 }
 
+/// The default maximum length of the payload of a wire message,
+/// returned by `SharedData::max_payload_len()`.
+pub const DEFAULT_MAX_PAYLOAD_LEN: usize = 64 * 1024 * 1024;
+
 // max no. of bytes to inline before doing a heap alloc
 const NODE_BUFSIZ: usize = 16384;
 
 /// The buffer type used to serialize messages into.
 pub type Buf = SmallVec<[u8; NODE_BUFSIZ]>;
 
+/// A pool of `Buf`s, reused to serialize the messages sent by a node,
+/// rather than allocating a new buffer for each of them.
+///
+/// Only buffers which spilled to the heap are retained, since smaller
+/// messages are serialized into the inline storage of a `Buf` anyway.
+pub struct BufPool {
+    capacity: usize,
+    bufs: Mutex<Vec<Buf>>,
+}
+
+impl BufPool {
+    /// Creates a new `BufPool`, retaining at most `capacity` buffers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            bufs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Takes an empty buffer from the pool, with room
+    /// for at least `size_hint` bytes.
+    pub fn get(&self, size_hint: usize) -> Buf {
+        let mut buf = self.bufs.lock().pop().unwrap_or_else(Buf::new);
+        buf.reserve(size_hint);
+        buf
+    }
+
+    /// Same as `get()`, but the buffer is filled with a copy of `data`.
+    pub fn copy_of(&self, data: &[u8]) -> Buf {
+        let mut buf = self.get(data.len());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    /// Returns `buf` to the pool, to be reused by a later `get()`.
+    pub fn put(&self, mut buf: Buf) {
+        if !buf.spilled() {
+            return;
+        }
+        buf.clear();
+        let mut bufs = self.bufs.lock();
+        if bufs.len() < self.capacity {
+            bufs.push(buf);
+        }
+    }
+
+    /// Returns the number of buffers retained in the pool.
+    pub fn len(&self) -> usize {
+        self.bufs.lock().len()
+    }
+
+    /// Checks if no buffers are retained in the pool.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// tags prefixed to the payload of every wire message, telling apart
+// messages encoded with `SharedData` from compact consensus votes
+const TAG_GENERIC: u8 = 0;
+const TAG_COMPACT_VOTE: u8 = 1;
+
+/// The length of the tag prefixed to the payload of every wire message,
+/// before a message encoded with the routines of `SharedData`.
+pub const WIRE_TAG_LENGTH: usize = 1;
+
 /// Extension of `SharedData` to obtain hash digests.
 pub trait DigestData: SharedData {
     /// Convenience function to obtain the digest of a request upon
     /// serialization.
+    ///
+    /// The message is serialized in its wire format, i.e. `PREPARE` and
+    /// `COMMIT` messages are encoded in their compact form.
     fn serialize_digest<W: Write + AsRef<[u8]>>(
         message: &SystemMessage<Self::State, Self::Request, Self::Reply>,
         mut w: W,
     ) -> Result<Digest> {
-        Self::serialize_message(&mut w, message)?;
-        let mut ctx = Context::new();
-        ctx.update(w.as_ref());
-        Ok(ctx.finish())
+        match message {
+            SystemMessage::Consensus(m) if is_vote(m) => {
+                TAG_COMPACT_VOTE.to_bytes(&mut w)?;
+                m.to_bytes(&mut w)?;
+            }
+            _ => {
+                TAG_GENERIC.to_bytes(&mut w)?;
+                Self::serialize_message(&mut w, message)?;
+            }
+        }
+        Ok(Self::Hasher::digest(&[w.as_ref()]))
     }
+
+    /// Returns the digest of the payload of a wire message, whose message
+    /// was already serialized with the routines of `SharedData` into
+    /// `serialized`, i.e. the same as `serialize_digest()` would.
+    fn serialized_digest(serialized: &[u8]) -> Digest {
+        Self::Hasher::digest(&[&[TAG_GENERIC], serialized])
+    }
+
+    /// Deserialize a wire message, serialized with `serialize_digest()`,
+    /// from the payload `buf`.
+    fn deserialize_wire(
+        mut buf: &[u8],
+    ) -> Result<SystemMessage<Self::State, Self::Request, Self::Reply>> {
+        match u8::from_bytes(&mut buf)? {
+            TAG_GENERIC => Self::deserialize_message(buf),
+            TAG_COMPACT_VOTE => match ConsensusMessage::from_bytes(&mut buf)? {
+                m if is_vote(&m) => Ok(SystemMessage::Consensus(m)),
+                _ => Err("Compact consensus message is not a vote")
+                    .wrapped(ErrorKind::CommunicationSerialize),
+            },
+            _ => Err("Invalid wire message tag").wrapped(ErrorKind::CommunicationSerialize),
+        }
+    }
+}
+
+// `PREPARE` and `COMMIT` messages have a compact encoding
+fn is_vote(message: &ConsensusMessage) -> bool {
+    !matches!(message.kind(), ConsensusMessageKind::PrePrepare(_))
 }
 
 impl<D: SharedData> DigestData for D {}
+
+/// Types with a hand-rolled binary representation, which does
+/// not depend on the `serialize_serde` feature.
+///
+/// All integers are encoded in little endian byte order.
+pub trait ToBytes: Sized {
+    /// Serialize `self` into the writer `W`.
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()>;
+
+    /// Deserialize a value of this type from a reader `R`.
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self>;
+}
+
+macro_rules! impl_to_bytes_int {
+    ($($t:ty),*) => {
+        $(
+            impl ToBytes for $t {
+                fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+                    w.write_all(&self.to_le_bytes())
+                        .wrapped(ErrorKind::CommunicationSerialize)
+                }
+
+                fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+                    let mut buf = [0; std::mem::size_of::<$t>()];
+                    r.read_exact(&mut buf)
+                        .wrapped(ErrorKind::CommunicationSerialize)?;
+                    Ok(<$t>::from_le_bytes(buf))
+                }
+            }
+        )*
+    }
+}
+
+impl_to_bytes_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl ToBytes for SeqNo {
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        u32::from(*self).to_bytes(w)
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        u32::from_bytes(r).map(SeqNo::from)
+    }
+}
+
+// digests are prefixed by their length, which
+// depends on the `DigestHasher` that produced them
+impl ToBytes for Digest {
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        (self.as_ref().len() as u8).to_bytes(w)?;
+        w.write_all(self.as_ref())
+            .wrapped(ErrorKind::CommunicationSerialize)
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        let len = u8::from_bytes(r)? as usize;
+        let mut buf = [0; Digest::MAX_LENGTH];
+        let buf = buf
+            .get_mut(..len)
+            .ok_or("Digest has an invalid length")
+            .wrapped(ErrorKind::CommunicationSerialize)?;
+        r.read_exact(buf)
+            .wrapped(ErrorKind::CommunicationSerialize)?;
+        Digest::from_bytes(buf)
+    }
+}
+
+impl<T: ToBytes> ToBytes for Vec<T> {
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        (self.len() as u64).to_bytes(w)?;
+        for x in self.iter() {
+            x.to_bytes(w)?;
+        }
+        Ok(())
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        let len = u64::from_bytes(r)? as usize;
+        // don't trust the length to pre-allocate memory,
+        // since it was read from a foreign source
+        let mut xs = Vec::new();
+        for _ in 0..len {
+            xs.push(T::from_bytes(r)?);
+        }
+        Ok(xs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ToBytes;
+    use crate::bft::crypto::hash::{Context, Digest};
+    use crate::bft::ordering::SeqNo;
+
+    fn round_trip<T: ToBytes>(x: &T) -> T {
+        let mut buf = Vec::new();
+        x.to_bytes(&mut buf).expect("Serialize failed");
+        let mut r = &buf[..];
+        let y = T::from_bytes(&mut r).expect("Deserialize failed");
+        assert!(r.is_empty());
+        y
+    }
+
+    #[test]
+    fn test_to_bytes_round_trip() {
+        assert_eq!(round_trip(&0xdead_beef_u64), 0xdead_beef);
+        assert_eq!(round_trip(&-1234_i32), -1234);
+        assert_eq!(round_trip(&SeqNo::from(42)), SeqNo::from(42));
+
+        let mut ctx = Context::new();
+        ctx.update(b"bafomet");
+        let digest = ctx.finish();
+        assert!(round_trip(&digest) == digest);
+
+        let digests = vec![
+            digest,
+            Digest::from_bytes(&[0; Digest::MAX_LENGTH]).unwrap(),
+        ];
+        assert!(round_trip(&digests) == digests);
+    }
+
+    #[test]
+    fn test_send_bufs_recycled() {
+        use super::{BufPool, NODE_BUFSIZ};
+
+        const SENDS: usize = 256;
+        const LARGE: usize = 4 * NODE_BUFSIZ;
+
+        let pool = BufPool::new(2);
+
+        // messages serialized inline are never pooled
+        let mut buf = pool.get(16);
+        buf.extend_from_slice(&[0xba; 16]);
+        pool.put(buf);
+        assert!(pool.is_empty());
+
+        // while every large message reuses the same allocation
+        let mut allocation = None;
+        for i in 0..SENDS {
+            let mut buf = pool.get(LARGE);
+            assert!(buf.is_empty() && buf.capacity() >= LARGE);
+            buf.extend_from_slice(&vec![i as u8; LARGE][..]);
+
+            let ptr = buf.as_ptr();
+            assert_eq!(*allocation.get_or_insert(ptr), ptr);
+            pool.put(buf);
+            assert_eq!(pool.len(), 1);
+        }
+
+        // broadcasts copy the serialized message into pooled buffers;
+        // the pool never retains more than its capacity
+        let message = pool.copy_of(&[0xfe; LARGE][..]);
+        let copies: Vec<_> = (0..4).map(|_| pool.copy_of(&message[..])).collect();
+        assert!(copies.iter().all(|copy| copy[..] == message[..]));
+        for copy in copies {
+            pool.put(copy);
+        }
+        pool.put(message);
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_to_bytes_truncated() {
+        let mut r = &[0_u8; 3][..];
+        assert!(u64::from_bytes(&mut r).is_err());
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_compact_vote_encoding() {
+        use super::{DigestData, SharedData};
+        use crate::bft::communication::message::{
+            ConsensusMessage, ConsensusMessageKind, SystemMessage,
+        };
+        use crate::bft::ordering::Orderable;
+        use crate::bft::testing::CounterData;
+
+        let mut ctx = Context::new();
+        ctx.update(b"pre-prepare");
+        let digest = ctx.finish();
+
+        let votes = [
+            ConsensusMessageKind::Prepare(digest),
+            ConsensusMessageKind::Commit(digest),
+        ];
+        for kind in votes {
+            let is_prepare = matches!(kind, ConsensusMessageKind::Prepare(_));
+            let message = SystemMessage::Consensus(ConsensusMessage::new(
+                SeqNo::from(3),
+                SeqNo::from(1),
+                kind,
+            ));
+
+            let mut compact = Vec::new();
+            CounterData::serialize_digest(&message, &mut compact).unwrap();
+            let mut generic = Vec::new();
+            CounterData::serialize_message(&mut generic, &message).unwrap();
+            assert!(compact.len() < generic.len());
+
+            match CounterData::deserialize_wire(&compact[..]).unwrap() {
+                SystemMessage::Consensus(m) => {
+                    assert_eq!(m.sequence_number(), SeqNo::from(3));
+                    assert_eq!(m.view(), SeqNo::from(1));
+                    assert_eq!(m.has_proposed_digest(&digest), Some(true));
+                    assert_eq!(
+                        matches!(m.kind(), ConsensusMessageKind::Prepare(_)),
+                        is_prepare
+                    );
+                }
+                _ => panic!("Expected a consensus message"),
+            }
+        }
+
+        // a PRE-PREPARE is never encoded in the compact form
+        let pre_prepare = ConsensusMessage::new(
+            SeqNo::from(3),
+            SeqNo::from(1),
+            ConsensusMessageKind::PrePrepare(vec![digest]),
+        );
+        let mut buf = vec![1];
+        pre_prepare.to_bytes(&mut buf).unwrap();
+        assert!(CounterData::deserialize_wire(&buf[..]).is_err());
+    }
+
+    #[cfg(all(feature = "serialize_serde", feature = "ring", feature = "blake3"))]
+    #[test]
+    fn test_application_hashers() {
+        use std::io::{Read, Write};
+        use std::marker::PhantomData;
+
+        use super::{DigestData, SharedData};
+        use crate::bft::communication::message::{Header, SignedWireMessage, SystemMessage};
+        use crate::bft::communication::shard::ShardId;
+        use crate::bft::communication::NodeId;
+        use crate::bft::crypto::hash::{Blake3, DigestHasher, Sha256, Sha512};
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::error::*;
+        use crate::bft::testing::CounterData;
+
+        // the types of `CounterData`, digested with `H`
+        struct HashedData<H>(PhantomData<H>);
+
+        impl<H: DigestHasher> SharedData for HashedData<H> {
+            type State = u64;
+            type Request = u64;
+            type Reply = u64;
+            type Hasher = H;
+
+            fn serialize_state<W: Write>(w: W, s: &u64) -> Result<()> {
+                CounterData::serialize_state(w, s)
+            }
+
+            fn deserialize_state<R: Read>(r: R) -> Result<u64> {
+                CounterData::deserialize_state(r)
+            }
+
+            fn serialize_message<W: Write>(w: W, m: &SystemMessage<u64, u64, u64>) -> Result<()> {
+                CounterData::serialize_message(w, m)
+            }
+
+            fn deserialize_message<R: Read>(r: R) -> Result<SystemMessage<u64, u64, u64>> {
+                CounterData::deserialize_message(r)
+            }
+        }
+
+        let message = SystemMessage::Goodbye;
+        let mut payload = Vec::new();
+        let sha256 = HashedData::<Sha256>::serialize_digest(&message, &mut payload).unwrap();
+        let sha512 = HashedData::<Sha512>::serialize_digest(&message, Vec::new()).unwrap();
+        let blake3 = HashedData::<Blake3>::serialize_digest(&message, Vec::new()).unwrap();
+
+        // each algorithm produces digests of its own length
+        assert_eq!(sha256.as_ref().len(), Sha256::LENGTH);
+        assert_eq!(sha512.as_ref().len(), Sha512::LENGTH);
+        assert_eq!(blake3.as_ref().len(), Blake3::LENGTH);
+        assert_ne!(Sha256::LENGTH, Sha512::LENGTH);
+        assert_ne!(sha256, blake3);
+        assert_eq!(sha512, Sha512::digest(&[&payload[..]]));
+        assert_eq!(sha256, Sha256::digest(&[&payload[..]]));
+        assert_eq!(blake3, Blake3::digest(&[&payload[..]]));
+        assert_eq!(
+            HashedData::<Sha256>::serialized_digest(&payload[1..]),
+            sha256
+        );
+
+        // the payload, and the header signed along with its
+        // digest, are checked with the same algorithm
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let pk = sk.public_key().into();
+        let id = NodeId::from(0u32);
+        let (header, _) = SignedWireMessage::new_in_shard_hashed::<Sha256>(
+            ShardId::ZERO,
+            id,
+            id,
+            &payload[..],
+            0,
+            Some(sha256),
+            &sk,
+        )
+        .into_inner();
+        assert!(SignedWireMessage::from_parts_hashed::<Sha256>(header, &payload[..], &pk).is_ok());
+        assert!(SignedWireMessage::from_parts_hashed::<Blake3>(header, &payload[..], &pk).is_err());
+
+        // longer digests travel whole, and so
+        // does the header signed along with them
+        let (header, _) = SignedWireMessage::new_in_shard_hashed::<Sha512>(
+            ShardId::ZERO,
+            id,
+            id,
+            &payload[..],
+            0,
+            Some(sha512),
+            &sk,
+        )
+        .into_inner();
+        let mut buf = [0; Header::LENGTH];
+        header.serialize_into(&mut buf[..]).unwrap();
+        let header = Header::deserialize_from(&buf[..]).unwrap();
+        assert_eq!(header.digest(), &sha512);
+        assert!(header.verify_signature_hashed::<Sha512>(&pk));
+        assert!(!header.verify_signature_hashed::<Sha256>(&pk));
+        assert!(SignedWireMessage::from_parts_hashed::<Sha512>(header, &payload[..], &pk).is_ok());
+    }
+}
@@ -0,0 +1,239 @@
+//! Hosting several independent services over the connections of one `Node`.
+//!
+//! A process may run a replica of each partition of the state of an
+//! application, i.e. each shard, without opening a set of connections
+//! per shard. The `Node` of the process is split into one `Node` per
+//! shard, each with its own master channel, such that a `Replica` may
+//! be bootstrapped on top of it; the messages sent by a shard are
+//! tagged with its `ShardId`, in their signed `Header`, and delivered
+//! to the same shard of the receiving process.
+
+#[cfg(feature = "serialize_serde")]
+use serde::{Deserialize, Serialize};
+
+use std::sync::Arc;
+
+use futures::channel::mpsc;
+use futures::{select, FutureExt, StreamExt};
+use parking_lot::RwLock;
+
+use crate::bft::async_runtime as rt;
+use crate::bft::collections::{self, HashMap, HashSet};
+use crate::bft::communication::channel::{new_message_channel, MessageChannelTx};
+use crate::bft::communication::message::Message;
+use crate::bft::communication::serialize::SharedData;
+use crate::bft::communication::shutdown::Shutdown;
+use crate::bft::communication::{Node, PeerLinks, PeerTx};
+use crate::bft::error::*;
+
+/// A `ShardId` identifies one of the services hosted by a `Node`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[repr(transparent)]
+pub struct ShardId(u32);
+
+impl ShardId {
+    /// The shard of a `Node` which wasn't split into shards.
+    pub const ZERO: Self = ShardId(0);
+}
+
+impl From<u32> for ShardId {
+    #[inline]
+    fn from(id: u32) -> ShardId {
+        ShardId(id)
+    }
+}
+
+impl From<ShardId> for u32 {
+    #[inline]
+    fn from(id: ShardId) -> u32 {
+        id.0
+    }
+}
+
+// a shard of a `Node`, along with its rogue messages
+type Shard<D> = (
+    Node<D>,
+    Vec<Message<<D as SharedData>::State, <D as SharedData>::Request, <D as SharedData>::Reply>>,
+);
+
+// the master channels of the shards of a `Node`
+type ShardTxs<D> = HashMap<
+    ShardId,
+    MessageChannelTx<
+        <D as SharedData>::State,
+        <D as SharedData>::Request,
+        <D as SharedData>::Reply,
+    >,
+>;
+
+// the handles of a shard to the `Node` it was split from
+pub(super) struct RootLink<D: SharedData> {
+    // the master channel of the root `Node`
+    pub(super) tx: MessageChannelTx<D::State, D::Request, D::Reply>,
+    // dropped when the shard is shut down; the root `Node`
+    // is shut down once every shard dropped its handle
+    pub(super) alive: Option<mpsc::Sender<()>>,
+}
+
+impl<D> Node<D>
+where
+    D: SharedData + 'static,
+    D::State: Send + Clone + 'static,
+    D::Request: Send + 'static,
+    D::Reply: Send + 'static,
+{
+    /// Splits this bootstrapped `Node` into one `Node` per shard in
+    /// `shards`, which share its connections, along with the `rogue`
+    /// messages returned by `Node::bootstrap()` for each shard.
+    ///
+    /// The messages sent by a shard are delivered to the same shard of
+    /// the peer nodes, while the messages of unknown shards are dropped.
+    /// This `Node` keeps handling the connections in the background,
+    /// so the shards don't track their state, e.g. `is_connected()`
+    /// always returns `false`, and states are sent over interactive
+    /// links. It is shut down once every shard is shut down, or dropped.
+    pub fn into_shards(
+        mut self,
+        shards: &[ShardId],
+        rogue: Vec<Message<D::State, D::Request, D::Reply>>,
+    ) -> Result<Vec<Shard<D>>> {
+        if self.root.is_some() {
+            return Err("Shards can't be split any further").wrapped(ErrorKind::CommunicationShard);
+        }
+        if shards.is_empty() {
+            return Err("No shards to split the node into").wrapped(ErrorKind::CommunicationShard);
+        }
+        let mut seen: HashSet<ShardId> = collections::hash_set();
+        if !shards.iter().all(|&shard| seen.insert(shard)) {
+            return Err("Duplicate shard ids").wrapped(ErrorKind::CommunicationShard);
+        }
+
+        // share the sockets of our peers with the shards
+        if let PeerTx::Server(ref mut map) = self.peer_tx {
+            let map = std::mem::replace(map, collections::hash_map());
+            self.peer_tx = PeerTx::Client(Arc::new(RwLock::new(map)));
+        }
+
+        let (alive, exited) = mpsc::channel(0);
+        let mut txs: ShardTxs<D> = collections::hash_map();
+        let mut nodes: Vec<Shard<D>> = Vec::with_capacity(shards.len());
+
+        for &shard in shards {
+            let (my_tx, my_rx) = new_message_channel(self.channel_bound);
+            txs.insert(shard, my_tx.clone());
+            let node = Node {
+                id: self.id,
+                members: self.members.clone(),
+                f: self.f,
+                first_cli: self.first_cli,
+                my_tx,
+                my_rx,
+                nonces: self.nonces.fork(),
+                shared: Arc::clone(&self.shared),
+                peer_tx: self.peer_tx.clone(),
+                // states fall back to the interactive links
+                bulk_tx: collections::hash_map(),
+                connector: self.connector.clone(),
+                peer_addrs: self.peer_addrs.clone(),
+                connect_retries: self.connect_retries,
+                pool: self.pool.clone(),
+                deserialize_timeout: self.deserialize_timeout,
+                verify_parallelism: self.verify_parallelism,
+                socket_opts: self.socket_opts,
+                socket_provider: Arc::clone(&self.socket_provider),
+                write_queue: self.write_queue,
+                links: PeerLinks::new(),
+                reconnect: self.reconnect,
+                reconnecting: collections::hash_set(),
+                shutdown: Shutdown::new().0,
+                channel_bound: self.channel_bound,
+                shard,
+                root: Some(RootLink {
+                    tx: self.my_tx.clone(),
+                    alive: Some(alive.clone()),
+                }),
+                codecs: self.codecs,
+            };
+            nodes.push((node, Vec::new()));
+        }
+
+        // hand the rogue messages over to their shards
+        for message in rogue {
+            if let Message::System(ref header, _) = message {
+                let shard = header.shard();
+                if let Some((_, rogue)) = nodes.iter_mut().find(|(node, _)| node.shard == shard) {
+                    rogue.push(message);
+                }
+            }
+        }
+
+        rt::spawn(Self::demux_shards(self, txs, exited));
+        Ok(nodes)
+    }
+
+    // delivers the messages received by the root `Node` to their shards,
+    // handling the connections on their behalf, until every shard exits
+    async fn demux_shards(mut root: Self, mut txs: ShardTxs<D>, mut exited: mpsc::Receiver<()>) {
+        loop {
+            let message = select! {
+                message = root.my_rx.recv().fuse() => match message {
+                    Ok(message) => message,
+                    Err(_) => break,
+                },
+                _ = exited.next() => break,
+            };
+            match message {
+                Message::System(header, message) => {
+                    if let Some(tx) = txs.get_mut(&header.shard()) {
+                        tx.send(Message::System(header, message))
+                            .await
+                            .unwrap_or(());
+                    }
+                }
+                Message::ConnectedTx(id, sock, codec) => root.handle_connected_tx(id, sock, codec),
+                Message::ConnectedBulkTx(id, sock, codec) => {
+                    root.handle_connected_bulk_tx(id, sock, codec)
+                }
+                Message::ConnectedRx(id, sock, codec) => root.handle_connected_rx(id, sock, codec),
+                // the shards hand their own send side disconnects over to us
+                Message::DisconnectedTx(id) => root.handle_disconnected_tx(id),
+                Message::DisconnectedRx(some_id) => {
+                    if let Some(id) = some_id {
+                        root.handle_disconnected_rx(id);
+                    }
+                    for tx in txs.values_mut() {
+                        tx.send(Message::DisconnectedRx(some_id))
+                            .await
+                            .unwrap_or(());
+                    }
+                }
+                Message::Departed(id) => {
+                    root.handle_disconnected_rx(id);
+                    for tx in txs.values_mut() {
+                        tx.send(Message::Departed(id)).await.unwrap_or(());
+                    }
+                }
+                // the other messages are produced by the
+                // layers built on top of each shard
+                _ => (),
+            }
+        }
+        root.shutdown().await;
+    }
+
+    /// Tags the messages sent by this `Node` with `shard`, e.g. for a
+    /// client of the replicas hosting the shard.
+    ///
+    /// Unlike with `into_shards()`, the messages received are not
+    /// filtered by their shard.
+    pub fn in_shard(mut self, shard: ShardId) -> Self {
+        self.shard = shard;
+        self
+    }
+
+    /// Reports the shard the messages sent by this `Node` are tagged with.
+    pub fn shard(&self) -> ShardId {
+        self.shard
+    }
+}
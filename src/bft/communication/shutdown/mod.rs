@@ -0,0 +1,150 @@
+//! Graceful shutdown of the tasks spawned by a `Node`.
+//!
+//! The tasks accepting connections, and the ones reading from them,
+//! would otherwise loop until their sockets error. Each of them holds
+//! a `ShutdownListener`, which resolves once the node is shut down,
+//! and which is dropped when the task exits, such that the node can
+//! await the termination of all of them.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::{mpsc, oneshot};
+use futures::future::{FusedFuture, Shared};
+use futures::{FutureExt, StreamExt};
+
+/// Signals the tasks holding a `ShutdownListener` to exit.
+pub struct Shutdown {
+    // dropping this sender signals the shutdown
+    signal: Option<oneshot::Sender<()>>,
+    // the listener cloned into each new task
+    listener: Option<ShutdownListener>,
+    exited: Option<mpsc::Receiver<()>>,
+}
+
+/// Resolves once the `Node` it was obtained from is shut down.
+///
+/// Every clone of a `ShutdownListener` should be dropped by the
+/// task holding it when that task exits.
+#[derive(Clone)]
+pub struct ShutdownListener {
+    signal: Shared<oneshot::Receiver<()>>,
+    // never sent on; the channel is closed once every
+    // listener is dropped, i.e. every task exited
+    _alive: mpsc::Sender<()>,
+}
+
+/// Resolves once every task holding a `ShutdownListener` exited.
+pub struct Terminated {
+    exited: Option<mpsc::Receiver<()>>,
+}
+
+impl Shutdown {
+    /// Creates a new `Shutdown` signal, along with a first listener.
+    pub fn new() -> (Self, ShutdownListener) {
+        let (signal, listener) = oneshot::channel();
+        let (alive, exited) = mpsc::channel(0);
+        let listener = ShutdownListener {
+            signal: listener.shared(),
+            _alive: alive,
+        };
+        let shutdown = Self {
+            signal: Some(signal),
+            listener: Some(listener.clone()),
+            exited: Some(exited),
+        };
+        (shutdown, listener)
+    }
+
+    /// Returns a new `ShutdownListener`, to be handed to a task,
+    /// or `None` if the shutdown was already signalled, in which
+    /// case no more tasks should be spawned.
+    pub fn listener(&self) -> Option<ShutdownListener> {
+        self.listener.clone()
+    }
+
+    /// Checks if the shutdown was signalled.
+    pub fn is_signalled(&self) -> bool {
+        self.signal.is_none()
+    }
+
+    /// Signals every task holding a `ShutdownListener` to exit.
+    ///
+    /// The returned future resolves once all of them did. If the
+    /// shutdown was already signalled, it resolves immediately.
+    pub fn signal(&mut self) -> Terminated {
+        self.signal = None;
+        self.listener = None;
+        Terminated {
+            exited: self.exited.take(),
+        }
+    }
+}
+
+impl Future for ShutdownListener {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // the sender is only ever dropped, so the
+        // listener resolves with `Err(Canceled)`
+        self.signal.poll_unpin(cx).map(|_| ())
+    }
+}
+
+impl FusedFuture for ShutdownListener {
+    fn is_terminated(&self) -> bool {
+        self.signal.is_terminated()
+    }
+}
+
+impl Future for Terminated {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let exited = match self.exited {
+            Some(ref mut exited) => exited,
+            None => return Poll::Ready(()),
+        };
+        loop {
+            match exited.poll_next_unpin(cx) {
+                Poll::Ready(Some(())) => continue,
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::FutureExt;
+
+    use super::Shutdown;
+
+    #[test]
+    fn test_shutdown_awaits_tasks() {
+        let (mut shutdown, first) = Shutdown::new();
+        let mut second = shutdown.listener().expect("Shutdown signalled");
+
+        // nothing resolves before the shutdown is signalled
+        assert!((&mut second).now_or_never().is_none());
+        let mut terminated = shutdown.signal();
+        assert!(shutdown.is_signalled());
+        assert!(shutdown.listener().is_none());
+
+        // the tasks are told to exit, but are still running
+        assert!((&mut second).now_or_never().is_some());
+        assert!((&mut terminated).now_or_never().is_none());
+
+        // ... until every one of them drops its listener
+        block_on(first);
+        assert!((&mut terminated).now_or_never().is_none());
+        drop(second);
+        assert!(terminated.now_or_never().is_some());
+
+        // signalling it again has no effect
+        assert!(shutdown.signal().now_or_never().is_some());
+    }
+}
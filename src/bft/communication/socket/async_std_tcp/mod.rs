@@ -4,7 +4,12 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use ::async_std::net::{TcpListener, TcpStream};
+use futures::future::BoxFuture;
 use futures::io::{AsyncRead, AsyncWrite};
+use futures::FutureExt;
+use socket2::SockRef;
+
+use super::{RawListener, RawSocket};
 
 pub struct Listener {
     inner: TcpListener,
@@ -53,8 +58,41 @@ impl AsyncWrite for Socket {
     }
 }
 
-impl Listener {
-    pub async fn accept(&self) -> io::Result<Socket> {
-        self.inner.accept().await.map(|(inner, _)| Socket { inner })
+impl RawListener for Listener {
+    fn accept(&self) -> BoxFuture<'_, io::Result<super::Socket>> {
+        self.inner
+            .accept()
+            .map(|result| result.map(|(inner, _)| super::Socket::new(Socket { inner })))
+            .boxed()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl RawSocket for Socket {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    fn nodelay(&self) -> io::Result<bool> {
+        self.inner.nodelay()
+    }
+
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        SockRef::from(&self.inner).set_send_buffer_size(size)
+    }
+
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(&self.inner).send_buffer_size()
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        SockRef::from(&self.inner).set_recv_buffer_size(size)
+    }
+
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(&self.inner).recv_buffer_size()
     }
 }
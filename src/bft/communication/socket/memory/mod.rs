@@ -0,0 +1,436 @@
+//! An in-memory transport, connecting nodes running in the same process
+//! through channels, rather than through the network.
+//!
+//! This is useful to test the protocols of the BFT system end-to-end,
+//! without binding any ports. Every node should be configured with a
+//! clone of the same `MemoryNetwork`, obtained with `MemoryNetwork::host()`
+//! if the node is to be partitioned off from other nodes at some point.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::lock::Mutex as AsyncMutex;
+use futures::task::AtomicWaker;
+use futures::{FutureExt, StreamExt};
+
+use super::{Listener, RawListener, RawSocket, Socket, SocketProvider};
+use crate::bft::collections::HashMap;
+
+// the first port assigned to listeners bound to port 0
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// A `SocketProvider` whose connections are established in memory.
+///
+/// Listeners are registered under the address they are bound to,
+/// which is only reachable by clones of the same `MemoryNetwork`.
+///
+/// The network may be split into groups of addresses which can't reach
+/// each other, with `MemoryNetwork::partition()`, e.g. to test liveness
+/// under network partitions.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    shared: Arc<Mutex<Registry>>,
+    // the address of the node connecting through this handle
+    host: Option<SocketAddr>,
+}
+
+#[derive(Default)]
+struct Registry {
+    listeners: HashMap<SocketAddr, mpsc::UnboundedSender<MemorySocket>>,
+    next_port: u16,
+    // the group of each partitioned address
+    groups: HashMap<SocketAddr, usize>,
+    // the connections which are still open, and which are
+    // severed if a partition separates their ends
+    links: Vec<Weak<Link>>,
+}
+
+// the state of a connection shared by both of its ends
+struct Link {
+    from: Option<SocketAddr>,
+    to: SocketAddr,
+    severed: AtomicBool,
+    // the tasks reading from each end
+    readers: [AtomicWaker; 2],
+}
+
+struct MemoryListener {
+    addr: SocketAddr,
+    network: MemoryNetwork,
+    incoming: AsyncMutex<mpsc::UnboundedReceiver<MemorySocket>>,
+}
+
+// one end of a connection; bytes are written
+// to the peer in chunks, without backpressure
+struct MemorySocket {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    // the remainder of the last chunk read
+    pending: Vec<u8>,
+    offset: usize,
+    link: Arc<Link>,
+    // the index of this end in `Link::readers`
+    end: usize,
+}
+
+impl MemoryNetwork {
+    /// Creates a new `MemoryNetwork`, without any listeners.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a handle to this network for the node listening on `addr`,
+    /// such that its connections are severed by the partitions separating
+    /// `addr` from the address they were made to.
+    ///
+    /// Connections made through handles not obtained with this method
+    /// are never severed.
+    pub fn host(&self, addr: SocketAddr) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            host: Some(addr),
+        }
+    }
+
+    /// Partitions the network into `groups` of addresses, replacing any
+    /// previous partition. The nodes hosted in different groups can't
+    /// reach each other: the connections between them are severed, as
+    /// if they were reset, and new ones are refused, until the partition
+    /// is healed. Addresses missing from every group reach every other.
+    pub fn partition(&self, groups: &[&[SocketAddr]]) {
+        let mut registry = self.shared.lock().unwrap();
+        registry.groups.clear();
+        for (group, addrs) in groups.iter().enumerate() {
+            for &addr in addrs.iter() {
+                registry.groups.insert(addr, group);
+            }
+        }
+        let Registry { groups, links, .. } = &mut *registry;
+        links.retain(|link| match link.upgrade() {
+            Some(link) if !reachable(groups, link.from, link.to) => {
+                link.sever();
+                false
+            }
+            Some(_) => true,
+            None => false,
+        });
+    }
+
+    /// Heals the partition of the network, such that every address may
+    /// be reached again; the connections it severed are not restored.
+    pub fn heal(&self) {
+        self.shared.lock().unwrap().groups.clear();
+    }
+}
+
+// checks if a node hosted at `from` may reach the address `to`
+fn reachable(
+    groups: &HashMap<SocketAddr, usize>,
+    from: Option<SocketAddr>,
+    to: SocketAddr,
+) -> bool {
+    let from = from.and_then(|from| groups.get(&from));
+    match (from, groups.get(&to)) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+impl Link {
+    fn sever(&self) {
+        self.severed.store(true, Ordering::Release);
+        for reader in self.readers.iter() {
+            reader.wake();
+        }
+    }
+
+    fn is_severed(&self) -> bool {
+        self.severed.load(Ordering::Acquire)
+    }
+}
+
+impl Registry {
+    // checks if `addr` may be bound, assigning it a
+    // port first, if it was bound to port 0
+    fn claim(&mut self, mut addr: SocketAddr) -> io::Result<SocketAddr> {
+        if addr.port() == 0 {
+            loop {
+                let port = FIRST_EPHEMERAL_PORT.wrapping_add(self.next_port);
+                self.next_port = self.next_port.wrapping_add(1);
+                addr.set_port(port);
+                if !self.listeners.contains_key(&addr) {
+                    break;
+                }
+            }
+        }
+        if self.listeners.contains_key(&addr) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        Ok(addr)
+    }
+}
+
+impl SocketProvider for MemoryNetwork {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Listener>> {
+        let result: io::Result<Listener> = {
+            let mut registry = self.shared.lock().unwrap();
+            registry.claim(addr).map(|addr| {
+                let (tx, rx) = mpsc::unbounded();
+                registry.listeners.insert(addr, tx);
+                Listener::new(MemoryListener {
+                    addr,
+                    network: self.clone(),
+                    incoming: AsyncMutex::new(rx),
+                })
+            })
+        };
+        futures::future::ready(result).boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Socket>> {
+        let link = Arc::new(Link {
+            from: self.host,
+            to: addr,
+            severed: AtomicBool::new(false),
+            readers: [AtomicWaker::new(), AtomicWaker::new()],
+        });
+        let (ours, theirs) = MemorySocket::pair(&link);
+        let result: io::Result<Socket> = {
+            let mut registry = self.shared.lock().unwrap();
+            let result = registry
+                .listeners
+                .get(&addr)
+                .filter(|_| reachable(&registry.groups, self.host, addr))
+                .ok_or_else(|| io::ErrorKind::ConnectionRefused.into())
+                .and_then(|listener| {
+                    listener
+                        .unbounded_send(theirs)
+                        .map_err(|_| io::ErrorKind::ConnectionRefused.into())
+                })
+                .map(|_| Socket::new(ours));
+            if result.is_ok() {
+                registry.links.retain(|link| link.strong_count() > 0);
+                registry.links.push(Arc::downgrade(&link));
+            }
+            result
+        };
+        futures::future::ready(result).boxed()
+    }
+}
+
+impl RawListener for MemoryListener {
+    fn accept(&self) -> BoxFuture<'_, io::Result<Socket>> {
+        async move {
+            let mut incoming = self.incoming.lock().await;
+            incoming
+                .next()
+                .await
+                .map(Socket::new)
+                .ok_or_else(|| io::ErrorKind::NotConnected.into())
+        }
+        .boxed()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        // make the address available again
+        if let Ok(mut registry) = self.network.shared.lock() {
+            registry.listeners.remove(&self.addr);
+        }
+    }
+}
+
+impl MemorySocket {
+    fn pair(link: &Arc<Link>) -> (Self, Self) {
+        let (a_tx, a_rx) = mpsc::unbounded();
+        let (b_tx, b_rx) = mpsc::unbounded();
+        let a = MemorySocket {
+            tx: a_tx,
+            rx: b_rx,
+            pending: Vec::new(),
+            offset: 0,
+            link: Arc::clone(link),
+            end: 0,
+        };
+        let b = MemorySocket {
+            tx: b_tx,
+            rx: a_rx,
+            pending: Vec::new(),
+            offset: 0,
+            link: Arc::clone(link),
+            end: 1,
+        };
+        (a, b)
+    }
+}
+
+impl RawSocket for MemorySocket {}
+
+impl AsyncRead for MemorySocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // the bytes in flight are lost along with a severed connection
+        if self.link.is_severed() {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        while self.offset == self.pending.len() {
+            match self.rx.poll_next_unpin(cx) {
+                Poll::Ready(Some(chunk)) => {
+                    self.pending = chunk;
+                    self.offset = 0;
+                }
+                // the peer closed the connection
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => {
+                    self.link.readers[self.end].register(cx.waker());
+                    if self.link.is_severed() {
+                        return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+        let this = &mut *self;
+        let chunk = &this.pending[this.offset..];
+        let len = chunk.len().min(buf.len());
+        buf[..len].copy_from_slice(&chunk[..len]);
+        this.offset += len;
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for MemorySocket {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.link.is_severed() {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let result = self
+            .tx
+            .unbounded_send(buf.to_vec())
+            .map(|_| buf.len())
+            .map_err(|_| io::ErrorKind::BrokenPipe.into());
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.tx.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use futures::executor::block_on;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::MemoryNetwork;
+    use crate::bft::communication::socket::SocketProvider;
+
+    #[test]
+    fn test_memory_network() {
+        let network = MemoryNetwork::new();
+        let other = network.clone();
+
+        block_on(async {
+            // listeners bound to port 0 are assigned a port
+            let listener = network.bind(([127, 0, 0, 1], 0).into()).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            assert_ne!(addr.port(), 0);
+            let err = other.bind(addr).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+            // bytes flow both ways, across clones of the network
+            let mut dialed = other.connect(addr).await.unwrap();
+            let mut accepted = listener.accept().await.unwrap();
+            dialed.write_all(b"ping").await.unwrap();
+            accepted.write_all(b"pong").await.unwrap();
+
+            let mut buf = [0; 4];
+            accepted.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+            dialed.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"pong");
+
+            // partial reads keep the rest of a chunk
+            dialed.write_all(b"abcdef").await.unwrap();
+            let mut half = [0; 3];
+            accepted.read_exact(&mut half).await.unwrap();
+            assert_eq!(&half, b"abc");
+            accepted.read_exact(&mut half).await.unwrap();
+            assert_eq!(&half, b"def");
+
+            // closing one end is seen as EOF by the other
+            dialed.close().await.unwrap();
+            assert_eq!(accepted.read(&mut buf).await.unwrap(), 0);
+
+            // the address is unreachable once the listener is dropped
+            drop(listener);
+            let err = other.connect(addr).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        });
+    }
+
+    #[test]
+    fn test_memory_network_partition() {
+        let network = MemoryNetwork::new();
+        let a = ([127, 0, 0, 1], 1).into();
+        let b = ([127, 0, 0, 1], 2).into();
+        let (host_a, host_b) = (network.host(a), network.host(b));
+
+        block_on(async {
+            let listener_a = host_a.bind(a).await.unwrap();
+            let listener_b = host_b.bind(b).await.unwrap();
+            let mut dialed = host_a.connect(b).await.unwrap();
+            let mut accepted = listener_b.accept().await.unwrap();
+            let mut unhosted = network.connect(b).await.unwrap();
+            let _peer = listener_b.accept().await.unwrap();
+
+            // the connections across the partition are severed
+            network.partition(&[&[a], &[b]]);
+            let mut buf = [0; 4];
+            assert!(accepted.read(&mut buf).await.is_err());
+            assert!(dialed.write_all(b"ping").await.is_err());
+            let err = host_b.connect(a).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+            // nodes outside of every group are unaffected
+            unhosted.write_all(b"ping").await.unwrap();
+
+            // once healed, the groups reach each other again
+            network.heal();
+            let mut dialed = host_b.connect(a).await.unwrap();
+            let mut accepted = listener_a.accept().await.unwrap();
+            dialed.write_all(b"pong").await.unwrap();
+            accepted.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"pong");
+        });
+    }
+}
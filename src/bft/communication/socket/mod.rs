@@ -1,4 +1,10 @@
 //! Abstractions over different socket types of crates in the Rust ecosystem.
+//!
+//! The sockets of a `Node` are established through a `SocketProvider`.
+//! The built-in providers are TCP based, each backed by the crate selected
+//! with one of the `socket_*` features; other transports may be plugged in
+//! by implementing `SocketProvider`, `RawListener` and `RawSocket`, such
+//! as the in-memory `MemoryNetwork`, used to test nodes in a single process.
 
 #[cfg(feature = "socket_tokio_tcp")]
 mod tokio_tcp;
@@ -9,39 +15,191 @@ mod async_std_tcp;
 #[cfg(feature = "socket_rio_tcp")]
 mod rio_tcp;
 
+// the in-memory transport is only reachable from tests, unless
+// the internals of the crate are exposed
+#[cfg(any(test, feature = "expose_impl"))]
+mod memory;
+
+#[cfg(any(test, feature = "expose_impl"))]
+pub use memory::MemoryNetwork;
+
 use std::io;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use futures::future::BoxFuture;
 use futures::io::{AsyncRead, AsyncWrite};
+use futures::FutureExt;
 
 use crate::bft::error;
 
 /// A `Listener` represents a socket listening on new communications
 /// initiated by peer nodes in the BFT system.
 pub struct Listener {
-    #[cfg(feature = "socket_tokio_tcp")]
-    inner: tokio_tcp::Listener,
-
-    #[cfg(feature = "socket_async_std_tcp")]
-    inner: async_std_tcp::Listener,
-
-    #[cfg(feature = "socket_rio_tcp")]
-    inner: rio_tcp::Listener,
+    inner: Box<dyn RawListener>,
 }
 
 /// A `Socket` represents a connection between two peer processes
 /// in the BFT system.
 pub struct Socket {
-    #[cfg(feature = "socket_tokio_tcp")]
-    inner: tokio_tcp::Socket,
+    inner: Box<dyn RawSocket>,
+}
 
-    #[cfg(feature = "socket_async_std_tcp")]
-    inner: async_std_tcp::Socket,
+/// A transport over which the connections of a `Node` are established.
+pub trait SocketProvider: Send + Sync {
+    /// Creates a new `Listener`, bound to the address `addr`.
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Listener>>;
 
-    #[cfg(feature = "socket_rio_tcp")]
-    inner: rio_tcp::Socket,
+    /// Connects to the remote node pointed to by the address `addr`.
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Socket>>;
+}
+
+/// The listening end of a `SocketProvider`, wrapped by a `Listener`.
+pub trait RawListener: Send + Sync {
+    /// Accepts a new connection initiated by a peer node.
+    fn accept(&self) -> BoxFuture<'_, io::Result<Socket>>;
+
+    /// Returns the local address this listener is bound to.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+/// A connection established by a `SocketProvider`, wrapped by a `Socket`.
+///
+/// Transports without the options of TCP sockets ignore them when set,
+/// and fail with `io::ErrorKind::Unsupported` when they are queried.
+pub trait RawSocket: AsyncRead + AsyncWrite + Send + Unpin {
+    /// See `Socket::set_nodelay()`.
+    fn set_nodelay(&self, _nodelay: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// See `Socket::nodelay()`.
+    fn nodelay(&self) -> io::Result<bool> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// See `Socket::set_send_buffer_size()`.
+    fn set_send_buffer_size(&self, _size: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// See `Socket::send_buffer_size()`.
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// See `Socket::set_recv_buffer_size()`.
+    fn set_recv_buffer_size(&self, _size: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// See `Socket::recv_buffer_size()`.
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// The built-in `SocketProvider` over TCP, backed by `tokio`.
+#[cfg(feature = "socket_tokio_tcp")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TokioTcp;
+
+/// The built-in `SocketProvider` over TCP, backed by `async-std`.
+#[cfg(feature = "socket_async_std_tcp")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AsyncStdTcp;
+
+/// The built-in `SocketProvider` over TCP, backed by `rio`.
+#[cfg(feature = "socket_rio_tcp")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RioTcp;
+
+/// The `SocketProvider` used by default, selected with the `socket_*` features.
+#[cfg(feature = "socket_tokio_tcp")]
+pub type DefaultProvider = TokioTcp;
+
+/// The `SocketProvider` used by default, selected with the `socket_*` features.
+#[cfg(feature = "socket_async_std_tcp")]
+pub type DefaultProvider = AsyncStdTcp;
+
+/// The `SocketProvider` used by default, selected with the `socket_*` features.
+#[cfg(feature = "socket_rio_tcp")]
+pub type DefaultProvider = RioTcp;
+
+#[cfg(feature = "socket_tokio_tcp")]
+impl SocketProvider for TokioTcp {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Listener>> {
+        tokio_tcp::bind(addr)
+            .map(|result| result.map(Listener::new))
+            .boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Socket>> {
+        tokio_tcp::connect(addr)
+            .map(|result| result.map(Socket::new))
+            .boxed()
+    }
+}
+
+#[cfg(feature = "socket_async_std_tcp")]
+impl SocketProvider for AsyncStdTcp {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Listener>> {
+        async_std_tcp::bind(addr)
+            .map(|result| result.map(Listener::new))
+            .boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Socket>> {
+        async_std_tcp::connect(addr)
+            .map(|result| result.map(Socket::new))
+            .boxed()
+    }
+}
+
+#[cfg(feature = "socket_rio_tcp")]
+impl SocketProvider for RioTcp {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Listener>> {
+        rio_tcp::bind(addr)
+            .map(|result| result.map(Listener::new))
+            .boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Socket>> {
+        rio_tcp::connect(addr)
+            .map(|result| result.map(Socket::new))
+            .boxed()
+    }
+}
+
+/// Options applied to the sockets of a `Node`, both after connecting
+/// to a peer, and after accepting the connection of a peer.
+#[derive(Copy, Clone, Debug)]
+pub struct SocketOpts {
+    /// The value of the `TCP_NODELAY` option of the sockets.
+    ///
+    /// See `Socket::set_nodelay()`. Defaults to `true`, since the
+    /// messages of the sub-protocols are mostly small, and latency
+    /// sensitive. Dedicated bulk links always keep it disabled.
+    pub nodelay: bool,
+    /// The size of the send buffer of the sockets, i.e. `SO_SNDBUF`.
+    ///
+    /// If unset, the default of the operating system is kept.
+    pub send_buffer_size: Option<usize>,
+    /// The size of the receive buffer of the sockets, i.e. `SO_RCVBUF`.
+    ///
+    /// If unset, the default of the operating system is kept.
+    pub recv_buffer_size: Option<usize>,
+}
+
+impl Default for SocketOpts {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+        }
+    }
 }
 
 /// Initialize the sockets module.
@@ -64,51 +222,91 @@ pub unsafe fn drop() -> error::Result<()> {
     Ok(())
 }
 
-/// Creates a new `Listener` socket, bound to the address `addr`.
+/// Creates a new `Listener` socket, bound to the address `addr`,
+/// with the `DefaultProvider`.
 pub async fn bind<A: Into<SocketAddr>>(addr: A) -> io::Result<Listener> {
-    {
-        #[cfg(feature = "socket_tokio_tcp")]
-        {
-            tokio_tcp::bind(addr).await
-        }
+    DefaultProvider::default().bind(addr.into()).await
+}
 
-        #[cfg(feature = "socket_async_std_tcp")]
-        {
-            async_std_tcp::bind(addr).await
-        }
+/// Connects to the remote node pointed to by the address `addr`,
+/// with the `DefaultProvider`.
+pub async fn connect<A: Into<SocketAddr>>(addr: A) -> io::Result<Socket> {
+    DefaultProvider::default().connect(addr.into()).await
+}
 
-        #[cfg(feature = "socket_rio_tcp")]
-        {
-            rio_tcp::bind(addr).await
+impl Listener {
+    /// Wraps the listening end of a `SocketProvider`.
+    pub fn new<L: RawListener + 'static>(inner: L) -> Self {
+        Listener {
+            inner: Box::new(inner),
         }
     }
-    .map(|inner| Listener { inner })
+
+    pub async fn accept(&self) -> io::Result<Socket> {
+        self.inner.accept().await
+    }
+
+    /// Returns the local address this `Listener` is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
 }
 
-/// Connects to the remote node pointed to by the address `addr`.
-pub async fn connect<A: Into<SocketAddr>>(addr: A) -> io::Result<Socket> {
-    {
-        #[cfg(feature = "socket_tokio_tcp")]
-        {
-            tokio_tcp::connect(addr).await
+impl Socket {
+    /// Wraps a connection established by a `SocketProvider`.
+    pub fn new<S: RawSocket + 'static>(inner: S) -> Self {
+        Socket {
+            inner: Box::new(inner),
         }
+    }
 
-        #[cfg(feature = "socket_async_std_tcp")]
-        {
-            async_std_tcp::connect(addr).await
-        }
+    /// Sets the value of the `TCP_NODELAY` option of this `Socket`.
+    ///
+    /// If set, Nagle's algorithm is disabled, i.e. small writes are
+    /// sent out immediately, rather than coalesced into larger segments.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
 
-        #[cfg(feature = "socket_rio_tcp")]
-        {
-            rio_tcp::connect(addr).await
-        }
+    /// Returns the value of the `TCP_NODELAY` option of this `Socket`.
+    pub fn nodelay(&self) -> io::Result<bool> {
+        self.inner.nodelay()
     }
-    .map(|inner| Socket { inner })
-}
 
-impl Listener {
-    pub async fn accept(&self) -> io::Result<Socket> {
-        self.inner.accept().await.map(|inner| Socket { inner })
+    /// Sets the size of the send buffer of this `Socket`.
+    ///
+    /// The operating system may adjust the size, e.g. Linux doubles it.
+    pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        self.inner.set_send_buffer_size(size)
+    }
+
+    /// Returns the size of the send buffer of this `Socket`.
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        self.inner.send_buffer_size()
+    }
+
+    /// Sets the size of the receive buffer of this `Socket`.
+    ///
+    /// The operating system may adjust the size, e.g. Linux doubles it.
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        self.inner.set_recv_buffer_size(size)
+    }
+
+    /// Returns the size of the receive buffer of this `Socket`.
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        self.inner.recv_buffer_size()
+    }
+
+    /// Applies the given `SocketOpts` to this `Socket`.
+    pub fn set_opts(&self, opts: &SocketOpts) -> io::Result<()> {
+        self.set_nodelay(opts.nodelay)?;
+        if let Some(size) = opts.send_buffer_size {
+            self.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = opts.recv_buffer_size {
+            self.set_recv_buffer_size(size)?;
+        }
+        Ok(())
     }
 }
 
@@ -12,13 +12,17 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
+use futures::future::BoxFuture;
 use futures::io::{AsyncRead, AsyncWrite};
+use futures::FutureExt;
 use rio::{Completion, Uring};
-use socket2::{Domain, Protocol, Socket as SSocket, Type};
+use socket2::{Domain, Protocol, SockRef, Socket as SSocket, Type};
 
 use crate::bft::error::{self, ErrorKind, ResultSimpleExt, ResultWrappedExt};
 use crate::bft::globals::Global;
 
+use super::{RawListener, RawSocket};
+
 // the same type used by rio 0.9
 struct Rio(Arc<Uring>);
 
@@ -85,13 +89,50 @@ pub async fn connect<A: Into<SocketAddr>>(addr: A) -> io::Result<Socket> {
     })
 }
 
-impl Listener {
-    pub async fn accept(&self) -> io::Result<Socket> {
-        ring().accept(&self.inner).await.map(|inner| Socket {
-            inner,
-            reading: None,
-            writing: None,
-        })
+impl RawListener for Listener {
+    fn accept(&self) -> BoxFuture<'_, io::Result<super::Socket>> {
+        ring()
+            .accept(&self.inner)
+            .map(|result| {
+                result.map(|inner| {
+                    super::Socket::new(Socket {
+                        inner,
+                        reading: None,
+                        writing: None,
+                    })
+                })
+            })
+            .boxed()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl RawSocket for Socket {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    fn nodelay(&self) -> io::Result<bool> {
+        self.inner.nodelay()
+    }
+
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        SockRef::from(&self.inner).set_send_buffer_size(size)
+    }
+
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(&self.inner).send_buffer_size()
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        SockRef::from(&self.inner).set_recv_buffer_size(size)
+    }
+
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(&self.inner).recv_buffer_size()
     }
 }
 
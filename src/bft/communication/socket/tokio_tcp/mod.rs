@@ -3,10 +3,15 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use futures::future::BoxFuture;
 use futures::io::{AsyncRead, AsyncWrite};
+use futures::FutureExt;
+use socket2::SockRef;
 use tokio::net::{TcpListener, TcpStream};
 use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
 
+use super::{RawListener, RawSocket};
+
 pub struct Socket {
     inner: Compat<TcpStream>,
 }
@@ -29,12 +34,18 @@ impl Listener {
     fn new(inner: TcpListener) -> Self {
         Listener { inner }
     }
+}
 
-    pub async fn accept(&self) -> io::Result<Socket> {
+impl RawListener for Listener {
+    fn accept(&self) -> BoxFuture<'_, io::Result<super::Socket>> {
         self.inner
             .accept()
-            .await
-            .map(|(s, _)| Socket::new(s.compat()))
+            .map(|result| result.map(|(s, _)| super::Socket::new(Socket::new(s.compat()))))
+            .boxed()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
     }
 }
 
@@ -44,6 +55,32 @@ impl Socket {
     }
 }
 
+impl RawSocket for Socket {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.get_ref().set_nodelay(nodelay)
+    }
+
+    fn nodelay(&self) -> io::Result<bool> {
+        self.inner.get_ref().nodelay()
+    }
+
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        SockRef::from(self.inner.get_ref()).set_send_buffer_size(size)
+    }
+
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(self.inner.get_ref()).send_buffer_size()
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        SockRef::from(self.inner.get_ref()).set_recv_buffer_size(size)
+    }
+
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(self.inner.get_ref()).recv_buffer_size()
+    }
+}
+
 impl AsyncRead for Socket {
     fn poll_read(
         mut self: Pin<&mut Self>,
@@ -1,26 +1,35 @@
 //! A module to manage the `bafomet` message log.
 
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
+use std::io::{Read, Write};
 use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "serialize_serde")]
 use serde::{Deserialize, Serialize};
 
+use bytes::Bytes;
+
 use crate::bft::collections::{self, HashMap, OrderedMap};
 use crate::bft::communication::message::{
     ConsensusMessage, ConsensusMessageKind, Header, RequestMessage, StoredMessage, SystemMessage,
+    WireMessage,
 };
+use crate::bft::communication::serialize::{DigestData, SharedData, ToBytes};
+use crate::bft::communication::NodeId;
 use crate::bft::core::server::ViewInfo;
-use crate::bft::crypto::hash::Digest;
-use crate::bft::cst::RecoveryState;
+use crate::bft::crypto::hash::{Context, Digest, DigestHasher};
+use crate::bft::crypto::signature::PublicKey;
+use crate::bft::cst::{Delta, RecoveryState};
 use crate::bft::error::*;
 use crate::bft::executable::UpdateBatch;
 use crate::bft::ordering::{Orderable, SeqNo};
 
-/// Checkpoint period.
+/// Default checkpoint period.
 ///
 /// Every `PERIOD` messages, the message log is cleared,
-/// and a new log checkpoint is initiated.
+/// and a new log checkpoint is initiated. A different
+/// period may be configured with `Log::new`.
 pub const PERIOD: u32 = 1000;
 
 /// Information reported after a logging operation.
@@ -42,10 +51,16 @@ enum CheckpointState<S> {
     Partial {
         // sequence number of the last executed request
         seq: SeqNo,
+        // the requests executed before the checkpoint
+        executed: ExecutedRequests,
+        // no. of decided requests covered by the checkpoint
+        decided: usize,
     },
     PartialWithEarlier {
         // sequence number of the last executed request
         seq: SeqNo,
+        executed: ExecutedRequests,
+        decided: usize,
         // save the earlier checkpoint, in case corruption takes place
         earlier: Checkpoint<S>,
     },
@@ -62,6 +77,7 @@ enum CheckpointState<S> {
 pub struct Checkpoint<S> {
     seq: SeqNo,
     appstate: S,
+    executed: ExecutedRequests,
 }
 
 impl<S> Orderable for Checkpoint<S> {
@@ -73,18 +89,262 @@ impl<S> Orderable for Checkpoint<S> {
 }
 
 impl<S> Checkpoint<S> {
+    /// Creates a new local checkpoint, with the state `appstate` of the
+    /// application after the batch with sequence number `seq` was executed.
+    pub fn new(seq: SeqNo, appstate: S) -> Self {
+        Self {
+            seq,
+            appstate,
+            executed: ExecutedRequests::default(),
+        }
+    }
+
     /// Returns a reference to the state of the application before
     /// the local checkpoint.
     pub fn state(&self) -> &S {
         &self.appstate
     }
 
+    /// Returns the client requests executed before the local checkpoint.
+    pub fn executed(&self) -> &ExecutedRequests {
+        &self.executed
+    }
+
+    /// Sets the client requests executed before the local checkpoint,
+    /// which are none by default.
+    pub fn with_executed(mut self, executed: ExecutedRequests) -> Self {
+        self.executed = executed;
+        self
+    }
+
     /// Returns the inner values within this local checkpoint.
     pub fn into_inner(self) -> (SeqNo, S) {
         (self.seq, self.appstate)
     }
 }
 
+/// A client request decided by the consensus layer, which is
+/// retained in the `Log` until the next local checkpoint.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub enum DecidedRequest<O> {
+    /// The request, cloned from the batch delivered
+    /// to the execution layer.
+    Operation(Header, RequestMessage<O>),
+    /// The serialized `SystemMessage::Request` the request was
+    /// received in, shared with the `StoredMessage` it came from.
+    Serialized(Header, Bytes),
+}
+
+impl<O> DecidedRequest<O> {
+    /// Returns the header of the message the request was received in,
+    /// identifying the client that sent it.
+    pub fn header(&self) -> &Header {
+        match self {
+            DecidedRequest::Operation(header, _) | DecidedRequest::Serialized(header, _) => header,
+        }
+    }
+
+    /// Returns the request of this `DecidedRequest`, deserializing
+    /// it with `D` if only its serialized form was retained.
+    pub fn into_request<D>(self) -> Result<RequestMessage<O>>
+    where
+        D: SharedData<Request = O>,
+    {
+        match self {
+            DecidedRequest::Operation(_, message) => Ok(message),
+            DecidedRequest::Serialized(_, bytes) => match D::deserialize_message(&bytes[..])? {
+                SystemMessage::Request(message) => Ok(message),
+                _ => Err("Serialized request is not a client request")
+                    .wrapped(ErrorKind::ConsensusLog),
+            },
+        }
+    }
+
+    /// Same as `into_request()`, but only returns the operation.
+    pub fn into_operation<D>(self) -> Result<O>
+    where
+        D: SharedData<Request = O>,
+    {
+        self.into_request::<D>().map(RequestMessage::into_inner)
+    }
+
+    /// Returns the digest of the payload of the wire message the request
+    /// was received in, recomputed with `D`, which its header should carry.
+    pub fn payload_digest<D>(&self) -> Result<Digest>
+    where
+        D: DigestData<Request = O>,
+        O: Clone,
+    {
+        match self {
+            DecidedRequest::Operation(_, message) => {
+                let message = SystemMessage::Request(message.clone());
+                D::serialize_digest(&message, Vec::new())
+            }
+            DecidedRequest::Serialized(_, bytes) => Ok(D::serialized_digest(&bytes[..])),
+        }
+    }
+
+    /// Returns the digest of the header and the serialized
+    /// `SystemMessage::Request` this `DecidedRequest` was decided in,
+    /// which is the same for both of its variants.
+    pub fn digest<D>(&self) -> Result<Digest>
+    where
+        D: SharedData<Request = O>,
+        O: Clone,
+    {
+        let mut ctx = D::Hasher::context();
+        let mut buf = [0; Header::LENGTH];
+        // infallible, since the buffer is large enough
+        self.header().serialize_into(&mut buf[..]).unwrap();
+        ctx.update(&buf[..]);
+        match self {
+            DecidedRequest::Operation(_, message) => {
+                let message = SystemMessage::Request(message.clone());
+                D::serialize_message(&mut ctx, &message)?;
+            }
+            DecidedRequest::Serialized(_, bytes) => ctx.update(&bytes[..]),
+        }
+        Ok(ctx.finish())
+    }
+}
+
+// the number of sequence numbers preceding the latest request
+// executed on behalf of a client, for which it is tracked whether
+// the respective request was executed
+const EXECUTED_WINDOW: u32 = u64::BITS;
+
+/// The client requests executed by a replica, used to detect
+/// replayed requests.
+///
+/// For each client, besides its latest executed request, the requests
+/// with the `EXECUTED_WINDOW` sequence numbers preceding it are tracked,
+/// since a client with several requests in flight may have them decided
+/// out of order. Requests older than that are assumed to be executed.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Default)]
+pub struct ExecutedRequests {
+    clients: HashMap<NodeId, ExecutedWindow>,
+}
+
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone)]
+struct ExecutedWindow {
+    latest: SeqNo,
+    // bit `i` is set if the request with the sequence
+    // number `i` places before `latest` was executed
+    executed: u64,
+}
+
+impl ToBytes for ExecutedRequests {
+    fn to_bytes<W: Write>(&self, w: &mut W) -> Result<()> {
+        // sort the clients, such that replicas which executed
+        // the same requests serialize them in the same way
+        let mut clients: Vec<_> = self.clients.iter().collect();
+        clients.sort_unstable_by_key(|(id, _)| u32::from(**id));
+
+        (clients.len() as u64).to_bytes(w)?;
+        for (id, window) in clients {
+            u32::from(*id).to_bytes(w)?;
+            window.latest.to_bytes(w)?;
+            window.executed.to_bytes(w)?;
+        }
+        Ok(())
+    }
+
+    fn from_bytes<R: Read>(r: &mut R) -> Result<Self> {
+        let count = u64::from_bytes(r)?;
+        // don't trust the count to pre-allocate memory
+        let mut clients = collections::hash_map();
+        for _ in 0..count {
+            let id = NodeId::from(u32::from_bytes(r)?);
+            let latest = SeqNo::from_bytes(r)?;
+            let executed = u64::from_bytes(r)?;
+            clients.insert(id, ExecutedWindow { latest, executed });
+        }
+        Ok(Self { clients })
+    }
+}
+
+// where a sequence number falls, relative to an `ExecutedWindow`
+enum WindowPosition {
+    Within(u32),
+    Ahead(u32),
+    Older,
+}
+
+impl ExecutedWindow {
+    fn position(&self, seq: SeqNo) -> WindowPosition {
+        let behind = self.latest.distance(seq);
+        if behind < EXECUTED_WINDOW {
+            WindowPosition::Within(behind)
+        } else if behind > u32::MAX >> 2 {
+            // `distance()` wrapped around, so `seq` is
+            // actually ahead of the latest request
+            WindowPosition::Ahead(seq.distance(self.latest))
+        } else {
+            WindowPosition::Older
+        }
+    }
+}
+
+impl ExecutedRequests {
+    /// Checks if the request with sequence number `seq`, sent by the
+    /// client `from`, was executed.
+    pub fn contains(&self, from: NodeId, seq: SeqNo) -> bool {
+        let window = match self.clients.get(&from) {
+            Some(window) => window,
+            None => return false,
+        };
+        match window.position(seq) {
+            WindowPosition::Within(i) => window.executed & (1 << i) != 0,
+            WindowPosition::Ahead(_) => false,
+            WindowPosition::Older => true,
+        }
+    }
+
+    /// Marks the request with sequence number `seq`, sent by the client
+    /// `from`, as executed, returning whether it wasn't executed before.
+    pub fn insert(&mut self, from: NodeId, seq: SeqNo) -> bool {
+        let window = self.clients.entry(from).or_insert(ExecutedWindow {
+            latest: seq,
+            executed: 0,
+        });
+        match window.position(seq) {
+            WindowPosition::Within(i) => {
+                let fresh = window.executed & (1 << i) == 0;
+                window.executed |= 1 << i;
+                fresh
+            }
+            WindowPosition::Ahead(n) => {
+                window.executed = window.executed.checked_shl(n).unwrap_or(0) | 1;
+                window.latest = seq;
+                true
+            }
+            WindowPosition::Older => false,
+        }
+    }
+
+    /// Takes the operations of the `requests` which weren't executed
+    /// yet, in order, and marks them as executed, such as when they are
+    /// replayed after installing a checkpoint.
+    pub fn unexecuted_operations<D, O, I>(&mut self, requests: I) -> Result<Vec<O>>
+    where
+        D: SharedData<Request = O>,
+        I: IntoIterator<Item = DecidedRequest<O>>,
+    {
+        let mut operations = Vec::new();
+        for request in requests {
+            let from = request.header().from();
+            let message = request.into_request::<D>()?;
+            if self.insert(from, message.sequence_number()) {
+                operations.push(message.into_inner());
+            }
+        }
+        Ok(operations)
+    }
+}
+
 /// Subset of a `Log`, containing only consensus messages.
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
@@ -214,6 +474,31 @@ impl DecisionLog {
         self.last_exec
     }
 
+    /// Returns the digest of the consensus messages in this `DecisionLog`.
+    ///
+    /// Only the headers of the messages are hashed, since these
+    /// already include the digests of their payloads.
+    pub fn digest(&self) -> Digest {
+        let mut ctx = Context::new();
+        match self.last_exec {
+            Some(seq) => {
+                ctx.update(&[1]);
+                ctx.update(&u32::from(seq).to_le_bytes());
+            }
+            None => ctx.update(&[0]),
+        }
+        let mut buf = [0; Header::LENGTH];
+        for messages in &[&self.pre_prepares, &self.prepares, &self.commits] {
+            ctx.update(&(messages.len() as u64).to_le_bytes());
+            for stored in messages.iter() {
+                // infallible, since the buffer is large enough
+                stored.header().serialize_into(&mut buf[..]).unwrap();
+                ctx.update(&buf[..]);
+            }
+        }
+        ctx.finish()
+    }
+
     /// Returns the list of `PRE-PREPARE` messages after the last checkpoint
     /// at the moment of the creation of this `DecisionLog`.
     pub fn pre_prepares(&self) -> &[StoredMessage<ConsensusMessage>] {
@@ -232,8 +517,100 @@ impl DecisionLog {
         &self.commits[..]
     }
 
+    /// Writes a portable serialization of this `DecisionLog` to `w`,
+    /// e.g. to be audited by a third party.
+    ///
+    /// The output starts with the wire format version, which pins the
+    /// signature scheme in use, followed by the sequence number of the
+    /// last executed batch. Then, for each of the `PRE-PREPARE`, `PREPARE`
+    /// and `COMMIT` lists, a message count precedes the messages, each
+    /// prefixed by its length in bytes. Messages are stored along with
+    /// their original headers, so their signatures are preserved.
+    pub fn export<W: Write>(&self, w: &mut W) -> Result<()> {
+        WireMessage::CURRENT_VERSION.to_bytes(w)?;
+        match self.last_exec {
+            Some(seq) => {
+                1_u8.to_bytes(w)?;
+                seq.to_bytes(w)?;
+            }
+            None => 0_u8.to_bytes(w)?,
+        }
+        let mut buf = Vec::new();
+        for messages in &[&self.pre_prepares, &self.prepares, &self.commits] {
+            (messages.len() as u64).to_bytes(w)?;
+            for stored in messages.iter() {
+                buf.clear();
+                stored.header().to_bytes(&mut buf)?;
+                stored.message().to_bytes(&mut buf)?;
+                (buf.len() as u64).to_bytes(w)?;
+                w.write_all(&buf[..]).wrapped(ErrorKind::ConsensusLog)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a `DecisionLog` previously written with `export()`.
+    ///
+    /// The signature of every message is checked against the public
+    /// key of its sender, looked up in `public_keys`. Since signatures
+    /// only cover the headers, the digest of each payload is recomputed
+    /// with `D` and compared against the one in its header.
+    pub fn import<D: DigestData, R: Read>(
+        r: &mut R,
+        public_keys: &HashMap<NodeId, PublicKey>,
+    ) -> Result<Self> {
+        if u32::from_bytes(r)? != WireMessage::CURRENT_VERSION {
+            return Err("Unsupported decision log version").wrapped(ErrorKind::ConsensusLog);
+        }
+        let last_exec = match u8::from_bytes(r)? {
+            0 => None,
+            1 => Some(SeqNo::from_bytes(r)?),
+            _ => return Err("Invalid last execution").wrapped(ErrorKind::ConsensusLog),
+        };
+        let mut lists = [Vec::new(), Vec::new(), Vec::new()];
+        for messages in lists.iter_mut() {
+            let count = u64::from_bytes(r)?;
+            // don't trust the count to pre-allocate memory,
+            // since it was read from a foreign source
+            for _ in 0..count {
+                let length = u64::from_bytes(r)?;
+                let mut entry = r.by_ref().take(length);
+                let header = Header::from_bytes(&mut entry)?;
+                let message = ConsensusMessage::from_bytes(&mut entry)?;
+                if entry.limit() != 0 {
+                    return Err("Invalid message length").wrapped(ErrorKind::ConsensusLog);
+                }
+                let message = SystemMessage::Consensus(message);
+                let digest = D::serialize_digest(&message, Vec::new())?;
+                if &digest != header.digest() {
+                    return Err("Invalid message digest").wrapped(ErrorKind::ConsensusLog);
+                }
+                let verified = public_keys
+                    .get(&header.from())
+                    .map(|pk| header.verify_signature_hashed::<D::Hasher>(pk))
+                    .unwrap_or(false);
+                if !verified {
+                    return Err("Invalid message signature").wrapped(ErrorKind::ConsensusLog);
+                }
+                let message = match message {
+                    SystemMessage::Consensus(message) => message,
+                    // we wrapped the message above
+                    _ => unreachable!(),
+                };
+                messages.push(StoredMessage::new(header, message));
+            }
+        }
+        let [pre_prepares, prepares, commits] = lists;
+        Ok(Self {
+            last_exec,
+            pre_prepares,
+            prepares,
+            commits,
+        })
+    }
+
     // TODO: quorum sizes may differ when we implement reconfiguration
-    pub fn collect_data(&self, view: ViewInfo) -> CollectData {
+    pub fn collect_data(&self, view: &ViewInfo) -> CollectData {
         CollectData {
             incomplete_proof: self.to_be_decided(view),
             last_proof: self.last_decision(view),
@@ -252,7 +629,7 @@ impl DecisionLog {
 
     /// Returns an incomplete proof of the consensus
     /// instance currently being decided in this `DecisionLog`.
-    pub fn to_be_decided(&self, view: ViewInfo) -> IncompleteProof {
+    pub fn to_be_decided(&self, view: &ViewInfo) -> IncompleteProof {
         let in_exec = self.executing();
 
         // fetch write set
@@ -317,7 +694,7 @@ impl DecisionLog {
 
     /// Returns the proof of the last executed consensus
     /// instance registered in this `DecisionLog`.
-    pub fn last_decision(&self, view: ViewInfo) -> Option<Proof> {
+    pub fn last_decision(&self, view: &ViewInfo) -> Option<Proof> {
         let last_exec = self.last_exec?;
 
         let pre_prepare = 'outer: loop {
@@ -402,6 +779,111 @@ impl DecisionLog {
         })
     }
 
+    /// Returns the `COMMIT` messages proving the decision of the
+    /// consensus instance with sequence number `seq`.
+    ///
+    /// The certificate holds one `COMMIT` per replica, from the latest
+    /// view in which the instance was committed, all of them with the
+    /// same digest. If fewer than a quorum of replicas committed it, or
+    /// its messages were already cleared from the log, `None` is returned.
+    pub fn quorum_certificate(
+        &self,
+        seq: SeqNo,
+        view: &ViewInfo,
+    ) -> Option<Vec<StoredMessage<ConsensusMessage>>> {
+        let last_view = self
+            .commits
+            .iter()
+            .filter(|stored| stored.message().sequence_number() == seq)
+            .map(|stored| stored.message().view())
+            .max()?;
+
+        // replicas committing different digests don't vouch for the
+        // same decision, so their votes are grouped by digest
+        let mut certificates: Vec<(Digest, Vec<StoredMessage<ConsensusMessage>>)> = Vec::new();
+        for stored in self.commits.iter() {
+            let matches =
+                stored.message().sequence_number() == seq && stored.message().view() == last_view;
+            let digest = match stored.message().kind() {
+                ConsensusMessageKind::Commit(digest) if matches => *digest,
+                _ => continue,
+            };
+            let i = match certificates.iter().position(|(d, _)| *d == digest) {
+                Some(i) => i,
+                None => {
+                    certificates.push((digest, Vec::new()));
+                    certificates.len() - 1
+                }
+            };
+            // count a single vote per replica
+            let certificate = &mut certificates[i].1;
+            let from = stored.header().from();
+            if !certificate.iter().any(|c| c.header().from() == from) {
+                certificate.push(stored.clone());
+            }
+        }
+
+        certificates
+            .into_iter()
+            .map(|(_, certificate)| certificate)
+            .find(|certificate| certificate.len() >= view.params().quorum())
+    }
+
+    // removes the messages cast in the consensus instance with sequence
+    // number `seq` in views other than the latest one it was committed in,
+    // which don't belong to the proof of its decision
+    fn trim_superseded(&mut self, seq: SeqNo) {
+        let decided_view = match self
+            .commits
+            .iter()
+            .filter(|stored| stored.message().sequence_number() == seq)
+            .map(|stored| stored.message().view())
+            .max()
+        {
+            Some(view) => view,
+            None => return,
+        };
+        for log in [
+            &mut self.pre_prepares,
+            &mut self.prepares,
+            &mut self.commits,
+        ] {
+            log.retain(|stored| {
+                stored.message().sequence_number() != seq || stored.message().view() == decided_view
+            });
+        }
+    }
+
+    // returns the messages of the consensus instances from `from`
+    // up to, but excluding, `to`
+    fn instances(&self, from: SeqNo, to: SeqNo) -> DecisionLog {
+        let len = to.distance(from);
+        let within = |stored: &&StoredMessage<ConsensusMessage>| {
+            stored.message().sequence_number().distance(from) < len
+        };
+        let select = |messages: &[StoredMessage<ConsensusMessage>]| {
+            messages.iter().filter(within).cloned().collect()
+        };
+        DecisionLog {
+            last_exec: None,
+            pre_prepares: select(&self.pre_prepares),
+            prepares: select(&self.prepares),
+            commits: select(&self.commits),
+        }
+    }
+
+    // removes the messages of the consensus instances decided before
+    // the instance with sequence number `seq`
+    fn trim_before(&mut self, seq: SeqNo) {
+        for log in [
+            &mut self.pre_prepares,
+            &mut self.prepares,
+            &mut self.commits,
+        ] {
+            log.retain(|stored| stored.message().sequence_number() >= seq);
+        }
+    }
+
     /// Clear incomplete proofs from the log, which match the consensus
     /// with sequence number `in_exec`.
     ///
@@ -469,14 +951,90 @@ impl DecisionLog {
     }
 }
 
+// the key requests are sorted by, when scheduling them to be proposed:
+// requests which arrived over `max_wait` ago come first, in arrival
+// order, such that none is starved; then higher priorities, up to
+// `max_priority`, then earlier deadlines, relative to the arrival of
+// each request, then requests without a deadline; requests without
+// metadata have the lowest priority
+fn schedule_key<O>(
+    stored: &StoredMessage<RequestMessage<O>>,
+    arrival: Instant,
+    now: Instant,
+    max_priority: u32,
+    max_wait: Duration,
+) -> (bool, Reverse<u32>, bool, Option<Instant>, Instant) {
+    if now.saturating_duration_since(arrival) >= max_wait {
+        return (false, Reverse(0), true, None, arrival);
+    }
+    let metadata = stored.message().metadata().copied().unwrap_or_default();
+    // a deadline too far into the future to be represented
+    // is as good as no deadline at all
+    let deadline = metadata
+        .deadline()
+        .and_then(|deadline| arrival.checked_add(deadline));
+    let priority = metadata.priority().min(max_priority);
+    (
+        true,
+        Reverse(priority),
+        deadline.is_none(),
+        deadline,
+        arrival,
+    )
+}
+
+// the serialized size of a decided request, if it was retained
+fn serialized_len<O>(request: &DecidedRequest<O>) -> usize {
+    match request {
+        DecidedRequest::Serialized(_, bytes) => bytes.len(),
+        // we don't know the size of the operation
+        DecidedRequest::Operation(..) => 0,
+    }
+}
+
+// a request received while ingestion is paused, with its arrival time
+type DeferredRequest<O> = (Digest, StoredMessage<RequestMessage<O>>, Instant);
+
 /// Represents a log of messages received by the BFT system.
 pub struct Log<S, O, P> {
     curr_seq: SeqNo,
+    period: u32,
+    // the sequence number of the last checkpoint; the next one
+    // is taken `period` instances after it, which, unlike taking
+    // them on multiples of `period`, works across a wraparound
+    last_checkpoint: SeqNo,
     batch_size: usize,
+    max_batch_bytes: usize,
+    // sum of the serialized sizes of the requests in `deciding`
+    deciding_bytes: usize,
+    // the `deciding_bytes` forcing the proposal of a partial batch
+    max_deciding_bytes: usize,
+    max_bytes: usize,
+    // sum of the serialized sizes of the requests in `decided`
+    decided_bytes: usize,
     declog: DecisionLog,
     requests: OrderedMap<Digest, StoredMessage<RequestMessage<O>>>,
+    // no. of requests in `requests` carrying metadata; while there
+    // are none, requests are proposed in arrival order, without
+    // scanning `requests` for the next one to schedule
+    with_metadata: usize,
+    // the highest priority clients may schedule their requests with
+    max_priority: u32,
+    // the time after which a request is scheduled ahead of
+    // those with a higher priority or an earlier deadline
+    max_wait: Duration,
+    // the arrival times of the requests in `requests` and `deciding`
+    arrivals: HashMap<Digest, Instant>,
+    // requests received while ingestion is paused, in arrival order
+    deferred: Option<Vec<DeferredRequest<O>>>,
     deciding: HashMap<Digest, StoredMessage<RequestMessage<O>>>,
-    decided: Vec<O>,
+    decided: Vec<DecidedRequest<O>>,
+    // the consensus instances decided since the last checkpoint,
+    // each paired with the index of its first request in `decided`
+    decided_batches: Vec<(SeqNo, usize)>,
+    // the requests executed on behalf of each
+    // client, to detect replayed requests
+    executed: ExecutedRequests,
     checkpoint: CheckpointState<S>,
     _marker: PhantomData<P>,
 }
@@ -489,20 +1047,150 @@ impl<S, O, P> Log<S, O, P> {
     ///
     /// The value `batch_size` represents the maximum number of
     /// client requests to queue before executing a consensus instance.
-    pub fn new(batch_size: usize) -> Self {
+    /// Likewise, `max_batch_bytes` bounds the sum of the serialized
+    /// sizes of the requests in a batch, such that the payloads of
+    /// `PRE-PREPARE` messages remain within the transport bounds.
+    /// It also bounds the memory held by the requests being decided:
+    /// once they reach `max_batch_bytes`, the partial batch is proposed,
+    /// even if fewer than `batch_size` requests were queued.
+    ///
+    /// Every `period` consensus instances, the log is cleared,
+    /// and a new log checkpoint is initiated. If checkpoints stall,
+    /// the decided requests keep piling up, so `max_bytes` sets a
+    /// hard ceiling on the sum of their serialized sizes; check out
+    /// `exceeds_max_bytes()`.
+    pub fn new(batch_size: usize, max_batch_bytes: usize, period: u32, max_bytes: usize) -> Self {
         Self {
+            period,
             batch_size,
+            max_batch_bytes,
+            max_bytes,
+            deciding_bytes: 0,
+            max_deciding_bytes: usize::MAX,
+            decided_bytes: 0,
             curr_seq: SeqNo::ZERO,
+            last_checkpoint: SeqNo::ZERO,
             declog: DecisionLog::new(),
             deciding: collections::hash_map_capacity(batch_size),
-            // TODO: use config value instead of const
-            decided: Vec::with_capacity(PERIOD as usize),
+            decided: Vec::with_capacity(period as usize),
+            decided_batches: Vec::with_capacity(period as usize),
             requests: collections::ordered_map(),
+            with_metadata: 0,
+            max_priority: u32::MAX,
+            max_wait: Duration::MAX,
+            arrivals: collections::hash_map(),
+            deferred: None,
+            executed: ExecutedRequests::default(),
             checkpoint: CheckpointState::None,
             _marker: PhantomData,
         }
     }
 
+    /// Bounds the priority of the requests in this log to `max_priority`,
+    /// which is unbounded by default. Requests carrying a higher priority
+    /// in their `RequestMeta` are scheduled as if they carried this one,
+    /// such that clients can't push their requests ahead of others at will.
+    pub fn with_max_priority(mut self, max_priority: u32) -> Self {
+        self.max_priority = max_priority;
+        self
+    }
+
+    /// Bounds the time a request may be scheduled behind others to
+    /// `max_wait`, which is unbounded by default. Requests which arrived
+    /// longer than `max_wait` ago are scheduled ahead of those with
+    /// a higher priority or an earlier deadline, in arrival order,
+    /// such that requests without either aren't starved.
+    pub fn with_max_request_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Bounds the memory held by the requests being decided to
+    /// `max_deciding_bytes`, which is unbounded by default, other than
+    /// by `max_batch_bytes`. Once the requests being decided reach this
+    /// size, the partial batch is proposed; check `deciding_bytes()`.
+    pub fn with_max_deciding_bytes(mut self, max_deciding_bytes: usize) -> Self {
+        self.max_deciding_bytes = max_deciding_bytes;
+        self
+    }
+
+    /// Returns the number of consensus instances between two checkpoints.
+    pub fn checkpoint_period(&self) -> u32 {
+        self.period
+    }
+
+    /// Returns the maximum number of client requests in a batch.
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Returns the maximum sum of the serialized sizes of the
+    /// client requests in a batch.
+    pub fn max_batch_bytes(&self) -> usize {
+        self.max_batch_bytes
+    }
+
+    /// Returns the number of requests being decided, i.e. those in the
+    /// batch being formed, and those proposed but not yet decided.
+    pub fn deciding_len(&self) -> usize {
+        self.deciding.len()
+    }
+
+    /// Returns the sum of the serialized sizes of the requests
+    /// being decided; check `deciding_len()`.
+    pub fn deciding_bytes(&self) -> usize {
+        self.deciding_bytes
+    }
+
+    /// Returns the bound on `deciding_bytes()`, past which
+    /// a partial batch is proposed.
+    pub fn max_deciding_bytes(&self) -> usize {
+        self.max_deciding_bytes
+    }
+
+    /// Returns the ceiling on the sum of the serialized sizes of
+    /// the requests decided since the last checkpoint.
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Returns the sum of the serialized sizes of the requests
+    /// decided since the last checkpoint.
+    pub fn decided_bytes(&self) -> usize {
+        self.decided_bytes
+    }
+
+    /// Checks if the requests decided since the last checkpoint
+    /// went over the ceiling set with `Log::new`.
+    ///
+    /// When this is the case, the replica should call `drop_decided()`,
+    /// and recover the latest state from its peers, with the CST protocol.
+    pub fn exceeds_max_bytes(&self) -> bool {
+        self.decided_bytes > self.max_bytes
+    }
+
+    /// Drops the requests decided since the last checkpoint, as well as
+    /// the decision log, releasing their memory.
+    ///
+    /// The last checkpoint is retained, so this log remains consistent,
+    /// albeit behind the rest of the system.
+    pub fn drop_decided(&mut self) {
+        self.decided = Vec::new();
+        self.decided_batches = Vec::new();
+        self.decided_bytes = 0;
+        self.declog = DecisionLog::new();
+    }
+
+    /// Drops the requests pulled into a batch that was not decided yet,
+    /// e.g. because the consensus instance deciding it was abandoned.
+    pub fn drop_deciding(&mut self) {
+        for digest in self.deciding.keys() {
+            self.arrivals.remove(digest);
+        }
+        self.deciding.clear();
+        self.deciding_bytes = 0;
+    }
+
     /// Returns a reference to a subset of this log, containing only
     /// consensus messages.
     pub fn decision_log(&self) -> &DecisionLog {
@@ -515,12 +1203,144 @@ impl<S, O, P> Log<S, O, P> {
         &mut self.declog
     }
 
+    /// Invoked once the consensus instance with sequence number `seq`
+    /// decides, after its batch was finalized with `finalize_batch()`.
+    ///
+    /// The messages cast in this instance in earlier views, which aren't
+    /// part of the proof of its decision, are dropped from the decision
+    /// log, without waiting for the next local checkpoint. The proofs of
+    /// the instances decided since the last checkpoint are retained, as
+    /// recovering replicas check the requests they are handed against them.
+    pub fn instance_decided(&mut self, seq: SeqNo) {
+        self.declog.trim_superseded(seq);
+    }
+
+    /// Returns the number of decided requests retained in this log,
+    /// which will only be cleared by the next local checkpoint.
+    pub fn pending_execution_len(&self) -> usize {
+        self.decided.len()
+    }
+
+    /// Returns the requests decided after the last local checkpoint,
+    /// in the order they were delivered to the execution layer.
+    ///
+    /// Requests retained in their serialized form aren't deserialized
+    /// again just to be peeked at, so the operations themselves are
+    /// only available through `DecidedRequest::into_operation()`.
+    pub fn peek_decided(&self) -> &[DecidedRequest<O>] {
+        &self.decided[..]
+    }
+
+    // returns the requests decided in each consensus instance from
+    // `from` up to, but excluding, `to`, if every one of them was
+    // decided since the last checkpoint
+    fn batches_in_range(&self, from: SeqNo, to: SeqNo) -> Option<Vec<&[DecidedRequest<O>]>> {
+        let len = to.distance(from) as usize;
+        let first = self
+            .decided_batches
+            .iter()
+            .position(|&(seq, _)| seq == from)?;
+        let batches = self.decided_batches.get(first..first.checked_add(len)?)?;
+
+        let mut expected = from;
+        let mut ranges = Vec::with_capacity(len);
+        for (i, &(seq, start)) in batches.iter().enumerate() {
+            if seq != expected {
+                return None;
+            }
+            let end = self
+                .decided_batches
+                .get(first + i + 1)
+                .map(|&(_, end)| end)
+                .unwrap_or(self.decided.len());
+            ranges.push(&self.decided[start..end]);
+            expected = expected.next();
+        }
+        Some(ranges)
+    }
+
+    /// Returns the operations decided in the consensus instances from
+    /// `from` up to, but excluding, `to`, in the order they were
+    /// delivered to the execution layer.
+    ///
+    /// Only the instances decided since the last local checkpoint are
+    /// retained; requesting any other instance is reported as an error.
+    pub fn operations_in_range<D>(&self, from: SeqNo, to: SeqNo) -> Result<Vec<O>>
+    where
+        D: SharedData<Request = O>,
+        O: Clone,
+    {
+        self.batches_in_range(from, to)
+            .ok_or("Consensus instances not retained in the log")
+            .wrapped(ErrorKind::ConsensusLog)?
+            .into_iter()
+            .flatten()
+            .cloned()
+            .map(DecidedRequest::into_operation::<D>)
+            .collect()
+    }
+
+    /// Returns the requests decided in the consensus instances from
+    /// `from` up to, but excluding, `to`, used to catch up a replica
+    /// lagging slightly behind, if all of them are retained.
+    pub fn delta(&self, from: SeqNo, to: SeqNo) -> Option<Delta<O>>
+    where
+        O: Clone,
+    {
+        let batches = self.batches_in_range(from, to)?;
+        let batches = batches.into_iter().map(<[_]>::to_vec).collect();
+        Some(Delta::new(from, batches, self.declog.instances(from, to)))
+    }
+
+    /// Appends the requests in `delta`, received from the CST protocol,
+    /// to the requests decided since the last checkpoint, returning the
+    /// operations of those which weren't executed yet, to be replayed.
+    /// The proofs of their decisions are appended to the decision log.
+    pub fn install_delta<D>(&mut self, delta: Delta<O>) -> Result<Vec<O>>
+    where
+        D: SharedData<Request = O>,
+        O: Clone,
+    {
+        let mut executed = self.executed.clone();
+        let operations =
+            executed.unexecuted_operations::<D, _, _>(delta.batches.iter().flatten().cloned())?;
+        self.executed = executed;
+
+        let declog = delta.declog;
+        self.declog.pre_prepares.extend(declog.pre_prepares);
+        self.declog.prepares.extend(declog.prepares);
+        self.declog.commits.extend(declog.commits);
+
+        let mut seq = delta.from;
+        for batch in delta.batches {
+            self.decided_batches.push((seq, self.decided.len()));
+            self.decided_bytes += batch.iter().map(serialized_len).sum::<usize>();
+            self.decided.extend(batch);
+            self.declog.last_exec = Some(seq);
+            seq = seq.next();
+        }
+        Ok(operations)
+    }
+
     /// Update the log state, received from the CST protocol.
-    pub fn install_state(&mut self, last_seq: SeqNo, rs: RecoveryState<S, O>) {
+    ///
+    /// The requests in `executed` are those executed before the checkpoint
+    /// of `rs`, as well as the ones decided after it, once replayed.
+    pub fn install_state(
+        &mut self,
+        last_seq: SeqNo,
+        rs: RecoveryState<S, O>,
+        executed: ExecutedRequests,
+    ) {
         // FIXME: what to do with `self.deciding`..?
 
+        self.executed = executed;
         self.declog = rs.declog;
+        self.decided_bytes = rs.requests.iter().map(serialized_len).sum();
         self.decided = rs.requests;
+        // the instances the requests were decided in are unknown
+        self.decided_batches.clear();
+        self.last_checkpoint = rs.checkpoint.seq;
         self.checkpoint = CheckpointState::Complete(rs.checkpoint);
         self.curr_seq = last_seq;
     }
@@ -557,14 +1377,35 @@ impl<S, O, P> Log<S, O, P> {
         }
     */
 
+    /// Checks if a client request with sequence number `seq`, sent by
+    /// the client `from`, was already executed; check `ExecutedRequests`.
+    ///
+    /// Such requests should be dropped before they reach the
+    /// consensus layer, since they may have been replayed.
+    pub fn is_replayed(&self, from: NodeId, seq: SeqNo) -> bool {
+        self.has_decided(from, seq)
+    }
+
+    /// Checks if the request with sequence number `seq`, sent by the
+    /// client `from`, was decided.
+    ///
+    /// Decided requests are queued for execution in order, so anything
+    /// queued for execution afterwards observes their effects.
+    pub fn has_decided(&self, from: NodeId, seq: SeqNo) -> bool {
+        self.executed.contains(from, seq)
+    }
+
     /// Adds a new `message` and its respective `header` to the log.
     pub fn insert(&mut self, header: Header, message: SystemMessage<S, O, P>) {
         match message {
             SystemMessage::Request(message) => {
                 let digest = header.unique_digest();
                 let stored = StoredMessage::new(header, message);
-                self.requests.insert(digest, stored);
-                self.deciding.remove(&digest);
+                let arrival = Instant::now();
+                match self.deferred {
+                    Some(ref mut deferred) => deferred.push((digest, stored, arrival)),
+                    None => self.insert_request(digest, stored, arrival),
+                }
             }
             SystemMessage::Consensus(message) => {
                 let stored = StoredMessage::new(header, message);
@@ -579,28 +1420,178 @@ impl<S, O, P> Log<S, O, P> {
         }
     }
 
+    fn insert_request(
+        &mut self,
+        digest: Digest,
+        stored: StoredMessage<RequestMessage<O>>,
+        arrival: Instant,
+    ) {
+        // a replayed request keeps its original arrival time
+        self.arrivals.entry(digest).or_insert(arrival);
+        if stored.message().metadata().is_some() {
+            self.with_metadata += 1;
+        }
+        if let Some(replaced) = self.requests.insert(digest, stored) {
+            self.forget_request(&replaced);
+        }
+        if let Some(stored) = self.deciding.remove(&digest) {
+            self.deciding_bytes -= stored.header().payload_length();
+        }
+    }
+
+    /// Stops admitting client requests into the log, e.g. while
+    /// a checkpoint, a state transfer or a view change is ongoing.
+    ///
+    /// The requests received in the meantime are buffered, and
+    /// only become available for proposing after a call to
+    /// `resume_ingestion()`.
+    pub fn pause_ingestion(&mut self) {
+        if self.deferred.is_none() {
+            self.deferred = Some(Vec::new());
+        }
+    }
+
+    /// Resumes admitting client requests into the log, inserting
+    /// the requests buffered while ingestion was paused.
+    pub fn resume_ingestion(&mut self) {
+        let deferred = match self.deferred.take() {
+            Some(deferred) => deferred,
+            None => return,
+        };
+        for (digest, stored, arrival) in deferred {
+            self.insert_request(digest, stored, arrival);
+        }
+    }
+
+    /// Checks if the client request with the given digest, sent by
+    /// `from`, is in the log, waiting to be proposed, or in the process
+    /// of being decided.
+    ///
+    /// Unlike `has_request()`, requests deferred while the ingestion of
+    /// requests is paused are also accounted for.
+    pub fn is_request_pending(&self, from: NodeId, digest: &Digest) -> bool {
+        let sent_by = |stored: &StoredMessage<RequestMessage<O>>| stored.header().from() == from;
+        self.requests.get(digest).map(sent_by).unwrap_or(false)
+            || self.deciding.get(digest).map(sent_by).unwrap_or(false)
+            || self
+                .deferred
+                .as_ref()
+                .map(|deferred| {
+                    deferred
+                        .iter()
+                        .any(|(d, stored, _)| d == digest && sent_by(stored))
+                })
+                .unwrap_or(false)
+    }
+
+    /// Checks if the ingestion of client requests is paused.
+    pub fn is_ingestion_paused(&self) -> bool {
+        self.deferred.is_some()
+    }
+
+    /// Withdraws the client request with the given digest, if it was
+    /// sent by `from` and it wasn't taken to be proposed yet, returning
+    /// whether the request was withdrawn.
+    pub fn cancel_request(&mut self, from: NodeId, digest: &Digest) -> bool {
+        let sent_by = |stored: &StoredMessage<RequestMessage<O>>| stored.header().from() == from;
+        if let Some(ref mut deferred) = self.deferred {
+            let position = deferred
+                .iter()
+                .position(|(d, stored, _)| d == digest && sent_by(stored));
+            if let Some(i) = position {
+                deferred.remove(i);
+                return true;
+            }
+        }
+        match self.requests.get(digest) {
+            Some(stored) if sent_by(stored) => (),
+            _ => return false,
+        }
+        if let Some(stored) = self.requests.remove(digest) {
+            self.forget_request(&stored);
+            self.arrivals.remove(digest);
+        }
+        true
+    }
+
+    // updates the count of requests carrying metadata,
+    // after `stored` was taken out of `requests`
+    fn forget_request(&mut self, stored: &StoredMessage<RequestMessage<O>>) {
+        if stored.message().metadata().is_some() {
+            self.with_metadata -= 1;
+        }
+    }
+
+    // the key the request `stored`, with the given digest,
+    // is scheduled by at the instant `now`
+    fn schedule_key(
+        &self,
+        digest: &Digest,
+        stored: &StoredMessage<RequestMessage<O>>,
+        now: Instant,
+    ) -> (bool, Reverse<u32>, bool, Option<Instant>, Instant) {
+        let arrival = self.arrivals.get(digest).copied().unwrap_or(now);
+        schedule_key(stored, arrival, now, self.max_priority, self.max_wait)
+    }
+
+    // takes the next request to be proposed out of `requests`, i.e.
+    // the first one in arrival order to be scheduled the earliest
+    fn pop_request(&mut self, now: Instant) -> Option<(Digest, StoredMessage<RequestMessage<O>>)> {
+        if self.with_metadata == 0 {
+            return self.requests.pop_front();
+        }
+        let digest = self
+            .requests
+            .iter()
+            .min_by_key(|(digest, stored)| self.schedule_key(digest, stored, now))
+            .map(|(digest, _)| *digest)?;
+        let stored = self.requests.remove(&digest)?;
+        self.forget_request(&stored);
+        Some((digest, stored))
+    }
+
     /// Retrieves the next batch of requests available for proposing, if any.
+    ///
+    /// Requests carrying metadata are scheduled according to it, e.g.
+    /// higher priority requests are batched first; check `RequestMeta`.
     pub fn next_batch(&mut self) -> Option<Vec<Digest>> {
-        let (digest, stored) = self.requests.pop_front()?;
+        let now = Instant::now();
+        let (digest, stored) = self.pop_request(now)?;
+        self.deciding_bytes += stored.header().payload_length();
         self.deciding.insert(digest, stored);
         // TODO:
         // - we may include another condition here to decide on a
         // smaller batch size, so that client request latency is lower
         // - prevent non leader replicas from collecting a batch of digests,
         // as only the leader will actually propose!
-        if self.deciding.len() >= self.batch_size {
-            Some(
-                self.deciding
-                    .keys()
-                    .copied()
-                    .take(self.batch_size)
-                    .collect(),
-            )
+        let max_deciding_bytes = self.max_batch_bytes.min(self.max_deciding_bytes);
+        if self.deciding.len() >= self.batch_size || self.deciding_bytes >= max_deciding_bytes {
+            Some(self.bounded_batch(now))
         } else {
             None
         }
     }
 
+    // collects the digests of the requests being decided, stopping
+    // when either the count or the byte size limits are reached;
+    // at least one request is always included, even if it alone
+    // exceeds `max_batch_bytes`
+    fn bounded_batch(&self, now: Instant) -> Vec<Digest> {
+        let mut deciding: Vec<_> = self.deciding.iter().collect();
+        deciding.sort_by_key(|(digest, stored)| self.schedule_key(digest, stored, now));
+
+        let mut bytes = 0;
+        let mut batch = Vec::with_capacity(self.batch_size);
+        for (digest, stored) in deciding.into_iter().take(self.batch_size) {
+            bytes += stored.header().payload_length();
+            if bytes > self.max_batch_bytes && !batch.is_empty() {
+                break;
+            }
+            batch.push(*digest);
+        }
+        batch
+    }
+
     /// Retrieves a batch of requests to be proposed during a view change.
     pub fn view_change_propose(&self) -> Vec<Digest> {
         self.requests
@@ -646,22 +1637,51 @@ impl<S, O, P> Log<S, O, P> {
     where
         O: Clone,
     {
+        self.decided_batches.push((seq, self.decided.len()));
+
         let mut batch = UpdateBatch::new();
         for digest in digests {
-            let (header, message) = self
-                .deciding
-                .remove(digest)
-                .or_else(|| self.requests.remove(digest))
-                .map(StoredMessage::into_inner)
-                .ok_or(Error::simple(ErrorKind::ConsensusLog))?;
-            batch.add(header.from(), digest.clone(), message.into_inner());
-        }
+            self.arrivals.remove(digest);
+            let (header, message) = match self.deciding.remove(digest) {
+                Some(stored) => {
+                    self.deciding_bytes -= stored.header().payload_length();
+                    Some(stored)
+                }
+                None => self.requests.remove(digest).map(|stored| {
+                    self.forget_request(&stored);
+                    stored
+                }),
+            }
+            .map(StoredMessage::into_inner)
+            .ok_or(Error::simple(ErrorKind::ConsensusLog))?;
+
+            // reuse the serialized request, if it was retained, rather than
+            // cloning the operation, which can take several ms if the batch
+            // size is large, and each request also large
+            let decided = match message.serialized() {
+                Some(bytes) => DecidedRequest::Serialized(header, bytes.clone()),
+                None => DecidedRequest::Operation(header, message.clone()),
+            };
+            self.decided.push(decided);
+            self.decided_bytes += header.payload_length();
+
+            // a request decided more than once, e.g. replayed to
+            // a faulty leader, is only executed the first time
+            if !self
+                .executed
+                .insert(header.from(), message.sequence_number())
+            {
+                continue;
+            }
 
-        // TODO: optimize batch cloning, as this can take
-        // several ms if the batch size is large, and each
-        // request also large
-        for update in batch.as_ref() {
-            self.decided.push(update.operation().clone());
+            let trace_id = message.trace_id();
+            batch.add(
+                header.from(),
+                message.sequence_number(),
+                digest.clone(),
+                message.into_inner(),
+                trace_id,
+            );
         }
 
         // retrive the sequence number stored within the PRE-PREPARE message
@@ -675,9 +1695,8 @@ impl<S, O, P> Log<S, O, P> {
             // the seq number stored before the log was cleared
             self.curr_seq
         };
-        let last_seq_no_u32 = u32::from(last_seq_no);
 
-        let info = if last_seq_no_u32 > 0 && last_seq_no_u32 % PERIOD == 0 {
+        let info = if last_seq_no.distance(self.last_checkpoint) >= self.period {
             self.begin_checkpoint(last_seq_no)?
         } else {
             Info::Nil
@@ -690,17 +1709,27 @@ impl<S, O, P> Log<S, O, P> {
     }
 
     fn begin_checkpoint(&mut self, seq: SeqNo) -> Result<Info> {
+        let executed = self.executed.clone();
+        let decided = self.decided.len();
         let earlier = std::mem::replace(&mut self.checkpoint, CheckpointState::None);
         self.checkpoint = match earlier {
-            CheckpointState::None => CheckpointState::Partial { seq },
-            CheckpointState::Complete(earlier) => {
-                CheckpointState::PartialWithEarlier { seq, earlier }
-            }
+            CheckpointState::None => CheckpointState::Partial {
+                seq,
+                executed,
+                decided,
+            },
+            CheckpointState::Complete(earlier) => CheckpointState::PartialWithEarlier {
+                seq,
+                executed,
+                decided,
+                earlier,
+            },
             // FIXME: this may not be an invalid state after all; we may just be generating
             // checkpoints too fast for the execution layer to keep up, delivering the
             // hash digests of the appstate
             _ => return Err("Invalid checkpoint state detected").wrapped(ErrorKind::ConsensusLog),
         };
+        self.last_checkpoint = seq;
         Ok(Info::BeginCheckpoint)
     }
 
@@ -717,34 +1746,747 @@ impl<S, O, P> Log<S, O, P> {
             CheckpointState::Complete(_) => {
                 Err("Checkpoint already finalized").wrapped(ErrorKind::ConsensusLog)
             }
-            CheckpointState::Partial { ref seq }
-            | CheckpointState::PartialWithEarlier { ref seq, .. } => {
-                let seq = *seq;
-                self.checkpoint = CheckpointState::Complete(Checkpoint { seq, appstate });
-                self.decided.clear();
-                //
-                // NOTE: workaround bug where when we clear the log,
-                // we remove the PRE-PREPARE of an on-going request
-                //
-                // FIXME: the log should not be cleared until a request is over
-                //
-                match self.declog.pre_prepares.pop() {
-                    Some(last_pre_prepare) => {
-                        self.declog.pre_prepares.clear();
-
-                        // store the id of the last received pre-prepare,
-                        // which corresponds to the request currently being
-                        // processed
-                        self.curr_seq = last_pre_prepare.message().sequence_number();
-                    }
-                    None => {
-                        // no stored PRE-PREPARE messages, NOOP
-                    }
+            CheckpointState::Partial { .. } | CheckpointState::PartialWithEarlier { .. } => {
+                let (seq, executed, decided) =
+                    match std::mem::replace(&mut self.checkpoint, CheckpointState::None) {
+                        CheckpointState::Partial {
+                            seq,
+                            executed,
+                            decided,
+                        }
+                        | CheckpointState::PartialWithEarlier {
+                            seq,
+                            executed,
+                            decided,
+                            ..
+                        } => (seq, executed, decided),
+                        _ => unreachable!(),
+                    };
+                self.checkpoint = CheckpointState::Complete(Checkpoint {
+                    seq,
+                    appstate,
+                    executed,
+                });
+                // the requests decided while the application state was
+                // being retrieved aren't covered by the checkpoint
+                let decided = decided.min(self.decided.len());
+                self.decided_bytes -= self.decided[..decided]
+                    .iter()
+                    .map(serialized_len)
+                    .sum::<usize>();
+                self.decided.drain(..decided);
+                self.decided_batches.retain(|&(_, start)| start >= decided);
+                for (_, start) in self.decided_batches.iter_mut() {
+                    *start -= decided;
+                }
+                // store the id of the last received pre-prepare,
+                // which corresponds to the request currently being
+                // processed
+                if let Some(last_pre_prepare) = self.declog.pre_prepares.last() {
+                    self.curr_seq = last_pre_prepare.message().sequence_number();
                 }
-                self.declog.prepares.clear();
-                self.declog.commits.clear();
+                // the instances before the checkpoint are subsumed by it;
+                // the proofs of the later ones are retained, as is the one
+                // of the instance it was taken at, needed to change views
+                self.declog.trim_before(seq);
                 Ok(())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Info, Log, PERIOD};
+    use crate::bft::communication::message::{
+        ConsensusMessage, ConsensusMessageKind, Header, RequestMessage, RequestMeta, SystemMessage,
+        WireMessage,
+    };
+    use crate::bft::communication::NodeId;
+    use crate::bft::core::server::ViewInfo;
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::error::ErrorKind;
+    use crate::bft::ordering::{Orderable, SeqNo};
+    use crate::bft::testing::forge_header;
+
+    fn request_header(nonce: u64, length: u64) -> Header {
+        forge_header(4, 0, nonce, length)
+    }
+
+    #[test]
+    fn test_batch_cut_by_bytes() {
+        const BATCH_SIZE: usize = 64;
+        const MAX_BATCH_BYTES: usize = 4096;
+        const REQUEST_BYTES: u64 = 1024;
+
+        let mut log: Log<(), Vec<u8>, ()> =
+            Log::new(BATCH_SIZE, MAX_BATCH_BYTES, PERIOD, usize::MAX);
+        for nonce in 0..BATCH_SIZE as u64 {
+            let header = request_header(nonce, REQUEST_BYTES);
+            let request = RequestMessage::new(SeqNo::ZERO, vec![0; REQUEST_BYTES as usize]);
+            log.insert(header, SystemMessage::Request(request));
+        }
+
+        let batch = std::iter::repeat_with(|| log.next_batch())
+            .take(BATCH_SIZE)
+            .flatten()
+            .next()
+            .expect("No batch was cut");
+
+        assert_eq!(batch.len(), MAX_BATCH_BYTES / REQUEST_BYTES as usize);
+        assert!(batch.len() < BATCH_SIZE);
+    }
+
+    #[test]
+    fn test_deciding_bytes_force_proposal() {
+        const BATCH_SIZE: usize = 1000;
+        const MAX_BATCH_BYTES: usize = 64 * 1024;
+        const REQUEST_BYTES: u64 = 16 * 1024;
+
+        let mut log: Log<(), Vec<u8>, ()> =
+            Log::new(BATCH_SIZE, MAX_BATCH_BYTES, PERIOD, usize::MAX);
+        let requests = MAX_BATCH_BYTES / REQUEST_BYTES as usize;
+        for nonce in 0..2 * requests as u64 {
+            let header = request_header(nonce, REQUEST_BYTES);
+            let request = RequestMessage::new(SeqNo::ZERO, vec![0; REQUEST_BYTES as usize]);
+            log.insert(header, SystemMessage::Request(request));
+        }
+
+        // requests keep arriving, never filling a batch by their
+        // count, but the memory they hold forces an early proposal
+        for i in 1..requests {
+            assert!(log.next_batch().is_none());
+            assert_eq!(log.deciding_len(), i);
+            assert_eq!(log.deciding_bytes(), i * REQUEST_BYTES as usize);
+        }
+        let batch = log.next_batch().expect("No batch was proposed");
+        assert_eq!(batch.len(), requests);
+        assert_eq!(log.deciding_bytes(), MAX_BATCH_BYTES);
+        assert!(log.deciding_len() < BATCH_SIZE);
+    }
+
+    #[test]
+    fn test_max_deciding_bytes_force_proposal() {
+        const BATCH_SIZE: usize = 1000;
+        const MAX_DECIDING_BYTES: usize = 32 * 1024;
+        const REQUEST_BYTES: u64 = 8 * 1024;
+
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(BATCH_SIZE, usize::MAX, PERIOD, usize::MAX)
+            .with_max_deciding_bytes(MAX_DECIDING_BYTES);
+        let requests = MAX_DECIDING_BYTES / REQUEST_BYTES as usize;
+        for nonce in 0..requests as u64 {
+            let header = request_header(nonce, REQUEST_BYTES);
+            let request = RequestMessage::new(SeqNo::ZERO, vec![0; REQUEST_BYTES as usize]);
+            log.insert(header, SystemMessage::Request(request));
+        }
+
+        // the batch is bounded by neither its count nor its size,
+        // but the memory held by its requests is
+        for _ in 1..requests {
+            assert!(log.next_batch().is_none());
+        }
+        let batch = log.next_batch().expect("No batch was proposed");
+        assert_eq!(batch.len(), requests);
+        assert_eq!(log.deciding_bytes(), MAX_DECIDING_BYTES);
+    }
+
+    #[test]
+    fn test_higher_priority_requests_batched_first() {
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(2, usize::MAX, PERIOD, usize::MAX);
+        let priorities = [None, Some(0), Some(7), Some(3)];
+        for (nonce, priority) in priorities.iter().enumerate() {
+            let header = request_header(nonce as u64, 1);
+            let metadata = priority.map(|priority| RequestMeta::new(priority, None));
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8])
+                .with_metadata(metadata);
+            log.insert(header, SystemMessage::Request(request));
+        }
+
+        // the requests with the highest priorities are batched
+        // first, in spite of arriving after the other ones
+        assert!(log.next_batch().is_none());
+        let batch = log.next_batch().expect("No batch was cut");
+        let expected = [
+            request_header(2, 1).unique_digest(),
+            request_header(3, 1).unique_digest(),
+        ];
+        assert!(batch == expected);
+
+        // among requests of equal priority, earlier deadlines come first
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(3, usize::MAX, PERIOD, usize::MAX);
+        let deadlines = [
+            None,
+            Some(Duration::from_secs(9)),
+            Some(Duration::from_secs(1)),
+        ];
+        for (nonce, deadline) in deadlines.iter().enumerate() {
+            let header = request_header(nonce as u64, 1);
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8])
+                .with_metadata(Some(RequestMeta::new(1, *deadline)));
+            log.insert(header, SystemMessage::Request(request));
+        }
+        let batch = std::iter::repeat_with(|| log.next_batch())
+            .take(3)
+            .flatten()
+            .next()
+            .expect("No batch was cut");
+        let expected: Vec<_> = [2, 1, 0]
+            .iter()
+            .map(|&nonce| request_header(nonce, 1).unique_digest())
+            .collect();
+        assert!(batch == expected);
+
+        // priorities past the bound are as good as the bound itself,
+        // so requests carrying either are batched in arrival order
+        let mut log: Log<(), Vec<u8>, ()> =
+            Log::new(1, usize::MAX, PERIOD, usize::MAX).with_max_priority(3);
+        for (nonce, priority) in [3, u32::MAX].iter().enumerate() {
+            let header = request_header(nonce as u64, 1);
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8])
+                .with_metadata(Some(RequestMeta::new(*priority, None)));
+            log.insert(header, SystemMessage::Request(request));
+        }
+        let batch = log.next_batch().expect("No batch was cut");
+        assert!(batch == [request_header(0, 1).unique_digest()]);
+    }
+
+    #[test]
+    fn test_deadlines_relative_to_arrival() {
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(2, usize::MAX, PERIOD, usize::MAX);
+        let deadlines = [Duration::from_secs(10), Duration::from_secs(5)];
+        for (nonce, deadline) in deadlines.iter().enumerate() {
+            let header = request_header(nonce as u64, 1);
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8])
+                .with_metadata(Some(RequestMeta::new(1, Some(*deadline))));
+            log.insert(header, SystemMessage::Request(request));
+        }
+
+        // the first request arrived long before the second one,
+        // so it is due first, despite its longer deadline
+        let first = request_header(0, 1).unique_digest();
+        let arrival = Instant::now()
+            .checked_sub(Duration::from_secs(8))
+            .expect("Instant too early");
+        log.arrivals.insert(first, arrival);
+
+        assert!(log.next_batch().is_none());
+        let batch = log.next_batch().expect("No batch was cut");
+        let expected = [first, request_header(1, 1).unique_digest()];
+        assert!(batch == expected);
+    }
+
+    #[test]
+    fn test_waiting_requests_not_starved() {
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(1, usize::MAX, PERIOD, usize::MAX)
+            .with_max_request_wait(Duration::from_secs(5));
+        let metadata = [
+            None,
+            Some(RequestMeta::new(7, Some(Duration::from_secs(1)))),
+        ];
+        for (nonce, metadata) in metadata.iter().enumerate() {
+            let header = request_header(nonce as u64, 1);
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8])
+                .with_metadata(*metadata);
+            log.insert(header, SystemMessage::Request(request));
+        }
+
+        // the request without metadata has the lowest priority
+        let low = request_header(0, 1).unique_digest();
+        let high = request_header(1, 1).unique_digest();
+        let batch = log.next_batch().expect("No batch was cut");
+        assert!(batch == [high]);
+
+        // however, once it has waited for long enough,
+        // it is batched ahead of higher priority requests
+        let header = request_header(2, 1);
+        let request = RequestMessage::new(SeqNo::from(2u32), vec![2]).with_metadata(metadata[1]);
+        log.insert(header, SystemMessage::Request(request));
+        log.drop_deciding();
+        let arrival = Instant::now()
+            .checked_sub(Duration::from_secs(6))
+            .expect("Instant too early");
+        log.arrivals.insert(low, arrival);
+
+        let batch = log.next_batch().expect("No batch was cut");
+        assert!(batch == [low]);
+    }
+
+    #[test]
+    fn test_paused_ingestion_defers_requests() {
+        const REQUESTS: u64 = 4;
+
+        let mut log: Log<(), Vec<u8>, ()> =
+            Log::new(REQUESTS as usize, usize::MAX, PERIOD, usize::MAX);
+        log.pause_ingestion();
+        assert!(log.is_ingestion_paused());
+
+        for nonce in 0..REQUESTS {
+            let header = request_header(nonce, 1);
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8]);
+            log.insert(header, SystemMessage::Request(request));
+        }
+        assert!(log.next_batch().is_none());
+
+        // the deferred requests are admitted in arrival order
+        log.resume_ingestion();
+        assert!(!log.is_ingestion_paused());
+        let mut expected: Vec<_> = (0..REQUESTS)
+            .map(|nonce| request_header(nonce, 1).unique_digest())
+            .collect();
+        assert!(log.view_change_propose() == expected);
+
+        // ... and may be proposed
+        for _ in 1..REQUESTS {
+            assert!(log.next_batch().is_none());
+        }
+        let mut batch = log.next_batch().expect("No batch was cut");
+        batch.sort();
+        expected.sort();
+        assert!(batch == expected);
+        assert!(log.next_batch().is_none());
+    }
+
+    #[test]
+    fn test_quorum_certificate() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let seq = SeqNo::from(1u32);
+        let commit_to = |from: u32, seq: SeqNo, digest: u8| {
+            let header = forge_header(from, 0, from as u64, 0);
+            let digest = Digest::from_bytes(&[digest; DefaultHasher::LENGTH]).unwrap();
+            let kind = ConsensusMessageKind::Commit(digest);
+            let message = ConsensusMessage::new(seq, SeqNo::ZERO, kind);
+            (header, SystemMessage::Consensus(message))
+        };
+        let commit = |from, seq| commit_to(from, seq, 0);
+
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        for from in 0..2 {
+            let (header, message) = commit(from, seq);
+            log.insert(header, message);
+        }
+        // a duplicate vote and a vote for another instance
+        // must not complete the certificate
+        for (from, seq) in [(1, seq), (2, seq.next())] {
+            let (header, message) = commit(from, seq);
+            log.insert(header, message);
+        }
+        assert!(log.decision_log().quorum_certificate(seq, &view).is_none());
+
+        // nor may a vote for another digest
+        let (header, message) = commit_to(2, seq, 1);
+        log.insert(header, message);
+        assert!(log.decision_log().quorum_certificate(seq, &view).is_none());
+
+        let (header, message) = commit(3, seq);
+        log.insert(header, message);
+        let certificate = log
+            .decision_log()
+            .quorum_certificate(seq, &view)
+            .expect("No quorum certificate");
+
+        assert_eq!(certificate.len(), view.params().quorum());
+        let mut voters: Vec<u32> = certificate
+            .iter()
+            .map(|stored| {
+                assert_eq!(stored.message().sequence_number(), seq);
+                assert!(matches!(
+                    stored.message().kind(),
+                    ConsensusMessageKind::Commit(_)
+                ));
+                stored.header().from().into()
+            })
+            .collect();
+        voters.sort_unstable();
+        assert_eq!(voters, vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn test_decided_instances_trimmed() {
+        const INSTANCES: u32 = 5;
+        const PERIOD: u32 = 3;
+
+        let digest = Digest::from_bytes(&[0; DefaultHasher::LENGTH]).unwrap();
+        let consensus = |seq: u32, view: u32, kind| {
+            let message = ConsensusMessage::new(SeqNo::from(seq), SeqNo::from(view), kind);
+            SystemMessage::Consensus(message)
+        };
+        let sequence_numbers = |log: &Log<(), Vec<u8>, ()>| {
+            let declog = log.decision_log();
+            let mut seqs: Vec<u32> = declog
+                .pre_prepares()
+                .iter()
+                .map(|stored| stored.message().sequence_number().into())
+                .collect();
+            seqs.dedup();
+            seqs
+        };
+
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        for seq in 0..INSTANCES {
+            let nonce = u64::from(seq);
+            // a vote cast in a view which failed to decide the instance
+            let kind = ConsensusMessageKind::Prepare(digest.clone());
+            log.insert(request_header(nonce, 0), consensus(seq, 0, kind));
+            let kinds = [
+                ConsensusMessageKind::PrePrepare(Vec::new()),
+                ConsensusMessageKind::Prepare(digest.clone()),
+                ConsensusMessageKind::Commit(digest.clone()),
+            ];
+            for kind in kinds {
+                log.insert(request_header(nonce, 0), consensus(seq, 1, kind));
+            }
+
+            let request = RequestMessage::new(SeqNo::from(seq), vec![seq as u8]);
+            log.insert(request_header(nonce, 1), SystemMessage::Request(request));
+            let batch = log.next_batch().expect("No batch was cut");
+            let (info, _) = log.finalize_batch(SeqNo::from(seq), &batch).unwrap();
+            log.instance_decided(SeqNo::from(seq));
+
+            // the superseded vote is dropped right away...
+            let declog = log.decision_log();
+            assert!(declog
+                .prepares()
+                .iter()
+                .all(|stored| stored.message().view() == SeqNo::from(1u32)));
+
+            // ... while the proofs of the instances decided since the
+            // last checkpoint are retained until the next one
+            match info {
+                Info::BeginCheckpoint => {
+                    assert_eq!(sequence_numbers(&log), (0..=seq).collect::<Vec<_>>());
+                    log.finalize_checkpoint(()).unwrap();
+                    assert_eq!(sequence_numbers(&log), vec![seq]);
+                }
+                Info::Nil => assert_eq!(sequence_numbers(&log).last(), Some(&seq)),
+            }
+        }
+        assert_eq!(sequence_numbers(&log), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_pending_execution_backlog() {
+        const BATCHES: u64 = 3;
+
+        fn decide(log: &mut Log<(), Vec<u8>, ()>, nonce: u64) -> Info {
+            let header = request_header(nonce, 1);
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8]);
+            log.insert(header, SystemMessage::Request(request));
+            let batch = log.next_batch().expect("No batch was cut");
+            let (info, _) = log
+                .finalize_batch(SeqNo::from(nonce as u32), &batch)
+                .unwrap();
+            info
+        }
+
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // the backlog grows with each decided batch
+        assert_eq!(log.pending_execution_len(), 0);
+        for nonce in 0..BATCHES {
+            assert!(matches!(decide(&mut log, nonce), Info::Nil));
+            assert_eq!(log.pending_execution_len(), nonce as usize + 1);
+        }
+        assert_eq!(log.peek_decided().len(), BATCHES as usize);
+
+        // ... until it is cleared by a local checkpoint
+        let header = request_header(BATCHES, 1);
+        let pre_prepare = ConsensusMessage::new(
+            SeqNo::from(PERIOD),
+            SeqNo::ZERO,
+            ConsensusMessageKind::PrePrepare(Vec::new()),
+        );
+        log.insert(header, SystemMessage::Consensus(pre_prepare));
+        assert!(matches!(decide(&mut log, BATCHES), Info::BeginCheckpoint));
+        assert_eq!(log.pending_execution_len(), BATCHES as usize + 1);
+
+        log.finalize_checkpoint(()).unwrap();
+        assert_eq!(log.pending_execution_len(), 0);
+        assert!(log.peek_decided().is_empty());
+    }
+
+    #[test]
+    fn test_requests_executed_once() {
+        let mut log: Log<(), u64, ()> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        let decide = |log: &mut Log<(), u64, ()>, nonce: u64, seq: u32| {
+            let request = RequestMessage::new(SeqNo::from(seq), u64::from(seq));
+            log.insert(request_header(nonce, 1), SystemMessage::Request(request));
+            let batch = log.next_batch().expect("No batch was cut");
+            let (info, update) = log
+                .finalize_batch(SeqNo::from(nonce as u32), &batch)
+                .unwrap();
+            (info, update.as_ref().len())
+        };
+
+        // requests decided out of order are executed
+        assert_eq!(decide(&mut log, 0, 1).1, 1);
+        assert!(!log.has_decided(NodeId::from(4u32), SeqNo::ZERO));
+        assert_eq!(decide(&mut log, 1, 0).1, 1);
+        assert!(log.has_decided(NodeId::from(4u32), SeqNo::ZERO));
+
+        // a request decided again, with a fresh nonce, isn't executed twice,
+        // though it is retained along with the other decided requests
+        assert_eq!(decide(&mut log, 2, 1).1, 0);
+        assert_eq!(log.pending_execution_len(), 3);
+
+        // requests preceding the window of the latest executed
+        // request of a client are assumed to be executed
+        assert_eq!(decide(&mut log, 3, 100).1, 1);
+        assert!(log.is_replayed(NodeId::from(4u32), SeqNo::from(2u32)));
+        assert!(!log.is_replayed(NodeId::from(4u32), SeqNo::from(99u32)));
+
+        // the executed requests are carried in the checkpoint, which
+        // excludes the requests decided while it was being finalized
+        let pre_prepare = ConsensusMessage::new(
+            SeqNo::from(PERIOD),
+            SeqNo::ZERO,
+            ConsensusMessageKind::PrePrepare(Vec::new()),
+        );
+        log.insert(request_header(4, 0), SystemMessage::Consensus(pre_prepare));
+        assert!(matches!(decide(&mut log, 4, 99).0, Info::BeginCheckpoint));
+        decide(&mut log, 5, 98);
+        log.finalize_checkpoint(()).unwrap();
+        assert_eq!(log.pending_execution_len(), 1);
+
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let recovery_state = log.snapshot(view).unwrap();
+        let executed = recovery_state.checkpoint().executed();
+        assert!(executed.contains(NodeId::from(4u32), SeqNo::from(99u32)));
+        assert!(!executed.contains(NodeId::from(4u32), SeqNo::from(98u32)));
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_operations_in_range() {
+        use crate::bft::testing::CounterData;
+
+        const INSTANCES: u32 = 6;
+
+        // each instance decides the operation `100 + seq`
+        let mut log: Log<u64, u64, u64> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        for seq in 0..INSTANCES {
+            let kind = ConsensusMessageKind::PrePrepare(Vec::new());
+            let pre_prepare = ConsensusMessage::new(SeqNo::from(seq), SeqNo::ZERO, kind);
+            let header = request_header(u64::from(seq), 0);
+            log.insert(header, SystemMessage::Consensus(pre_prepare));
+            let header = request_header(u64::from(seq), 1);
+            let request = RequestMessage::new(SeqNo::from(seq), 100 + u64::from(seq));
+            log.insert(header, SystemMessage::Request(request));
+            let batch = log.next_batch().expect("No batch was cut");
+            log.finalize_batch(SeqNo::from(seq), &batch).unwrap();
+        }
+
+        // only the operations of the requested instances are returned
+        let (from, to) = (SeqNo::from(2u32), SeqNo::from(4u32));
+        let operations = log.operations_in_range::<CounterData>(from, to).unwrap();
+        assert_eq!(operations, [102, 103]);
+
+        // instances not decided yet are not retained
+        let err = log
+            .operations_in_range::<CounterData>(from, SeqNo::from(INSTANCES + 1))
+            .expect_err("Undecided instances returned");
+        assert_eq!(err.kind(), ErrorKind::ConsensusLog);
+
+        // a lagging log catches up with the delta of the same range
+        let mut lagging: Log<u64, u64, u64> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        let delta = log.delta(from, to).expect("Delta not retained");
+        assert!(delta.covers(from, to));
+        let replayed = lagging.install_delta::<CounterData>(delta).unwrap();
+        assert_eq!(replayed, [102, 103]);
+        let operations = lagging
+            .operations_in_range::<CounterData>(from, to)
+            .unwrap();
+        assert_eq!(operations, [102, 103]);
+        assert_eq!(
+            lagging.decision_log().last_execution(),
+            Some(SeqNo::from(3u32))
+        );
+
+        // along with the proofs of the decisions of those instances
+        let seqs: Vec<u32> = lagging
+            .decision_log()
+            .pre_prepares()
+            .iter()
+            .map(|stored| stored.message().sequence_number().into())
+            .collect();
+        assert_eq!(seqs, [2, 3]);
+    }
+
+    #[test]
+    fn test_max_bytes_exceeded() {
+        const MAX_BYTES: usize = 4096;
+        const REQUEST_BYTES: u64 = 1024;
+
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(1, usize::MAX, PERIOD, MAX_BYTES);
+        let mut nonce = 0;
+        while !log.exceeds_max_bytes() {
+            let request = RequestMessage::new(SeqNo::ZERO, vec![0; REQUEST_BYTES as usize]);
+            log.insert(
+                request_header(nonce, REQUEST_BYTES),
+                SystemMessage::Request(request),
+            );
+            let batch = log.next_batch().expect("No batch was cut");
+            log.finalize_batch(SeqNo::from(nonce as u32), &batch)
+                .unwrap();
+            nonce += 1;
+        }
+        assert_eq!(nonce, MAX_BYTES as u64 / REQUEST_BYTES + 1);
+        assert_eq!(log.decided_bytes(), nonce as usize * REQUEST_BYTES as usize);
+
+        log.drop_decided();
+        assert!(!log.exceeds_max_bytes());
+        assert_eq!(log.pending_execution_len(), 0);
+        assert!(log.decision_log().last_execution().is_none());
+    }
+
+    #[test]
+    fn test_checkpoints_across_seqno_wraparound() {
+        const PERIOD: u32 = 10;
+
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        let start = SeqNo::from(i32::MAX as u32 - 15);
+        log.last_checkpoint = start;
+
+        let mut checkpoints = Vec::new();
+        let mut seq = start;
+        for nonce in 0..45 {
+            seq = seq.next();
+
+            let pre_prepare = ConsensusMessage::new(
+                seq,
+                SeqNo::ZERO,
+                ConsensusMessageKind::PrePrepare(Vec::new()),
+            );
+            log.insert(
+                request_header(nonce, 0),
+                SystemMessage::Consensus(pre_prepare),
+            );
+            let request = RequestMessage::new(SeqNo::from(nonce as u32), vec![nonce as u8]);
+            log.insert(request_header(nonce, 1), SystemMessage::Request(request));
+
+            let batch = log.next_batch().expect("No batch was cut");
+            match log.finalize_batch(seq, &batch).unwrap() {
+                (Info::BeginCheckpoint, _) => {
+                    checkpoints.push(u32::from(seq));
+                    log.finalize_checkpoint(()).unwrap();
+                }
+                (Info::Nil, _) => (),
+            }
+        }
+
+        // we went past the wraparound
+        assert_eq!(u32::from(seq), 29);
+
+        // checkpoints are still taken every `PERIOD` instances
+        assert_eq!(checkpoints, vec![i32::MAX as u32 - 5, 4, 14, 24]);
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_serialized_requests_reused() {
+        use bytes::Bytes;
+
+        use super::DecidedRequest;
+
+        let serialize = |operation: Vec<u8>| {
+            let message: SystemMessage<(), Vec<u8>, ()> =
+                SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, operation));
+            bincode::serialize(&message).unwrap()
+        };
+
+        let operation = vec![0xba; 1024];
+        let serialized = Bytes::from(serialize(operation.clone()));
+        let header = request_header(0, serialized.len() as u64);
+        let request =
+            RequestMessage::with_serialized(SeqNo::ZERO, operation.clone(), serialized.clone());
+
+        let mut log: Log<(), Vec<u8>, ()> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        log.insert(header, SystemMessage::Request(request));
+        let batch = log.next_batch().expect("No batch was cut");
+        let (_, update) = log.finalize_batch(SeqNo::ZERO, &batch).unwrap();
+
+        assert_eq!(update.as_ref()[0].operation(), &operation);
+        match &log.decided[..] {
+            [DecidedRequest::Serialized(_, cached)] => {
+                assert_eq!(&cached[..], &serialize(operation)[..]);
+                // no copies were made since the request was inserted
+                assert_eq!(cached.as_ptr(), serialized.as_ptr());
+            }
+            _ => panic!("Serialized request was not reused"),
+        }
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_decision_log_export_round_trip() {
+        use super::DecisionLog;
+        use crate::bft::collections;
+        use crate::bft::communication::message::StoredMessage;
+        use crate::bft::communication::serialize::DigestData;
+        use crate::bft::communication::NodeId;
+        use crate::bft::crypto::hash::Context;
+        use crate::bft::crypto::signature::{KeyPair, PublicKey};
+        use crate::bft::testing::CounterData;
+
+        let keys: Vec<KeyPair> = (0..4u8)
+            .map(|i| KeyPair::from_bytes(&[i; KeyPair::SEED_LENGTH][..]).unwrap())
+            .collect();
+        let mut public_keys = collections::hash_map();
+        for (i, sk) in keys.iter().enumerate() {
+            public_keys.insert(NodeId::from(i as u32), PublicKey::from(sk.public_key()));
+        }
+
+        let mut ctx = Context::new();
+        ctx.update(b"batch");
+        let batch = ctx.finish();
+        let seq = SeqNo::from(7u32);
+        let signed = |from: u32, kind: ConsensusMessageKind| {
+            let message = SystemMessage::Consensus(ConsensusMessage::new(seq, SeqNo::ZERO, kind));
+            let mut buf = Vec::new();
+            let digest = CounterData::serialize_digest(&message, &mut buf).unwrap();
+            let (header, _) = WireMessage::new(
+                NodeId::from(from),
+                NodeId::from(0u32),
+                &buf[..],
+                from as u64,
+                Some(digest),
+                Some(&keys[from as usize]),
+            )
+            .into_inner();
+            match message {
+                SystemMessage::Consensus(m) => StoredMessage::new(header, m),
+                _ => unreachable!(),
+            }
+        };
+
+        let declog = DecisionLog {
+            last_exec: Some(SeqNo::from(6u32)),
+            pre_prepares: vec![signed(0, ConsensusMessageKind::PrePrepare(vec![batch]))],
+            prepares: (1..4)
+                .map(|from| signed(from, ConsensusMessageKind::Prepare(batch)))
+                .collect(),
+            commits: (0..4)
+                .map(|from| signed(from, ConsensusMessageKind::Commit(batch)))
+                .collect(),
+        };
+
+        let mut exported = Vec::new();
+        declog.export(&mut exported).unwrap();
+        let imported =
+            DecisionLog::import::<CounterData, _>(&mut &exported[..], &public_keys).unwrap();
+
+        assert_eq!(imported.last_execution(), declog.last_execution());
+        assert_eq!(imported.digest(), declog.digest());
+        assert_eq!(imported.commits().len(), 4);
+        for stored in imported.commits() {
+            let pk = &public_keys[&stored.header().from()];
+            assert!(stored.header().verify_signature(pk));
+        }
+
+        // a tampered payload no longer matches its signed digest
+        let last = exported.len() - 1;
+        exported[last] ^= 1;
+        assert!(DecisionLog::import::<CounterData, _>(&mut &exported[..], &public_keys).is_err());
+    }
+}
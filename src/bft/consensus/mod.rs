@@ -5,25 +5,27 @@ pub mod log;
 use std::collections::VecDeque;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
 
 use either::{Left, Right};
 
+use crate::bft::collections::{self, HashSet};
 use crate::bft::communication::message::{
-    ConsensusMessage, ConsensusMessageKind, Header, StoredMessage, SystemMessage,
+    ConsensusMessage, ConsensusMessageKind, Header, RequestMissingMessage, StoredMessage,
+    SystemMessage,
 };
-use crate::bft::crypto::hash::Digest;
-use crate::bft::cst::RecoveryState;
-use crate::bft::sync::Synchronizer;
-//use crate::bft::collections::{
-//    self,
-//    HashSet,
-//};
+use crate::bft::communication::serialize::SharedData;
 use crate::bft::communication::{Node, NodeId};
 use crate::bft::consensus::log::Log;
+use crate::bft::core::SystemParams;
+use crate::bft::crypto::hash::Digest;
+use crate::bft::cst::RecoveryState;
 use crate::bft::executable::{Reply, Request, Service, State};
 use crate::bft::ordering::{
-    tbo_advance_message_queue, tbo_pop_message, tbo_queue_message, Orderable, SeqNo,
+    tbo_advance_message_queue, tbo_drain_stale, tbo_pop_message, tbo_queue_message,
+    tbo_queued_counts, Orderable, SeqNo,
 };
+use crate::bft::sync::Synchronizer;
 
 /// Represents the status of calling `poll()` on a `Consensus`.
 pub enum ConsensusPollStatus {
@@ -53,6 +55,9 @@ pub struct TboQueue {
     pre_prepares: VecDeque<VecDeque<StoredMessage<ConsensusMessage>>>,
     prepares: VecDeque<VecDeque<StoredMessage<ConsensusMessage>>>,
     commits: VecDeque<VecDeque<StoredMessage<ConsensusMessage>>>,
+    // the nodes whose messages were dropped for lying beyond
+    // the `TBO_QUEUE_WINDOW` of the current instance
+    ahead: HashSet<NodeId>,
 }
 
 impl Orderable for TboQueue {
@@ -70,6 +75,7 @@ impl TboQueue {
             pre_prepares: VecDeque::new(),
             prepares: VecDeque::new(),
             commits: VecDeque::new(),
+            ahead: collections::hash_set(),
         }
     }
 
@@ -82,6 +88,7 @@ impl TboQueue {
     /// Advances the message queue, and updates the consensus instance id.
     fn next_instance_queue(&mut self) {
         self.curr_seq = self.curr_seq.next();
+        self.ahead.clear();
         tbo_advance_message_queue(&mut self.pre_prepares);
         tbo_advance_message_queue(&mut self.prepares);
         tbo_advance_message_queue(&mut self.commits);
@@ -89,40 +96,95 @@ impl TboQueue {
 
     /// Queues a consensus message for later processing, or drops it
     /// immediately if it pertains to an older consensus instance.
-    pub fn queue(&mut self, h: Header, m: ConsensusMessage) {
+    ///
+    /// The replicas of the current view are given by `members`; only
+    /// they are tracked when their messages lie beyond the window of
+    /// the current instance, check `fallen_behind()`.
+    pub fn queue(&mut self, h: Header, m: ConsensusMessage, members: &[NodeId]) {
         match m.kind() {
-            ConsensusMessageKind::PrePrepare(_) => self.queue_pre_prepare(h, m),
-            ConsensusMessageKind::Prepare(_) => self.queue_prepare(h, m),
-            ConsensusMessageKind::Commit(_) => self.queue_commit(h, m),
+            ConsensusMessageKind::PrePrepare(_) => self.queue_pre_prepare(h, m, members),
+            ConsensusMessageKind::Prepare(_) => self.queue_prepare(h, m, members),
+            ConsensusMessageKind::Commit(_) => self.queue_commit(h, m, members),
         }
     }
 
     /// Queues a `PRE-PREPARE` message for later processing, or drops it
     /// immediately if it pertains to an older consensus instance.
-    fn queue_pre_prepare(&mut self, h: Header, m: ConsensusMessage) {
-        tbo_queue_message(
+    fn queue_pre_prepare(&mut self, h: Header, m: ConsensusMessage, members: &[NodeId]) {
+        let from = h.from();
+        let ahead = tbo_queue_message(
             self.curr_seq,
             &mut self.pre_prepares,
             StoredMessage::new(h, m),
-        )
+        );
+        self.dropped_ahead(from, ahead, members);
     }
 
     /// Queues a `PREPARE` message for later processing, or drops it
     /// immediately if it pertains to an older consensus instance.
-    fn queue_prepare(&mut self, h: Header, m: ConsensusMessage) {
-        tbo_queue_message(self.curr_seq, &mut self.prepares, StoredMessage::new(h, m))
+    fn queue_prepare(&mut self, h: Header, m: ConsensusMessage, members: &[NodeId]) {
+        let from = h.from();
+        let ahead = tbo_queue_message(self.curr_seq, &mut self.prepares, StoredMessage::new(h, m));
+        self.dropped_ahead(from, ahead, members);
     }
 
     /// Queues a `COMMIT` message for later processing, or drops it
     /// immediately if it pertains to an older consensus instance.
-    fn queue_commit(&mut self, h: Header, m: ConsensusMessage) {
-        tbo_queue_message(self.curr_seq, &mut self.commits, StoredMessage::new(h, m))
+    fn queue_commit(&mut self, h: Header, m: ConsensusMessage, members: &[NodeId]) {
+        let from = h.from();
+        let ahead = tbo_queue_message(self.curr_seq, &mut self.commits, StoredMessage::new(h, m));
+        self.dropped_ahead(from, ahead, members);
+    }
+
+    // records `from` as being ahead of us, if its message was dropped
+    // for lying beyond the window, and it is a replica; any other
+    // node, e.g. a client, may send consensus messages at will
+    fn dropped_ahead(&mut self, from: NodeId, ahead: bool, members: &[NodeId]) {
+        if ahead && members.contains(&from) {
+            self.ahead.insert(from);
+        }
+    }
+
+    /// Checks if more than `f` replicas sent messages beyond the
+    /// `TBO_QUEUE_WINDOW` of the current consensus instance.
+    ///
+    /// At least one correct replica is then that far ahead of us,
+    /// so rather than waiting for messages that were dropped, we
+    /// should run the state transfer protocol.
+    pub fn fallen_behind(&self, f: usize) -> bool {
+        self.ahead.len() > f
+    }
+
+    /// Reports the number of `PRE-PREPARE` messages waiting in this
+    /// `TboQueue`, for each consensus instance they pertain to.
+    ///
+    /// This is useful to debug stalled consensus instances.
+    pub fn queued_pre_prepares(&self) -> Vec<(SeqNo, usize)> {
+        tbo_queued_counts(&self.pre_prepares)
+    }
+
+    /// Same as `queued_pre_prepares()`, but for `PREPARE` messages.
+    pub fn queued_prepares(&self) -> Vec<(SeqNo, usize)> {
+        tbo_queued_counts(&self.prepares)
+    }
+
+    /// Same as `queued_pre_prepares()`, but for `COMMIT` messages.
+    pub fn queued_commits(&self) -> Vec<(SeqNo, usize)> {
+        tbo_queued_counts(&self.commits)
+    }
+
+    /// Drops the queued messages pertaining to consensus instances
+    /// older than the current one, returning how many were dropped.
+    pub fn drain_stale(&mut self) -> usize {
+        tbo_drain_stale(self.curr_seq, &mut self.pre_prepares)
+            + tbo_drain_stale(self.curr_seq, &mut self.prepares)
+            + tbo_drain_stale(self.curr_seq, &mut self.commits)
     }
 }
 
 /// Repreents the current phase of the consensus protocol.
 #[derive(Debug, Copy, Clone)]
-enum ProtoPhase {
+pub enum ProtoPhase {
     /// Start of a new consensus instance.
     Init,
     /// Running the `PRE-PREPARE` phase.
@@ -139,6 +201,21 @@ enum ProtoPhase {
     Committing(usize),
 }
 
+/// When to verify the signatures of the `PREPARE` and `COMMIT`
+/// votes of a consensus instance.
+#[derive(Debug, Copy, Clone)]
+pub enum VoteVerification {
+    /// Votes are counted without checking their signatures.
+    Disabled,
+    /// Every vote is verified as soon as it is processed,
+    /// even if it is queued or dropped afterwards.
+    Eager,
+    /// A vote is only verified right before it is counted, i.e. when
+    /// it may contribute to reaching a quorum. Votes arriving after a
+    /// quorum was reached are never verified.
+    Lazy,
+}
+
 /// Contains the state of an active consensus instance, as well
 /// as future instances.
 pub struct Consensus<S: Service> {
@@ -146,19 +223,39 @@ pub struct Consensus<S: Service> {
     // but never longer
     batch_size: usize,
     phase: ProtoPhase,
+    // the instant of the last phase transition
+    phase_since: Instant,
     tbo: TboQueue,
     current: Vec<Digest>,
     current_digest: Digest,
-    //voted: HashSet<NodeId>,
+    // the replicas whose votes were counted in the running phase
+    voted: HashSet<NodeId>,
     missing_requests: VecDeque<Digest>,
     missing_swapbuf: Vec<usize>,
+    verification: VoteVerification,
+    // the view and header of the `PRE-PREPARE` accepted
+    // in the running consensus instance
+    pre_prepared: Option<(SeqNo, Header)>,
+    // whether the leader proposed two distinct batches
+    // in the running consensus instance
+    equivocated: bool,
+    // the number of vote signatures checked so far
+    #[cfg(test)]
+    verified: usize,
     _phantom: PhantomData<S>,
 }
 
 /// Status returned from processing a consensus message.
 pub enum ConsensusStatus<'a> {
-    /// A particular node tried voting twice.
+    /// A particular node tried voting twice in the same phase
+    /// of the running consensus instance; the vote is dropped.
     VotedTwice(NodeId),
+    /// The leader with the given id sent two distinct, validly signed
+    /// `PRE-PREPARE` messages for the running consensus instance, in the
+    /// same view, e.g. because two nodes believe they are the leader after
+    /// a network partition heals. No further messages are processed for
+    /// this instance, so a view change should be started.
+    Equivocation(NodeId),
     /// A `bafomet` quorum still hasn't made a decision
     /// on a client request to be executed.
     Deciding,
@@ -192,20 +289,23 @@ where
     Reply<S>: Send + 'static,
 {
     /// Starts a new consensus protocol tracker.
-    pub fn new(initial_seq_no: SeqNo, batch_size: usize) -> Self {
+    pub fn new(initial_seq_no: SeqNo, batch_size: usize, verification: VoteVerification) -> Self {
         Self {
             batch_size: 0,
+            verification,
+            #[cfg(test)]
+            verified: 0,
             _phantom: PhantomData,
             phase: ProtoPhase::Init,
+            phase_since: Instant::now(),
             missing_swapbuf: Vec::new(),
             missing_requests: VecDeque::new(),
-            //voted: collections::hash_set(),
+            pre_prepared: None,
+            equivocated: false,
+            voted: collections::hash_set(),
             tbo: TboQueue::new(initial_seq_no),
-            current_digest: Digest::from_bytes(&[0; Digest::LENGTH][..]).unwrap(),
-            current: std::iter::repeat_with(|| Digest::from_bytes(&[0; Digest::LENGTH][..]))
-                .flat_map(|d| d) // unwrap
-                .take(batch_size)
-                .collect(),
+            current_digest: Digest::EMPTY,
+            current: vec![Digest::EMPTY; batch_size],
         }
     }
 
@@ -247,7 +347,7 @@ where
         node: &mut Node<S::Data>,
     ) {
         match self.phase {
-            ProtoPhase::Init => self.phase = ProtoPhase::PrePreparing,
+            ProtoPhase::Init => self.set_phase(ProtoPhase::PrePreparing),
             _ => return,
         }
         if node.id() != synchronizer.view().leader() {
@@ -258,10 +358,75 @@ where
             synchronizer.view().sequence_number(),
             ConsensusMessageKind::PrePrepare(digests),
         ));
-        let targets = NodeId::targets(0..synchronizer.view().params().n());
+        let targets = synchronizer.view().members().iter().copied();
         node.broadcast(message, targets);
     }
 
+    /// Checks if the running consensus instance has not changed
+    /// phase for at least `timeout`, at the instant `now`.
+    ///
+    /// If this is the case, the sequence number of the stalled instance
+    /// is returned, along with its phase.
+    pub fn stalled(&self, timeout: Duration, now: Instant) -> Option<(SeqNo, ProtoPhase)> {
+        match self.phase {
+            ProtoPhase::Init => None,
+            phase if now.saturating_duration_since(self.phase_since) >= timeout => {
+                Some((self.sequence_number(), phase))
+            }
+            _ => None,
+        }
+    }
+
+    /// Requests the client requests referenced by the accepted `PRE-PREPARE`
+    /// which are still missing from our log, from the leader of the current
+    /// view. The leader must have them, since it proposed them.
+    ///
+    /// This function will only send a message if the `node` is
+    /// in the phase `ProtoPhase::PreparingRequests`.
+    pub fn request_missing(&self, synchronizer: &Synchronizer<S>, node: &mut Node<S::Data>) {
+        match self.phase {
+            ProtoPhase::PreparingRequests => (),
+            _ => return,
+        }
+        let digests = self.missing_requests.iter().cloned().collect();
+        let message = SystemMessage::RequestMissing(RequestMissingMessage::new(digests));
+        node.send(message, synchronizer.view().leader());
+    }
+
+    // forgets the `PRE-PREPARE` accepted in the previous consensus
+    // instance, or in the previous view
+    fn clear_pre_prepared(&mut self) {
+        self.pre_prepared = None;
+        self.equivocated = false;
+    }
+
+    // checks if a `PRE-PREPARE` conflicts with the one accepted in the
+    // running consensus instance, i.e. if its sender proposed a distinct
+    // batch for the same instance and view; the signatures of both are
+    // checked, such that a third party can't frame the leader
+    fn equivocates(
+        &mut self,
+        header: &Header,
+        message: &ConsensusMessage,
+        node: &Node<S::Data>,
+    ) -> bool {
+        let (view, accepted) = match self.pre_prepared {
+            Some(pre_prepared) => pre_prepared,
+            None => return false,
+        };
+        message.sequence_number() == self.sequence_number()
+            && message.view() == view
+            && header.from() == accepted.from()
+            && header.digest() != accepted.digest()
+            && self.verify_vote(header, node)
+            && self.verify_vote(&accepted, node)
+    }
+
+    fn set_phase(&mut self, phase: ProtoPhase) {
+        self.phase = phase;
+        self.phase_since = Instant::now();
+    }
+
     /// Returns true if there is a running consensus instance.
     pub fn is_deciding(&self) -> bool {
         match self.phase {
@@ -270,6 +435,18 @@ where
         }
     }
 
+    /// Lists the consensus instances currently being decided, along with
+    /// their phase, which includes the number of votes received so far.
+    ///
+    /// This is meant for debugging purposes. Since only one instance
+    /// is decided at a time, at most one entry is returned.
+    pub fn in_flight(&self) -> Vec<(SeqNo, ProtoPhase)> {
+        match self.phase {
+            ProtoPhase::Init => Vec::new(),
+            phase => vec![(self.sequence_number(), phase)],
+        }
+    }
+
     /// Create a fake `PRE-PREPARE`. This is useful during the view
     /// change protocol.
     pub fn forge_propose(
@@ -294,11 +471,9 @@ where
         log: &Log<State<S>, Request<S>, Reply<S>>,
         node: &mut Node<S::Data>,
     ) {
-        // update phase
-        self.phase = ProtoPhase::Preparing(1);
-
         // copy digests from PRE-PREPARE
         self.current_digest = digest;
+        self.clear_pre_prepared();
 
         let pre_prepares = log.decision_log().pre_prepares();
         let last = &pre_prepares[pre_prepares.len() - 1];
@@ -317,18 +492,77 @@ where
                 synchronizer.view().sequence_number(),
                 ConsensusMessageKind::Prepare(self.current_digest.clone()),
             ));
-            let targets = NodeId::targets(0..synchronizer.view().params().n());
+            let targets = synchronizer.view().members().iter().copied();
             node.broadcast(message, targets);
         }
+
+        self.enter_preparing(synchronizer, node);
+    }
+
+    // enters the `PREPARE` phase, counting the vote of the leader,
+    // cast with its `PRE-PREPARE`; in single node mode, this vote
+    // is a quorum by itself, and no `PREPARE` messages will ever
+    // arrive, so we move on to the `COMMIT` phase right away
+    fn enter_preparing(&mut self, synchronizer: &Synchronizer<S>, node: &mut Node<S::Data>) {
+        self.voted.clear();
+        self.voted.insert(synchronizer.view().leader());
+        if is_quorum(1, synchronizer.view().params()) {
+            self.enter_committing(synchronizer, node);
+        } else {
+            self.set_phase(ProtoPhase::Preparing(1));
+        }
+    }
+
+    // enters the `COMMIT` phase, broadcasting our vote
+    fn enter_committing(&mut self, synchronizer: &Synchronizer<S>, node: &mut Node<S::Data>) {
+        let message = SystemMessage::Consensus(ConsensusMessage::new(
+            self.sequence_number(),
+            synchronizer.view().sequence_number(),
+            ConsensusMessageKind::Commit(self.current_digest.clone()),
+        ));
+        let targets = synchronizer.view().members().iter().copied();
+        node.broadcast(message, targets);
+        self.voted.clear();
+        self.set_phase(ProtoPhase::Committing(0));
+    }
+
+    // checks if the signature of a vote matches the public
+    // key of the node that claims to have cast it
+    fn verify_vote(&mut self, header: &Header, node: &Node<S::Data>) -> bool {
+        #[cfg(test)]
+        {
+            self.verified += 1;
+        }
+        node.get_public_key(header.from())
+            .map(|key| header.verify_signature_hashed::<<S::Data as SharedData>::Hasher>(key))
+            .unwrap_or(false)
+    }
+
+    // checks if a vote about to be counted should be dropped,
+    // because of an invalid signature
+    fn reject_counted_vote(&mut self, header: &Header, node: &Node<S::Data>) -> bool {
+        match self.verification {
+            VoteVerification::Lazy => !self.verify_vote(header, node),
+            _ => false,
+        }
     }
 
     /// Check if we can process new consensus messages.
-    pub fn poll(&mut self, log: &Log<State<S>, Request<S>, Reply<S>>) -> ConsensusPollStatus {
+    ///
+    /// Once the requests missing from a `PRE-PREPARE` arrive, the
+    /// `PREPARE` phase is entered, which in single node mode may
+    /// already broadcast our `COMMIT` with `node`.
+    pub fn poll(
+        &mut self,
+        log: &Log<State<S>, Request<S>, Reply<S>>,
+        synchronizer: &Synchronizer<S>,
+        node: &mut Node<S::Data>,
+    ) -> ConsensusPollStatus {
         match self.phase {
             ProtoPhase::Init if self.tbo.get_queue => {
                 extract_msg!(
                     {
-                        self.phase = ProtoPhase::PrePreparing;
+                        self.set_phase(ProtoPhase::PrePreparing);
                     },
                     ConsensusPollStatus::TryProposeAndRecv,
                     &mut self.tbo.get_queue,
@@ -352,14 +586,11 @@ where
                     self.missing_requests.swap_remove_back(index);
                 }
                 if self.missing_requests.is_empty() {
-                    extract_msg!(
-                        {
-                            self.phase = ProtoPhase::Preparing(1);
-                        },
-                        ConsensusPollStatus::Recv,
-                        &mut self.tbo.get_queue,
-                        &mut self.tbo.prepares
-                    )
+                    self.enter_preparing(synchronizer, node);
+                    // process the votes queued while we were
+                    // waiting for the missing requests
+                    self.signal();
+                    self.poll(log, synchronizer, node)
                 } else {
                     ConsensusPollStatus::Recv
                 }
@@ -377,11 +608,15 @@ where
     /// Starts a new consensus instance.
     pub fn next_instance(&mut self) {
         self.tbo.next_instance_queue();
-        //self.voted.clear();
+        self.clear_pre_prepared();
+        self.voted.clear();
     }
 
     /// Sets the id of the current consensus.
     pub fn install_sequence_number(&mut self, seq: SeqNo) {
+        // peers ahead of us will be seen again, if they still are
+        self.tbo.ahead.clear();
+
         // drop old msgs
         match seq.index(self.sequence_number()) {
             // nothing to do if we are on the same seq
@@ -421,7 +656,9 @@ where
         // all messages, which is fine, until we call `install_new_phase`
         self.tbo.curr_seq = seq;
         self.tbo.get_queue = true;
-        self.phase = ProtoPhase::Init;
+        self.set_phase(ProtoPhase::Init);
+        self.clear_pre_prepared();
+        self.voted.clear();
 
         // FIXME: do we need to clear the missing requests buffers?
     }
@@ -435,24 +672,40 @@ where
         log: &mut Log<State<S>, Request<S>, Reply<S>>,
         node: &mut Node<S::Data>,
     ) -> ConsensusStatus<'a> {
-        // FIXME: make sure a replica doesn't vote twice
-        // by keeping track of who voted, and not just
-        // the amount of votes received
+        let is_vote = match message.kind() {
+            ConsensusMessageKind::PrePrepare(_) => false,
+            ConsensusMessageKind::Prepare(_) | ConsensusMessageKind::Commit(_) => true,
+        };
+        if is_vote
+            && matches!(self.verification, VoteVerification::Eager)
+            && !self.verify_vote(&header, node)
+        {
+            // drop votes with an invalid signature
+            return ConsensusStatus::Deciding;
+        }
+        if self.equivocated && message.sequence_number() == self.sequence_number() {
+            // refuse to act on either of the conflicting proposals
+            return ConsensusStatus::Deciding;
+        }
+        if !is_vote && self.equivocates(&header, &message, node) {
+            self.equivocated = true;
+            return ConsensusStatus::Equivocation(header.from());
+        }
         match self.phase {
             ProtoPhase::Init => {
                 // in the init phase, we can't do anything,
                 // queue the message for later
                 match message.kind() {
                     ConsensusMessageKind::PrePrepare(_) => {
-                        self.queue_pre_prepare(header, message);
+                        self.queue_pre_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Prepare(_) => {
-                        self.queue_prepare(header, message);
+                        self.queue_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Commit(_) => {
-                        self.queue_commit(header, message);
+                        self.queue_commit(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                 }
@@ -470,20 +723,21 @@ where
                     ConsensusMessageKind::PrePrepare(_)
                         if message.sequence_number() != self.sequence_number() =>
                     {
-                        self.queue_pre_prepare(header, message);
+                        self.queue_pre_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::PrePrepare(digests) => {
+                        self.pre_prepared = Some((message.view(), header));
                         self.batch_size = digests.len();
                         self.current_digest = header.digest().clone();
                         (&mut self.current[..digests.len()]).copy_from_slice(&digests[..]);
                     }
                     ConsensusMessageKind::Prepare(_) => {
-                        self.queue_prepare(header, message);
+                        self.queue_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Commit(_) => {
-                        self.queue_commit(header, message);
+                        self.queue_commit(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                 }
@@ -494,20 +748,22 @@ where
                         synchronizer.view().sequence_number(),
                         ConsensusMessageKind::Prepare(self.current_digest.clone()),
                     ));
-                    let targets = NodeId::targets(0..synchronizer.view().params().n());
+                    let targets = synchronizer.view().members().iter().copied();
                     node.broadcast(message, targets);
                 }
                 // add message to the log
                 log.insert(header, SystemMessage::Consensus(message));
                 // try entering preparing phase
-                for digest in self.current.iter().filter(|d| !log.has_request(d)) {
+                let current = &self.current[..self.batch_size];
+                for digest in current.iter().filter(|d| !log.has_request(d)) {
                     self.missing_requests.push_back(digest.clone());
                 }
-                self.phase = if self.missing_requests.is_empty() {
-                    ProtoPhase::Preparing(1)
+                if self.missing_requests.is_empty() {
+                    self.enter_preparing(synchronizer, node);
                 } else {
-                    ProtoPhase::PreparingRequests
-                };
+                    self.set_phase(ProtoPhase::PreparingRequests);
+                    self.request_missing(synchronizer, node);
+                }
                 ConsensusStatus::Deciding
             }
             ProtoPhase::PreparingRequests => {
@@ -515,15 +771,15 @@ where
                 // queue the message for later
                 match message.kind() {
                     ConsensusMessageKind::PrePrepare(_) => {
-                        self.queue_pre_prepare(header, message);
+                        self.queue_pre_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Prepare(_) => {
-                        self.queue_prepare(header, message);
+                        self.queue_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Commit(_) => {
-                        self.queue_commit(header, message);
+                        self.queue_commit(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                 }
@@ -533,7 +789,7 @@ where
                 // or in the same seq as the message
                 let i = match message.kind() {
                     ConsensusMessageKind::PrePrepare(_) => {
-                        self.queue_pre_prepare(header, message);
+                        self.queue_pre_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Prepare(_)
@@ -549,12 +805,20 @@ where
                     ConsensusMessageKind::Prepare(_)
                         if message.sequence_number() != self.sequence_number() =>
                     {
-                        self.queue_prepare(header, message);
+                        self.queue_prepare(header, message, synchronizer.view().members());
+                        return ConsensusStatus::Deciding;
+                    }
+                    ConsensusMessageKind::Prepare(_) if self.reject_counted_vote(&header, node) => {
+                        // drop msg with an invalid signature
                         return ConsensusStatus::Deciding;
                     }
+                    ConsensusMessageKind::Prepare(_) if !self.voted.insert(header.from()) => {
+                        // drop repeated votes of the same replica
+                        return ConsensusStatus::VotedTwice(header.from());
+                    }
                     ConsensusMessageKind::Prepare(_) => i + 1,
                     ConsensusMessageKind::Commit(_) => {
-                        self.queue_commit(header, message);
+                        self.queue_commit(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                 };
@@ -562,18 +826,11 @@ where
                 log.insert(header, SystemMessage::Consensus(message));
                 // check if we have gathered enough votes,
                 // and transition to a new phase
-                self.phase = if i == synchronizer.view().params().quorum() {
-                    let message = SystemMessage::Consensus(ConsensusMessage::new(
-                        self.sequence_number(),
-                        synchronizer.view().sequence_number(),
-                        ConsensusMessageKind::Commit(self.current_digest.clone()),
-                    ));
-                    let targets = NodeId::targets(0..synchronizer.view().params().n());
-                    node.broadcast(message, targets);
-                    ProtoPhase::Committing(0)
+                if is_quorum(i, synchronizer.view().params()) {
+                    self.enter_committing(synchronizer, node);
                 } else {
-                    ProtoPhase::Preparing(i)
-                };
+                    self.set_phase(ProtoPhase::Preparing(i));
+                }
                 ConsensusStatus::Deciding
             }
             ProtoPhase::Committing(i) => {
@@ -581,11 +838,11 @@ where
                 // or in the same seq as the message
                 let i = match message.kind() {
                     ConsensusMessageKind::PrePrepare(_) => {
-                        self.queue_pre_prepare(header, message);
+                        self.queue_pre_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Prepare(_) => {
-                        self.queue_prepare(header, message);
+                        self.queue_prepare(header, message, synchronizer.view().members());
                         return ConsensusStatus::Deciding;
                     }
                     ConsensusMessageKind::Commit(_)
@@ -601,22 +858,30 @@ where
                     ConsensusMessageKind::Commit(_)
                         if message.sequence_number() != self.sequence_number() =>
                     {
-                        self.queue_commit(header, message);
+                        self.queue_commit(header, message, synchronizer.view().members());
+                        return ConsensusStatus::Deciding;
+                    }
+                    ConsensusMessageKind::Commit(_) if self.reject_counted_vote(&header, node) => {
+                        // drop msg with an invalid signature
                         return ConsensusStatus::Deciding;
                     }
+                    ConsensusMessageKind::Commit(_) if !self.voted.insert(header.from()) => {
+                        // drop repeated votes of the same replica
+                        return ConsensusStatus::VotedTwice(header.from());
+                    }
                     ConsensusMessageKind::Commit(_) => i + 1,
                 };
                 // add message to the log
                 log.insert(header, SystemMessage::Consensus(message));
                 // check if we have gathered enough votes,
                 // and transition to a new phase
-                if i == synchronizer.view().params().quorum() {
+                if is_quorum(i, synchronizer.view().params()) {
                     // we have reached a decision,
                     // notify core protocol
-                    self.phase = ProtoPhase::Init;
+                    self.set_phase(ProtoPhase::Init);
                     ConsensusStatus::Decided(&self.current[..self.batch_size])
                 } else {
-                    self.phase = ProtoPhase::Committing(i);
+                    self.set_phase(ProtoPhase::Committing(i));
                     ConsensusStatus::Deciding
                 }
             }
@@ -624,6 +889,13 @@ where
     }
 }
 
+// checks if `votes` for the value proposed in the
+// running consensus instance amount to a quorum
+#[inline]
+fn is_quorum(votes: usize, params: &SystemParams) -> bool {
+    votes == params.quorum()
+}
+
 impl<S> Deref for Consensus<S>
 where
     S: Service + Send + 'static,
@@ -651,3 +923,716 @@ where
         &mut self.tbo
     }
 }
+
+#[cfg(all(test, feature = "serialize_serde"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Consensus, ConsensusPollStatus, ConsensusStatus, ProtoPhase, VoteVerification};
+    use crate::bft::async_runtime as rt;
+    use crate::bft::communication::message::{
+        ConsensusMessage, ConsensusMessageKind, ForwardedRequestsMessage, Message, RequestMessage,
+        SystemMessage, WireMessage,
+    };
+    use crate::bft::communication::{Node, NodeId};
+    use crate::bft::consensus::log::{Log, PERIOD};
+    use crate::bft::core::server::ViewInfo;
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::crypto::signature::KeyPair;
+    use crate::bft::executable::Service;
+    use crate::bft::ordering::{Orderable, SeqNo, TBO_QUEUE_WINDOW};
+    use crate::bft::sync::Synchronizer;
+    use crate::bft::testing::{forge_header, init_runtime, CounterData, CounterService};
+    use crate::bft::timeouts::Timeouts;
+
+    #[test]
+    fn test_single_node_decide_and_execute() {
+        init_runtime();
+
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        let mut service = CounterService;
+        let mut state = service.initial_state().unwrap();
+
+        rt::block_on(async {
+            // deliver a client request to ourselves
+            node.send(
+                SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+                id,
+            );
+            let digests = match node.receive().await.unwrap() {
+                Message::System(header, message @ SystemMessage::Request(_)) => {
+                    log.insert(header, message);
+                    log.next_batch().expect("No batch was cut")
+                }
+                _ => panic!("Expected a client request"),
+            };
+
+            // our own vote should be enough to decide
+            consensus.propose(digests, &synchronizer, &mut node);
+            let decided = loop {
+                let (header, message) = match node.receive().await.unwrap() {
+                    Message::System(header, SystemMessage::Consensus(message)) => (header, message),
+                    _ => panic!("Expected a consensus message"),
+                };
+                let status =
+                    consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+                match status {
+                    ConsensusStatus::Decided(digests) => break digests.to_vec(),
+                    ConsensusStatus::Deciding => (),
+                    ConsensusStatus::VotedTwice(_) => panic!("Voted twice"),
+                    ConsensusStatus::Equivocation(_) => panic!("Equivocation"),
+                }
+            };
+
+            let seq = consensus.sequence_number();
+            let (_, batch) = log.finalize_batch(seq, &decided).unwrap();
+            for update in batch.into_inner() {
+                let (_, _, request) = update.into_inner();
+                assert_eq!(service.update(&mut state, request), 42);
+            }
+        });
+
+        assert_eq!(state, 42);
+    }
+
+    #[test]
+    fn test_single_node_missing_requests() {
+        init_runtime();
+
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // the proposed client request isn't in our log yet
+        let mut other_log: Log<u64, u64, u64> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        other_log.insert(
+            forge_header(0, 0, 0, 0),
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+        );
+        let digests = other_log.next_batch().expect("No batch was cut");
+
+        let kind = ConsensusMessageKind::PrePrepare(digests.clone());
+        consensus.queue(
+            forge_header(0, 0, 1, 0),
+            ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind),
+            synchronizer.view().members(),
+        );
+        consensus.signal();
+        let (header, message) = match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(header, message) => (header, message),
+            _ => panic!("Expected a PRE-PREPARE"),
+        };
+        consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+        assert!(matches!(consensus.phase, ProtoPhase::PreparingRequests));
+
+        // no `PREPARE` will ever arrive, but our own vote is a
+        // quorum, so we commit as soon as the request is logged
+        for stored in other_log.clone_requests(&digests) {
+            let (header, message) = stored.into_inner();
+            log.insert(header, SystemMessage::Request(message));
+        }
+        assert!(matches!(
+            consensus.poll(&log, &synchronizer, &mut node),
+            ConsensusPollStatus::Recv
+        ));
+        assert!(matches!(consensus.phase, ProtoPhase::Committing(0)));
+
+        rt::block_on(async {
+            loop {
+                let (header, message) = match node.receive().await.unwrap() {
+                    Message::System(header, SystemMessage::Consensus(message)) => (header, message),
+                    _ => continue,
+                };
+                let status =
+                    consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+                match status {
+                    ConsensusStatus::Decided(decided) => {
+                        assert!(decided == &digests[..]);
+                        break;
+                    }
+                    _ => panic!("Expected a decision"),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_consensus_stall_detected() {
+        const WATCHDOG: Duration = Duration::from_secs(10);
+
+        // we are the leader, so we never broadcast a `PREPARE`
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        log.insert(
+            forge_header(0, 0, 0, 0),
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+        );
+        let digests = log.next_batch().expect("No batch was cut");
+
+        // accept a `PRE-PREPARE`, entering the `PREPARE` phase
+        let kind = ConsensusMessageKind::PrePrepare(digests);
+        consensus.queue(
+            forge_header(0, 0, 1, 0),
+            ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind),
+            synchronizer.view().members(),
+        );
+        consensus.signal();
+        let (header, message) = match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(header, message) => (header, message),
+            _ => panic!("Expected a PRE-PREPARE"),
+        };
+        let status = consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+        assert!(matches!(status, ConsensusStatus::Deciding));
+
+        // no further votes arrive
+        assert!(consensus.stalled(WATCHDOG, Instant::now()).is_none());
+        match consensus.stalled(WATCHDOG, Instant::now() + WATCHDOG) {
+            Some((seq, ProtoPhase::Preparing(1))) => assert_eq!(seq, SeqNo::ZERO),
+            _ => panic!("Stalled consensus instance not detected"),
+        }
+    }
+
+    #[test]
+    fn test_in_flight_instances() {
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        assert!(consensus.in_flight().is_empty());
+
+        log.insert(
+            forge_header(0, 0, 0, 0),
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+        );
+        let digests = log.next_batch().expect("No batch was cut");
+        let kind = ConsensusMessageKind::PrePrepare(digests);
+        consensus.queue(
+            forge_header(0, 0, 1, 0),
+            ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind),
+            synchronizer.view().members(),
+        );
+        consensus.signal();
+        let (header, message) = match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(header, message) => (header, message),
+            _ => panic!("Expected a PRE-PREPARE"),
+        };
+        consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+
+        // the reported instance matches the tracked state
+        match &consensus.in_flight()[..] {
+            [(seq, ProtoPhase::Preparing(1))] => {
+                assert_eq!(*seq, consensus.sequence_number());
+                assert!(consensus.is_deciding());
+            }
+            _ => panic!("Expected a single instance in the PREPARE phase"),
+        }
+    }
+
+    #[test]
+    fn test_tbo_queue_diagnostics() {
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let header = forge_header(1, 0, 0, 0);
+        let digest = Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap();
+        let message = |seq: u32, kind| ConsensusMessage::new(SeqNo::from(seq), SeqNo::ZERO, kind);
+
+        // queue messages of future instances, out of order
+        let messages = [
+            message(3, ConsensusMessageKind::Commit(digest)),
+            message(1, ConsensusMessageKind::Prepare(digest)),
+            message(2, ConsensusMessageKind::PrePrepare(Vec::new())),
+            message(1, ConsensusMessageKind::Prepare(digest)),
+        ];
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        for m in messages {
+            consensus.queue(header, m, view.members());
+        }
+
+        assert_eq!(consensus.queued_pre_prepares(), [(SeqNo::from(2u32), 1)]);
+        assert_eq!(consensus.queued_prepares(), [(SeqNo::from(1u32), 2)]);
+        assert_eq!(consensus.queued_commits(), [(SeqNo::from(3u32), 1)]);
+        assert_eq!(consensus.drain_stale(), 0);
+
+        // jump ahead, as if the sequence number was installed
+        // without dropping the messages of skipped instances
+        consensus.tbo.curr_seq = SeqNo::from(2u32);
+        assert_eq!(consensus.drain_stale(), 2);
+        assert!(consensus.queued_prepares().is_empty());
+        assert_eq!(consensus.queued_pre_prepares(), [(SeqNo::from(2u32), 1)]);
+        assert_eq!(consensus.queued_commits(), [(SeqNo::from(3u32), 1)]);
+
+        // the queues were realigned with the current instance,
+        // so its `PRE-PREPARE` is the next message polled
+        let log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        consensus.signal();
+        match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(_, message) => {
+                assert_eq!(message.sequence_number(), SeqNo::from(2u32));
+                assert!(matches!(
+                    message.kind(),
+                    ConsensusMessageKind::PrePrepare(_)
+                ));
+            }
+            _ => panic!("Expected the PRE-PREPARE of instance 2"),
+        }
+    }
+
+    #[test]
+    fn test_fallen_behind() {
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let digest = Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap();
+        let ahead = SeqNo::from(TBO_QUEUE_WINDOW as u32);
+        let message =
+            |seq| ConsensusMessage::new(seq, SeqNo::ZERO, ConsensusMessageKind::Commit(digest));
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        // clients aren't replicas, so no matter how many of them
+        // send messages beyond the window, we haven't fallen behind
+        for client in 1000..1004 {
+            consensus.queue(
+                forge_header(client, 0, 0, 0),
+                message(ahead),
+                view.members(),
+            );
+        }
+        assert!(!consensus.fallen_behind(1));
+
+        // a single node may be faulty, even if it sends us
+        // a couple of messages beyond the window
+        consensus.queue(forge_header(1, 0, 0, 0), message(ahead), view.members());
+        consensus.queue(
+            forge_header(1, 0, 1, 0),
+            message(ahead.next()),
+            view.members(),
+        );
+        assert!(!consensus.fallen_behind(1));
+
+        // messages within the window don't count
+        consensus.queue(
+            forge_header(2, 0, 0, 0),
+            message(SeqNo::from(1u32)),
+            view.members(),
+        );
+        assert!(!consensus.fallen_behind(1));
+
+        consensus.queue(forge_header(3, 0, 0, 0), message(ahead), view.members());
+        assert!(consensus.fallen_behind(1));
+
+        // recovering the state of our peers resets the count
+        consensus.install_sequence_number(ahead);
+        assert!(!consensus.fallen_behind(1));
+    }
+
+    #[test]
+    fn test_lazy_vote_verification() {
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        // every replica shares the same key pair
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let pk = node.get_public_key(id).unwrap().clone();
+        for other in 1..4u32 {
+            node.insert_peer_key(NodeId::from(other), pk.clone());
+        }
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Lazy);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        let sign_header = |from: u32, nonce| {
+            let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+            let from = NodeId::from(from);
+            let (header, _) = WireMessage::new(from, id, &[], nonce, None, Some(&sk)).into_inner();
+            header
+        };
+
+        log.insert(
+            forge_header(0, 0, 0, 0),
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+        );
+        let digests = log.next_batch().expect("No batch was cut");
+        let kind = ConsensusMessageKind::PrePrepare(digests);
+        consensus.queue(
+            forge_header(0, 0, 1, 0),
+            ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind),
+            synchronizer.view().members(),
+        );
+        consensus.signal();
+        let (header, message) = match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(header, message) => (header, message),
+            _ => panic!("Expected a PRE-PREPARE"),
+        };
+        consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(1)));
+        assert_eq!(consensus.verified, 0);
+
+        // returns whether the vote was dropped for being repeated
+        let digest = consensus.current_digest.clone();
+        let mut vote = |consensus: &mut Consensus<CounterService>, header, kind| {
+            let message = ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind);
+            let status =
+                consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+            matches!(status, ConsensusStatus::VotedTwice(_))
+        };
+        let prepare = || ConsensusMessageKind::Prepare(digest.clone());
+        let commit = || ConsensusMessageKind::Commit(digest.clone());
+
+        // a vote with an invalid signature is verified, but never counted
+        assert!(!vote(&mut consensus, forge_header(1, 0, 2, 0), prepare()));
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(1)));
+        assert_eq!(consensus.verified, 1);
+
+        // the leader already voted with its `PRE-PREPARE`, and
+        // neither it nor any other replica may vote again
+        assert!(vote(&mut consensus, sign_header(0, 3), prepare()));
+        assert!(!vote(&mut consensus, sign_header(1, 4), prepare()));
+        assert!(vote(&mut consensus, sign_header(1, 5), prepare()));
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(2)));
+
+        // every counted vote is verified
+        assert!(!vote(&mut consensus, sign_header(2, 6), prepare()));
+        assert!(matches!(consensus.phase, ProtoPhase::Committing(0)));
+        assert_eq!(consensus.verified, 5);
+
+        // a surplus `PREPARE`, arriving after the quorum, is not verified
+        assert!(!vote(&mut consensus, sign_header(3, 7), prepare()));
+        assert_eq!(consensus.verified, 5);
+
+        // the votes of the `PREPARE` phase don't carry over
+        assert!(!vote(&mut consensus, sign_header(1, 8), commit()));
+        assert!(vote(&mut consensus, sign_header(1, 9), commit()));
+        assert!(matches!(consensus.phase, ProtoPhase::Committing(1)));
+        for from in 2..4 {
+            assert!(!vote(
+                &mut consensus,
+                sign_header(from, 10 + from as u64),
+                commit()
+            ));
+        }
+        assert!(matches!(consensus.phase, ProtoPhase::Init));
+        assert_eq!(consensus.verified, 9);
+
+        // neither is a surplus `COMMIT`, arriving after the decision
+        assert!(!vote(&mut consensus, sign_header(0, 14), commit()));
+        assert_eq!(consensus.verified, 9);
+    }
+
+    #[test]
+    fn test_eager_vote_dedup() {
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let pk = node.get_public_key(id).unwrap().clone();
+        node.insert_peer_key(NodeId::from(1u32), pk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Eager);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        let sign_header = |from: u32, nonce| {
+            let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+            let from = NodeId::from(from);
+            let (header, _) = WireMessage::new(from, id, &[], nonce, None, Some(&sk)).into_inner();
+            header
+        };
+
+        log.insert(
+            sign_header(0, 0),
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+        );
+        let digests = log.next_batch().expect("No batch was cut");
+        let kind = ConsensusMessageKind::PrePrepare(digests);
+        let message = ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind);
+        consensus.queue(sign_header(0, 1), message, synchronizer.view().members());
+        consensus.signal();
+        let (header, message) = match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(header, message) => (header, message),
+            _ => panic!("Expected a PRE-PREPARE"),
+        };
+        consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(1)));
+
+        // a replica voting twice is only counted once
+        let digest = consensus.current_digest.clone();
+        for nonce in 2..4 {
+            let kind = ConsensusMessageKind::Prepare(digest.clone());
+            let message = ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind);
+            let header = sign_header(1, nonce);
+            consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+        }
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(2)));
+        assert_eq!(consensus.verified, 2);
+    }
+
+    #[test]
+    fn test_leader_equivocation_detected() {
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // the headers of two proposals of the leader, with distinct digests
+        let proposal_header = |nonce, byte, signed: bool| {
+            let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+            let digest = Digest::from_bytes(&[byte; DefaultHasher::LENGTH][..]).unwrap();
+            let sk = if signed { Some(&sk) } else { None };
+            let (header, _) = WireMessage::new(id, id, &[], nonce, Some(digest), sk).into_inner();
+            header
+        };
+
+        log.insert(
+            proposal_header(0, 0, false),
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+        );
+        let digests = log.next_batch().expect("No batch was cut");
+        let pre_prepare = || {
+            ConsensusMessage::new(
+                SeqNo::ZERO,
+                SeqNo::ZERO,
+                ConsensusMessageKind::PrePrepare(digests.clone()),
+            )
+        };
+
+        // accept the first proposal
+        consensus.queue(
+            proposal_header(1, 1, true),
+            pre_prepare(),
+            synchronizer.view().members(),
+        );
+        consensus.signal();
+        let (header, message) = match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(header, message) => (header, message),
+            _ => panic!("Expected a PRE-PREPARE"),
+        };
+        let status = consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+        assert!(matches!(status, ConsensusStatus::Deciding));
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(1)));
+
+        // a conflicting proposal with a forged signature doesn't frame the leader
+        let status = consensus.process_message(
+            proposal_header(2, 2, false),
+            pre_prepare(),
+            &synchronizer,
+            &mut log,
+            &mut node,
+        );
+        assert!(matches!(status, ConsensusStatus::Deciding));
+
+        // nor does a proposal matching the accepted one
+        let status = consensus.process_message(
+            proposal_header(3, 1, true),
+            pre_prepare(),
+            &synchronizer,
+            &mut log,
+            &mut node,
+        );
+        assert!(matches!(status, ConsensusStatus::Deciding));
+
+        // the leader equivocates
+        let status = consensus.process_message(
+            proposal_header(4, 2, true),
+            pre_prepare(),
+            &synchronizer,
+            &mut log,
+            &mut node,
+        );
+        match status {
+            ConsensusStatus::Equivocation(leader) => assert_eq!(leader, id),
+            _ => panic!("Equivocation not detected"),
+        }
+
+        // the instance no longer makes progress, and the
+        // equivocation is only reported once
+        let digest = consensus.current_digest.clone();
+        for nonce in 5..8 {
+            let status = consensus.process_message(
+                proposal_header(nonce, 1, true),
+                ConsensusMessage::new(
+                    SeqNo::ZERO,
+                    SeqNo::ZERO,
+                    ConsensusMessageKind::Prepare(digest.clone()),
+                ),
+                &synchronizer,
+                &mut log,
+                &mut node,
+            );
+            assert!(matches!(status, ConsensusStatus::Deciding));
+        }
+        let status = consensus.process_message(
+            proposal_header(8, 3, true),
+            pre_prepare(),
+            &synchronizer,
+            &mut log,
+            &mut node,
+        );
+        assert!(matches!(status, ConsensusStatus::Deciding));
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(1)));
+
+        // the next instance starts afresh
+        consensus.next_instance();
+        assert!(!consensus.equivocated);
+        assert!(consensus.pre_prepared.is_none());
+    }
+
+    #[test]
+    fn test_missing_requests_pulled() {
+        init_runtime();
+
+        // we are the leader, so the request for the missing
+        // client requests is delivered to ourselves
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // only a peer has the proposed client request
+        let mut peer_log: Log<u64, u64, u64> = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        peer_log.insert(
+            forge_header(0, 0, 0, 0),
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+        );
+        let digests = peer_log.next_batch().expect("No batch was cut");
+
+        let kind = ConsensusMessageKind::PrePrepare(digests.clone());
+        consensus.queue(
+            forge_header(0, 0, 1, 0),
+            ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind),
+            synchronizer.view().members(),
+        );
+        consensus.signal();
+        let (header, message) = match consensus.poll(&log, &synchronizer, &mut node) {
+            ConsensusPollStatus::NextMessage(header, message) => (header, message),
+            _ => panic!("Expected a PRE-PREPARE"),
+        };
+        consensus.process_message(header, message, &synchronizer, &mut log, &mut node);
+        assert!(matches!(consensus.phase, ProtoPhase::PreparingRequests));
+
+        rt::block_on(async {
+            let requested = match node.receive().await.unwrap() {
+                Message::System(_, SystemMessage::RequestMissing(message)) => message,
+                _ => panic!("Expected a request for the missing client requests"),
+            };
+            assert!(requested.digests() == &digests[..]);
+
+            // the peer replies with the client request
+            for stored in peer_log.clone_requests(requested.digests()) {
+                let (header, message) = stored.into_inner();
+                log.insert(header, SystemMessage::Request(message));
+            }
+        });
+
+        // a `PREPARE` from a peer is now processed
+        let kind = ConsensusMessageKind::Prepare(consensus.current_digest.clone());
+        consensus.queue(
+            forge_header(0, 0, 2, 0),
+            ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind),
+            synchronizer.view().members(),
+        );
+        assert!(matches!(
+            consensus.poll(&log, &synchronizer, &mut node),
+            ConsensusPollStatus::NextMessage(_, _)
+        ));
+        assert!(matches!(consensus.phase, ProtoPhase::Preparing(1)));
+    }
+
+    #[test]
+    fn test_missing_requests_served() {
+        init_runtime();
+
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let timeouts = Timeouts::<CounterService>::new(node.master_channel());
+        let mut synchronizer =
+            Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+
+        // the serving replica holds a signed client request,
+        // while the requesting replica has an empty log
+        let mut serving_log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        let mut requesting_log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        rt::block_on(async {
+            node.send(
+                SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 42)),
+                id,
+            );
+            match node.receive().await.unwrap() {
+                Message::System(header, message @ SystemMessage::Request(_)) => {
+                    serving_log.insert(header, message);
+                }
+                _ => panic!("Expected a client request"),
+            }
+        });
+        let digests = serving_log.next_batch().expect("No batch was cut");
+
+        // a request with a forged signature is also served
+        let forged = forge_header(0, 0, 1, 0);
+        let forged_digest = forged.unique_digest();
+        serving_log.insert(
+            forged,
+            SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 666)),
+        );
+
+        let mut requested = digests.clone();
+        requested.push(forged_digest.clone());
+        let requests = serving_log.clone_requests(&requested);
+        assert_eq!(requests.len(), 2);
+
+        // only the genuine request is accepted by the requesting replica
+        synchronizer.watch_forwarded_requests(
+            ForwardedRequestsMessage::new(requests),
+            &timeouts,
+            &mut requesting_log,
+            &node,
+        );
+        assert!(requesting_log.has_request(&digests[0]));
+        assert!(!requesting_log.has_request(&forged_digest));
+    }
+}
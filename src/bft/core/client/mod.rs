@@ -6,47 +6,82 @@ use std::sync::Arc;
 use std::task::{Context, Poll, Waker};
 use std::time::{Duration, Instant};
 
+use futures_timer::Delay;
 use parking_lot::Mutex;
 
 use super::SystemParams;
 
 use crate::bft::async_runtime as rt;
 use crate::bft::collections::{self, HashMap};
-use crate::bft::communication::message::{Message, RequestMessage, SystemMessage};
-use crate::bft::communication::serialize::SharedData;
+use crate::bft::communication::message::{
+    CancelRequestMessage, Message, QueryStatusMessage, QueryStatusReplyMessage, ReadRequestMessage,
+    RedirectMessage, ReplyMessage, RequestMessage, RequestMeta, RequestStatus, SystemMessage,
+    TraceId,
+};
+use crate::bft::communication::reconnect::Backoff;
+use crate::bft::communication::serialize::{Buf, DigestData, SharedData};
+use crate::bft::communication::shard::ShardId;
 use crate::bft::communication::{Node, NodeConfig, NodeId, SendNode};
 use crate::bft::crypto::hash::Digest;
 use crate::bft::error::*;
-
-struct ClientData<P> {
+use crate::bft::ordering::{Orderable, SeqNo};
+
+struct ClientData<O, P> {
+    // the sequence number of the next request, and
+    // that of the latest request, if any
+    next_seq: Mutex<(SeqNo, Option<SeqNo>)>,
+    // the update requests awaiting their replies, keyed by their
+    // unique digests, to be sent again if replicas throttle them
+    pending: Mutex<HashMap<Digest, PendingRequest<O>>>,
+    // the digests of the requests sent again, after a replica
+    // redirected or throttled them, mapped to the digests of
+    // the original requests
+    resent: Mutex<HashMap<Digest, Digest>>,
     wakers: Mutex<HashMap<Digest, Waker>>,
     ready: Mutex<HashMap<Digest, P>>,
+    // the statuses of the requests being queried, which
+    // are `None` until enough replicas agree on them
+    statuses: Mutex<HashMap<Digest, Option<RequestStatus<P>>>>,
+    status_wakers: Mutex<HashMap<Digest, Waker>>,
+    // the replica update requests are sent to, if
+    // requests aren't sent to every replica
+    leader: Mutex<Option<NodeId>>,
+    // the instant before which no update requests are sent,
+    // after a replica throttled one of our requests
+    backoff_until: Mutex<Option<Instant>>,
+    // the ids of the replicas of the system
+    members: Vec<NodeId>,
+}
+
+struct PendingRequest<O> {
+    request: RequestMessage<O>,
+    // whether the request is about to be sent again,
+    // after a replica throttled it
+    resending: bool,
 }
 
 /// Represents a client node in `bafomet`.
 // TODO: maybe make the clone impl more efficient
 pub struct Client<D: SharedData> {
-    data: Arc<ClientData<D::Reply>>,
-    params: SystemParams,
+    data: Arc<ClientData<D::Request, D::Reply>>,
     node: SendNode<D>,
 }
 
 impl<D: SharedData> Clone for Client<D> {
     fn clone(&self) -> Self {
         Self {
-            params: self.params,
             node: self.node.clone(),
             data: Arc::clone(&self.data),
         }
     }
 }
 
-struct ClientRequestFut<'a, P> {
+struct ClientRequestFut<'a, O, P> {
     digest: Digest,
-    data: &'a ClientData<P>,
+    data: &'a ClientData<O, P>,
 }
 
-impl<'a, P> Future for ClientRequestFut<'a, P> {
+impl<'a, O, P> Future for ClientRequestFut<'a, O, P> {
     type Output = P;
 
     // TODO: maybe make this impl more efficient;
@@ -71,9 +106,67 @@ impl<'a, P> Future for ClientRequestFut<'a, P> {
     }
 }
 
+struct StatusQueryFut<'a, O, P> {
+    digest: Digest,
+    data: &'a ClientData<O, P>,
+}
+
+impl<'a, O, P> Future for StatusQueryFut<'a, O, P> {
+    type Output = RequestStatus<P>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<RequestStatus<P>> {
+        {
+            let mut statuses = self.data.statuses.lock();
+            if let Some(Some(_)) = statuses.get(&self.digest) {
+                let status = statuses.remove(&self.digest).and_then(|status| status);
+                return Poll::Ready(status.unwrap());
+            }
+        }
+        {
+            let mut status_wakers = self.data.status_wakers.lock();
+            status_wakers.insert(self.digest, cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Identifies an update request sent with `Client::submit()`, whose
+/// reply may be awaited with `Client::wait()`, or which may be
+/// withdrawn with `Client::cancel()`.
+#[derive(Clone)]
+pub struct RequestReceipt {
+    digest: Digest,
+    // the unique digest of the request, which
+    // the replies of replicas are matched against
+    unique: Digest,
+}
+
+impl RequestReceipt {
+    /// Returns the digest of the update request, i.e. its content
+    /// digest, if `NodeConfig::digest_entropy` is unset.
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+}
+
 /// Represents a configuration used to bootstrap a `Client`.
 pub struct ClientConfig {
+    /// The replica believed to be the leader of the current view.
+    ///
+    /// If set, update requests are only sent to this replica, rather than
+    /// to every replica, and the client follows the redirects of replicas
+    /// configured with `ReplicaConfig::redirect_requests`, to find out the
+    /// actual leader. Note that a faulty replica may stall the requests
+    /// sent to it, since there are no request timeouts yet.
+    pub leader_hint: Option<NodeId>,
+    /// The shard of the replicas requests are sent to, which is
+    /// `ShardId::ZERO` unless they host several shards; check out
+    /// `Node::into_shards()`.
+    pub shard: ShardId,
     /// Check out the docs on `NodeConfig`.
+    ///
+    /// Clients always reconnect to replicas; if `NodeConfig::reconnect`
+    /// is unset, the default `Backoff` is used.
     pub node: NodeConfig,
 }
 
@@ -86,9 +179,13 @@ impl<D> Client<D>
 where
     D: SharedData + 'static,
     D::State: Send + Clone + 'static,
-    D::Request: Send + 'static,
+    D::Request: Send + Clone + 'static,
     D::Reply: Send + 'static,
 {
+    // the longest a replica may ask us to back off for, such
+    // that a faulty replica can't stall our requests forever
+    const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
     // elapsed time since last garbage collection
     // of the replica vote counts hashmap;
     //
@@ -101,7 +198,17 @@ where
 
     /// Bootstrap a client in `bafomet`.
     pub async fn bootstrap(cfg: ClientConfig) -> Result<Self> {
-        let ClientConfig { node: node_config } = cfg;
+        let ClientConfig {
+            leader_hint,
+            shard,
+            node: mut node_config,
+        } = cfg;
+
+        // always reconnect to replicas, which otherwise become
+        // unreachable once they reap our idle connections
+        if node_config.reconnect.is_none() {
+            node_config.reconnect = Some(Backoff::default());
+        }
 
         // system params
         let n = node_config.n;
@@ -114,11 +221,20 @@ where
         // perhaps when it reconnects to a replica after experiencing
         // network problems? for now ignore rogue messages...
         let (node, _rogue) = Node::bootstrap(node_config).await?;
+        let node = node.in_shard(shard);
 
         // create shared data
         let data = Arc::new(ClientData {
+            next_seq: Mutex::new((SeqNo::ZERO, None)),
+            pending: Mutex::new(collections::hash_map()),
+            resent: Mutex::new(collections::hash_map()),
             wakers: Mutex::new(collections::hash_map()),
             ready: Mutex::new(collections::hash_map()),
+            statuses: Mutex::new(collections::hash_map()),
+            status_wakers: Mutex::new(collections::hash_map()),
+            leader: Mutex::new(leader_hint),
+            backoff_until: Mutex::new(None),
+            members: node.members().to_vec(),
         });
         let task_data = Arc::clone(&data);
 
@@ -130,119 +246,662 @@ where
 
         Ok(Client {
             data,
-            params,
             node: send_node,
         })
     }
 
     /// Updates the replicated state of the application running
     /// on top of `bafomet`.
+    ///
+    /// Every request is tagged with a sequence number, which is shared
+    /// by all clones of this `Client`. Replicas track which of the last
+    /// 64 sequence numbers before the latest request executed for this
+    /// client were executed, so concurrent calls whose requests are
+    /// decided out of order still complete. Requests older than that
+    /// window are assumed to be executed, and are dropped as replays.
+    ///
+    /// Replicas configured with a `ReplicaConfig::client_rate_limit` drop
+    /// the requests exceeding it, asking the client to back off, as do
+    /// replicas whose ingestion of requests is paused. The dropped request
+    /// is sent again once the requested time elapses, and further requests
+    /// are held back until then.
     //
     // TODO: request timeout
     pub async fn update(&mut self, operation: D::Request) -> D::Reply {
-        let message = SystemMessage::Request(RequestMessage::new(operation));
+        self.update_impl(operation, None, None).await
+    }
+
+    /// Same as `update()`, but attaches the trace id `trace_id` to the
+    /// request, which is carried along with it through the log of each
+    /// replica, and echoed back in the replies of the replicas.
+    pub async fn update_traced(&mut self, operation: D::Request, trace_id: TraceId) -> D::Reply {
+        self.update_impl(operation, Some(trace_id), None).await
+    }
+
+    /// Same as `update()`, but attaches the metadata `metadata` to the
+    /// request, which replicas use to schedule it, e.g. proposing
+    /// requests with a higher priority ahead of other requests.
+    pub async fn update_with_metadata(
+        &mut self,
+        operation: D::Request,
+        metadata: RequestMeta,
+    ) -> D::Reply {
+        self.update_impl(operation, None, Some(metadata)).await
+    }
+
+    /// Same as `update()`, but returns once the request is sent, rather
+    /// than once its reply is received, with a receipt of the request.
+    pub async fn submit(&mut self, operation: D::Request) -> RequestReceipt {
+        self.submit_impl(operation, None, None).await
+    }
+
+    /// Waits for the reply of the update request identified by `receipt`.
+    pub async fn wait(&self, receipt: RequestReceipt) -> D::Reply {
+        let digest = receipt.unique;
+        let data = &*self.data;
+        ClientRequestFut { digest, data }.await
+    }
+
+    /// Withdraws the update request identified by `receipt`, such that
+    /// replicas which didn't propose it yet never order nor execute it.
+    ///
+    /// Requests already proposed are executed regardless, but their
+    /// replies are discarded. Note that requests resent to the leader,
+    /// after a replica redirected this `Client`, are not withdrawn.
+    pub fn cancel(&mut self, receipt: RequestReceipt) {
+        let digest = receipt.unique;
+        let message = SystemMessage::CancelRequest(CancelRequestMessage::new(digest));
+        let targets = self.data.members.iter().copied();
+        self.node.broadcast(message, targets);
+
+        self.data.pending.lock().remove(&digest);
+        self.data.wakers.lock().remove(&digest);
+        self.data.ready.lock().remove(&digest);
+    }
+
+    /// Queries the replicas about the status of the update request
+    /// identified by `receipt`, e.g. to find out if a request whose
+    /// reply is taking too long was executed at all.
+    ///
+    /// Returns once `f + 1` replicas report the same status, such that
+    /// at least one correct replica vouches for it. Replicas only report
+    /// the status of a request to the client which sent it.
+    pub async fn query_status(&mut self, receipt: &RequestReceipt) -> RequestStatus<D::Reply> {
+        let digest = receipt.unique;
+        self.data.statuses.lock().entry(digest).or_insert(None);
+
+        let message = SystemMessage::QueryStatus(QueryStatusMessage::new(digest));
+        let targets = self.data.members.iter().copied();
+        self.node.broadcast(message, targets);
+
+        let data = &*self.data;
+        StatusQueryFut { digest, data }.await
+    }
+
+    /// Reads the replicated state of the application running on top
+    /// of `bafomet`, without ordering the request through consensus.
+    ///
+    /// Replicas only serve the read after executing the latest update
+    /// issued by this `Client` (or any of its clones), so the read
+    /// observes the effects of every update that has completed.
+    pub async fn read(&mut self, operation: D::Request) -> D::Reply {
+        let (_, after) = *self.data.next_seq.lock();
+        let request = ReadRequestMessage::new(after, operation);
+        let message = SystemMessage::ReadRequest(request);
 
         // broadcast our request to the node group
-        let targets = NodeId::targets(0..self.params.n());
-        let digest = self.node.broadcast(message, targets);
+        let targets = self.data.members.iter().copied();
+        let (_, digest) = self.node.broadcast_with_unique(message, targets);
 
         // await response
         let data = &*self.data;
         ClientRequestFut { digest, data }.await
     }
 
+    async fn update_impl(
+        &mut self,
+        operation: D::Request,
+        trace_id: Option<TraceId>,
+        metadata: Option<RequestMeta>,
+    ) -> D::Reply {
+        let receipt = self.submit_impl(operation, trace_id, metadata).await;
+        self.wait(receipt).await
+    }
+
+    async fn submit_impl(
+        &mut self,
+        operation: D::Request,
+        trace_id: Option<TraceId>,
+        metadata: Option<RequestMeta>,
+    ) -> RequestReceipt {
+        // back off if our requests are being throttled
+        let backoff_until = *self.data.backoff_until.lock();
+        if let Some(until) = backoff_until {
+            let now = Instant::now();
+            if until > now {
+                Delay::new(until - now).await;
+            }
+        }
+
+        let seq = {
+            let mut next_seq = self.data.next_seq.lock();
+            let (seq, _) = *next_seq;
+            *next_seq = (seq.next(), Some(seq));
+            seq
+        };
+        let request = RequestMessage::new(seq, operation)
+            .with_trace_id(trace_id)
+            .with_metadata(metadata);
+
+        // hold on to the request before sending it, in case it is
+        // throttled before we get the chance to track it
+        let mut pending = self.data.pending.lock();
+        let (digest, unique) = Self::send_request(&mut self.node, &self.data, request.clone());
+        let resending = false;
+        pending.insert(unique, PendingRequest { request, resending });
+
+        RequestReceipt { digest, unique }
+    }
+
+    // sends `request` to the leader, if we are following it,
+    // otherwise broadcasts it to the node group
+    fn send_request(
+        node: &mut SendNode<D>,
+        data: &ClientData<D::Request, D::Reply>,
+        request: RequestMessage<D::Request>,
+    ) -> (Digest, Digest) {
+        let message = SystemMessage::Request(request);
+        let leader = *data.leader.lock();
+        match leader {
+            Some(leader) => node.send_with_unique(message, leader),
+            None => {
+                let targets = data.members.iter().copied();
+                node.broadcast_with_unique(message, targets)
+            }
+        }
+    }
+
+    /// Returns the replica this `Client` sends its update requests to,
+    /// i.e. the latest leader it was redirected to, if it was configured
+    /// with a `leader_hint`.
+    pub fn leader_hint(&self) -> Option<NodeId> {
+        *self.data.leader.lock()
+    }
+
     async fn message_recv_task(
         params: SystemParams,
-        data: Arc<ClientData<D::Reply>>,
+        data: Arc<ClientData<D::Request, D::Reply>>,
         mut node: Node<D>,
     ) {
         let mut earlier = Instant::now();
         let mut votes: HashMap<Digest, ReplicaVotes> = collections::hash_map();
+        // the replicas which reported each status of the requests
+        // being queried, keyed by the digest of their replies
+        let mut status_votes: HashMap<Digest, HashMap<Digest, Vec<NodeId>>> =
+            collections::hash_map();
 
         while let Ok(message) = node.receive().await {
             match message {
                 Message::System(header, message) => {
                     match message {
                         SystemMessage::Reply(message) => {
-                            let now = Instant::now();
-
-                            // garbage collect old votes
-                            //
-                            // TODO: switch to `HashMap::drain_filter` when
-                            // this API reaches stable Rust
-                            if now.duration_since(earlier) > Self::GC_DUR {
-                                let mut to_remove = Vec::new();
-
-                                for (dig, v) in votes.iter() {
-                                    if v.count > params.f() {
-                                        to_remove.push(dig.clone());
-                                    }
-                                }
-
-                                for dig in to_remove {
-                                    votes.remove(&dig);
-                                }
+                            Self::collect_votes(params, &mut votes, &mut earlier);
+                            let digest = header.digest().clone();
+                            Self::reply_received(params, &data, &mut votes, digest, message);
+                        }
+                        SystemMessage::ReplyBatch(message) => {
+                            Self::collect_votes(params, &mut votes, &mut earlier);
+                            for message in message.into_inner() {
+                                // vote on the digest each reply would have if it
+                                // was sent on its own, such that batched replies
+                                // are counted along with unbatched ones
+                                let message = SystemMessage::Reply(message);
+                                let mut buf = Buf::new();
+                                let digest =
+                                    match <D as DigestData>::serialize_digest(&message, &mut buf) {
+                                        Ok(digest) => digest,
+                                        Err(_) => continue,
+                                    };
+                                let message = match message {
+                                    SystemMessage::Reply(message) => message,
+                                    _ => unreachable!(),
+                                };
+                                Self::reply_received(params, &data, &mut votes, digest, message);
                             }
-                            earlier = now;
-
-                            let (digest, payload) = message.into_inner();
-                            let votes = votes
-                                .entry(digest)
-                                // FIXME: cache every reply's digest, instead of just the first one
-                                // we receive, because the first reply may be faulty, while the
-                                // remaining ones may be correct, therefore we would not be able to
-                                // count at least f+1 identical replies
-                                //
-                                // NOTE: the `digest()` call in the header returns the digest of
-                                // the payload
-                                .or_insert_with(|| ReplicaVotes {
-                                    count: 0,
-                                    digest: header.digest().clone(),
-                                });
-
-                            // reply already delivered to application
-                            if votes.count > params.f() {
+                        }
+                        SystemMessage::Redirect(message) => {
+                            Self::redirect_received(&data, &mut node, header.from(), message);
+                        }
+                        SystemMessage::Throttled(message) => {
+                            // only replicas throttle our requests
+                            if !data.members.contains(&header.from()) {
                                 continue;
                             }
-
-                            // register new reply received
-                            if &votes.digest == header.digest() {
-                                votes.count += 1;
-                            }
-
-                            // TODO: check if a replica hasn't voted
-                            // twice for the same digest
-
-                            // wait for at least f+1 identical replies
-                            if votes.count > params.f() {
-                                // register response
-                                {
-                                    let mut ready = data.ready.lock();
-                                    ready.insert(digest, payload);
-                                }
-
-                                // try to wake up a waiting task
-                                {
-                                    let mut wakers = data.wakers.lock();
-                                    if let Some(waker) = wakers.remove(&digest) {
-                                        waker.wake();
-                                    }
+                            let backoff = message.retry_after().min(Self::MAX_BACKOFF);
+                            let until = Instant::now() + backoff;
+                            {
+                                let mut backoff_until = data.backoff_until.lock();
+                                if backoff_until.map(|t| t < until).unwrap_or(true) {
+                                    *backoff_until = Some(until);
                                 }
                             }
+                            let seq = message.sequence_number();
+                            Self::throttled_received(&data, &node, seq, backoff);
+                        }
+                        SystemMessage::QueryStatusReply(message) => {
+                            Self::status_received(
+                                params,
+                                &data,
+                                &mut status_votes,
+                                header.from(),
+                                header.digest().clone(),
+                                message,
+                            );
                         }
                         // FIXME: handle rogue messages on clients
                         _ => panic!("rogue message detected"),
                     }
                 }
-                Message::ConnectedTx(id, sock) => node.handle_connected_tx(id, sock),
-                Message::ConnectedRx(id, sock) => node.handle_connected_rx(id, sock),
-                // TODO: node disconnected on send side
-                Message::DisconnectedTx(_id) => todo!(),
-                // TODO: node disconnected on receive side
-                Message::DisconnectedRx(_some_id) => todo!(),
+                Message::ConnectedTx(id, sock, codec) => node.handle_connected_tx(id, sock, codec),
+                Message::ConnectedRx(id, sock, codec) => node.handle_connected_rx(id, sock, codec),
+                // replicas may reap our idle connections; the link we
+                // send requests over is re-established in the background,
+                // and the replica connects back to us once it is
+                Message::DisconnectedTx(id) => node.handle_disconnected_tx(id),
+                Message::DisconnectedRx(Some(id)) | Message::Departed(id) => {
+                    node.handle_disconnected_rx(id)
+                }
+                // the peer dropped before identifying itself
+                Message::DisconnectedRx(None) => (),
                 // we don't receive any other type of messages as a client node
                 _ => (),
             }
         }
     }
+
+    // garbage collects the votes of requests whose replies
+    // were delivered to the application already
+    fn collect_votes(
+        params: SystemParams,
+        votes: &mut HashMap<Digest, ReplicaVotes>,
+        earlier: &mut Instant,
+    ) {
+        let now = Instant::now();
+
+        // TODO: switch to `HashMap::drain_filter` when
+        // this API reaches stable Rust
+        if now.duration_since(*earlier) > Self::GC_DUR {
+            let mut to_remove = Vec::new();
+
+            for (dig, v) in votes.iter() {
+                if v.count > params.f() {
+                    to_remove.push(dig.clone());
+                }
+            }
+
+            for dig in to_remove {
+                votes.remove(&dig);
+            }
+        }
+        *earlier = now;
+    }
+
+    // registers the vote of a replica on the reply `message`, whose
+    // wire digest is `reply_digest`, waking up the task waiting on
+    // the reply once `f + 1` replicas sent identical replies
+    fn reply_received(
+        params: SystemParams,
+        data: &ClientData<D::Request, D::Reply>,
+        votes: &mut HashMap<Digest, ReplicaVotes>,
+        reply_digest: Digest,
+        message: ReplyMessage<D::Reply>,
+    ) {
+        let (digest, payload) = message.into_inner();
+        // replies to resent requests wake up
+        // the tasks waiting on the original ones
+        let mut resent = data.resent.lock();
+        let digest = match resent.get(&digest) {
+            Some(original) => original.clone(),
+            None => digest,
+        };
+        let votes = votes
+            .entry(digest)
+            // FIXME: cache every reply's digest, instead of just the first one
+            // we receive, because the first reply may be faulty, while the
+            // remaining ones may be correct, therefore we would not be able to
+            // count at least f+1 identical replies
+            //
+            // NOTE: `reply_digest` is the digest of the payload of the reply
+            .or_insert_with(|| ReplicaVotes {
+                count: 0,
+                digest: reply_digest.clone(),
+            });
+
+        // reply already delivered to application
+        if votes.count > params.f() {
+            return;
+        }
+
+        // register new reply received
+        if votes.digest == reply_digest {
+            votes.count += 1;
+        }
+
+        // TODO: check if a replica hasn't voted
+        // twice for the same digest
+
+        // wait for at least f+1 identical replies
+        if votes.count > params.f() {
+            resent.retain(|_, original| *original != digest);
+            drop(resent);
+            data.pending.lock().remove(&digest);
+
+            // register response
+            {
+                let mut ready = data.ready.lock();
+                ready.insert(digest, payload);
+            }
+
+            // try to wake up a waiting task
+            {
+                let mut wakers = data.wakers.lock();
+                if let Some(waker) = wakers.remove(&digest) {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    // registers the status of a queried request reported by the replica
+    // `from`, whose wire digest is `reply_digest`, waking up the task
+    // waiting on the query once `f + 1` replicas reported the same status
+    fn status_received(
+        params: SystemParams,
+        data: &ClientData<D::Request, D::Reply>,
+        status_votes: &mut HashMap<Digest, HashMap<Digest, Vec<NodeId>>>,
+        from: NodeId,
+        reply_digest: Digest,
+        message: QueryStatusReplyMessage<D::Reply>,
+    ) {
+        let (digest, status) = message.into_inner();
+
+        // only replicas answer the queries we are waiting on
+        let mut statuses = data.statuses.lock();
+        let querying = matches!(statuses.get(&digest), Some(None));
+        if !querying || !data.members.contains(&from) {
+            return;
+        }
+
+        let voters = status_votes
+            .entry(digest)
+            .or_insert_with(collections::hash_map)
+            .entry(reply_digest)
+            .or_insert_with(Vec::new);
+        if !voters.contains(&from) {
+            voters.push(from);
+        }
+        if voters.len() <= params.f() {
+            return;
+        }
+
+        status_votes.remove(&digest);
+        statuses.insert(digest, Some(status));
+        drop(statuses);
+
+        let mut status_wakers = data.status_wakers.lock();
+        if let Some(waker) = status_wakers.remove(&digest) {
+            waker.wake();
+        }
+    }
+
+    // resends a request rejected by the follower `from` to the leader it
+    // points us to, as long as the request really is one we sent to `from`
+    fn redirect_received(
+        data: &ClientData<D::Request, D::Reply>,
+        node: &mut Node<D>,
+        from: NodeId,
+        message: RedirectMessage<D::Request>,
+    ) {
+        let (leader, rejected) = message.into_inner();
+        let (header, request) = rejected.into_inner();
+
+        // the requests resent after being throttled are tracked
+        // before the leader they are sent to is read, so lock
+        // both in the same order
+        let mut resent = data.resent.lock();
+
+        // only clients following the leader are redirected,
+        // and only to replicas
+        let mut hint = data.leader.lock();
+        if hint.is_none() || !data.members.contains(&leader) {
+            return;
+        }
+
+        // check if we signed the rejected request, and if
+        // it wasn't tampered with, before sending it again
+        if header.from() != node.id() || header.to() != from {
+            return;
+        }
+        let signed = node
+            .get_public_key(node.id())
+            .map(|key| header.verify_signature_hashed::<D::Hasher>(key))
+            .unwrap_or(false);
+        if !signed {
+            return;
+        }
+        let message = SystemMessage::Request(request);
+        let mut buf = Buf::new();
+        let digest = match <D as DigestData>::serialize_digest(&message, &mut buf) {
+            Ok(digest) => digest,
+            Err(_) => return,
+        };
+        if &digest != header.digest() {
+            return;
+        }
+
+        // several replicas may redirect the same request
+        let original = header.unique_digest();
+        if resent.values().any(|d| *d == original) {
+            return;
+        }
+
+        *hint = Some(leader);
+        let (_, unique) = node.send_with_unique(message, leader);
+        resent.insert(unique, original);
+    }
+
+    // sends the pending request with sequence number `seq` again, after
+    // `backoff` elapses, once a replica throttled it; the replies to the
+    // request sent again wake up the task waiting on the original one
+    fn throttled_received(
+        data: &Arc<ClientData<D::Request, D::Reply>>,
+        node: &Node<D>,
+        seq: SeqNo,
+        backoff: Duration,
+    ) {
+        // several replicas may throttle the same request
+        let original = {
+            let mut pending = data.pending.lock();
+            let throttled = pending
+                .iter_mut()
+                .find(|(_, p)| p.request.sequence_number() == seq && !p.resending);
+            match throttled {
+                Some((original, pending)) => {
+                    pending.resending = true;
+                    *original
+                }
+                None => return,
+            }
+        };
+        let data = Arc::clone(data);
+        let mut node = node.send_node();
+        rt::spawn(async move {
+            Delay::new(backoff).await;
+
+            // the request may have been answered, or withdrawn, meanwhile
+            let request = match data.pending.lock().get_mut(&original) {
+                Some(pending) => {
+                    pending.resending = false;
+                    pending.request.clone()
+                }
+                None => return,
+            };
+            let mut resent = data.resent.lock();
+            let (_, unique) = Self::send_request(&mut node, &data, request);
+            resent.insert(unique, original);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::{Client, ClientData, ClientRequestFut};
+    use crate::bft::async_runtime as rt;
+    use crate::bft::collections;
+    use crate::bft::communication::message::{Header, Message, ReplyMessage, SystemMessage};
+    use crate::bft::communication::{Node, NodeId};
+    use crate::bft::core::SystemParams;
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::crypto::signature::KeyPair;
+    use crate::bft::ordering::SeqNo;
+    use crate::bft::testing::{forge_header, init_runtime, CounterData};
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_client_survives_reaped_connections() {
+        init_runtime();
+
+        let replica = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let node: Node<CounterData> = Node::loopback(NodeId::from(1000u32), sk);
+        let mut tx = node.master_channel();
+
+        let params = SystemParams::new(1, 0).unwrap();
+        let data = Arc::new(ClientData {
+            next_seq: Mutex::new((SeqNo::ZERO, None)),
+            pending: Mutex::new(collections::hash_map()),
+            resent: Mutex::new(collections::hash_map()),
+            wakers: Mutex::new(collections::hash_map()),
+            ready: Mutex::new(collections::hash_map()),
+            statuses: Mutex::new(collections::hash_map()),
+            status_wakers: Mutex::new(collections::hash_map()),
+            leader: Mutex::new(None),
+            backoff_until: Mutex::new(None),
+            members: vec![replica],
+        });
+        rt::spawn(Client::<CounterData>::message_recv_task(
+            params,
+            Arc::clone(&data),
+            node,
+        ));
+
+        let digest = Digest::from_bytes(&[1; DefaultHasher::LENGTH][..]).unwrap();
+        let header = Header {
+            digest: Digest::from_bytes(&[2; DefaultHasher::LENGTH][..]).unwrap(),
+            ..forge_header(0, 1000, 0, 0)
+        };
+        let reply = rt::block_on(async {
+            // the replica reaps our connections, in both directions
+            let disconnects = [
+                Message::DisconnectedTx(replica),
+                Message::DisconnectedRx(Some(replica)),
+                Message::DisconnectedRx(None),
+            ];
+            for message in disconnects {
+                tx.send(message).await.unwrap();
+            }
+
+            // ... and replies once we are reconnected
+            let reply = SystemMessage::Reply(ReplyMessage::new(digest.clone(), 7));
+            tx.send(Message::System(header, reply)).await.unwrap();
+            ClientRequestFut {
+                digest,
+                data: &*data,
+            }
+            .await
+        });
+        assert_eq!(reply, 7);
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_throttled_only_by_members() {
+        use std::time::Duration;
+
+        use crate::bft::communication::message::ThrottledMessage;
+
+        init_runtime();
+
+        // the only replica doesn't take the id zero
+        let replica = NodeId::from(5u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let node: Node<CounterData> = Node::loopback(NodeId::from(1000u32), sk);
+        let mut tx = node.master_channel();
+
+        let params = SystemParams::new(1, 0).unwrap();
+        let data = Arc::new(ClientData {
+            next_seq: Mutex::new((SeqNo::ZERO, None)),
+            pending: Mutex::new(collections::hash_map()),
+            resent: Mutex::new(collections::hash_map()),
+            wakers: Mutex::new(collections::hash_map()),
+            ready: Mutex::new(collections::hash_map()),
+            statuses: Mutex::new(collections::hash_map()),
+            status_wakers: Mutex::new(collections::hash_map()),
+            leader: Mutex::new(None),
+            backoff_until: Mutex::new(None),
+            members: vec![replica],
+        });
+        rt::spawn(Client::<CounterData>::message_recv_task(
+            params,
+            Arc::clone(&data),
+            node,
+        ));
+
+        let throttled = || {
+            let message = ThrottledMessage::new(SeqNo::ZERO, Duration::from_secs(1));
+            SystemMessage::Throttled(message)
+        };
+        let digest = Digest::from_bytes(&[1; DefaultHasher::LENGTH][..]).unwrap();
+        let reply = SystemMessage::Reply(ReplyMessage::new(digest.clone(), 7));
+        rt::block_on(async {
+            // the reply is processed after the throttled messages
+            let messages = [
+                Message::System(forge_header(0, 1000, 0, 0), throttled()),
+                Message::System(forge_header(5, 1000, 0, 0), reply),
+            ];
+            for message in messages {
+                tx.send(message).await.unwrap();
+            }
+            ClientRequestFut {
+                digest: digest.clone(),
+                data: &*data,
+            }
+            .await
+        });
+
+        // node zero isn't a replica, so it can't throttle us
+        assert!(data.backoff_until.lock().is_none());
+
+        let digest = Digest::from_bytes(&[3; DefaultHasher::LENGTH][..]).unwrap();
+        let reply = SystemMessage::Reply(ReplyMessage::new(digest.clone(), 7));
+        rt::block_on(async {
+            let messages = [
+                Message::System(forge_header(5, 1000, 1, 0), throttled()),
+                Message::System(forge_header(5, 1000, 2, 0), reply),
+            ];
+            for message in messages {
+                tx.send(message).await.unwrap();
+            }
+            ClientRequestFut {
+                digest,
+                data: &*data,
+            }
+            .await
+        });
+        assert!(data.backoff_until.lock().is_some());
+    }
 }
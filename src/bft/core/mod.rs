@@ -1,6 +1,7 @@
 //! Contains the core protocol logic of `bafomet`.
 
 pub mod client;
+pub mod observer;
 pub mod server;
 
 #[cfg(feature = "serialize_serde")]
@@ -11,6 +12,11 @@ use crate::bft::error::*;
 /// This struct contains the system parameters of
 /// a replica or client in `bafomet`, i.e. `n` and `f`
 /// such that `n >= 3*f + 1`.
+///
+/// Setting `n = 1` and `f = 0` runs `bafomet` in single node
+/// mode, where the quorum size is 1; the lone replica decides
+/// the batches it proposes without waiting for any peer. This
+/// tolerates no faults, and is only meant for testing services.
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone)]
 pub struct SystemParams {
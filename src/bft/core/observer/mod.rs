@@ -0,0 +1,417 @@
+//! Contains the observer side core protocol logic of `bafomet`.
+//!
+//! An observer is a node which follows the batches decided by the
+//! replicas, applying them to its own copy of the application state,
+//! e.g. to scale out read only workloads. Observers never vote, so
+//! they aren't members of any view, nor counted towards any quorum.
+
+use std::future::Future;
+
+use super::server::ViewInfo;
+use crate::bft::collections::{self, HashMap};
+use crate::bft::communication::message::{DecidedMessage, Header, Message, SystemMessage};
+use crate::bft::communication::reconnect::Backoff;
+use crate::bft::communication::{Node, NodeConfig, NodeId};
+use crate::bft::crypto::hash::Digest;
+use crate::bft::error::*;
+use crate::bft::executable::{
+    Executor, ExecutorHandle, Reply, Request, Service, State, UpdateBatch,
+};
+use crate::bft::ordering::{Orderable, SeqNo};
+
+// the maximum number of consensus instances, past the next one
+// to be applied, for which an observer holds decided batches;
+// batches further ahead are dropped, such that a malicious
+// replica can't exhaust the memory of the observer
+const MAX_PENDING_DECIDED: u32 = 128;
+
+// the replicas which sent the same batch, decided by some
+// consensus instance, with the digest `digest`
+struct DecidedVotes<O> {
+    digest: Digest,
+    voters: Vec<NodeId>,
+    batch: UpdateBatch<O>,
+}
+
+/// Represents an observer in `bafomet`.
+pub struct Observer<S: Service> {
+    view: ViewInfo,
+    next_seq: SeqNo,
+    pending: HashMap<SeqNo, Vec<DecidedVotes<Request<S>>>>,
+    executor: ExecutorHandle<S>,
+    node: Node<S::Data>,
+}
+
+/// Represents a configuration used to bootstrap an `Observer`.
+pub struct ObserverConfig<S> {
+    /// The application logic.
+    pub service: S,
+    /// The sequence number of the first consensus instance whose
+    /// decided batch is applied by this observer.
+    ///
+    /// Observers don't run the state transfer protocol yet, so
+    /// they must be attached before this instance is decided.
+    pub next_consensus_seq: SeqNo,
+    /// The number of replies to executed client requests kept
+    /// in memory by the executor of the observer.
+    pub reply_cache_size: usize,
+    /// Check out the docs on `NodeConfig`. The id of the observer
+    /// must be in between `n` and `first_cli`, and be listed in
+    /// `ReplicaConfig::observers` of every replica.
+    ///
+    /// Observers always reconnect to replicas; if `NodeConfig::reconnect`
+    /// is unset, the default `Backoff` is used.
+    pub node: NodeConfig,
+}
+
+impl<S> Observer<S>
+where
+    S: Service + Send + 'static,
+    State<S>: Send + Clone + 'static,
+    Request<S>: Send + Clone + 'static,
+    Reply<S>: Send + Clone + 'static,
+{
+    /// Bootstrap an observer in `bafomet`.
+    pub async fn bootstrap(cfg: ObserverConfig<S>) -> Result<Self> {
+        Self::bootstrap_with(cfg, Node::bootstrap).await
+    }
+
+    // bootstraps an observer, connecting to the replicas with `connect`
+    async fn bootstrap_with<F, C>(cfg: ObserverConfig<S>, connect: C) -> Result<Self>
+    where
+        C: FnOnce(NodeConfig) -> F,
+        F: Future<Output = Result<(Node<S::Data>, Vec<Message<State<S>, Request<S>, Reply<S>>>)>>,
+    {
+        let ObserverConfig {
+            service,
+            next_consensus_seq,
+            reply_cache_size,
+            node: mut node_config,
+        } = cfg;
+
+        // always reconnect to replicas, otherwise the observer
+        // stops following the system once a link drops
+        if node_config.reconnect.is_none() {
+            node_config.reconnect = Some(Backoff::default());
+        }
+
+        if !node_config
+            .id
+            .is_observer(node_config.n, node_config.first_cli)
+        {
+            return Err("Observer ids must be in between n and first_cli")
+                .wrapped(ErrorKind::CoreObserver);
+        }
+
+        // the observer only uses the view to
+        // tell apart the replicas in the system
+        let view = ViewInfo::new(SeqNo::ZERO, node_config.n, node_config.f)?;
+
+        // connect to the replicas
+        let (node, rogue) = connect(node_config).await?;
+
+        let executor =
+            Executor::new(node.master_channel(), service, reply_cache_size, None).await?;
+
+        let mut observer = Observer {
+            view,
+            next_seq: next_consensus_seq,
+            pending: collections::hash_map(),
+            executor,
+            node,
+        };
+
+        // handle rogue messages
+        for message in rogue {
+            if let Message::System(header, SystemMessage::Decided(message)) = message {
+                observer.decided_received(header, message)?;
+            }
+        }
+
+        Ok(observer)
+    }
+
+    /// The main loop of an observer.
+    ///
+    /// Together with `Observer::bootstrap()`, this is the entrypoint
+    /// of an observer: the returned future applies the batches decided
+    /// by the replicas, and only resolves if an unrecoverable error occurs.
+    pub async fn run(mut self) -> Result<()> {
+        // TODO: exit condition?
+        loop {
+            match self.node.receive().await? {
+                Message::System(header, SystemMessage::Decided(message)) => {
+                    self.decided_received(header, message)?;
+                }
+                // observers don't take part in any other sub-protocol
+                Message::System(_, _) => (),
+                // the replies are sent to clients by the replicas
+                Message::ExecutionFinished(_) | Message::ExecutionFinishedWithAppstate(_, _) => (),
+                Message::ConnectedTx(id, sock, codec) => {
+                    self.node.handle_connected_tx(id, sock, codec)
+                }
+                Message::ConnectedBulkTx(id, sock, codec) => {
+                    self.node.handle_connected_bulk_tx(id, sock, codec)
+                }
+                Message::ConnectedRx(id, sock, codec) => {
+                    self.node.handle_connected_rx(id, sock, codec)
+                }
+                // drop the link, and reconnect to the replica in the
+                // background; note that the batches it decides in the
+                // meantime aren't relayed to us again
+                Message::DisconnectedTx(id) => self.node.handle_disconnected_tx(id),
+                Message::DisconnectedRx(Some(id)) | Message::Departed(id) => {
+                    self.node.handle_disconnected_rx(id)
+                }
+                // the peer dropped before identifying itself
+                Message::DisconnectedRx(None) => (),
+                Message::Timeout(_) | Message::ConsensusStalled(_, _) => (),
+                Message::StatusQueried(_, _) | Message::ClusterUnrecoverable(_) => (),
+                // only replicas are administered through a handle
+                Message::Command(_) => (),
+                // the connection is dropped by its read task
+                Message::Error(_, _) => (),
+            }
+        }
+    }
+
+    /// Returns the sequence number of the next consensus
+    /// instance whose decided batch is applied by this observer.
+    pub fn next_consensus_seq(&self) -> SeqNo {
+        self.next_seq
+    }
+
+    // records the batch decided by the consensus instance of `message`,
+    // sent by the replica `header.from()`; once `f + 1` replicas sent
+    // the same batch, at least one of them is correct, so the batch is
+    // applied, as soon as every batch decided before it has been applied
+    fn decided_received(
+        &mut self,
+        header: Header,
+        message: DecidedMessage<Request<S>>,
+    ) -> Result<()> {
+        let from = header.from();
+        if !self.view.members().contains(&from) {
+            return Ok(());
+        }
+
+        let seq = message.sequence_number();
+        if seq < self.next_seq || seq.distance(self.next_seq) > MAX_PENDING_DECIDED {
+            return Ok(());
+        }
+
+        // NOTE: the `digest()` call in the header returns
+        // the digest of the payload, i.e. of the batch
+        let digest = header.digest().clone();
+        let batch = message.into_inner();
+
+        let votes = self.pending.entry(seq).or_insert_with(Vec::new);
+        if votes.iter().any(|v| v.voters.contains(&from)) {
+            // a replica may only vote once per instance
+            return Ok(());
+        }
+        match votes.iter_mut().find(|v| v.digest == digest) {
+            Some(v) => v.voters.push(from),
+            None => votes.push(DecidedVotes {
+                digest,
+                voters: vec![from],
+                batch,
+            }),
+        }
+
+        self.apply_decided()
+    }
+
+    // applies the batches decided by the next consensus
+    // instances, for which enough votes have been received
+    fn apply_decided(&mut self) -> Result<()> {
+        let f = self.view.params().f();
+        loop {
+            let decided = self
+                .pending
+                .get(&self.next_seq)
+                .and_then(|votes| votes.iter().position(|v| v.voters.len() > f));
+            let index = match decided {
+                Some(index) => index,
+                None => return Ok(()),
+            };
+            let mut votes = self.pending.remove(&self.next_seq).unwrap();
+            self.executor.queue_update(votes.swap_remove(index).batch)?;
+            self.next_seq = self.next_seq.next();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serialize_serde"))]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use rustls::{ClientConfig, NoClientAuth, ServerConfig};
+
+    use super::*;
+    use crate::bft::async_runtime as rt;
+    use crate::bft::communication::compress::Codec;
+    use crate::bft::communication::message::WireMessage;
+    use crate::bft::communication::serialize::{Buf, DigestData};
+    use crate::bft::communication::socket::{DefaultProvider, SocketOpts};
+    use crate::bft::crypto::hash::{DefaultHasher, DigestHasher};
+    use crate::bft::crypto::signature::KeyPair;
+    use crate::bft::testing::{init_runtime, CounterData, CounterService};
+    use crate::bft::threadpool;
+
+    const OBSERVER: u32 = 4;
+
+    fn config() -> ObserverConfig<CounterService> {
+        let node = NodeConfig {
+            n: 4,
+            f: 1,
+            id: NodeId::from(OBSERVER),
+            first_cli: NodeId::from(1000u32),
+            addrs: collections::hash_map(),
+            pk: collections::hash_map(),
+            sk: KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap(),
+            client_config: ClientConfig::new(),
+            server_config: ServerConfig::new(NoClientAuth::new()),
+            channel_bound: 64,
+            connect_retries: 10,
+            pool: threadpool::Builder::new().num_threads(1).build(),
+            deserialize_timeout: Duration::from_secs(1),
+            authenticate_peers: false,
+            write_queue: None,
+            send_buf_pool: 16,
+            rogue_bound: 64,
+            client_idle_timeout: None,
+            bulk_link: false,
+            digest_entropy: true,
+            write_timeout: None,
+            verify_parallelism: 1,
+            socket_opts: SocketOpts::default(),
+            socket_provider: Arc::new(DefaultProvider::default()),
+            reconnect: None,
+            compression: vec![Codec::None],
+        };
+        ObserverConfig {
+            service: CounterService,
+            next_consensus_seq: SeqNo::ZERO,
+            reply_cache_size: 16,
+            node,
+        }
+    }
+
+    // the loopback node never connects to any peers
+    async fn loopback(
+        node: NodeConfig,
+    ) -> Result<(Node<CounterData>, Vec<Message<u64, u64, u64>>)> {
+        let node = Node::loopback(node.id, node.sk);
+        Ok((node, Vec::new()))
+    }
+
+    // the batch with a single request, decided by the consensus
+    // instance `seq`, as sent by the replica `from`
+    fn decided(from: u32, seq: u32, operation: u64) -> (Header, DecidedMessage<u64>) {
+        let mut batch = UpdateBatch::new();
+        batch.add(
+            NodeId::from(1000u32),
+            SeqNo::from(seq),
+            Digest::from_bytes(&[seq as u8; DefaultHasher::LENGTH][..]).unwrap(),
+            operation,
+            None,
+        );
+        let message = DecidedMessage::new(SeqNo::from(seq), batch);
+
+        let mut buf = Buf::new();
+        let system = SystemMessage::Decided(message.clone());
+        let digest = <CounterData as DigestData>::serialize_digest(&system, &mut buf).unwrap();
+        let (from, to) = (NodeId::from(from), NodeId::from(OBSERVER));
+        let header = WireMessage::new(from, to, &buf, 0, Some(digest), None).header;
+
+        (header, message)
+    }
+
+    #[test]
+    fn test_observer_applies_decided_batches() {
+        init_runtime();
+
+        let cfg = config();
+        let mut observer = rt::block_on(Observer::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the observer isn't a member of the view
+        let me = NodeId::from(OBSERVER);
+        assert!(!observer.view.members().contains(&me));
+
+        // the votes of the observer itself, of clients, and repeated
+        // votes of the same replica aren't counted towards the f + 1
+        // matching batches required to apply a batch
+        for from in [OBSERVER, 1000, 0, 0] {
+            let (header, message) = decided(from, 0, 40);
+            observer.decided_received(header, message).unwrap();
+            assert_eq!(observer.next_consensus_seq(), SeqNo::ZERO);
+        }
+
+        // a batch sent by a faulty replica doesn't match the others
+        let (header, message) = decided(1, 0, 666);
+        observer.decided_received(header, message).unwrap();
+        assert_eq!(observer.next_consensus_seq(), SeqNo::ZERO);
+
+        // batches decided ahead of time are held back
+        // until the batches before them are applied
+        let (header, message) = decided(0, 1, 2);
+        observer.decided_received(header, message).unwrap();
+        let (header, message) = decided(3, 1, 2);
+        observer.decided_received(header, message).unwrap();
+        assert_eq!(observer.next_consensus_seq(), SeqNo::ZERO);
+
+        let (header, message) = decided(2, 0, 40);
+        observer.decided_received(header, message).unwrap();
+        assert_eq!(observer.next_consensus_seq(), SeqNo::from(2u32));
+        assert!(observer.pending.is_empty());
+
+        // the batches were applied in order to the state of the observer
+        let counters = rt::block_on(async {
+            let mut counters = Vec::new();
+            while counters.len() < 2 {
+                if let Message::ExecutionFinished(batch) = observer.node.receive().await.unwrap() {
+                    for reply in batch.into_inner() {
+                        let (_, _, counter) = reply.into_inner();
+                        counters.push(counter);
+                    }
+                }
+            }
+            counters
+        });
+        assert_eq!(counters, vec![40, 42]);
+    }
+
+    #[test]
+    fn test_observer_survives_disconnects() {
+        use futures::future::{self, Either};
+        use futures_timer::Delay;
+
+        init_runtime();
+
+        let cfg = config();
+        let observer = rt::block_on(Observer::bootstrap_with(cfg, loopback)).unwrap();
+        let mut tx = observer.node.master_channel();
+
+        // the links to a replica drop, and so does
+        // a peer that never identified itself
+        let replica = NodeId::from(0u32);
+        let disconnects = [
+            Message::DisconnectedTx(replica),
+            Message::DisconnectedRx(Some(replica)),
+            Message::DisconnectedRx(None),
+        ];
+
+        // the observer keeps running, rather than panicking
+        let running = rt::block_on(async move {
+            for message in disconnects {
+                tx.send(message).await.unwrap();
+            }
+            let run = Box::pin(observer.run());
+            let wait = Delay::new(Duration::from_millis(200));
+            matches!(future::select(run, wait).await, Either::Right(_))
+        });
+        assert!(running);
+    }
+}
@@ -0,0 +1,145 @@
+//! Administration of a running replica.
+//!
+//! `Replica::run()` takes ownership of the replica, so its administrative
+//! operations are issued through a `ReplicaHandle` instead, obtained with
+//! `Replica::handle()` beforehand. Each operation is delivered to the main
+//! loop of the replica as a `Message::Command`, and carried out in between
+//! the messages it processes, such that it never races with the protocol.
+
+#[cfg(not(feature = "admin"))]
+use std::convert::Infallible;
+#[cfg(not(feature = "admin"))]
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+
+use super::ViewInfo;
+use crate::bft::communication::channel::MessageChannelTx;
+use crate::bft::communication::message::Message;
+use crate::bft::communication::NodeId;
+use crate::bft::error::*;
+use crate::bft::executable::{Reply, Request, Service, State};
+#[cfg(feature = "admin")]
+use crate::bft::ordering::SeqNo;
+
+/// An administrative command, sent to a replica through a `ReplicaHandle`.
+pub struct ReplicaCommand<S> {
+    pub(super) kind: CommandKind<S>,
+}
+
+// the operations of a `ReplicaHandle`, along with
+// the channel their outcome is returned through
+pub(super) enum CommandKind<S> {
+    Metrics(oneshot::Sender<String>),
+    CurrentView(oneshot::Sender<ViewInfo>),
+    StepDown(oneshot::Sender<Result<()>>),
+    PauseIngestion(oneshot::Sender<()>),
+    ResumeIngestion(oneshot::Sender<()>),
+    PersistSnapshot(PathBuf, oneshot::Sender<Result<()>>),
+    RestoreSnapshot(PathBuf, oneshot::Sender<Result<()>>),
+    #[cfg(feature = "admin")]
+    ForceInstallCheckpoint(SeqNo, S, oneshot::Sender<Result<()>>),
+    Shutdown(Duration, oneshot::Sender<Result<()>>),
+    // keeps `S` in use when the `admin` feature is disabled
+    #[cfg(not(feature = "admin"))]
+    #[allow(dead_code)]
+    Never(Infallible, PhantomData<S>),
+}
+
+/// A handle to a `Replica`, which remains usable after the replica
+/// is moved into `Replica::run()`.
+///
+/// Every operation fails once the replica stops running, e.g.
+/// after it is shut down with `ReplicaHandle::shutdown()`.
+pub struct ReplicaHandle<S: Service> {
+    system_tx: MessageChannelTx<State<S>, Request<S>, Reply<S>>,
+}
+
+impl<S: Service> Clone for ReplicaHandle<S> {
+    fn clone(&self) -> Self {
+        let system_tx = self.system_tx.clone();
+        Self { system_tx }
+    }
+}
+
+impl<S: Service> ReplicaHandle<S> {
+    pub(super) fn new(system_tx: MessageChannelTx<State<S>, Request<S>, Reply<S>>) -> Self {
+        Self { system_tx }
+    }
+
+    // sends the command built by `kind` to the replica,
+    // and waits for its outcome to be returned
+    async fn issue<T, F>(&mut self, kind: F) -> Result<T>
+    where
+        F: FnOnce(oneshot::Sender<T>) -> CommandKind<State<S>>,
+    {
+        let (tx, rx) = oneshot::channel();
+        let command = ReplicaCommand { kind: kind(tx) };
+        self.system_tx
+            .send(Message::Command(command))
+            .await
+            .simple_msg(ErrorKind::CoreServer, "The replica is not running")?;
+        rx.await
+            .simple_msg(ErrorKind::CoreServer, "The replica is not running")
+    }
+
+    /// See `Replica::metrics_prometheus()`.
+    pub async fn metrics_prometheus(&mut self) -> Result<String> {
+        self.issue(CommandKind::Metrics).await
+    }
+
+    /// See `Replica::current_view()`.
+    pub async fn current_view(&mut self) -> Result<ViewInfo> {
+        self.issue(CommandKind::CurrentView).await
+    }
+
+    /// See `Replica::current_leader()`.
+    pub async fn current_leader(&mut self) -> Result<NodeId> {
+        self.current_view().await.map(|view| view.leader())
+    }
+
+    /// See `Replica::step_down()`.
+    pub async fn step_down(&mut self) -> Result<()> {
+        self.issue(CommandKind::StepDown).await?
+    }
+
+    /// See `Replica::pause_ingestion()`.
+    pub async fn pause_ingestion(&mut self) -> Result<()> {
+        self.issue(CommandKind::PauseIngestion).await
+    }
+
+    /// See `Replica::resume_ingestion()`.
+    pub async fn resume_ingestion(&mut self) -> Result<()> {
+        self.issue(CommandKind::ResumeIngestion).await
+    }
+
+    /// See `Replica::persist_snapshot()`.
+    pub async fn persist_snapshot<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
+        let path = path.into();
+        self.issue(|tx| CommandKind::PersistSnapshot(path, tx))
+            .await?
+    }
+
+    /// See `Replica::restore_snapshot()`.
+    pub async fn restore_snapshot<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
+        let path = path.into();
+        self.issue(|tx| CommandKind::RestoreSnapshot(path, tx))
+            .await?
+    }
+
+    /// See `Replica::force_install_checkpoint()`.
+    #[cfg(feature = "admin")]
+    pub async fn force_install_checkpoint(&mut self, seq: SeqNo, state: State<S>) -> Result<()> {
+        self.issue(|tx| CommandKind::ForceInstallCheckpoint(seq, state, tx))
+            .await?
+    }
+
+    /// See `Replica::shutdown()`.
+    ///
+    /// Once the replica is shut down, `Replica::run()` resolves.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<()> {
+        self.issue(|tx| CommandKind::Shutdown(timeout, tx)).await?
+    }
+}
@@ -1,22 +1,47 @@
 //! Contains the server side core protocol logic of `bafomet`.
 
-use std::time::Duration;
+pub mod handle;
+pub mod throttle;
 
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use futures::{select, FutureExt};
+use futures_timer::Delay;
 #[cfg(feature = "serialize_serde")]
 use serde::{Deserialize, Serialize};
 
+use self::handle::{CommandKind, ReplicaCommand, ReplicaHandle};
+use self::throttle::{RateLimit, RateLimiter};
 use super::SystemParams;
 use crate::bft::async_runtime as rt;
+use crate::bft::collections::{self, HashMap, HashSet};
 use crate::bft::communication::message::{
-    ForwardedRequestsMessage, Message, ReplyMessage, SystemMessage,
+    CancelRequestMessage, DecidedMessage, ForwardRequestMessage, ForwardedRequestsMessage, Header,
+    Message, QueryStatusMessage, QueryStatusReplyMessage, ReadRequestMessage, RedirectMessage,
+    ReplyBatchMessage, ReplyMessage, RequestMessage, RequestMissingMessage, RequestStatus,
+    StoredMessage, SystemMessage, ThrottledMessage,
 };
+use crate::bft::communication::serialize::ToBytes;
 use crate::bft::communication::{Node, NodeConfig, NodeId};
 use crate::bft::consensus::log::{Info, Log};
-use crate::bft::consensus::{Consensus, ConsensusPollStatus, ConsensusStatus};
-use crate::bft::cst::{install_recovery_state, CollabStateTransfer, CstProgress, CstStatus};
+use crate::bft::consensus::{
+    Consensus, ConsensusPollStatus, ConsensusStatus, ProtoPhase, VoteVerification,
+};
+use crate::bft::cst::{
+    install_delta, install_recovery_state, CheckpointStore, CollabStateTransfer, CstProgress,
+    CstStatus, RecoveryState,
+};
 use crate::bft::error::*;
 use crate::bft::executable::{
-    Executor, ExecutorHandle, Reply, Request, Service, State, UpdateBatchReplies,
+    DeadLetterSink, Executor, ExecutorHandle, Reply, Request, Service, State, UpdateBatch,
+    UpdateBatchReplies,
 };
 use crate::bft::ordering::{Orderable, SeqNo};
 use crate::bft::sync::{Synchronizer, SynchronizerPollStatus, SynchronizerStatus};
@@ -37,10 +62,11 @@ enum ReplicaPhase {
 /// This struct contains information related with an
 /// active `bafomet` view.
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct ViewInfo {
     seq: SeqNo,
     params: SystemParams,
+    members: Vec<NodeId>,
 }
 
 impl Orderable for ViewInfo {
@@ -53,8 +79,27 @@ impl Orderable for ViewInfo {
 impl ViewInfo {
     /// Creates a new instance of `ViewInfo`.
     pub fn new(seq: SeqNo, n: usize, f: usize) -> Result<Self> {
-        let params = SystemParams::new(n, f)?;
-        Ok(ViewInfo { seq, params })
+        Self::with_members(seq, NodeId::targets(0..n).collect(), f)
+    }
+
+    /// Creates a new instance of `ViewInfo`, whose replicas
+    /// are the nodes with the ids in `members`.
+    ///
+    /// Fails if `members` is empty, or if it repeats an id.
+    pub fn with_members(seq: SeqNo, members: Vec<NodeId>, f: usize) -> Result<Self> {
+        if members.is_empty() {
+            return Err("A view needs at least one member").wrapped(ErrorKind::CoreServer);
+        }
+        let distinct: HashSet<_> = members.iter().collect();
+        if distinct.len() != members.len() {
+            return Err("The members of a view must be distinct").wrapped(ErrorKind::CoreServer);
+        }
+        let params = SystemParams::new(members.len(), f)?;
+        Ok(ViewInfo {
+            seq,
+            params,
+            members,
+        })
     }
 
     /// Returns a copy of this node's `SystemParams`.
@@ -62,6 +107,11 @@ impl ViewInfo {
         &self.params
     }
 
+    /// Returns the ids of the replicas in this view.
+    pub fn members(&self) -> &[NodeId] {
+        &self.members
+    }
+
     /// Returns a new view with the sequence number after
     /// the current view's number.
     pub fn next_view(&self) -> ViewInfo {
@@ -70,14 +120,14 @@ impl ViewInfo {
 
     /// Returns a new view with the specified sequence number.
     pub fn peek(&self, seq: SeqNo) -> ViewInfo {
-        let mut view = *self;
+        let mut view = self.clone();
         view.seq = seq;
         view
     }
 
     /// Returns the leader of the current view.
     pub fn leader(&self) -> NodeId {
-        NodeId::from(usize::from(self.seq) % self.params.n())
+        self.members[usize::from(self.seq) % self.members.len()]
     }
 }
 
@@ -94,11 +144,64 @@ pub struct Replica<S: Service> {
     cst: CollabStateTransfer<S>,
     log: Log<State<S>, Request<S>, Reply<S>>,
     node: Node<S::Data>,
+    consensus_watchdog: Duration,
+    // read only requests waiting for an update request of the
+    // same client to be decided, along with when they arrived
+    pending_reads: Vec<(Instant, StoredMessage<ReadRequestMessage<Request<S>>>)>,
+    // nodes following the decided batches without voting
+    observers: Vec<NodeId>,
+    // whether followers forward client requests to the leader
+    forward_requests: bool,
+    // whether followers redirect clients to the leader
+    redirect_requests: bool,
+    // limits the rate of the requests admitted from each client
+    throttle: Option<RateLimiter>,
+    // a shutdown requested through a `ReplicaHandle`, carried out
+    // by `Replica::run()` once the current message is handled
+    shutting_down: Option<(Duration, oneshot::Sender<Result<()>>)>,
+}
+
+// the maximum number of read only requests waiting for
+// an update request to be decided, past which new reads
+// are dropped, to bound the memory used by malicious clients
+const MAX_PENDING_READS: usize = 1024;
+
+// the maximum number of read only requests of a single client
+// waiting for its update requests to be decided, such that one
+// client can't crowd out the reads of the others
+const MAX_PENDING_READS_PER_CLIENT: usize = 16;
+
+// how long a read only request waits for an update request to be
+// decided, before being dropped; e.g. the update may never arrive
+const PENDING_READS_EXPIRY: Duration = Duration::from_secs(30);
+
+// how long clients are told to back off when their requests are
+// rejected, while the ingestion of client requests is paused
+const PAUSED_INGESTION_RETRY_AFTER: Duration = Duration::from_millis(100);
+
+// appends the samples of a metric to `buf`, in the Prometheus
+// text exposition format; each sample is labeled with `labels`,
+// along with the extra labels paired with its value, if any
+fn write_metric(
+    buf: &mut String,
+    name: &str,
+    help: &str,
+    kind: &str,
+    labels: &str,
+    samples: &[(&str, u64)],
+) {
+    // writing to a `String` never fails
+    let _ = writeln!(buf, "# HELP {} {}", name, help);
+    let _ = writeln!(buf, "# TYPE {} {}", name, kind);
+    for &(extra, value) in samples {
+        let sep = if extra.is_empty() { "" } else { "," };
+        let _ = writeln!(buf, "{}{{{}{}{}}} {}", name, labels, sep, extra, value);
+    }
 }
 
 /// Represents a configuration used to bootstrap a `Replica`.
 // TODO: load files from persistent storage
-pub struct ReplicaConfig<S> {
+pub struct ReplicaConfig<S: Service> {
     /// The application logic.
     pub service: S,
     /// The sequence number for the current view.
@@ -106,10 +209,121 @@ pub struct ReplicaConfig<S> {
     /// Next sequence number attributed to a request by
     /// the consensus layer.
     pub next_consensus_seq: SeqNo,
+    /// The number of consensus instances between two
+    /// checkpoints of the log, e.g. `log::PERIOD`.
+    pub checkpoint_period: u32,
     /// The maximum number of client requests to queue
     /// before executing the consensus algorithm.
     pub batch_size: usize,
-    /// Check out the docs on `NodeConfig`.
+    /// The maximum sum of the serialized sizes of the client
+    /// requests in a batch, i.e. in a single consensus instance.
+    ///
+    /// Once the requests queued for the next batch reach this size,
+    /// the batch is proposed early, bounding the memory they hold.
+    pub max_batch_bytes: usize,
+    /// The maximum sum of the serialized sizes of the client requests
+    /// being decided, i.e. those queued for the next batch, and those
+    /// proposed but not yet decided. Once it is reached, the partial
+    /// batch is proposed. A value of `usize::MAX` leaves it unbounded,
+    /// other than by `max_batch_bytes`.
+    pub max_deciding_bytes: usize,
+    /// The maximum sum of the serialized sizes of the client requests
+    /// decided since the last checkpoint. Past this ceiling, the log is
+    /// dropped, and the replica recovers the latest state from its peers.
+    pub max_log_bytes: usize,
+    /// The base duration a client request may wait to be decided,
+    /// before it is forwarded to the leader, and ultimately, before
+    /// a view change is started. Requests waiting for longer than
+    /// this are proposed ahead of those with a higher priority or an
+    /// earlier deadline, so that no request is starved by others.
+    pub request_timeout: Duration,
+    /// The maximum number of view changes which may fail in a row, i.e.
+    /// be superseded by another view change before a new view is
+    /// installed, after which `Message::ClusterUnrecoverable` is emitted,
+    /// and `Replica::run()` resolves with an error.
+    ///
+    /// This signals that the cluster is fundamentally broken, e.g. because
+    /// a majority of the replicas is faulty, and needs to be reconfigured.
+    /// If `None`, view changes are retried indefinitely.
+    pub max_failed_view_changes: Option<usize>,
+    /// The base duration of the timeouts of the CST protocol,
+    /// which is doubled whenever a timeout fires.
+    pub cst_timeout: Duration,
+    /// The minimum interval between two application states served
+    /// to the same recovering node by the CST protocol.
+    pub state_serve_interval: Duration,
+    /// The maximum number of application states the CST protocol
+    /// serves at a time; further requests are deferred until one
+    /// of these transfers completes.
+    pub max_state_transfers: usize,
+    /// The size of the chunks the CST protocol splits the application
+    /// states it serves into, such that an interrupted transfer is
+    /// resumed by fetching only the missing chunks, from any replica
+    /// holding the same state. If `None`, states are sent whole.
+    pub state_chunk_size: Option<usize>,
+    /// The maximum number of bytes worth of state chunks served per
+    /// second by the CST protocol, such that large state transfers
+    /// don't starve consensus traffic. If `None`, chunks are sent
+    /// as fast as they are requested.
+    pub state_chunk_rate: Option<u64>,
+    /// The maximum number of chunks a recovering node buffers past the
+    /// first chunk it is missing from the state it is reassembling;
+    /// chunks further ahead are dropped, and requested again later.
+    pub state_chunk_window: usize,
+    /// A shared object store where states served by the CST protocol
+    /// are placed, and fetched from by recovering nodes. If `None`,
+    /// states are transferred directly between peer nodes.
+    pub checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    /// Receives the replies to executed client requests, and the states
+    /// reported for local checkpoints, which the executor failed to deliver
+    /// to this replica. If `None`, these outcomes are dropped.
+    pub dead_letters: Option<DeadLetterSink<S>>,
+    /// The maximum duration a consensus instance may remain in the
+    /// same phase, before `Message::ConsensusStalled` is emitted.
+    ///
+    /// The watchdog checks the consensus layer once every such
+    /// duration, so a stall is reported at most twice as late.
+    pub consensus_watchdog: Duration,
+    /// When to verify the signatures of the votes cast
+    /// by other replicas, in the consensus layer.
+    pub vote_verification: VoteVerification,
+    /// The number of replies to executed client requests kept
+    /// in memory, which are sent again to clients retransmitting
+    /// their requests, instead of executing them twice.
+    pub reply_cache_size: usize,
+    /// The ids of the observers attached to this replica, i.e. nodes
+    /// with an id in between `n` and `first_cli`, to whom every batch
+    /// decided by this replica is sent. Observers never vote, so they
+    /// aren't counted towards any quorum.
+    pub observers: Vec<NodeId>,
+    /// Whether replicas which aren't the leader of the current view
+    /// forward the client requests they receive to the leader, right
+    /// away, rather than only after the requests time out.
+    ///
+    /// This is only needed by clients which don't send their requests
+    /// to every replica, e.g. because they don't know the current leader.
+    pub forward_requests: bool,
+    /// Whether replicas which aren't the leader of the current view reject
+    /// the client requests they receive, pointing the clients to the leader
+    /// with a `RedirectMessage`. Takes precedence over `forward_requests`.
+    ///
+    /// This saves the forwarding round trip for clients which only send
+    /// their requests to the leader, i.e. clients with a `leader_hint`;
+    /// the requests of other clients still reach the leader directly.
+    pub redirect_requests: bool,
+    /// The rate at which the requests of each client are admitted into
+    /// the log. Requests exceeding it are dropped, and the client is told
+    /// to back off with a `ThrottledMessage`. If `None`, every request
+    /// is admitted.
+    pub client_rate_limit: Option<RateLimit>,
+    /// The highest priority clients may attach to their requests, with
+    /// a `RequestMeta`. Requests carrying a higher priority are scheduled
+    /// as if they carried this one, such that a client can't push its
+    /// requests ahead of every other client's. Use `0` to only schedule
+    /// requests by their deadlines, or `u32::MAX` to trust every client.
+    pub max_request_priority: u32,
+    /// Check out the docs on `NodeConfig`, which include the
+    /// tunables of the communication layer.
     pub node: NodeConfig,
 }
 
@@ -118,14 +332,57 @@ where
     S: Service + Send + 'static,
     State<S>: Send + Clone + 'static,
     Request<S>: Send + Clone + 'static,
-    Reply<S>: Send + 'static,
+    Reply<S>: Send + Clone + 'static,
 {
     /// Bootstrap a replica in `bafomet`.
     pub async fn bootstrap(cfg: ReplicaConfig<S>) -> Result<Self> {
+        Self::bootstrap_with(cfg, Node::bootstrap).await
+    }
+
+    /// Bootstrap a replica on top of a shard of a `Node`, along with the
+    /// rogue messages of the shard; check `Node::into_shards()`.
+    ///
+    /// Only the system params are read from `ReplicaConfig::node`.
+    pub async fn bootstrap_shard(
+        cfg: ReplicaConfig<S>,
+        node: Node<S::Data>,
+        rogue: Vec<Message<State<S>, Request<S>, Reply<S>>>,
+    ) -> Result<Self> {
+        Self::bootstrap_with(cfg, |_| async move { Ok((node, rogue)) }).await
+    }
+
+    // bootstraps a replica, connecting to its peer nodes with `connect`
+    async fn bootstrap_with<F, C>(cfg: ReplicaConfig<S>, connect: C) -> Result<Self>
+    where
+        C: FnOnce(NodeConfig) -> F,
+        F: Future<Output = Result<(Node<S::Data>, Vec<Message<State<S>, Request<S>, Reply<S>>>)>>,
+    {
         let ReplicaConfig {
             next_consensus_seq,
             node: node_config,
+            checkpoint_period,
             batch_size,
+            max_batch_bytes,
+            max_deciding_bytes,
+            max_log_bytes,
+            request_timeout,
+            max_failed_view_changes,
+            cst_timeout,
+            state_serve_interval,
+            max_state_transfers,
+            state_chunk_size,
+            state_chunk_rate,
+            state_chunk_window,
+            checkpoint_store,
+            dead_letters,
+            consensus_watchdog,
+            vote_verification,
+            reply_cache_size,
+            observers,
+            forward_requests,
+            redirect_requests,
+            client_rate_limit,
+            max_request_priority,
             service,
             view,
         } = cfg;
@@ -136,34 +393,62 @@ where
         let view = ViewInfo::new(view, n, f)?;
 
         // connect to peer nodes
-        let (node, rogue) = Node::bootstrap(node_config).await?;
+        let (node, rogue) = connect(node_config).await?;
 
-        // start executor
-        let executor = Executor::new(node.master_channel(), service)?;
+        // start executor; loading the initial state of the service
+        // may take a while, during which the messages of our peers
+        // are buffered in the channels of the node
+        let executor = Executor::new(
+            node.master_channel(),
+            service,
+            reply_cache_size,
+            dead_letters,
+        )
+        .await?;
 
         // start timeouts handler
         let timeouts = Timeouts::new(node.master_channel());
 
         // TODO: get log from persistent storage
-        let log = Log::new(batch_size);
+        let log = Log::new(
+            batch_size,
+            max_batch_bytes,
+            checkpoint_period,
+            max_log_bytes,
+        )
+        .with_max_priority(max_request_priority)
+        .with_max_request_wait(request_timeout)
+        .with_max_deciding_bytes(max_deciding_bytes);
 
-        // TODO:
-        // - client req timeout base dur configure param
-        // - cst timeout base dur configure param
-        // - ask for latest cid when bootstrapping
-        const CST_BASE_DUR: Duration = Duration::from_secs(30);
-        const REQ_BASE_DUR: Duration = Duration::from_secs(2 * 60);
+        // start watching the consensus layer
+        timeouts.timeout(consensus_watchdog, TimeoutKind::ConsensusWatchdog);
 
+        // TODO: ask for latest cid when bootstrapping
         let mut replica = Replica {
-            cst: CollabStateTransfer::new(CST_BASE_DUR),
-            synchronizer: Synchronizer::new(REQ_BASE_DUR, view),
-            consensus: Consensus::new(next_consensus_seq, batch_size),
+            cst: CollabStateTransfer::new(
+                cst_timeout,
+                state_serve_interval,
+                max_state_transfers,
+                checkpoint_store,
+            )
+            .with_chunk_size(state_chunk_size)
+            .with_chunk_rate(state_chunk_rate)
+            .with_chunk_window(state_chunk_window),
+            synchronizer: Synchronizer::new(request_timeout, view, max_failed_view_changes),
+            consensus: Consensus::new(next_consensus_seq, batch_size, vote_verification),
             phase: ReplicaPhase::NormalPhase,
             phase_stack: None,
             timeouts,
             executor,
             node,
             log,
+            consensus_watchdog,
+            pending_reads: Vec::new(),
+            observers,
+            forward_requests,
+            redirect_requests,
+            throttle: client_rate_limit.map(RateLimiter::new),
+            shutting_down: None,
         };
 
         // handle rogue messages
@@ -171,17 +456,21 @@ where
             match message {
                 Message::System(header, message) => {
                     match message {
-                        request @ SystemMessage::Request(_) => {
-                            replica
-                                .synchronizer
-                                .watch_request(header.unique_digest(), &replica.timeouts);
-                            replica.log.insert(header, request);
+                        SystemMessage::Request(request) => {
+                            replica.request_received(header, request)?;
+                        }
+                        SystemMessage::ReadRequest(request) => {
+                            replica.read_request_received(header, request)?;
                         }
                         SystemMessage::Consensus(message) => {
-                            replica.consensus.queue(header, message);
+                            let members = replica.synchronizer.view().members();
+                            replica.consensus.queue(header, message, members);
                         }
                         // FIXME: handle rogue reply messages
                         SystemMessage::Reply(_) => panic!("Rogue reply message detected"),
+                        SystemMessage::ReplyBatch(_) => {
+                            panic!("Rogue reply batch message detected")
+                        }
                         // FIXME: handle rogue cst messages
                         SystemMessage::Cst(_) => panic!("Rogue cst message detected"),
                         // FIXME: handle rogue view change messages
@@ -192,6 +481,30 @@ where
                         SystemMessage::ForwardedRequests(_) => {
                             panic!("Rogue forwarded requests message detected")
                         }
+                        SystemMessage::ForwardRequest(request) => {
+                            replica.forwarded_request_received(request);
+                        }
+                        // a leader will not step down before
+                        // every replica has bootstrapped
+                        SystemMessage::LeaderStepDown(_) => (),
+                        // no batch has been proposed yet
+                        SystemMessage::RequestMissing(_) => (),
+                        SystemMessage::QueryStatus(message) => {
+                            replica.query_status_received(header, message)?;
+                        }
+                        SystemMessage::CancelRequest(message) => {
+                            replica.cancel_request_received(header, message);
+                        }
+                        // only clients query the status of their requests
+                        SystemMessage::QueryStatusReply(_) => (),
+                        // only observers follow decided batches
+                        SystemMessage::Decided(_) => (),
+                        // only clients are redirected
+                        SystemMessage::Redirect(_) => (),
+                        // only clients are throttled
+                        SystemMessage::Throttled(_) => (),
+                        // handled by the read task of the connection
+                        SystemMessage::Goodbye => (),
                     }
                 }
                 // ignore other messages for now
@@ -203,14 +516,24 @@ where
     }
 
     /// The main loop of a replica.
-    pub async fn run(&mut self) -> Result<()> {
-        // TODO: exit condition?
+    ///
+    /// Together with `Replica::bootstrap()`, this is the entrypoint of
+    /// a replica: the returned future drives every component of the
+    /// replica, and only resolves if an unrecoverable error occurs, or
+    /// with `Ok(())`, once the replica is shut down through
+    /// `ReplicaHandle::shutdown()`.
+    pub async fn run(mut self) -> Result<()> {
         loop {
             match self.phase {
                 ReplicaPhase::RetrievingState => self.update_retrieving_state().await?,
                 ReplicaPhase::NormalPhase => self.update_normal_phase().await?,
                 ReplicaPhase::SyncPhase => self.update_sync_phase().await.map(|_| ())?,
             }
+            if let Some((timeout, tx)) = self.shutting_down.take() {
+                let result = self.shutdown(timeout).await;
+                tx.send(result).unwrap_or(());
+                return Ok(());
+            }
         }
     }
 
@@ -225,17 +548,28 @@ where
                         // while we are retrieving state...
                         self.forwarded_requests_received(requests);
                     }
-                    request @ SystemMessage::Request(_) => {
-                        self.synchronizer
-                            .watch_request(header.unique_digest(), &self.timeouts);
-                        self.log.insert(header, request);
+                    SystemMessage::ForwardRequest(request) => {
+                        self.forwarded_request_received(request);
+                    }
+                    SystemMessage::Request(request) => {
+                        self.request_received(header, request)?;
+                    }
+                    SystemMessage::ReadRequest(request) => {
+                        self.read_request_received(header, request)?;
                     }
                     SystemMessage::Consensus(message) => {
-                        self.consensus.queue(header, message);
+                        let members = self.synchronizer.view().members();
+                        self.consensus.queue(header, message, members);
                     }
                     SystemMessage::ViewChange(message) => {
                         self.synchronizer.queue(header, message);
                     }
+                    // we are lagging behind, so we will
+                    // catch up with the new view later
+                    SystemMessage::LeaderStepDown(_) => (),
+                    SystemMessage::RequestMissing(message) => {
+                        self.request_missing_received(header, message);
+                    }
                     SystemMessage::Cst(message) => {
                         let status = self.cst.process_message(
                             CstProgress::Message(header, message),
@@ -247,8 +581,10 @@ where
                         match status {
                             CstStatus::Running => (),
                             CstStatus::State(state) => {
+                                let changing_to = self.synchronizer.view().sequence_number().next();
                                 install_recovery_state(
                                     state,
+                                    &self.node,
                                     &mut self.synchronizer,
                                     &mut self.log,
                                     &mut self.executor,
@@ -256,9 +592,20 @@ where
                                 )?;
                                 self.phase =
                                     self.phase_stack.take().unwrap_or(ReplicaPhase::NormalPhase);
+                                // the view change we were stuck in was
+                                // finished by our peers, without us
+                                if self.synchronizer.caught_up(changing_to) {
+                                    self.phase = ReplicaPhase::NormalPhase;
+                                }
+                            }
+                            CstStatus::Delta(delta) => {
+                                install_delta(delta, &mut self.log, &mut self.executor)?;
+                                self.phase =
+                                    self.phase_stack.take().unwrap_or(ReplicaPhase::NormalPhase);
                             }
                             CstStatus::SeqNo(seq) => {
-                                if self.consensus.sequence_number() < seq {
+                                let ours = self.consensus.sequence_number();
+                                if ours < seq {
                                     // this step will allow us to ignore any messages
                                     // for older consensus instances we may have had stored;
                                     //
@@ -268,11 +615,24 @@ where
                                     // `install_recovery_state` from cst
                                     self.consensus.install_sequence_number(seq);
 
-                                    self.cst.request_latest_state(
-                                        &self.synchronizer,
-                                        &self.timeouts,
-                                        &mut self.node,
-                                    );
+                                    // if we are only slightly behind, our peers likely
+                                    // still retain the instances we missed, since their
+                                    // last checkpoint, so we only fetch their requests
+                                    if seq.distance(ours) < self.log.checkpoint_period() {
+                                        self.cst.request_delta(
+                                            ours,
+                                            seq,
+                                            &self.synchronizer,
+                                            &self.timeouts,
+                                            &mut self.node,
+                                        );
+                                    } else {
+                                        self.cst.request_latest_state(
+                                            &self.synchronizer,
+                                            &self.timeouts,
+                                            &mut self.node,
+                                        );
+                                    }
                                 } else {
                                     self.phase = ReplicaPhase::NormalPhase;
                                 }
@@ -291,19 +651,39 @@ where
                                     &mut self.node,
                                 );
                             }
+                            CstStatus::RequestChunks => {
+                                self.cst
+                                    .request_missing_chunks(&self.timeouts, &mut self.node);
+                            }
                             // should not happen...
                             CstStatus::Nil => {
-                                return Err("Invalid state reached!")
-                                    .wrapped(ErrorKind::CoreServer);
+                                return Err("Invalid state reached!").wrapped(ErrorKind::Cst);
                             }
                         }
                     }
+                    SystemMessage::QueryStatus(message) => {
+                        self.query_status_received(header, message)?;
+                    }
+                    SystemMessage::CancelRequest(message) => {
+                        self.cancel_request_received(header, message);
+                    }
                     // FIXME: handle rogue reply messages
                     SystemMessage::Reply(_) => panic!("Rogue reply message detected"),
+                    SystemMessage::ReplyBatch(_) => panic!("Rogue reply batch message detected"),
+                    // only clients query the status of their requests
+                    SystemMessage::QueryStatusReply(_) => (),
+                    // only observers follow decided batches
+                    SystemMessage::Decided(_) => (),
+                    // only clients are redirected
+                    SystemMessage::Redirect(_) => (),
+                    // only clients are throttled
+                    SystemMessage::Throttled(_) => (),
+                    // handled by the read task of the connection
+                    SystemMessage::Goodbye => (),
                 }
             }
             Message::Timeout(timeout_kind) => {
-                self.timeout_received(timeout_kind);
+                self.timeout_received(timeout_kind)?;
             }
             Message::ExecutionFinished(batch)
             | Message::ExecutionFinishedWithAppstate(batch, _) => {
@@ -311,12 +691,44 @@ where
                 // receiving state from peer nodes is correct
                 self.execution_finished(batch);
             }
-            Message::ConnectedTx(id, sock) => self.node.handle_connected_tx(id, sock),
-            Message::ConnectedRx(id, sock) => self.node.handle_connected_rx(id, sock),
+            Message::ConnectedTx(id, sock, codec) => self.node.handle_connected_tx(id, sock, codec),
+            Message::ConnectedBulkTx(id, sock, codec) => {
+                self.node.handle_connected_bulk_tx(id, sock, codec)
+            }
+            Message::ConnectedRx(id, sock, codec) => self.node.handle_connected_rx(id, sock, codec),
+            // clients come and go, e.g. when their idle connections are reaped
+            Message::DisconnectedTx(id) if self.node.is_client(id) => {
+                self.node.handle_disconnected_tx(id)
+            }
+            Message::DisconnectedRx(Some(id)) if self.node.is_client(id) => {
+                self.node.handle_disconnected_rx(id)
+            }
+            // a planned departure, e.g. of a replica being restarted,
+            // rather than a crash, so no alarms are raised
+            Message::Departed(id) => self.node.handle_disconnected_rx(id),
+            // replicas are reconnected to, if configured to
+            Message::DisconnectedTx(id) if self.node.reconnects() => {
+                self.node.handle_disconnected_tx(id)
+            }
+            Message::DisconnectedRx(Some(id)) if self.node.reconnects() => {
+                self.node.handle_disconnected_rx(id)
+            }
             // TODO: node disconnected on send side
             Message::DisconnectedTx(id) => panic!("{:?} disconnected", id),
             // TODO: node disconnected on receive side
             Message::DisconnectedRx(some_id) => panic!("{:?} disconnected", some_id),
+            // the connection is dropped by its read task
+            Message::Error(_, _) => (),
+            // we are recovering the latest state already
+            Message::ConsensusStalled(_, _) => (),
+            Message::StatusQueried(id, reply) => {
+                self.node.send(SystemMessage::QueryStatusReply(reply), id);
+            }
+            Message::ClusterUnrecoverable(view) => {
+                let s = format!("Gave up on changing views, at view {:?}", view);
+                return Err(s).wrapped(ErrorKind::ViewChange);
+            }
+            Message::Command(command) => self.command_received(command),
         }
 
         Ok(())
@@ -344,15 +756,27 @@ where
             Message::System(header, message) => {
                 match message {
                     SystemMessage::Consensus(message) => {
-                        self.consensus.queue(header, message);
+                        self.synchronizer
+                            .consensus_received(header.from(), message.view());
+                        let members = self.synchronizer.view().members();
+                        self.consensus.queue(header, message, members);
                     }
                     SystemMessage::ForwardedRequests(requests) => {
                         self.forwarded_requests_received(requests);
                     }
-                    request @ SystemMessage::Request(_) => {
-                        self.synchronizer
-                            .watch_request(header.unique_digest(), &self.timeouts);
-                        self.log.insert(header, request);
+                    SystemMessage::ForwardRequest(request) => {
+                        self.forwarded_request_received(request);
+                    }
+                    // we are already changing views
+                    SystemMessage::LeaderStepDown(_) => (),
+                    SystemMessage::RequestMissing(message) => {
+                        self.request_missing_received(header, message);
+                    }
+                    SystemMessage::Request(request) => {
+                        self.request_received(header, request)?;
+                    }
+                    SystemMessage::ReadRequest(request) => {
+                        self.read_request_received(header, request)?;
                     }
                     SystemMessage::Cst(message) => {
                         let status = self.cst.process_message(
@@ -362,13 +786,8 @@ where
                             &self.log,
                             &mut self.node,
                         );
-                        match status {
-                            CstStatus::Nil => (),
-                            // should not happen...
-                            _ => {
-                                return Err("Invalid state reached!").wrapped(ErrorKind::CoreServer)
-                            }
-                        }
+                        // should not return anything other than `CstStatus::Nil`
+                        status.expect_nil()?;
                     }
                     SystemMessage::ViewChange(message) => {
                         let status = self.synchronizer.process_message(
@@ -393,12 +812,29 @@ where
                             }
                             // should not happen...
                             _ => {
-                                return Err("Invalid state reached!").wrapped(ErrorKind::CoreServer)
+                                return Err("Invalid state reached!").wrapped(ErrorKind::ViewChange)
                             }
                         }
                     }
+                    SystemMessage::QueryStatus(message) => {
+                        self.query_status_received(header, message)?;
+                    }
+                    SystemMessage::CancelRequest(message) => {
+                        self.cancel_request_received(header, message);
+                    }
                     // FIXME: handle rogue reply messages
                     SystemMessage::Reply(_) => panic!("Rogue reply message detected"),
+                    SystemMessage::ReplyBatch(_) => panic!("Rogue reply batch message detected"),
+                    // only clients query the status of their requests
+                    SystemMessage::QueryStatusReply(_) => (),
+                    // only observers follow decided batches
+                    SystemMessage::Decided(_) => (),
+                    // only clients are redirected
+                    SystemMessage::Redirect(_) => (),
+                    // only clients are throttled
+                    SystemMessage::Throttled(_) => (),
+                    // handled by the read task of the connection
+                    SystemMessage::Goodbye => (),
                 }
             }
             //////// XXX XXX XXX XXX
@@ -408,7 +844,7 @@ where
             //
             //
             Message::Timeout(timeout_kind) => {
-                self.timeout_received(timeout_kind);
+                self.timeout_received(timeout_kind)?;
             }
             Message::ExecutionFinished(batch) => {
                 self.execution_finished(batch);
@@ -416,12 +852,44 @@ where
             Message::ExecutionFinishedWithAppstate(batch, appstate) => {
                 self.execution_finished_with_appstate(batch, appstate)?;
             }
-            Message::ConnectedTx(id, sock) => self.node.handle_connected_tx(id, sock),
-            Message::ConnectedRx(id, sock) => self.node.handle_connected_rx(id, sock),
+            Message::ConnectedTx(id, sock, codec) => self.node.handle_connected_tx(id, sock, codec),
+            Message::ConnectedBulkTx(id, sock, codec) => {
+                self.node.handle_connected_bulk_tx(id, sock, codec)
+            }
+            Message::ConnectedRx(id, sock, codec) => self.node.handle_connected_rx(id, sock, codec),
+            // clients come and go, e.g. when their idle connections are reaped
+            Message::DisconnectedTx(id) if self.node.is_client(id) => {
+                self.node.handle_disconnected_tx(id)
+            }
+            Message::DisconnectedRx(Some(id)) if self.node.is_client(id) => {
+                self.node.handle_disconnected_rx(id)
+            }
+            // a planned departure, e.g. of a replica being restarted,
+            // rather than a crash, so no alarms are raised
+            Message::Departed(id) => self.node.handle_disconnected_rx(id),
+            // replicas are reconnected to, if configured to
+            Message::DisconnectedTx(id) if self.node.reconnects() => {
+                self.node.handle_disconnected_tx(id)
+            }
+            Message::DisconnectedRx(Some(id)) if self.node.reconnects() => {
+                self.node.handle_disconnected_rx(id)
+            }
             // TODO: node disconnected on send side
             Message::DisconnectedTx(id) => panic!("{:?} disconnected", id),
             // TODO: node disconnected on receive side
             Message::DisconnectedRx(some_id) => panic!("{:?} disconnected", some_id),
+            // the connection is dropped by its read task
+            Message::Error(_, _) => (),
+            // the view change restarts the stalled instance
+            Message::ConsensusStalled(_, _) => (),
+            Message::StatusQueried(id, reply) => {
+                self.node.send(SystemMessage::QueryStatusReply(reply), id);
+            }
+            Message::ClusterUnrecoverable(view) => {
+                let s = format!("Gave up on changing views, at view {:?}", view);
+                return Err(s).wrapped(ErrorKind::ViewChange);
+            }
+            Message::Command(command) => self.command_received(command),
         }
 
         Ok(true)
@@ -443,7 +911,10 @@ where
         //
         // the order of the next consensus message is guaranteed by
         // `TboQueue`, in the consensus module.
-        let message = match self.consensus.poll(&self.log) {
+        let message = match self
+            .consensus
+            .poll(&self.log, &self.synchronizer, &mut self.node)
+        {
             ConsensusPollStatus::Recv => self.node.receive().await?,
             ConsensusPollStatus::NextMessage(h, m) => {
                 Message::System(h, SystemMessage::Consensus(m))
@@ -463,10 +934,14 @@ where
                     SystemMessage::ForwardedRequests(requests) => {
                         self.forwarded_requests_received(requests);
                     }
-                    request @ SystemMessage::Request(_) => {
-                        self.synchronizer
-                            .watch_request(header.unique_digest(), &self.timeouts);
-                        self.log.insert(header, request);
+                    SystemMessage::ForwardRequest(request) => {
+                        self.forwarded_request_received(request);
+                    }
+                    SystemMessage::Request(request) => {
+                        self.request_received(header, request)?;
+                    }
+                    SystemMessage::ReadRequest(request) => {
+                        self.read_request_received(header, request)?;
                     }
                     SystemMessage::Cst(message) => {
                         let status = self.cst.process_message(
@@ -476,13 +951,8 @@ where
                             &self.log,
                             &mut self.node,
                         );
-                        match status {
-                            CstStatus::Nil => (),
-                            // should not happen...
-                            _ => {
-                                return Err("Invalid state reached!").wrapped(ErrorKind::CoreServer)
-                            }
-                        }
+                        // should not return anything other than `CstStatus::Nil`
+                        status.expect_nil()?;
                     }
                     SystemMessage::ViewChange(message) => {
                         let status = self.synchronizer.process_message(
@@ -499,10 +969,21 @@ where
                             SynchronizerStatus::Running => self.phase = ReplicaPhase::SyncPhase,
                             // should not happen...
                             _ => {
-                                return Err("Invalid state reached!").wrapped(ErrorKind::CoreServer)
+                                return Err("Invalid state reached!").wrapped(ErrorKind::ViewChange)
                             }
                         }
                     }
+                    SystemMessage::LeaderStepDown(message) => {
+                        let status =
+                            self.synchronizer
+                                .leader_stepped_down(header, message, &mut self.node);
+                        if let SynchronizerStatus::Running = status {
+                            self.phase = ReplicaPhase::SyncPhase;
+                        }
+                    }
+                    SystemMessage::RequestMissing(message) => {
+                        self.request_missing_received(header, message);
+                    }
                     SystemMessage::Consensus(message) => {
                         let seq = self.consensus.sequence_number();
                         let status = self.consensus.process_message(
@@ -515,8 +996,18 @@ where
                         match status {
                             // if deciding, nothing to do
                             ConsensusStatus::Deciding => rt::yield_now().await,
-                            // FIXME: implement this
-                            ConsensusStatus::VotedTwice(_) => todo!(),
+                            // the repeated vote wasn't counted; note that it
+                            // may have been forged, if vote signatures aren't
+                            // verified, so the replica isn't blamed for it
+                            ConsensusStatus::VotedTwice(_) => rt::yield_now().await,
+                            // the leader proposed two distinct batches for
+                            // the same instance; refuse both, and elect a
+                            // new leader
+                            ConsensusStatus::Equivocation(_) => {
+                                self.synchronizer
+                                    .begin_view_change(Some(Vec::new()), &mut self.node);
+                                self.phase = ReplicaPhase::SyncPhase;
+                            }
                             // reached agreement, execute requests
                             //
                             // FIXME: execution layer needs to receive the id
@@ -527,6 +1018,8 @@ where
                                     self.synchronizer.unwatch_request(digest);
                                 }
                                 let (info, batch) = self.log.finalize_batch(seq, digests)?;
+                                self.log.instance_decided(seq);
+                                self.notify_observers(seq, &batch);
                                 match info {
                                     // normal execution
                                     Info::Nil => self.executor.queue_update(batch)?,
@@ -536,8 +1029,11 @@ where
                                     }
                                 }
                                 self.consensus.next_instance();
+                                self.enforce_log_ceiling();
+                                self.serve_pending_reads()?;
                             }
                         }
+                        self.fallen_behind();
 
                         // we processed a consensus message,
                         // signal the consensus layer of this event
@@ -548,12 +1044,29 @@ where
                         // TBO queue in the consensus layer
                         rt::yield_now().await;
                     }
+                    SystemMessage::QueryStatus(message) => {
+                        self.query_status_received(header, message)?;
+                    }
+                    SystemMessage::CancelRequest(message) => {
+                        self.cancel_request_received(header, message);
+                    }
                     // FIXME: handle rogue reply messages
                     SystemMessage::Reply(_) => panic!("Rogue reply message detected"),
+                    SystemMessage::ReplyBatch(_) => panic!("Rogue reply batch message detected"),
+                    // only clients query the status of their requests
+                    SystemMessage::QueryStatusReply(_) => (),
+                    // only observers follow decided batches
+                    SystemMessage::Decided(_) => (),
+                    // only clients are redirected
+                    SystemMessage::Redirect(_) => (),
+                    // only clients are throttled
+                    SystemMessage::Throttled(_) => (),
+                    // handled by the read task of the connection
+                    SystemMessage::Goodbye => (),
                 }
             }
             Message::Timeout(timeout_kind) => {
-                self.timeout_received(timeout_kind);
+                self.timeout_received(timeout_kind)?;
             }
             Message::ExecutionFinished(batch) => {
                 self.execution_finished(batch);
@@ -561,25 +1074,438 @@ where
             Message::ExecutionFinishedWithAppstate(batch, appstate) => {
                 self.execution_finished_with_appstate(batch, appstate)?;
             }
-            Message::ConnectedTx(id, sock) => self.node.handle_connected_tx(id, sock),
-            Message::ConnectedRx(id, sock) => self.node.handle_connected_rx(id, sock),
+            Message::ConnectedTx(id, sock, codec) => self.node.handle_connected_tx(id, sock, codec),
+            Message::ConnectedBulkTx(id, sock, codec) => {
+                self.node.handle_connected_bulk_tx(id, sock, codec)
+            }
+            Message::ConnectedRx(id, sock, codec) => self.node.handle_connected_rx(id, sock, codec),
+            // clients come and go, e.g. when their idle connections are reaped
+            Message::DisconnectedTx(id) if self.node.is_client(id) => {
+                self.node.handle_disconnected_tx(id)
+            }
+            Message::DisconnectedRx(Some(id)) if self.node.is_client(id) => {
+                self.node.handle_disconnected_rx(id)
+            }
+            // a planned departure, e.g. of a replica being restarted,
+            // rather than a crash, so no alarms are raised
+            Message::Departed(id) => self.node.handle_disconnected_rx(id),
+            // replicas are reconnected to, if configured to
+            Message::DisconnectedTx(id) if self.node.reconnects() => {
+                self.node.handle_disconnected_tx(id)
+            }
+            Message::DisconnectedRx(Some(id)) if self.node.reconnects() => {
+                self.node.handle_disconnected_rx(id)
+            }
             // TODO: node disconnected on send side
             Message::DisconnectedTx(id) => panic!("{:?} disconnected", id),
             // TODO: node disconnected on receive side
             Message::DisconnectedRx(some_id) => panic!("{:?} disconnected", some_id),
+            // the connection is dropped by its read task
+            Message::Error(_, _) => (),
+            Message::ConsensusStalled(seq, _) => self.consensus_stalled(seq, Instant::now()),
+            Message::StatusQueried(id, reply) => {
+                self.node.send(SystemMessage::QueryStatusReply(reply), id);
+            }
+            Message::ClusterUnrecoverable(view) => {
+                let s = format!("Gave up on changing views, at view {:?}", view);
+                return Err(s).wrapped(ErrorKind::ViewChange);
+            }
+            Message::Command(command) => self.command_received(command),
         }
         Ok(())
     }
 
+    // sends a batch decided by the consensus instance
+    // `seq` to the observers attached to this replica
+    fn notify_observers(&mut self, seq: SeqNo, batch: &UpdateBatch<Request<S>>) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let message = SystemMessage::Decided(DecidedMessage::new(seq, batch.clone()));
+        let targets = self.observers.iter().copied();
+        self.node.broadcast(message, targets);
+    }
+
+    /// Returns a handle to this replica, through which it is
+    /// administered once it is moved into `run()`.
+    pub fn handle(&self) -> ReplicaHandle<S> {
+        ReplicaHandle::new(self.node.master_channel())
+    }
+
+    // carries out a command issued through a `ReplicaHandle`; its
+    // outcome is discarded if the handle was dropped in the meantime
+    fn command_received(&mut self, command: ReplicaCommand<State<S>>) {
+        match command.kind {
+            CommandKind::Metrics(tx) => tx.send(self.metrics_prometheus()).unwrap_or(()),
+            CommandKind::CurrentView(tx) => tx.send(self.current_view()).unwrap_or(()),
+            CommandKind::StepDown(tx) => tx.send(self.step_down()).unwrap_or(()),
+            CommandKind::PauseIngestion(tx) => {
+                self.pause_ingestion();
+                tx.send(()).unwrap_or(())
+            }
+            CommandKind::ResumeIngestion(tx) => {
+                self.resume_ingestion();
+                tx.send(()).unwrap_or(())
+            }
+            CommandKind::PersistSnapshot(path, tx) => {
+                tx.send(self.persist_snapshot(path)).unwrap_or(())
+            }
+            CommandKind::RestoreSnapshot(path, tx) => {
+                tx.send(self.restore_snapshot(path)).unwrap_or(())
+            }
+            #[cfg(feature = "admin")]
+            CommandKind::ForceInstallCheckpoint(seq, state, tx) => tx
+                .send(self.force_install_checkpoint(seq, state))
+                .unwrap_or(()),
+            CommandKind::Shutdown(timeout, tx) => self.shutting_down = Some((timeout, tx)),
+            #[cfg(not(feature = "admin"))]
+            CommandKind::Never(never, _) => match never {},
+        }
+    }
+
+    /// Shuts down this replica, e.g. during a rolling restart, after
+    /// sending the replies of the requests queued for execution to
+    /// their clients, and flushing the sockets of the peers, such
+    /// that these replies are not lost.
+    ///
+    /// Requests received in the meantime are dropped. If draining the
+    /// executor and flushing the sockets take longer than `timeout`,
+    /// the `Node` of the replica is shut down regardless, and an error
+    /// is returned. The replica should not be used afterwards.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<()> {
+        let started = Instant::now();
+        let drained = select! {
+            result = self.drain_executor().fuse() => result,
+            _ = Delay::new(timeout).fuse() => {
+                Err("Timed out draining the executor").wrapped(ErrorKind::CoreServer)
+            },
+        };
+        let flushed = match drained {
+            Ok(_) => {
+                self.node
+                    .flush(timeout.saturating_sub(started.elapsed()))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        self.node.shutdown().await;
+        flushed
+    }
+
+    // waits for the executor to deliver the outcomes of the requests
+    // queued so far, and answers the clients with them
+    async fn drain_executor(&mut self) -> Result<()> {
+        self.executor
+            .queue_barrier()?
+            .await
+            .simple_msg(ErrorKind::CoreServer, "The executor has exited")?;
+        while let Some(message) = self.node.receive().now_or_never() {
+            match message? {
+                Message::ExecutionFinished(batch)
+                | Message::ExecutionFinishedWithAppstate(batch, _) => {
+                    self.execution_finished(batch);
+                }
+                Message::StatusQueried(id, reply) => {
+                    self.node.send(SystemMessage::QueryStatusReply(reply), id);
+                }
+                // we are shutting down, so any other
+                // message, e.g. a new request, is dropped
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands off the leadership of the current view to the next leader,
+    /// e.g. before this replica is taken down for maintenance.
+    ///
+    /// Upon receiving the step down message, every replica (including this
+    /// one) immediately starts a view change, rather than waiting for
+    /// client requests to time out.
+    pub fn step_down(&mut self) -> Result<()> {
+        if self.node.id() != self.synchronizer.view().leader() {
+            return Err("Only the leader of the current view may step down")
+                .wrapped(ErrorKind::CoreServer);
+        }
+        self.synchronizer.step_down(&mut self.node);
+        Ok(())
+    }
+
+    /// Stops admitting client requests, e.g. while the application
+    /// carries out maintenance which would grow the log unboundedly.
+    ///
+    /// Clients sending requests in the meantime are told to back off
+    /// with a `ThrottledMessage`, while the requests forwarded by other
+    /// replicas are deferred until `resume_ingestion()` is called.
+    pub fn pause_ingestion(&mut self) {
+        self.log.pause_ingestion();
+    }
+
+    /// Resumes admitting client requests, after `pause_ingestion()`.
+    pub fn resume_ingestion(&mut self) {
+        self.log.resume_ingestion();
+    }
+
+    /// Returns the view currently installed by this replica.
+    pub fn current_view(&self) -> ViewInfo {
+        self.synchronizer.view().clone()
+    }
+
+    /// Returns the leader of the view currently installed by this
+    /// replica, e.g. for clients to send their requests to it directly.
+    pub fn current_leader(&self) -> NodeId {
+        self.synchronizer.view().leader()
+    }
+
+    /// Renders the metrics gathered by this replica in the Prometheus
+    /// text exposition format, e.g. to be served by a thin HTTP wrapper
+    /// scraped by Prometheus.
+    ///
+    /// Every sample is labeled with the id of this replica, and the
+    /// sequence number of its current view.
+    pub fn metrics_prometheus(&self) -> String {
+        let labels = format!(
+            "node=\"{}\",view=\"{}\"",
+            u32::from(self.node.id()),
+            u32::from(self.synchronizer.view().sequence_number()),
+        );
+        let connected_peers = self
+            .synchronizer
+            .view()
+            .members()
+            .iter()
+            .filter(|&&id| id != self.node.id() && self.node.is_connected(id))
+            .count();
+        let queued = |counts: Vec<(SeqNo, usize)>| counts.iter().map(|&(_, n)| n).sum::<usize>();
+
+        let mut buf = String::new();
+        write_metric(
+            &mut buf,
+            "bafomet_consensus_seq",
+            "The sequence number of the current consensus instance.",
+            "gauge",
+            &labels,
+            &[("", u32::from(self.consensus.sequence_number()) as u64)],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_consensus_deciding",
+            "Whether the current consensus instance is being decided.",
+            "gauge",
+            &labels,
+            &[("", self.consensus.is_deciding() as u64)],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_consensus_queued_messages",
+            "The consensus messages waiting for their instance to be processed.",
+            "gauge",
+            &labels,
+            &[
+                (
+                    "phase=\"pre_prepare\"",
+                    queued(self.consensus.queued_pre_prepares()) as u64,
+                ),
+                (
+                    "phase=\"prepare\"",
+                    queued(self.consensus.queued_prepares()) as u64,
+                ),
+                (
+                    "phase=\"commit\"",
+                    queued(self.consensus.queued_commits()) as u64,
+                ),
+            ],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_log_decided_bytes",
+            "The serialized size of the requests decided since the last checkpoint.",
+            "gauge",
+            &labels,
+            &[("", self.log.decided_bytes() as u64)],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_log_deciding_bytes",
+            "The serialized size of the requests being decided.",
+            "gauge",
+            &labels,
+            &[("", self.log.deciding_bytes() as u64)],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_log_pending_execution",
+            "The batches decided since the last checkpoint.",
+            "gauge",
+            &labels,
+            &[("", self.log.pending_execution_len() as u64)],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_pending_reads",
+            "The read only requests waiting for an update to be decided.",
+            "gauge",
+            &labels,
+            &[("", self.pending_reads.len() as u64)],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_connected_peers",
+            "The replicas of the current view this replica is connected to.",
+            "gauge",
+            &labels,
+            &[("", connected_peers as u64)],
+        );
+        write_metric(
+            &mut buf,
+            "bafomet_dropped_client_messages_total",
+            "The client messages dropped because the inbound channel was saturated.",
+            "counter",
+            &labels,
+            &[("", self.node.dropped_client_messages())],
+        );
+        buf
+    }
+
+    /// Forces this replica onto the known good checkpoint `state`, taken
+    /// after the batch with sequence number `seq` was executed, dropping
+    /// every decision after it, and resuming consensus with the
+    /// following instance.
+    ///
+    /// This is a last resort tool for operators recovering a wedged
+    /// cluster, e.g. after the replicas diverged, so it is only available
+    /// with the `admin` feature; every replica should be pinned to the
+    /// same checkpoint.
+    #[cfg(feature = "admin")]
+    pub fn force_install_checkpoint(&mut self, seq: SeqNo, state: State<S>) -> Result<()> {
+        use crate::bft::consensus::log::{Checkpoint, DecisionLog, ExecutedRequests};
+
+        let next_seq = seq.next();
+        let recovery_state = RecoveryState::new(
+            self.synchronizer.view().clone(),
+            Checkpoint::new(seq, state.clone()),
+            Vec::new(),
+            DecisionLog::new(),
+        );
+        self.consensus.install_sequence_number(next_seq);
+        self.executor.install_state(state, Vec::new())?;
+        // the batch being decided belonged to an abandoned instance, and
+        // the forced checkpoint carries no record of executed requests
+        self.log.drop_deciding();
+        self.log
+            .install_state(next_seq, recovery_state, ExecutedRequests::default());
+        self.phase = ReplicaPhase::NormalPhase;
+        self.phase_stack = None;
+
+        Ok(())
+    }
+
+    /// Writes a snapshot of the durable state of this replica to `path`,
+    /// i.e. the sequence number of the current consensus instance, the
+    /// latest checkpoint, and the requests and decision log since then.
+    ///
+    /// The snapshot is written to a temporary file, synced to disk, and
+    /// only then renamed to `path`, whose directory is synced in turn, so
+    /// a crash never leaves a partially written snapshot behind. This
+    /// method fails if no checkpoint was finalized yet.
+    pub fn persist_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let seq = self.consensus.sequence_number();
+        let recovery_state = self.log.snapshot(self.synchronizer.view().clone())?;
+
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        let file = File::create(&tmp_path).wrapped(ErrorKind::CoreServer)?;
+        let mut writer = BufWriter::new(file);
+        seq.to_bytes(&mut writer)?;
+        recovery_state.write_to::<S::Data, _>(&mut writer)?;
+        let file = writer.into_inner().wrapped(ErrorKind::CoreServer)?;
+        file.sync_all().wrapped(ErrorKind::CoreServer)?;
+
+        fs::rename(&tmp_path, path).wrapped(ErrorKind::CoreServer)?;
+        // the rename only survives a crash once the
+        // directory entry pointing to the snapshot is synced
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .wrapped(ErrorKind::CoreServer)
+    }
+
+    /// Restores the snapshot written to `path` by `persist_snapshot()`,
+    /// e.g. when restarting after a crash.
+    ///
+    /// The view, the log, the application state and the consensus layer
+    /// are rebuilt from the same snapshot, and consensus resumes with the
+    /// instance that was running when the snapshot was taken.
+    pub fn restore_snapshot<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let file = File::open(path).wrapped(ErrorKind::CoreServer)?;
+        let mut reader = BufReader::new(file);
+
+        let seq = SeqNo::from_bytes(&mut reader)?;
+        let recovery_state =
+            RecoveryState::read_from::<S::Data, _>(&mut reader, self.node.public_keys())?;
+
+        install_recovery_state(
+            recovery_state,
+            &self.node,
+            &mut self.synchronizer,
+            &mut self.log,
+            &mut self.executor,
+            &mut self.consensus,
+        )?;
+        self.consensus.install_sequence_number(seq);
+
+        Ok(())
+    }
+
     fn execution_finished(&mut self, batch: UpdateBatchReplies<Reply<S>>) {
-        // deliver replies to clients
+        // coalesce the replies destined to the same client
+        let mut replies: HashMap<NodeId, Vec<ReplyMessage<Reply<S>>>> = collections::hash_map();
         for update_reply in batch.into_inner() {
+            let trace_id = update_reply.trace_id();
             let (peer_id, digest, payload) = update_reply.into_inner();
-            let message = SystemMessage::Reply(ReplyMessage::new(digest, payload));
+            let reply = ReplyMessage::new(digest, payload).with_trace_id(trace_id);
+            replies.entry(peer_id).or_insert_with(Vec::new).push(reply);
+        }
+
+        // deliver replies to clients
+        for (peer_id, mut replies) in replies {
+            let message = if replies.len() == 1 {
+                SystemMessage::Reply(replies.swap_remove(0))
+            } else {
+                SystemMessage::ReplyBatch(ReplyBatchMessage::new(replies))
+            };
             self.node.send(message, peer_id);
         }
     }
 
+    fn query_status_received(&mut self, header: Header, message: QueryStatusMessage) -> Result<()> {
+        let digest = *message.digest();
+        if self.log.is_request_pending(header.from(), &digest) {
+            let reply = QueryStatusReplyMessage::new(digest, RequestStatus::Pending);
+            self.node
+                .send(SystemMessage::QueryStatusReply(reply), header.from());
+            return Ok(());
+        }
+        // requests no longer in the log were either executed,
+        // or are queued for execution, in which case the executor
+        // answers after executing them
+        self.executor.queue_query_status(header.from(), digest)
+    }
+
+    fn cancel_request_received(&mut self, header: Header, message: CancelRequestMessage) {
+        // only the client which sent a request may withdraw it;
+        // requests already proposed are executed regardless
+        let digest = message.digest();
+        if self.log.cancel_request(header.from(), digest) {
+            self.synchronizer.unwatch_request(digest);
+        }
+    }
+
     fn execution_finished_with_appstate(
         &mut self,
         batch: UpdateBatchReplies<Reply<S>>,
@@ -601,12 +1527,240 @@ where
         Ok(())
     }
 
-    fn forwarded_requests_received(&mut self, requests: ForwardedRequestsMessage<Request<S>>) {
+    fn request_received(
+        &mut self,
+        header: Header,
+        request: RequestMessage<Request<S>>,
+    ) -> Result<()> {
+        // drop replayed requests before they reach the consensus layer;
+        // retransmitted requests are answered with their cached reply
+        if self
+            .log
+            .is_replayed(header.from(), request.sequence_number())
+        {
+            return self.executor.queue_retransmission(
+                header.from(),
+                request.sequence_number(),
+                header.unique_digest(),
+            );
+        }
+        let throttled = self
+            .throttle
+            .as_mut()
+            .and_then(|throttle| throttle.throttle(header.from(), Instant::now()));
+        // while ingestion is paused, clients are told to try again
+        // later, rather than having their requests pile up in the log
+        let retry_after = throttled.or_else(|| {
+            self.log
+                .is_ingestion_paused()
+                .then_some(PAUSED_INGESTION_RETRY_AFTER)
+        });
+        if let Some(retry_after) = retry_after {
+            let seq = request.sequence_number();
+            let message = SystemMessage::Throttled(ThrottledMessage::new(seq, retry_after));
+            self.node.send(message, header.from());
+            return Ok(());
+        }
+        if let Some(leader) = self.redirect_target() {
+            let rejected = StoredMessage::new(header, request);
+            let message = SystemMessage::Redirect(RedirectMessage::new(leader, rejected));
+            self.node.send(message, header.from());
+            return Ok(());
+        }
+        if let Some(leader) = self.forward_target() {
+            let forwarded = StoredMessage::new(header, request.clone());
+            let message = SystemMessage::ForwardRequest(ForwardRequestMessage::new(forwarded));
+            self.node.send(message, leader);
+        }
         self.synchronizer
-            .watch_forwarded_requests(requests, &self.timeouts, &mut self.log);
+            .watch_request(header.unique_digest(), &self.timeouts);
+        self.log.insert(header, SystemMessage::Request(request));
+        Ok(())
+    }
+
+    // the leader of the current view, unless we are the leader
+    fn leader_if_follower(&self) -> Option<NodeId> {
+        let leader = self.synchronizer.view().leader();
+        if leader != self.node.id() {
+            Some(leader)
+        } else {
+            None
+        }
+    }
+
+    // the leader client requests are forwarded to, if any;
+    // only followers forward requests, if configured to
+    fn forward_target(&self) -> Option<NodeId> {
+        self.leader_if_follower().filter(|_| self.forward_requests)
+    }
+
+    // the leader clients are redirected to, if any;
+    // only followers redirect clients, if configured to
+    fn redirect_target(&self) -> Option<NodeId> {
+        self.leader_if_follower().filter(|_| self.redirect_requests)
+    }
+
+    fn read_request_received(
+        &mut self,
+        header: Header,
+        request: ReadRequestMessage<Request<S>>,
+    ) -> Result<()> {
+        let ready = request
+            .after()
+            .map(|seq| self.log.has_decided(header.from(), seq))
+            .unwrap_or(true);
+        if ready {
+            let digest = header.unique_digest();
+            return self
+                .executor
+                .queue_read(header.from(), digest, request.into_inner());
+        }
+        // wait for the update request the client
+        // wants to observe to be decided
+        let now = Instant::now();
+        self.expire_pending_reads(now);
+        let from = header.from();
+        let queued = self
+            .pending_reads
+            .iter()
+            .filter(|(_, stored)| stored.header().from() == from)
+            .count();
+        if self.pending_reads.len() < MAX_PENDING_READS && queued < MAX_PENDING_READS_PER_CLIENT {
+            let stored = StoredMessage::new(header, request);
+            self.pending_reads.push((now, stored));
+        }
+        Ok(())
+    }
+
+    // drops the pending read only requests which
+    // waited for longer than `PENDING_READS_EXPIRY`
+    fn expire_pending_reads(&mut self, now: Instant) {
+        self.pending_reads
+            .retain(|(arrived, _)| now.saturating_duration_since(*arrived) < PENDING_READS_EXPIRY);
     }
 
-    fn timeout_received(&mut self, timeout_kind: TimeoutKind) {
+    // serves the pending read only requests whose
+    // update requests were decided in the meantime
+    fn serve_pending_reads(&mut self) -> Result<()> {
+        self.expire_pending_reads(Instant::now());
+        let log = &self.log;
+        let (ready, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_reads)
+            .into_iter()
+            .partition(|(_, stored)| {
+                let from = stored.header().from();
+                let seq = stored.message().after().unwrap();
+                log.has_decided(from, seq)
+            });
+        self.pending_reads = pending;
+        for (_, stored) in ready {
+            let (header, request) = stored.into_inner();
+            self.executor.queue_read(
+                header.from(),
+                header.unique_digest(),
+                request.into_inner(),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn forwarded_request_received(&mut self, request: ForwardRequestMessage<Request<S>>) {
+        self.synchronizer.watch_forwarded_request(
+            request,
+            &self.timeouts,
+            &mut self.log,
+            &self.node,
+        );
+    }
+
+    fn forwarded_requests_received(&mut self, requests: ForwardedRequestsMessage<Request<S>>) {
+        self.synchronizer.watch_forwarded_requests(
+            requests,
+            &self.timeouts,
+            &mut self.log,
+            &self.node,
+        );
+    }
+
+    // drops the decided requests in the log, if they went over the
+    // configured ceiling, e.g. because checkpoints stalled, and starts
+    // recovering the latest state from our peers, rather than risking
+    // running out of memory
+    fn enforce_log_ceiling(&mut self) {
+        if !self.log.exceeds_max_bytes() {
+            return;
+        }
+        self.log.drop_decided();
+        self.cst.request_latest_consensus_seq_no(
+            &self.synchronizer,
+            &self.timeouts,
+            &mut self.node,
+        );
+        self.phase = ReplicaPhase::RetrievingState;
+    }
+
+    // handles the consensus instance `seq` reported stalled by the watchdog;
+    // if it is still stalled voting at the instant `now`, the other replicas
+    // may have decided it without us, e.g. if we missed their votes, so we
+    // recover the latest state from them. stalls waiting on a proposal are
+    // dealt with by the client request timeouts, which depose the leader
+    fn consensus_stalled(&mut self, seq: SeqNo, now: Instant) {
+        match self.consensus.stalled(self.consensus_watchdog, now) {
+            Some((stalled, ProtoPhase::Preparing(_) | ProtoPhase::Committing(_)))
+                if stalled == seq => {}
+            _ => return,
+        }
+        self.cst.request_latest_consensus_seq_no(
+            &self.synchronizer,
+            &self.timeouts,
+            &mut self.node,
+        );
+        self.phase = ReplicaPhase::RetrievingState;
+    }
+
+    // handles our peers running consensus instances too far ahead of
+    // ours for their messages to be queued; rather than waiting for
+    // the messages we dropped, we recover the latest state from them
+    fn fallen_behind(&mut self) {
+        let f = self.synchronizer.view().params().f();
+        if !matches!(self.phase, ReplicaPhase::NormalPhase) || !self.consensus.fallen_behind(f) {
+            return;
+        }
+        self.cst.request_latest_consensus_seq_no(
+            &self.synchronizer,
+            &self.timeouts,
+            &mut self.node,
+        );
+        self.phase = ReplicaPhase::RetrievingState;
+    }
+
+    // handles the view change we are running not finishing in time,
+    // at the instant `now`, while our peers already run consensus in
+    // a new view, e.g. if we were partitioned from them; since they
+    // ignore our STOP messages for an older view, we recover the latest
+    // state from them, which includes their view
+    fn view_change_stalled(&mut self, now: Instant) {
+        if !matches!(self.phase, ReplicaPhase::SyncPhase)
+            || !self.synchronizer.stalled(self.consensus_watchdog, now)
+        {
+            return;
+        }
+        self.cst
+            .request_latest_state(&self.synchronizer, &self.timeouts, &mut self.node);
+        self.phase = ReplicaPhase::RetrievingState;
+        self.phase_stack = Some(ReplicaPhase::SyncPhase);
+    }
+
+    fn request_missing_received(&mut self, header: Header, message: RequestMissingMessage) {
+        let requests = self.log.clone_requests(message.digests());
+        // we don't have any of the requests either
+        if requests.is_empty() {
+            return;
+        }
+        let message = SystemMessage::ForwardedRequests(ForwardedRequestsMessage::new(requests));
+        self.node.send(message, header.from());
+    }
+
+    fn timeout_received(&mut self, timeout_kind: TimeoutKind) -> Result<()> {
         match timeout_kind {
             TimeoutKind::Cst(cst_seq) => {
                 let status = self.cst.timed_out(cst_seq);
@@ -628,8 +1782,15 @@ where
                         );
                         self.phase = ReplicaPhase::RetrievingState;
                     }
+                    CstStatus::RequestChunks => {
+                        self.cst
+                            .request_missing_chunks(&self.timeouts, &mut self.node);
+                        self.phase = ReplicaPhase::RetrievingState;
+                    }
                     // nothing to do
-                    _ => (),
+                    CstStatus::Nil => (),
+                    // should not happen...
+                    _ => return Err("Invalid state reached!").wrapped(ErrorKind::Timeout),
                 }
             }
             TimeoutKind::ClientRequests(timeout_seq) => {
@@ -651,9 +1812,1532 @@ where
                         }
                     }
                     // nothing to do
-                    _ => (),
+                    SynchronizerStatus::Nil => (),
+                    // should not happen...
+                    _ => return Err("Invalid state reached!").wrapped(ErrorKind::Timeout),
+                }
+            }
+            TimeoutKind::ConsensusWatchdog => {
+                let now = Instant::now();
+                self.view_change_stalled(now);
+                let stalled = self.consensus.stalled(self.consensus_watchdog, now);
+                if let Some((seq, phase)) = stalled {
+                    // the missing requests may have been lost,
+                    // so we ask for them again
+                    if let ProtoPhase::PreparingRequests = phase {
+                        self.consensus
+                            .request_missing(&self.synchronizer, &mut self.node);
+                    }
+                    // the alert is dropped if the master channel is saturated
+                    let mut system_tx = self.node.master_channel();
+                    system_tx
+                        .try_send(Message::ConsensusStalled(seq, phase))
+                        .unwrap_or(false);
+                }
+                self.timeouts
+                    .timeout(self.consensus_watchdog, TimeoutKind::ConsensusWatchdog);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "serialize_serde"))]
+mod tests {
+    use std::future::Future;
+    use std::time::{Duration, Instant};
+
+    use std::sync::Arc;
+
+    use futures::channel::oneshot;
+    use futures::future::{self, Either};
+    use futures_timer::Delay;
+    use rustls::{ClientConfig, NoClientAuth, ServerConfig};
+
+    use super::handle::ReplicaHandle;
+    use super::throttle::RateLimit;
+    use super::{
+        Info, Replica, ReplicaConfig, ReplicaPhase, ViewInfo, MAX_PENDING_READS_PER_CLIENT,
+        PAUSED_INGESTION_RETRY_AFTER, PENDING_READS_EXPIRY,
+    };
+    use crate::bft::async_runtime as rt;
+    use crate::bft::collections;
+    use crate::bft::communication::compress::Codec;
+    use crate::bft::communication::message::{
+        CancelRequestMessage, ConsensusMessage, ConsensusMessageKind, ForwardRequestMessage,
+        ForwardedRequestsMessage, Header, Message, QueryStatusMessage, ReadRequestMessage,
+        RequestMessage, RequestStatus, StoredMessage, SystemMessage, TraceId, WireMessage,
+    };
+    use crate::bft::communication::reconnect::Backoff;
+    use crate::bft::communication::serialize::{Buf, DigestData};
+    use crate::bft::communication::shard::ShardId;
+    use crate::bft::communication::socket::{DefaultProvider, MemoryNetwork, SocketOpts};
+    use crate::bft::communication::{Node, NodeConfig, NodeConfigBuilder, NodeId};
+    use crate::bft::consensus::{ConsensusPollStatus, VoteVerification};
+    use crate::bft::core::client::{self, Client};
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::crypto::signature::KeyPair;
+    use crate::bft::error::*;
+    use crate::bft::executable::UpdateBatch;
+    use crate::bft::ordering::{Orderable, SeqNo};
+    use crate::bft::testing::{
+        forge_header, init_runtime, tls_configs, CounterData, CounterService,
+    };
+    use crate::bft::threadpool;
+
+    fn config(max_log_bytes: usize) -> ReplicaConfig<CounterService> {
+        let node = NodeConfig {
+            n: 1,
+            f: 0,
+            id: NodeId::from(0u32),
+            first_cli: NodeId::from(1000u32),
+            addrs: collections::hash_map(),
+            pk: collections::hash_map(),
+            sk: KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap(),
+            client_config: ClientConfig::new(),
+            server_config: ServerConfig::new(NoClientAuth::new()),
+            channel_bound: 64,
+            connect_retries: 10,
+            pool: threadpool::Builder::new().num_threads(1).build(),
+            deserialize_timeout: Duration::from_secs(1),
+            authenticate_peers: false,
+            write_queue: None,
+            send_buf_pool: 16,
+            rogue_bound: 64,
+            client_idle_timeout: None,
+            bulk_link: false,
+            digest_entropy: true,
+            write_timeout: None,
+            verify_parallelism: 1,
+            socket_opts: SocketOpts::default(),
+            socket_provider: Arc::new(DefaultProvider::default()),
+            reconnect: None,
+            compression: vec![Codec::None],
+        };
+        ReplicaConfig {
+            service: CounterService,
+            view: SeqNo::ZERO,
+            next_consensus_seq: SeqNo::ZERO,
+            checkpoint_period: 100,
+            batch_size: 8,
+            max_batch_bytes: 4096,
+            max_deciding_bytes: usize::MAX,
+            max_log_bytes,
+            request_timeout: Duration::from_secs(5),
+            max_failed_view_changes: None,
+            cst_timeout: Duration::from_secs(3),
+            state_serve_interval: Duration::from_secs(7),
+            max_state_transfers: 2,
+            state_chunk_size: Some(4096),
+            state_chunk_rate: Some(1 << 20),
+            state_chunk_window: 32,
+            checkpoint_store: None,
+            dead_letters: None,
+            consensus_watchdog: Duration::from_secs(11),
+            vote_verification: VoteVerification::Disabled,
+            reply_cache_size: 16,
+            observers: Vec::new(),
+            forward_requests: false,
+            redirect_requests: false,
+            client_rate_limit: None,
+            max_request_priority: u32::MAX,
+            node,
+        }
+    }
+
+    // the loopback node never connects to any peers
+    async fn loopback(
+        node: NodeConfig,
+    ) -> Result<(Node<CounterData>, Vec<Message<u64, u64, u64>>)> {
+        let node = Node::loopback(node.id, node.sk);
+        Ok((node, Vec::new()))
+    }
+
+    #[test]
+    fn test_view_members_validated() {
+        let members = |ids: &[u32]| ids.iter().copied().map(NodeId::from).collect();
+        assert!(ViewInfo::with_members(SeqNo::ZERO, members(&[]), 0).is_err());
+        assert!(ViewInfo::with_members(SeqNo::ZERO, members(&[0, 2, 4, 2]), 1).is_err());
+        assert!(ViewInfo::with_members(SeqNo::ZERO, members(&[0, 2, 4, 6]), 1).is_ok());
+    }
+
+    #[test]
+    fn test_replica_config_distributed() {
+        init_runtime();
+
+        let mut cfg = config(1 << 20);
+        cfg.max_deciding_bytes = 2048;
+        let replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        assert_eq!(replica.log.checkpoint_period(), 100);
+        assert_eq!(replica.log.batch_size(), 8);
+        assert_eq!(replica.log.max_batch_bytes(), 4096);
+        assert_eq!(replica.log.max_deciding_bytes(), 2048);
+        assert_eq!(replica.log.max_bytes(), 1 << 20);
+        assert_eq!(
+            replica.synchronizer.request_timeout(),
+            Duration::from_secs(5)
+        );
+        assert_eq!(replica.cst.base_timeout(), Duration::from_secs(3));
+        assert_eq!(replica.cst.serve_interval(), Duration::from_secs(7));
+        assert_eq!(replica.cst.max_transfers(), 2);
+        assert_eq!(replica.cst.chunk_size(), Some(4096));
+        assert_eq!(replica.cst.chunk_rate(), Some(1 << 20));
+        assert_eq!(replica.cst.chunk_window(), 32);
+        assert_eq!(replica.consensus_watchdog, Duration::from_secs(11));
+        assert_eq!(replica.synchronizer.view().params().n(), 1);
+    }
+
+    #[test]
+    fn test_log_ceiling_forces_cst() {
+        const MAX_LOG_BYTES: usize = 16 * 1024;
+
+        init_runtime();
+
+        let cfg = config(MAX_LOG_BYTES);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        let mut nonce = 0;
+        while !replica.log.exceeds_max_bytes() {
+            // still below the ceiling, carry on as usual
+            replica.enforce_log_ceiling();
+            assert!(matches!(replica.phase, ReplicaPhase::NormalPhase));
+
+            // fills a whole batch
+            let header = forge_header(1000, 0, nonce, 4096);
+            let request =
+                SystemMessage::Request(RequestMessage::new(SeqNo::from(nonce as u32), nonce));
+            replica.log.insert(header, request);
+            let batch = replica.log.next_batch().expect("No batch was cut");
+            replica
+                .log
+                .finalize_batch(SeqNo::from(nonce as u32), &batch)
+                .unwrap();
+            nonce += 1;
+        }
+
+        replica.enforce_log_ceiling();
+        assert!(matches!(replica.phase, ReplicaPhase::RetrievingState));
+        assert_eq!(replica.log.decided_bytes(), 0);
+    }
+
+    #[test]
+    fn test_stalled_consensus_forces_cst() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let watchdog = cfg.consensus_watchdog;
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        let header = |from, length| forge_header(from, 0, 0, length);
+        let request = SystemMessage::Request(RequestMessage::new(SeqNo::ZERO, 1));
+        // fills a whole batch
+        replica.log.insert(header(1000, 4096), request);
+        let digests = replica.log.next_batch().expect("No batch was cut");
+
+        // accept our own `PRE-PREPARE`, whose votes never arrive
+        let kind = ConsensusMessageKind::PrePrepare(digests);
+        replica.consensus.queue(
+            header(0, 0),
+            ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind),
+            replica.synchronizer.view().members(),
+        );
+        replica.consensus.signal();
+        let (header, message) =
+            match replica
+                .consensus
+                .poll(&replica.log, &replica.synchronizer, &mut replica.node)
+            {
+                ConsensusPollStatus::NextMessage(header, message) => (header, message),
+                _ => panic!("Expected a PRE-PREPARE"),
+            };
+        replica.consensus.process_message(
+            header,
+            message,
+            &replica.synchronizer,
+            &mut replica.log,
+            &mut replica.node,
+        );
+        assert!(replica.consensus.is_deciding());
+
+        // not stalled for long enough
+        replica.consensus_stalled(SeqNo::ZERO, Instant::now());
+        assert!(matches!(replica.phase, ReplicaPhase::NormalPhase));
+
+        // an outdated report of a stall
+        let later = Instant::now() + watchdog;
+        replica.consensus_stalled(SeqNo::from(1u32), later);
+        assert!(matches!(replica.phase, ReplicaPhase::NormalPhase));
+
+        replica.consensus_stalled(SeqNo::ZERO, later);
+        assert!(matches!(replica.phase, ReplicaPhase::RetrievingState));
+    }
+
+    #[test]
+    fn test_replayed_request_dropped() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        let request = |nonce: u64, seq: u32| {
+            // fills a whole batch
+            let header = forge_header(1000, 0, nonce, 4096);
+            (header, RequestMessage::new(SeqNo::from(seq), nonce))
+        };
+
+        // execute the request with sequence number 3
+        let (header, message) = request(0, 3);
+        replica.request_received(header, message.clone()).unwrap();
+        let batch = replica.log.next_batch().expect("No batch was cut");
+        replica.log.finalize_batch(SeqNo::ZERO, &batch).unwrap();
+
+        // the same request, replayed, never reaches the consensus layer
+        replica.request_received(header, message).unwrap();
+        assert!(replica.log.next_batch().is_none());
+
+        // an older request of the same client, which wasn't decided
+        // yet, e.g. because it was sent concurrently, is still accepted,
+        // as are newer requests, and the requests of other clients
+        let (header, message) = request(1, 2);
+        replica.request_received(header, message).unwrap();
+        assert!(replica.log.next_batch().is_some());
+        let (header, message) = request(2, 4);
+        replica.request_received(header, message).unwrap();
+        assert!(replica.log.next_batch().is_some());
+        let (mut header, message) = request(3, 3);
+        header.from = 1001;
+        replica.request_received(header, message).unwrap();
+        assert!(replica.log.next_batch().is_some());
+    }
+
+    #[test]
+    fn test_trace_id_echoed_in_reply() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the replica replies to itself, as the loopback
+        // node isn't connected to any clients
+        // fills a whole batch
+        let header = forge_header(0, 0, 0, 4096);
+        let trace_id = Some(TraceId::from(0xbeef));
+        let request = RequestMessage::new(SeqNo::ZERO, 42).with_trace_id(trace_id);
+        replica.request_received(header, request).unwrap();
+
+        // the trace id is carried along to the executor
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        let (_, batch) = replica.log.finalize_batch(SeqNo::ZERO, &digests).unwrap();
+        assert_eq!(batch.as_ref()[0].trace_id(), trace_id);
+        replica.executor.queue_update(batch).unwrap();
+
+        let reply = rt::block_on(async {
+            let batch = loop {
+                if let Message::ExecutionFinished(batch) = replica.node.receive().await.unwrap() {
+                    break batch;
+                }
+            };
+            replica.execution_finished(batch);
+            match replica.node.receive().await.unwrap() {
+                Message::System(_, SystemMessage::Reply(reply)) => reply,
+                _ => panic!("Expected a reply"),
+            }
+        });
+        assert_eq!(*reply.payload(), 42);
+        assert_eq!(reply.trace_id(), trace_id);
+    }
+
+    #[test]
+    fn test_replies_of_same_batch_coalesced() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the replica replies to itself, as the loopback
+        // node isn't connected to any clients
+        // two requests fill a whole batch
+        let header = |nonce| forge_header(0, 0, nonce, 2048);
+        replica
+            .request_received(header(0), RequestMessage::new(SeqNo::ZERO, 1))
+            .unwrap();
+        assert!(replica.log.next_batch().is_none());
+        replica
+            .request_received(header(1), RequestMessage::new(SeqNo::from(1u32), 2))
+            .unwrap();
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        assert_eq!(digests.len(), 2);
+        let (_, batch) = replica.log.finalize_batch(SeqNo::ZERO, &digests).unwrap();
+        replica.executor.queue_update(batch).unwrap();
+
+        let replies = rt::block_on(async {
+            let batch = loop {
+                if let Message::ExecutionFinished(batch) = replica.node.receive().await.unwrap() {
+                    break batch;
+                }
+            };
+            replica.execution_finished(batch);
+            match replica.node.receive().await.unwrap() {
+                Message::System(_, SystemMessage::ReplyBatch(replies)) => replies.into_inner(),
+                _ => panic!("Expected a reply batch"),
+            }
+        });
+
+        // a single message carries the replies to both requests,
+        // in the order they were executed
+        assert_eq!(replies.len(), 2);
+        assert_eq!(*replies[1].payload(), 3);
+        for (reply, digest) in replies.iter().zip(digests.iter()) {
+            assert!(reply.digest() == digest);
+        }
+    }
+
+    #[test]
+    fn test_query_status_of_executed_request() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the replica queries itself, as the loopback
+        // node isn't connected to any clients
+        // fills a whole batch
+        let header = forge_header(0, 0, 0, 4096);
+        let digest = header.unique_digest();
+        // another client, querying the same request
+        let other = Header { from: 1, ..header };
+        let query_status_from = |replica: &mut Replica<CounterService>, header: Header| {
+            replica
+                .query_status_received(header, QueryStatusMessage::new(digest))
+                .unwrap();
+            rt::block_on(async {
+                loop {
+                    match replica.node.receive().await.unwrap() {
+                        Message::System(_, SystemMessage::QueryStatusReply(reply)) => break reply,
+                        Message::StatusQueried(id, reply) => {
+                            assert_eq!(id, header.from());
+                            break reply;
+                        }
+                        _ => (),
+                    }
+                }
+            })
+        };
+        let query_status =
+            |replica: &mut Replica<CounterService>| query_status_from(replica, header);
+
+        // unknown before the request is received
+        let reply = query_status(&mut replica);
+        assert!(matches!(reply.status(), RequestStatus::Unknown));
+
+        // pending while the request is in the log
+        replica
+            .request_received(header, RequestMessage::new(SeqNo::ZERO, 42))
+            .unwrap();
+        let reply = query_status(&mut replica);
+        assert!(matches!(reply.status(), RequestStatus::Pending));
+        let reply = query_status_from(&mut replica, other);
+        assert!(matches!(reply.status(), RequestStatus::Unknown));
+
+        // executed afterwards, along with the cached reply
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        let (_, batch) = replica.log.finalize_batch(SeqNo::ZERO, &digests).unwrap();
+        replica.executor.queue_update(batch).unwrap();
+        let reply = query_status(&mut replica);
+        assert!(reply.digest() == &digest);
+        assert!(matches!(reply.status(), RequestStatus::Executed(42)));
+
+        // only the client which sent the request learns its reply
+        let reply = query_status_from(&mut replica, other);
+        assert!(matches!(reply.status(), RequestStatus::Unknown));
+    }
+
+    #[test]
+    fn test_cancelled_request_never_executed() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the replica replies to itself, as the loopback
+        // node isn't connected to any clients
+        // fills a whole batch
+        let header = |nonce| forge_header(0, 0, nonce, 4096);
+        let (cancelled, kept) = (header(0), header(1));
+        replica
+            .request_received(cancelled, RequestMessage::new(SeqNo::ZERO, 1))
+            .unwrap();
+        replica
+            .request_received(kept, RequestMessage::new(SeqNo::from(1u32), 2))
+            .unwrap();
+
+        // only the client which sent a request may withdraw it
+        let mut other = kept;
+        other.from = 1001;
+        let message = CancelRequestMessage::new(kept.unique_digest());
+        replica.cancel_request_received(other, message);
+        let message = CancelRequestMessage::new(cancelled.unique_digest());
+        replica.cancel_request_received(cancelled, message);
+        assert!(!replica.log.has_request(&cancelled.unique_digest()));
+        assert!(replica.log.has_request(&kept.unique_digest()));
+
+        // the withdrawn request is never proposed
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        assert!(digests == [kept.unique_digest()]);
+        assert!(replica.log.next_batch().is_none());
+
+        // nor executed, as the counter only reflects the kept request
+        let (_, batch) = replica.log.finalize_batch(SeqNo::ZERO, &digests).unwrap();
+        replica.executor.queue_update(batch).unwrap();
+        let reply = rt::block_on(async {
+            let batch = loop {
+                if let Message::ExecutionFinished(batch) = replica.node.receive().await.unwrap() {
+                    break batch;
+                }
+            };
+            replica.execution_finished(batch);
+            match replica.node.receive().await.unwrap() {
+                Message::System(_, SystemMessage::Reply(reply)) => reply,
+                _ => panic!("Expected a reply"),
+            }
+        });
+        assert_eq!(*reply.payload(), 2);
+    }
+
+    #[test]
+    fn test_retransmitted_request_answered_from_cache() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the replica replies to itself, as the loopback
+        // node isn't connected to any clients
+        let request = |nonce: u64, seq: u32, operation: u64| {
+            // fills a whole batch
+            let header = forge_header(0, 0, nonce, 4096);
+            (header, RequestMessage::new(SeqNo::from(seq), operation))
+        };
+        let next_reply = |replica: &mut Replica<CounterService>| {
+            rt::block_on(async {
+                let batch = loop {
+                    if let Message::ExecutionFinished(batch) = replica.node.receive().await.unwrap()
+                    {
+                        break batch;
+                    }
+                };
+                replica.execution_finished(batch);
+                match replica.node.receive().await.unwrap() {
+                    Message::System(_, SystemMessage::Reply(reply)) => reply.into_inner(),
+                    _ => panic!("Expected a reply"),
+                }
+            })
+        };
+
+        // execute the request
+        let (header, message) = request(0, 0, 42);
+        replica.request_received(header, message.clone()).unwrap();
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        let (_, batch) = replica.log.finalize_batch(SeqNo::ZERO, &digests).unwrap();
+        replica.executor.queue_update(batch).unwrap();
+        assert_eq!(next_reply(&mut replica), (header.unique_digest(), 42));
+
+        // the retransmitted request, sent with a new nonce, is answered
+        // with the cached reply, without being ordered again
+        let (header, message) = request(7, 0, 42);
+        replica.request_received(header, message).unwrap();
+        assert!(replica.log.next_batch().is_none());
+        assert_eq!(next_reply(&mut replica), (header.unique_digest(), 42));
+
+        // the request was only executed once
+        let (header, message) = request(1, 1, 1);
+        replica.request_received(header, message).unwrap();
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        let (_, batch) = replica
+            .log
+            .finalize_batch(SeqNo::from(1u32), &digests)
+            .unwrap();
+        replica.executor.queue_update(batch).unwrap();
+        assert_eq!(next_reply(&mut replica), (header.unique_digest(), 43));
+    }
+
+    #[test]
+    fn test_read_waits_for_client_update() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the replica replies to itself, as the loopback
+        // node isn't connected to any clients
+        // fills a whole batch
+        let header = |nonce| forge_header(0, 0, nonce, 4096);
+        // executed batches and replies may arrive in any order
+        let next_replies = |replica: &mut Replica<CounterService>, count| {
+            rt::block_on(async {
+                let mut replies = Vec::new();
+                while replies.len() < count {
+                    match replica.node.receive().await.unwrap() {
+                        Message::ExecutionFinished(batch) => replica.execution_finished(batch),
+                        Message::System(_, SystemMessage::Reply(reply)) => replies.push(reply),
+                        _ => (),
+                    }
+                }
+                replies
+            })
+        };
+
+        // the read arrives before the update it must observe
+        let read = ReadRequestMessage::new(Some(SeqNo::ZERO), 0);
+        replica.read_request_received(header(1), read).unwrap();
+        assert_eq!(replica.pending_reads.len(), 1);
+
+        replica
+            .request_received(header(0), RequestMessage::new(SeqNo::ZERO, 42))
+            .unwrap();
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        let (_, batch) = replica.log.finalize_batch(SeqNo::ZERO, &digests).unwrap();
+        replica.executor.queue_update(batch).unwrap();
+        replica.serve_pending_reads().unwrap();
+        assert!(replica.pending_reads.is_empty());
+
+        // the read is served after the update is executed
+        let replies = next_replies(&mut replica, 2);
+        for nonce in 0..2 {
+            let digest = header(nonce).unique_digest();
+            let reply = replies
+                .iter()
+                .find(|reply| reply.digest() == &digest)
+                .expect("Missing reply");
+            assert_eq!(*reply.payload(), 42);
+        }
+    }
+
+    #[test]
+    fn test_pending_reads_bounded() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        let header = |from, nonce| forge_header(from, 0, nonce, 0);
+        let read = || ReadRequestMessage::new(Some(SeqNo::ZERO), 0);
+
+        // a client can only keep so many reads waiting on its updates
+        for nonce in 0..=MAX_PENDING_READS_PER_CLIENT as u64 {
+            replica
+                .read_request_received(header(1000, nonce), read())
+                .unwrap();
+        }
+        assert_eq!(replica.pending_reads.len(), MAX_PENDING_READS_PER_CLIENT);
+
+        // ... which doesn't stop other clients from reading
+        replica
+            .read_request_received(header(1001, 0), read())
+            .unwrap();
+        assert_eq!(
+            replica.pending_reads.len(),
+            MAX_PENDING_READS_PER_CLIENT + 1
+        );
+
+        // the reads whose updates never arrive are eventually dropped
+        replica.expire_pending_reads(Instant::now());
+        assert_eq!(
+            replica.pending_reads.len(),
+            MAX_PENDING_READS_PER_CLIENT + 1
+        );
+        replica.expire_pending_reads(Instant::now() + PENDING_READS_EXPIRY);
+        assert!(replica.pending_reads.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_restored_after_crash() {
+        // decide one batch past the first checkpoint
+        const BATCHES: u32 = 102;
+
+        init_runtime();
+
+        let dir =
+            std::env::temp_dir().join(format!("bafomet-test-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("snapshot");
+
+        // the only replica, with id 0, certifies every decision
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let header = |from: u32, nonce, message: &SystemMessage<u64, u64, u64>| {
+            let digest = CounterData::serialize_digest(message, Vec::new()).unwrap();
+            let (from, to) = (NodeId::from(from), NodeId::from(0u32));
+            let mut header = WireMessage::new(from, to, &[], nonce, Some(digest), Some(&sk)).header;
+            // fills a whole batch
+            header.length = 4096;
+            header
+        };
+        let consensus = |seq: u32, kind| {
+            SystemMessage::Consensus(ConsensusMessage::new(SeqNo::from(seq), SeqNo::ZERO, kind))
+        };
+        let request = |seq: u32| {
+            let request = RequestMessage::new(SeqNo::from(seq), 1);
+            let header = header(
+                1000,
+                u64::from(seq),
+                &SystemMessage::Request(request.clone()),
+            );
+            (header, request)
+        };
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+        for seq in 0..BATCHES {
+            let nonce = u64::from(seq);
+            let (request_header, request) = request(seq);
+            replica.request_received(request_header, request).unwrap();
+            let digests = replica.log.next_batch().expect("No batch was cut");
+
+            let pre_prepare = consensus(seq, ConsensusMessageKind::PrePrepare(digests.clone()));
+            let pre_prepare_header = header(0, nonce, &pre_prepare);
+            let commit = consensus(
+                seq,
+                ConsensusMessageKind::Commit(*pre_prepare_header.digest()),
+            );
+            let commit_header = header(0, nonce, &commit);
+            replica.log.insert(pre_prepare_header, pre_prepare);
+            replica.log.insert(commit_header, commit);
+
+            let (info, _) = replica
+                .log
+                .finalize_batch(SeqNo::from(seq), &digests)
+                .unwrap();
+            replica.log.instance_decided(SeqNo::from(seq));
+            if let Info::BeginCheckpoint = info {
+                // every request adds one to the counter
+                replica.log.finalize_checkpoint(nonce + 1).unwrap();
+            }
+            replica.consensus.next_instance();
+        }
+        replica.persist_snapshot(&path).unwrap();
+
+        // crash, and restart from the snapshot
+        drop(replica);
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+        replica.restore_snapshot(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // consensus resumes where it left off...
+        assert_eq!(replica.consensus.sequence_number(), SeqNo::from(BATCHES));
+        assert_eq!(replica.log.peek_decided().len(), 1);
+
+        // ... knowing which requests were executed, both before
+        // the checkpoint, and after it, once replayed...
+        let client = NodeId::from(1000u32);
+        for seq in [BATCHES - 2, BATCHES - 1] {
+            assert!(replica.log.is_replayed(client, SeqNo::from(seq)));
+        }
+        assert!(!replica.log.is_replayed(client, SeqNo::from(BATCHES)));
+
+        // ... on top of the same application state
+        let (header, request) = request(BATCHES);
+        replica.request_received(header, request).unwrap();
+        let digests = replica.log.next_batch().expect("No batch was cut");
+        let (_, batch) = replica
+            .log
+            .finalize_batch(SeqNo::from(BATCHES), &digests)
+            .unwrap();
+        replica.executor.queue_update(batch).unwrap();
+        let reply = rt::block_on(async {
+            loop {
+                if let Message::ExecutionFinished(batch) = replica.node.receive().await.unwrap() {
+                    let (_, _, reply) = batch.into_inner().remove(0).into_inner();
+                    break reply;
+                }
+            }
+        });
+        assert_eq!(reply, u64::from(BATCHES) + 1);
+    }
+
+    #[cfg(feature = "admin")]
+    #[test]
+    fn test_force_install_checkpoint() {
+        init_runtime();
+
+        // fills a whole batch
+        let header = |nonce| forge_header(1000, 0, nonce, 4096);
+        let decide = |replica: &mut Replica<CounterService>, seq: u32| {
+            replica
+                .request_received(
+                    header(u64::from(seq)),
+                    RequestMessage::new(SeqNo::from(seq), 1),
+                )
+                .unwrap();
+            let digests = replica.log.next_batch().expect("No batch was cut");
+            let (_, batch) = replica
+                .log
+                .finalize_batch(SeqNo::from(seq), &digests)
+                .unwrap();
+            replica.consensus.next_instance();
+            batch
+        };
+
+        let cfg = config(usize::MAX);
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+        for seq in 0..3 {
+            decide(&mut replica, seq);
+        }
+
+        // a batch is cut, but the force install abandons its instance
+        replica
+            .request_received(header(3), RequestMessage::new(SeqNo::from(3u32), 1))
+            .unwrap();
+        assert!(replica.log.next_batch().is_some());
+        assert_eq!(replica.log.deciding_len(), 1);
+        assert!(replica
+            .log
+            .is_replayed(NodeId::from(1000u32), SeqNo::from(0u32)));
+
+        // pin the replica to a checkpoint taken at instance 9
+        replica
+            .force_install_checkpoint(SeqNo::from(9u32), 500)
+            .unwrap();
+        assert_eq!(replica.consensus.sequence_number(), SeqNo::from(10u32));
+        assert!(!replica.consensus.is_deciding());
+        assert!(replica.log.peek_decided().is_empty());
+        assert_eq!(replica.log.deciding_len(), 0);
+        assert_eq!(replica.log.deciding_bytes(), 0);
+        assert!(!replica
+            .log
+            .is_replayed(NodeId::from(1000u32), SeqNo::from(0u32)));
+
+        // consensus resumes on top of the installed state
+        let batch = decide(&mut replica, 10);
+        replica.executor.queue_update(batch).unwrap();
+        let reply = rt::block_on(async {
+            loop {
+                if let Message::ExecutionFinished(batch) = replica.node.receive().await.unwrap() {
+                    let (_, _, reply) = batch.into_inner().remove(0).into_inner();
+                    break reply;
+                }
+            }
+        });
+        assert_eq!(reply, 501);
+    }
+
+    #[test]
+    fn test_metrics_prometheus() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+        let text = replica.metrics_prometheus();
+
+        // every sample follows the HELP and TYPE lines of its metric
+        let mut described = Vec::new();
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut words = comment.splitn(3, ' ');
+                let keyword = words.next().unwrap();
+                let name = words.next().unwrap();
+                let rest = words.next().unwrap();
+                match keyword {
+                    "HELP" => described.push(name.to_owned()),
+                    "TYPE" => assert!(rest == "gauge" || rest == "counter"),
+                    _ => panic!("Invalid comment: {}", line),
+                }
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').expect("Missing value");
+            value.parse::<f64>().expect("Invalid value");
+            let (name, labels) = series.split_once('{').expect("Missing labels");
+            assert_eq!(Some(name), described.last().map(String::as_str));
+            let labels = labels.strip_suffix('}').expect("Unterminated labels");
+            for label in labels.split(',') {
+                let (key, value) = label.split_once('=').expect("Invalid label");
+                assert!(key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+                assert!(value.starts_with('"') && value.ends_with('"'));
+            }
+            assert!(labels.starts_with("node=\"0\",view=\"0\""));
+        }
+
+        for name in [
+            "bafomet_consensus_seq",
+            "bafomet_consensus_deciding",
+            "bafomet_consensus_queued_messages",
+            "bafomet_log_decided_bytes",
+            "bafomet_log_deciding_bytes",
+            "bafomet_log_pending_execution",
+            "bafomet_pending_reads",
+            "bafomet_connected_peers",
+            "bafomet_dropped_client_messages_total",
+        ] {
+            assert!(described.iter().any(|d| d == name), "Missing {}", name);
+        }
+    }
+
+    #[test]
+    fn test_current_view_after_view_change() {
+        init_runtime();
+
+        let mut replica =
+            rt::block_on(Replica::bootstrap_with(config(usize::MAX), loopback)).unwrap();
+        assert_eq!(replica.current_view().sequence_number(), SeqNo::ZERO);
+        assert_eq!(replica.current_leader(), NodeId::from(0u32));
+
+        // the only replica steps down, and changes views with itself
+        replica.step_down().unwrap();
+        rt::block_on(async {
+            while replica.current_view().sequence_number() == SeqNo::ZERO {
+                match replica.phase {
+                    ReplicaPhase::NormalPhase => replica.update_normal_phase().await.unwrap(),
+                    ReplicaPhase::SyncPhase => {
+                        replica.update_sync_phase().await.unwrap();
+                    }
+                    ReplicaPhase::RetrievingState => panic!("Unexpected state transfer"),
+                }
+            }
+        });
+
+        let view = replica.current_view();
+        assert_eq!(view.sequence_number(), SeqNo::from(1u32));
+        assert_eq!(replica.current_leader(), view.leader());
+    }
+
+    #[test]
+    fn test_request_forwarded_to_leader() {
+        init_runtime();
+
+        // replicas 0 and 1 of a system with n = 4, where
+        // 0 is the leader of the current view
+        let replica = |id: u32| {
+            let mut cfg = config(usize::MAX);
+            cfg.node.n = 4;
+            cfg.node.f = 1;
+            cfg.node.id = NodeId::from(id);
+            cfg.batch_size = 1;
+            cfg.forward_requests = true;
+            rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap()
+        };
+        let follower = replica(1);
+        let mut leader = replica(0);
+
+        // only the follower forwards the requests it receives
+        assert_eq!(follower.forward_target(), Some(NodeId::from(0u32)));
+        assert_eq!(leader.forward_target(), None);
+
+        // the loopback node of the leader only knows its own key,
+        // so the request is sent by a client with the same key
+        let request = RequestMessage::new(SeqNo::ZERO, 42);
+        let mut buf = Buf::new();
+        let digest = <CounterData as DigestData>::serialize_digest(
+            &SystemMessage::Request(request.clone()),
+            &mut buf,
+        )
+        .unwrap();
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let (from, to) = (NodeId::from(0u32), NodeId::from(1u32));
+        let signed = |sk: Option<&KeyPair>, request: RequestMessage<u64>| {
+            let header = WireMessage::new(from, to, &buf, 0, Some(digest), sk).header;
+            StoredMessage::new(header, request)
+        };
+        let forward = |sk: Option<&KeyPair>| {
+            let stored = signed(sk, request.clone());
+            (
+                stored.header().unique_digest(),
+                ForwardRequestMessage::new(stored),
+            )
+        };
+
+        // requests whose signature doesn't match the client are dropped
+        let (_, forged) = forward(None);
+        leader.forwarded_request_received(forged);
+        assert!(leader.log.next_batch().is_none());
+
+        // as are requests tampered with by the forwarding replica
+        let tampered = signed(Some(&sk), RequestMessage::new(SeqNo::ZERO, 43));
+        leader.forwarded_request_received(ForwardRequestMessage::new(tampered.clone()));
+        leader.forwarded_requests_received(ForwardedRequestsMessage::new(vec![tampered]));
+        assert!(leader.log.next_batch().is_none());
+
+        // the forwarded request is proposed by the leader
+        let (digest, forwarded) = forward(Some(&sk));
+        leader.forwarded_request_received(forwarded);
+        let batch = leader.log.next_batch().expect("No batch was cut");
+        assert!(batch == vec![digest]);
+    }
+
+    #[test]
+    fn test_flooding_client_throttled() {
+        init_runtime();
+
+        let mut cfg = config(usize::MAX);
+        cfg.client_rate_limit = Some(RateLimit {
+            burst: 2,
+            refill_interval: Duration::from_secs(60),
+        });
+        let mut replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+
+        // the loopback node can only reply to itself, so the
+        // flooding client shares the id of the replica
+        let flooder = NodeId::from(0u32);
+        let other = NodeId::from(1001u32);
+        let mut nonce = 0;
+        let mut send = |replica: &mut Replica<CounterService>, from, seq: u32| {
+            let request = RequestMessage::new(SeqNo::from(seq), 42);
+            let mut buf = Buf::new();
+            let digest = <CounterData as DigestData>::serialize_digest(
+                &SystemMessage::Request(request.clone()),
+                &mut buf,
+            )
+            .unwrap();
+            nonce += 1;
+            let to = NodeId::from(0u32);
+            let header = WireMessage::new(from, to, &buf, nonce, Some(digest), None).header;
+            replica.request_received(header, request).unwrap();
+        };
+        for seq in 0..5 {
+            send(&mut replica, flooder, seq);
+            // the other client stays within its burst
+            if seq < 2 {
+                send(&mut replica, other, seq);
+            }
+        }
+
+        // only the burst of the flooding client was admitted,
+        // while the other client's requests flowed freely
+        for _ in 0..10 {
+            assert!(replica.log.next_batch().is_none());
+        }
+        assert_eq!(replica.log.deciding_len(), 2 + 2);
+
+        // the flooding client was told to back off for its excess requests
+        let throttled: Vec<_> = rt::block_on(async {
+            let mut throttled = Vec::new();
+            while throttled.len() < 3 {
+                if let Message::System(header, SystemMessage::Throttled(message)) =
+                    replica.node.receive().await.unwrap()
+                {
+                    assert_eq!(header.to(), flooder);
+                    assert!(message.retry_after() <= Duration::from_secs(60));
+                    throttled.push(message.sequence_number());
+                }
+            }
+            throttled
+        });
+        assert_eq!(
+            throttled,
+            vec![SeqNo::from(2u32), SeqNo::from(3u32), SeqNo::from(4u32)]
+        );
+    }
+
+    #[test]
+    fn test_paused_ingestion_rejects_requests() {
+        init_runtime();
+
+        let mut replica =
+            rt::block_on(Replica::bootstrap_with(config(usize::MAX), loopback)).unwrap();
+
+        // the loopback node can only reply to itself, so the
+        // client shares the id of the replica
+        let client = NodeId::from(0u32);
+        let send = |replica: &mut Replica<CounterService>, seq: u32| {
+            let request = RequestMessage::new(SeqNo::from(seq), 42);
+            let mut buf = Buf::new();
+            let digest = <CounterData as DigestData>::serialize_digest(
+                &SystemMessage::Request(request.clone()),
+                &mut buf,
+            )
+            .unwrap();
+            let header =
+                WireMessage::new(client, client, &buf, seq as u64, Some(digest), None).header;
+            replica.request_received(header, request).unwrap();
+        };
+
+        // the client is told to try again later
+        replica.pause_ingestion();
+        send(&mut replica, 0);
+        assert!(replica.log.next_batch().is_none());
+        assert_eq!(replica.log.deciding_len(), 0);
+        let throttled = rt::block_on(async {
+            loop {
+                if let Message::System(_, SystemMessage::Throttled(message)) =
+                    replica.node.receive().await.unwrap()
+                {
+                    break message;
+                }
+            }
+        });
+        assert_eq!(throttled.sequence_number(), SeqNo::ZERO);
+        assert_eq!(throttled.retry_after(), PAUSED_INGESTION_RETRY_AFTER);
+
+        // ... which succeeds once ingestion is resumed
+        replica.resume_ingestion();
+        send(&mut replica, 1);
+        assert!(replica.log.next_batch().is_none());
+        assert_eq!(replica.log.deciding_len(), 1);
+    }
+
+    // the longest a test running a cluster may take to complete
+    const CLUSTER_TIMEOUT: Duration = Duration::from_secs(60);
+
+    // runs `future` to completion, failing the test if it takes
+    // longer than `CLUSTER_TIMEOUT`, e.g. because the cluster stalled
+    fn block_on_cluster<F: Future>(future: F) -> F::Output {
+        rt::block_on(async {
+            match future::select(Box::pin(future), Delay::new(CLUSTER_TIMEOUT)).await {
+                Either::Left((output, _)) => output,
+                Either::Right(_) => panic!("Timed out waiting on the cluster"),
+            }
+        })
+    }
+
+    // returns the configurations of the nodes of a cluster of 4 replicas,
+    // and `clients` clients, with ids from 1000 onwards, indexed by their
+    // ids; the nodes are connected through a `MemoryNetwork` of their own
+    fn cluster_configs(clients: u32) -> impl Fn(u32) -> NodeConfig {
+        cluster_network(clients).1
+    }
+
+    // same as `cluster_configs()`, also returning the `MemoryNetwork`
+    // connecting the nodes, e.g. to partition it
+    fn cluster_network(clients: u32) -> (MemoryNetwork, impl Fn(u32) -> NodeConfig) {
+        let network = MemoryNetwork::new();
+        let sk = || KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        // the clients share the certificate of the first one
+        let name = |id: u32| match id {
+            0..=3 => format!("cop0{}", id + 1),
+            _ => String::from("cli1000"),
+        };
+        let ids: Vec<u32> = (0..4).chain(1000..1000 + clients).collect();
+        let mut addrs = collections::hash_map();
+        let mut pk = collections::hash_map();
+        for (i, &id) in ids.iter().enumerate() {
+            let addr = format!("127.0.0.1:{}", 10000 + i).parse().unwrap();
+            addrs.insert(NodeId::from(id), (addr, name(id)));
+            pk.insert(NodeId::from(id), sk().public_key().into());
+        }
+        let configs = network.clone();
+        let node_config = move |id: u32| {
+            let (client_config, server_config) = tls_configs(&name(id));
+            let addr = addrs[&NodeId::from(id)].0;
+            NodeConfigBuilder::new()
+                .n(4)
+                .f(1)
+                .id(NodeId::from(id))
+                .first_cli(NodeId::from(1000u32))
+                .addrs(addrs.clone())
+                .pk(pk.clone())
+                .sk(sk())
+                .client_config(client_config)
+                .server_config(server_config)
+                .socket_provider(Arc::new(configs.host(addr)))
+                .pool(threadpool::Builder::new().num_threads(1).build())
+                .build()
+                .unwrap()
+        };
+        (network, node_config)
+    }
+
+    // spawns a cluster of 4 replicas, tuned by `tune`, connected through
+    // a `MemoryNetwork` of their own, and returns the configuration of the
+    // node of its client, along with the handles of the replicas, which
+    // resolve once they are bootstrapped
+    fn spawn_cluster<F>(
+        tune: F,
+    ) -> (
+        NodeConfig,
+        Vec<oneshot::Receiver<ReplicaHandle<CounterService>>>,
+    )
+    where
+        F: Fn(&mut ReplicaConfig<CounterService>),
+    {
+        let (_, node, handles) = spawn_cluster_network(tune);
+        (node, handles)
+    }
+
+    // same as `spawn_cluster()`, also returning the `MemoryNetwork`
+    // connecting the nodes, e.g. to partition it
+    fn spawn_cluster_network<F>(
+        tune: F,
+    ) -> (
+        MemoryNetwork,
+        NodeConfig,
+        Vec<oneshot::Receiver<ReplicaHandle<CounterService>>>,
+    )
+    where
+        F: Fn(&mut ReplicaConfig<CounterService>),
+    {
+        let (network, node_config) = cluster_network(1);
+
+        let mut handles = Vec::new();
+        for id in 0..4 {
+            let mut cfg = config(usize::MAX);
+            cfg.node = node_config(id);
+            cfg.batch_size = 1;
+            tune(&mut cfg);
+            let (tx, rx) = oneshot::channel();
+            handles.push(rx);
+            rt::spawn(async move {
+                let replica = Replica::bootstrap(cfg).await.unwrap();
+                tx.send(replica.handle()).unwrap_or(());
+                replica.run().await.unwrap();
+            });
+        }
+
+        (network, node_config(1000), handles)
+    }
+
+    #[test]
+    fn test_cluster_decides_client_request() {
+        init_runtime();
+
+        let (node, handles) = spawn_cluster(|_| ());
+
+        // the reply is only returned once f+1 replicas executed the request
+        let (reply, leader, metrics) = block_on_cluster(async {
+            let leader_hint = None;
+            let mut client = Client::<CounterData>::bootstrap(client::ClientConfig {
+                leader_hint,
+                shard: ShardId::ZERO,
+                node,
+            })
+            .await
+            .unwrap();
+            let reply = client.update(42).await;
+
+            // the replicas are still reachable while running
+            let mut handle = handles.into_iter().next().unwrap().await.unwrap();
+            let leader = handle.current_leader().await.unwrap();
+            let metrics = handle.metrics_prometheus().await.unwrap();
+            (reply, leader, metrics)
+        });
+        assert_eq!(reply, 42);
+        assert_eq!(leader, NodeId::from(0u32));
+        assert!(metrics.contains("node=\"0\""));
+    }
+
+    #[test]
+    fn test_client_queries_request_status() {
+        init_runtime();
+
+        let (node, _handles) = spawn_cluster(|_| ());
+
+        // the request is reported as executed once
+        // its reply was returned to the client
+        let status = block_on_cluster(async {
+            let leader_hint = None;
+            let mut client = Client::<CounterData>::bootstrap(client::ClientConfig {
+                leader_hint,
+                shard: ShardId::ZERO,
+                node,
+            })
+            .await
+            .unwrap();
+            let receipt = client.submit(42).await;
+            let reply = client.wait(receipt.clone()).await;
+            assert_eq!(reply, 42);
+            client.query_status(&receipt).await
+        });
+        assert!(matches!(status, RequestStatus::Executed(42)));
+    }
+
+    #[test]
+    fn test_leader_steps_down() {
+        init_runtime();
+
+        let (node, handles) = spawn_cluster(|_| ());
+
+        let (views, reply) = block_on_cluster(async {
+            let mut replicas = Vec::new();
+            for handle in handles {
+                replicas.push(handle.await.unwrap());
+            }
+
+            // only the leader of the current view may step down
+            assert!(replicas[1].step_down().await.is_err());
+            replicas[0].step_down().await.unwrap();
+
+            // every replica changes views, including the old leader
+            let mut views = Vec::new();
+            for replica in replicas.iter_mut() {
+                let view = loop {
+                    let view = replica.current_view().await.unwrap();
+                    if view.sequence_number() != SeqNo::ZERO {
+                        break view;
+                    }
+                    Delay::new(Duration::from_millis(10)).await;
+                };
+                views.push(view);
+            }
+
+            // the new leader keeps deciding client requests
+            let leader_hint = None;
+            let mut client = Client::<CounterData>::bootstrap(client::ClientConfig {
+                leader_hint,
+                shard: ShardId::ZERO,
+                node,
+            })
+            .await
+            .unwrap();
+            (views, client.update(42).await)
+        });
+        for view in views {
+            assert_eq!(view.sequence_number(), SeqNo::from(1u32));
+            assert_eq!(view.leader(), NodeId::from(1u32));
+        }
+        assert_eq!(reply, 42);
+    }
+
+    #[test]
+    fn test_partitioned_leader_catches_up() {
+        init_runtime();
+
+        let (network, node, handles) = spawn_cluster_network(|cfg| {
+            cfg.node.reconnect = Some(Backoff {
+                base: Duration::from_millis(50),
+                max: Duration::from_millis(500),
+                factor: 2.0,
+            });
+            cfg.request_timeout = Duration::from_millis(500);
+            cfg.cst_timeout = Duration::from_millis(500);
+            cfg.consensus_watchdog = Duration::from_secs(1);
+            // so the majority soon has a checkpoint to serve, whole,
+            // as often as the old leader requests it
+            cfg.checkpoint_period = 2;
+            cfg.state_serve_interval = Duration::ZERO;
+            cfg.state_chunk_size = None;
+        });
+        let addr = |id: u32| node.addrs[&NodeId::from(id)].0;
+        let (old_leader, majority) = ([addr(0)], [addr(1), addr(2), addr(3)]);
+
+        let (replies, stalled, elected, caught_up) = block_on_cluster(async {
+            let mut replicas = Vec::new();
+            for handle in handles {
+                replicas.push(handle.await.unwrap());
+            }
+            let leader_hint = None;
+            let mut client = Client::<CounterData>::bootstrap(client::ClientConfig {
+                leader_hint,
+                shard: ShardId::ZERO,
+                node,
+            })
+            .await
+            .unwrap();
+            let mut replies = vec![client.update(1).await];
+
+            // the client reaches both sides of the partition, but the
+            // leader of the first view can't reach the other replicas,
+            // which elect a new leader to decide the request
+            network.partition(&[&old_leader[..], &majority[..]]);
+            replies.push(client.update(1).await);
+            let stalled = replicas[0].current_view().await.unwrap();
+            let elected = replicas[1].current_view().await.unwrap();
+
+            // once healed, the old leader sees its peers deciding
+            // requests in the new view, and recovers the latest
+            // view and state from them
+            network.heal();
+            let caught_up = loop {
+                replies.push(client.update(1).await);
+                let view = replicas[0].current_view().await.unwrap();
+                if view.sequence_number() != SeqNo::ZERO {
+                    break view;
                 }
+                Delay::new(Duration::from_millis(250)).await;
+            };
+            (replies, stalled, elected, caught_up)
+        });
+        let expected: Vec<_> = (1..=replies.len() as u64).collect();
+        assert_eq!(replies, expected);
+        assert_eq!(stalled.sequence_number(), SeqNo::ZERO);
+        assert_ne!(elected.leader(), NodeId::from(0u32));
+        assert!(caught_up.sequence_number() >= elected.sequence_number());
+    }
+
+    #[test]
+    fn test_client_redirected_to_leader() {
+        init_runtime();
+
+        let (node, _handles) = spawn_cluster(|cfg| cfg.redirect_requests = true);
+
+        // replica 1 isn't the leader of the first view, so it rejects
+        // the request, and the client sends it again to replica 0
+        let (reply, leader) = block_on_cluster(async {
+            let leader_hint = Some(NodeId::from(1u32));
+            let mut client = Client::<CounterData>::bootstrap(client::ClientConfig {
+                leader_hint,
+                shard: ShardId::ZERO,
+                node,
+            })
+            .await
+            .unwrap();
+            let reply = client.update(42).await;
+            (reply, client.leader_hint())
+        });
+        assert_eq!(reply, 42);
+        assert_eq!(leader, Some(NodeId::from(0u32)));
+    }
+
+    #[test]
+    fn test_throttled_client_resends_request() {
+        const PAUSE: Duration = Duration::from_millis(500);
+
+        init_runtime();
+
+        let (node, handles) = spawn_cluster(|_| ());
+
+        // every replica throttles the request while paused, so it is
+        // only executed once the client sends it again after they resume
+        let (reply, elapsed) = block_on_cluster(async {
+            let mut replicas = Vec::new();
+            for handle in handles {
+                let mut handle = handle.await.unwrap();
+                handle.pause_ingestion().await.unwrap();
+                replicas.push(handle);
+            }
+            let start = Instant::now();
+            rt::spawn(async move {
+                Delay::new(PAUSE).await;
+                for handle in replicas.iter_mut() {
+                    handle.resume_ingestion().await.unwrap();
+                }
+            });
+
+            let mut client = Client::<CounterData>::bootstrap(client::ClientConfig {
+                leader_hint: None,
+                shard: ShardId::ZERO,
+                node,
+            })
+            .await
+            .unwrap();
+            let reply = client.update(42).await;
+            (reply, start.elapsed())
+        });
+        assert_eq!(reply, 42);
+        assert!(elapsed >= PAUSE);
+    }
+
+    #[test]
+    fn test_shards_decide_independently() {
+        init_runtime();
+
+        let node_config = cluster_configs(2);
+        let shards = [ShardId::from(1u32), ShardId::from(2u32)];
+
+        // each replica hosts both shards over the connections of one node
+        for id in 0..4 {
+            let node_config = node_config(id);
+            rt::spawn(async move {
+                let (node, rogue) = Node::bootstrap(node_config).await.unwrap();
+                for (node, rogue) in node.into_shards(&shards, rogue).unwrap() {
+                    let mut cfg = config(usize::MAX);
+                    cfg.node.n = 4;
+                    cfg.node.f = 1;
+                    cfg.batch_size = 1;
+                    rt::spawn(async move {
+                        let replica = Replica::bootstrap_shard(cfg, node, rogue).await.unwrap();
+                        replica.run().await.unwrap();
+                    });
+                }
+            });
+        }
+
+        // the counters of the shards are updated independently
+        let replies = block_on_cluster(async {
+            let mut clients = Vec::new();
+            for (i, &shard) in shards.iter().enumerate() {
+                let node = node_config(1000 + i as u32);
+                let client = Client::<CounterData>::bootstrap(client::ClientConfig {
+                    leader_hint: None,
+                    shard,
+                    node,
+                });
+                clients.push(client.await.unwrap());
             }
+            let mut replies = Vec::new();
+            for (client, &update) in clients.iter_mut().zip(&[1, 10]) {
+                replies.push(client.update(update).await);
+            }
+            replies.push(clients[1].update(10).await);
+            replies.push(clients[0].update(1).await);
+            replies
+        });
+        assert_eq!(replies, vec![1, 10, 20, 2]);
+    }
+
+    #[test]
+    fn test_queued_replies_delivered_before_shutdown() {
+        init_runtime();
+
+        let node_config = cluster_configs(1);
+        for id in 1..4 {
+            let mut cfg = config(usize::MAX);
+            cfg.node = node_config(id);
+            rt::spawn(async move {
+                let replica = Replica::bootstrap(cfg).await.unwrap();
+                replica.run().await.unwrap();
+            });
         }
+        let mut cfg = config(usize::MAX);
+        cfg.node = node_config(0);
+        let client_id = NodeId::from(1000u32);
+
+        let replies = block_on_cluster(async {
+            // replica 0 is driven by hand, until the client is connected
+            let replica = async {
+                let mut replica = Replica::bootstrap(cfg).await.unwrap();
+                while !replica.node.is_connected(client_id) {
+                    replica.update_normal_phase().await.unwrap();
+                }
+                replica
+            };
+            let client = Node::<CounterData>::bootstrap(node_config(1000));
+            let (mut replica, client) = future::join(replica, client).await;
+            let (mut client, _) = client.unwrap();
+
+            // the requests of the client were decided, and are queued
+            // for execution when the replica is told to shut down
+            for seq in 0..4u32 {
+                let mut batch = UpdateBatch::new();
+                let digest = Digest::from_bytes(&[seq as u8; DefaultHasher::LENGTH][..]).unwrap();
+                batch.add(client_id, SeqNo::from(seq), digest, 1, None);
+                replica.executor.queue_update(batch).unwrap();
+            }
+            replica.shutdown(Duration::from_secs(10)).await.unwrap();
+            drop(replica);
+
+            // the replies were written before the shutdown completed
+            let mut replies = Vec::new();
+            while replies.len() < 4 {
+                if let Message::System(header, SystemMessage::Reply(reply)) =
+                    client.receive().await.unwrap()
+                {
+                    assert_eq!(header.from(), NodeId::from(0u32));
+                    replies.push(*reply.payload());
+                }
+            }
+            // each reply is sent by a task of its own
+            replies.sort_unstable();
+            replies
+        });
+        assert_eq!(replies, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_handle_shuts_down_replica() {
+        init_runtime();
+
+        let cfg = config(usize::MAX);
+        let replica = rt::block_on(Replica::bootstrap_with(cfg, loopback)).unwrap();
+        let mut handle = replica.handle();
+
+        // the replica stops running once it is shut down
+        let (tx, rx) = oneshot::channel();
+        rt::spawn(async move {
+            tx.send(replica.run().await).unwrap_or(());
+        });
+        rt::block_on(async {
+            handle.shutdown(Duration::from_secs(1)).await.unwrap();
+            rx.await.unwrap().unwrap();
+            assert!(handle.current_view().await.is_err());
+        });
     }
 }
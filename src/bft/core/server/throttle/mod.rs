@@ -0,0 +1,131 @@
+//! Per client rate limiting of the requests admitted by a replica.
+//!
+//! Each client is assigned a token bucket, from which a token is taken
+//! whenever one of its requests is admitted, such that a single client
+//! flooding a replica can't crowd out the requests of other clients.
+
+use std::time::{Duration, Instant};
+
+use crate::bft::collections::{self, HashMap};
+use crate::bft::communication::NodeId;
+
+/// The rate at which a replica admits the requests of each client.
+#[derive(Debug, Copy, Clone)]
+pub struct RateLimit {
+    /// The number of requests a client may send in a burst,
+    /// i.e. the capacity of its token bucket.
+    pub burst: u32,
+    /// The interval at which a token is added to the bucket
+    /// of a client, i.e. the sustained rate of its requests.
+    /// A zero interval disables rate limiting.
+    pub refill_interval: Duration,
+}
+
+struct Bucket {
+    tokens: u32,
+    refilled_at: Instant,
+}
+
+/// Enforces a `RateLimit` on the requests of every client.
+pub struct RateLimiter {
+    limit: RateLimit,
+    // NOTE: only clients with a known public key get
+    // their requests this far, so the map stays bounded
+    buckets: HashMap<NodeId, Bucket>,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter`, enforcing `limit`.
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: collections::hash_map(),
+        }
+    }
+
+    /// Takes a token from the bucket of `client`, at the instant `now`.
+    ///
+    /// If the bucket of the client is empty, its request should be
+    /// dropped, and the duration until a token is added to the bucket
+    /// is returned, for the client to back off.
+    pub fn throttle(&mut self, client: NodeId, now: Instant) -> Option<Duration> {
+        let RateLimit {
+            burst,
+            refill_interval,
+        } = self.limit;
+        if refill_interval == Duration::ZERO {
+            return None;
+        }
+        let bucket = self.buckets.entry(client).or_insert_with(|| Bucket {
+            tokens: burst,
+            refilled_at: now,
+        });
+
+        // add the tokens accrued since the last refill
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        let accrued = elapsed.as_nanos() / refill_interval.as_nanos();
+        if accrued > 0 {
+            let accrued = accrued.min(burst as u128) as u32;
+            bucket.tokens = bucket.tokens.saturating_add(accrued).min(burst);
+            bucket.refilled_at = if bucket.tokens == burst {
+                now
+            } else {
+                bucket.refilled_at + refill_interval * accrued
+            };
+        }
+
+        if bucket.tokens > 0 {
+            bucket.tokens -= 1;
+            return None;
+        }
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        Some(refill_interval.saturating_sub(elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{RateLimit, RateLimiter};
+    use crate::bft::communication::NodeId;
+
+    #[test]
+    fn test_token_bucket_refill() {
+        let limit = RateLimit {
+            burst: 2,
+            refill_interval: Duration::from_millis(100),
+        };
+        let mut limiter = RateLimiter::new(limit);
+        let client = NodeId::from(1000u32);
+        let start = Instant::now();
+
+        // the burst is admitted right away
+        assert_eq!(limiter.throttle(client, start), None);
+        assert_eq!(limiter.throttle(client, start), None);
+
+        // then the client must wait for the next token
+        let now = start + Duration::from_millis(40);
+        assert_eq!(
+            limiter.throttle(client, now),
+            Some(Duration::from_millis(60))
+        );
+        let now = start + Duration::from_millis(100);
+        assert_eq!(limiter.throttle(client, now), None);
+        assert!(limiter.throttle(client, now).is_some());
+
+        // idle clients accrue no more than a burst
+        let now = start + Duration::from_secs(10);
+        for _ in 0..2 {
+            assert_eq!(limiter.throttle(client, now), None);
+        }
+        assert!(limiter.throttle(client, now).is_some());
+
+        // a zero interval disables rate limiting
+        let mut limiter = RateLimiter::new(RateLimit {
+            burst: 0,
+            refill_interval: Duration::ZERO,
+        });
+        assert_eq!(limiter.throttle(client, start), None);
+    }
+}
@@ -1,99 +1,133 @@
 //! Abstractions over different crypto hash digest algorithms.
 
-#[cfg(feature = "crypto_hash_ring_sha2")]
+// the backends are compiled whenever their crate is linked, such
+// that applications may pick them through `SharedData::Hasher`
+
+#[cfg(feature = "ring")]
 mod ring_sha2;
 
-#[cfg(feature = "crypto_hash_blake3_blake3")]
+#[cfg(feature = "blake3")]
 mod blake3_blake3;
 
-#[cfg(feature = "serialize_serde")]
-use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
 
 use crate::bft::error::*;
 
 /// The type `Context` represents an on-going hash digest calculation.
+///
+/// Each `DigestHasher` hands out contexts computing its own algorithm,
+/// through `DigestHasher::context()`.
 pub struct Context {
-    #[cfg(feature = "crypto_hash_ring_sha2")]
-    inner: ring_sha2::Context,
+    inner: ContextInner,
+}
+
+enum ContextInner {
+    #[cfg(feature = "ring")]
+    Ring(ring_sha2::Context),
 
-    #[cfg(feature = "crypto_hash_blake3_blake3")]
-    inner: blake3_blake3::Context,
+    #[cfg(feature = "blake3")]
+    Blake3(blake3_blake3::Context),
 }
 
 /// Represents a hash digest.
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
-#[repr(transparent)]
-#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+///
+/// A `Digest` is as long as the digests of the `DigestHasher` which
+/// produced it, i.e. `DigestHasher::LENGTH` bytes, up to a maximum of
+/// `Digest::MAX_LENGTH` bytes. Digests of different lengths never
+/// compare equal.
+#[derive(Copy, Clone)]
+#[repr(C)]
 pub struct Digest {
-    #[cfg(feature = "crypto_hash_ring_sha2")]
-    inner: ring_sha2::Digest,
-
-    #[cfg(feature = "crypto_hash_blake3_blake3")]
-    inner: blake3_blake3::Digest,
+    // the no. of bytes in use, the remaining ones are zeroed
+    len: u8,
+    bytes: [u8; Digest::MAX_LENGTH],
 }
 
 impl Context {
-    /// Initializes a new `Context` instance.
+    /// Initializes a new `Context` instance, computing the hash digest
+    /// algorithm picked with the `crypto_hash_*` features.
     ///
     /// Feed this it data with `Context::update`.
     pub fn new() -> Self {
-        let inner = {
-            #[cfg(feature = "crypto_hash_ring_sha2")]
-            {
-                ring_sha2::Context::new()
-            }
-
-            #[cfg(feature = "crypto_hash_blake3_blake3")]
-            {
-                blake3_blake3::Context::new()
-            }
-        };
-        Context { inner }
+        DefaultHasher::context()
     }
 
     /// Feeds the `Context` some data to be hashed.
     pub fn update(&mut self, data: &[u8]) {
-        self.inner.update(data);
+        match self.inner {
+            #[cfg(feature = "ring")]
+            ContextInner::Ring(ref mut ctx) => ctx.update(data),
+
+            #[cfg(feature = "blake3")]
+            ContextInner::Blake3(ref mut ctx) => ctx.update(data),
+        }
     }
 
     /// Extracts the resulting digest of hashing data onto the `Context`.
     pub fn finish(self) -> Digest {
-        let inner = self.inner.finish();
-        Digest { inner }
+        match self.inner {
+            #[cfg(feature = "ring")]
+            ContextInner::Ring(ctx) => Digest::from_hasher(ctx.finish().as_ref()),
+
+            #[cfg(feature = "blake3")]
+            ContextInner::Blake3(ctx) => Digest::from_hasher(ctx.finish().as_bytes()),
+        }
+    }
+}
+
+impl io::Write for Context {
+    /// Feeds the `Context` the data in `buf`, which is fully consumed.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
 }
 
 impl Digest {
-    /// The length of the `Digest` in bytes.
-    pub const LENGTH: usize = {
-        #[cfg(feature = "crypto_hash_ring_sha2")]
-        {
-            ring_sha2::Digest::LENGTH
-        }
+    /// The maximum length of a `Digest` in bytes, i.e. the length of
+    /// the longest digests produced by any `DigestHasher`.
+    pub const MAX_LENGTH: usize = 64;
 
-        #[cfg(feature = "crypto_hash_blake3_blake3")]
-        {
-            blake3_blake3::Digest::LENGTH
-        }
+    // the digest carried by the headers of messages without a payload
+    pub(crate) const EMPTY: Self = Digest {
+        len: 0,
+        bytes: [0; Self::MAX_LENGTH],
     };
 
-    /// Constructs a `Digest` from a byte buffer of appropriate size.
+    /// Constructs a `Digest` from a byte buffer, holding the digest
+    /// produced by some `DigestHasher`.
     pub fn from_bytes(raw_bytes: &[u8]) -> Result<Self> {
-        let inner = {
-            #[cfg(feature = "crypto_hash_ring_sha2")]
-            {
-                ring_sha2::Digest::from_bytes(raw_bytes)
-            }
+        if raw_bytes.is_empty() || raw_bytes.len() > Self::MAX_LENGTH {
+            return Err("Digest has an invalid length").wrapped(ErrorKind::CryptoHash);
+        }
+        Ok(Self::from_hasher(raw_bytes))
+    }
 
-            #[cfg(feature = "crypto_hash_blake3_blake3")]
-            {
-                blake3_blake3::Digest::from_bytes(raw_bytes)
-            }
-        }?;
-        Ok(Digest { inner })
+    // wraps the digest `raw_bytes` of some `DigestHasher`, whose
+    // length was checked against `Digest::MAX_LENGTH`
+    fn from_hasher(raw_bytes: &[u8]) -> Self {
+        let mut bytes = [0; Self::MAX_LENGTH];
+        bytes[..raw_bytes.len()].copy_from_slice(raw_bytes);
+        let len = raw_bytes.len() as u8;
+        Digest { len, bytes }
     }
 
-    /// Hashes this `Digest` with the given `nonce`.
+    // checks if the bytes of this `Digest` were read from a foreign
+    // source in the layout of a valid `Digest`
+    pub(crate) fn is_well_formed(&self) -> bool {
+        let len = self.len as usize;
+        len <= Self::MAX_LENGTH && self.bytes[len..].iter().all(|&b| b == 0)
+    }
+
+    /// Hashes this `Digest` with the given `nonce`, with the hash
+    /// digest algorithm picked with the `crypto_hash_*` features.
     pub fn entropy<B: AsRef<[u8]>>(&self, nonce: B) -> Self {
         let mut ctx = Context::new();
         ctx.update(nonce.as_ref());
@@ -102,18 +136,173 @@ impl Digest {
     }
 }
 
+/// A hash digest algorithm, which applications pick through
+/// `SharedData::Hasher` to digest the payloads of their messages,
+/// as well as the headers they sign and the states they transfer.
+pub trait DigestHasher {
+    /// The length of the digests produced by this algorithm, in bytes,
+    /// which is at most `Digest::MAX_LENGTH`.
+    const LENGTH: usize;
+
+    /// Returns a new `Context` hashing data with this algorithm.
+    fn context() -> Context;
+
+    /// Returns the digest of the concatenation of `chunks`.
+    fn digest(chunks: &[&[u8]]) -> Digest {
+        let mut ctx = Self::context();
+        for chunk in chunks {
+            ctx.update(chunk);
+        }
+        ctx.finish()
+    }
+}
+
+/// The hash digest algorithm picked with the `crypto_hash_*` features.
+#[cfg(feature = "crypto_hash_ring_sha2")]
+pub type DefaultHasher = Sha256;
+
+/// The hash digest algorithm picked with the `crypto_hash_*` features.
+#[cfg(feature = "crypto_hash_blake3_blake3")]
+pub type DefaultHasher = Blake3;
+
+/// SHA-256, as implemented by `ring`.
+#[cfg(feature = "ring")]
+pub struct Sha256;
+
+#[cfg(feature = "ring")]
+impl DigestHasher for Sha256 {
+    const LENGTH: usize = ring_sha2::SHA256_LENGTH;
+
+    fn context() -> Context {
+        let inner = ContextInner::Ring(ring_sha2::Context::sha256());
+        Context { inner }
+    }
+}
+
+/// SHA-512, as implemented by `ring`.
+#[cfg(feature = "ring")]
+pub struct Sha512;
+
+#[cfg(feature = "ring")]
+impl DigestHasher for Sha512 {
+    const LENGTH: usize = ring_sha2::SHA512_LENGTH;
+
+    fn context() -> Context {
+        let inner = ContextInner::Ring(ring_sha2::Context::sha512());
+        Context { inner }
+    }
+}
+
+/// BLAKE3, as implemented by the `blake3` crate.
+#[cfg(feature = "blake3")]
+pub struct Blake3;
+
+#[cfg(feature = "blake3")]
+impl DigestHasher for Blake3 {
+    const LENGTH: usize = blake3_blake3::LENGTH;
+
+    fn context() -> Context {
+        let inner = ContextInner::Blake3(blake3_blake3::Context::new());
+        Context { inner }
+    }
+}
+
 impl AsRef<[u8]> for Digest {
     fn as_ref(&self) -> &[u8] {
-        self.inner.as_ref()
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl PartialEq for Digest {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl Eq for Digest {}
+
+impl Hash for Digest {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state)
+    }
+}
+
+// digests are ordered by their bytes, such that every
+// node orders them the same way, e.g. to break ties
+impl PartialOrd for Digest {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Digest {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_ref().cmp(other.as_ref())
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest(")?;
+        for byte in self.as_ref() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(feature = "serialize_serde")]
+impl serde::Serialize for Digest {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serde_bytes::serialize(self.as_ref(), serializer)
+    }
+}
+
+#[cfg(feature = "serialize_serde")]
+impl<'de> serde::Deserialize<'de> for Digest {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Digest, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let bytes: serde_bytes::ByteBuf = serde::Deserialize::deserialize(deserializer)?;
+        Digest::from_bytes(&bytes[..]).map_err(serde::de::Error::custom)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Digest;
+    use super::{Context, DefaultHasher, Digest, DigestHasher};
 
     #[test]
     fn test_length() {
-        assert_eq!(Digest::LENGTH, std::mem::size_of::<Digest>());
+        assert_eq!(Digest::MAX_LENGTH + 1, std::mem::size_of::<Digest>());
+        assert_eq!(
+            Context::new().finish().as_ref().len(),
+            DefaultHasher::LENGTH
+        );
+    }
+
+    #[cfg(all(feature = "ring", feature = "blake3"))]
+    #[test]
+    fn test_hasher_lengths() {
+        use super::{Blake3, Sha256, Sha512};
+
+        let sha256 = Sha256::digest(&[b"cool\n"]);
+        let sha512 = Sha512::digest(&[b"cool\n"]);
+        let blake3 = Blake3::digest(&[b"cool\n"]);
+        assert_eq!(sha256.as_ref().len(), Sha256::LENGTH);
+        assert_eq!(sha512.as_ref().len(), Sha512::LENGTH);
+        assert_eq!(blake3.as_ref().len(), Blake3::LENGTH);
+        assert_eq!(Sha512::LENGTH, Digest::MAX_LENGTH);
+
+        // digests of different lengths never match, even if
+        // one of them is a prefix of the other
+        let prefix = Digest::from_bytes(&sha512.as_ref()[..Sha256::LENGTH]).unwrap();
+        assert!(prefix != sha512);
+        assert!(Digest::from_bytes(&[0; Digest::MAX_LENGTH + 1][..]).is_err());
+        assert!(Digest::from_bytes(&[]).is_err());
     }
 }
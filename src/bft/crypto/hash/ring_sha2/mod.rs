@@ -1,55 +1,30 @@
-use ring::digest::{self, SHA256, SHA256_OUTPUT_LEN};
+use ring::digest::{self, SHA256, SHA256_OUTPUT_LEN, SHA512, SHA512_OUTPUT_LEN};
 
-#[cfg(feature = "serialize_serde")]
-use serde::{Deserialize, Serialize};
+pub const SHA256_LENGTH: usize = SHA256_OUTPUT_LEN;
 
-use crate::bft::error::*;
+pub const SHA512_LENGTH: usize = SHA512_OUTPUT_LEN;
 
 pub struct Context {
     inner: digest::Context,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
-#[repr(transparent)]
-#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
-pub struct Digest([u8; Digest::LENGTH]);
-
 impl Context {
-    pub fn new() -> Self {
+    pub fn sha256() -> Self {
         let inner = digest::Context::new(&SHA256);
         Context { inner }
     }
 
-    pub fn update(&mut self, data: &[u8]) {
-        self.inner.update(data);
-    }
-
-    pub fn finish(self) -> Digest {
-        let h = self.inner.finish();
-        Digest::from_bytes_unchecked(h.as_ref())
-    }
-}
-
-impl Digest {
-    pub const LENGTH: usize = SHA256_OUTPUT_LEN;
-
-    pub fn from_bytes(raw_bytes: &[u8]) -> Result<Self> {
-        if raw_bytes.len() < Self::LENGTH {
-            return Err("Digest has an invalid length").wrapped(ErrorKind::CryptoHashRingSha2);
-        }
-        Ok(Self::from_bytes_unchecked(raw_bytes))
+    pub fn sha512() -> Self {
+        let inner = digest::Context::new(&SHA512);
+        Context { inner }
     }
 
-    fn from_bytes_unchecked(raw_bytes: &[u8]) -> Self {
-        let mut inner = [0; Self::LENGTH];
-        inner.copy_from_slice(&raw_bytes[..Self::LENGTH]);
-        Self(inner)
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
     }
-}
 
-impl AsRef<[u8]> for Digest {
-    fn as_ref(&self) -> &[u8] {
-        &self.0
+    pub fn finish(self) -> digest::Digest {
+        self.inner.finish()
     }
 }
 
@@ -59,7 +34,7 @@ mod tests {
 
     #[test]
     fn test_digest() {
-        let mut ctx = Context::new();
+        let mut ctx = Context::sha256();
         ctx.update(b"cool\n");
         let h = ctx.finish();
         assert_eq!(
@@ -0,0 +1,123 @@
+#[cfg(feature = "serialize_serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serialize_serde")]
+use serde_big_array::big_array;
+
+use ed448_rust::{KEY_LENGTH, SIG_LENGTH};
+
+use crate::bft::error::*;
+
+pub struct KeyPair {
+    sk: ed448_rust::PrivateKey,
+    pk: PublicKey,
+}
+
+#[derive(Copy, Clone)]
+pub struct PublicKey {
+    // like ring's `UnparsedPublicKey`, the key is
+    // only decoded when a signature is verified
+    pk: [u8; KEY_LENGTH],
+}
+
+#[cfg(feature = "serialize_serde")]
+big_array! { SignatureArray; }
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(transparent)]
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+pub struct Signature(
+    #[cfg_attr(feature = "serialize_serde", serde(with = "SignatureArray"))]
+    [u8; Signature::LENGTH],
+);
+
+impl KeyPair {
+    pub const SEED_LENGTH: usize = KEY_LENGTH;
+
+    pub fn from_bytes(seed_bytes: &[u8]) -> Result<Self> {
+        if seed_bytes.len() < KEY_LENGTH {
+            return Err("Invalid seed for ed448 key")
+                .wrapped(ErrorKind::CryptoSignatureEd448RustEd448);
+        }
+        let sk = ed448_rust::PrivateKey::try_from(&seed_bytes[..KEY_LENGTH]).simple_msg(
+            ErrorKind::CryptoSignatureEd448RustEd448,
+            "Invalid seed for ed448 key",
+        )?;
+        let pk = ed448_rust::PublicKey::from(&sk);
+        let pk = PublicKey { pk: pk.as_byte() };
+        Ok(KeyPair { sk, pk })
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.pk
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<Signature> {
+        let signature = self
+            .sk
+            .sign(message, None)
+            .simple_msg(ErrorKind::CryptoSignatureEd448RustEd448, "Signing failed")?;
+        Ok(Signature(signature))
+    }
+}
+
+impl PublicKey {
+    pub fn from_bytes(raw_bytes: &[u8]) -> Result<Self> {
+        if raw_bytes.len() < KEY_LENGTH {
+            return Err("Public key has an invalid length")
+                .wrapped(ErrorKind::CryptoSignatureEd448RustEd448);
+        }
+        let mut pk = [0; KEY_LENGTH];
+        pk.copy_from_slice(&raw_bytes[..KEY_LENGTH]);
+        Ok(PublicKey { pk })
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<()> {
+        ed448_rust::PublicKey::try_from(&self.pk[..])
+            .and_then(|pk| pk.verify(message, signature.as_ref(), None))
+            .simple_msg(
+                ErrorKind::CryptoSignatureEd448RustEd448,
+                "Invalid signature",
+            )
+    }
+}
+
+impl Signature {
+    pub const LENGTH: usize = SIG_LENGTH;
+
+    pub fn from_bytes(raw_bytes: &[u8]) -> Result<Self> {
+        if raw_bytes.len() < Self::LENGTH {
+            return Err("Signature has an invalid length")
+                .wrapped(ErrorKind::CryptoSignatureEd448RustEd448);
+        }
+        let mut inner = [0; Self::LENGTH];
+        inner.copy_from_slice(&raw_bytes[..Self::LENGTH]);
+        Ok(Self(inner))
+    }
+}
+
+impl AsRef<[u8]> for Signature {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyPair, Signature};
+
+    #[test]
+    fn test_sign_verify() {
+        let k = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).expect("Invalid key bytes");
+
+        let message = b"test message";
+        let signature = k.sign(message).expect("Signature failed");
+        assert_eq!(signature.as_ref().len(), Signature::LENGTH);
+        k.public_key()
+            .verify(message, &signature)
+            .expect("Verify failed");
+
+        let forged = Signature::from_bytes(&[0; Signature::LENGTH][..]).unwrap();
+        assert!(k.public_key().verify(message, &forged).is_err());
+    }
+}
@@ -8,11 +8,17 @@ use crate::bft::error::*;
 #[cfg(feature = "crypto_signature_ring_ed25519")]
 mod ring_ed25519;
 
+#[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+mod ed448_rust_ed448;
+
 /// A `KeyPair` holds both the private and public key components
 /// that form a digital identity.
 pub struct KeyPair {
     #[cfg(feature = "crypto_signature_ring_ed25519")]
     inner: ring_ed25519::KeyPair,
+
+    #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+    inner: ed448_rust_ed448::KeyPair,
 }
 
 /// The public component of a `KeyPair`.
@@ -20,12 +26,18 @@ pub struct KeyPair {
 pub struct PublicKey {
     #[cfg(feature = "crypto_signature_ring_ed25519")]
     inner: ring_ed25519::PublicKey,
+
+    #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+    inner: ed448_rust_ed448::PublicKey,
 }
 
 /// Reference to a `PublicKey`.
 pub struct PublicKeyRef<'a> {
     #[cfg(feature = "crypto_signature_ring_ed25519")]
     inner: &'a ring_ed25519::PublicKey,
+
+    #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+    inner: &'a ed448_rust_ed448::PublicKey,
 }
 
 /// A `Signature` is the result of using `KeyPair::sign`. Represents
@@ -42,9 +54,26 @@ pub struct PublicKeyRef<'a> {
 pub struct Signature {
     #[cfg(feature = "crypto_signature_ring_ed25519")]
     inner: ring_ed25519::Signature,
+
+    #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+    inner: ed448_rust_ed448::Signature,
 }
 
 impl KeyPair {
+    /// Length in bytes of the seed a `KeyPair` is derived from,
+    /// i.e. the size of the buffer passed to `from_bytes()`.
+    pub const SEED_LENGTH: usize = {
+        #[cfg(feature = "crypto_signature_ring_ed25519")]
+        {
+            ring_ed25519::KeyPair::SEED_LENGTH
+        }
+
+        #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+        {
+            ed448_rust_ed448::KeyPair::SEED_LENGTH
+        }
+    };
+
     /// Constructs a `KeyPair` from a byte buffer of appropriate size.
     pub fn from_bytes(raw_bytes: &[u8]) -> Result<Self> {
         let inner = {
@@ -52,6 +81,11 @@ impl KeyPair {
             {
                 ring_ed25519::KeyPair::from_bytes(raw_bytes)?
             }
+
+            #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+            {
+                ed448_rust_ed448::KeyPair::from_bytes(raw_bytes)?
+            }
         };
         Ok(KeyPair { inner })
     }
@@ -97,6 +131,11 @@ impl PublicKey {
             {
                 ring_ed25519::PublicKey::from_bytes(raw_bytes)?
             }
+
+            #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+            {
+                ed448_rust_ed448::PublicKey::from_bytes(raw_bytes)?
+            }
         };
         Ok(PublicKey { inner })
     }
@@ -117,6 +156,26 @@ impl Signature {
         {
             ring_ed25519::Signature::LENGTH
         }
+
+        #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+        {
+            ed448_rust_ed448::Signature::LENGTH
+        }
+    };
+
+    /// Identifies the signature scheme in use, which is part of
+    /// the wire protocol version, since `Signature::LENGTH` sizes
+    /// every `Header`; nodes using different schemes can't talk.
+    pub const SCHEME: u32 = {
+        #[cfg(feature = "crypto_signature_ring_ed25519")]
+        {
+            0
+        }
+
+        #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+        {
+            1
+        }
     };
 
     /// Constructs a `Signature` from a byte buffer of appropriate size.
@@ -126,6 +185,11 @@ impl Signature {
             {
                 ring_ed25519::Signature::from_bytes(raw_bytes)?
             }
+
+            #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+            {
+                ed448_rust_ed448::Signature::from_bytes(raw_bytes)?
+            }
         };
         Ok(Signature { inner })
     }
@@ -139,10 +203,37 @@ impl AsRef<[u8]> for Signature {
 
 #[cfg(test)]
 mod tests {
-    use super::Signature;
+    use super::{KeyPair, Signature};
 
     #[test]
     fn test_length() {
         assert_eq!(Signature::LENGTH, std::mem::size_of::<Signature>());
     }
+
+    #[test]
+    fn test_sign_verify() {
+        let k = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let message = b"test message";
+        let signature = k.sign(message).unwrap();
+        assert_eq!(signature.as_ref().len(), Signature::LENGTH);
+        assert!(k.public_key().verify(message, &signature).is_ok());
+        assert!(k.public_key().verify(b"other message", &signature).is_err());
+
+        // seeds shorter than the scheme's are rejected
+        assert!(KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH - 1][..]).is_err());
+    }
+
+    #[cfg(feature = "crypto_signature_ring_ed25519")]
+    #[test]
+    fn test_ed25519_sizes() {
+        assert_eq!(KeyPair::SEED_LENGTH, 32);
+        assert_eq!(Signature::LENGTH, 64);
+    }
+
+    #[cfg(feature = "crypto_signature_ed448_rust_ed448")]
+    #[test]
+    fn test_ed448_sizes() {
+        assert_eq!(KeyPair::SEED_LENGTH, 57);
+        assert_eq!(Signature::LENGTH, 114);
+    }
 }
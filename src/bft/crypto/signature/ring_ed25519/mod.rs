@@ -32,6 +32,8 @@ pub struct Signature(
 );
 
 impl KeyPair {
+    pub const SEED_LENGTH: usize = 32;
+
     pub fn from_bytes(seed_bytes: &[u8]) -> Result<Self> {
         let sk = rsig::Ed25519KeyPair::from_seed_unchecked(seed_bytes).simple_msg(
             ErrorKind::CryptoSignatureRingEd25519,
@@ -110,7 +112,7 @@ mod tests {
 
     #[test]
     fn test_sign_verify() {
-        let k = KeyPair::from_bytes(&[0; 32][..]).expect("Invalid key bytes");
+        let k = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).expect("Invalid key bytes");
 
         let message = b"test message";
         let signature = k.sign(message).expect("Signature failed");
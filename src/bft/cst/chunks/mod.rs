@@ -0,0 +1,439 @@
+//! Splitting the states served by the CST protocol into chunks.
+//!
+//! The chunks of a state are content addressed by the merkle root of
+//! their digests, such that a recovering node may fetch them from any
+//! replica holding the same state, and resume an interrupted transfer
+//! by fetching only the chunks it is still missing.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serialize_serde")]
+use serde::{Deserialize, Serialize};
+
+use bytes::{Bytes, BytesMut};
+use parking_lot::Mutex;
+
+use crate::bft::crypto::hash::{Context, Digest};
+use crate::bft::error::*;
+
+// prefixes hashed before the leaves and the inner nodes of the
+// merkle tree, such that an inner node can't pose as a leaf
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Describes a serialized state split into chunks of `chunk_size()`
+/// bytes, except for the last one, which may be shorter.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct StateManifest {
+    root: Digest,
+    length: u64,
+    chunk_size: u64,
+    chunks: Vec<Digest>,
+}
+
+/// A chunk of the serialized state described by a `StateManifest`.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct StateChunk {
+    root: Digest,
+    index: u64,
+    data: Bytes,
+}
+
+impl StateManifest {
+    /// Splits the serialized state `data` into chunks of `chunk_size`
+    /// bytes, and describes them.
+    pub fn new(data: &[u8], chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let chunks: Vec<_> = data.chunks(chunk_size).map(leaf_digest).collect();
+        Self {
+            root: merkle_root(&chunks),
+            length: data.len() as u64,
+            chunk_size: chunk_size as u64,
+            chunks,
+        }
+    }
+
+    /// Returns the merkle root of the digests of the chunks,
+    /// which identifies the state they are a part of.
+    pub fn root(&self) -> &Digest {
+        &self.root
+    }
+
+    /// Returns the length in bytes of the serialized state.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the length in bytes of every chunk but the last.
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Returns the number of chunks the state is split into.
+    pub fn chunks(&self) -> u64 {
+        self.chunks.len() as u64
+    }
+
+    /// Checks if the digests of the chunks add up to the merkle root,
+    /// and if there are as many of them as the length of the state
+    /// calls for.
+    pub fn validate(&self) -> Result<()> {
+        if self.chunk_size == 0 {
+            return Err("Chunks of zero bytes").wrapped(ErrorKind::CstChunks);
+        }
+        if self.chunks() != self.length.div_ceil(self.chunk_size) {
+            return Err("Chunk count doesn't match the state length").wrapped(ErrorKind::CstChunks);
+        }
+        if merkle_root(&self.chunks) != self.root {
+            return Err("Chunks don't match the merkle root").wrapped(ErrorKind::CstChunks);
+        }
+        Ok(())
+    }
+
+    /// Checks if `chunk` is a part of the state described by this manifest.
+    pub fn contains(&self, chunk: &StateChunk) -> bool {
+        chunk.root == self.root
+            && self
+                .chunk_range(chunk.index)
+                .map(|(start, end)| end - start)
+                == Some(chunk.data.len() as u64)
+            && leaf_digest(&chunk.data) == self.chunks[chunk.index as usize]
+    }
+
+    // the range of bytes of the serialized state in the chunk `index`
+    fn chunk_range(&self, index: u64) -> Option<(u64, u64)> {
+        if index >= self.chunks() {
+            return None;
+        }
+        let start = index * self.chunk_size;
+        Some((start, self.length.min(start + self.chunk_size)))
+    }
+}
+
+impl StateChunk {
+    /// Returns the chunk `index` of the serialized state `data`,
+    /// described by `manifest`, if there is such a chunk.
+    pub fn new(manifest: &StateManifest, data: &Bytes, index: u64) -> Option<Self> {
+        let (start, end) = manifest.chunk_range(index)?;
+        if end > data.len() as u64 {
+            return None;
+        }
+        Some(Self {
+            root: manifest.root,
+            index,
+            data: data.slice(start as usize..end as usize),
+        })
+    }
+
+    /// Returns the merkle root of the state this chunk is a part of.
+    pub fn root(&self) -> &Digest {
+        &self.root
+    }
+
+    /// Returns the position of this chunk in the state.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Returns the bytes of the serialized state in this chunk.
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+}
+
+/// Reassembles a serialized state from its chunks, received in any
+/// order, and from any replica holding the same state.
+///
+/// Chunks are appended to the state as soon as every chunk before them
+/// was received, and at most `window` chunks past the first missing one
+/// are buffered meanwhile; chunks further ahead are dropped, such that
+/// a replica sending sparse chunks can't exhaust our memory.
+pub(crate) struct ChunkAssembly {
+    manifest: StateManifest,
+    window: u64,
+    // the chunks received contiguously from the front of the state
+    data: BytesMut,
+    // the index of the first chunk we are missing
+    next: u64,
+    // the chunks received past the first missing one
+    pending: BTreeMap<u64, Bytes>,
+    // the end of the range of chunks requested so far
+    requested: u64,
+}
+
+impl ChunkAssembly {
+    /// Starts reassembling the state described by `manifest`,
+    /// which should have been validated, buffering at most
+    /// `window` chunks out of order.
+    pub(crate) fn new(manifest: StateManifest, window: usize) -> Self {
+        Self {
+            manifest,
+            window: window.max(1) as u64,
+            data: BytesMut::new(),
+            next: 0,
+            pending: BTreeMap::new(),
+            requested: 0,
+        }
+    }
+
+    /// Returns the manifest of the state being reassembled.
+    pub(crate) fn manifest(&self) -> &StateManifest {
+        &self.manifest
+    }
+
+    /// Stores `chunk`, returning whether it is a part of the state
+    /// being reassembled, within the window of chunks we are still
+    /// missing.
+    pub(crate) fn insert(&mut self, chunk: StateChunk) -> bool {
+        if !self.manifest.contains(&chunk) {
+            return false;
+        }
+        let index = chunk.index;
+        if index < self.next || index >= self.window_end() || self.pending.contains_key(&index) {
+            return false;
+        }
+        self.pending.insert(index, chunk.data);
+        while let Some(data) = self.pending.remove(&self.next) {
+            self.data.extend_from_slice(&data[..]);
+            self.next += 1;
+        }
+        true
+    }
+
+    /// Returns the indices of the chunks within the window we are still
+    /// missing.
+    pub(crate) fn missing(&self) -> impl Iterator<Item = u64> + '_ {
+        (self.next..self.window_end()).filter(move |index| !self.pending.contains_key(index))
+    }
+
+    /// Same as `missing()`, but also marks these chunks as requested.
+    pub(crate) fn request(&mut self) -> Vec<u64> {
+        self.requested = self.window_end();
+        self.missing().collect()
+    }
+
+    /// Checks if every chunk requested so far was received, while the
+    /// state is still incomplete, in which case the next window of chunks
+    /// should be requested.
+    pub(crate) fn needs_request(&self) -> bool {
+        !self.is_complete() && self.next >= self.requested
+    }
+
+    /// Returns the number of chunks buffered out of order.
+    #[cfg(test)]
+    pub(crate) fn buffered(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Checks if every chunk of the state was received.
+    pub(crate) fn is_complete(&self) -> bool {
+        self.next == self.manifest.chunks()
+    }
+
+    /// Returns the reassembled state, once it is complete.
+    pub(crate) fn assemble(self) -> Option<Bytes> {
+        if !self.is_complete() {
+            return None;
+        }
+        Some(self.data.freeze())
+    }
+
+    // the end of the window of chunks we may buffer
+    fn window_end(&self) -> u64 {
+        self.manifest.chunks().min(self.next + self.window)
+    }
+}
+
+/// Paces the chunks of the states served by a replica, such that at
+/// most `rate()` bytes worth of chunks are sent per second, across all
+/// the nodes they are served to.
+///
+/// This keeps large state transfers from saturating the links they
+/// share with the consensus traffic of the replica.
+#[derive(Clone)]
+pub(crate) struct ChunkPacer {
+    rate: u64,
+    // the instant when the next chunk may be sent
+    next: Arc<Mutex<Instant>>,
+}
+
+impl ChunkPacer {
+    /// Creates a new `ChunkPacer`, sending `rate` bytes per second.
+    pub(crate) fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Returns the number of bytes sent per second.
+    pub(crate) fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// Reserves the next slot to send a chunk of `len` bytes in,
+    /// returning how long to wait before sending it.
+    pub(crate) fn reserve(&self, len: usize) -> Duration {
+        let now = Instant::now();
+        let mut next = self.next.lock();
+        let start = (*next).max(now);
+        *next = start + Duration::from_secs_f64(len as f64 / self.rate as f64);
+        start - now
+    }
+}
+
+fn leaf_digest(data: &[u8]) -> Digest {
+    let mut ctx = Context::new();
+    ctx.update(&[LEAF_PREFIX]);
+    ctx.update(data);
+    ctx.finish()
+}
+
+// the merkle root of the digests in `leaves`, combining pairs of nodes
+// level by level; a node without a sibling is carried over to the next
+// level as is, and a tree without any leaves hashes no data at all
+fn merkle_root(leaves: &[Digest]) -> Digest {
+    let mut level = leaves.to_vec();
+    if level.is_empty() {
+        return Context::new().finish();
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut ctx = Context::new();
+                    ctx.update(&[NODE_PREFIX]);
+                    ctx.update(left.as_ref());
+                    ctx.update(right.as_ref());
+                    ctx.finish()
+                }
+                [node] => *node,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use std::time::Duration;
+
+    use super::{ChunkAssembly, ChunkPacer, StateChunk, StateManifest};
+    use crate::bft::error::ErrorKind;
+
+    #[test]
+    fn test_state_chunks_reassembled() {
+        let data = Bytes::from((0..1000u32).map(|i| i as u8).collect::<Vec<_>>());
+        let manifest = StateManifest::new(&data[..], 64);
+        manifest.validate().unwrap();
+        assert_eq!(manifest.chunks(), 16);
+
+        // the chunks arrive out of order, and some of them twice
+        let mut assembly = ChunkAssembly::new(manifest.clone(), 16);
+        for index in (0..16).rev() {
+            let chunk = StateChunk::new(&manifest, &data, index).unwrap();
+            assert!(assembly.insert(chunk));
+        }
+        for index in 3..5 {
+            let chunk = StateChunk::new(&manifest, &data, index).unwrap();
+            assert!(!assembly.insert(chunk));
+        }
+        assert!(assembly.is_complete());
+        assert_eq!(assembly.missing().count(), 0);
+        assert_eq!(assembly.assemble().unwrap(), data);
+        assert!(StateChunk::new(&manifest, &data, 16).is_none());
+    }
+
+    #[test]
+    fn test_state_chunks_verified() {
+        let data = Bytes::from(vec![7; 100]);
+        let manifest = StateManifest::new(&data[..], 30);
+        let other = StateManifest::new(&[8; 100][..], 30);
+        assert!(manifest.root() != other.root());
+
+        // chunks of another state, or tampered with, are rejected
+        let mut assembly = ChunkAssembly::new(manifest.clone(), 16);
+        let foreign = StateChunk::new(&other, &Bytes::from(vec![8; 100]), 0).unwrap();
+        assert!(!assembly.insert(foreign));
+        let mut tampered = StateChunk::new(&manifest, &data, 1).unwrap();
+        tampered.data = Bytes::from(vec![7; 29]);
+        assert!(!assembly.insert(tampered));
+        assert_eq!(assembly.missing().collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert!(assembly.assemble().is_none());
+
+        // as are manifests whose chunks don't add up
+        let mut forged = manifest.clone();
+        forged.chunks.swap(0, 3);
+        let err = forged.validate().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::CstChunks);
+        let mut forged = manifest;
+        forged.length = 200;
+        assert!(forged.validate().is_err());
+    }
+
+    #[test]
+    fn test_chunk_pacer_slots() {
+        // the first chunk is sent right away, and every
+        // following chunk once the previous one was paid for
+        let pacer = ChunkPacer::new(1000);
+        assert_eq!(pacer.reserve(100), Duration::ZERO);
+        let wait = pacer.reserve(100);
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+        let wait = pacer.reserve(500);
+        assert!(wait > Duration::from_millis(190) && wait <= Duration::from_millis(200));
+
+        // clones share the same budget
+        let wait = pacer.clone().reserve(100);
+        assert!(wait > Duration::from_millis(690) && wait <= Duration::from_millis(700));
+    }
+
+    #[test]
+    fn test_chunk_reassembly_window() {
+        let data = Bytes::from((0..1024u32).map(|i| i as u8).collect::<Vec<_>>());
+        let manifest = StateManifest::new(&data[..], 16);
+        assert_eq!(manifest.chunks(), 64);
+        let chunk = |index| StateChunk::new(&manifest, &data, index).unwrap();
+
+        let mut assembly = ChunkAssembly::new(manifest.clone(), 8);
+        assert_eq!(assembly.request(), (0..8).collect::<Vec<_>>());
+
+        // sparse chunks past the window are dropped, rather than buffered
+        for index in (8..64).step_by(3) {
+            assert!(!assembly.insert(chunk(index)));
+        }
+        for index in [7, 5, 3, 1] {
+            assert!(assembly.insert(chunk(index)));
+        }
+        assert_eq!(assembly.buffered(), 4);
+        assert!(!assembly.needs_request());
+
+        // the gap is requested again, and once filled, the
+        // buffered chunks are appended to the state
+        assert_eq!(assembly.request(), [0, 2, 4, 6]);
+        for index in [6, 4, 2, 0] {
+            assert!(assembly.insert(chunk(index)));
+        }
+        assert_eq!(assembly.buffered(), 0);
+        assert!(assembly.needs_request());
+
+        // the window moves past the chunks appended
+        assert_eq!(assembly.request(), (8..16).collect::<Vec<_>>());
+        for index in 8..64 {
+            if index % 8 == 0 {
+                assert_eq!(assembly.request().len(), 8);
+            }
+            assert!(assembly.insert(chunk(index)));
+            assert!(assembly.buffered() <= 8);
+        }
+        assert!(!assembly.needs_request());
+        assert_eq!(assembly.assemble().unwrap(), data);
+    }
+}
@@ -3,33 +3,52 @@
 //! The implementation is based on the paper «On the Efﬁciency of
 //! Durable State Machine Replication», by A. Bessani et al.
 
+pub mod chunks;
+
 // NOTE: in this module, we may use cid interchangeably with
 // consensus sequence number
 
 use std::cmp::Ordering;
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "serialize_serde")]
 use serde::{Deserialize, Serialize};
 
+use bytes::Bytes;
+use futures_timer::Delay;
+
+use crate::bft::async_runtime as rt;
 use crate::bft::collections::{self, HashMap};
-use crate::bft::communication::message::{CstMessage, CstMessageKind, Header, SystemMessage};
+use crate::bft::communication::message::{
+    ConsensusMessage, ConsensusMessageKind, CstMessage, CstMessageKind, Header, StoredMessage,
+    SystemMessage,
+};
+use crate::bft::communication::serialize::{DigestData, SharedData, ToBytes};
 use crate::bft::communication::{Node, NodeId};
-use crate::bft::consensus::log::{Checkpoint, DecisionLog, Log};
+use crate::bft::consensus::log::{Checkpoint, DecidedRequest, DecisionLog, ExecutedRequests, Log};
 use crate::bft::consensus::Consensus;
 use crate::bft::core::server::ViewInfo;
-use crate::bft::crypto::hash::Digest;
+use crate::bft::crypto::hash::{Digest, DigestHasher};
+use crate::bft::crypto::signature::PublicKey;
+use crate::bft::cst::chunks::{ChunkAssembly, ChunkPacer, StateChunk, StateManifest};
 use crate::bft::error::*;
-use crate::bft::executable::{ExecutorHandle, Reply, Request, Service, State};
+use crate::bft::executable::{self, ExecutorHandle, Reply, Request, Service, State};
 use crate::bft::ordering::{Orderable, SeqNo};
 use crate::bft::sync::Synchronizer;
-use crate::bft::timeouts::{TimeoutKind, TimeoutsHandle};
+use crate::bft::timeouts::{Clock, TimeoutKind, TimeoutsHandle};
 
 enum ProtoPhase<S, O> {
     Init,
     WaitingCheckpoint(Header, CstMessage<S, O>),
     ReceivingCid(usize),
     ReceivingState(usize),
+    // the range of consensus instances requested
+    ReceivingDelta(usize, SeqNo, SeqNo),
+    ReceivingChunks,
 }
 
 /// Contains state used by a recovering node.
@@ -41,13 +60,64 @@ pub struct RecoveryState<S, O> {
     // used to replay log on recovering replicas;
     // the request batches have been concatenated,
     // for memory efficiency
-    pub(crate) requests: Vec<O>,
+    pub(crate) requests: Vec<DecidedRequest<O>>,
+    pub(crate) declog: DecisionLog,
+}
+
+/// The requests decided in a range of consensus instances.
+///
+/// A replica lagging slightly behind its peers replays these requests
+/// to catch up, rather than installing a full `RecoveryState`.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct Delta<O> {
+    pub(crate) from: SeqNo,
+    // the requests decided in each instance, starting at `from`
+    pub(crate) batches: Vec<Vec<DecidedRequest<O>>>,
+    // the proofs of the decisions of these instances
     pub(crate) declog: DecisionLog,
 }
 
+/// A shared object store, such as one offered by a cloud provider,
+/// where replicas place the states they serve to recovering nodes.
+///
+/// Recovering nodes fetch these states directly from the store, rather
+/// than from their peers, so large checkpoints don't compete with the
+/// consensus messages for the bandwidth of the replicas.
+pub trait CheckpointStore: Send + Sync {
+    /// Stores the serialized recovery state `data`, returning
+    /// the URL it may be fetched from.
+    fn put(&self, data: &[u8]) -> Result<String>;
+
+    /// Fetches the serialized recovery state stored at `url`.
+    fn get(&self, url: &str) -> Result<Vec<u8>>;
+}
+
+/// A reference to a recovery state placed in a `CheckpointStore`.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct CheckpointRef {
+    url: String,
+    digest: Digest,
+}
+
+impl CheckpointRef {
+    /// Returns the URL the recovery state may be fetched from.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the digest of the serialized recovery state, which
+    /// should be checked after fetching it from the store.
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+}
+
 /// Allow a replica to recover from the state received by peer nodes.
 pub fn install_recovery_state<S>(
     recovery_state: RecoveryState<State<S>, Request<S>>,
+    node: &Node<S::Data>,
     synchronizer: &mut Synchronizer<S>,
     log: &mut Log<State<S>, Request<S>, Reply<S>>,
     executor: &mut ExecutorHandle<S>,
@@ -59,28 +129,76 @@ where
     Request<S>: Send + Clone + 'static,
     Reply<S>: Send + 'static,
 {
+    // even though f+1 replicas served us matching states,
+    // we don't install a state that doesn't make sense
+    recovery_state.validate()?;
+    // nor do we trust the senders about the decisions in its log
+    recovery_state.validate_certificates(node)?;
+
     // TODO: maybe try to optimize this, to avoid clone(),
     // which may be quite expensive depending on the size
     // of the state and the amount of batched requests
     let state = recovery_state.checkpoint.state().clone();
-    let requests = recovery_state.requests.clone();
+    // the requests decided after the checkpoint may include requests
+    // executed before it, which must not be executed twice
+    let mut executed = recovery_state.checkpoint.executed().clone();
+    let requests =
+        executed.unexecuted_operations::<S::Data, _, _>(recovery_state.requests.iter().cloned())?;
 
     // TODO: update pub/priv keys when reconfig is implemented?
 
-    synchronizer.install_view(recovery_state.view);
+    synchronizer.install_view(recovery_state.view.clone());
     consensus.install_new_phase(&recovery_state);
     executor.install_state(state, requests)?;
-    log.install_state(consensus.sequence_number(), recovery_state);
+    log.install_state(consensus.sequence_number(), recovery_state, executed);
+
+    Ok(())
+}
+
+/// Allow a replica lagging slightly behind to catch up, replaying
+/// the requests decided in a `Delta` received by peer nodes.
+pub fn install_delta<S>(
+    delta: Delta<Request<S>>,
+    log: &mut Log<State<S>, Request<S>, Reply<S>>,
+    executor: &mut ExecutorHandle<S>,
+) -> Result<()>
+where
+    S: Service + Send + 'static,
+    Request<S>: Send + Clone + 'static,
+    Reply<S>: Send + 'static,
+{
+    let requests = log.install_delta::<S::Data>(delta)?;
+    executor.queue_replay(requests)?;
 
     Ok(())
 }
 
+/// Rebuilds the application state of a replica offline, replaying the
+/// requests decided after the checkpoint in `recovery_state` through
+/// `service`, such as when the state was restored from persistent storage.
+pub fn replay_recovery_state<S>(
+    service: &mut S,
+    recovery_state: RecoveryState<State<S>, Request<S>>,
+) -> Result<State<S>>
+where
+    S: Service,
+{
+    let mut executed = recovery_state.checkpoint.executed().clone();
+    let requests = executed.unexecuted_operations::<S::Data, _, _>(recovery_state.requests)?;
+    let (_, state) = recovery_state.checkpoint.into_inner();
+    Ok(executable::replay(service, state, requests))
+}
+
 impl<S, O> RecoveryState<S, O> {
     /// Creates a new `RecoveryState`.
+    ///
+    /// The decided `requests` may be retained in the serialized form
+    /// they were received in, with `DecidedRequest::Serialized`, which
+    /// is then sent to recovering replicas as is.
     pub fn new(
         view: ViewInfo,
         checkpoint: Checkpoint<S>,
-        requests: Vec<O>,
+        requests: Vec<DecidedRequest<O>>,
         declog: DecisionLog,
     ) -> Self {
         Self {
@@ -92,8 +210,8 @@ impl<S, O> RecoveryState<S, O> {
     }
 
     /// Returns the view this `RecoveryState` is tracking.
-    pub fn view(&self) -> ViewInfo {
-        self.view
+    pub fn view(&self) -> &ViewInfo {
+        &self.view
     }
 
     /// Returns the local checkpoint of this recovery state.
@@ -101,16 +219,423 @@ impl<S, O> RecoveryState<S, O> {
         &self.checkpoint
     }
 
-    /// Returns the operations embedded in the requests sent by clients
-    /// after the last checkpoint at the moment of the creation of this `RecoveryState`.
-    pub fn requests(&self) -> &[O] {
+    /// Returns the requests sent by clients after the last checkpoint
+    /// at the moment of the creation of this `RecoveryState`.
+    ///
+    /// Some of them may not be deserialized yet; this method used to
+    /// return their operations, which `operations()` now returns.
+    pub fn requests(&self) -> &[DecidedRequest<O>] {
         &self.requests[..]
     }
 
+    /// Returns the operations embedded in the requests of this
+    /// `RecoveryState`, deserializing those still serialized with `D`.
+    pub fn operations<D>(&self) -> Result<Vec<O>>
+    where
+        D: SharedData<Request = O>,
+        O: Clone,
+    {
+        self.requests
+            .iter()
+            .cloned()
+            .map(DecidedRequest::into_operation::<D>)
+            .collect()
+    }
+
     /// Returns a reference to the decided consensus messages of this recovery state.
     pub fn decision_log(&self) -> &DecisionLog {
         &self.declog
     }
+
+    /// Checks if the checkpoint of this recovery state is consistent
+    /// with its decision log and decided requests.
+    ///
+    /// The decision log may only contain consensus messages decided after
+    /// the checkpoint, and requests may only have been decided after the
+    /// checkpoint if the decision log reports a later executed batch.
+    pub fn validate(&self) -> Result<()> {
+        let checkpoint_seq = self.checkpoint.sequence_number();
+        let declog = &self.declog;
+
+        let earlier_message = declog
+            .pre_prepares()
+            .iter()
+            .chain(declog.prepares())
+            .chain(declog.commits())
+            .any(|stored| stored.message().sequence_number() < checkpoint_seq);
+        if earlier_message {
+            return Err("Decision log predates the checkpoint").wrapped(ErrorKind::Cst);
+        }
+
+        match declog.last_execution() {
+            Some(seq) if seq < checkpoint_seq => {
+                Err("Last execution predates the checkpoint").wrapped(ErrorKind::Cst)
+            }
+            Some(seq) if seq == checkpoint_seq && !self.requests.is_empty() => {
+                Err("Requests decided without a later execution").wrapped(ErrorKind::Cst)
+            }
+            None if !self.requests.is_empty() => {
+                Err("Requests decided without a later execution").wrapped(ErrorKind::Cst)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks if every consensus instance reported as executed by the
+    /// decision log of this recovery state is backed by a certificate.
+    ///
+    /// A certificate is made of the `PRE-PREPARE` of an instance, and the
+    /// `PREPARE` and `COMMIT` votes matching it, cast by enough distinct
+    /// replicas to reach their respective quorums. Every message must
+    /// carry a valid signature of a member of the view, such that a single
+    /// replica is unable to forge decisions.
+    ///
+    /// Likewise, the requests decided after the checkpoint are checked
+    /// against the certified `PRE-PREPARE` of each instance, re-deriving
+    /// its digest from the digests of the requests in its batch.
+    pub fn validate_certificates<D>(&self, node: &Node<D>) -> Result<()>
+    where
+        D: SharedData<State = S, Request = O> + 'static,
+        S: Send + Clone + 'static,
+        O: Send + Clone + 'static,
+        D::Reply: Send + 'static,
+    {
+        let last_exec = match self.declog.last_execution() {
+            Some(seq) => seq,
+            None => return Ok(()),
+        };
+        let params = self.view.params();
+        let is_signed = |stored: &StoredMessage<ConsensusMessage>| {
+            let from = stored.header().from();
+            usize::from(from) < params.n()
+                && node
+                    .get_public_key(from)
+                    .map(|key| stored.header().verify_signature_hashed::<D::Hasher>(key))
+                    .unwrap_or(false)
+        };
+
+        // an instance may have been proposed in more than one view,
+        // but it only needs to be certified in one of them
+        let mut decided = Vec::new();
+        let mut certified: Vec<&StoredMessage<ConsensusMessage>> = Vec::new();
+        for pre_prepare in self.declog.pre_prepares() {
+            let seq = pre_prepare.message().sequence_number();
+            if seq > last_exec {
+                continue;
+            }
+            if !decided.contains(&seq) {
+                decided.push(seq);
+            }
+            let is_certified =
+                |c: &&StoredMessage<ConsensusMessage>| c.message().sequence_number() == seq;
+            if certified.iter().any(is_certified) || !is_signed(pre_prepare) {
+                continue;
+            }
+            // the leader doesn't vote in the PREPARE phase,
+            // hence the quorum of 2F rather than 2F + 1
+            let prepares = count_votes(self.declog.prepares(), pre_prepare, &is_signed);
+            let commits = count_votes(self.declog.commits(), pre_prepare, &is_signed);
+            if prepares >= params.f() << 1 && commits >= params.quorum() {
+                certified.push(pre_prepare);
+            }
+        }
+        if decided.len() != certified.len() {
+            return Err("Decided instance lacks a quorum certificate").wrapped(ErrorKind::Cst);
+        }
+
+        // every instance executed after the checkpoint must be certified,
+        // and its batch made up of the following decided requests
+        let checkpoint_seq = self.checkpoint.sequence_number();
+        let instances = last_exec.distance(checkpoint_seq) as usize;
+        if last_exec < checkpoint_seq || instances > certified.len() {
+            return Err("Decided instance lacks a quorum certificate").wrapped(ErrorKind::Cst);
+        }
+        let mut requests = self.requests.iter();
+        let mut seq = checkpoint_seq;
+        for _ in 0..instances {
+            seq = seq.next();
+            let pre_prepare = certified
+                .iter()
+                .find(|c| c.message().sequence_number() == seq)
+                .ok_or("Decided instance lacks a quorum certificate")
+                .wrapped(ErrorKind::Cst)?;
+            let len = match pre_prepare.message().kind() {
+                ConsensusMessageKind::PrePrepare(digests) => digests.len(),
+                _ => unreachable!(),
+            };
+            let mut batch = Vec::with_capacity(len);
+            for request in requests.by_ref().take(len) {
+                let header = request.header();
+                if request.payload_digest::<D>()? != *header.digest() {
+                    return Err("Decided request doesn't match its digest").wrapped(ErrorKind::Cst);
+                }
+                batch.push(header.unique_digest());
+            }
+            let kind = ConsensusMessageKind::PrePrepare(batch);
+            let message = ConsensusMessage::new(seq, pre_prepare.message().view(), kind);
+            let digest = D::serialize_digest(&SystemMessage::Consensus(message), Vec::new())?;
+            if digest != *pre_prepare.header().digest() {
+                return Err("Decided requests don't match their batch").wrapped(ErrorKind::Cst);
+            }
+        }
+        if requests.next().is_some() {
+            return Err("Decided requests don't match their batch").wrapped(ErrorKind::Cst);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a portable serialization of this `RecoveryState` to `w`,
+    /// e.g. to persist it to disk, serializing the application state and
+    /// the decided requests with `D`.
+    ///
+    /// The view comes first, followed by the checkpoint, i.e. its sequence
+    /// number, the requests executed before it, and the application state,
+    /// prefixed by its length in bytes. Then, a count precedes the decided
+    /// requests, each made up of its header and its length prefixed payload.
+    /// The decision log is written last, with `DecisionLog::export()`.
+    pub fn write_to<D, W>(&self, w: &mut W) -> Result<()>
+    where
+        D: SharedData<State = S, Request = O>,
+        O: Clone,
+        W: Write,
+    {
+        self.write_contents::<D, _>(w)?;
+        self.declog.export(w)
+    }
+
+    // writes everything `write_to()` does, but the decision log, such
+    // that replicas holding the same state write the same bytes,
+    // regardless of the quorum of votes each of them collected
+    fn write_contents<D, W>(&self, w: &mut W) -> Result<()>
+    where
+        D: SharedData<State = S, Request = O>,
+        O: Clone,
+        W: Write,
+    {
+        self.view.sequence_number().to_bytes(w)?;
+        (self.view.params().f() as u64).to_bytes(w)?;
+        (self.view.members().len() as u64).to_bytes(w)?;
+        for member in self.view.members() {
+            u32::from(*member).to_bytes(w)?;
+        }
+
+        let mut buf = Vec::new();
+        self.checkpoint.sequence_number().to_bytes(w)?;
+        self.checkpoint.executed().to_bytes(w)?;
+        D::serialize_state(&mut buf, self.checkpoint.state())?;
+        write_prefixed(w, &buf[..])?;
+
+        (self.requests.len() as u64).to_bytes(w)?;
+        for request in self.requests.iter() {
+            request.header().to_bytes(w)?;
+            match request {
+                DecidedRequest::Operation(_, message) => {
+                    buf.clear();
+                    let message = SystemMessage::Request(message.clone());
+                    D::serialize_message(&mut buf, &message)?;
+                    write_prefixed(w, &buf[..])?;
+                }
+                DecidedRequest::Serialized(_, bytes) => write_prefixed(w, &bytes[..])?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a `RecoveryState` previously written with `write_to()`.
+    ///
+    /// The decision log is imported with `DecisionLog::import()`, checking
+    /// its messages against the public keys in `public_keys`. The rest
+    /// of the state should be checked with `validate()` and
+    /// `validate_certificates()`, before it is installed.
+    pub fn read_from<D, R>(r: &mut R, public_keys: &HashMap<NodeId, PublicKey>) -> Result<Self>
+    where
+        D: SharedData<State = S, Request = O>,
+        R: Read,
+    {
+        let mut state = Self::read_contents::<D, _>(r)?;
+        state.declog = DecisionLog::import::<D, _>(r, public_keys)?;
+        Ok(state)
+    }
+
+    // reads the contents written with `write_contents()`,
+    // along with an empty decision log
+    fn read_contents<D, R>(r: &mut R) -> Result<Self>
+    where
+        D: SharedData<State = S, Request = O>,
+        R: Read,
+    {
+        let seq = SeqNo::from_bytes(r)?;
+        let f = u64::from_bytes(r)? as usize;
+        let count = u64::from_bytes(r)?;
+        if count == 0 || count > u64::from(u32::MAX) {
+            return Err("Invalid number of view members").wrapped(ErrorKind::Cst);
+        }
+        // don't trust the counts to pre-allocate memory
+        let mut members = Vec::new();
+        for _ in 0..count {
+            members.push(NodeId::from(u32::from_bytes(r)?));
+        }
+        let view = ViewInfo::with_members(seq, members, f)?;
+
+        let seq = SeqNo::from_bytes(r)?;
+        let executed = ExecutedRequests::from_bytes(r)?;
+        let state = D::deserialize_state(&read_prefixed(r)?[..])?;
+        let checkpoint = Checkpoint::new(seq, state).with_executed(executed);
+
+        let count = u64::from_bytes(r)?;
+        let mut requests = Vec::new();
+        for _ in 0..count {
+            let header = Header::from_bytes(r)?;
+            let payload = Bytes::from(read_prefixed(r)?);
+            requests.push(DecidedRequest::Serialized(header, payload));
+        }
+
+        Ok(Self::new(view, checkpoint, requests, DecisionLog::new()))
+    }
+
+    /// Returns the digest of the contents of this recovery state, i.e. its
+    /// view, checkpoint and decided requests, serialized with `D`. These
+    /// are the same contents a state served in chunks is made up of.
+    ///
+    /// Unlike the digest of the message this state was received in, this
+    /// value doesn't depend on how the state was framed for transport.
+    /// Note that the whole application state is serialized to compute it.
+    ///
+    /// The decision log isn't hashed, since each replica may
+    /// have collected a different quorum of votes.
+    pub fn digest<D>(&self) -> Result<Digest>
+    where
+        D: SharedData<State = S, Request = O>,
+        O: Clone,
+    {
+        let mut state_ctx = D::Hasher::context();
+        D::serialize_state(&mut state_ctx, self.checkpoint.state())?;
+
+        let mut ctx = D::Hasher::context();
+        let params = self.view.params();
+        ctx.update(&u32::from(self.view.sequence_number()).to_le_bytes());
+        ctx.update(&(params.n() as u64).to_le_bytes());
+        ctx.update(&(params.f() as u64).to_le_bytes());
+        for member in self.view.members() {
+            ctx.update(&u32::from(*member).to_le_bytes());
+        }
+        ctx.update(&u32::from(self.checkpoint.sequence_number()).to_le_bytes());
+        self.checkpoint.executed().to_bytes(&mut ctx)?;
+        ctx.update(state_ctx.finish().as_ref());
+        ctx.update(&(self.requests.len() as u64).to_le_bytes());
+        for request in self.requests.iter() {
+            ctx.update(request.digest::<D>()?.as_ref());
+        }
+        Ok(ctx.finish())
+    }
+}
+
+impl<O> Delta<O> {
+    /// Creates a new `Delta`, with the requests decided in each
+    /// consensus instance, starting at `from`, and the consensus
+    /// messages proving their decisions.
+    pub fn new(from: SeqNo, batches: Vec<Vec<DecidedRequest<O>>>, declog: DecisionLog) -> Self {
+        Self {
+            from,
+            batches,
+            declog,
+        }
+    }
+
+    /// Returns the sequence number of the first consensus
+    /// instance in this `Delta`.
+    pub fn from(&self) -> SeqNo {
+        self.from
+    }
+
+    /// Returns the requests decided in each consensus instance
+    /// of this `Delta`, in order.
+    pub fn batches(&self) -> &[Vec<DecidedRequest<O>>] {
+        &self.batches[..]
+    }
+
+    /// Returns the consensus messages proving the decisions
+    /// of the instances in this `Delta`.
+    pub fn decision_log(&self) -> &DecisionLog {
+        &self.declog
+    }
+
+    /// Checks if this `Delta` contains exactly the consensus instances
+    /// from `from` up to, but excluding, `to`.
+    pub fn covers(&self, from: SeqNo, to: SeqNo) -> bool {
+        self.from == from && self.batches.len() == to.distance(from) as usize
+    }
+
+    /// Returns the digest of the requests in this `Delta`,
+    /// serialized with `D`, as well as the instances they
+    /// were decided in.
+    ///
+    /// The decision log isn't hashed, since each replica may
+    /// have collected a different quorum of votes.
+    pub fn digest<D>(&self) -> Result<Digest>
+    where
+        D: SharedData<Request = O>,
+        O: Clone,
+    {
+        let mut ctx = D::Hasher::context();
+        ctx.update(&u32::from(self.from).to_le_bytes());
+        for batch in self.batches.iter() {
+            ctx.update(&(batch.len() as u64).to_le_bytes());
+            for request in batch {
+                ctx.update(request.digest::<D>()?.as_ref());
+            }
+        }
+        Ok(ctx.finish())
+    }
+}
+
+// writes `data` to `w`, prefixed by its length in bytes
+fn write_prefixed<W: Write>(w: &mut W, data: &[u8]) -> Result<()> {
+    (data.len() as u64).to_bytes(w)?;
+    w.write_all(data).wrapped(ErrorKind::Cst)
+}
+
+// reads data written with `write_prefixed()` from `r`
+fn read_prefixed<R: Read>(r: &mut R) -> Result<Vec<u8>> {
+    let length = u64::from_bytes(r)?;
+    let mut data = Vec::new();
+    r.by_ref()
+        .take(length)
+        .read_to_end(&mut data)
+        .wrapped(ErrorKind::Cst)?;
+    if data.len() as u64 != length {
+        return Err("Truncated length prefixed data").wrapped(ErrorKind::Cst);
+    }
+    Ok(data)
+}
+
+// counts the distinct replicas that cast a vote in `votes` matching
+// `pre_prepare`, with a valid signature according to `is_signed`
+fn count_votes<F>(
+    votes: &[StoredMessage<ConsensusMessage>],
+    pre_prepare: &StoredMessage<ConsensusMessage>,
+    is_signed: F,
+) -> usize
+where
+    F: Fn(&StoredMessage<ConsensusMessage>) -> bool,
+{
+    let mut voters = Vec::new();
+    for stored in votes {
+        let digest = match stored.message().kind() {
+            ConsensusMessageKind::Prepare(digest) | ConsensusMessageKind::Commit(digest) => digest,
+            ConsensusMessageKind::PrePrepare(_) => continue,
+        };
+        let from = stored.header().from();
+        let matches = stored.message().sequence_number() == pre_prepare.message().sequence_number()
+            && stored.message().view() == pre_prepare.message().view()
+            && digest == pre_prepare.header().digest();
+        if matches && !voters.contains(&from) && is_signed(stored) {
+            voters.push(from);
+        }
+    }
+    voters.len()
 }
 
 struct ReceivedState<S, O> {
@@ -118,6 +643,33 @@ struct ReceivedState<S, O> {
     state: RecoveryState<S, O>,
 }
 
+struct ReceivedDelta<O> {
+    // the replicas who sent us this delta
+    senders: Vec<NodeId>,
+    delta: Delta<O>,
+}
+
+struct ReceivedManifest {
+    // the replicas who sent us this manifest
+    holders: Vec<NodeId>,
+    // the decision logs they sent along with it, in the same order
+    declogs: Vec<DecisionLog>,
+    manifest: StateManifest,
+}
+
+// a state served in chunks, retained while it
+// may still be requested by recovering nodes
+struct ChunkedState {
+    manifest: StateManifest,
+    data: Bytes,
+}
+
+// the number of states served in chunks we retain
+const MAX_CHUNKED_STATES: usize = 2;
+
+// the default number of chunks buffered out of order
+const CHUNK_WINDOW: usize = 256;
+
 /// Represents the state of an on-going colloborative
 /// state transfer protocol execution.
 pub struct CollabStateTransfer<S: Service> {
@@ -130,7 +682,34 @@ pub struct CollabStateTransfer<S: Service> {
     // received already, to avoid replays
     //voted: HashSet<NodeId>,
     received_states: HashMap<Digest, ReceivedState<State<S>, Request<S>>>,
+    received_deltas: HashMap<Digest, ReceivedDelta<Request<S>>>,
     phase: ProtoPhase<State<S>, Request<S>>,
+    // minimum interval between two full states served to the same node
+    serve_interval: Duration,
+    // the instants when we last served our state to each node
+    served_states: HashMap<NodeId, Instant>,
+    // the states being served, and the requests deferred meanwhile
+    transfers: Transfers<(Header, CstMessage<State<S>, Request<S>>)>,
+    // where states are exchanged, if not directly between peers
+    store: Option<Arc<dyn CheckpointStore>>,
+    // the size of the chunks states are served in, if any
+    chunk_size: Option<usize>,
+    // the maximum number of chunks buffered out of order
+    chunk_window: usize,
+    // the latest states we served in chunks
+    chunked_states: VecDeque<ChunkedState>,
+    // paces the chunks we serve, if they are rate limited
+    pacer: Option<ChunkPacer>,
+    received_manifests: HashMap<Digest, ReceivedManifest>,
+    // the state being reassembled from its chunks, which outlives
+    // the request it was received for, such that an interrupted
+    // transfer is resumed, rather than started from scratch
+    assembly: Option<ChunkAssembly>,
+    // the replicas we are fetching the chunks of the state from
+    holders: Vec<NodeId>,
+    // the decision logs of the holders, one of which is
+    // installed along with the state, once reassembled
+    declogs: Vec<DecisionLog>,
 }
 
 /// Status returned from processnig a state transfer message.
@@ -145,12 +724,32 @@ pub enum CstStatus<S, O> {
     RequestLatestCid,
     /// We should request the latest state from the view.
     RequestState,
+    /// We should request the chunks of the latest state
+    /// we are missing from the replicas holding it.
+    RequestChunks,
     /// We have received and validated the largest consensus sequence
     /// number available.
     SeqNo(SeqNo),
     /// We have received and validated the state from
     /// a group of replicas.
     State(RecoveryState<S, O>),
+    /// We have received and validated the requests decided in the
+    /// consensus instances we were missing from a group of replicas.
+    Delta(Delta<O>),
+}
+
+impl<S, O> CstStatus<S, O> {
+    /// Checks that this status is `CstStatus::Nil`, which is the only
+    /// valid status to be returned when a node isn't running the CST
+    /// protocol, e.g. when it is serving state to a peer node.
+    ///
+    /// Other statuses are reported as an error of kind `ErrorKind::Cst`.
+    pub fn expect_nil(self) -> Result<()> {
+        match self {
+            CstStatus::Nil => Ok(()),
+            _ => Err("Invalid state reached!").wrapped(ErrorKind::Cst),
+        }
+    }
 }
 
 /// Represents progress in the CST state machine.
@@ -192,11 +791,41 @@ where
     Reply<S>: Send + 'static,
 {
     /// Craete a new instance of `CollabStateTransfer`.
-    pub fn new(base_timeout: Duration) -> Self {
+    ///
+    /// The value `serve_interval` bounds how often a full state is
+    /// served to the same node; requests arriving before this interval
+    /// elapses are answered with `CstMessageKind::ReplyStateThrottled`.
+    ///
+    /// At most `max_transfers` states are served at a time; requests
+    /// arriving while this many states are still being sent are deferred,
+    /// and served in the order they arrived, as the transfers complete.
+    ///
+    /// If a `store` is provided, states are served through it, falling
+    /// back to sending them directly to the recovering node if placing
+    /// them in the store fails.
+    pub fn new(
+        base_timeout: Duration,
+        serve_interval: Duration,
+        max_transfers: usize,
+        store: Option<Arc<dyn CheckpointStore>>,
+    ) -> Self {
         Self {
             base_timeout,
+            serve_interval,
+            store,
             curr_timeout: base_timeout,
+            served_states: collections::hash_map(),
+            transfers: Transfers::new(max_transfers),
             received_states: collections::hash_map(),
+            received_deltas: collections::hash_map(),
+            chunk_size: None,
+            chunk_window: CHUNK_WINDOW,
+            chunked_states: VecDeque::new(),
+            pacer: None,
+            received_manifests: collections::hash_map(),
+            assembly: None,
+            holders: Vec::new(),
+            declogs: Vec::new(),
             phase: ProtoPhase::Init,
             latest_cid: SeqNo::ZERO,
             latest_cid_count: 0,
@@ -204,6 +833,67 @@ where
         }
     }
 
+    /// Serves states in chunks of `chunk_size` bytes, if not `None`, which
+    /// may be fetched from any replica holding the same state. Recovering
+    /// nodes keep the chunks they received across retries, so only the
+    /// missing chunks are fetched again after a transfer is interrupted.
+    ///
+    /// States placed in a checkpoint store are not split into chunks.
+    pub fn with_chunk_size(mut self, chunk_size: Option<usize>) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Returns the size of the chunks states are served in, if any.
+    pub fn chunk_size(&self) -> Option<usize> {
+        self.chunk_size
+    }
+
+    /// Buffers at most `chunk_window` chunks past the first one missing
+    /// from the state being reassembled, dropping chunks further ahead;
+    /// chunks are requested one window at a time.
+    ///
+    /// By default, this value is 256.
+    pub fn with_chunk_window(mut self, chunk_window: usize) -> Self {
+        self.chunk_window = chunk_window.max(1);
+        self
+    }
+
+    /// Returns the maximum number of chunks buffered out of order.
+    pub fn chunk_window(&self) -> usize {
+        self.chunk_window
+    }
+
+    /// Sends at most `chunk_rate` bytes worth of chunks per second, if not
+    /// `None`, across all the nodes states are served to, such that large
+    /// state transfers don't starve the consensus traffic of the replica.
+    pub fn with_chunk_rate(mut self, chunk_rate: Option<u64>) -> Self {
+        self.pacer = chunk_rate.map(ChunkPacer::new);
+        self
+    }
+
+    /// Returns the number of bytes worth of chunks
+    /// sent per second, if they are rate limited.
+    pub fn chunk_rate(&self) -> Option<u64> {
+        self.pacer.as_ref().map(ChunkPacer::rate)
+    }
+
+    /// Returns the base duration of the timeouts of the CST protocol.
+    pub fn base_timeout(&self) -> Duration {
+        self.base_timeout
+    }
+
+    /// Returns the minimum interval between two states
+    /// served to the same node.
+    pub fn serve_interval(&self) -> Duration {
+        self.serve_interval
+    }
+
+    /// Returns the maximum number of states served at a time.
+    pub fn max_transfers(&self) -> usize {
+        self.transfers.max
+    }
+
     /// Checks if the CST layer is waiting for a local checkpoint to
     /// complete.
     ///
@@ -220,18 +910,213 @@ where
         log: &Log<State<S>, Request<S>, Reply<S>>,
         node: &mut Node<S::Data>,
     ) {
-        let snapshot = match log.snapshot(*synchronizer.view()) {
+        let snapshot = match log.snapshot(synchronizer.view().clone()) {
             Ok(snapshot) => snapshot,
             Err(_) => {
                 self.phase = ProtoPhase::WaitingCheckpoint(header, message);
                 return;
             }
         };
-        let reply = SystemMessage::Cst(CstMessage::new(
+        let mut reply = SystemMessage::Cst(CstMessage::new(
             message.sequence_number(),
             CstMessageKind::ReplyState(snapshot),
         ));
-        node.send(reply, header.from());
+        if let Some(checkpoint) = self.store_state(&reply) {
+            reply = SystemMessage::Cst(CstMessage::new(
+                message.sequence_number(),
+                CstMessageKind::ReplyStateRef(checkpoint),
+            ));
+        } else if let Some((manifest, declog)) = self.chunk_state(&reply) {
+            reply = SystemMessage::Cst(CstMessage::new(
+                message.sequence_number(),
+                CstMessageKind::ReplyStateManifest(manifest, declog),
+            ));
+        }
+
+        // the transfer is in flight until the state
+        // is written to the connection of the node
+        let transfer = self.transfers.start();
+        if let Ok((_, sent)) = node.broadcast_detached(reply, std::iter::once(header.from())) {
+            rt::spawn(async move {
+                sent.await;
+                drop(transfer);
+            });
+        }
+    }
+
+    // places the serialized `reply` in the checkpoint store,
+    // returning a reference to it on success
+    fn store_state(
+        &self,
+        reply: &SystemMessage<State<S>, Request<S>, Reply<S>>,
+    ) -> Option<CheckpointRef> {
+        let store = self.store.as_ref()?;
+        let mut buf = Vec::new();
+        S::Data::serialize_message(&mut buf, reply).ok()?;
+        let digest = <S::Data as SharedData>::Hasher::digest(&[&buf[..]]);
+        let url = store.put(&buf[..]).ok()?;
+        Some(CheckpointRef { url, digest })
+    }
+
+    // splits the state in `reply` into chunks, if configured to, retaining
+    // them to be requested later, and returns the manifest describing them,
+    // along with the decision log, which is left out of the chunks, since
+    // the votes in it vary across replicas holding the same state
+    fn chunk_state(
+        &mut self,
+        reply: &SystemMessage<State<S>, Request<S>, Reply<S>>,
+    ) -> Option<(StateManifest, DecisionLog)> {
+        let chunk_size = self.chunk_size?;
+        let state = match reply {
+            SystemMessage::Cst(message) => match message.kind() {
+                CstMessageKind::ReplyState(state) => state,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let mut buf = Vec::new();
+        state.write_contents::<S::Data, _>(&mut buf).ok()?;
+        let manifest = StateManifest::new(&buf[..], chunk_size);
+
+        // the same state may be requested by several nodes
+        let root = manifest.root();
+        if !self
+            .chunked_states
+            .iter()
+            .any(|c| c.manifest.root() == root)
+        {
+            if self.chunked_states.len() == MAX_CHUNKED_STATES {
+                self.chunked_states.pop_front();
+            }
+            self.chunked_states.push_back(ChunkedState {
+                manifest: manifest.clone(),
+                data: Bytes::from(buf),
+            });
+        }
+        Some((manifest, state.declog.clone()))
+    }
+
+    // sends the chunks with the given `indices` of the state identified
+    // by `root` to `peer`, if we still retain that state
+    fn serve_chunks(
+        &self,
+        seq: SeqNo,
+        peer: NodeId,
+        root: &Digest,
+        indices: &[u64],
+        node: &mut Node<S::Data>,
+    ) {
+        let state = match self
+            .chunked_states
+            .iter()
+            .find(|c| c.manifest.root() == root)
+        {
+            Some(state) => state,
+            None => return,
+        };
+        // a state has no more chunks than this to be requested
+        let count = state.manifest.chunks() as usize;
+        let chunks = indices
+            .iter()
+            .take(count)
+            .filter_map(|&index| StateChunk::new(&state.manifest, &state.data, index));
+        let pacer = match self.pacer {
+            Some(ref pacer) => pacer.clone(),
+            None => {
+                for chunk in chunks {
+                    let kind = CstMessageKind::ReplyChunk(chunk);
+                    node.send(SystemMessage::Cst(CstMessage::new(seq, kind)), peer);
+                }
+                return;
+            }
+        };
+
+        // send the chunks in the background, as
+        // their slots in the budget come up
+        let chunks: Vec<_> = chunks.collect();
+        let mut node = node.send_node();
+        rt::spawn(async move {
+            for chunk in chunks {
+                let wait = pacer.reserve(chunk.data().len());
+                if wait > Duration::ZERO {
+                    Delay::new(wait).await;
+                }
+                let kind = CstMessageKind::ReplyChunk(chunk);
+                node.send(SystemMessage::Cst(CstMessage::new(seq, kind)), peer);
+            }
+        });
+    }
+
+    // counts the manifest of the state served by `peer`, along with its
+    // decision log, and starts fetching the chunks of that state once f+1
+    // replicas vouch for it, in which case `CstStatus::RequestChunks` is
+    // returned
+    fn process_manifest(
+        &mut self,
+        peer: NodeId,
+        manifest: StateManifest,
+        declog: DecisionLog,
+        f: usize,
+    ) -> Option<CstStatus<State<S>, Request<S>>> {
+        let root = *manifest.root();
+        let received = self
+            .received_manifests
+            .entry(root)
+            .or_insert_with(|| ReceivedManifest {
+                holders: Vec::new(),
+                declogs: Vec::new(),
+                manifest,
+            });
+        if !received.holders.contains(&peer) {
+            received.holders.push(peer);
+            received.declogs.push(declog);
+        }
+        if received.holders.len() <= f {
+            return None;
+        }
+
+        let ReceivedManifest {
+            holders,
+            declogs,
+            manifest,
+        } = self.received_manifests.remove(&root)?;
+        self.received_manifests.clear();
+        self.received_states.clear();
+
+        // resume the transfer of the same state, if it was interrupted
+        match self.assembly {
+            Some(ref assembly) if *assembly.manifest().root() == root => (),
+            _ => self.assembly = Some(ChunkAssembly::new(manifest, self.chunk_window)),
+        }
+        self.holders = holders;
+        self.declogs = declogs;
+        self.phase = ProtoPhase::ReceivingChunks;
+        Some(CstStatus::RequestChunks)
+    }
+
+    // fetches the state referenced by `message` from the checkpoint
+    // store, if the message is a `CstMessageKind::ReplyStateRef`
+    fn fetch_state(
+        &self,
+        message: &CstMessage<State<S>, Request<S>>,
+    ) -> Option<RecoveryState<State<S>, Request<S>>> {
+        let checkpoint = match message.kind() {
+            CstMessageKind::ReplyStateRef(checkpoint) => checkpoint,
+            _ => return None,
+        };
+        let store = self.store.as_ref()?;
+        let buf = store.get(checkpoint.url()).ok()?;
+
+        // the store is not trusted to return the
+        // state the replica placed there
+        let digest = <S::Data as SharedData>::Hasher::digest(&[&buf[..]]);
+        if &digest != checkpoint.digest() {
+            return None;
+        }
+        match S::Data::deserialize_message(&buf[..]).ok()? {
+            SystemMessage::Cst(mut message) => message.take_state(),
+            _ => None,
+        }
     }
 
     /// Advances the state of the CST state machine.
@@ -250,6 +1135,17 @@ where
                 CstStatus::Nil
             }
             ProtoPhase::Init => {
+                // serve the states deferred while too
+                // many of them were being sent
+                while !self.needs_checkpoint() {
+                    match self.transfers.next_deferred() {
+                        Some((header, message)) => {
+                            self.process_reply_state(header, message, synchronizer, log, node)
+                        }
+                        None => break,
+                    }
+                }
+
                 let (header, message) = getmessage!(progress, CstStatus::Nil);
                 match message.kind() {
                     CstMessageKind::RequestLatestConsensusSeq => {
@@ -260,7 +1156,48 @@ where
                         node.send(reply, header.from());
                     }
                     CstMessageKind::RequestState => {
-                        self.process_reply_state(header, message, synchronizer, log, node);
+                        let now = Instant::now();
+                        let interval = self.serve_interval;
+                        let peer = header.from();
+                        if self.transfers.is_deferred(peer) {
+                            // the node retried a deferred request; only
+                            // the latest one is answered, in its place
+                            self.transfers.defer(peer, (header, message));
+                        } else if !may_serve_state(&mut self.served_states, interval, peer, now) {
+                            let kind = CstMessageKind::ReplyStateThrottled;
+                            let reply = SystemMessage::Cst(CstMessage::new(
+                                message.sequence_number(),
+                                kind,
+                            ));
+                            node.send(reply, peer);
+                        } else if self.transfers.is_full() || self.needs_checkpoint() {
+                            // a deferred state may have been waiting
+                            // for a checkpoint, served above
+                            self.transfers.defer(peer, (header, message));
+                        } else {
+                            self.process_reply_state(header, message, synchronizer, log, node);
+                        }
+                    }
+                    CstMessageKind::RequestChunks(root, indices) => {
+                        // the chunks of states we served are not throttled,
+                        // since recovering nodes only request the chunks
+                        // they are still missing
+                        let seq = message.sequence_number();
+                        self.serve_chunks(seq, header.from(), root, indices, node);
+                    }
+                    CstMessageKind::RequestDelta(from, to) => {
+                        // deltas are bounded by the checkpoint period, so
+                        // they are not throttled like full states; if we no
+                        // longer retain the range, the recovering node times
+                        // out, and requests a full state instead
+                        if let Some(delta) = log.delta(*from, *to) {
+                            let kind = CstMessageKind::ReplyDelta(delta);
+                            let reply = SystemMessage::Cst(CstMessage::new(
+                                message.sequence_number(),
+                                kind,
+                            ));
+                            node.send(reply, header.from());
+                        }
                     }
                     // we are not running cst, so drop any reply msgs
                     //
@@ -275,7 +1212,7 @@ where
                 let (_header, message) = getmessage!(progress, CstStatus::RequestLatestCid);
 
                 // drop cst messages with invalid seq no
-                if message.sequence_number() != self.cst_seq {
+                if !self.is_latest(message.sequence_number()) {
                     // FIXME: how to handle old or newer messages?
                     // BFT-SMaRt simply ignores messages with a
                     // value of `queryID` different from the current
@@ -330,22 +1267,47 @@ where
                 let (header, mut message) = getmessage!(progress, CstStatus::RequestState);
 
                 // NOTE: check comment above, on ProtoPhase::ReceivingCid
-                if message.sequence_number() != self.cst_seq {
+                if !self.is_latest(message.sequence_number()) {
                     return CstStatus::Running;
                 }
 
-                let state = match message.take_state() {
-                    Some(state) => state,
-                    // drop invalid message kinds
-                    None => return CstStatus::Running,
-                };
+                if let Some((manifest, declog)) = message.take_manifest() {
+                    // the state is served in chunks; drop manifests
+                    // whose chunks don't add up to their root
+                    if manifest.validate().is_err() {
+                        return CstStatus::Running;
+                    }
+                    let f = synchronizer.view().params().f();
+                    let from = header.from();
+                    if let Some(status) = self.process_manifest(from, manifest, declog, f) {
+                        return status;
+                    }
+                } else {
+                    let state = match message.take_state() {
+                        Some(state) => state,
+                        None => match self.fetch_state(&message) {
+                            Some(state) => state,
+                            // drop invalid message kinds, throttled replies,
+                            // as well as states we failed to fetch; if not
+                            // enough replicas serve us their state, we will
+                            // time out and try again later
+                            None => return CstStatus::Running,
+                        },
+                    };
 
-                let received_state = self
-                    .received_states
-                    .entry(header.digest().clone())
-                    .or_insert(ReceivedState { count: 0, state });
+                    // match states by their contents, rather
+                    // than by the messages they were framed in
+                    let digest = match state.digest::<S::Data>() {
+                        Ok(digest) => digest,
+                        Err(_) => return CstStatus::Running,
+                    };
+                    let received_state = self
+                        .received_states
+                        .entry(digest)
+                        .or_insert(ReceivedState { count: 0, state });
 
-                received_state.count += 1;
+                    received_state.count += 1;
+                }
 
                 // check if we have gathered enough state
                 // replies from peer nodes
@@ -364,9 +1326,9 @@ where
 
                 // check if we have at least f+1 matching states
                 let digest = {
-                    let received_state = self.received_states.iter().max_by_key(|(_, st)| st.count);
-                    match received_state {
-                        Some((digest, _)) => digest.clone(),
+                    let candidates = self.received_states.iter().map(|(d, st)| (d, st.count));
+                    match most_voted(candidates) {
+                        Some(digest) => digest,
                         None => {
                             self.received_states.clear();
                             return CstStatus::RequestState;
@@ -376,6 +1338,7 @@ where
                 let received_state = {
                     let received_state = self.received_states.remove(&digest);
                     self.received_states.clear();
+                    self.received_manifests.clear();
                     received_state
                 };
 
@@ -389,30 +1352,145 @@ where
                     _ => CstStatus::RequestState,
                 }
             }
-        }
-    }
+            ProtoPhase::ReceivingChunks => {
+                let (_, mut message) = getmessage!(progress, CstStatus::Running);
 
-    fn next_seq(&mut self) -> SeqNo {
-        let next = self.cst_seq;
-        self.cst_seq = self.cst_seq.next();
-        next
-    }
+                // chunks are content addressed, so they are accepted
+                // regardless of the request they were served for
+                let (chunk, assembly) = match (message.take_chunk(), self.assembly.as_mut()) {
+                    (Some(chunk), Some(assembly)) => (chunk, assembly),
+                    _ => return CstStatus::Running,
+                };
+                assembly.insert(chunk);
+                if assembly.needs_request() {
+                    // fetch the next window of chunks
+                    return CstStatus::RequestChunks;
+                }
+                if !assembly.is_complete() {
+                    return CstStatus::Running;
+                }
+                let data = self.assembly.take().and_then(ChunkAssembly::assemble);
+                let declogs = std::mem::take(&mut self.declogs);
+                self.holders.clear();
+                self.phase = ProtoPhase::Init;
 
-    /// Handle a timeout received from the timeouts layer.
-    pub fn timed_out(&mut self, seq: SeqNo) -> CstStatus<State<S>, Request<S>> {
-        if seq.next() != self.cst_seq {
-            // the timeout we received is for a request
-            // that has already completed, therefore we ignore it
-            //
-            // TODO: this check is probably not necessary,
-            // as we have likely already updated the `ProtoPhase`
-            // to reflect the fact we are no longer receiving state
-            // from peer nodes
-            return CstStatus::Nil;
-        }
-        match self.phase {
-            // retry requests if receiving state and we have timed out
-            ProtoPhase::ReceivingCid(_) => {
+                // reset timeout, since req was successful
+                self.curr_timeout = self.base_timeout;
+
+                // f+1 replicas vouched for the chunks, but we still
+                // check the state they add up to, and install it with
+                // the first decision log of its holders certifying it
+                let mut state = match data.and_then(|data| {
+                    RecoveryState::read_contents::<S::Data, _>(&mut &data[..]).ok()
+                }) {
+                    Some(state) => state,
+                    None => return CstStatus::RequestState,
+                };
+                for declog in declogs {
+                    state.declog = declog;
+                    if state.validate().is_ok() && state.validate_certificates(node).is_ok() {
+                        return CstStatus::State(state);
+                    }
+                }
+                CstStatus::RequestState
+            }
+            ProtoPhase::ReceivingDelta(i, from, to) => {
+                let (header, mut message) = getmessage!(progress, CstStatus::RequestState);
+
+                // NOTE: check comment above, on ProtoPhase::ReceivingCid
+                if !self.is_latest(message.sequence_number()) {
+                    return CstStatus::Running;
+                }
+
+                // only count one reply from each replica, such that
+                // a faulty replica can't vouch for a forged delta alone
+                let sender = header.from();
+                let replied = self
+                    .received_deltas
+                    .values()
+                    .any(|received| received.senders.contains(&sender));
+                if replied || !synchronizer.view().members().contains(&sender) {
+                    return CstStatus::Running;
+                }
+
+                // drop invalid message kinds, as well as
+                // deltas not matching the requested range
+                let delta = match message.take_delta() {
+                    Some(delta) if delta.covers(from, to) => delta,
+                    _ => return CstStatus::Running,
+                };
+                let digest = match delta.digest::<S::Data>() {
+                    Ok(digest) => digest,
+                    Err(_) => return CstStatus::Running,
+                };
+                self.received_deltas
+                    .entry(digest)
+                    .or_insert(ReceivedDelta {
+                        senders: Vec::new(),
+                        delta,
+                    })
+                    .senders
+                    .push(sender);
+
+                let i = i + 1;
+
+                if i != synchronizer.view().params().quorum() {
+                    self.phase = ProtoPhase::ReceivingDelta(i, from, to);
+                    return CstStatus::Running;
+                }
+                self.phase = ProtoPhase::Init;
+
+                let candidates = self
+                    .received_deltas
+                    .iter()
+                    .map(|(d, rd)| (d, rd.senders.len()));
+                let received_delta =
+                    most_voted(candidates).and_then(|digest| self.received_deltas.remove(&digest));
+                self.received_deltas.clear();
+
+                // reset timeout, since req was successful
+                self.curr_timeout = self.base_timeout;
+
+                // fall back to a full state transfer, if
+                // not enough replicas served matching deltas
+                let f = synchronizer.view().params().f();
+                match received_delta {
+                    Some(ReceivedDelta { senders, delta }) if senders.len() > f => {
+                        CstStatus::Delta(delta)
+                    }
+                    _ => CstStatus::RequestState,
+                }
+            }
+        }
+    }
+
+    fn next_seq(&mut self) -> SeqNo {
+        let next = self.cst_seq;
+        self.cst_seq = self.cst_seq.next();
+        next
+    }
+
+    // checks if `seq` is the sequence number of our latest
+    // request, i.e. the last one returned by `next_seq()`
+    fn is_latest(&self, seq: SeqNo) -> bool {
+        seq.next() == self.cst_seq
+    }
+
+    /// Handle a timeout received from the timeouts layer.
+    pub fn timed_out(&mut self, seq: SeqNo) -> CstStatus<State<S>, Request<S>> {
+        if !self.is_latest(seq) {
+            // the timeout we received is for a request
+            // that has already completed, therefore we ignore it
+            //
+            // TODO: this check is probably not necessary,
+            // as we have likely already updated the `ProtoPhase`
+            // to reflect the fact we are no longer receiving state
+            // from peer nodes
+            return CstStatus::Nil;
+        }
+        match self.phase {
+            // retry requests if receiving state and we have timed out
+            ProtoPhase::ReceivingCid(_) => {
                 self.curr_timeout *= 2;
                 CstStatus::RequestLatestCid
             }
@@ -420,6 +1498,16 @@ where
                 self.curr_timeout *= 2;
                 CstStatus::RequestState
             }
+            // our peers may no longer retain the instances
+            // we are missing, so request a full state instead
+            ProtoPhase::ReceivingDelta(_, _, _) => CstStatus::RequestState,
+            // some of the replicas holding the state may have crashed,
+            // or disconnected from us, so ask the others for the
+            // chunks we are still missing
+            ProtoPhase::ReceivingChunks => {
+                self.curr_timeout *= 2;
+                CstStatus::RequestChunks
+            }
             // ignore timeouts if not receiving any kind
             // of state from peer nodes
             _ => CstStatus::Nil,
@@ -428,10 +1516,10 @@ where
 
     /// Used by a recovering node to retrieve the latest sequence number
     /// attributed to a client request by the consensus layer.
-    pub fn request_latest_consensus_seq_no(
+    pub fn request_latest_consensus_seq_no<C: Clock>(
         &mut self,
         synchronizer: &Synchronizer<S>,
-        timeouts: &TimeoutsHandle<S>,
+        timeouts: &TimeoutsHandle<S, C>,
         node: &mut Node<S::Data>,
     ) {
         // reset state of latest seq no. request
@@ -446,15 +1534,15 @@ where
             cst_seq,
             CstMessageKind::RequestLatestConsensusSeq,
         ));
-        let targets = NodeId::targets(0..synchronizer.view().params().n());
+        let targets = synchronizer.view().members().iter().copied();
         node.broadcast(message, targets);
     }
 
     /// Used by a recovering node to retrieve the latest state.
-    pub fn request_latest_state(
+    pub fn request_latest_state<C: Clock>(
         &mut self,
         synchronizer: &Synchronizer<S>,
-        timeouts: &TimeoutsHandle<S>,
+        timeouts: &TimeoutsHandle<S, C>,
         node: &mut Node<S::Data>,
     ) {
         // reset hashmap of received states
@@ -465,7 +1553,1393 @@ where
         self.phase = ProtoPhase::ReceivingState(0);
 
         let message = SystemMessage::Cst(CstMessage::new(cst_seq, CstMessageKind::RequestState));
-        let targets = NodeId::targets(0..synchronizer.view().params().n());
+        let targets = synchronizer.view().members().iter().copied();
         node.broadcast(message, targets);
     }
+
+    /// Used by a recovering node to fetch the chunks of the latest state
+    /// it is still missing, within its window of buffered chunks, from the
+    /// replicas holding that state.
+    ///
+    /// The requests are spread across these replicas, rotating them with
+    /// every retry, such that chunks requested from a replica which has
+    /// since crashed are requested from another one.
+    pub fn request_missing_chunks<C: Clock>(
+        &mut self,
+        timeouts: &TimeoutsHandle<S, C>,
+        node: &mut Node<S::Data>,
+    ) {
+        let assembly = match self.assembly {
+            Some(ref mut assembly) if !self.holders.is_empty() => assembly,
+            _ => return,
+        };
+        let root = *assembly.manifest().root();
+        let mut requests = vec![Vec::new(); self.holders.len()];
+        for (i, index) in assembly.request().into_iter().enumerate() {
+            requests[i % self.holders.len()].push(index);
+        }
+        self.holders.rotate_left(1);
+
+        let cst_seq = self.next_seq();
+        timeouts.timeout(self.curr_timeout, TimeoutKind::Cst(cst_seq));
+        self.phase = ProtoPhase::ReceivingChunks;
+
+        for (&holder, indices) in self.holders.iter().zip(requests) {
+            if indices.is_empty() {
+                continue;
+            }
+            let kind = CstMessageKind::RequestChunks(root, indices);
+            node.send(SystemMessage::Cst(CstMessage::new(cst_seq, kind)), holder);
+        }
+    }
+
+    /// Used by a recovering node lagging slightly behind to retrieve the
+    /// requests decided in the consensus instances from `from` up to, but
+    /// excluding, `to`, rather than the latest state.
+    ///
+    /// If not enough peers still retain these instances, the recovering
+    /// node is told to request the latest state instead.
+    pub fn request_delta<C: Clock>(
+        &mut self,
+        from: SeqNo,
+        to: SeqNo,
+        synchronizer: &Synchronizer<S>,
+        timeouts: &TimeoutsHandle<S, C>,
+        node: &mut Node<S::Data>,
+    ) {
+        // reset hashmap of received deltas
+        self.received_deltas.clear();
+
+        let cst_seq = self.next_seq();
+        timeouts.timeout(self.curr_timeout, TimeoutKind::Cst(cst_seq));
+        self.phase = ProtoPhase::ReceivingDelta(0, from, to);
+
+        let kind = CstMessageKind::RequestDelta(from, to);
+        let message = SystemMessage::Cst(CstMessage::new(cst_seq, kind));
+        let targets = synchronizer.view().members().iter().copied();
+        node.broadcast(message, targets);
+    }
+}
+
+// checks if we may serve a full state to `peer` at the instant `now`,
+// which is only the case once every `interval`; this bounds the cost
+// of a recovering (or malicious) node repeatedly requesting our state
+//
+// nodes served longer than `interval` ago are forgotten, so `served`
+// only grows with the nodes requesting states within an interval
+fn may_serve_state(
+    served: &mut HashMap<NodeId, Instant>,
+    interval: Duration,
+    peer: NodeId,
+    now: Instant,
+) -> bool {
+    served.retain(|_, last| now.saturating_duration_since(*last) < interval);
+    if served.contains_key(&peer) {
+        return false;
+    }
+    served.insert(peer, now);
+    true
+}
+
+// returns the digest of the candidate replied by the most nodes; ties
+// are broken by picking the lowest digest, rather than depending on the
+// iteration order of the candidates, such that all nodes pick the same
+fn most_voted<'a, I>(candidates: I) -> Option<Digest>
+where
+    I: IntoIterator<Item = (&'a Digest, usize)>,
+{
+    candidates
+        .into_iter()
+        .max_by(|(d1, c1), (d2, c2)| c1.cmp(c2).then_with(|| d2.cmp(d1)))
+        .map(|(digest, _)| *digest)
+}
+
+// bounds the number of states served at a time, since
+// each of them may take a while to be sent, and holds
+// the requests of the nodes waiting for their turn
+struct Transfers<M> {
+    max: usize,
+    in_flight: Arc<AtomicUsize>,
+    // at most one request per node, in the order they arrived
+    deferred: VecDeque<(NodeId, M)>,
+}
+
+// a state being served; the transfer completes when it is dropped
+struct TransferGuard {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl<M> Transfers<M> {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            deferred: VecDeque::new(),
+        }
+    }
+
+    fn start(&self) -> TransferGuard {
+        self.in_flight.fetch_add(1, atomic::Ordering::AcqRel);
+        TransferGuard {
+            in_flight: Arc::clone(&self.in_flight),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.in_flight.load(atomic::Ordering::Acquire) >= self.max
+    }
+
+    fn is_deferred(&self, peer: NodeId) -> bool {
+        self.deferred.iter().any(|(id, _)| *id == peer)
+    }
+
+    // a node retrying a deferred request keeps its place in the queue
+    fn defer(&mut self, peer: NodeId, request: M) {
+        match self.deferred.iter_mut().find(|(id, _)| *id == peer) {
+            Some(entry) => entry.1 = request,
+            None => self.deferred.push_back((peer, request)),
+        }
+    }
+
+    fn next_deferred(&mut self) -> Option<M> {
+        if self.is_full() {
+            return None;
+        }
+        self.deferred.pop_front().map(|(_, request)| request)
+    }
+}
+
+impl Drop for TransferGuard {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, atomic::Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{may_serve_state, most_voted, CstStatus, Transfers};
+    use crate::bft::collections;
+    use crate::bft::communication::NodeId;
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::error::ErrorKind;
+
+    #[test]
+    fn test_cst_failure_kind() {
+        let status: CstStatus<(), ()> = CstStatus::Running;
+        let err = status.expect_nil().expect_err("Expected CST failure");
+        assert_eq!(err.kind(), ErrorKind::Cst);
+
+        let status: CstStatus<(), ()> = CstStatus::Nil;
+        status.expect_nil().expect("Unexpected CST failure");
+    }
+
+    #[test]
+    fn test_state_requests_throttled() {
+        const INTERVAL: Duration = Duration::from_secs(10);
+
+        let mut served = collections::hash_map();
+        let peer = NodeId::from(3u32);
+        let now = Instant::now();
+
+        // only the first of a burst of requests is served
+        assert!(may_serve_state(&mut served, INTERVAL, peer, now));
+        for i in 1..100 {
+            let later = now + Duration::from_millis(i);
+            assert!(!may_serve_state(&mut served, INTERVAL, peer, later));
+        }
+
+        // other nodes are throttled independently
+        let other = NodeId::from(2u32);
+        assert!(may_serve_state(&mut served, INTERVAL, other, now));
+
+        // the state is served again once the interval elapses
+        assert!(may_serve_state(&mut served, INTERVAL, peer, now + INTERVAL));
+
+        // and the nodes served before it are forgotten
+        assert_eq!(served.len(), 1);
+        assert!(served.contains_key(&peer));
+    }
+
+    #[test]
+    fn test_most_voted_tie_break() {
+        let low = Digest::from_bytes(&[1; DefaultHasher::LENGTH][..]).unwrap();
+        let high = Digest::from_bytes(&[2; DefaultHasher::LENGTH][..]).unwrap();
+        assert!(low < high);
+
+        // equal counts pick the lowest digest, in any order
+        let tied = [(&high, 2), (&low, 2)];
+        assert_eq!(most_voted(tied.iter().copied()), Some(low));
+        assert_eq!(most_voted(tied.iter().rev().copied()), Some(low));
+
+        // otherwise, the most voted candidate is picked
+        assert_eq!(most_voted([(&low, 1), (&high, 2)]), Some(high));
+        assert_eq!(most_voted(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_state_transfers_deferred() {
+        let mut transfers = Transfers::new(2);
+        let first = transfers.start();
+        let second = transfers.start();
+        assert!(transfers.is_full());
+
+        // more nodes request our state than we may serve at a time
+        for i in 2..5u32 {
+            transfers.defer(NodeId::from(i), i);
+        }
+        assert!(transfers.is_deferred(NodeId::from(3u32)));
+        assert_eq!(transfers.next_deferred(), None);
+
+        // a retried request replaces the deferred one in place
+        transfers.defer(NodeId::from(3u32), 30);
+        assert_eq!(transfers.deferred.len(), 3);
+
+        // each completed transfer makes room for a deferred one
+        drop(first);
+        assert_eq!(transfers.next_deferred(), Some(2));
+        let _third = transfers.start();
+        assert_eq!(transfers.next_deferred(), None);
+        drop(second);
+        assert_eq!(transfers.next_deferred(), Some(30));
+        assert!(!transfers.is_deferred(NodeId::from(3u32)));
+    }
+
+    // records a log of `batches` decided with a checkpoint `period`,
+    // executing each batch through a `CounterService` as it is decided;
+    // receives the next CST message sent over the loopback node
+    #[cfg(feature = "serialize_serde")]
+    fn next_cst(
+        node: &mut crate::bft::communication::Node<crate::bft::testing::CounterData>,
+    ) -> (
+        crate::bft::communication::message::Header,
+        crate::bft::communication::message::CstMessage<u64, u64>,
+    ) {
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::message::{Message, SystemMessage};
+
+        loop {
+            if let Message::System(header, SystemMessage::Cst(message)) =
+                rt::block_on(node.receive()).unwrap()
+            {
+                return (header, message);
+            }
+        }
+    }
+
+    // if `sk` is provided, the consensus messages are signed with it,
+    // and each batch is committed by the node with id 0
+    #[cfg(feature = "serialize_serde")]
+    fn record_log(
+        period: u32,
+        batches: u32,
+        sk: Option<&crate::bft::crypto::signature::KeyPair>,
+    ) -> (crate::bft::consensus::log::Log<u64, u64, u64>, u64) {
+        use crate::bft::communication::message::{
+            ConsensusMessage, ConsensusMessageKind, RequestMessage, SystemMessage, WireMessage,
+        };
+        use crate::bft::communication::serialize::DigestData;
+        use crate::bft::consensus::log::{Info, Log};
+        use crate::bft::executable::Service;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::testing::{CounterData, CounterService};
+
+        let id = NodeId::from(0u32);
+        let header = |nonce, message: &SystemMessage<u64, u64, u64>| {
+            let digest = CounterData::serialize_digest(message, Vec::new()).unwrap();
+            WireMessage::new(id, id, &[], nonce, Some(digest), sk).header
+        };
+
+        let mut service = CounterService;
+        let mut state = service.initial_state().unwrap();
+        let mut log: Log<u64, u64, u64> = Log::new(1, usize::MAX, period, usize::MAX);
+        for seq in 0..batches {
+            let nonce = u64::from(seq);
+            let request = SystemMessage::Request(RequestMessage::new(SeqNo::from(seq), nonce + 1));
+            let request_header = header(nonce, &request);
+
+            let kind = ConsensusMessageKind::PrePrepare(vec![request_header.unique_digest()]);
+            let pre_prepare = SystemMessage::Consensus(ConsensusMessage::new(
+                SeqNo::from(seq),
+                SeqNo::ZERO,
+                kind,
+            ));
+            let pre_prepare_header = header(nonce, &pre_prepare);
+            log.insert(pre_prepare_header, pre_prepare);
+            if sk.is_some() {
+                let kind = ConsensusMessageKind::Commit(*pre_prepare_header.digest());
+                let commit = SystemMessage::Consensus(ConsensusMessage::new(
+                    SeqNo::from(seq),
+                    SeqNo::ZERO,
+                    kind,
+                ));
+                log.insert(header(nonce, &commit), commit);
+            }
+            log.insert(request_header, request);
+
+            let digests = log.next_batch().expect("No batch was cut");
+            let (info, batch) = log.finalize_batch(SeqNo::from(seq), &digests).unwrap();
+            for update in batch.into_inner() {
+                let (_, _, request) = update.into_inner();
+                service.update(&mut state, request);
+            }
+            if let Info::BeginCheckpoint = info {
+                log.finalize_checkpoint(state).unwrap();
+            }
+        }
+
+        (log, state)
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_recovery_state_replayed() {
+        use super::replay_recovery_state;
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::testing::CounterService;
+
+        let (log, state) = record_log(2, 6, None);
+
+        // some requests were decided after the last checkpoint
+        assert!(!log.peek_decided().is_empty());
+
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let recovery_state = log.snapshot(view).unwrap();
+        let replayed = replay_recovery_state(&mut CounterService, recovery_state).unwrap();
+        assert_eq!(replayed, state);
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_recovery_state_without_members_rejected() {
+        use super::RecoveryState;
+        use crate::bft::communication::serialize::ToBytes;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::testing::CounterData;
+
+        // a view without members, as read from a corrupted snapshot
+        let mut buf = Vec::new();
+        SeqNo::ZERO.to_bytes(&mut buf).unwrap();
+        0u64.to_bytes(&mut buf).unwrap();
+        0u64.to_bytes(&mut buf).unwrap();
+
+        let public_keys = collections::hash_map();
+        let read =
+            RecoveryState::<u64, u64>::read_from::<CounterData, _>(&mut &buf[..], &public_keys);
+        assert!(read.is_err());
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_recovery_state_digest() {
+        use bytes::Bytes;
+
+        use super::RecoveryState;
+        use crate::bft::communication::message::SystemMessage;
+        use crate::bft::consensus::log::DecidedRequest;
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::testing::CounterData;
+
+        let (log, _) = record_log(2, 6, None);
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let recovery_state = log.snapshot(view.clone()).unwrap();
+
+        // the same state, with the requests retained in their serialized form
+        let serialized = recovery_state
+            .requests()
+            .iter()
+            .cloned()
+            .map(|request| {
+                let header = *request.header();
+                let request = request.into_request::<CounterData>().unwrap();
+                let message: SystemMessage<u64, u64, u64> = SystemMessage::Request(request);
+                let serialized = Bytes::from(bincode::serialize(&message).unwrap());
+                DecidedRequest::Serialized(header, serialized)
+            })
+            .collect();
+        let reframed = RecoveryState::new(
+            view.clone(),
+            recovery_state.checkpoint().clone(),
+            serialized,
+            recovery_state.decision_log().clone(),
+        );
+        assert!(matches!(
+            reframed.requests()[0],
+            DecidedRequest::Serialized(..)
+        ));
+        let operations = recovery_state.operations::<CounterData>().unwrap();
+        assert_eq!(operations, reframed.operations::<CounterData>().unwrap());
+
+        let digest = recovery_state.digest::<CounterData>().unwrap();
+        assert!(digest == reframed.digest::<CounterData>().unwrap());
+
+        // a state with more decided requests differs
+        let (log, _) = record_log(2, 7, None);
+        let other = log.snapshot(view.clone()).unwrap();
+        assert!(digest != other.digest::<CounterData>().unwrap());
+
+        // as does the same state in another view, or with other members
+        for other_view in [view.next_view(), ViewInfo::new(SeqNo::ZERO, 7, 2).unwrap()] {
+            let other = RecoveryState::new(
+                other_view,
+                recovery_state.checkpoint().clone(),
+                recovery_state.requests().to_vec(),
+                recovery_state.decision_log().clone(),
+            );
+            assert!(digest != other.digest::<CounterData>().unwrap());
+        }
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_inconsistent_recovery_state_rejected() {
+        use super::{install_recovery_state, RecoveryState};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::executable::Executor;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{init_runtime, CounterData, CounterService};
+
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let (log, _) = record_log(2, 6, None);
+        let consistent = log.snapshot(view.clone()).unwrap();
+        consistent
+            .validate()
+            .expect("Consistent recovery state rejected");
+
+        // a later checkpoint, paired with an earlier decision log
+        let (later_log, _) = record_log(2, 8, None);
+        let later = later_log.snapshot(view.clone()).unwrap();
+        let inconsistent = RecoveryState::new(
+            view.clone(),
+            later.checkpoint().clone(),
+            consistent.requests().to_vec(),
+            consistent.decision_log().clone(),
+        );
+
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+        init_runtime();
+        let mut executor = rt::block_on(Executor::new(
+            node.master_channel(),
+            CounterService,
+            0,
+            None,
+        ))
+        .unwrap();
+        let mut synchronizer = Synchronizer::new(Duration::from_secs(1), view, None);
+        let mut consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        let err = install_recovery_state(
+            inconsistent,
+            &node,
+            &mut synchronizer,
+            &mut log,
+            &mut executor,
+            &mut consensus,
+        )
+        .expect_err("Inconsistent recovery state installed");
+        assert_eq!(err.kind(), ErrorKind::Cst);
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_synthetic_recovery_state_installed() {
+        use super::{install_recovery_state, RecoveryState};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{Checkpoint, DecisionLog, Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::executable::Executor;
+        use crate::bft::ordering::{Orderable, SeqNo};
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{init_runtime, CounterData, CounterService};
+
+        // a recovery state built from its components, rather
+        // than taken from the log of a replica
+        let seq = SeqNo::from(4u32);
+        let view = ViewInfo::new(SeqNo::from(2u32), 4, 1).unwrap();
+        let recovery_state = RecoveryState::new(
+            view.clone(),
+            Checkpoint::new(seq, 42),
+            Vec::new(),
+            DecisionLog::new(),
+        );
+
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+        init_runtime();
+        let mut executor = rt::block_on(Executor::new(
+            node.master_channel(),
+            CounterService,
+            0,
+            None,
+        ))
+        .unwrap();
+        let initial = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let mut synchronizer = Synchronizer::new(Duration::from_secs(1), initial, None);
+        let mut consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        install_recovery_state(
+            recovery_state,
+            &node,
+            &mut synchronizer,
+            &mut log,
+            &mut executor,
+            &mut consensus,
+        )
+        .expect("Synthetic recovery state rejected");
+
+        assert_eq!(
+            synchronizer.view().sequence_number(),
+            view.sequence_number()
+        );
+        let installed = log.snapshot(view).unwrap();
+        assert_eq!(installed.checkpoint().sequence_number(), seq);
+        assert_eq!(*installed.checkpoint().state(), 42);
+        assert!(installed.requests().is_empty());
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_forged_decision_log_rejected() {
+        use super::{install_recovery_state, RecoveryState};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::message::RequestMessage;
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{DecidedRequest, Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::executable::Executor;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{init_runtime, CounterData, CounterService};
+
+        // a view whose only member is the node with id 0
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let (log, _) = record_log(3, 6, Some(&sk));
+        let certified = log.snapshot(view.clone()).unwrap();
+        assert!(!certified.decision_log().pre_prepares().is_empty());
+        certified
+            .validate_certificates(&node)
+            .expect("Certified recovery state rejected");
+
+        // the certified decisions, paired with requests other than
+        // the ones proposed in their batches
+        let requests = certified.requests();
+        assert!(requests.len() > 1);
+        let (seq, header) = (SeqNo::from(42u32), *requests[0].header());
+        let altered = DecidedRequest::Operation(header, RequestMessage::new(seq, 42));
+        let tampered = [
+            requests[1..].to_vec(),
+            std::iter::once(altered)
+                .chain(requests[1..].iter().cloned())
+                .collect(),
+            requests.iter().rev().cloned().collect(),
+        ];
+        for requests in tampered {
+            let tampered = RecoveryState::new(
+                view.clone(),
+                certified.checkpoint().clone(),
+                requests,
+                certified.decision_log().clone(),
+            );
+            assert!(tampered.validate_certificates(&node).is_err());
+        }
+
+        // decisions without any votes
+        let (log, _) = record_log(2, 6, None);
+        let unvoted = log.snapshot(view.clone()).unwrap();
+        unvoted
+            .validate()
+            .expect("Consistent recovery state rejected");
+        assert!(unvoted.validate_certificates(&node).is_err());
+
+        // the same decisions, signed by someone impersonating node 0
+        let forger = KeyPair::from_bytes(&[1; 32][..]).unwrap();
+        let (log, _) = record_log(2, 6, Some(&forger));
+        let forged = log.snapshot(view.clone()).unwrap();
+        forged
+            .validate()
+            .expect("Consistent recovery state rejected");
+
+        init_runtime();
+        let mut executor = rt::block_on(Executor::new(
+            node.master_channel(),
+            CounterService,
+            0,
+            None,
+        ))
+        .unwrap();
+        let mut synchronizer = Synchronizer::new(Duration::from_secs(1), view, None);
+        let mut consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        let err = install_recovery_state(
+            forged,
+            &node,
+            &mut synchronizer,
+            &mut log,
+            &mut executor,
+            &mut consensus,
+        )
+        .expect_err("Forged recovery state installed");
+        assert_eq!(err.kind(), ErrorKind::Cst);
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_state_fetched_from_checkpoint_store() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        use super::{CheckpointStore, CollabStateTransfer, CstProgress, ProtoPhase};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::message::{
+            CstMessage, CstMessageKind, Message, SystemMessage, WireMessage,
+        };
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::error::*;
+        use crate::bft::ordering::{Orderable, SeqNo};
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{init_runtime, CounterData, CounterService};
+
+        #[derive(Default)]
+        struct MemoryStore {
+            objects: Mutex<Vec<Vec<u8>>>,
+            gets: AtomicUsize,
+        }
+
+        impl CheckpointStore for MemoryStore {
+            fn put(&self, data: &[u8]) -> Result<String> {
+                let mut objects = self.objects.lock().unwrap();
+                objects.push(data.to_vec());
+                Ok(format!("mem://{}", objects.len() - 1))
+            }
+
+            fn get(&self, url: &str) -> Result<Vec<u8>> {
+                self.gets.fetch_add(1, Ordering::SeqCst);
+                url.strip_prefix("mem://")
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .and_then(|index| self.objects.lock().unwrap().get(index).cloned())
+                    .ok_or("No such object")
+                    .wrapped(ErrorKind::Cst)
+            }
+        }
+
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+
+        init_runtime();
+        let synchronizer = Synchronizer::new(Duration::from_secs(1), view.clone(), None);
+        let consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let store = Arc::new(MemoryStore::default());
+
+        // the replica serves its state through the store
+        let (serving_log, _) = record_log(2, 6, None);
+        let expected = serving_log
+            .snapshot(view.clone())
+            .unwrap()
+            .digest::<CounterData>()
+            .unwrap();
+        let mut serving: CollabStateTransfer<CounterService> = CollabStateTransfer::new(
+            Duration::from_secs(1),
+            Duration::ZERO,
+            1,
+            Some(store.clone()),
+        );
+        let header = WireMessage::new(id, id, &[], 0, None, None).header;
+        let request = CstMessage::new(SeqNo::ZERO, CstMessageKind::RequestState);
+        serving
+            .process_message(
+                CstProgress::Message(header, request),
+                &synchronizer,
+                &consensus,
+                &serving_log,
+                &mut node,
+            )
+            .expect_nil()
+            .unwrap();
+
+        let (header, reply) = match rt::block_on(node.receive()).unwrap() {
+            Message::System(header, SystemMessage::Cst(reply)) => (header, reply),
+            _ => panic!("Expected a CST reply"),
+        };
+        assert!(matches!(reply.kind(), CstMessageKind::ReplyStateRef(_)));
+        assert_eq!(store.objects.lock().unwrap().len(), 1);
+
+        let mut recovering: CollabStateTransfer<CounterService> = CollabStateTransfer::new(
+            Duration::from_secs(1),
+            Duration::ZERO,
+            1,
+            Some(store.clone()),
+        );
+        recovering.cst_seq = reply.sequence_number().next();
+        recovering.phase = ProtoPhase::ReceivingState(0);
+        let recovering_log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // a state tampered with in the store is dropped
+        let original = {
+            let mut objects = store.objects.lock().unwrap();
+            let original = objects[0].clone();
+            objects[0][0] ^= 1;
+            original
+        };
+        let status = recovering.process_message(
+            CstProgress::Message(header, reply.clone()),
+            &synchronizer,
+            &consensus,
+            &recovering_log,
+            &mut node,
+        );
+        assert!(matches!(status, CstStatus::Running));
+
+        // while the state placed by the replica is installed
+        store.objects.lock().unwrap()[0] = original;
+        let status = recovering.process_message(
+            CstProgress::Message(header, reply),
+            &synchronizer,
+            &consensus,
+            &recovering_log,
+            &mut node,
+        );
+        match status {
+            CstStatus::State(state) => {
+                assert!(state.digest::<CounterData>().unwrap() == expected);
+            }
+            _ => panic!("Recovery state not fetched from the store"),
+        }
+        assert_eq!(store.gets.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_chunked_state_transfer_resumed() {
+        use super::{CollabStateTransfer, CstProgress};
+        use crate::bft::communication::message::CstMessageKind;
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::ordering::{Orderable, SeqNo};
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{init_runtime, CounterData, CounterService};
+        use crate::bft::timeouts::Timeouts;
+
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+        let seed = [0; KeyPair::SEED_LENGTH];
+        let sk = KeyPair::from_bytes(&seed[..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+
+        init_runtime();
+        let synchronizer = Synchronizer::new(Duration::from_secs(1), view.clone(), None);
+        let consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let timeouts = Timeouts::new(node.master_channel());
+
+        // the requests in the state are checked against the
+        // public key of the loopback node, once reassembled
+        let sk = KeyPair::from_bytes(&seed[..]).unwrap();
+        let (serving_log, _) = record_log(2, 6, Some(&sk));
+        let expected = serving_log
+            .snapshot(view.clone())
+            .unwrap()
+            .digest::<CounterData>()
+            .unwrap();
+        let mut serving: CollabStateTransfer<CounterService> =
+            CollabStateTransfer::new(Duration::from_secs(60), Duration::ZERO, 1, None)
+                .with_chunk_size(Some(32));
+        let mut recovering: CollabStateTransfer<CounterService> =
+            CollabStateTransfer::new(Duration::from_secs(60), Duration::ZERO, 1, None);
+        let recovering_log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // the replica serves the manifest of its state, once it is
+        // requested, and the recovering node starts fetching its chunks
+        let request_state = |recovering: &mut CollabStateTransfer<CounterService>,
+                             serving: &mut CollabStateTransfer<CounterService>,
+                             node: &mut Node<CounterData>| {
+            recovering.request_latest_state(&synchronizer, &timeouts, node);
+            let (header, request) = next_cst(node);
+            serving
+                .process_message(
+                    CstProgress::Message(header, request),
+                    &synchronizer,
+                    &consensus,
+                    &serving_log,
+                    node,
+                )
+                .expect_nil()
+                .unwrap();
+            let (header, reply) = next_cst(node);
+            let chunks = match reply.kind() {
+                CstMessageKind::ReplyStateManifest(manifest, _) => manifest.chunks(),
+                _ => panic!("Expected a state manifest"),
+            };
+            let status = recovering.process_message(
+                CstProgress::Message(header, reply),
+                &synchronizer,
+                &consensus,
+                &recovering_log,
+                node,
+            );
+            assert!(matches!(status, CstStatus::RequestChunks));
+
+            // the missing chunks are requested, and served
+            recovering.request_missing_chunks(&timeouts, node);
+            let (header, request) = next_cst(node);
+            let (seq, indices) = match request.kind() {
+                CstMessageKind::RequestChunks(_, indices) => {
+                    (request.sequence_number(), indices.clone())
+                }
+                _ => panic!("Expected a chunk request"),
+            };
+            serving
+                .process_message(
+                    CstProgress::Message(header, request),
+                    &synchronizer,
+                    &consensus,
+                    &serving_log,
+                    node,
+                )
+                .expect_nil()
+                .unwrap();
+            let served: Vec<_> = indices.iter().map(|_| next_cst(node)).collect();
+            (chunks, seq, indices, served)
+        };
+
+        let (chunks, seq, indices, served) =
+            request_state(&mut recovering, &mut serving, &mut node);
+        assert!(chunks > 4);
+        assert_eq!(indices, (0..chunks).collect::<Vec<_>>());
+
+        // the transfer is interrupted midway, e.g. by a
+        // disconnect, so only half of the chunks arrive
+        let half = chunks / 2;
+        for (header, chunk) in served.into_iter().take(half as usize) {
+            let status = recovering.process_message(
+                CstProgress::Message(header, chunk),
+                &synchronizer,
+                &consensus,
+                &recovering_log,
+                &mut node,
+            );
+            assert!(matches!(status, CstStatus::Running));
+        }
+        assert!(matches!(
+            recovering.timed_out(seq),
+            CstStatus::RequestChunks
+        ));
+
+        // once reconnected, the same state is served again,
+        // and only the chunks still missing are fetched
+        let (_, _, indices, served) = request_state(&mut recovering, &mut serving, &mut node);
+        assert_eq!(indices, (half..chunks).collect::<Vec<_>>());
+        let mut status = CstStatus::Running;
+        for (header, chunk) in served {
+            assert!(matches!(status, CstStatus::Running));
+            status = recovering.process_message(
+                CstProgress::Message(header, chunk),
+                &synchronizer,
+                &consensus,
+                &recovering_log,
+                &mut node,
+            );
+        }
+        match status {
+            CstStatus::State(state) => {
+                assert!(state.digest::<CounterData>().unwrap() == expected);
+            }
+            _ => panic!("Recovery state not reassembled from its chunks"),
+        }
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_chunked_state_matched_across_vote_sets() {
+        use super::{CollabStateTransfer, CstProgress};
+        use crate::bft::communication::message::{
+            ConsensusMessage, ConsensusMessageKind, CstMessage, CstMessageKind, SystemMessage,
+            WireMessage,
+        };
+        use crate::bft::communication::serialize::DigestData;
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::ordering::{Orderable, SeqNo};
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{forge_header, init_runtime, CounterData, CounterService};
+        use crate::bft::timeouts::Timeouts;
+
+        // the recovering node needs f+1 = 2 replicas to vouch for a
+        // state, while the certificates of the served state are
+        // checked against the view it was decided in
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+        let recovering_view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let seed = [0; KeyPair::SEED_LENGTH];
+        let sk = KeyPair::from_bytes(&seed[..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+
+        init_runtime();
+        let synchronizer = Synchronizer::new(Duration::from_secs(1), view.clone(), None);
+        let recovering_synchronizer =
+            Synchronizer::new(Duration::from_secs(1), recovering_view, None);
+        let consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let timeouts = Timeouts::new(node.master_channel());
+
+        // both replicas decided the same requests, but the second
+        // one collected an extra commit for the latest instance
+        let sk = KeyPair::from_bytes(&seed[..]).unwrap();
+        let (log_a, _) = record_log(2, 6, Some(&sk));
+        let (mut log_b, _) = record_log(2, 6, Some(&sk));
+        let (seq, digest) = {
+            let pre_prepare = log_b.decision_log().pre_prepares().last().unwrap();
+            (
+                pre_prepare.message().sequence_number(),
+                *pre_prepare.header().digest(),
+            )
+        };
+        let commit = SystemMessage::Consensus(ConsensusMessage::new(
+            seq,
+            SeqNo::ZERO,
+            ConsensusMessageKind::Commit(digest),
+        ));
+        let commit_digest = CounterData::serialize_digest(&commit, Vec::new()).unwrap();
+        let id = NodeId::from(0u32);
+        let header = WireMessage::new(id, id, &[], 1000, Some(commit_digest), Some(&sk)).header;
+        log_b.insert(header, commit);
+
+        let export = |log: &Log<u64, u64, u64>| {
+            let mut buf = Vec::new();
+            log.decision_log().export(&mut buf).unwrap();
+            buf
+        };
+        assert!(export(&log_a) != export(&log_b));
+        let expected = log_a
+            .snapshot(view.clone())
+            .unwrap()
+            .digest::<CounterData>()
+            .unwrap();
+
+        let serving = || {
+            CollabStateTransfer::<CounterService>::new(
+                Duration::from_secs(60),
+                Duration::ZERO,
+                1,
+                None,
+            )
+            .with_chunk_size(Some(32))
+        };
+        let (mut serving_a, mut serving_b) = (serving(), serving());
+        let mut recovering: CollabStateTransfer<CounterService> =
+            CollabStateTransfer::new(Duration::from_secs(60), Duration::ZERO, 1, None);
+        let recovering_log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // both replicas serve the manifest of their state
+        recovering.request_latest_state(&recovering_synchronizer, &timeouts, &mut node);
+        let (header, request) = next_cst(&mut node);
+        let mut replies = Vec::new();
+        for (serving, log) in [(&mut serving_a, &log_a), (&mut serving_b, &log_b)] {
+            serving
+                .process_message(
+                    CstProgress::Message(header, request.clone()),
+                    &synchronizer,
+                    &consensus,
+                    log,
+                    &mut node,
+                )
+                .expect_nil()
+                .unwrap();
+            replies.push(next_cst(&mut node));
+        }
+        let manifests: Vec<_> = replies
+            .iter()
+            .map(|(_, reply)| match reply.kind() {
+                CstMessageKind::ReplyStateManifest(manifest, _) => {
+                    (*manifest.root(), manifest.chunks())
+                }
+                _ => panic!("Expected a state manifest"),
+            })
+            .collect();
+        assert!(manifests[0] == manifests[1]);
+        let (root, chunks) = manifests[0];
+
+        // the manifests match, hence the chunks of the
+        // state are fetched from both replicas
+        let mut replies = replies.into_iter();
+        let (header, reply) = replies.next().unwrap();
+        let status = recovering.process_message(
+            CstProgress::Message(header, reply),
+            &recovering_synchronizer,
+            &consensus,
+            &recovering_log,
+            &mut node,
+        );
+        assert!(matches!(status, CstStatus::Running));
+        let (_, reply) = replies.next().unwrap();
+        let status = recovering.process_message(
+            CstProgress::Message(forge_header(1, 0, 0, 0), reply),
+            &recovering_synchronizer,
+            &consensus,
+            &recovering_log,
+            &mut node,
+        );
+        assert!(matches!(status, CstStatus::RequestChunks));
+
+        // the loopback node only delivers the chunk requests sent to
+        // the first replica, so the second is asked for the rest
+        recovering.request_missing_chunks(&timeouts, &mut node);
+        let (header, request) = next_cst(&mut node);
+        let (cst_seq, indices) = match request.kind() {
+            CstMessageKind::RequestChunks(_, indices) => {
+                (request.sequence_number(), indices.clone())
+            }
+            _ => panic!("Expected a chunk request"),
+        };
+        let rest: Vec<_> = (0..chunks).filter(|i| !indices.contains(i)).collect();
+        assert!(!rest.is_empty());
+        let rest = CstMessage::new(cst_seq, CstMessageKind::RequestChunks(root, rest));
+        let mut served = Vec::new();
+        for (serving, log, request) in [
+            (&mut serving_a, &log_a, request),
+            (&mut serving_b, &log_b, rest),
+        ] {
+            let count = match request.kind() {
+                CstMessageKind::RequestChunks(_, indices) => indices.len(),
+                _ => unreachable!(),
+            };
+            serving
+                .process_message(
+                    CstProgress::Message(header, request),
+                    &synchronizer,
+                    &consensus,
+                    log,
+                    &mut node,
+                )
+                .expect_nil()
+                .unwrap();
+            served.extend((0..count).map(|_| next_cst(&mut node)));
+        }
+
+        // the state is reassembled, and installed along
+        // with the decision log of either replica
+        let mut status = CstStatus::Running;
+        for (header, chunk) in served {
+            assert!(matches!(status, CstStatus::Running));
+            status = recovering.process_message(
+                CstProgress::Message(header, chunk),
+                &recovering_synchronizer,
+                &consensus,
+                &recovering_log,
+                &mut node,
+            );
+        }
+        match status {
+            CstStatus::State(state) => {
+                assert!(state.digest::<CounterData>().unwrap() == expected);
+                assert!(state.validate_certificates(&node).is_ok());
+            }
+            _ => panic!("Recovery state not reassembled from its chunks"),
+        }
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_delta_replies_counted_once_per_replica() {
+        use super::{CollabStateTransfer, CstProgress};
+        use crate::bft::communication::message::{CstMessage, CstMessageKind};
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::ordering::{Orderable, SeqNo};
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{forge_header, init_runtime, CounterData, CounterService};
+        use crate::bft::timeouts::Timeouts;
+
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(NodeId::from(0u32), sk);
+
+        init_runtime();
+        let synchronizer = Synchronizer::new(Duration::from_secs(1), view, None);
+        let consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let timeouts = Timeouts::new(node.master_channel());
+        let log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        // no checkpoint is taken, so every instance is retained
+        let (serving_log, _) = record_log(100, 3, None);
+        let (from, to) = (SeqNo::ZERO, SeqNo::from(3u32));
+        let delta = serving_log.delta(from, to).expect("Delta not retained");
+
+        let mut cst: CollabStateTransfer<CounterService> =
+            CollabStateTransfer::new(Duration::from_secs(60), Duration::ZERO, 1, None);
+        cst.request_delta(from, to, &synchronizer, &timeouts, &mut node);
+        let (_, request) = next_cst(&mut node);
+        let seq = request.sequence_number();
+
+        let mut nonce = 0;
+        let mut reply = |cst: &mut CollabStateTransfer<CounterService>, sender: u32| {
+            nonce += 1;
+            let kind = CstMessageKind::ReplyDelta(delta.clone());
+            let message = CstMessage::new(seq, kind);
+            cst.process_message(
+                CstProgress::Message(forge_header(sender, 0, nonce, 0), message),
+                &synchronizer,
+                &consensus,
+                &log,
+                &mut node,
+            )
+        };
+
+        // a single replica, or a node outside the view,
+        // replying over and over doesn't make up a quorum
+        for sender in [1, 1, 1, 1000, 1000] {
+            assert!(matches!(reply(&mut cst, sender), CstStatus::Running));
+        }
+
+        // ... but replies from distinct replicas do
+        assert!(matches!(reply(&mut cst, 2), CstStatus::Running));
+        assert!(matches!(reply(&mut cst, 3), CstStatus::Delta(_)));
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_chunked_state_transfer_paced() {
+        use super::{CollabStateTransfer, CstProgress};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::message::{
+            ConsensusMessage, ConsensusMessageKind, CstMessage, CstMessageKind, Message,
+            SystemMessage,
+        };
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{init_runtime, CounterData, CounterService};
+
+        const CHUNK_SIZE: usize = 16;
+        const RATE: u64 = 3200;
+
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let me = NodeId::from(0u32);
+        let mut node: Node<CounterData> = Node::loopback(me, sk);
+
+        init_runtime();
+        let synchronizer = Synchronizer::new(Duration::from_secs(1), view, None);
+        let consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let (log, _) = record_log(2, 6, None);
+        let mut serving: CollabStateTransfer<CounterService> =
+            CollabStateTransfer::new(Duration::from_secs(60), Duration::ZERO, 1, None)
+                .with_chunk_size(Some(CHUNK_SIZE))
+                .with_chunk_rate(Some(RATE));
+        assert_eq!(serving.chunk_rate(), Some(RATE));
+
+        // delivers `kind` to the serving replica, as if requested by us
+        let mut serve = |kind, node: &mut Node<CounterData>| {
+            let request = SystemMessage::Cst(CstMessage::new(SeqNo::ZERO, kind));
+            node.send(request, me);
+            let (header, message) = match rt::block_on(node.receive()).unwrap() {
+                Message::System(header, SystemMessage::Cst(message)) => (header, message),
+                _ => panic!("Expected a CST request"),
+            };
+            serving
+                .process_message(
+                    CstProgress::Message(header, message),
+                    &synchronizer,
+                    &consensus,
+                    &log,
+                    node,
+                )
+                .expect_nil()
+                .unwrap();
+        };
+
+        serve(CstMessageKind::RequestState, &mut node);
+        let manifest = match rt::block_on(node.receive()).unwrap() {
+            Message::System(_, SystemMessage::Cst(mut message)) => message.take_manifest(),
+            _ => None,
+        }
+        .map(|(manifest, _)| manifest)
+        .expect("Expected a state manifest");
+        assert!(manifest.chunks() > 8);
+
+        // every chunk is requested at once, yet each one is
+        // only sent once the previous ones were paid for
+        let start = Instant::now();
+        let indices = (0..manifest.chunks()).collect();
+        serve(
+            CstMessageKind::RequestChunks(*manifest.root(), indices),
+            &mut node,
+        );
+
+        // consensus messages sent meanwhile aren't held back
+        let kind = ConsensusMessageKind::PrePrepare(Vec::new());
+        let pre_prepare = ConsensusMessage::new(SeqNo::ZERO, SeqNo::ZERO, kind);
+        node.send(SystemMessage::Consensus(pre_prepare), me);
+
+        let mut chunks = Vec::new();
+        let mut consensus_at = None;
+        while (chunks.len() as u64) < manifest.chunks() {
+            match rt::block_on(node.receive()).unwrap() {
+                Message::System(_, SystemMessage::Cst(mut message)) => {
+                    chunks.push(message.take_chunk().expect("Expected a chunk"));
+                }
+                Message::System(_, SystemMessage::Consensus(_)) => {
+                    consensus_at = Some(chunks.len());
+                }
+                _ => (),
+            }
+        }
+        let elapsed = start.elapsed();
+        assert!(consensus_at.unwrap() < chunks.len() / 2);
+
+        // the last chunk went out once every
+        // other chunk was paid for at `RATE`
+        let paid: usize = chunks[..chunks.len() - 1]
+            .iter()
+            .map(|chunk| chunk.data().len())
+            .sum();
+        assert!(elapsed >= Duration::from_secs_f64(paid as f64 / RATE as f64));
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_chunk_window_bounded() {
+        use bytes::Bytes;
+
+        use super::chunks::{StateChunk, StateManifest};
+        use super::{CollabStateTransfer, CstProgress};
+        use crate::bft::communication::message::{CstMessage, CstMessageKind, SystemMessage};
+        use crate::bft::communication::Node;
+        use crate::bft::consensus::log::{DecisionLog, Log, PERIOD};
+        use crate::bft::consensus::{Consensus, VoteVerification};
+        use crate::bft::core::server::ViewInfo;
+        use crate::bft::crypto::signature::KeyPair;
+        use crate::bft::ordering::{Orderable, SeqNo};
+        use crate::bft::sync::Synchronizer;
+        use crate::bft::testing::{init_runtime, CounterData, CounterService};
+        use crate::bft::timeouts::Timeouts;
+
+        let view = ViewInfo::new(SeqNo::ZERO, 1, 0).unwrap();
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let me = NodeId::from(0u32);
+        let mut node: Node<CounterData> = Node::loopback(me, sk);
+
+        init_runtime();
+        let synchronizer = Synchronizer::new(Duration::from_secs(1), view.clone(), None);
+        let consensus = Consensus::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let timeouts = Timeouts::new(node.master_channel());
+        let log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+
+        let (serving_log, _) = record_log(2, 6, None);
+        let mut buf = Vec::new();
+        serving_log
+            .snapshot(view)
+            .unwrap()
+            .write_to::<CounterData, _>(&mut buf)
+            .unwrap();
+        let data = Bytes::from(buf);
+        let manifest = StateManifest::new(&data[..], 16);
+        assert!(manifest.chunks() > 12);
+
+        let mut recovering: CollabStateTransfer<CounterService> =
+            CollabStateTransfer::new(Duration::from_secs(60), Duration::ZERO, 1, None)
+                .with_chunk_window(4);
+        recovering.request_latest_state(&synchronizer, &timeouts, &mut node);
+        let (_, request) = next_cst(&mut node);
+        let seq = request.sequence_number();
+
+        // delivers `kind` to the recovering node, as if sent by us
+        let deliver = |recovering: &mut CollabStateTransfer<CounterService>,
+                       node: &mut Node<CounterData>,
+                       kind| {
+            node.send(SystemMessage::Cst(CstMessage::new(seq, kind)), me);
+            let (header, message) = next_cst(node);
+            recovering.process_message(
+                CstProgress::Message(header, message),
+                &synchronizer,
+                &consensus,
+                &log,
+                node,
+            )
+        };
+        let chunk =
+            |index| CstMessageKind::ReplyChunk(StateChunk::new(&manifest, &data, index).unwrap());
+        // requests the chunks we are missing, returning their indices
+        let request = |recovering: &mut CollabStateTransfer<CounterService>,
+                       node: &mut Node<CounterData>| {
+            recovering.request_missing_chunks(&timeouts, node);
+            let (_, request) = next_cst(node);
+            match request.kind() {
+                CstMessageKind::RequestChunks(_, indices) => {
+                    (request.sequence_number(), indices.clone())
+                }
+                _ => panic!("Expected a chunk request"),
+            }
+        };
+        let buffered = |recovering: &CollabStateTransfer<CounterService>| {
+            recovering.assembly.as_ref().unwrap().buffered()
+        };
+
+        let kind = CstMessageKind::ReplyStateManifest(manifest.clone(), DecisionLog::new());
+        let status = deliver(&mut recovering, &mut node, kind);
+        assert!(matches!(status, CstStatus::RequestChunks));
+        let (request_seq, indices) = request(&mut recovering, &mut node);
+        assert_eq!(indices, [0, 1, 2, 3]);
+
+        // a replica sends sparse chunks far ahead of the ones we are
+        // missing, which are dropped, rather than buffered
+        for index in (4..manifest.chunks()).step_by(2) {
+            let status = deliver(&mut recovering, &mut node, chunk(index));
+            assert!(matches!(status, CstStatus::Running));
+        }
+        assert_eq!(buffered(&recovering), 0);
+        for index in [3, 1] {
+            deliver(&mut recovering, &mut node, chunk(index));
+        }
+        assert_eq!(buffered(&recovering), 2);
+
+        // once timed out, only the gap is requested again
+        let status = recovering.timed_out(request_seq);
+        assert!(matches!(status, CstStatus::RequestChunks));
+        let (_, indices) = request(&mut recovering, &mut node);
+        assert_eq!(indices, [0, 2]);
+
+        // and filling it moves the window forward
+        deliver(&mut recovering, &mut node, chunk(2));
+        let status = deliver(&mut recovering, &mut node, chunk(0));
+        assert!(matches!(status, CstStatus::RequestChunks));
+        assert_eq!(buffered(&recovering), 0);
+        let (_, indices) = request(&mut recovering, &mut node);
+        assert_eq!(indices, [4, 5, 6, 7]);
+    }
 }
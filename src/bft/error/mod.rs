@@ -16,6 +16,13 @@ pub trait ResultWrappedExt {
 
     fn wrapped_msg(self, kind: ErrorKind, msg: &str) -> Result<Self::T>;
     fn wrapped(self, kind: ErrorKind) -> Result<Self::T>;
+
+    /// Similar to `wrapped_msg()`, but the context message is only
+    /// computed by `f` on the error path, e.g. to include the id of
+    /// a peer node without formatting a `String` eagerly.
+    fn with_context<F>(self, kind: ErrorKind, f: F) -> Result<Self::T>
+    where
+        F: FnOnce() -> String;
 }
 
 /// Extension of the standard library's `Result` type.
@@ -45,6 +52,13 @@ where
     fn wrapped_msg(self, kind: ErrorKind, msg: &str) -> Result<Self::T> {
         self.map_err(|e| Error::wrapped(kind, format!("{}: {}", msg, e.into())))
     }
+
+    fn with_context<F>(self, kind: ErrorKind, f: F) -> Result<Self::T>
+    where
+        F: FnOnce() -> String,
+    {
+        self.map_err(|e| Error::wrapped(kind, format!("{}: {}", f(), e.into())))
+    }
 }
 
 impl<T, E> ResultSimpleExt for result::Result<T, E> {
@@ -71,6 +85,21 @@ pub struct Error {
 enum ErrorInner {
     Simple(ErrorKind),
     Wrapped(ErrorKind, Box<dyn error::Error + Send + Sync>),
+    // the result of cloning a `Wrapped` error; since the
+    // wrapped error may not implement `Clone`, we keep its
+    // `String` representation instead
+    Cloneable(ErrorKind, String),
+}
+
+impl Clone for Error {
+    fn clone(&self) -> Self {
+        let inner = match &self.inner {
+            ErrorInner::Simple(k) => ErrorInner::Simple(*k),
+            ErrorInner::Wrapped(k, e) => ErrorInner::Cloneable(*k, e.to_string()),
+            ErrorInner::Cloneable(k, e) => ErrorInner::Cloneable(*k, e.clone()),
+        };
+        Error { inner }
+    }
 }
 
 impl fmt::Debug for Error {
@@ -102,6 +131,7 @@ impl Error {
         match &self.inner {
             ErrorInner::Simple(k) => *k,
             ErrorInner::Wrapped(k, _) => *k,
+            ErrorInner::Cloneable(k, _) => *k,
         }
     }
 
@@ -110,6 +140,7 @@ impl Error {
         let inner = match self.inner {
             ErrorInner::Simple(_) => ErrorInner::Simple(k),
             ErrorInner::Wrapped(_, e) => ErrorInner::Wrapped(k, e),
+            ErrorInner::Cloneable(_, e) => ErrorInner::Cloneable(k, e),
         };
         Error { inner }
     }
@@ -120,6 +151,7 @@ impl fmt::Display for Error {
         match &self.inner {
             ErrorInner::Simple(k) => write!(fmt, "{:?}", k),
             ErrorInner::Wrapped(k, e) => write!(fmt, "{:?}: {}", k, e),
+            ErrorInner::Cloneable(k, e) => write!(fmt, "{:?}: {}", k, e),
         }
     }
 }
@@ -137,3 +169,45 @@ pub use error_kind::ErrorKind;
 mod error_kind {
     include!(concat!(env!("OUT_DIR"), "/error_kind.rs"));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind, ResultWrappedExt};
+
+    #[test]
+    fn test_with_context() {
+        let peer_id = 3;
+
+        let ok: Result<u32, &str> = Ok(1);
+        let result = ok.with_context(ErrorKind::Communication, || {
+            panic!("Context computed on the happy path")
+        });
+        assert_eq!(result.expect("Unexpected error"), 1);
+
+        let err: Result<u32, &str> = Err("connection reset");
+        let err = err
+            .with_context(ErrorKind::Communication, || {
+                format!("Failed to reach node {}", peer_id)
+            })
+            .expect_err("Expected error");
+        assert_eq!(err.kind(), ErrorKind::Communication);
+        assert_eq!(
+            err.to_string(),
+            "Communication: Failed to reach node 3: connection reset",
+        );
+    }
+
+    #[test]
+    fn test_clone() {
+        let simple = Error::simple(ErrorKind::Cst);
+        let cloned = simple.clone();
+        assert_eq!(cloned.kind(), ErrorKind::Cst);
+        assert_eq!(cloned.to_string(), simple.to_string());
+
+        let wrapped = Error::wrapped(ErrorKind::ViewChange, "leader is faulty");
+        let cloned = wrapped.clone();
+        assert_eq!(cloned.kind(), ErrorKind::ViewChange);
+        assert_eq!(cloned.to_string(), wrapped.to_string());
+        assert_eq!(cloned.clone().to_string(), "ViewChange: leader is faulty");
+    }
+}
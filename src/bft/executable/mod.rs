@@ -1,22 +1,37 @@
 //! User application execution business logic.
 
-use std::sync::mpsc;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures_timer::Delay;
+#[cfg(feature = "serialize_serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::bft::async_runtime as rt;
+use crate::bft::collections::{self, HashMap, OrderedMap};
 use crate::bft::communication::channel::MessageChannelTx;
-use crate::bft::communication::message::Message;
+use crate::bft::communication::message::{
+    Message, QueryStatusReplyMessage, RequestStatus, TraceId,
+};
 use crate::bft::communication::serialize::SharedData;
 use crate::bft::communication::NodeId;
 use crate::bft::crypto::hash::Digest;
 use crate::bft::error::*;
+use crate::bft::ordering::{Orderable, SeqNo};
 
 /// Represents a single client update request, to be executed.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 pub struct Update<O> {
     from: NodeId,
+    seq: SeqNo,
     digest: Digest,
     operation: O,
+    trace_id: Option<TraceId>,
 }
 
 /// Represents a single client update reply.
@@ -25,9 +40,11 @@ pub struct UpdateReply<P> {
     to: NodeId,
     digest: Digest,
     payload: P,
+    trace_id: Option<TraceId>,
 }
 
 /// Storage for a batch of client update requests to be executed.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 pub struct UpdateBatch<O> {
     inner: Vec<Update<O>>,
@@ -42,13 +59,79 @@ pub struct UpdateBatchReplies<P> {
 enum ExecutionRequest<S, O> {
     // install state from state transfer protocol
     InstallState(S, Vec<O>),
+    // replay requests decided while we were lagging behind,
+    // on top of the current state, discarding their replies
+    Replay(Vec<O>),
     // update the state of the service
     Update(UpdateBatch<O>),
     // same as above, and include the application state
     // in the reply, used for local checkpoints
     UpdateAndGetAppstate(UpdateBatch<O>),
-    // read the state of the service
-    Read(NodeId),
+    // serve a read only request, on behalf of a client
+    Read(NodeId, Digest, O),
+    // look up the reply to an executed request, on behalf of a client
+    QueryStatus(NodeId, Digest),
+    // send the cached reply to an executed request again, if any,
+    // answering the retransmission with the given digest
+    Retransmit(NodeId, SeqNo, Digest),
+    // notify once the outcomes of the requests queued so far are delivered
+    Barrier(oneshot::Sender<()>),
+}
+
+// the replies to the latest executed client requests, keyed by the
+// clients which sent them and their sequence numbers, such that
+// retransmissions, which are sent with a new nonce, and thus have a
+// distinct digest, hit the cache; the least recently used replies
+// are evicted first
+struct ReplyCache<P> {
+    capacity: usize,
+    replies: OrderedMap<(NodeId, SeqNo), (Digest, P)>,
+    // the requests the cached replies pertain to, by their digest,
+    // which is all clients have to go by when querying their status
+    requests: HashMap<Digest, (NodeId, SeqNo)>,
+}
+
+impl<P: Clone> ReplyCache<P> {
+    fn new(capacity: usize) -> Self {
+        let replies = collections::ordered_map();
+        let requests = collections::hash_map();
+        Self {
+            capacity,
+            replies,
+            requests,
+        }
+    }
+
+    fn insert(&mut self, from: NodeId, seq: SeqNo, digest: Digest, reply: &P) {
+        if self.capacity == 0 {
+            return;
+        }
+        let replaced = self.replies.insert((from, seq), (digest, reply.clone()));
+        if let Some((digest, _)) = replaced {
+            self.requests.remove(&digest);
+        }
+        self.requests.insert(digest, (from, seq));
+        if self.replies.len() > self.capacity {
+            if let Some((_, (digest, _))) = self.replies.pop_front() {
+                self.requests.remove(&digest);
+            }
+        }
+    }
+
+    // the reply to the request `seq` of the client `from`
+    fn get(&mut self, from: NodeId, seq: SeqNo) -> Option<&P> {
+        self.replies
+            .get_refresh(&(from, seq))
+            .map(|(_, reply)| &*reply)
+    }
+
+    // only the client which sent a request may look up its reply
+    fn get_by_digest(&mut self, from: NodeId, digest: &Digest) -> Option<&P> {
+        match self.requests.get(digest) {
+            Some(&(owner, seq)) if owner == from => self.get(owner, seq),
+            _ => None,
+        }
+    }
 }
 
 /* NOTE: unused
@@ -67,6 +150,16 @@ macro_rules! deserialize_st {
 
 */
 
+// the number of attempts made to deliver the outcome of an
+// execution request to the system channel, before giving up
+const DELIVERY_ATTEMPTS: u32 = 4;
+
+// the delay before retrying a failed delivery, doubled after every attempt
+const DELIVERY_BACKOFF: Duration = Duration::from_millis(10);
+
+// how often a barrier checks if the pending deliveries completed
+const BARRIER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
 /// State type of the `Service`.
 pub type State<S> = <<S as Service>::Data as SharedData>::State;
 
@@ -76,6 +169,11 @@ pub type Request<S> = <<S as Service>::Data as SharedData>::Request;
 /// Reply type of the `Service`.
 pub type Reply<S> = <<S as Service>::Data as SharedData>::Reply;
 
+/// Receives the outcomes of execution requests, e.g. the replies to a
+/// batch, which couldn't be delivered to the system channel, such as
+/// when the node owning the channel has been shut down.
+pub type DeadLetterSink<S> = Arc<dyn Fn(Message<State<S>, Request<S>, Reply<S>>) + Send + Sync>;
+
 /// A user defined `Service`.
 ///
 /// Application logic is implemented by this trait.
@@ -92,9 +190,34 @@ pub trait Service {
     /// Returns the initial state of the application.
     fn initial_state(&mut self) -> Result<State<Self>>;
 
+    /// Same as `initial_state()`, but may perform asynchronous I/O,
+    /// e.g. to load the genesis state of the application from a database.
+    ///
+    /// This is the method awaited by the executor; by default,
+    /// it calls `initial_state()`.
+    fn initial_state_async(&mut self) -> impl Future<Output = Result<State<Self>>> + Send
+    where
+        Self: Send,
+    {
+        async move { self.initial_state() }
+    }
+
     /// Process a user request, producing a matching reply,
     /// meanwhile updating the application state.
     fn update(&mut self, state: &mut State<Self>, request: Request<Self>) -> Reply<Self>;
+
+    /// Process a read only user request, producing a matching reply,
+    /// without updating the application state.
+    ///
+    /// By default, the request is processed by `update()`, on top of
+    /// a copy of the application state, which is discarded afterwards.
+    fn read(&mut self, state: &State<Self>, request: Request<Self>) -> Reply<Self>
+    where
+        State<Self>: Clone,
+    {
+        let mut state = state.clone();
+        self.update(&mut state, request)
+    }
 }
 
 /// Stateful data of the task responsible for executing
@@ -102,8 +225,12 @@ pub trait Service {
 pub struct Executor<S: Service> {
     service: S,
     state: State<S>,
+    replies: ReplyCache<Reply<S>>,
     e_rx: mpsc::Receiver<ExecutionRequest<State<S>, Request<S>>>,
     system_tx: MessageChannelTx<State<S>, Request<S>, Reply<S>>,
+    dead_letters: Option<DeadLetterSink<S>>,
+    // the no. of outcomes being delivered to `system_tx`
+    delivering: Arc<AtomicUsize>,
 }
 
 /// Represents a handle to the client request executor.
@@ -124,6 +251,17 @@ where
             .simple(ErrorKind::Executable)
     }
 
+    /// Executes the requests `requests` on top of the current state of
+    /// the execution layer, without replying to their clients.
+    ///
+    /// This is used to catch up with the requests decided while the
+    /// replica was lagging behind its peers.
+    pub fn queue_replay(&mut self, requests: Vec<Request<S>>) -> Result<()> {
+        self.e_tx
+            .send(ExecutionRequest::Replay(requests))
+            .simple(ErrorKind::Executable)
+    }
+
     /// Queues a batch of requests `batch` for execution.
     pub fn queue_update(&mut self, batch: UpdateBatch<Request<S>>) -> Result<()> {
         self.e_tx
@@ -140,6 +278,58 @@ where
             .send(ExecutionRequest::UpdateAndGetAppstate(batch))
             .simple(ErrorKind::Executable)
     }
+
+    /// Queues the read only request `operation` of the client with id
+    /// `from`, which is served after every batch queued so far is executed.
+    ///
+    /// The reply is delivered through `Message::ExecutionFinished`.
+    pub fn queue_read(
+        &mut self,
+        from: NodeId,
+        digest: Digest,
+        operation: Request<S>,
+    ) -> Result<()> {
+        self.e_tx
+            .send(ExecutionRequest::Read(from, digest, operation))
+            .simple(ErrorKind::Executable)
+    }
+
+    /// Replies again to the client with id `from`, if the reply to its
+    /// executed request with sequence number `seq` is still cached,
+    /// without executing the request a second time.
+    ///
+    /// The reply is delivered through `Message::ExecutionFinished`, with
+    /// the `digest` of the retransmitted request; nothing is delivered if
+    /// the reply was evicted from the cache.
+    pub fn queue_retransmission(&mut self, from: NodeId, seq: SeqNo, digest: Digest) -> Result<()> {
+        self.e_tx
+            .send(ExecutionRequest::Retransmit(from, seq, digest))
+            .simple(ErrorKind::Executable)
+    }
+
+    /// Looks up the reply to the executed client request with the
+    /// given digest, answering the client with id `from`. Requests
+    /// sent by other clients are reported as `RequestStatus::Unknown`.
+    ///
+    /// The lookup happens after every batch queued so far is executed.
+    pub fn queue_query_status(&mut self, from: NodeId, digest: Digest) -> Result<()> {
+        self.e_tx
+            .send(ExecutionRequest::QueryStatus(from, digest))
+            .simple(ErrorKind::Executable)
+    }
+
+    /// Returns a future resolving once the outcomes of every request
+    /// queued so far were delivered to the system channel, e.g. the
+    /// replies to the batches executed before shutting down a replica.
+    ///
+    /// Outcomes handed to the `DeadLetterSink` count as delivered.
+    pub fn queue_barrier(&mut self) -> Result<oneshot::Receiver<()>> {
+        let (tx, rx) = oneshot::channel();
+        self.e_tx
+            .send(ExecutionRequest::Barrier(tx))
+            .simple(ErrorKind::Executable)?;
+        Ok(rx)
+    }
 }
 
 impl<S: Service> Clone for ExecutorHandle<S> {
@@ -154,23 +344,38 @@ where
     S: Service + Send + 'static,
     State<S>: Send + Clone + 'static,
     Request<S>: Send + 'static,
-    Reply<S>: Send + 'static,
+    Reply<S>: Send + Clone + 'static,
 {
     /// Spawns a new service executor into the async runtime.
     ///
     /// A handle to the master message channel, `system_tx`, should be provided.
-    pub fn new(
+    ///
+    /// The replies to the latest `reply_cache_size` executed requests
+    /// are cached, to answer retransmitted requests and status queries.
+    ///
+    /// Deliveries to `system_tx` are retried a few times; outcomes which
+    /// still can't be delivered are handed to `dead_letters`, if any,
+    /// or dropped otherwise.
+    ///
+    /// The returned future resolves once the initial state of the
+    /// service is loaded, with `Service::initial_state_async()`.
+    pub async fn new(
         system_tx: MessageChannelTx<State<S>, Request<S>, Reply<S>>,
         mut service: S,
+        reply_cache_size: usize,
+        dead_letters: Option<DeadLetterSink<S>>,
     ) -> Result<ExecutorHandle<S>> {
         let (e_tx, e_rx) = mpsc::channel();
 
-        let state = service.initial_state()?;
+        let state = service.initial_state_async().await?;
         let mut exec = Executor {
             e_rx,
             system_tx,
+            dead_letters,
             service,
             state,
+            replies: ReplyCache::new(reply_cache_size),
+            delivering: Arc::new(AtomicUsize::new(0)),
         };
 
         // this thread is responsible for actually executing
@@ -182,47 +387,86 @@ where
             while let Ok(exec_req) = exec.e_rx.recv() {
                 match exec_req {
                     ExecutionRequest::InstallState(checkpoint, after) => {
-                        exec.state = checkpoint;
-                        for req in after {
-                            exec.service.update(&mut exec.state, req);
+                        exec.state = replay(&mut exec.service, checkpoint, after);
+                    }
+                    ExecutionRequest::Replay(requests) => {
+                        for request in requests {
+                            exec.service.update(&mut exec.state, request);
                         }
                     }
                     ExecutionRequest::Update(batch) => {
                         let mut reply_batch = UpdateBatchReplies::with_capacity(batch.len());
 
                         for update in batch.into_inner() {
+                            let trace_id = update.trace_id();
+                            let seq = update.sequence_number();
                             let (peer_id, dig, req) = update.into_inner();
                             let reply = exec.service.update(&mut exec.state, req);
-                            reply_batch.add(peer_id, dig, reply);
+                            exec.replies.insert(peer_id, seq, dig, &reply);
+                            reply_batch.add(peer_id, dig, reply, trace_id);
                         }
 
                         // deliver replies
-                        let mut system_tx = exec.system_tx.clone();
-                        rt::spawn(async move {
-                            let m = Message::ExecutionFinished(reply_batch);
-                            system_tx.send(m).await.unwrap();
-                        });
+                        exec.deliver(move || Message::ExecutionFinished(reply_batch.clone()));
                     }
                     ExecutionRequest::UpdateAndGetAppstate(batch) => {
                         let mut reply_batch = UpdateBatchReplies::with_capacity(batch.len());
 
                         for update in batch.into_inner() {
+                            let trace_id = update.trace_id();
+                            let seq = update.sequence_number();
                             let (peer_id, dig, req) = update.into_inner();
                             let reply = exec.service.update(&mut exec.state, req);
-                            reply_batch.add(peer_id, dig, reply);
+                            exec.replies.insert(peer_id, seq, dig, &reply);
+                            reply_batch.add(peer_id, dig, reply, trace_id);
                         }
                         let cloned_state = exec.state.clone();
 
                         // deliver replies
-                        let mut system_tx = exec.system_tx.clone();
-                        rt::spawn(async move {
-                            let m =
-                                Message::ExecutionFinishedWithAppstate(reply_batch, cloned_state);
-                            system_tx.send(m).await.unwrap();
+                        exec.deliver(move || {
+                            Message::ExecutionFinishedWithAppstate(
+                                reply_batch.clone(),
+                                cloned_state.clone(),
+                            )
                         });
                     }
-                    ExecutionRequest::Read(_peer_id) => {
-                        todo!()
+                    ExecutionRequest::Read(peer_id, dig, req) => {
+                        let reply = exec.service.read(&exec.state, req);
+                        let mut reply_batch = UpdateBatchReplies::with_capacity(1);
+                        reply_batch.add(peer_id, dig, reply, None);
+
+                        // deliver reply
+                        exec.deliver(move || Message::ExecutionFinished(reply_batch.clone()));
+                    }
+                    ExecutionRequest::Retransmit(peer_id, seq, dig) => {
+                        let reply = match exec.replies.get(peer_id, seq) {
+                            Some(reply) => reply.clone(),
+                            None => continue,
+                        };
+                        let mut reply_batch = UpdateBatchReplies::with_capacity(1);
+                        reply_batch.add(peer_id, dig, reply, None);
+
+                        // deliver reply
+                        exec.deliver(move || Message::ExecutionFinished(reply_batch.clone()));
+                    }
+                    ExecutionRequest::QueryStatus(peer_id, dig) => {
+                        let status = match exec.replies.get_by_digest(peer_id, &dig) {
+                            Some(reply) => RequestStatus::Executed(reply.clone()),
+                            None => RequestStatus::Unknown,
+                        };
+                        let reply = QueryStatusReplyMessage::new(dig, status);
+
+                        // deliver status
+                        exec.deliver(move || Message::StatusQueried(peer_id, reply.clone()));
+                    }
+                    ExecutionRequest::Barrier(tx) => {
+                        let delivering = Arc::clone(&exec.delivering);
+                        rt::spawn(async move {
+                            while delivering.load(Ordering::Relaxed) > 0 {
+                                Delay::new(BARRIER_POLL_INTERVAL).await;
+                            }
+                            tx.send(()).unwrap_or(());
+                        });
                     }
                 }
             }
@@ -230,6 +474,56 @@ where
 
         Ok(ExecutorHandle { e_tx })
     }
+
+    // delivers the outcome of an execution request to the system channel,
+    // in the background; `message` builds the outcome anew on every
+    // attempt, since a failed send consumes it
+    fn deliver<F>(&self, message: F)
+    where
+        F: Fn() -> Message<State<S>, Request<S>, Reply<S>> + Send + 'static,
+    {
+        let mut system_tx = self.system_tx.clone();
+        let dead_letters = self.dead_letters.clone();
+        let delivering = Arc::clone(&self.delivering);
+        delivering.fetch_add(1, Ordering::Relaxed);
+        rt::spawn(async move {
+            let mut backoff = DELIVERY_BACKOFF;
+            let mut delivered = false;
+            for attempt in 1..=DELIVERY_ATTEMPTS {
+                if system_tx.send(message()).await.is_ok() {
+                    delivered = true;
+                    break;
+                }
+                if attempt < DELIVERY_ATTEMPTS {
+                    Delay::new(backoff).await;
+                    backoff *= 2;
+                }
+            }
+            if !delivered {
+                if let Some(dead_letters) = dead_letters {
+                    dead_letters(message());
+                }
+            }
+            delivering.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Executes the client `requests` through `service`, on top of the
+/// application `state`, discarding their replies, and returns the
+/// resulting state.
+///
+/// No networking is involved, so this may be used to rebuild the state
+/// of a replica offline, e.g. to audit its log, or to debug a divergence.
+pub fn replay<S, I>(service: &mut S, mut state: State<S>, requests: I) -> State<S>
+where
+    S: Service,
+    I: IntoIterator<Item = Request<S>>,
+{
+    for request in requests {
+        service.update(&mut state, request);
+    }
+    state
 }
 
 impl<O> UpdateBatch<O> {
@@ -238,12 +532,23 @@ impl<O> UpdateBatch<O> {
         Self { inner: Vec::new() }
     }
 
-    /// Adds a new update request to the batch.
-    pub fn add(&mut self, from: NodeId, digest: Digest, operation: O) {
+    /// Adds a new update request to the batch, sent by the client `from`
+    /// with the sequence number `seq`, along with the trace id of its
+    /// request, if any.
+    pub fn add(
+        &mut self,
+        from: NodeId,
+        seq: SeqNo,
+        digest: Digest,
+        operation: O,
+        trace_id: Option<TraceId>,
+    ) {
         self.inner.push(Update {
             from,
+            seq,
             digest,
             operation,
+            trace_id,
         });
     }
 
@@ -274,6 +579,18 @@ impl<O> Update<O> {
     pub fn operation(&self) -> &O {
         &self.operation
     }
+
+    /// Returns the trace id of the request of this `Update`, if any.
+    pub fn trace_id(&self) -> Option<TraceId> {
+        self.trace_id
+    }
+}
+
+impl<O> Orderable for Update<O> {
+    /// Returns the sequence number of the client request of this `Update`.
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
 }
 
 impl<P> UpdateBatchReplies<P> {
@@ -291,12 +608,14 @@ impl<P> UpdateBatchReplies<P> {
         }
     }
 
-    /// Adds a new update reply to the batch.
-    pub fn add(&mut self, to: NodeId, digest: Digest, payload: P) {
+    /// Adds a new update reply to the batch, along
+    /// with the trace id of its request, if any.
+    pub fn add(&mut self, to: NodeId, digest: Digest, payload: P, trace_id: Option<TraceId>) {
         self.inner.push(UpdateReply {
             to,
             digest,
             payload,
+            trace_id,
         });
     }
 
@@ -316,4 +635,122 @@ impl<P> UpdateReply<P> {
     pub fn into_inner(self) -> (NodeId, Digest, P) {
         (self.to, self.digest, self.payload)
     }
+
+    /// Returns the trace id of the request replied to, if any.
+    pub fn trace_id(&self) -> Option<TraceId> {
+        self.trace_id
+    }
+}
+
+#[cfg(all(test, feature = "serialize_serde"))]
+mod tests {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{DeadLetterSink, Executor, Service, UpdateBatch};
+    use crate::bft::async_runtime as rt;
+    use crate::bft::communication::message::Message;
+    use crate::bft::communication::{Node, NodeId};
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::crypto::signature::KeyPair;
+    use crate::bft::error::*;
+    use crate::bft::ordering::SeqNo;
+    use crate::bft::testing::{init_runtime, CounterData};
+
+    // a counter whose initial value is loaded asynchronously
+    struct LoadedCounterService;
+
+    impl Service for LoadedCounterService {
+        type Data = CounterData;
+
+        fn initial_state(&mut self) -> Result<u64> {
+            Ok(0)
+        }
+
+        async fn initial_state_async(&mut self) -> Result<u64> {
+            // stands in for a slow database query
+            let (tx, rx) = oneshot::channel();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                tx.send(100).unwrap();
+            });
+            Ok(rx.await.unwrap())
+        }
+
+        fn update(&mut self, state: &mut u64, request: u64) -> u64 {
+            *state += request;
+            *state
+        }
+    }
+
+    #[test]
+    fn test_async_initial_state_loaded_before_updates() {
+        init_runtime();
+
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+
+        let reply = rt::block_on(async {
+            let mut executor = Executor::new(node.master_channel(), LoadedCounterService, 0, None)
+                .await
+                .unwrap();
+
+            let digest = Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap();
+            let mut batch = UpdateBatch::new();
+            batch.add(id, SeqNo::ZERO, digest, 1, None);
+            executor.queue_update(batch).unwrap();
+
+            loop {
+                if let Message::ExecutionFinished(batch) = node.receive().await.unwrap() {
+                    let (_, _, reply) = batch.into_inner().remove(0).into_inner();
+                    break reply;
+                }
+            }
+        });
+
+        // the update ran on top of the loaded state
+        assert_eq!(reply, 101);
+    }
+
+    #[test]
+    fn test_undeliverable_replies_dead_lettered() {
+        init_runtime();
+
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let node: Node<CounterData> = Node::loopback(id, sk);
+        let system_tx = node.master_channel();
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let dead_letters: DeadLetterSink<LoadedCounterService> = Arc::new(move |message| {
+            if let Message::ExecutionFinished(batch) = message {
+                let (_, _, reply) = batch.into_inner().remove(0).into_inner();
+                tx.lock().unwrap().send(reply).unwrap();
+            }
+        });
+
+        let mut executor = rt::block_on(Executor::new(
+            system_tx,
+            LoadedCounterService,
+            0,
+            Some(dead_letters),
+        ))
+        .unwrap();
+
+        // close the system channel
+        drop(node);
+
+        let digest = Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap();
+        let mut batch = UpdateBatch::new();
+        batch.add(id, SeqNo::ZERO, digest, 1, None);
+        executor.queue_update(batch).unwrap();
+
+        // the executor didn't panic, and gave up
+        // on the reply after retrying its delivery
+        let reply = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(reply, 101);
+    }
 }
@@ -16,6 +16,8 @@ pub mod globals;
 pub mod ordering;
 pub mod prng;
 pub mod sync;
+#[cfg(all(test, feature = "serialize_serde"))]
+pub(crate) mod testing;
 pub mod threadpool;
 pub mod timeouts;
 
@@ -65,6 +65,18 @@ impl SeqNo {
         SeqNo(if overflow { 0 } else { next })
     }
 
+    /// Returns the number of sequence numbers from `earlier` up to
+    /// `self`, accounting for the wraparound performed by `next()`.
+    #[inline]
+    pub(crate) fn distance(self, earlier: SeqNo) -> u32 {
+        let d = (self.0 as i64) - (earlier.0 as i64);
+        if d < 0 {
+            (d + i32::MAX as i64 + 1) as u32
+        } else {
+            d as u32
+        }
+    }
+
     /// Return an appropriate value to index the `TboQueue`.
     #[inline]
     pub(crate) fn index(self, other: SeqNo) -> Either<InvalidSeqNo, usize> {
@@ -98,6 +110,15 @@ impl SeqNo {
     }
 }
 
+/// The maximum number of sequence numbers, starting at the current one,
+/// for which a `TboQueue` holds messages.
+///
+/// Messages further ahead are dropped, rather than having a malicious
+/// node force the allocation of a queue for each sequence number in
+/// between; a replica lagging this far behind runs the state transfer
+/// protocol instead, once enough of its peers are seen this far ahead.
+pub const TBO_QUEUE_WINDOW: usize = 128;
+
 /// Takes an internal queue of a `TboQueue` (e.g. the one used in the consensus
 /// module), and pops a message.
 pub fn tbo_pop_message<M>(
@@ -112,28 +133,28 @@ pub fn tbo_pop_message<M>(
 
 /// Takes an internal queue of a `TboQueue` (e.g. the one used in the consensus
 /// module), and queues a message.
+///
+/// Returns `true` if the message was dropped for lying beyond the
+/// `TBO_QUEUE_WINDOW`, meaning we may be lagging behind its sender.
 pub fn tbo_queue_message<M: Orderable>(
     curr_seq: SeqNo,
     tbo: &mut VecDeque<VecDeque<StoredMessage<M>>>,
     m: StoredMessage<M>,
-) {
+) -> bool {
     let index = match m.message().sequence_number().index(curr_seq) {
-        Right(i) => i,
-        Left(_) => {
-            // FIXME: maybe notify peers if we detect a message
-            // with an invalid (too large) seq no? return the
-            // `NodeId` of the offending node.
-            //
-            // NOTE: alternatively, if this seq no pertains to consensus,
-            // we can try running the state transfer protocol
-            return;
-        }
+        Right(i) if i < TBO_QUEUE_WINDOW => i,
+        Right(_) | Left(InvalidSeqNo::Big) => return true,
+        // FIXME: maybe notify peers if we detect a message
+        // with an invalid (too small) seq no? return the
+        // `NodeId` of the offending node.
+        Left(InvalidSeqNo::Small) => return false,
     };
     if index >= tbo.len() {
         let len = index - tbo.len() + 1;
         tbo.extend(std::iter::repeat_with(VecDeque::new).take(len));
     }
     tbo[index].push_back(m);
+    false
 }
 
 /// Takes an internal queue of a `TboQueue` (e.g. the one used in the consensus
@@ -149,8 +170,89 @@ pub fn tbo_advance_message_queue<M>(tbo: &mut VecDeque<VecDeque<StoredMessage<M>
     }
 }
 
+/// Takes an internal queue of a `TboQueue` (e.g. the one used in the consensus
+/// module), and reports the number of messages queued for each sequence number.
+pub fn tbo_queued_counts<M: Orderable>(
+    tbo: &VecDeque<VecDeque<StoredMessage<M>>>,
+) -> Vec<(SeqNo, usize)> {
+    tbo.iter()
+        .filter_map(|queue| {
+            let seq = queue.front()?.message().sequence_number();
+            Some((seq, queue.len()))
+        })
+        .collect()
+}
+
+/// Takes an internal queue of a `TboQueue` (e.g. the one used in the consensus
+/// module), and drops the messages pertaining to sequence numbers older than
+/// `curr_seq`, returning the number of dropped messages.
+///
+/// The leading queues of stale sequence numbers are popped as well, so
+/// the front queue once again holds the messages of `curr_seq`.
+pub fn tbo_drain_stale<M: Orderable>(
+    curr_seq: SeqNo,
+    tbo: &mut VecDeque<VecDeque<StoredMessage<M>>>,
+) -> usize {
+    let mut dropped = 0;
+    for queue in tbo.iter_mut() {
+        let len = queue.len();
+        queue.retain(|m| m.message().sequence_number().index(curr_seq).is_right());
+        dropped += len - queue.len();
+    }
+
+    // the queue at index `i` holds the messages of the seq no. `i`
+    // instances after the one at the front; find out how far ahead
+    // of `curr_seq` the front queue is, from any queued message
+    let offset = tbo.iter().enumerate().find_map(|(i, queue)| {
+        let seq = queue.front()?.message().sequence_number();
+        Some(i.saturating_sub(seq.index(curr_seq).right()?))
+    });
+    match offset {
+        Some(stale) => drop(tbo.drain(..stale)),
+        // only empty queues are left
+        None => tbo.clear(),
+    }
+
+    dropped
+}
+
 /// Represents any value that can be oredered.
 pub trait Orderable {
     /// Returns the sequence number of this value.
     fn sequence_number(&self) -> SeqNo;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{tbo_queue_message, SeqNo, TBO_QUEUE_WINDOW};
+    use crate::bft::communication::message::{LeaderStepDownMessage, StoredMessage};
+    use crate::bft::consensus::log::PERIOD;
+    use crate::bft::testing::forge_header;
+
+    fn message(seq: u32) -> StoredMessage<LeaderStepDownMessage> {
+        let header = forge_header(1, 0, 0, 0);
+        StoredMessage::new(header, LeaderStepDownMessage::new(SeqNo::from(seq)))
+    }
+
+    #[test]
+    fn test_tbo_queue_window() {
+        let mut tbo = VecDeque::new();
+
+        // a near threshold seq no. is still a valid index,
+        // but it lies beyond the window, so it is dropped
+        assert!(tbo_queue_message(SeqNo::ZERO, &mut tbo, message(PERIOD)));
+        assert!(tbo.is_empty());
+
+        // the last seq no. in the window is queued
+        let last = TBO_QUEUE_WINDOW as u32 - 1;
+        assert!(!tbo_queue_message(SeqNo::ZERO, &mut tbo, message(last)));
+        assert_eq!(tbo.len(), TBO_QUEUE_WINDOW);
+        assert_eq!(tbo[TBO_QUEUE_WINDOW - 1].len(), 1);
+
+        // ... but not the following one
+        assert!(tbo_queue_message(SeqNo::ZERO, &mut tbo, message(last + 1)));
+        assert_eq!(tbo.len(), TBO_QUEUE_WINDOW);
+    }
+}
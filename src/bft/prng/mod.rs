@@ -3,6 +3,12 @@
 //! The current implementation is based on [xoshiro256**](https://prng.di.unimi.it/xoshiro256starstar.c),
 //! from David Blackman and Sebastiano Vigna. This source code is a one-to-one translation of their
 //! C code, released to the public domain.
+//!
+//! Every `State` is seeded from the entropy source of the operating system,
+//! never from a clock, so two states created in the same instant yield
+//! uncorrelated sequences of random numbers.
+
+use std::cell::RefCell;
 
 use rand_core::{OsRng, RngCore};
 
@@ -11,8 +17,16 @@ pub struct State {
     s: [u64; 4],
 }
 
+thread_local! {
+    static THREAD_STATE: RefCell<State> = RefCell::new(State::new());
+}
+
 impl State {
     /// Creates a new PRNG from a cryptographically secure random seed.
+    ///
+    /// The seed is read from the operating system, with `OsRng`; if
+    /// the OS fails to provide entropy, this function panics, rather
+    /// than falling back to a weaker seed.
     pub fn new() -> Self {
         let mut seed = [0; 32];
         OsRng.fill_bytes(&mut seed);
@@ -24,6 +38,22 @@ impl State {
         s
     }
 
+    /// Creates a new PRNG from a state shared by the current thread.
+    ///
+    /// The thread local state is seeded only once, with `new()`, which
+    /// avoids reading entropy from the OS every time a PRNG is created.
+    /// Before returning, the shared state is advanced by 2^128 calls to
+    /// `next_state()`, therefore the sequences of the returned PRNGs
+    /// never overlap in practice.
+    pub fn from_thread_local() -> Self {
+        THREAD_STATE.with(|shared| {
+            let mut shared = shared.borrow_mut();
+            let s = State { s: shared.s };
+            shared.jump();
+            s
+        })
+    }
+
     /// Returns a new 64-bit random number.
     #[inline]
     pub fn next_state(&mut self) -> u64 {
@@ -42,6 +72,17 @@ impl State {
         result
     }
 
+    #[inline]
+    fn jump(&mut self) {
+        const JUMP: [u64; 4] = [
+            0x180ec6d33cfd0aba,
+            0xd5a61266f0c9392c,
+            0xa9582618e03fc9aa,
+            0x39abdc4529b1661c,
+        ];
+        self.jump_with(&JUMP);
+    }
+
     #[inline]
     fn long_jump(&mut self) {
         const LONG_JUMP: [u64; 4] = [
@@ -50,13 +91,17 @@ impl State {
             0x77710069854ee241,
             0x39109bb02acbe635,
         ];
+        self.jump_with(&LONG_JUMP);
+    }
 
+    #[inline]
+    fn jump_with(&mut self, jump: &[u64; 4]) {
         let mut s0 = 0;
         let mut s1 = 0;
         let mut s2 = 0;
         let mut s3 = 0;
 
-        for &jmp_val in LONG_JUMP.iter() {
+        for &jmp_val in jump.iter() {
             for b in 0..64 {
                 if jmp_val & 1 << b != 0 {
                     s0 ^= self.s[0];
@@ -88,3 +133,47 @@ impl Iterator for State {
 fn rotl(x: u64, k: u64) -> u64 {
     (x << k) | (x >> (64 - k))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+
+    // the Pearson correlation coefficient of the random
+    // numbers output by two PRNGs, mapped to [0, 1)
+    fn correlation(a: &mut State, b: &mut State) -> f64 {
+        const SAMPLES: usize = 100_000;
+
+        let to_unit = |x: u64| (x >> 11) as f64 / (1u64 << 53) as f64;
+        let pairs: Vec<(f64, f64)> = a
+            .zip(b)
+            .take(SAMPLES)
+            .map(|(x, y)| (to_unit(x), to_unit(y)))
+            .collect();
+
+        let n = SAMPLES as f64;
+        let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+        let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+        for (x, y) in pairs {
+            cov += (x - mean_x) * (y - mean_y);
+            var_x += (x - mean_x) * (x - mean_x);
+            var_y += (y - mean_y) * (y - mean_y);
+        }
+        cov / (var_x * var_y).sqrt()
+    }
+
+    #[test]
+    fn test_uncorrelated_os_seeded() {
+        let mut a = State::new();
+        let mut b = State::new();
+        assert!(correlation(&mut a, &mut b).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_uncorrelated_thread_local() {
+        let mut a = State::from_thread_local();
+        let mut b = State::from_thread_local();
+        assert!(correlation(&mut a, &mut b).abs() < 0.02);
+    }
+}
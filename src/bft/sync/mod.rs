@@ -16,12 +16,13 @@ use serde::{Deserialize, Serialize};
 //    Right,
 //};
 
-use crate::bft::collections::{self, HashMap};
+use crate::bft::collections::{self, HashMap, HashSet};
 use crate::bft::communication::message::{
-    ForwardedRequestsMessage, Header, RequestMessage, StoredMessage, SystemMessage,
-    ViewChangeMessage, ViewChangeMessageKind, WireMessage,
+    ForwardRequestMessage, ForwardedRequestsMessage, Header, LeaderStepDownMessage, Message,
+    RequestMessage, StoredMessage, SystemMessage, ViewChangeMessage, ViewChangeMessageKind,
+    WireMessage,
 };
-use crate::bft::communication::serialize::{Buf, DigestData};
+use crate::bft::communication::serialize::{Buf, DigestData, SharedData};
 use crate::bft::communication::{Node, NodeId};
 use crate::bft::consensus::log::{CollectData, Log, Proof, ViewDecisionPair};
 use crate::bft::consensus::Consensus;
@@ -30,9 +31,10 @@ use crate::bft::crypto::hash::Digest;
 use crate::bft::executable::{Reply, Request, Service, State};
 use crate::bft::ordering::{
     tbo_advance_message_queue, tbo_pop_message, tbo_queue_message, Orderable, SeqNo,
+    TBO_QUEUE_WINDOW,
 };
 use crate::bft::prng;
-use crate::bft::timeouts::{TimeoutKind, TimeoutsHandle};
+use crate::bft::timeouts::{Clock, TimeoutKind, TimeoutsHandle};
 
 /// Contains the `COLLECT` structures the leader received in the `STOP-DATA` phase
 /// of the view change protocol, as well as a value to be proposed in the `SYNC` message.
@@ -154,24 +156,70 @@ impl<O> TboQueue<O> {
         // NOTE: we use next() because we want to retrieve messages
         // for v+1, as we haven't started installing the new view yet
         let seq = self.view.sequence_number().next();
-        tbo_queue_message(seq, &mut self.stop, StoredMessage::new(h, m))
+        tbo_queue_message(seq, &mut self.stop, StoredMessage::new(h, m));
     }
 
     /// Queues a `STOP-DATA` message for later processing, or drops it
     /// immediately if it pertains to an older view change instance.
     fn queue_stop_data(&mut self, h: Header, m: ViewChangeMessage<O>) {
         let seq = self.view.sequence_number();
-        tbo_queue_message(seq, &mut self.stop_data, StoredMessage::new(h, m))
+        tbo_queue_message(seq, &mut self.stop_data, StoredMessage::new(h, m));
     }
 
     /// Queues a `SYNC` message for later processing, or drops it
     /// immediately if it pertains to an older view change instance.
     fn queue_sync(&mut self, h: Header, m: ViewChangeMessage<O>) {
         let seq = self.view.sequence_number();
-        tbo_queue_message(seq, &mut self.sync, StoredMessage::new(h, m))
+        tbo_queue_message(seq, &mut self.sync, StoredMessage::new(h, m));
     }
 }
 
+// tracks the `STOP` messages received for views past the next one,
+// i.e. the views other replicas are trying to change to
+struct ViewVotes {
+    votes: HashMap<SeqNo, HashSet<NodeId>>,
+}
+
+impl ViewVotes {
+    fn new() -> Self {
+        Self {
+            votes: collections::hash_map(),
+        }
+    }
+
+    // records the vote of `from` to change to the view `target`, returning
+    // the highest view past the next one with at least `f+1` votes
+    fn vote(&mut self, view: &ViewInfo, from: NodeId, target: SeqNo) -> Option<SeqNo> {
+        let next = view.sequence_number().next();
+        if !ahead_of(next, target) || !view.members().contains(&from) {
+            return None;
+        }
+        self.votes
+            .entry(target)
+            .or_insert_with(collections::hash_set)
+            .insert(from);
+        let f = view.params().f();
+        self.votes
+            .iter()
+            .filter(|(_, voters)| voters.len() > f)
+            .map(|(target, _)| *target)
+            .max_by_key(|target| target.distance(next))
+    }
+
+    // drops the votes for views up to the next view of `view`
+    fn prune(&mut self, view: &ViewInfo) {
+        let next = view.sequence_number().next();
+        self.votes.retain(|target, _| ahead_of(next, *target));
+    }
+}
+
+// checks if the view `target` is past the view `next`, but
+// not so far ahead that its messages would be dropped
+fn ahead_of(next: SeqNo, target: SeqNo) -> bool {
+    let distance = target.distance(next) as usize;
+    distance > 0 && distance < TBO_QUEUE_WINDOW
+}
+
 #[derive(Copy, Clone)]
 enum TimeoutPhase {
     // we have never received a timeout
@@ -255,6 +303,19 @@ pub struct Synchronizer<S: Service> {
     watching: HashMap<Digest, TimeoutPhase>,
     tbo: TboQueue<Request<S>>,
     finalize_state: Option<FinalizeState>,
+    view_votes: ViewVotes,
+    // no. of view changes abandoned in a row, i.e. superseded
+    // by another view change before a new view was installed
+    failed_view_changes: usize,
+    max_failed_view_changes: Option<usize>,
+    // when we sent our own STOP, for the view change we are running
+    changing_since: Instant,
+    // the replicas we received consensus messages from, pertaining
+    // to the view we are changing to, or a more recent one
+    ahead: HashSet<NodeId>,
+    // whether we gave up on changing views, after
+    // `max_failed_view_changes` view changes failed
+    unrecoverable: bool,
 }
 
 macro_rules! extract_msg {
@@ -324,7 +385,11 @@ where
     Request<S>: Send + Clone + 'static,
     Reply<S>: Send + 'static,
 {
-    pub fn new(timeout_dur: Duration, view: ViewInfo) -> Self {
+    pub fn new(
+        timeout_dur: Duration,
+        view: ViewInfo,
+        max_failed_view_changes: Option<usize>,
+    ) -> Self {
         Self {
             timeout_dur,
             phase: ProtoPhase::Init,
@@ -335,41 +400,90 @@ where
             collects: collections::hash_map(),
             tbo: TboQueue::new(view),
             finalize_state: None,
+            view_votes: ViewVotes::new(),
+            failed_view_changes: 0,
+            max_failed_view_changes,
+            changing_since: Instant::now(),
+            ahead: collections::hash_set(),
+            unrecoverable: false,
         }
     }
 
     /// Watch a client request with the digest `digest`.
-    pub fn watch_request(&mut self, digest: Digest, timeouts: &TimeoutsHandle<S>) {
-        let phase = TimeoutPhase::Init(Instant::now());
+    pub fn watch_request<C: Clock>(&mut self, digest: Digest, timeouts: &TimeoutsHandle<S, C>) {
+        let phase = TimeoutPhase::Init(timeouts.now());
         self.watch_request_impl(phase, digest, timeouts);
     }
 
     /// Watch a group of client requests that we received from a
     /// forwarded requests system message.
-    pub fn watch_forwarded_requests(
+    ///
+    /// Requests whose signature doesn't match the client that
+    /// allegedly sent them, or whose payload doesn't match the
+    /// digest signed by the client, are dropped.
+    pub fn watch_forwarded_requests<C: Clock>(
         &mut self,
         requests: ForwardedRequestsMessage<Request<S>>,
-        timeouts: &TimeoutsHandle<S>,
+        timeouts: &TimeoutsHandle<S, C>,
         log: &mut Log<State<S>, Request<S>, Reply<S>>,
+        node: &Node<S::Data>,
     ) {
-        let phase = TimeoutPhase::TimedOutOnce(Instant::now());
+        let phase = TimeoutPhase::TimedOutOnce(timeouts.now());
         let requests = requests
             .into_inner()
             .into_iter()
+            .filter(|forwarded| validate_request::<S>(node, forwarded))
             .map(|forwarded| forwarded.into_inner());
 
         for (header, request) in requests {
+            if log.is_replayed(header.from(), request.sequence_number()) {
+                continue;
+            }
             self.watch_request_impl(phase, header.unique_digest(), timeouts);
             log.insert(header, SystemMessage::Request(request));
         }
     }
 
-    fn add_stopped_requests(&mut self, log: &mut Log<State<S>, Request<S>, Reply<S>>) {
+    /// Watch a client request forwarded by a follower replica,
+    /// as soon as the follower received it from the client.
+    ///
+    /// The request is dropped if its signature or digest doesn't
+    /// match the client that allegedly sent it, or if it is already
+    /// watched, e.g. because the client also sent it to us.
+    pub fn watch_forwarded_request<C: Clock>(
+        &mut self,
+        request: ForwardRequestMessage<Request<S>>,
+        timeouts: &TimeoutsHandle<S, C>,
+        log: &mut Log<State<S>, Request<S>, Reply<S>>,
+        node: &Node<S::Data>,
+    ) {
+        let forwarded = request.into_inner();
+        if !validate_request::<S>(node, &forwarded) {
+            return;
+        }
+        let (header, request) = forwarded.into_inner();
+        let digest = header.unique_digest();
+        if self.watching.contains_key(&digest)
+            || log.is_replayed(header.from(), request.sequence_number())
+        {
+            return;
+        }
+        let phase = TimeoutPhase::Init(timeouts.now());
+        self.watch_request_impl(phase, digest, timeouts);
+        log.insert(header, SystemMessage::Request(request));
+    }
+
+    fn add_stopped_requests(
+        &mut self,
+        log: &mut Log<State<S>, Request<S>, Reply<S>>,
+        node: &Node<S::Data>,
+    ) {
         // TODO: maybe optimize this `stopped_requests` call, to avoid
         // a heap allocation of a `Vec`?
         let requests = self
             .stopped_requests(None)
             .into_iter()
+            .filter(|stopped| validate_request::<S>(node, stopped))
             .map(|stopped| stopped.into_inner());
 
         for (header, request) in requests {
@@ -379,11 +493,11 @@ where
         }
     }
 
-    fn watch_request_impl(
+    fn watch_request_impl<C: Clock>(
         &mut self,
         phase: TimeoutPhase,
         digest: Digest,
-        timeouts: &TimeoutsHandle<S>,
+        timeouts: &TimeoutsHandle<S, C>,
     ) {
         if !self.watching_timeouts {
             let seq = self.next_timeout();
@@ -408,8 +522,8 @@ where
     }
 
     /// Start watching all pending client requests.
-    pub fn watch_all_requests(&mut self, timeouts: &TimeoutsHandle<S>) {
-        let phase = TimeoutPhase::Init(Instant::now());
+    pub fn watch_all_requests<C: Clock>(&mut self, timeouts: &TimeoutsHandle<S, C>) {
+        let phase = TimeoutPhase::Init(timeouts.now());
         for timeout_phase in self.watching.values_mut() {
             *timeout_phase = phase;
         }
@@ -425,6 +539,7 @@ where
     pub fn install_view(&mut self, view: ViewInfo) {
         // FIXME: is the following line necessary?
         //self.phase = ProtoPhase::Init;
+        self.view_votes.prune(&view);
         self.tbo.view = view;
     }
 
@@ -464,15 +579,24 @@ where
     /// Advances the state of the view change state machine.
     //
     // TODO: retransmit STOP msgs
-    pub fn process_message(
+    pub fn process_message<C: Clock>(
         &mut self,
         header: Header,
         message: ViewChangeMessage<Request<S>>,
-        timeouts: &TimeoutsHandle<S>,
+        timeouts: &TimeoutsHandle<S, C>,
         log: &mut Log<State<S>, Request<S>, Reply<S>>,
         consensus: &mut Consensus<S>,
         node: &mut Node<S::Data>,
     ) -> SynchronizerStatus {
+        // if f+1 replicas are changing to a view past the next one,
+        // at least one correct replica is, so we may as well join it
+        if let ViewChangeMessageKind::Stop(_) = message.kind() {
+            let target = message.sequence_number();
+            if let Some(target) = self.view_votes.vote(&self.tbo.view, header.from(), target) {
+                self.join_view_change(target, node);
+            }
+        }
+
         match self.phase {
             ProtoPhase::Init => match message.kind() {
                 ViewChangeMessageKind::Stop(_) => {
@@ -539,7 +663,7 @@ where
                     //   messages with TimeoutPhase::Init(_)
                     // - install new view (i.e. update view seq no)
                     // - send STOP-DATA message
-                    self.add_stopped_requests(log);
+                    self.add_stopped_requests(log, node);
                     self.watch_all_requests(timeouts);
 
                     self.install_view(self.view().next_view());
@@ -549,7 +673,7 @@ where
                         ProtoPhase::StoppingData(0)
                     };
 
-                    let collect = log.decision_log().collect_data(*self.view());
+                    let collect = log.decision_log().collect_data(self.view());
                     let message = SystemMessage::ViewChange(ViewChangeMessage::new(
                         self.view().sequence_number(),
                         ViewChangeMessageKind::StopData(collect),
@@ -613,7 +737,7 @@ where
                 // - broadcast SYNC msg with collected
                 //   STOP-DATA proofs so other replicas
                 //   can repeat the leader's computation
-                let proof = self.highest_proof(*self.view(), node);
+                let proof = self.highest_proof(self.view(), node);
                 let curr_cid = proof
                     .map(|p| p.pre_prepare().message().sequence_number())
                     .map(|seq| SeqNo::from(u32::from(seq) + 1))
@@ -622,7 +746,7 @@ where
                 let normalized_collects: Vec<Option<&CollectData>> =
                     self.normalized_collects(curr_cid).collect();
 
-                let sound = sound(*self.view(), &normalized_collects);
+                let sound = sound(self.view(), &normalized_collects);
                 if !sound.test() {
                     // FIXME: BFT-SMaRt doesn't do anything if `sound`
                     // evaluates to false; do we keep the same behavior,
@@ -643,8 +767,12 @@ where
                     }),
                 ));
                 let node_id = node.id();
-                let targets =
-                    NodeId::targets(0..self.view().params().n()).filter(move |&id| id != node_id);
+                let targets = self
+                    .view()
+                    .members()
+                    .iter()
+                    .copied()
+                    .filter(move |&id| id != node_id);
                 node.broadcast(message, targets);
 
                 let state = FinalizeState {
@@ -692,7 +820,7 @@ where
                 // leader has already performed this computation in the
                 // STOP-DATA phase of Mod-SMaRt
                 let signed: Vec<_> = signed_collects::<S>(node, collects);
-                let proof = highest_proof::<S, _>(*self.view(), node, signed.iter());
+                let proof = highest_proof::<S, _>(self.view(), node, signed.iter());
                 let curr_cid = proof
                     .map(|p| p.pre_prepare().message().sequence_number())
                     .map(|seq| SeqNo::from(u32::from(seq) + 1))
@@ -700,7 +828,7 @@ where
                 let normalized_collects: Vec<_> =
                     { normalized_collects(curr_cid, collect_data(signed.iter())).collect() };
 
-                let sound = sound(*self.view(), &normalized_collects);
+                let sound = sound(self.view(), &normalized_collects);
                 if !sound.test() {
                     // FIXME: BFT-SMaRt doesn't do anything if `sound`
                     // evaluates to false; do we keep the same behavior,
@@ -751,12 +879,13 @@ where
     // have surpassed their defined timeout period, after the timeout event
     // is fired on the master channel of the core server task
     //
-    pub fn client_requests_timed_out(
+    pub fn client_requests_timed_out<C: Clock>(
         &mut self,
         seq: SeqNo,
-        timeouts: &TimeoutsHandle<S>,
+        timeouts: &TimeoutsHandle<S, C>,
     ) -> SynchronizerStatus {
-        let ignore_timeout = !self.watching_timeouts || seq.next() != self.timeout_seq;
+        let ignore_timeout =
+            self.unrecoverable || !self.watching_timeouts || seq.next() != self.timeout_seq;
 
         if ignore_timeout {
             return SynchronizerStatus::Nil;
@@ -767,7 +896,7 @@ where
         // to peer nodes
         let mut forwarded = Vec::new();
         let mut stopped = Vec::new();
-        let now = Instant::now();
+        let now = timeouts.now();
 
         for (digest, timeout_phase) in self.watching.iter_mut() {
             // NOTE:
@@ -807,6 +936,12 @@ where
         timed_out: Option<Vec<StoredMessage<RequestMessage<Request<S>>>>>,
         node: &mut Node<S::Data>,
     ) {
+        if !self.is_changing_views() {
+            self.changing_since = Instant::now();
+            self.ahead.clear();
+        } else if self.view_change_failed(node) {
+            return;
+        }
         match (&self.phase, &timed_out) {
             // we have received STOP messages from peer nodes,
             // but haven't sent our own STOP, yet;
@@ -838,10 +973,57 @@ where
             self.view().sequence_number().next(),
             ViewChangeMessageKind::Stop(requests),
         ));
-        let targets = NodeId::targets(0..self.view().params().n());
+        let targets = self.view().members().iter().copied();
+        node.broadcast(message, targets);
+    }
+
+    // skips the views up to the one preceding `target`,
+    // and triggers a view change to `target`
+    fn join_view_change(&mut self, target: SeqNo, node: &mut Node<S::Data>) {
+        if self.is_changing_views() && self.view_change_failed(node) {
+            return;
+        }
+        while self.view().sequence_number().next() != target {
+            self.tbo.next_instance_queue();
+            self.install_view(self.view().next_view());
+        }
+
+        // abandon the view change we might have been running
+        self.finalize_state = None;
+        self.phase = ProtoPhase::Init;
+        self.begin_view_change(Some(Vec::new()), node);
+
+        // process the STOP messages queued for `target`
+        self.tbo.signal();
+    }
+
+    /// Hand off the leadership of the current view to the leader
+    /// of the next view, by broadcasting a `LeaderStepDown` message.
+    pub fn step_down(&self, node: &mut Node<S::Data>) {
+        let message = SystemMessage::LeaderStepDown(LeaderStepDownMessage::new(
+            self.view().sequence_number(),
+        ));
+        let targets = self.view().members().iter().copied();
         node.broadcast(message, targets);
     }
 
+    /// Handle a `LeaderStepDown` message, sent by the leader of the current view.
+    ///
+    /// If the message is valid, the view change protocol is started right
+    /// away, without waiting for any client requests to time out.
+    pub fn leader_stepped_down(
+        &mut self,
+        header: Header,
+        message: LeaderStepDownMessage,
+        node: &mut Node<S::Data>,
+    ) -> SynchronizerStatus {
+        if self.is_changing_views() || !accepts_step_down(self.view(), header.from(), &message) {
+            return SynchronizerStatus::Nil;
+        }
+        self.begin_view_change(Some(Vec::new()), node);
+        SynchronizerStatus::Running
+    }
+
     /// Forward the requests that timed out, `timed_out`, to all the nodes in the
     /// current view.
     pub fn forward_requests(
@@ -850,7 +1032,7 @@ where
         node: &mut Node<S::Data>,
     ) {
         let message = SystemMessage::ForwardedRequests(ForwardedRequestsMessage::new(timed_out));
-        let targets = NodeId::targets(0..self.view().params().n());
+        let targets = self.view().members().iter().copied();
         node.broadcast(message, targets);
     }
 
@@ -860,6 +1042,90 @@ where
         &self.tbo.view
     }
 
+    /// Returns the base duration client requests may
+    /// wait to be decided, before timing out.
+    pub fn request_timeout(&self) -> Duration {
+        self.timeout_dur
+    }
+
+    /// Checks if this `Synchronizer` gave up on changing views, after
+    /// too many view changes failed in a row; check out the docs on
+    /// `ReplicaConfig::max_failed_view_changes`.
+    pub fn is_unrecoverable(&self) -> bool {
+        self.unrecoverable
+    }
+
+    /// Accounts for a consensus message pertaining to the view `view`,
+    /// received from the replica `from` while we are changing views.
+    pub fn consensus_received(&mut self, from: NodeId, view: SeqNo) {
+        if self.is_changing_views() && view >= self.view().sequence_number().next() {
+            self.ahead.insert(from);
+        }
+    }
+
+    /// Checks if the view change we are running has not finished for
+    /// at least `timeout`, at the instant `now`, while at least f+1
+    /// replicas are running consensus in the view we are changing to,
+    /// or a more recent one.
+    ///
+    /// This happens if the other replicas finished the view change
+    /// without us, e.g. because we were partitioned from them.
+    pub fn stalled(&self, timeout: Duration, now: Instant) -> bool {
+        self.is_changing_views()
+            && self.ahead.len() > self.view().params().f()
+            && now.saturating_duration_since(self.changing_since) >= timeout
+    }
+
+    /// Abandons the view change we are running, if the view recovered
+    /// with CST is at least as recent as the view `changing_to` we were
+    /// changing to. Returns `true` if the view change was abandoned.
+    pub fn caught_up(&mut self, changing_to: SeqNo) -> bool {
+        let view = self.view().sequence_number();
+        if !self.is_changing_views() || self.finalize_state.is_some() || view < changing_to {
+            return false;
+        }
+        // skip the messages queued for the views we missed
+        let mut skipped = changing_to;
+        while skipped <= view {
+            self.tbo.next_instance_queue();
+            skipped = skipped.next();
+        }
+        self.stopped.clear();
+        self.collects.clear();
+        self.ahead.clear();
+        self.phase = ProtoPhase::Init;
+        self.failed_view_changes = 0;
+        true
+    }
+
+    // checks if we sent our own STOP, and the view
+    // change didn't finish yet, i.e. if it is running
+    fn is_changing_views(&self) -> bool {
+        !matches!(self.phase, ProtoPhase::Init | ProtoPhase::Stopping(_))
+    }
+
+    // accounts for the view change we were running being abandoned,
+    // returning whether we gave up on changing views altogether
+    //
+    // we only give up once the replica was told that the cluster
+    // is unrecoverable, otherwise we keep retrying
+    fn view_change_failed(&mut self, node: &mut Node<S::Data>) -> bool {
+        self.failed_view_changes += 1;
+        match self.max_failed_view_changes {
+            Some(max) if self.failed_view_changes >= max => (),
+            _ => return false,
+        }
+        let view = self.view().sequence_number().next();
+        let mut system_tx = node.master_channel();
+        self.unrecoverable = system_tx
+            .try_send(Message::ClusterUnrecoverable(view))
+            .unwrap_or(false);
+        if self.unrecoverable {
+            self.unwatch_all_requests();
+        }
+        self.unrecoverable
+    }
+
     fn next_timeout(&mut self) -> SeqNo {
         let next = self.timeout_seq;
         self.timeout_seq = self.timeout_seq.next();
@@ -913,7 +1179,7 @@ where
 
     // TODO: quorum sizes may differ when we implement reconfiguration
     #[inline]
-    fn highest_proof<'a>(&'a self, view: ViewInfo, node: &Node<S::Data>) -> Option<&'a Proof> {
+    fn highest_proof<'a>(&'a self, view: &ViewInfo, node: &Node<S::Data>) -> Option<&'a Proof> {
         highest_proof::<S, _>(view, node, self.collects.values())
     }
 
@@ -999,6 +1265,7 @@ where
         // and update proto phase
         self.tbo.next_instance_queue();
         self.phase = ProtoPhase::Init;
+        self.failed_view_changes = 0;
 
         // resume normal phase
         SynchronizerStatus::NewView
@@ -1046,7 +1313,7 @@ where
 //
 ////////////////////////////////////////////////////////////////////////////////
 
-fn sound<'a>(curr_view: ViewInfo, normalized_collects: &[Option<&'a CollectData>]) -> Sound {
+fn sound<'a>(curr_view: &ViewInfo, normalized_collects: &[Option<&'a CollectData>]) -> Sound {
     // collect timestamps and values
     let mut timestamps = collections::hash_set();
     let mut values = collections::hash_set();
@@ -1090,7 +1357,7 @@ fn sound<'a>(curr_view: ViewInfo, normalized_collects: &[Option<&'a CollectData>
 }
 
 fn binds(
-    curr_view: ViewInfo,
+    curr_view: &ViewInfo,
     ts: SeqNo,
     value: &Digest,
     normalized_collects: &[Option<&CollectData>],
@@ -1103,7 +1370,7 @@ fn binds(
     }
 }
 
-fn unbound(curr_view: ViewInfo, normalized_collects: &[Option<&CollectData>]) -> bool {
+fn unbound(curr_view: &ViewInfo, normalized_collects: &[Option<&CollectData>]) -> bool {
     if normalized_collects.len() < curr_view.params().quorum() {
         false
     } else {
@@ -1140,7 +1407,7 @@ fn unbound(curr_view: ViewInfo, normalized_collects: &[Option<&CollectData>]) ->
 // therefore, our code *should* be correct :)
 
 fn quorum_highest(
-    curr_view: ViewInfo,
+    curr_view: &ViewInfo,
     ts: SeqNo,
     value: &Digest,
     normalized_collects: &[Option<&CollectData>],
@@ -1179,7 +1446,7 @@ fn quorum_highest(
 }
 
 fn certified_value(
-    curr_view: ViewInfo,
+    curr_view: &ViewInfo,
     ts: SeqNo,
     value: &Digest,
     normalized_collects: &[Option<&CollectData>],
@@ -1223,6 +1490,13 @@ fn normalized_collects<'a>(
     })
 }
 
+// a step down is only accepted if it was sent by
+// the leader of the view it refers to, and if that
+// view is the current one
+fn accepts_step_down(view: &ViewInfo, from: NodeId, message: &LeaderStepDownMessage) -> bool {
+    message.sequence_number() == view.sequence_number() && from == view.leader()
+}
+
 fn signed_collects<S>(
     node: &Node<S::Data>,
     collects: Vec<StoredMessage<ViewChangeMessage<Request<S>>>>,
@@ -1246,20 +1520,44 @@ where
     Request<S>: Send + Clone + 'static,
     Reply<S>: Send + 'static,
 {
-    let wm = match WireMessage::from_parts(*stored.header(), &[]) {
-        Ok(wm) => wm,
-        _ => return false,
-    };
     // check if we even have the public key of the node that claims
     // to have sent this particular message
     let key = match node.get_public_key(stored.header().from()) {
         Some(k) => k,
         None => return false,
     };
-    wm.is_valid(Some(key))
+    // NOTE: the payload is not available, so we can't use
+    // `WireMessage::is_valid`, which checks its length
+    stored
+        .header()
+        .verify_signature_hashed::<<S::Data as SharedData>::Hasher>(key)
 }
 
-fn highest_proof<'a, S, I>(view: ViewInfo, node: &Node<S::Data>, collects: I) -> Option<&'a Proof>
+// besides checking the signature of a client request relayed by
+// another replica, checks if the request hashes to the digest signed
+// by the client, since the relaying replica may have tampered with it
+fn validate_request<S>(
+    node: &Node<S::Data>,
+    stored: &StoredMessage<RequestMessage<Request<S>>>,
+) -> bool
+where
+    S: Service + Send + 'static,
+    State<S>: Send + Clone + 'static,
+    Request<S>: Send + Clone + 'static,
+    Reply<S>: Send + 'static,
+{
+    if !validate_signature::<S, _>(node, stored) {
+        return false;
+    }
+    let mut buf = Buf::new();
+    let message = SystemMessage::Request(stored.message().clone());
+    match <S::Data as DigestData>::serialize_digest(&message, &mut buf) {
+        Ok(digest) => digest == *stored.header().digest(),
+        Err(_) => false,
+    }
+}
+
+fn highest_proof<'a, S, I>(view: &ViewInfo, node: &Node<S::Data>, collects: I) -> Option<&'a Proof>
 where
     I: Iterator<Item = &'a StoredMessage<ViewChangeMessage<Request<S>>>>,
     S: Service + Send + 'static,
@@ -1292,3 +1590,247 @@ where
         })
         .max_by_key(|proof| proof.pre_prepare().message().sequence_number())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures::FutureExt;
+
+    use super::{accepts_step_down, Synchronizer, SynchronizerStatus, ViewVotes};
+    use crate::bft::async_runtime as rt;
+    use crate::bft::communication::message::{
+        LeaderStepDownMessage, Message, SystemMessage, ViewChangeMessage, ViewChangeMessageKind,
+        WireMessage,
+    };
+    use crate::bft::communication::{Node, NodeId};
+    use crate::bft::consensus::log::{Log, PERIOD};
+    use crate::bft::consensus::{Consensus, VoteVerification};
+    use crate::bft::core::server::ViewInfo;
+    use crate::bft::crypto::hash::{DefaultHasher, Digest, DigestHasher};
+    use crate::bft::crypto::signature::KeyPair;
+    use crate::bft::ordering::{Orderable, SeqNo};
+    use crate::bft::testing::{init_runtime, CounterData, CounterService};
+    use crate::bft::timeouts::{MockClock, Timeouts};
+
+    #[test]
+    fn test_leader_step_down() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let leader = view.leader();
+        let message = LeaderStepDownMessage::new(view.sequence_number());
+
+        // only the current leader may step down
+        assert!(accepts_step_down(&view, leader, &message));
+        for id in NodeId::targets(1..4) {
+            assert!(!accepts_step_down(&view, id, &message));
+        }
+
+        // a step down for an older view is ignored
+        let next = view.next_view();
+        assert!(!accepts_step_down(&next, next.leader(), &message));
+
+        // leadership is handed to the next replica
+        assert_ne!(next.leader(), leader);
+    }
+
+    #[test]
+    fn test_broadcast_targets_view_members() {
+        init_runtime();
+
+        // members whose ids aren't contiguous from zero
+        let members = vec![
+            NodeId::from(1u32),
+            NodeId::from(3u32),
+            NodeId::from(4u32),
+            NodeId::from(7u32),
+        ];
+        let mut view = ViewInfo::with_members(SeqNo::ZERO, members.clone(), 1).unwrap();
+        assert_eq!(view.members(), &members[..]);
+        for _ in 0..members.len() {
+            assert!(members.contains(&view.leader()));
+            view = view.next_view();
+        }
+
+        // a loopback node panics when sending to any node other than
+        // itself, so the broadcast reaches exactly the view's members
+        let id = NodeId::from(7u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::with_members(SeqNo::ZERO, vec![id], 0).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let synchronizer = Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+
+        synchronizer.step_down(&mut node);
+        rt::block_on(async {
+            match node.receive().await.unwrap() {
+                Message::System(header, SystemMessage::LeaderStepDown(_)) => {
+                    assert_eq!(header.from(), id);
+                }
+                _ => panic!("Expected a leader step down message"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_view_votes_highest_supported() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let mut votes = ViewVotes::new();
+        let vote = |votes: &mut ViewVotes, from: u32, target: u32| {
+            votes.vote(&view, NodeId::from(from), SeqNo::from(target))
+        };
+
+        // votes for the next view are handled by the regular
+        // view change protocol, so they are not tracked
+        assert_eq!(vote(&mut votes, 1, 1), None);
+        assert_eq!(vote(&mut votes, 2, 1), None);
+
+        // duplicate votes and votes from non members don't count
+        assert_eq!(vote(&mut votes, 1, 3), None);
+        assert_eq!(vote(&mut votes, 1, 3), None);
+        assert_eq!(vote(&mut votes, 9, 3), None);
+        assert_eq!(vote(&mut votes, 2, 3), Some(SeqNo::from(3u32)));
+
+        // the highest view with f+1 votes is picked
+        assert_eq!(vote(&mut votes, 3, 5), Some(SeqNo::from(3u32)));
+        assert_eq!(vote(&mut votes, 0, 5), Some(SeqNo::from(5u32)));
+
+        // the votes for views up to the next one are dropped
+        votes.prune(&view.peek(SeqNo::from(4u32)));
+        assert_eq!(vote(&mut votes, 2, 5), None);
+        assert_eq!(vote(&mut votes, 1, 6), None);
+        assert_eq!(vote(&mut votes, 3, 6), Some(SeqNo::from(6u32)));
+    }
+
+    #[test]
+    fn test_cluster_unrecoverable_after_failed_view_changes() {
+        const MAX_FAILED: usize = 3;
+
+        init_runtime();
+
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::with_members(SeqNo::ZERO, vec![id], 0).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let timeouts =
+            Timeouts::<CounterService>::with_clock(node.master_channel(), MockClock::new());
+        let mut synchronizer =
+            Synchronizer::<CounterService>::new(Duration::from_secs(1), view, Some(MAX_FAILED));
+
+        // the first view change starts normally, and every
+        // retry abandons the view change preceding it
+        for _ in 0..MAX_FAILED {
+            synchronizer.begin_view_change(Some(Vec::new()), &mut node);
+            assert!(!synchronizer.is_unrecoverable());
+        }
+        synchronizer.begin_view_change(Some(Vec::new()), &mut node);
+        assert!(synchronizer.is_unrecoverable());
+
+        // no STOP is broadcast once we give up, but
+        // the replica is told the cluster is unrecoverable;
+        // the STOPs are sent from other tasks, so they may
+        // arrive after the replica is told so
+        rt::block_on(async {
+            let (mut stops, mut unrecoverable) = (0, false);
+            while stops < MAX_FAILED || !unrecoverable {
+                match node.receive().await.unwrap() {
+                    Message::System(_, SystemMessage::ViewChange(_)) => stops += 1,
+                    Message::ClusterUnrecoverable(view) => {
+                        assert_eq!(view, SeqNo::from(1u32));
+                        unrecoverable = true;
+                    }
+                    _ => panic!("Expected a view change message"),
+                }
+            }
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(node.receive().now_or_never().is_none());
+
+        // further timeouts no longer trigger view changes
+        let status = synchronizer.client_requests_timed_out(SeqNo::ZERO, &timeouts);
+        assert!(matches!(status, SynchronizerStatus::Nil));
+    }
+
+    #[test]
+    fn test_view_change_stalled_once_peers_moved_on() {
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        init_runtime();
+
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let mut synchronizer = Synchronizer::<CounterService>::new(TIMEOUT, view.clone(), None);
+
+        // consensus messages only count while changing views,
+        // and if they pertain to the view we are changing to
+        synchronizer.consensus_received(NodeId::from(1u32), SeqNo::from(1u32));
+        synchronizer.begin_view_change(Some(Vec::new()), &mut node);
+        let later = Instant::now() + TIMEOUT;
+        synchronizer.consensus_received(NodeId::from(1u32), SeqNo::ZERO);
+        synchronizer.consensus_received(NodeId::from(2u32), SeqNo::from(1u32));
+        assert!(!synchronizer.stalled(TIMEOUT, later));
+
+        // f+1 replicas moved on without us
+        synchronizer.consensus_received(NodeId::from(3u32), SeqNo::from(2u32));
+        assert!(!synchronizer.stalled(TIMEOUT, Instant::now()));
+        assert!(synchronizer.stalled(TIMEOUT, later));
+
+        // the view change is only abandoned once
+        // we recover the view our peers moved on to
+        assert!(!synchronizer.caught_up(SeqNo::from(1u32)));
+        synchronizer.install_view(view.next_view().next_view());
+        assert!(synchronizer.caught_up(SeqNo::from(1u32)));
+        assert!(!synchronizer.stalled(TIMEOUT, later));
+    }
+
+    #[test]
+    fn test_view_change_joined_on_f_plus_one_stops() {
+        init_runtime();
+
+        // with a single replica, f = 0, so a single
+        // STOP message is enough to join a view change
+        let id = NodeId::from(0u32);
+        let sk = KeyPair::from_bytes(&[0; KeyPair::SEED_LENGTH][..]).unwrap();
+        let view = ViewInfo::with_members(SeqNo::ZERO, vec![id], 0).unwrap();
+        let mut node: Node<CounterData> = Node::loopback(id, sk);
+        let timeouts =
+            Timeouts::<CounterService>::with_clock(node.master_channel(), MockClock::new());
+        let mut consensus =
+            Consensus::<CounterService>::new(SeqNo::ZERO, 1, VoteVerification::Disabled);
+        let mut log = Log::new(1, usize::MAX, PERIOD, usize::MAX);
+        let mut synchronizer =
+            Synchronizer::<CounterService>::new(Duration::from_secs(1), view, None);
+
+        // a STOP for a view past the next one
+        let target = SeqNo::from(3u32);
+        let digest = Digest::from_bytes(&[0; DefaultHasher::LENGTH][..]).unwrap();
+        let (header, _) = WireMessage::new(id, id, &[], 0, Some(digest), None).into_inner();
+        let message = ViewChangeMessage::new(target, ViewChangeMessageKind::Stop(Vec::new()));
+
+        let status = synchronizer.process_message(
+            header,
+            message,
+            &timeouts,
+            &mut log,
+            &mut consensus,
+            &mut node,
+        );
+        assert!(matches!(status, SynchronizerStatus::Running));
+        assert_eq!(synchronizer.view().sequence_number(), target);
+
+        // we broadcast our own STOP for the view we joined
+        rt::block_on(async {
+            loop {
+                match node.receive().await.unwrap() {
+                    Message::System(_, SystemMessage::ViewChange(m)) => {
+                        if let ViewChangeMessageKind::Stop(_) = m.kind() {
+                            assert_eq!(m.sequence_number(), target);
+                            break;
+                        }
+                    }
+                    _ => panic!("Expected a view change message"),
+                }
+            }
+        });
+    }
+}
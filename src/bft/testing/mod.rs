@@ -0,0 +1,118 @@
+//! Fixtures shared by the tests of the different modules of `bafomet`.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::sync::Once;
+
+use rustls::internal::pemfile;
+use rustls::{ClientConfig, NoClientAuth, ServerConfig};
+
+use crate::bft::async_runtime as rt;
+use crate::bft::communication::message::{Header, SystemMessage, WireMessage};
+use crate::bft::communication::serialize::SharedData;
+use crate::bft::crypto::hash::{DefaultHasher, Digest};
+use crate::bft::crypto::signature::Signature;
+use crate::bft::error::*;
+use crate::bft::executable::Service;
+
+static INIT: Once = Once::new();
+
+/// Initializes the async runtime, once for all the tests.
+pub fn init_runtime() {
+    INIT.call_once(|| unsafe { rt::init(1).unwrap() });
+}
+
+/// Returns the TLS configuration of the node with the certificate
+/// `name`, found in `ca-root`, trusting the root certificate.
+pub fn tls_configs(name: &str) -> (ClientConfig, ServerConfig) {
+    let open = |path: String| {
+        let path = format!("{}/ca-root/{}", env!("CARGO_MANIFEST_DIR"), path);
+        BufReader::new(File::open(path).unwrap())
+    };
+    let root = pemfile::certs(&mut open("root.crt".into())).unwrap();
+    let mut chain = pemfile::certs(&mut open(format!("{0}/{0}.crt", name))).unwrap();
+    chain.extend(root.iter().cloned());
+    let mut keys = pemfile::rsa_private_keys(&mut open(format!("{0}/{0}.key", name))).unwrap();
+
+    let mut client_config = ClientConfig::new();
+    client_config.root_store.add(&root[0]).unwrap();
+    let mut server_config = ServerConfig::new(NoClientAuth::new());
+    server_config
+        .set_single_cert(chain, keys.remove(0))
+        .unwrap();
+
+    (client_config, server_config)
+}
+
+/// Forges the `Header` of a message sent from `from` to `to`, with
+/// the given `nonce` and payload `length`, in the first shard.
+///
+/// Its digest and signature are zeroed, so it is only
+/// accepted where messages are not verified.
+pub fn forge_header(from: u32, to: u32, nonce: u64, length: u64) -> Header {
+    Header {
+        shard: 0,
+        version: WireMessage::CURRENT_VERSION,
+        signature: [0; Signature::LENGTH],
+        digest: Digest::EMPTY,
+        nonce,
+        from,
+        to,
+        length,
+    }
+}
+
+/// Serializes the types of the `CounterService`.
+pub struct CounterData;
+
+impl SharedData for CounterData {
+    type State = u64;
+    type Request = u64;
+    type Reply = u64;
+    type Hasher = DefaultHasher;
+
+    fn serialize_state<W>(w: W, s: &u64) -> Result<()>
+    where
+        W: Write,
+    {
+        bincode::serialize_into(w, s).wrapped(ErrorKind::Communication)
+    }
+
+    fn deserialize_state<R>(r: R) -> Result<u64>
+    where
+        R: Read,
+    {
+        bincode::deserialize_from(r).wrapped(ErrorKind::Communication)
+    }
+
+    fn serialize_message<W>(w: W, m: &SystemMessage<u64, u64, u64>) -> Result<()>
+    where
+        W: Write,
+    {
+        bincode::serialize_into(w, m).wrapped(ErrorKind::Communication)
+    }
+
+    fn deserialize_message<R>(r: R) -> Result<SystemMessage<u64, u64, u64>>
+    where
+        R: Read,
+    {
+        bincode::deserialize_from(r).wrapped(ErrorKind::Communication)
+    }
+}
+
+/// A service adding each request to a counter,
+/// and replying with the updated value.
+pub struct CounterService;
+
+impl Service for CounterService {
+    type Data = CounterData;
+
+    fn initial_state(&mut self) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn update(&mut self, state: &mut u64, request: u64) -> u64 {
+        *state += request;
+        *state
+    }
+}
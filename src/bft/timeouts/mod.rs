@@ -2,12 +2,20 @@
 //!
 //! This includes on-going client requests, as well as CST and
 //! view change messages exchanged between replicas.
+//!
+//! Time is read from a `Clock`, which is the wall clock by default,
+//! but can be swapped for a `MockClock` in tests, to fire timeouts
+//! deterministically, without waiting for them.
 
+use std::future::Future;
 use std::marker::PhantomData;
+use std::pin::Pin;
 use std::sync::atomic::{self, AtomicU64};
 use std::sync::Arc;
-use std::time::Duration;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use futures::channel::oneshot;
 use futures_timer::Delay;
 use intmap::IntMap;
 use parking_lot::Mutex;
@@ -27,16 +35,129 @@ pub enum TimeoutKind {
     /// Timeout pertaining to a group of client requests
     /// awaiting to be decided.
     ClientRequests(ordering::SeqNo),
+    /// Periodic timeout used to check if the running
+    /// consensus instance is stuck.
+    ConsensusWatchdog,
     // TODO: add the rest of the timeout kinds,
     // e.g. client requests
 }
 
+/// A source of time for the timeouts layer.
+pub trait Clock: Clone + Send + Sync + 'static {
+    /// The future returned by `sleep()`.
+    type Sleep: Future<Output = ()> + Send + 'static;
+
+    /// Returns the current instant, according to this clock.
+    fn now(&self) -> Instant;
+
+    /// Returns a future which completes once `dur` has elapsed,
+    /// according to this clock.
+    fn sleep(&self, dur: Duration) -> Self::Sleep;
+}
+
+/// The wall clock, backed by `futures_timer`.
+#[derive(Copy, Clone, Default)]
+pub struct SystemClock;
+
+/// A `Clock` which only moves forward when it is advanced
+/// manually, with `MockClock::advance()`.
+#[derive(Clone)]
+pub struct MockClock {
+    inner: Arc<Mutex<MockClockState>>,
+}
+
+struct MockClockState {
+    now: Instant,
+    sleepers: Vec<(Instant, oneshot::Sender<()>)>,
+}
+
+/// The future returned by `MockClock::sleep()`.
+pub struct MockSleep {
+    rx: oneshot::Receiver<()>,
+}
+
+impl Clock for SystemClock {
+    type Sleep = Delay;
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, dur: Duration) -> Delay {
+        Delay::new(dur)
+    }
+}
+
+impl MockClock {
+    /// Creates a new `MockClock`, starting at the current instant.
+    pub fn new() -> Self {
+        let inner = Arc::new(Mutex::new(MockClockState {
+            now: Instant::now(),
+            sleepers: Vec::new(),
+        }));
+        MockClock { inner }
+    }
+
+    /// Moves this clock forward by `dur`, waking up every
+    /// sleeper whose deadline has been reached.
+    pub fn advance(&self, dur: Duration) {
+        let mut state = self.inner.lock();
+        state.now += dur;
+        let now = state.now;
+        let (expired, pending) = std::mem::take(&mut state.sleepers)
+            .into_iter()
+            .partition(|(deadline, _)| *deadline <= now);
+        state.sleepers = pending;
+        drop(state);
+        for (_, tx) in expired {
+            tx.send(()).unwrap_or(());
+        }
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    type Sleep = MockSleep;
+
+    fn now(&self) -> Instant {
+        self.inner.lock().now
+    }
+
+    fn sleep(&self, dur: Duration) -> MockSleep {
+        let (tx, rx) = oneshot::channel();
+        let mut state = self.inner.lock();
+        let deadline = state.now + dur;
+        if dur == Duration::ZERO {
+            tx.send(()).unwrap_or(());
+        } else {
+            state.sleepers.push((deadline, tx));
+        }
+        MockSleep { rx }
+    }
+}
+
+impl Future for MockSleep {
+    type Output = ();
+
+    // also completes if the `MockClock` is dropped, so
+    // sleepers are never stuck forever
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.rx).poll(cx).map(|_| ())
+    }
+}
+
 struct TimeoutsHandleShared {
     current_seq_no: AtomicSeqNo,
     canceled: Mutex<IntMap<()>>,
 }
 
-pub struct TimeoutsHandle<S: Service> {
+pub struct TimeoutsHandle<S: Service, C: Clock = SystemClock> {
+    clock: C,
     shared: Arc<TimeoutsHandleShared>,
     system_tx: MessageChannelTx<State<S>, Request<S>, Reply<S>>,
 }
@@ -73,33 +194,43 @@ impl TimeoutsHandleShared {
     }
 }
 
-impl<S: Service> Clone for TimeoutsHandle<S>
+impl<S, C> Clone for TimeoutsHandle<S, C>
 where
     S: Service + Send + 'static,
+    C: Clock,
     State<S>: Send + 'static,
     Request<S>: Send + 'static,
     Reply<S>: Send + 'static,
 {
     fn clone(&self) -> Self {
         Self {
+            clock: self.clock.clone(),
             shared: Arc::clone(&self.shared),
             system_tx: self.system_tx.clone(),
         }
     }
 }
 
-impl<S: Service> TimeoutsHandle<S>
+impl<S, C> TimeoutsHandle<S, C>
 where
     S: Service + Send + 'static,
+    C: Clock,
     State<S>: Send + 'static,
     Request<S>: Send + 'static,
     Reply<S>: Send + 'static,
 {
+    /// Returns the current instant, according to the clock
+    /// of this handle.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
     /// Creates a new timeout event, that will fire after a duration of `dur`.
     pub fn timeout(&self, dur: Duration, kind: TimeoutKind) {
         let mut system_tx = self.system_tx.clone();
+        let sleep = self.clock.sleep(dur);
         rt::spawn(async move {
-            Delay::new(dur).await;
+            sleep.await;
             system_tx.send(Message::Timeout(kind)).await.unwrap_or(());
         });
     }
@@ -111,10 +242,11 @@ where
     pub fn timeout_with_cancel(&self, dur: Duration, kind: TimeoutKind) -> TimeoutHandle {
         let mut system_tx = self.system_tx.clone();
         let seq = self.shared.gen_seq_no();
+        let sleep = self.clock.sleep(dur);
 
         let shared = Arc::clone(&self.shared);
         rt::spawn(async move {
-            Delay::new(dur).await;
+            sleep.await;
             if !shared.was_canceled(seq) {
                 system_tx.send(Message::Timeout(kind)).await.unwrap_or(());
             }
@@ -133,10 +265,77 @@ where
     Reply<S>: Send + 'static,
 {
     pub fn new(system_tx: MessageChannelTx<State<S>, Request<S>, Reply<S>>) -> TimeoutsHandle<S> {
+        Self::with_clock(system_tx, SystemClock)
+    }
+
+    /// Same as `new()`, but reading time from the given `clock`.
+    pub fn with_clock<C: Clock>(
+        system_tx: MessageChannelTx<State<S>, Request<S>, Reply<S>>,
+        clock: C,
+    ) -> TimeoutsHandle<S, C> {
         let shared = Arc::new(TimeoutsHandleShared {
             canceled: Mutex::new(IntMap::new()),
             current_seq_no: AtomicSeqNo::new(0),
         });
-        TimeoutsHandle { system_tx, shared }
+        TimeoutsHandle {
+            clock,
+            system_tx,
+            shared,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::FutureExt;
+
+    use super::{Clock, MockClock};
+
+    #[test]
+    fn test_mock_clock_sleep() {
+        let clock = MockClock::new();
+        let start = clock.now();
+
+        let mut sleep = clock.sleep(Duration::from_secs(3600));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1800));
+        assert_eq!(clock.now(), start + Duration::from_secs(1800));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1800));
+        assert!((&mut sleep).now_or_never().is_some());
+    }
+
+    #[cfg(feature = "serialize_serde")]
+    #[test]
+    fn test_timeout_fired_by_mock_clock() {
+        use super::{TimeoutKind, Timeouts};
+        use crate::bft::async_runtime as rt;
+        use crate::bft::communication::channel::new_message_channel;
+        use crate::bft::communication::message::Message;
+        use crate::bft::ordering::SeqNo;
+        use crate::bft::testing::{init_runtime, CounterService};
+
+        init_runtime();
+
+        let clock = MockClock::new();
+        let (tx, mut rx) = new_message_channel::<u64, u64, u64>(8);
+        let timeouts = Timeouts::<CounterService>::with_clock(tx, clock.clone());
+
+        // a canceled timeout is never delivered, so the
+        // first message must be the one we didn't cancel
+        timeouts
+            .timeout_with_cancel(Duration::from_secs(60), TimeoutKind::ConsensusWatchdog)
+            .cancel();
+        timeouts.timeout(Duration::from_secs(3600), TimeoutKind::Cst(SeqNo::from(7)));
+        clock.advance(Duration::from_secs(3600));
+
+        match rt::block_on(rx.recv()).unwrap() {
+            Message::Timeout(TimeoutKind::Cst(seq)) => assert_eq!(seq, SeqNo::from(7)),
+            _ => panic!("Expected a CST timeout"),
+        }
     }
 }
@@ -15,7 +15,10 @@
 //! - If the serialization of wire messages is possible with `serde`:
 //!     + With `serialize_serde`.
 //! - The crypto library used to perform public key crypto operations:
-//!     + E.g. `crypto_signature_ring_ed25519`.
+//!     + E.g. `crypto_signature_ring_ed25519`, or
+//!       `crypto_signature_ed448_rust_ed448` for a higher security margin.
+//!       The scheme is part of the wire protocol, so every node in the
+//!       system must pick the same one.
 //! - The crypto library used to calculate hash digests of messages:
 //!     + E.g. `crypto_hash_ring_sha2`.
 //!